@@ -0,0 +1,124 @@
+//! Opens and closes windows over and over, like hosts that open and close plugin editors in quick
+//! succession while scanning plugins. This checks that repeated open/close cycles, and windows
+//! opened on several threads at once, keep working and don't run out of window classes or timers.
+//! On Linux it also checks that every window thread exits and closes its X11 connection.
+//!
+//! ```text
+//! cargo run --release --example open_close_stress
+//! ```
+#![allow(clippy::uninlined_format_args)]
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+use baseview::{Event, EventStatus, Size, Window, WindowHandle, WindowHandler, WindowOpenOptions};
+
+/// The number of child windows that are opened and closed inside of the parent window.
+const PARENTED_CYCLES: usize = 300;
+/// The number of threads that open standalone windows at the same time.
+const THREADS: usize = 4;
+/// The number of standalone windows each of those threads opens and closes.
+const THREAD_CYCLES: usize = 50;
+
+fn options(title: &str) -> WindowOpenOptions {
+    WindowOpenOptions::builder().title(title).size(Size::new(160.0, 120.0)).build().unwrap()
+}
+
+/// Opens a child window on every frame and closes the previous one, then closes itself.
+struct Parent {
+    child: Option<WindowHandle>,
+    opened: usize,
+}
+
+impl WindowHandler for Parent {
+    fn on_frame(&mut self, window: &mut Window) {
+        if let Some(mut child) = self.child.take() {
+            child.close();
+            // Closing a window that's already closed has no effect
+            child.close();
+        }
+
+        if self.opened == PARENTED_CYCLES {
+            window.close();
+            return;
+        }
+
+        self.child = Some(Window::open_parented(window, options("child"), |_| Idle).unwrap());
+        self.opened += 1;
+    }
+
+    fn on_event(&mut self, _window: &mut Window, _event: Event) -> EventStatus {
+        EventStatus::Ignored
+    }
+}
+
+struct Idle;
+
+impl WindowHandler for Idle {
+    fn on_frame(&mut self, _window: &mut Window) {}
+
+    fn on_event(&mut self, _window: &mut Window, _event: Event) -> EventStatus {
+        EventStatus::Ignored
+    }
+}
+
+/// Closes its window on the first frame.
+struct CloseOnFirstFrame;
+
+impl WindowHandler for CloseOnFirstFrame {
+    fn on_frame(&mut self, window: &mut Window) {
+        window.close();
+    }
+
+    fn on_event(&mut self, _window: &mut Window, _event: Event) -> EventStatus {
+        EventStatus::Ignored
+    }
+}
+
+fn main() {
+    // Anything that's only set up for the first window doesn't count as a leak
+    Window::open_blocking(options("open_close_stress"), |_| CloseOnFirstFrame).unwrap();
+    #[cfg(target_os = "linux")]
+    let resources = open_resources();
+
+    Window::open_blocking(options("open_close_stress"), |_| Parent { child: None, opened: 0 })
+        .unwrap();
+    println!("Opened and closed {} child windows", PARENTED_CYCLES);
+
+    // AppKit windows can only be opened on the main thread
+    if cfg!(not(target_os = "macos")) {
+        let closed = Arc::new(AtomicUsize::new(0));
+        let threads: Vec<_> = (0..THREADS)
+            .map(|i| {
+                let closed = Arc::clone(&closed);
+                thread::spawn(move || {
+                    for _ in 0..THREAD_CYCLES {
+                        let title = format!("open_close_stress thread {}", i);
+                        Window::open_blocking(options(&title), |_| CloseOnFirstFrame).unwrap();
+                        closed.fetch_add(1, Ordering::Relaxed);
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        let closed = closed.load(Ordering::Relaxed);
+        assert_eq!(closed, THREADS * THREAD_CYCLES);
+        println!("Opened and closed {} windows on {} threads", closed, THREADS);
+    }
+    #[cfg(target_os = "linux")]
+    {
+        assert_eq!(open_resources(), resources, "window threads or X11 connections were leaked");
+        println!("All window threads and X11 connections were cleaned up");
+    }
+}
+
+/// The number of threads and file descriptors of this process.
+#[cfg(target_os = "linux")]
+fn open_resources() -> (usize, usize) {
+    let count = |path| std::fs::read_dir(path).unwrap().count();
+    (count("/proc/self/task"), count("/proc/self/fd"))
+}
@@ -1,8 +1,8 @@
 #![allow(clippy::uninlined_format_args)]
 
 use baseview::{
-    Event, EventStatus, PhySize, Window, WindowEvent, WindowHandle, WindowHandler,
-    WindowScalePolicy,
+    Event, EventStatus, InputEvents, MacLayerConfig, PhySize, Window, WindowEvent, WindowHandle,
+    WindowHandler, WindowScalePolicy, X11Visual,
 };
 use std::num::NonZeroU32;
 
@@ -25,13 +25,22 @@ impl ParentWindowHandler {
             title: "baseview child".into(),
             size: baseview::Size::new(256.0, 256.0),
             scale: WindowScalePolicy::SystemScaleFactor,
+            input_events: InputEvents::ALL,
+            snap_logical_size: false,
+            borderless: false,
+            non_activating: false,
+            drop_formats: Vec::new(),
+            wheel_zoom: false,
+            external_frame_clock: false,
+            mac_layer: MacLayerConfig::DEFAULT,
+            x11_visual: X11Visual::PreferArgb,
 
             // TODO: Add an example that uses the OpenGL context
             #[cfg(feature = "opengl")]
             gl_config: None,
         };
         let child_window =
-            Window::open_parented(window, window_open_options, ChildWindowHandler::new);
+            Window::open_parented(window, window_open_options, ChildWindowHandler::new).unwrap();
 
         // TODO: no way to query physical size initially?
         Self {
@@ -56,8 +65,8 @@ impl WindowHandler for ParentWindowHandler {
 
     fn on_event(&mut self, _window: &mut Window, event: Event) -> EventStatus {
         match event {
-            Event::Window(WindowEvent::Resized(info)) => {
-                println!("Parent Resized: {:?}", info);
+            Event::Window(WindowEvent::Resized(info, source)) => {
+                println!("Parent Resized: {:?} ({:?})", info, source);
                 let new_size = info.physical_size();
                 self.current_size = new_size;
 
@@ -70,7 +79,21 @@ impl WindowHandler for ParentWindowHandler {
             }
             Event::Mouse(e) => println!("Parent Mouse event: {:?}", e),
             Event::Keyboard(e) => println!("Parent Keyboard event: {:?}", e),
+            Event::Text(text) => println!("Parent Text: {:?}", text),
+            Event::Ime(event) => println!("Parent Ime: {:?}", event),
+            Event::Accelerator(id) => println!("Parent Accelerator: {}", id),
+            Event::GlobalShortcut(id) => println!("Parent Global shortcut: {}", id),
             Event::Window(e) => println!("Parent Window event: {:?}", e),
+            Event::Device(e) => println!("Parent Device event: {:?}", e),
+            Event::PowerSuspend => println!("Parent Power suspend"),
+            Event::PowerResume => println!("Parent Power resume"),
+            Event::SystemSettingsChanged => println!("Parent System settings changed"),
+            Event::ClipboardChanged => println!("Parent Clipboard changed"),
+            Event::ClipboardData(text) => println!("Parent Clipboard data: {:?}", text),
+            Event::ClipboardFormatData { format, data } => {
+                println!("Parent Clipboard data in {:?}: {:?}", format, data)
+            }
+            Event::DragFinished(effect) => println!("Parent Drag finished: {:?}", effect),
         }
 
         EventStatus::Captured
@@ -107,8 +130,8 @@ impl WindowHandler for ChildWindowHandler {
 
     fn on_event(&mut self, _window: &mut Window, event: Event) -> EventStatus {
         match event {
-            Event::Window(WindowEvent::Resized(info)) => {
-                println!("Child Resized: {:?}", info);
+            Event::Window(WindowEvent::Resized(info, source)) => {
+                println!("Child Resized: {:?} ({:?})", info, source);
                 let new_size = info.physical_size();
                 self.current_size = new_size;
 
@@ -121,7 +144,21 @@ impl WindowHandler for ChildWindowHandler {
             }
             Event::Mouse(e) => println!("Child Mouse event: {:?}", e),
             Event::Keyboard(e) => println!("Child Keyboard event: {:?}", e),
+            Event::Text(text) => println!("Child Text: {:?}", text),
+            Event::Ime(event) => println!("Child Ime: {:?}", event),
+            Event::Accelerator(id) => println!("Child Accelerator: {}", id),
+            Event::GlobalShortcut(id) => println!("Child Global shortcut: {}", id),
             Event::Window(e) => println!("Child Window event: {:?}", e),
+            Event::Device(e) => println!("Child Device event: {:?}", e),
+            Event::PowerSuspend => println!("Child Power suspend"),
+            Event::PowerResume => println!("Child Power resume"),
+            Event::SystemSettingsChanged => println!("Child System settings changed"),
+            Event::ClipboardChanged => println!("Child Clipboard changed"),
+            Event::ClipboardData(text) => println!("Child Clipboard data: {:?}", text),
+            Event::ClipboardFormatData { format, data } => {
+                println!("Child Clipboard data in {:?}: {:?}", format, data)
+            }
+            Event::DragFinished(effect) => println!("Child Drag finished: {:?}", effect),
         }
 
         EventStatus::Captured
@@ -133,11 +170,20 @@ fn main() {
         title: "baseview".into(),
         size: baseview::Size::new(512.0, 512.0),
         scale: WindowScalePolicy::SystemScaleFactor,
+        input_events: InputEvents::ALL,
+        snap_logical_size: false,
+        borderless: false,
+        non_activating: false,
+        drop_formats: Vec::new(),
+        wheel_zoom: false,
+        external_frame_clock: false,
+        mac_layer: MacLayerConfig::DEFAULT,
+        x11_visual: X11Visual::PreferArgb,
 
         // TODO: Add an example that uses the OpenGL context
         #[cfg(feature = "opengl")]
         gl_config: None,
     };
 
-    Window::open_blocking(window_open_options, ParentWindowHandler::new);
+    baseview::run_app(window_open_options, ParentWindowHandler::new).unwrap();
 }
@@ -7,7 +7,8 @@ use std::time::Duration;
 #[cfg(target_os = "macos")]
 use baseview::{copy_to_clipboard, MouseEvent};
 use baseview::{
-    Event, EventStatus, PhySize, Window, WindowEvent, WindowHandler, WindowScalePolicy,
+    Event, EventStatus, InputEvents, MacLayerConfig, PhySize, Window, WindowEvent, WindowHandler,
+    WindowScalePolicy, X11Visual,
 };
 
 #[derive(Debug, Clone)]
@@ -42,8 +43,8 @@ impl WindowHandler for OpenWindowExample {
         match &event {
             #[cfg(target_os = "macos")]
             Event::Mouse(MouseEvent::ButtonPressed { .. }) => copy_to_clipboard("This is a test!"),
-            Event::Window(WindowEvent::Resized(info)) => {
-                println!("Resized: {:?}", info);
+            Event::Window(WindowEvent::Resized(info, source)) => {
+                println!("Resized: {:?} ({:?})", info, source);
                 let new_size = info.physical_size();
                 self.current_size = new_size;
 
@@ -68,6 +69,15 @@ fn main() {
         title: "baseview".into(),
         size: baseview::Size::new(512.0, 512.0),
         scale: WindowScalePolicy::SystemScaleFactor,
+        input_events: InputEvents::ALL,
+        snap_logical_size: false,
+        borderless: false,
+        non_activating: false,
+        drop_formats: Vec::new(),
+        wheel_zoom: false,
+        external_frame_clock: false,
+        mac_layer: MacLayerConfig::DEFAULT,
+        x11_visual: X11Visual::PreferArgb,
 
         // TODO: Add an example that uses the OpenGL context
         #[cfg(feature = "opengl")]
@@ -84,7 +94,7 @@ fn main() {
         }
     });
 
-    Window::open_blocking(window_open_options, |window| {
+    baseview::run_app(window_open_options, |window| {
         let ctx = unsafe { softbuffer::Context::new(window) }.unwrap();
         let mut surface = unsafe { softbuffer::Surface::new(&ctx, window) }.unwrap();
         surface.resize(NonZeroU32::new(512).unwrap(), NonZeroU32::new(512).unwrap()).unwrap();
@@ -96,13 +106,28 @@ fn main() {
             current_size: PhySize::new(512, 512),
             damaged: true,
         }
-    });
+    })
+    .unwrap();
 }
 
 fn log_event(event: &Event) {
     match event {
         Event::Mouse(e) => println!("Mouse event: {:?}", e),
         Event::Keyboard(e) => println!("Keyboard event: {:?}", e),
+        Event::Text(text) => println!("Text: {:?}", text),
+        Event::Ime(event) => println!("Ime: {:?}", event),
+        Event::Accelerator(id) => println!("Accelerator: {}", id),
+        Event::GlobalShortcut(id) => println!("Global shortcut: {}", id),
         Event::Window(e) => println!("Window event: {:?}", e),
+        Event::Device(e) => println!("Device event: {:?}", e),
+        Event::PowerSuspend => println!("Power suspend"),
+        Event::PowerResume => println!("Power resume"),
+        Event::SystemSettingsChanged => println!("System settings changed"),
+        Event::ClipboardChanged => println!("Clipboard changed"),
+        Event::ClipboardData(text) => println!("Clipboard data: {:?}", text),
+        Event::ClipboardFormatData { format, data } => {
+            println!("Clipboard data in {:?}: {:?}", format, data)
+        }
+        Event::DragFinished(effect) => println!("Drag finished: {:?}", effect),
     }
 }
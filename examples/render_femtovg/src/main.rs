@@ -2,8 +2,8 @@
 
 use baseview::gl::GlConfig;
 use baseview::{
-    Event, EventStatus, MouseEvent, PhyPoint, Point, Size, Window, WindowEvent, WindowHandler,
-    WindowInfo, WindowOpenOptions, WindowScalePolicy,
+    Event, EventStatus, InputEvents, MacLayerConfig, MouseEvent, PhyPoint, Point, Size, Window,
+    WindowEvent, WindowHandler, WindowInfo, WindowOpenOptions, WindowScalePolicy, X11Visual,
 };
 use femtovg::renderer::OpenGl;
 use femtovg::{Canvas, Color};
@@ -79,7 +79,7 @@ impl WindowHandler for FemtovgExample {
 
     fn on_event(&mut self, window: &mut Window, event: Event) -> EventStatus {
         match event {
-            Event::Window(WindowEvent::Resized(size)) => {
+            Event::Window(WindowEvent::Resized(size, _)) => {
                 let phy_size = size.physical_size();
                 self.current_size = size;
                 self.canvas.set_size(phy_size.width, phy_size.height, size.scale() as f32);
@@ -105,17 +105,40 @@ fn main() {
         title: "Femtovg on Baseview".into(),
         size: Size::new(512.0, 512.0),
         scale: WindowScalePolicy::SystemScaleFactor,
+        input_events: InputEvents::ALL,
+        snap_logical_size: false,
+        borderless: false,
+        non_activating: false,
+        drop_formats: Vec::new(),
+        wheel_zoom: false,
+        external_frame_clock: false,
+        mac_layer: MacLayerConfig::DEFAULT,
+        x11_visual: X11Visual::PreferArgb,
 
         gl_config: Some(GlConfig { alpha_bits: 8, ..GlConfig::default() }),
     };
 
-    Window::open_blocking(window_open_options, FemtovgExample::new);
+    baseview::run_app(window_open_options, FemtovgExample::new).unwrap();
 }
 
 fn log_event(event: &Event) {
     match event {
         Event::Mouse(e) => println!("Mouse event: {:?}", e),
         Event::Keyboard(e) => println!("Keyboard event: {:?}", e),
+        Event::Text(text) => println!("Text: {:?}", text),
+        Event::Ime(event) => println!("Ime: {:?}", event),
+        Event::Accelerator(id) => println!("Accelerator: {}", id),
+        Event::GlobalShortcut(id) => println!("Global shortcut: {}", id),
         Event::Window(e) => println!("Window event: {:?}", e),
+        Event::Device(e) => println!("Device event: {:?}", e),
+        Event::PowerSuspend => println!("Power suspend"),
+        Event::PowerResume => println!("Power resume"),
+        Event::SystemSettingsChanged => println!("System settings changed"),
+        Event::ClipboardChanged => println!("Clipboard changed"),
+        Event::ClipboardData(text) => println!("Clipboard data: {:?}", text),
+        Event::ClipboardFormatData { format, data } => {
+            println!("Clipboard data in {:?}: {:?}", format, data)
+        }
+        Event::DragFinished(effect) => println!("Drag finished: {:?}", effect),
     }
 }
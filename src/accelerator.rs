@@ -0,0 +1,354 @@
+use std::cell::RefCell;
+use std::error::Error;
+use std::fmt;
+
+use keyboard_types::{Code, Key, KeyState, KeyboardEvent, Modifiers, NamedKey};
+
+use crate::Event;
+
+/// A keyboard shortcut that can be registered with
+/// [`Window::register_accelerator()`][crate::Window::register_accelerator()].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct KeyCombo {
+    /// The modifiers that need to be held. Only Shift, Control, Alt, and Meta are taken into
+    /// account, and they need to match exactly.
+    pub modifiers: Modifiers,
+    /// The key that triggers the shortcut. Character keys are matched case insensitively.
+    pub key: Key,
+}
+
+impl KeyCombo {
+    /// The modifier used for application shortcuts on the current platform: Cmd on macOS and
+    /// Control everywhere else.
+    #[cfg(target_os = "macos")]
+    pub const PRIMARY_MODIFIER: Modifiers = Modifiers::META;
+    /// The modifier used for application shortcuts on the current platform: Cmd on macOS and
+    /// Control everywhere else.
+    #[cfg(not(target_os = "macos"))]
+    pub const PRIMARY_MODIFIER: Modifiers = Modifiers::CONTROL;
+
+    pub fn new(modifiers: Modifiers, key: Key) -> Self {
+        Self { modifiers, key }
+    }
+
+    /// A shortcut using the platform's [primary modifier][Self::PRIMARY_MODIFIER], so
+    /// `KeyCombo::primary(Key::Character("z".into()))` is Cmd+Z on macOS and Ctrl+Z elsewhere.
+    pub fn primary(key: Key) -> Self {
+        Self::new(Self::PRIMARY_MODIFIER, key)
+    }
+
+    /// Whether a key press triggers this shortcut.
+    ///
+    /// Character keys are matched against the character produced with the current keyboard
+    /// layout. When the key doesn't produce a printable ASCII character, for instance with a
+    /// Cyrillic layout, ASCII letters and digits are matched against the physical key instead.
+    /// The same happens when Shift or Alt turn the key into a symbol, so a shortcut for Shift+1
+    /// matches even though the key produces `!`.
+    pub fn matches(&self, event: &KeyboardEvent) -> bool {
+        if event.state != KeyState::Down
+            || shortcut_modifiers(event.modifiers) != shortcut_modifiers(self.modifiers)
+        {
+            return false;
+        }
+
+        match (&self.key, &event.key) {
+            (Key::Character(expected), Key::Character(actual)) if is_printable_ascii(actual) => {
+                if expected.to_lowercase() == actual.to_lowercase() {
+                    return true;
+                }
+
+                let changes_character =
+                    event.modifiers.intersects(Modifiers::SHIFT | Modifiers::ALT);
+                changes_character
+                    && !actual.bytes().all(|b| b.is_ascii_alphanumeric())
+                    && character_code(expected) == Some(event.code)
+            }
+            (Key::Character(expected), _) => character_code(expected) == Some(event.code),
+            (expected, actual) => expected == actual,
+        }
+    }
+
+    /// The physical key that triggers this shortcut when it's registered as a global shortcut.
+    /// Character keys are mapped to their position on a US keyboard, since a global shortcut can't
+    /// follow the layout the way [`matches()`][Self::matches()] does.
+    pub(crate) fn code(&self) -> Option<Code> {
+        let code = match &self.key {
+            Key::Character(character) if character == " " => Code::Space,
+            Key::Character(character) => return character_code(character),
+            Key::Named(NamedKey::Enter) => Code::Enter,
+            Key::Named(NamedKey::Tab) => Code::Tab,
+            Key::Named(NamedKey::Escape) => Code::Escape,
+            Key::Named(NamedKey::Backspace) => Code::Backspace,
+            Key::Named(NamedKey::Delete) => Code::Delete,
+            Key::Named(NamedKey::Insert) => Code::Insert,
+            Key::Named(NamedKey::Home) => Code::Home,
+            Key::Named(NamedKey::End) => Code::End,
+            Key::Named(NamedKey::PageUp) => Code::PageUp,
+            Key::Named(NamedKey::PageDown) => Code::PageDown,
+            Key::Named(NamedKey::ArrowUp) => Code::ArrowUp,
+            Key::Named(NamedKey::ArrowDown) => Code::ArrowDown,
+            Key::Named(NamedKey::ArrowLeft) => Code::ArrowLeft,
+            Key::Named(NamedKey::ArrowRight) => Code::ArrowRight,
+            Key::Named(NamedKey::Pause) => Code::Pause,
+            Key::Named(NamedKey::F1) => Code::F1,
+            Key::Named(NamedKey::F2) => Code::F2,
+            Key::Named(NamedKey::F3) => Code::F3,
+            Key::Named(NamedKey::F4) => Code::F4,
+            Key::Named(NamedKey::F5) => Code::F5,
+            Key::Named(NamedKey::F6) => Code::F6,
+            Key::Named(NamedKey::F7) => Code::F7,
+            Key::Named(NamedKey::F8) => Code::F8,
+            Key::Named(NamedKey::F9) => Code::F9,
+            Key::Named(NamedKey::F10) => Code::F10,
+            Key::Named(NamedKey::F11) => Code::F11,
+            Key::Named(NamedKey::F12) => Code::F12,
+            Key::Named(NamedKey::MediaPlayPause) => Code::MediaPlayPause,
+            Key::Named(NamedKey::MediaStop) => Code::MediaStop,
+            Key::Named(NamedKey::MediaTrackNext) => Code::MediaTrackNext,
+            Key::Named(NamedKey::MediaTrackPrevious) => Code::MediaTrackPrevious,
+            Key::Named(NamedKey::AudioVolumeMute) => Code::AudioVolumeMute,
+            Key::Named(NamedKey::AudioVolumeDown) => Code::AudioVolumeDown,
+            Key::Named(NamedKey::AudioVolumeUp) => Code::AudioVolumeUp,
+            _ => return None,
+        };
+
+        Some(code)
+    }
+}
+
+/// Why a shortcut couldn't be registered with
+/// [`Window::register_global_shortcut()`][crate::Window::register_global_shortcut()].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GlobalShortcutError {
+    /// The key can't be used for global shortcuts. Only ASCII letters and digits, Space, and the
+    /// common named keys like the function keys, arrow keys, and media keys are supported, and not
+    /// every platform supports all of those.
+    UnsupportedKey,
+    /// The combination is already in use, usually by another application or by another window.
+    Unavailable,
+}
+
+impl fmt::Display for GlobalShortcutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GlobalShortcutError::UnsupportedKey => {
+                write!(f, "the key can't be used for global shortcuts")
+            }
+            GlobalShortcutError::Unavailable => write!(f, "the key combination is already in use"),
+        }
+    }
+}
+
+impl Error for GlobalShortcutError {}
+
+fn is_printable_ascii(text: &str) -> bool {
+    text.bytes().all(|b| b.is_ascii() && !b.is_ascii_control())
+}
+
+fn shortcut_modifiers(modifiers: Modifiers) -> Modifiers {
+    modifiers & (Modifiers::SHIFT | Modifiers::CONTROL | Modifiers::ALT | Modifiers::META)
+}
+
+/// The physical key that produces an ASCII letter or digit on a US keyboard.
+fn character_code(character: &str) -> Option<Code> {
+    let mut chars = character.chars();
+    let c = match (chars.next(), chars.next()) {
+        (Some(c), None) => c.to_ascii_lowercase(),
+        _ => return None,
+    };
+
+    let code = match c {
+        'a' => Code::KeyA,
+        'b' => Code::KeyB,
+        'c' => Code::KeyC,
+        'd' => Code::KeyD,
+        'e' => Code::KeyE,
+        'f' => Code::KeyF,
+        'g' => Code::KeyG,
+        'h' => Code::KeyH,
+        'i' => Code::KeyI,
+        'j' => Code::KeyJ,
+        'k' => Code::KeyK,
+        'l' => Code::KeyL,
+        'm' => Code::KeyM,
+        'n' => Code::KeyN,
+        'o' => Code::KeyO,
+        'p' => Code::KeyP,
+        'q' => Code::KeyQ,
+        'r' => Code::KeyR,
+        's' => Code::KeyS,
+        't' => Code::KeyT,
+        'u' => Code::KeyU,
+        'v' => Code::KeyV,
+        'w' => Code::KeyW,
+        'x' => Code::KeyX,
+        'y' => Code::KeyY,
+        'z' => Code::KeyZ,
+        '0' => Code::Digit0,
+        '1' => Code::Digit1,
+        '2' => Code::Digit2,
+        '3' => Code::Digit3,
+        '4' => Code::Digit4,
+        '5' => Code::Digit5,
+        '6' => Code::Digit6,
+        '7' => Code::Digit7,
+        '8' => Code::Digit8,
+        '9' => Code::Digit9,
+        _ => return None,
+    };
+
+    Some(code)
+}
+
+/// The accelerators registered on a window.
+#[derive(Debug, Default)]
+pub(crate) struct Accelerators {
+    accelerators: RefCell<Vec<(KeyCombo, u32)>>,
+}
+
+impl Accelerators {
+    /// Registering a combination again replaces its previous id.
+    pub fn register(&self, combo: KeyCombo, id: u32) {
+        let mut accelerators = self.accelerators.borrow_mut();
+        accelerators.retain(|(registered, _)| registered != &combo);
+        accelerators.push((combo, id));
+    }
+
+    pub fn unregister(&self, id: u32) {
+        self.accelerators.borrow_mut().retain(|(_, registered)| *registered != id);
+    }
+
+    /// The id of the accelerator triggered by a key press, if any.
+    pub fn find(&self, event: &KeyboardEvent) -> Option<u32> {
+        self.accelerators.borrow().iter().find(|(combo, _)| combo.matches(event)).map(|(_, id)| *id)
+    }
+
+    /// Turn a keyboard event into [`Event::Accelerator`] if it triggers a registered accelerator.
+    pub fn translate(&self, event: KeyboardEvent) -> Event {
+        match self.find(&event) {
+            Some(id) => Event::Accelerator(id),
+            None => Event::Keyboard(event),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key_down(modifiers: Modifiers, key: Key, code: Code) -> KeyboardEvent {
+        KeyboardEvent { state: KeyState::Down, key, code, modifiers, ..Default::default() }
+    }
+
+    fn character(text: &str) -> Key {
+        Key::Character(text.into())
+    }
+
+    #[test]
+    fn matches_characters_case_insensitively() {
+        let combo = KeyCombo::new(Modifiers::CONTROL, character("z"));
+
+        assert!(combo.matches(&key_down(Modifiers::CONTROL, character("z"), Code::KeyZ)));
+        assert!(combo.matches(&key_down(Modifiers::CONTROL, character("Z"), Code::KeyZ)));
+        assert!(!combo.matches(&key_down(Modifiers::CONTROL, character("y"), Code::KeyY)));
+    }
+
+    #[test]
+    fn requires_exact_modifiers() {
+        let combo = KeyCombo::new(Modifiers::CONTROL, character("z"));
+
+        assert!(!combo.matches(&key_down(Modifiers::empty(), character("z"), Code::KeyZ)));
+        assert!(!combo.matches(&key_down(
+            Modifiers::CONTROL | Modifiers::SHIFT,
+            character("Z"),
+            Code::KeyZ
+        )));
+        // Lock keys don't affect shortcuts
+        assert!(combo.matches(&key_down(
+            Modifiers::CONTROL | Modifiers::CAPS_LOCK | Modifiers::NUM_LOCK,
+            character("z"),
+            Code::KeyZ
+        )));
+    }
+
+    #[test]
+    fn ignores_key_releases() {
+        let combo = KeyCombo::new(Modifiers::CONTROL, character("z"));
+        let mut event = key_down(Modifiers::CONTROL, character("z"), Code::KeyZ);
+        event.state = KeyState::Up;
+
+        assert!(!combo.matches(&event));
+    }
+
+    #[test]
+    fn follows_the_keyboard_layout() {
+        // Ctrl+Z on a German layout is the key labelled Z, which is KeyY on a US keyboard
+        let combo = KeyCombo::new(Modifiers::CONTROL, character("z"));
+
+        assert!(combo.matches(&key_down(Modifiers::CONTROL, character("z"), Code::KeyY)));
+        assert!(!combo.matches(&key_down(Modifiers::CONTROL, character("y"), Code::KeyZ)));
+    }
+
+    #[test]
+    fn falls_back_to_the_physical_key_for_non_latin_layouts() {
+        let combo = KeyCombo::new(Modifiers::CONTROL, character("c"));
+
+        assert!(combo.matches(&key_down(Modifiers::CONTROL, character("с"), Code::KeyC)));
+        assert!(!combo.matches(&key_down(Modifiers::CONTROL, character("м"), Code::KeyV)));
+    }
+
+    #[test]
+    fn matches_shifted_digits_by_physical_key() {
+        let combo = KeyCombo::new(Modifiers::CONTROL | Modifiers::SHIFT, character("1"));
+
+        assert!(combo.matches(&key_down(
+            Modifiers::CONTROL | Modifiers::SHIFT,
+            character("!"),
+            Code::Digit1
+        )));
+        assert!(!combo.matches(&key_down(
+            Modifiers::CONTROL | Modifiers::SHIFT,
+            character("@"),
+            Code::Digit2
+        )));
+        // Without a modifier that changes the character, the character itself has to match
+        let combo = KeyCombo::new(Modifiers::CONTROL, character("1"));
+        assert!(!combo.matches(&key_down(Modifiers::CONTROL, character("&"), Code::Digit1)));
+    }
+
+    #[test]
+    fn matches_named_keys() {
+        let combo = KeyCombo::new(Modifiers::empty(), Key::Named(NamedKey::F5));
+
+        assert!(combo.matches(&key_down(Modifiers::empty(), Key::Named(NamedKey::F5), Code::F5)));
+        assert!(!combo.matches(&key_down(Modifiers::empty(), Key::Named(NamedKey::F6), Code::F6)));
+    }
+
+    #[test]
+    fn maps_keys_to_us_layout_codes() {
+        let code = |key| KeyCombo::primary(key).code();
+
+        assert_eq!(code(character("a")), Some(Code::KeyA));
+        assert_eq!(code(character("Q")), Some(Code::KeyQ));
+        assert_eq!(code(character("7")), Some(Code::Digit7));
+        assert_eq!(code(character(" ")), Some(Code::Space));
+        assert_eq!(code(Key::Named(NamedKey::ArrowLeft)), Some(Code::ArrowLeft));
+        assert_eq!(code(character("!")), None);
+        assert_eq!(code(character("ab")), None);
+        assert_eq!(code(Key::Named(NamedKey::CapsLock)), None);
+    }
+
+    #[test]
+    fn registering_a_combo_again_replaces_its_id() {
+        let accelerators = Accelerators::default();
+        let combo = KeyCombo::primary(character("s"));
+        let event = key_down(KeyCombo::PRIMARY_MODIFIER, character("s"), Code::KeyS);
+
+        accelerators.register(combo.clone(), 1);
+        assert_eq!(accelerators.find(&event), Some(1));
+        accelerators.register(combo, 2);
+        assert_eq!(accelerators.find(&event), Some(2));
+        accelerators.unregister(2);
+        assert_eq!(accelerators.find(&event), None);
+    }
+}
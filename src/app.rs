@@ -0,0 +1,152 @@
+#[cfg(unix)]
+use std::os::raw::c_int;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::{Window, WindowHandler, WindowOpenOptions, WindowOpenOptionsError};
+
+/// Set when the process has been asked to quit while [`run_app()`] is running.
+static QUIT_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Run a standalone application with a single window, blocking until that window is closed. This
+/// is [`Window::open_blocking()`] with the process level integration that a real application
+/// needs:
+///
+/// - Interrupting or terminating the process (`SIGINT` and `SIGTERM` on Linux and macOS, Ctrl+C
+///   and Ctrl+Break in a Windows console) closes the window normally, so the handler still
+///   receives [`WindowEvent::WillClose`][crate::WindowEvent::WillClose]. Interrupting the process
+///   a second time while the window is closing terminates it right away.
+/// - A `WM_QUIT` message posted to the thread on Windows also closes the window.
+/// - On macOS the application becomes a regular foreground application with a menu bar, and
+///   <kbd>Cmd</kbd>+<kbd>Q</kbd> closes the window.
+///
+/// The previous signal and console control handlers are restored before this function returns.
+/// This is meant for examples and for standalone builds of plugins. Plugin windows opened inside
+/// of a host should use [`Window::open_parented()`] instead.
+///
+/// # Errors
+///
+/// Returns an error without opening a window if `options` don't pass
+/// [`WindowOpenOptions::validate()`].
+pub fn run_app<H, B>(options: WindowOpenOptions, build: B) -> Result<(), WindowOpenOptionsError>
+where
+    H: WindowHandler + 'static,
+    B: FnOnce(&mut Window) -> H,
+    B: Send + 'static,
+{
+    QUIT_REQUESTED.store(false, Ordering::SeqCst);
+    let quit_handlers = QuitHandlers::install();
+
+    let result = Window::open_blocking_inner(options, build, true);
+
+    drop(quit_handlers);
+    QUIT_REQUESTED.store(false, Ordering::SeqCst);
+
+    result
+}
+
+/// Whether the window opened by [`run_app()`] should be closed. The blocking event loops check
+/// this periodically for the window they were opened with `quits_with_app` set, so other windows
+/// in the process are left alone.
+pub(crate) fn quit_requested() -> bool {
+    QUIT_REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Ask the window to close the first time the process is interrupted. Returns `false` if it was
+/// already asked to, in which case the process should be terminated instead.
+fn request_quit() -> bool {
+    !QUIT_REQUESTED.swap(true, Ordering::SeqCst)
+}
+
+#[cfg(unix)]
+struct QuitHandlers {
+    previous: Vec<(c_int, libc::sigaction)>,
+}
+
+#[cfg(unix)]
+impl QuitHandlers {
+    const SIGNALS: [c_int; 2] = [libc::SIGINT, libc::SIGTERM];
+
+    fn install() -> Self {
+        extern "C" fn handle_signal(signal: c_int) {
+            // Only async-signal-safe functions may be called here
+            if !request_quit() {
+                unsafe {
+                    libc::signal(signal, libc::SIG_DFL);
+                    libc::raise(signal);
+                }
+            }
+        }
+
+        let mut previous = Vec::with_capacity(Self::SIGNALS.len());
+        for &signal in &Self::SIGNALS {
+            unsafe {
+                let mut action: libc::sigaction = std::mem::zeroed();
+                action.sa_sigaction = handle_signal as extern "C" fn(c_int) as usize;
+                action.sa_flags = libc::SA_RESTART;
+                libc::sigemptyset(&mut action.sa_mask);
+
+                let mut previous_action: libc::sigaction = std::mem::zeroed();
+                if libc::sigaction(signal, &action, &mut previous_action) == 0 {
+                    previous.push((signal, previous_action));
+                }
+            }
+        }
+
+        Self { previous }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for QuitHandlers {
+    fn drop(&mut self) {
+        for (signal, previous_action) in &self.previous {
+            unsafe { libc::sigaction(*signal, previous_action, std::ptr::null_mut()) };
+        }
+    }
+}
+
+#[cfg(windows)]
+struct QuitHandlers {
+    installed: bool,
+}
+
+#[cfg(windows)]
+impl QuitHandlers {
+    fn install() -> Self {
+        use winapi::shared::minwindef::TRUE;
+
+        let installed = unsafe {
+            winapi::um::consoleapi::SetConsoleCtrlHandler(Some(handle_console_ctrl), TRUE) != 0
+        };
+
+        Self { installed }
+    }
+}
+
+#[cfg(windows)]
+impl Drop for QuitHandlers {
+    fn drop(&mut self) {
+        use winapi::shared::minwindef::FALSE;
+
+        if self.installed {
+            unsafe {
+                winapi::um::consoleapi::SetConsoleCtrlHandler(Some(handle_console_ctrl), FALSE)
+            };
+        }
+    }
+}
+
+/// This is called on a separate thread created by the system.
+#[cfg(windows)]
+unsafe extern "system" fn handle_console_ctrl(
+    ctrl_type: winapi::shared::minwindef::DWORD,
+) -> winapi::shared::minwindef::BOOL {
+    use winapi::shared::minwindef::{FALSE, TRUE};
+    use winapi::um::wincon::{CTRL_BREAK_EVENT, CTRL_C_EVENT};
+
+    match ctrl_type {
+        // Returning `FALSE` lets the default handler terminate the process
+        CTRL_C_EVENT | CTRL_BREAK_EVENT if request_quit() => TRUE,
+        _ => FALSE,
+    }
+}
@@ -5,6 +5,51 @@ use crate::win as platform;
 #[cfg(target_os = "linux")]
 use crate::x11 as platform;
 
+use crate::Event;
+
+/// A format of data on the clipboard.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "recording", derive(serde::Serialize, serde::Deserialize))]
+pub enum ClipboardFormat {
+    /// UTF-8 text. This is converted to and from the platform's text formats, so it can be
+    /// exchanged with any other application.
+    Text,
+    /// Raw data in a format identified by its name, like a plugin's presets. The data is stored as
+    /// is. On X11 the name is the target's atom, which is usually a MIME type. On Windows it's the
+    /// name of a registered clipboard format, and on macOS it's a pasteboard type. A reverse
+    /// domain name like `com.example.plugin.preset` works on all of them.
+    Custom(String),
+}
+
+impl ClipboardFormat {
+    /// The event sent for data read from the clipboard in this format, see
+    /// [`Window::request_clipboard_data()`][crate::Window::request_clipboard_data()].
+    pub(crate) fn into_event(self, data: Option<Vec<u8>>) -> Event {
+        match self {
+            ClipboardFormat::Text => Event::ClipboardData(data.map(|data| {
+                String::from_utf8(data)
+                    .unwrap_or_else(|err| String::from_utf8_lossy(err.as_bytes()).into_owned())
+            })),
+            ClipboardFormat::Custom(_) => Event::ClipboardFormatData { format: self, data },
+        }
+    }
+}
+
+/// Put `data` on the system clipboard as text.
+///
+/// On Linux the clipboard's contents are served by the X11 connection of one of baseview's event
+/// loop threads, so this requires a window to be open, and the text stays available until another
+/// application copies something or until the last window has been closed.
 pub fn copy_to_clipboard(data: &str) {
-    platform::copy_to_clipboard(data)
+    copy_formats_to_clipboard(&[(ClipboardFormat::Text, data.as_bytes())]);
+}
+
+/// Replace the system clipboard's contents with data in one or more formats, for instance a
+/// preset in a custom format along with a textual description of it. Applications pasting the
+/// data pick the first format they support, so the formats should be listed from the most to the
+/// least specific. Data for [`ClipboardFormat::Text`] must be valid UTF-8.
+///
+/// See [`copy_to_clipboard()`] for the limitations on Linux.
+pub fn copy_formats_to_clipboard(contents: &[(ClipboardFormat, &[u8])]) {
+    platform::copy_to_clipboard(contents)
 }
@@ -0,0 +1,253 @@
+//! Telling clicks and drags apart.
+//!
+//! A small movement while a mouse button is held is usually not meant as a drag, especially with
+//! touchscreens and pens. [`DragDetector`] wraps a [`WindowHandler`], passes every event on to it
+//! unchanged, and additionally sends a [`MouseEvent::DragStarted`] event once the cursor has moved
+//! further than the system's drag threshold during a press:
+//!
+//! ```no_run
+//! # use baseview::{Event, EventStatus, MouseEvent, Window, WindowHandler, WindowOpenOptions};
+//! # use baseview::drag::DragDetector;
+//! struct MyHandler;
+//!
+//! impl WindowHandler for MyHandler {
+//!     fn on_frame(&mut self, _window: &mut Window) {}
+//!
+//!     fn on_event(&mut self, _window: &mut Window, event: Event) -> EventStatus {
+//!         if let Event::Mouse(MouseEvent::DragStarted { start, .. }) = event {
+//!             println!("Started dragging at {:?}", start);
+//!         }
+//!
+//!         EventStatus::Captured
+//!     }
+//! }
+//!
+//! # fn options() -> WindowOpenOptions { unimplemented!() }
+//! Window::open_blocking(options(), |_| DragDetector::new(MyHandler)).unwrap();
+//! ```
+
+use crate::{
+    Event, EventStatus, MouseButton, MouseEvent, Point, Window, WindowEvent, WindowHandler,
+};
+
+#[derive(Debug, Copy, Clone)]
+enum State {
+    Idle,
+    Pressed { button: MouseButton, start: Point, threshold: f64 },
+    Dragging { button: MouseButton },
+}
+
+/// A [`WindowHandler`] that sends [`MouseEvent::DragStarted`] events to the wrapped handler. See
+/// the [module documentation][self] for an example.
+///
+/// Only the first button pressed starts a drag. Other buttons pressed during the drag are passed
+/// on as usual.
+pub struct DragDetector<H: WindowHandler> {
+    handler: H,
+    state: State,
+    /// The last known cursor position. The platforms send a cursor movement before a press,
+    /// since button events don't contain a position.
+    position: Option<Point>,
+}
+
+impl<H: WindowHandler> DragDetector<H> {
+    pub fn new(handler: H) -> Self {
+        Self { handler, state: State::Idle, position: None }
+    }
+
+    /// Returns the wrapped handler.
+    pub fn handler(&mut self) -> &mut H {
+        &mut self.handler
+    }
+
+    /// Update the drag state with an event that's about to be sent to the wrapped handler.
+    /// Returns the `DragStarted` event that should be sent before it, if any. `drag_threshold` is
+    /// only queried when a button is pressed.
+    fn detect(&mut self, event: &Event, drag_threshold: impl FnOnce() -> f64) -> Option<Event> {
+        let mouse_event = match event {
+            Event::Mouse(mouse_event) => mouse_event,
+            Event::Window(WindowEvent::Unfocused) | Event::Window(WindowEvent::WillClose) => {
+                self.state = State::Idle;
+                return None;
+            }
+            _ => return None,
+        };
+
+        match *mouse_event {
+            MouseEvent::CursorMoved { position, modifiers, device } => {
+                self.position = Some(position);
+
+                if let State::Pressed { button, start, threshold } = self.state {
+                    let (dx, dy) = (position.x - start.x, position.y - start.y);
+                    if dx.abs() > threshold || dy.abs() > threshold {
+                        self.state = State::Dragging { button };
+                        return Some(Event::Mouse(MouseEvent::DragStarted {
+                            button,
+                            start,
+                            position,
+                            modifiers,
+                            device,
+                        }));
+                    }
+                }
+            }
+            MouseEvent::ButtonPressed { button, .. } => {
+                if let (State::Idle, Some(start)) = (self.state, self.position) {
+                    let threshold = drag_threshold();
+                    self.state = State::Pressed { button, start, threshold };
+                }
+            }
+            MouseEvent::ButtonReleased { button, .. } => match self.state {
+                State::Pressed { button: pressed, .. } | State::Dragging { button: pressed }
+                    if pressed == button =>
+                {
+                    self.state = State::Idle
+                }
+                _ => {}
+            },
+            // A drag and drop operation takes over the mouse
+            MouseEvent::DragEntered { .. } => self.state = State::Idle,
+            _ => {}
+        }
+
+        None
+    }
+}
+
+impl<H: WindowHandler> WindowHandler for DragDetector<H> {
+    fn on_frame(&mut self, window: &mut Window) {
+        self.handler.on_frame(window);
+    }
+
+    fn on_event(&mut self, window: &mut Window, event: Event) -> EventStatus {
+        if let Some(drag_event) = self.detect(&event, || window.drag_threshold()) {
+            self.handler.on_event(window, drag_event);
+        }
+
+        self.handler.on_event(window, event)
+    }
+
+    fn before_events(&mut self, window: &mut Window) {
+        self.handler.before_events(window);
+    }
+
+    fn after_events(&mut self, window: &mut Window) {
+        self.handler.after_events(window);
+    }
+
+    #[cfg(feature = "opengl")]
+    fn on_present(&mut self, window: &mut Window, info: crate::gl::PresentInfo) {
+        self.handler.on_present(window, info);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use keyboard_types::Modifiers;
+
+    use super::*;
+    use crate::test_events::{moved, pressed, released};
+    use crate::{PointerDevice, PointerType};
+
+    struct NullHandler;
+
+    impl WindowHandler for NullHandler {
+        fn on_frame(&mut self, _window: &mut Window) {}
+
+        fn on_event(&mut self, _window: &mut Window, _event: Event) -> EventStatus {
+            EventStatus::Ignored
+        }
+    }
+
+    const THRESHOLD: f64 = 4.0;
+    const MOUSE: PointerDevice = PointerDevice { id: 1, pointer_type: PointerType::Mouse };
+
+    /// Feed events to a detector and collect the `DragStarted` events it produces.
+    fn detect(detector: &mut DragDetector<NullHandler>, events: &[Event]) -> Vec<MouseEvent> {
+        events
+            .iter()
+            .filter_map(|event| detector.detect(event, || THRESHOLD))
+            .map(|event| match event {
+                Event::Mouse(mouse_event) => mouse_event,
+                event => panic!("unexpected event {:?}", event),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn small_movements_are_clicks() {
+        let mut detector = DragDetector::new(NullHandler);
+        let events = [
+            moved(MOUSE, 10.0, 10.0),
+            pressed(MOUSE, MouseButton::Left),
+            moved(MOUSE, 13.0, 6.0),
+            released(MOUSE, MouseButton::Left),
+        ];
+
+        assert!(detect(&mut detector, &events).is_empty());
+    }
+
+    #[test]
+    fn starts_a_drag_once_past_the_threshold() {
+        let mut detector = DragDetector::new(NullHandler);
+        let events = [
+            moved(MOUSE, 10.0, 10.0),
+            pressed(MOUSE, MouseButton::Right),
+            moved(MOUSE, 12.0, 10.0),
+            moved(MOUSE, 15.0, 10.0),
+            moved(MOUSE, 30.0, 10.0),
+        ];
+
+        assert_eq!(
+            detect(&mut detector, &events),
+            [MouseEvent::DragStarted {
+                button: MouseButton::Right,
+                start: Point::new(10.0, 10.0),
+                position: Point::new(15.0, 10.0),
+                modifiers: Modifiers::empty(),
+                device: MOUSE,
+            }]
+        );
+    }
+
+    #[test]
+    fn only_the_first_button_starts_a_drag() {
+        let mut detector = DragDetector::new(NullHandler);
+        let events = [
+            moved(MOUSE, 0.0, 0.0),
+            pressed(MOUSE, MouseButton::Left),
+            pressed(MOUSE, MouseButton::Right),
+            // Releasing the other button doesn't end the press
+            released(MOUSE, MouseButton::Right),
+            moved(MOUSE, 0.0, 20.0),
+            released(MOUSE, MouseButton::Left),
+            moved(MOUSE, 0.0, 40.0),
+        ];
+
+        let drags = detect(&mut detector, &events);
+        assert_eq!(drags.len(), 1);
+        assert!(matches!(drags[0], MouseEvent::DragStarted { button: MouseButton::Left, .. }));
+    }
+
+    #[test]
+    fn losing_focus_ends_the_press() {
+        let mut detector = DragDetector::new(NullHandler);
+        let events = [
+            moved(MOUSE, 0.0, 0.0),
+            pressed(MOUSE, MouseButton::Left),
+            Event::Window(WindowEvent::Unfocused),
+            moved(MOUSE, 20.0, 20.0),
+        ];
+
+        assert!(detect(&mut detector, &events).is_empty());
+    }
+
+    #[test]
+    fn presses_without_a_known_position_are_ignored() {
+        let mut detector = DragDetector::new(NullHandler);
+        let events =
+            [pressed(MOUSE, MouseButton::Left), moved(MOUSE, 20.0, 20.0), moved(MOUSE, 40.0, 40.0)];
+
+        assert!(detect(&mut detector, &events).is_empty());
+    }
+}
@@ -1,10 +1,12 @@
 use std::path::PathBuf;
+use std::sync::Arc;
 
 use keyboard_types::{KeyboardEvent, Modifiers};
 
-use crate::{Point, WindowInfo};
+use crate::{AnimationFrame, ClipboardFormat, Point, Size, WindowInfo};
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "recording", derive(serde::Serialize, serde::Deserialize))]
 pub enum MouseButton {
     Left,
     Middle,
@@ -14,10 +16,49 @@ pub enum MouseButton {
     Other(u8),
 }
 
+/// The kind of device that generated a pointer event.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "recording", derive(serde::Serialize, serde::Deserialize))]
+pub enum PointerType {
+    Mouse,
+    Touchpad,
+    /// A pen or stylus on a graphics tablet or pen display.
+    Tablet,
+    Touchscreen,
+    /// The platform could not tell what kind of device this is.
+    Unknown,
+}
+
+impl Default for PointerType {
+    fn default() -> Self {
+        Self::Unknown
+    }
+}
+
+/// The device that generated a pointer event.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "recording", derive(serde::Serialize, serde::Deserialize))]
+pub struct PointerDevice {
+    /// A platform-specific identifier for the device. This stays the same for as long as the
+    /// device is connected, but it may be reused after it has been unplugged. `0` is used when
+    /// the platform doesn't provide an identifier.
+    pub id: u64,
+    /// The kind of device.
+    pub pointer_type: PointerType,
+}
+
 /// A scroll movement.
+///
+/// The variant describes the unit the platform reported the movement in. Use
+/// [`ScrollDelta::to_pixels()`] to get a movement in logical pixels regardless of the unit.
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "recording", derive(serde::Serialize, serde::Deserialize))]
 pub enum ScrollDelta {
     /// A line-based scroll movement
+    ///
+    /// A scroll wheel notch is not a single line. On Windows the user's lines per notch setting
+    /// has already been applied, or the characters per notch setting for horizontal scrolling. X11
+    /// doesn't have such a setting, so there every notch is three lines, the Windows default.
     Lines {
         /// The number of horizontal lines scrolled
         x: f32,
@@ -25,6 +66,14 @@ pub enum ScrollDelta {
         /// The number of vertical lines scrolled
         y: f32,
     },
+    /// A page-based scroll movement. This is used on Windows for vertical scrolling when the user
+    /// configured the scroll wheel to scroll one screen at a time.
+    Pages {
+        /// The number of horizontal pages scrolled
+        x: f32,
+        /// The number of vertical pages scrolled
+        y: f32,
+    },
     /// A pixel-based scroll movement
     Pixels {
         /// The number of horizontal pixels scrolled
@@ -34,14 +83,39 @@ pub enum ScrollDelta {
     },
 }
 
+impl ScrollDelta {
+    /// Convert the movement to logical pixels, returned as `(x, y)`.
+    ///
+    /// `line_height` is the height of a single line in logical pixels, and `page_size` is the
+    /// logical size of the area being scrolled. These are only used for line- and page-based
+    /// movements respectively.
+    pub fn to_pixels(&self, line_height: f32, page_size: Size) -> (f32, f32) {
+        match *self {
+            ScrollDelta::Lines { x, y } => (x * line_height, y * line_height),
+            ScrollDelta::Pages { x, y } => {
+                (x * page_size.width as f32, y * page_size.height as f32)
+            }
+            ScrollDelta::Pixels { x, y } => (x, y),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "recording", derive(serde::Serialize, serde::Deserialize))]
 pub enum MouseEvent {
     /// The mouse cursor was moved
+    ///
+    /// While a mouse button is held down the window keeps receiving these events when the cursor
+    /// is dragged outside of it, so the position can be negative or lie beyond the window's size.
+    /// Use [`MouseEvent::position_clamped()`] to get a position inside of the window.
     CursorMoved {
-        /// The logical coordinates of the mouse position
+        /// The logical coordinates of the mouse position, relative to the top left corner of the
+        /// window
         position: Point,
         /// The modifiers that were held down just before the event.
         modifiers: Modifiers,
+        /// The device that moved the cursor.
+        device: PointerDevice,
     },
 
     /// A mouse button was pressed.
@@ -50,6 +124,8 @@ pub enum MouseEvent {
         button: MouseButton,
         /// The modifiers that were held down just before the event.
         modifiers: Modifiers,
+        /// The device the button belongs to.
+        device: PointerDevice,
     },
 
     /// A mouse button was released.
@@ -58,14 +134,69 @@ pub enum MouseEvent {
         button: MouseButton,
         /// The modifiers that were held down just before the event.
         modifiers: Modifiers,
+        /// The device the button belongs to.
+        device: PointerDevice,
+    },
+
+    /// The pressure of a click on a Force Touch trackpad changed. This is sent between a
+    /// [`ButtonPressed`][Self::ButtonPressed] and [`ButtonReleased`][Self::ButtonReleased] event,
+    /// and can be used to implement actions for pressing deeper, like resetting a parameter.
+    ///
+    /// Only available on macOS.
+    PressureChanged {
+        /// The pressure within the current stage, from `0.0` to `1.0`.
+        pressure: f32,
+        /// `1` for a normal click and `2` for a deep press (a "force click"). Drops back to `0`
+        /// right before the button is released.
+        stage: u8,
+        /// The modifiers that were held down just before the event.
+        modifiers: Modifiers,
+        /// The device that was pressed.
+        device: PointerDevice,
+    },
+
+    /// The cursor moved further than the system's drag threshold while a mouse button was held,
+    /// so the press turned into a drag rather than a click. This is sent right before the
+    /// [`CursorMoved`][Self::CursorMoved] event that crossed the threshold. A press that's
+    /// released without this event is a click.
+    ///
+    /// Only sent to handlers wrapped in a [`DragDetector`][crate::drag::DragDetector].
+    DragStarted {
+        /// The button that is held down during the drag.
+        button: MouseButton,
+        /// The logical coordinates where the button was pressed, relative to the top left corner
+        /// of the window
+        start: Point,
+        /// The logical coordinates of the mouse position, relative to the top left corner of the
+        /// window
+        position: Point,
+        /// The modifiers that were held down just before the event.
+        modifiers: Modifiers,
+        /// The device that is being dragged with.
+        device: PointerDevice,
     },
 
     /// The mouse wheel was scrolled.
     WheelScrolled {
-        /// How much was scrolled, in factional lines.
+        /// How much was scrolled, and in which unit.
         delta: ScrollDelta,
         /// The modifiers that were held down just before the event.
         modifiers: Modifiers,
+        /// The device that was scrolled with.
+        device: PointerDevice,
+    },
+
+    /// The user zoomed by scrolling vertically while holding Ctrl, or with a pinch gesture. This
+    /// is only sent when [`WindowOpenOptions::wheel_zoom`][crate::WindowOpenOptions::wheel_zoom]
+    /// is enabled, in which case it replaces the [`WheelScrolled`][Self::WheelScrolled] event.
+    Zoomed {
+        /// The factor to multiply the current zoom level with. This is greater than 1 when zooming
+        /// in. A single notch of a scroll wheel zooms by 25% at the default scroll settings.
+        scale: f32,
+        /// The modifiers that were held down just before the event.
+        modifiers: Modifiers,
+        /// The device that was zoomed with.
+        device: PointerDevice,
     },
 
     /// The mouse cursor entered the window.
@@ -78,52 +209,365 @@ pub enum MouseEvent {
     /// May not be available on all platforms.
     CursorLeft,
 
+    /// Something is being dragged into the window from another application.
+    ///
+    /// Dragging suspends the normal hover events. If the cursor was hovering over the window,
+    /// then a [`CursorLeft`][Self::CursorLeft] event is sent right before this event. No
+    /// `CursorEntered`, `CursorMoved`, or `CursorLeft` events are sent until the drag ends with
+    /// either [`DragLeft`][Self::DragLeft] or [`DragDropped`][Self::DragDropped]. The next cursor
+    /// movement inside the window after that is preceded by a `CursorEntered` event. The events
+    /// for a drag that ends in a drop are thus:
+    ///
+    /// `CursorLeft` (if hovering), `DragEntered`, `DragMoved`..., `DragDropped`, `CursorEntered`,
+    /// `CursorMoved`...
+    ///
+    /// The data is an [`Arc<DropData>`] shared by all events of a drag, so these events can be
+    /// cloned at pointer rates without copying it. Handlers that keep the data after the drop can
+    /// store the `Arc` itself, or take the data out with [`Arc::try_unwrap()`] once the drag's
+    /// other events have been dropped.
+    ///
+    /// On Linux the window receives drags through XDND. Not all drag sources look for drop
+    /// targets inside of other applications' windows, so an embedded window may not receive
+    /// drags from every application.
     DragEntered {
-        /// The logical coordinates of the mouse position
+        /// The logical coordinates of the mouse position, relative to the top left corner of the
+        /// window
         position: Point,
         /// The modifiers that were held down just before the event.
         modifiers: Modifiers,
         /// Data being dragged
-        data: DropData,
+        data: Arc<DropData>,
     },
 
     DragMoved {
-        /// The logical coordinates of the mouse position
+        /// The logical coordinates of the mouse position, relative to the top left corner of the
+        /// window
         position: Point,
         /// The modifiers that were held down just before the event.
         modifiers: Modifiers,
         /// Data being dragged
-        data: DropData,
+        data: Arc<DropData>,
     },
 
+    /// The drag left the window, or it was cancelled. Hover events are sent again afterwards.
     DragLeft,
 
+    /// The dragged data was dropped onto the window. Hover events are sent again afterwards.
     DragDropped {
-        /// The logical coordinates of the mouse position
+        /// The logical coordinates of the mouse position, relative to the top left corner of the
+        /// window
         position: Point,
         /// The modifiers that were held down just before the event.
         modifiers: Modifiers,
         /// Data being dragged
-        data: DropData,
+        data: Arc<DropData>,
     },
 }
 
-#[derive(Debug, Clone)]
+/// The zoom factor for a single notch of a scroll wheel, see [`MouseEvent::Zoomed`].
+const ZOOM_PER_NOTCH: f32 = 1.25;
+/// Most systems scroll three lines per notch by default, which is what all scroll units are
+/// converted to for zooming. Pages are treated as a single notch.
+const ZOOM_LINES_PER_NOTCH: f32 = 3.0;
+/// Precise scrolling deltas are converted to lines using a typical line height.
+const ZOOM_PIXELS_PER_LINE: f32 = 20.0;
+
+impl MouseEvent {
+    /// The logical position of the cursor relative to the top left corner of the window, if this
+    /// event has one. This may lie outside of the window, see [`MouseEvent::CursorMoved`].
+    pub fn position(&self) -> Option<Point> {
+        match self {
+            MouseEvent::CursorMoved { position, .. }
+            | MouseEvent::DragStarted { position, .. }
+            | MouseEvent::DragEntered { position, .. }
+            | MouseEvent::DragMoved { position, .. }
+            | MouseEvent::DragDropped { position, .. } => Some(*position),
+            _ => None,
+        }
+    }
+
+    /// The same as [`MouseEvent::position()`], but with the position clamped to the window's
+    /// logical size. The result always lies between `(0, 0)` and `(size.width, size.height)`.
+    pub fn position_clamped(&self, size: Size) -> Option<Point> {
+        self.position().map(|position| Point {
+            x: position.x.min(size.width).max(0.0),
+            y: position.y.min(size.height).max(0.0),
+        })
+    }
+
+    /// Turn vertical scrolling with Ctrl held into a [`MouseEvent::Zoomed`] event, see
+    /// [`WindowOpenOptions::wheel_zoom`][crate::WindowOpenOptions::wheel_zoom]. Other events are
+    /// returned unchanged.
+    pub(crate) fn into_wheel_zoom(self) -> Self {
+        match self {
+            MouseEvent::WheelScrolled { delta, modifiers, device }
+                if modifiers.contains(Modifiers::CONTROL) =>
+            {
+                let notches = match delta {
+                    ScrollDelta::Lines { y, .. } => y / ZOOM_LINES_PER_NOTCH,
+                    ScrollDelta::Pages { y, .. } => y,
+                    ScrollDelta::Pixels { y, .. } => {
+                        y / ZOOM_PIXELS_PER_LINE / ZOOM_LINES_PER_NOTCH
+                    }
+                };
+                if notches == 0.0 {
+                    return self;
+                }
+
+                MouseEvent::Zoomed { scale: ZOOM_PER_NOTCH.powf(notches), modifiers, device }
+            }
+            event => event,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "recording", derive(serde::Serialize, serde::Deserialize))]
 pub enum WindowEvent {
-    Resized(WindowInfo),
+    /// The window's size or scale factor changed. The [`ResizeSource`] tells handlers that
+    /// negotiate their size with the host whether the host resized the window, so they don't
+    /// report their own size changes back to the host as new requests.
+    Resized(WindowInfo, ResizeSource),
     Focused,
     Unfocused,
+    /// The window is about to be closed. This is sent exactly once, regardless of whether the
+    /// window was closed with [`Window::close()`][crate::Window::close()], with
+    /// [`WindowHandle::close()`][crate::WindowHandle::close()], or by the user. It's always the
+    /// last callback the handler receives, and it's never sent during an event batch.
     WillClose,
+    /// Displays were connected or disconnected, or the configuration of the window's display
+    /// changed. If this changed the window's scale factor, then a [`WindowEvent::Resized`] event
+    /// is sent before this event.
+    ///
+    /// On Windows this is only sent to top level windows, since child windows are not notified
+    /// about display changes.
+    MonitorChanged(MonitorInfo),
+    /// The window's drawable changed in a way that invalidates rendering surfaces created from its
+    /// raw window handle, for instance because the window was moved to a display with a different
+    /// color depth. Software rendering surfaces, like the ones created by `softbuffer`, should be
+    /// recreated when this is received. The window's OpenGL context has already been updated to
+    /// render to the new drawable at this point. This is sent after any
+    /// [`WindowEvent::Resized`] event and before any [`WindowEvent::MonitorChanged`] event caused
+    /// by the same change.
+    ///
+    /// On X11 this is sent when a parented window is reparented by the host, on Windows when the
+    /// color depth of the window's display changes, and on macOS when the view is moved to
+    /// another window or the window is moved to a screen with a different color depth.
+    SurfaceChanged,
+    /// The window was minimized, or the host collapsed it to a size of zero, so nothing it draws
+    /// is visible. The handler can stop rendering until [`WindowEvent::Resumed`] is sent. No
+    /// [`WindowEvent::Resized`] events are sent and the OpenGL context isn't resized in the
+    /// meantime, so the handler never sees a window without any pixels.
+    ///
+    /// On X11 this is sent when the window is unmapped, which also happens for a moment when the
+    /// host reparents it.
+    Suspended,
+    /// The window is visible again after a [`WindowEvent::Suspended`] event. If its size changed
+    /// while it was suspended, then a [`WindowEvent::Resized`] event follows.
+    Resumed,
+    /// The user switched to another keyboard layout. The new layout can be queried with
+    /// [`Window::keyboard_layout()`][crate::Window::keyboard_layout()], for instance to update the
+    /// labels of a virtual keyboard.
+    ///
+    /// On X11 this is also sent when the keymap changes, for instance through `setxkbmap`, which
+    /// may send it more than once. On Windows the layout belongs to the host's GUI thread, so this
+    /// is only sent when that thread's layout changes.
+    KeyboardLayoutChanged,
+    /// Sent right before every call to
+    /// [`WindowHandler::on_frame()`][crate::WindowHandler::on_frame()] while animation frames are
+    /// enabled, see
+    /// [`Window::set_animation_frames()`][crate::Window::set_animation_frames()]. Animations can
+    /// use the frame's timing to run at the same speed on every display instead of assuming
+    /// 60 frames per second.
+    AnimationFrame(AnimationFrame),
+    /// A modifier key was pressed or released, or a lock key like Caps Lock was toggled. This lets
+    /// the handler update hints that depend on the modifiers, like showing that a drag will copy
+    /// while Alt is held, without waiting for the next mouse event. The current modifiers can also
+    /// be queried with [`Window::modifiers()`][crate::Window::modifiers()].
+    ///
+    /// On X11 this is sent regardless of which window has the keyboard focus. On Windows and macOS
+    /// it's only sent while the window has the keyboard focus, right before the key event that
+    /// changed the modifiers.
+    ModifiersChanged(Modifiers),
+}
+
+/// What caused a [`WindowEvent::Resized`] event.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "recording", derive(serde::Serialize, serde::Deserialize))]
+pub enum ResizeSource {
+    /// The handler resized the window with [`Window::resize()`][crate::Window::resize()].
+    Handler,
+    /// The window's scale factor changed, because the window moved to another display, the
+    /// display's scale factor changed, or the host called
+    /// [`WindowHandle::set_scale_factor()`][crate::WindowHandle::set_scale_factor()]. The window
+    /// keeps its logical size where possible.
+    ScaleFactor,
+    /// The host resized the parent window along with this window, the user resized a standalone
+    /// window, or the window was just opened.
+    External,
+}
+
+/// Information about the display the window is currently on.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "recording", derive(serde::Serialize, serde::Deserialize))]
+pub struct MonitorInfo {
+    /// The window's scale factor after the change.
+    pub scale: f64,
+    /// The display's refresh rate in Hz, if it could be determined.
+    pub refresh_rate: Option<f64>,
+}
+
+/// The kind of input device that was connected or disconnected.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "recording", derive(serde::Serialize, serde::Deserialize))]
+pub enum DeviceKind {
+    Pointer(PointerType),
+    Keyboard,
+}
+
+/// An input device was connected or disconnected while the window was open. This can be used to
+/// re-initialize device specific state, like tablet handling.
+///
+/// Currently only available on Linux and Windows.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "recording", derive(serde::Serialize, serde::Deserialize))]
+pub enum DeviceEvent {
+    Added {
+        /// A platform-specific identifier for the device. On Linux this matches
+        /// [`PointerDevice::id`] for pointer devices. On Windows this is only stable between
+        /// `DeviceEvent`s.
+        id: u64,
+        kind: DeviceKind,
+    },
+    Removed {
+        /// The same identifier that was used for the corresponding [`DeviceEvent::Added`] event.
+        id: u64,
+        kind: DeviceKind,
+    },
+}
+
+/// Input methods are used to type text that can't be entered with single key presses, like Chinese,
+/// Japanese, and Korean text. While the user is composing text, the uncommitted preedit text should
+/// be drawn at the text field's cursor, usually underlined. Call
+/// [`Window::set_ime_cursor_area()`][crate::Window::set_ime_cursor_area()] with the cursor's
+/// location so the input method's candidate window appears next to it.
+///
+/// These events are only sent while text input is active, see
+/// [`Window::set_text_input_active()`][crate::Window::set_text_input_active()]. Key presses
+/// consumed by the input method are sent as [`Event::Keyboard`] events with
+/// `Key::Named(NamedKey::Process)`, which don't type any text.
+///
+/// On X11 only the compose sequences of the user's locale, like dead keys, are sent as these
+/// events. Input methods like IBus and Fcitx aren't supported yet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "recording", derive(serde::Serialize, serde::Deserialize))]
+pub enum ImeEvent {
+    /// The text being composed changed. This replaces any previous preedit text, and an empty
+    /// string means that the composition ended without committing any text.
+    Preedit {
+        text: String,
+        /// The selected range within `text` as byte offsets, or `None` if no cursor should be
+        /// drawn. An empty range is a plain cursor.
+        cursor: Option<(usize, usize)>,
+    },
+    /// The composition finished, and the preedit text should be replaced by this text.
+    Commit(String),
+    /// Text input was cleared with
+    /// [`Window::clear_text_input()`][crate::Window::clear_text_input()] while text was being
+    /// composed. The composition has been cancelled, and any preedit text should be discarded.
+    Disabled,
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "recording", derive(serde::Serialize, serde::Deserialize))]
 pub enum Event {
     Mouse(MouseEvent),
+    /// The event's `key` is the character or named key that the pressed key produces with the
+    /// current keyboard layout, while `code` is the physical key, named after the key at that
+    /// position on a US QWERTY keyboard. The `code` doesn't depend on the layout, so input that
+    /// depends on where a key is, like playing notes on the rows of letter keys, should use it
+    /// instead of `key` to work the same way with AZERTY and QWERTZ layouts. It's derived from
+    /// the platform's raw scancode, which [`Window::key_scancode()`][crate::Window::key_scancode()]
+    /// returns while the event is being handled.
+    ///
+    /// Presses sent by the key repeat while a key is held down have `repeat` set, so actions like
+    /// triggering a note can ignore them.
+    ///
+    /// On X11 this requires the XKB extension's detectable auto-repeat. Without it, the X server
+    /// sends repeats as releases followed by presses, which aren't marked as repeats.
     Keyboard(KeyboardEvent),
+    /// Text typed by the user. The characters from consecutive key presses are combined into a
+    /// single event, which is sent before the next key press that doesn't type anything, like
+    /// Backspace or an arrow key, or otherwise at the end of the event batch. The text therefore
+    /// always arrives in the order it was typed, and editing keys are applied after it.
+    ///
+    /// The [`Event::Keyboard`] events for these key presses are still sent first. A text field
+    /// should either insert the characters from those or from this event, but not both.
+    ///
+    /// A dead key press is sent as `Key::Named(NamedKey::Dead)` and doesn't type anything. The
+    /// next key press then carries the composed character, so typing `´` and then `e` types `é`.
+    Text(String),
+    /// Text composed with an input method while text input is active, see [`ImeEvent`].
+    Ime(ImeEvent),
+    /// A key press matched an accelerator registered with
+    /// [`Window::register_accelerator()`][crate::Window::register_accelerator()]. This is sent
+    /// instead of the [`Event::Keyboard`] event for the key press, and returning
+    /// [`EventStatus::Ignored`] passes the key press back to the platform. The key release is
+    /// still sent as a regular keyboard event.
+    Accelerator(u32),
+    /// A key press matched a global shortcut registered with
+    /// [`Window::register_global_shortcut()`][crate::Window::register_global_shortcut()]. This is
+    /// sent even when another application has keyboard focus, and holding the keys down only
+    /// sends it once.
+    GlobalShortcut(u32),
     Window(WindowEvent),
+    Device(DeviceEvent),
+    /// The system is about to go to sleep. This is delivered on a best-effort basis, since the
+    /// system may be suspended before the event loop gets to process it.
+    ///
+    /// On Linux the power events are only available when the system uses systemd-logind or
+    /// elogind.
+    PowerSuspend,
+    /// The system woke up from sleep. OpenGL contexts may have lost their resources, and any
+    /// animation clocks will have jumped ahead by the time the system was asleep.
+    PowerResume,
+    /// System wide settings changed, like the number of lines to scroll per wheel notch, the
+    /// double click time, the drag threshold, or whether animations should be reduced. Handlers
+    /// that cache any of these should query them again.
+    ///
+    /// On Linux this is sent when the XSETTINGS manager, which is part of most desktop
+    /// environments, publishes new settings. On Windows this is sent for `WM_SETTINGCHANGE`, which
+    /// parented windows receive through the host's top level window on the same thread. On macOS
+    /// this is sent when the accessibility display options or the preferred scroller style change.
+    SystemSettingsChanged,
+    /// The contents of the system clipboard changed, including when the application itself
+    /// copied something. This can be used to update the state of a paste button.
+    ///
+    /// On X11 this requires the XFixes extension. On macOS the clipboard is checked once per
+    /// frame, since there is no notification for this.
+    ClipboardChanged,
+    /// The clipboard's text in response to
+    /// [`Window::request_clipboard_contents()`][crate::Window::request_clipboard_contents()], or
+    /// `None` if the clipboard is empty or doesn't contain any text.
+    ClipboardData(Option<String>),
+    /// The clipboard's data in a custom format in response to
+    /// [`Window::request_clipboard_data()`][crate::Window::request_clipboard_data()].
+    ClipboardFormatData {
+        format: ClipboardFormat,
+        /// `None` if the clipboard doesn't contain data in this format.
+        data: Option<Vec<u8>>,
+    },
+    /// A drag started with [`Window::start_drag()`][crate::Window::start_drag()] has ended. This
+    /// contains the effect the drop target performed, or `None` if the drag was cancelled, if the
+    /// target rejected the data, or if the drag couldn't be started.
+    ///
+    /// The mouse button release that ends the drag is not sent to the handler, so this event
+    /// takes its place.
+    DragFinished(Option<DropEffect>),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "recording", derive(serde::Serialize, serde::Deserialize))]
 pub enum DropEffect {
     Copy,
     Move,
@@ -132,9 +576,42 @@ pub enum DropEffect {
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "recording", derive(serde::Serialize, serde::Deserialize))]
 pub enum DropData {
     None,
     Files(Vec<PathBuf>),
+    /// The raw contents of one of the formats registered with
+    /// [`WindowOpenOptions::drop_formats`][crate::WindowOpenOptions::drop_formats]. This takes
+    /// precedence over [`DropData::Files`] when the dragged data offers both.
+    Custom {
+        /// The format as it was registered.
+        format: String,
+        data: Vec<u8>,
+    },
+}
+
+impl DropData {
+    /// A shared [`DropData::None`], so the backends don't allocate one for every drag that doesn't
+    /// carry any usable data, or whenever they forget the data of a drag that has ended.
+    pub(crate) fn none() -> Arc<DropData> {
+        thread_local! {
+            static NONE: Arc<DropData> = Arc::new(DropData::None);
+        }
+
+        NONE.with(Arc::clone)
+    }
+}
+
+/// Data dragged out of a window with [`Window::start_drag()`][crate::Window::start_drag()].
+#[derive(Debug, Clone, PartialEq)]
+pub enum DragData {
+    /// Local files, for instance a rendered audio clip that can be dropped into a DAW's
+    /// arrangement. The paths should be absolute.
+    Files(Vec<PathBuf>),
+    /// Raw data in a format that's understood by the drop target. The format is an XDND target on
+    /// Linux, a registered clipboard format on Windows, and a pasteboard type on macOS, like for
+    /// [`WindowOpenOptions::drop_formats`][crate::WindowOpenOptions::drop_formats].
+    Custom { format: String, data: Vec<u8> },
 }
 
 /// Return value for [WindowHandler::on_event](`crate::WindowHandler::on_event()`),
@@ -143,8 +620,8 @@ pub enum DropData {
 ///
 /// For most event types, this value won't have any effect. This is the case
 /// when there is no clear meaning of passing back the event to the platform,
-/// or it isn't obviously useful. Currently, only [`Event::Keyboard`] variants
-/// are supported.
+/// or it isn't obviously useful. Currently, only [`Event::Keyboard`] and
+/// [`Event::Accelerator`] variants are supported.
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum EventStatus {
     /// Event was handled by your window and will not be sent back to the
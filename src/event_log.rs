@@ -0,0 +1,204 @@
+//! A log of the most recent events delivered to every window, for diagnosing input problems in
+//! hosts you don't have access to.
+//!
+//! With the `event-log` feature enabled, every event sent to a window's handler is stored in a
+//! ring buffer along with the raw platform event it was translated from and the status the handler
+//! returned. Nothing is written anywhere until the log is dumped, so the feature can be left
+//! enabled in release builds. A plugin can then offer a way to copy or save the dump when a user
+//! reports that, say, the keyboard doesn't work in a particular host.
+//!
+//! ```no_run
+//! // For instance from a "copy diagnostics" button
+//! let dump = baseview::event_log::dump();
+//! # drop(dump);
+//! ```
+//!
+//! The raw events are the `x11rb` events on Linux, the window messages on Windows, and the
+//! `NSEvent` being handled on macOS. Events that aren't caused by a platform event, like the ones
+//! sent from a window's frame timer, are logged without one.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::ptr::null_mut;
+use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use crate::{Event, EventStatus, Window, WindowHandler};
+
+/// The number of entries kept when [`set_capacity()`] hasn't been called.
+pub const DEFAULT_CAPACITY: usize = 512;
+
+static LOG: AtomicPtr<Mutex<EventLog>> = AtomicPtr::new(null_mut());
+
+/// Used to tell the windows apart in the dump.
+static NEXT_WINDOW_NUMBER: AtomicUsize = AtomicUsize::new(1);
+
+thread_local! {
+    /// The platform event this thread is currently handling, see [`RawEvent::set()`].
+    static RAW_EVENT: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+struct Entry {
+    time: Instant,
+    window: usize,
+    raw_event: Option<String>,
+    event: String,
+    status: EventStatus,
+}
+
+struct EventLog {
+    entries: VecDeque<Entry>,
+    capacity: usize,
+    start: Instant,
+}
+
+/// Set the number of events that are kept, dropping the oldest ones if there are more than that.
+/// Setting it to zero stops logging altogether. Defaults to [`DEFAULT_CAPACITY`].
+pub fn set_capacity(capacity: usize) {
+    let mut log = log().lock().unwrap();
+    log.capacity = capacity;
+    while log.entries.len() > capacity {
+        log.entries.pop_front();
+    }
+}
+
+/// Remove all logged events.
+pub fn clear() {
+    log().lock().unwrap().entries.clear();
+}
+
+/// Format the logged events, oldest first. Every entry lists the time since the log was created,
+/// the window that received the event, the raw platform event, the event the handler received,
+/// and the status the handler returned.
+pub fn dump() -> String {
+    let log = log().lock().unwrap();
+
+    let mut dump = format!("baseview event log, {} events\n", log.entries.len());
+    for entry in &log.entries {
+        let time = entry.time.duration_since(log.start).as_secs_f64();
+        let _ = writeln!(dump, "\n[{:>12.6}] window {}", time, entry.window);
+        match &entry.raw_event {
+            Some(raw_event) => {
+                let _ = writeln!(dump, "    raw:    {}", raw_event);
+            }
+            None => {
+                let _ = writeln!(dump, "    raw:    -");
+            }
+        }
+        // Line up the pretty-printed event's fields with the label
+        let _ = writeln!(dump, "    event:  {}", entry.event.replace('\n', "\n            "));
+        let _ = writeln!(dump, "    status: {:?}", entry.status);
+    }
+
+    dump
+}
+
+fn log() -> &'static Mutex<EventLog> {
+    let mut log = LOG.load(Ordering::Acquire);
+    if log.is_null() {
+        let new = Box::into_raw(Box::new(Mutex::new(EventLog {
+            entries: VecDeque::new(),
+            capacity: DEFAULT_CAPACITY,
+            start: Instant::now(),
+        })));
+        log = match LOG.compare_exchange(null_mut(), new, Ordering::AcqRel, Ordering::Acquire) {
+            Ok(_) => new,
+            Err(existing) => {
+                // Another thread got there first
+                drop(unsafe { Box::from_raw(new) });
+                existing
+            }
+        };
+    }
+
+    unsafe { &*log }
+}
+
+fn is_enabled() -> bool {
+    log().lock().unwrap().capacity > 0
+}
+
+/// Marks the platform event the current thread is handling until it's dropped. Nested platform
+/// events, like messages sent from within a window procedure, restore the outer one afterwards.
+pub(crate) struct RawEvent {
+    previous: Option<String>,
+}
+
+impl RawEvent {
+    /// `describe` is only called while logging is enabled.
+    pub fn set(describe: impl FnOnce() -> String) -> Self {
+        let raw_event = if is_enabled() { Some(describe()) } else { None };
+
+        Self { previous: RAW_EVENT.with(|current| current.replace(raw_event)) }
+    }
+}
+
+impl Drop for RawEvent {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        RAW_EVENT.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+/// Logs the events sent to the wrapped handler. Every window's handler is wrapped in this when
+/// the `event-log` feature is enabled.
+pub(crate) struct EventLogger<H: WindowHandler> {
+    handler: H,
+    window: usize,
+}
+
+impl<H: WindowHandler> EventLogger<H> {
+    pub fn new(handler: H) -> Self {
+        Self { handler, window: NEXT_WINDOW_NUMBER.fetch_add(1, Ordering::Relaxed) }
+    }
+}
+
+impl<H: WindowHandler> WindowHandler for EventLogger<H> {
+    fn on_frame(&mut self, window: &mut Window) {
+        self.handler.on_frame(window);
+    }
+
+    fn on_event(&mut self, window: &mut Window, event: Event) -> EventStatus {
+        // Formatted up front, since the handler takes ownership of the event
+        let logged_event = if is_enabled() { Some(format!("{:#?}", event)) } else { None };
+
+        let status = self.handler.on_event(window, event);
+
+        if let Some(event) = logged_event {
+            let raw_event = RAW_EVENT.with(|current| current.borrow().clone());
+
+            // The capacity may have changed while the handler was running
+            let mut log = log().lock().unwrap();
+            if log.capacity == 0 {
+                return status;
+            }
+            while log.entries.len() >= log.capacity {
+                log.entries.pop_front();
+            }
+            log.entries.push_back(Entry {
+                time: Instant::now(),
+                window: self.window,
+                raw_event,
+                event,
+                status,
+            });
+        }
+
+        status
+    }
+
+    fn before_events(&mut self, window: &mut Window) {
+        self.handler.before_events(window);
+    }
+
+    fn after_events(&mut self, window: &mut Window) {
+        self.handler.after_events(window);
+    }
+
+    #[cfg(feature = "opengl")]
+    fn on_present(&mut self, window: &mut Window, info: crate::gl::PresentInfo) {
+        self.handler.on_present(window, info);
+    }
+}
@@ -0,0 +1,197 @@
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+/// The interval of the frame timer that calls
+/// [`WindowHandler::on_frame()`][crate::WindowHandler::on_frame()] when the refresh rate of the
+/// window's display isn't known. Otherwise the timer runs at the display's refresh rate.
+pub(crate) const FRAME_INTERVAL: Duration = Duration::from_millis(15);
+
+/// The shortest interval of the frame timer, so a display reporting a bogus refresh rate can't
+/// make it spin.
+const MIN_FRAME_INTERVAL: Duration = Duration::from_millis(4);
+
+/// Counters for a window's frame timer, see
+/// [`Window::frame_stats()`][crate::Window::frame_stats()].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameStats {
+    /// The number of times [`WindowHandler::on_frame()`][crate::WindowHandler::on_frame()] has
+    /// been called since the window was opened.
+    pub frames: u64,
+    /// The number of frames that were dropped because the previous frame, or the events handled
+    /// in between, took longer than the frame interval, which is the refresh period of the
+    /// window's display. Frames are dropped rather than queued up, so a slow handler is called
+    /// less often instead of falling further and further behind.
+    ///
+    /// This is always zero for windows with an
+    /// [`external_frame_clock`][crate::WindowOpenOptions::external_frame_clock], since the host
+    /// decides when their frames are drawn, and for windows driven by a virtual clock.
+    pub skipped_frames: u64,
+}
+
+/// The timing of a frame, sent as
+/// [`WindowEvent::AnimationFrame`][crate::WindowEvent::AnimationFrame] right before
+/// [`WindowHandler::on_frame()`][crate::WindowHandler::on_frame()] while animation frames are
+/// enabled, see [`Window::set_animation_frames()`][crate::Window::set_animation_frames()].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "recording", derive(serde::Serialize, serde::Deserialize))]
+pub struct AnimationFrame {
+    /// The time since the previous frame, or zero for the first frame. Animations should advance
+    /// by this much instead of by a fixed step, since frames can be dropped.
+    pub elapsed: Duration,
+    /// The time between two refreshes of the display the window is on, if its refresh rate is
+    /// known. This is about 8.3 ms on a 120 Hz display and 16.7 ms on a 60 Hz display.
+    pub refresh_period: Option<Duration>,
+}
+
+/// Counts the frames of a window's frame timer. The skipped frames are derived from the time
+/// between frames, since none of the platforms' timers report the ticks they've dropped.
+#[derive(Debug, Default)]
+pub(crate) struct FrameCounter {
+    stats: Cell<FrameStats>,
+    last_frame: Cell<Option<Instant>>,
+    animation_frames: Cell<bool>,
+    /// The refresh rate of the window's display, which is looked up when the window is opened and
+    /// when the display changes, since that can take a round trip on X11.
+    refresh_rate: Cell<Option<f64>>,
+    /// The time of the next frame if it's drawn on a virtual clock, see
+    /// [`set_virtual_time()`][Self::set_virtual_time()].
+    virtual_time: Cell<Option<Instant>>,
+}
+
+impl FrameCounter {
+    /// Must be called right before every call to `on_frame()`. Returns the frame's timing while
+    /// animation frames are enabled. Skipped frames aren't counted if `external_clock` is set or
+    /// if the frame is drawn on a virtual clock, since there's no frame interval to measure them
+    /// against.
+    pub fn frame(&self, external_clock: bool) -> Option<AnimationFrame> {
+        let virtual_time = self.virtual_time.take();
+        let now = virtual_time.unwrap_or_else(Instant::now);
+        let mut stats = self.stats.get();
+        stats.frames += 1;
+
+        let last_frame = self.last_frame.replace(Some(now));
+        if let (Some(last_frame), false) = (last_frame, external_clock || virtual_time.is_some()) {
+            stats.skipped_frames += skipped_frames(now.duration_since(last_frame), self.interval());
+        }
+
+        self.stats.set(stats);
+
+        self.animation_frames.get().then(|| AnimationFrame {
+            elapsed: last_frame.map_or(Duration::ZERO, |last_frame| now.duration_since(last_frame)),
+            refresh_period: refresh_period(self.refresh_rate.get()),
+        })
+    }
+
+    /// Time the next frame at `time` instead of at the current time. The virtual clock calls this
+    /// right before a frame is drawn on it.
+    #[cfg(feature = "virtual-clock")]
+    pub fn set_virtual_time(&self, time: Instant) {
+        self.virtual_time.set(Some(time));
+    }
+
+    pub fn set_animation_frames(&self, enabled: bool) {
+        self.animation_frames.set(enabled);
+    }
+
+    /// Called when the window is opened, and when its display or the display's configuration
+    /// changed. Returns whether the [`interval()`][Self::interval()] changed, in which case the
+    /// frame timer needs to be restarted.
+    pub fn set_refresh_rate(&self, refresh_rate: Option<f64>) -> bool {
+        let interval = self.interval();
+        self.refresh_rate.set(refresh_rate);
+        self.interval() != interval
+    }
+
+    /// The interval of the window's frame timer, which is the refresh period of the window's
+    /// display so frames are drawn at the display's pace.
+    pub fn interval(&self) -> Duration {
+        frame_interval(self.refresh_rate.get())
+    }
+
+    pub fn stats(&self) -> FrameStats {
+        self.stats.get()
+    }
+}
+
+/// The number of frames skipped between two frames that were `elapsed` apart. Timers aren't
+/// precise, so a frame only counts as skipped once the next one is closer to the frame after it
+/// than to its own time slot.
+fn skipped_frames(elapsed: Duration, interval: Duration) -> u64 {
+    let intervals = ((elapsed + interval / 2).as_nanos() / interval.as_nanos()) as u64;
+    intervals.saturating_sub(1)
+}
+
+fn refresh_period(refresh_rate: Option<f64>) -> Option<Duration> {
+    refresh_rate
+        .filter(|&refresh_rate| refresh_rate > 0.0 && refresh_rate.is_finite())
+        .map(|refresh_rate| Duration::from_secs_f64(1.0 / refresh_rate))
+}
+
+fn frame_interval(refresh_rate: Option<f64>) -> Duration {
+    refresh_period(refresh_rate).map_or(FRAME_INTERVAL, |period| period.max(MIN_FRAME_INTERVAL))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interval_follows_refresh_rate() {
+        assert_eq!(frame_interval(Some(50.0)), Duration::from_millis(20));
+        assert_eq!(frame_interval(Some(125.0)), Duration::from_millis(8));
+    }
+
+    #[test]
+    fn interval_falls_back_without_refresh_rate() {
+        assert_eq!(frame_interval(None), FRAME_INTERVAL);
+        assert_eq!(frame_interval(Some(0.0)), FRAME_INTERVAL);
+        assert_eq!(frame_interval(Some(f64::NAN)), FRAME_INTERVAL);
+    }
+
+    #[test]
+    fn interval_is_clamped() {
+        assert_eq!(frame_interval(Some(1000.0)), MIN_FRAME_INTERVAL);
+    }
+
+    #[test]
+    fn skipped_frames_use_the_interval() {
+        let interval = Duration::from_millis(10);
+        assert_eq!(skipped_frames(Duration::from_millis(12), interval), 0);
+        assert_eq!(skipped_frames(Duration::from_millis(16), interval), 1);
+        assert_eq!(skipped_frames(Duration::from_millis(31), interval), 2);
+        assert_eq!(skipped_frames(Duration::ZERO, interval), 0);
+    }
+
+    #[test]
+    fn external_clock_skips_no_frames() {
+        let counter = FrameCounter::default();
+        counter.frame(true);
+        std::thread::sleep(FRAME_INTERVAL * 3);
+        counter.frame(true);
+        assert_eq!(counter.stats(), FrameStats { frames: 2, skipped_frames: 0 });
+    }
+
+    #[test]
+    fn refresh_rate_change_is_reported() {
+        let counter = FrameCounter::default();
+        assert!(counter.set_refresh_rate(Some(120.0)));
+        assert!(!counter.set_refresh_rate(Some(120.0)));
+        assert!(counter.set_refresh_rate(None));
+        assert_eq!(counter.interval(), FRAME_INTERVAL);
+    }
+
+    #[cfg(feature = "virtual-clock")]
+    #[test]
+    fn virtual_frames_are_timed_on_the_clock() {
+        let counter = FrameCounter::default();
+        counter.set_animation_frames(true);
+        let start = Instant::now();
+
+        counter.set_virtual_time(start);
+        assert_eq!(counter.frame(false).unwrap().elapsed, Duration::ZERO);
+        // However long it took in real time, frames on a virtual clock are never skipped
+        counter.set_virtual_time(start + Duration::from_millis(45));
+        assert_eq!(counter.frame(false).unwrap().elapsed, Duration::from_millis(45));
+        assert_eq!(counter.stats(), FrameStats { frames: 2, skipped_frames: 0 });
+    }
+}
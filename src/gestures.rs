@@ -0,0 +1,400 @@
+//! Recognizing taps, long presses, and pans from touchscreen and pen input.
+//!
+//! Touchscreens and pens are reported as regular mouse events, with the [`PointerDevice`] telling
+//! them apart from a mouse. [`GestureRecognizer`] wraps a [`GestureHandler`], passes every event
+//! on to it unchanged, and additionally calls [`GestureHandler::on_gesture()`] when the input from
+//! those devices forms a gesture:
+//!
+//! ```no_run
+//! # use baseview::{Event, EventStatus, Window, WindowHandler, WindowOpenOptions};
+//! # use baseview::gestures::{Gesture, GestureHandler, GestureRecognizer};
+//! # struct MyHandler;
+//! # impl WindowHandler for MyHandler {
+//! #     fn on_frame(&mut self, _window: &mut Window) {}
+//! #     fn on_event(&mut self, _window: &mut Window, _event: Event) -> EventStatus {
+//! #         EventStatus::Ignored
+//! #     }
+//! # }
+//! impl GestureHandler for MyHandler {
+//!     fn on_gesture(&mut self, _window: &mut Window, gesture: Gesture) {
+//!         println!("{:?}", gesture);
+//!     }
+//! }
+//!
+//! # fn options() -> WindowOpenOptions { unimplemented!() }
+//! Window::open_blocking(options(), |_| GestureRecognizer::new(MyHandler)).unwrap();
+//! ```
+//!
+//! Pinching and other multi-touch gestures aren't recognized. baseview only reports the primary
+//! touch point as promoted mouse events on every platform. Windows does report the other contacts
+//! through `WM_POINTER` messages, but baseview has no events to pass them on with yet.
+
+use std::time::{Duration, Instant};
+
+use crate::{
+    Event, EventStatus, MouseButton, MouseEvent, Point, PointerDevice, PointerType, Window,
+    WindowEvent, WindowHandler,
+};
+
+/// The phase of a continuous gesture.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum GesturePhase {
+    Started,
+    Changed,
+    Ended,
+    /// The gesture was interrupted, for instance because the window lost focus. Any changes made
+    /// during the gesture should be reverted.
+    Cancelled,
+}
+
+/// A gesture recognized by [`GestureRecognizer`]. All positions are logical coordinates relative
+/// to the top left corner of the window.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Gesture {
+    /// The touchscreen or pen was pressed and released again without moving.
+    Tap { position: Point, device: PointerDevice },
+    /// The touchscreen or pen was pressed without moving for
+    /// [`GestureConfig::long_press_duration`]. This is sent while it's still being pressed, and no
+    /// other gesture follows until it's released.
+    LongPress { position: Point, device: PointerDevice },
+    /// The touchscreen or pen was moved while pressed.
+    Pan {
+        phase: GesturePhase,
+        /// Where the touchscreen or pen was first pressed.
+        start: Point,
+        position: Point,
+        device: PointerDevice,
+    },
+}
+
+/// The thresholds used by [`GestureRecognizer`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GestureConfig {
+    /// How far in logical pixels the touch point may move before a press becomes a pan instead of
+    /// a tap or a long press.
+    pub slop: f64,
+    /// How long a press has to be held for a long press.
+    pub long_press_duration: Duration,
+    /// Whether pen input is recognized as well. Touchscreen input is always recognized.
+    pub include_pens: bool,
+}
+
+impl Default for GestureConfig {
+    fn default() -> Self {
+        Self { slop: 8.0, long_press_duration: Duration::from_millis(500), include_pens: true }
+    }
+}
+
+/// A [`WindowHandler`] that receives the gestures recognized by [`GestureRecognizer`].
+pub trait GestureHandler: WindowHandler {
+    fn on_gesture(&mut self, window: &mut Window, gesture: Gesture);
+}
+
+#[derive(Debug, Copy, Clone)]
+enum State {
+    Idle,
+    Pressed { start: Point, position: Point, time: Instant, device: PointerDevice },
+    LongPressed,
+    Panning { start: Point, position: Point, device: PointerDevice },
+}
+
+/// A [`WindowHandler`] that recognizes gestures in the events sent to the wrapped handler. See the
+/// [module documentation][self] for an example.
+///
+/// Every event is still passed on to the wrapped handler, and gestures are sent right after the
+/// event that completed them. Long presses are detected on the next event or frame after the
+/// press has been held long enough.
+pub struct GestureRecognizer<H: GestureHandler> {
+    handler: H,
+    recognizer: Recognizer,
+}
+
+impl<H: GestureHandler> GestureRecognizer<H> {
+    pub fn new(handler: H) -> Self {
+        Self::with_config(handler, GestureConfig::default())
+    }
+
+    pub fn with_config(handler: H, config: GestureConfig) -> Self {
+        Self { handler, recognizer: Recognizer::new(config) }
+    }
+
+    /// Returns the wrapped handler.
+    pub fn handler(&mut self) -> &mut H {
+        &mut self.handler
+    }
+}
+
+/// The gesture state machine, kept apart from the handler so gestures can be collected without a
+/// window.
+struct Recognizer {
+    config: GestureConfig,
+    state: State,
+    /// The last known position of the touch point. The platforms send a cursor movement before
+    /// a press, since button events don't contain a position.
+    position: Option<Point>,
+}
+
+impl Recognizer {
+    fn new(config: GestureConfig) -> Self {
+        Self { config, state: State::Idle, position: None }
+    }
+
+    fn is_gesture_device(&self, device: PointerDevice) -> bool {
+        match device.pointer_type {
+            PointerType::Touchscreen => true,
+            PointerType::Tablet => self.config.include_pens,
+            _ => false,
+        }
+    }
+
+    fn check_long_press(&mut self, now: Instant, mut on_gesture: impl FnMut(Gesture)) {
+        if let State::Pressed { position, time, device, .. } = self.state {
+            if now.saturating_duration_since(time) >= self.config.long_press_duration {
+                self.state = State::LongPressed;
+                on_gesture(Gesture::LongPress { position, device });
+            }
+        }
+    }
+
+    fn cancel(&mut self, mut on_gesture: impl FnMut(Gesture)) {
+        if let State::Panning { start, position, device } = self.state {
+            let phase = GesturePhase::Cancelled;
+            on_gesture(Gesture::Pan { phase, start, position, device });
+        }
+
+        self.state = State::Idle;
+    }
+
+    /// Update the gesture state with an event that was just sent to the wrapped handler, which
+    /// arrived at `now`.
+    fn recognize(&mut self, event: &Event, now: Instant, mut on_gesture: impl FnMut(Gesture)) {
+        self.check_long_press(now, &mut on_gesture);
+
+        let mouse_event = match event {
+            Event::Mouse(mouse_event) => mouse_event,
+            Event::Window(WindowEvent::Unfocused) | Event::Window(WindowEvent::WillClose) => {
+                self.cancel(on_gesture);
+                return;
+            }
+            _ => return,
+        };
+
+        match *mouse_event {
+            MouseEvent::CursorMoved { position, device, .. } if self.is_gesture_device(device) => {
+                self.position = Some(position);
+
+                match self.state {
+                    State::Pressed { start, time, .. } => {
+                        let (dx, dy) = (position.x - start.x, position.y - start.y);
+                        if (dx * dx + dy * dy).sqrt() > self.config.slop {
+                            self.state = State::Panning { start, position, device };
+                            let phase = GesturePhase::Started;
+                            on_gesture(Gesture::Pan { phase, start, position, device });
+                        } else {
+                            self.state = State::Pressed { start, position, time, device };
+                        }
+                    }
+                    State::Panning { start, .. } => {
+                        self.state = State::Panning { start, position, device };
+                        let phase = GesturePhase::Changed;
+                        on_gesture(Gesture::Pan { phase, start, position, device });
+                    }
+                    State::Idle | State::LongPressed => {}
+                }
+            }
+            MouseEvent::ButtonPressed { button: MouseButton::Left, device, .. }
+                if self.is_gesture_device(device) =>
+            {
+                if let Some(position) = self.position {
+                    self.state = State::Pressed { start: position, position, time: now, device };
+                }
+            }
+            MouseEvent::ButtonReleased { button: MouseButton::Left, device, .. }
+                if self.is_gesture_device(device) =>
+            {
+                match self.state {
+                    State::Pressed { position, device, .. } => {
+                        on_gesture(Gesture::Tap { position, device });
+                    }
+                    State::Panning { start, position, device } => {
+                        let phase = GesturePhase::Ended;
+                        on_gesture(Gesture::Pan { phase, start, position, device });
+                    }
+                    State::Idle | State::LongPressed => {}
+                }
+
+                self.state = State::Idle;
+            }
+            // Touch input is interrupted when a drag and drop operation takes over
+            MouseEvent::DragEntered { .. } => self.cancel(on_gesture),
+            _ => {}
+        }
+    }
+}
+
+impl<H: GestureHandler> WindowHandler for GestureRecognizer<H> {
+    fn on_frame(&mut self, window: &mut Window) {
+        let handler = &mut self.handler;
+        self.recognizer.check_long_press(Instant::now(), |gesture| {
+            handler.on_gesture(window, gesture);
+        });
+
+        self.handler.on_frame(window);
+    }
+
+    fn on_event(&mut self, window: &mut Window, event: Event) -> EventStatus {
+        // The event is needed again after the handler is done with it
+        let status = self.handler.on_event(window, event.clone());
+
+        let handler = &mut self.handler;
+        self.recognizer.recognize(&event, Instant::now(), |gesture| {
+            handler.on_gesture(window, gesture);
+        });
+
+        status
+    }
+
+    fn before_events(&mut self, window: &mut Window) {
+        self.handler.before_events(window);
+    }
+
+    fn after_events(&mut self, window: &mut Window) {
+        self.handler.after_events(window);
+    }
+
+    #[cfg(feature = "opengl")]
+    fn on_present(&mut self, window: &mut Window, info: crate::gl::PresentInfo) {
+        self.handler.on_present(window, info);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_events::{moved, pressed, released};
+
+    const TOUCH: PointerDevice = PointerDevice { id: 1, pointer_type: PointerType::Touchscreen };
+    const PEN: PointerDevice = PointerDevice { id: 2, pointer_type: PointerType::Tablet };
+    const MOUSE: PointerDevice = PointerDevice { id: 3, pointer_type: PointerType::Mouse };
+
+    /// Feed events that all arrive at the same time to a recognizer and collect its gestures.
+    fn recognize(recognizer: &mut Recognizer, events: &[Event], now: Instant) -> Vec<Gesture> {
+        let mut gestures = Vec::new();
+        for event in events {
+            recognizer.recognize(event, now, |gesture| gestures.push(gesture));
+        }
+
+        gestures
+    }
+
+    fn pan(phase: GesturePhase, start: (f64, f64), position: (f64, f64)) -> Gesture {
+        Gesture::Pan {
+            phase,
+            start: Point::new(start.0, start.1),
+            position: Point::new(position.0, position.1),
+            device: TOUCH,
+        }
+    }
+
+    #[test]
+    fn recognizes_taps() {
+        let mut recognizer = Recognizer::new(GestureConfig::default());
+        let events = [
+            moved(TOUCH, 10.0, 10.0),
+            pressed(TOUCH, MouseButton::Left),
+            moved(TOUCH, 12.0, 13.0),
+            released(TOUCH, MouseButton::Left),
+        ];
+
+        assert_eq!(
+            recognize(&mut recognizer, &events, Instant::now()),
+            [Gesture::Tap { position: Point::new(12.0, 13.0), device: TOUCH }]
+        );
+    }
+
+    #[test]
+    fn recognizes_pans() {
+        let mut recognizer = Recognizer::new(GestureConfig::default());
+        let events = [
+            moved(TOUCH, 0.0, 0.0),
+            pressed(TOUCH, MouseButton::Left),
+            moved(TOUCH, 5.0, 5.0),
+            moved(TOUCH, 20.0, 0.0),
+            moved(TOUCH, 30.0, 0.0),
+            released(TOUCH, MouseButton::Left),
+        ];
+
+        assert_eq!(
+            recognize(&mut recognizer, &events, Instant::now()),
+            [
+                pan(GesturePhase::Started, (0.0, 0.0), (20.0, 0.0)),
+                pan(GesturePhase::Changed, (0.0, 0.0), (30.0, 0.0)),
+                pan(GesturePhase::Ended, (0.0, 0.0), (30.0, 0.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn recognizes_long_presses() {
+        let config = GestureConfig::default();
+        let mut recognizer = Recognizer::new(config);
+        let start = Instant::now();
+
+        let events = [moved(TOUCH, 10.0, 10.0), pressed(TOUCH, MouseButton::Left)];
+        assert!(recognize(&mut recognizer, &events, start).is_empty());
+
+        let mut gestures = Vec::new();
+        recognizer.check_long_press(start + config.long_press_duration / 2, |g| gestures.push(g));
+        assert!(gestures.is_empty());
+        recognizer.check_long_press(start + config.long_press_duration, |g| gestures.push(g));
+        assert_eq!(
+            gestures,
+            [Gesture::LongPress { position: Point::new(10.0, 10.0), device: TOUCH }]
+        );
+
+        // Nothing else follows until the touch point is released
+        let later = start + config.long_press_duration * 2;
+        let events = [moved(TOUCH, 50.0, 50.0), released(TOUCH, MouseButton::Left)];
+        assert!(recognize(&mut recognizer, &events, later).is_empty());
+    }
+
+    #[test]
+    fn cancels_pans_when_focus_is_lost() {
+        let mut recognizer = Recognizer::new(GestureConfig::default());
+        let events = [
+            moved(TOUCH, 0.0, 0.0),
+            pressed(TOUCH, MouseButton::Left),
+            moved(TOUCH, 0.0, 20.0),
+            Event::Window(WindowEvent::Unfocused),
+            released(TOUCH, MouseButton::Left),
+        ];
+
+        assert_eq!(
+            recognize(&mut recognizer, &events, Instant::now()),
+            [
+                pan(GesturePhase::Started, (0.0, 0.0), (0.0, 20.0)),
+                pan(GesturePhase::Cancelled, (0.0, 0.0), (0.0, 20.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn only_recognizes_touch_and_pen_input() {
+        let events = |device| {
+            [
+                moved(device, 0.0, 0.0),
+                pressed(device, MouseButton::Left),
+                released(device, MouseButton::Left),
+            ]
+        };
+
+        let mut recognizer = Recognizer::new(GestureConfig::default());
+        assert!(recognize(&mut recognizer, &events(MOUSE), Instant::now()).is_empty());
+        assert_eq!(recognize(&mut recognizer, &events(PEN), Instant::now()).len(), 1);
+
+        let config = GestureConfig { include_pens: false, ..GestureConfig::default() };
+        let mut recognizer = Recognizer::new(config);
+        assert!(recognize(&mut recognizer, &events(PEN), Instant::now()).is_empty());
+        assert_eq!(recognize(&mut recognizer, &events(TOUCH), Instant::now()).len(), 1);
+    }
+}
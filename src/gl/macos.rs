@@ -1,30 +1,35 @@
 use std::ffi::c_void;
 use std::str::FromStr;
+use std::time::Instant;
 
 use raw_window_handle::RawWindowHandle;
 
 use cocoa::appkit::{
-    NSOpenGLContext, NSOpenGLContextParameter, NSOpenGLPFAAccelerated, NSOpenGLPFAAlphaSize,
-    NSOpenGLPFAColorSize, NSOpenGLPFADepthSize, NSOpenGLPFADoubleBuffer, NSOpenGLPFAMultisample,
+    NSOpenGLContext, NSOpenGLContextParameter, NSOpenGLPFAAccelerated,
+    NSOpenGLPFAAllowOfflineRenderers, NSOpenGLPFAAlphaSize, NSOpenGLPFAColorSize,
+    NSOpenGLPFADepthSize, NSOpenGLPFADoubleBuffer, NSOpenGLPFAMultisample,
     NSOpenGLPFAOpenGLProfile, NSOpenGLPFASampleBuffers, NSOpenGLPFASamples, NSOpenGLPFAStencilSize,
-    NSOpenGLPixelFormat, NSOpenGLProfileVersion3_2Core, NSOpenGLProfileVersion4_1Core,
-    NSOpenGLProfileVersionLegacy, NSOpenGLView, NSView,
+    NSOpenGLPFATripleBuffer, NSOpenGLPixelFormat, NSOpenGLProfileVersion3_2Core,
+    NSOpenGLProfileVersion4_1Core, NSOpenGLProfileVersionLegacy, NSOpenGLView, NSView,
 };
-use cocoa::base::{id, nil, YES};
+use cocoa::base::{id, nil, BOOL, NO, YES};
 use cocoa::foundation::NSSize;
 
 use core_foundation::base::TCFType;
 use core_foundation::bundle::{CFBundleGetBundleWithIdentifier, CFBundleGetFunctionPointerForName};
 use core_foundation::string::CFString;
 
-use objc::{msg_send, sel, sel_impl};
+use objc::{class, msg_send, sel, sel_impl};
 
-use super::{GlConfig, GlError, Profile};
+use super::{GlConfig, GlError, GpuPreference, Profile, VsyncMode};
+use crate::PhySize;
 
 pub type CreationFailedError = ();
 pub struct GlContext {
-    view: id,
+    /// The `NSOpenGLView` the context renders to, or `None` for offscreen contexts
+    view: Option<id>,
     context: id,
+    vsync_mode: VsyncMode,
 }
 
 impl GlContext {
@@ -41,51 +46,13 @@ impl GlContext {
 
         let parent_view = handle.ns_view as id;
 
-        let version = if config.version < (3, 2) && config.profile == Profile::Compatibility {
-            NSOpenGLProfileVersionLegacy
-        } else if config.version == (3, 2) && config.profile == Profile::Core {
-            NSOpenGLProfileVersion3_2Core
-        } else if config.version > (3, 2) && config.profile == Profile::Core {
-            NSOpenGLProfileVersion4_1Core
-        } else {
-            return Err(GlError::VersionNotSupported);
-        };
-
-        #[rustfmt::skip]
-        let mut attrs = vec![
-            NSOpenGLPFAOpenGLProfile as u32, version as u32,
-            NSOpenGLPFAColorSize as u32, (config.red_bits + config.blue_bits + config.green_bits) as u32,
-            NSOpenGLPFAAlphaSize as u32, config.alpha_bits as u32,
-            NSOpenGLPFADepthSize as u32, config.depth_bits as u32,
-            NSOpenGLPFAStencilSize as u32, config.stencil_bits as u32,
-            NSOpenGLPFAAccelerated as u32,
-        ];
-
-        if config.samples.is_some() {
-            #[rustfmt::skip]
-            attrs.extend_from_slice(&[
-                NSOpenGLPFAMultisample as u32,
-                NSOpenGLPFASampleBuffers as u32, 1,
-                NSOpenGLPFASamples as u32, config.samples.unwrap() as u32,
-            ]);
-        }
-
-        if config.double_buffer {
-            attrs.push(NSOpenGLPFADoubleBuffer as u32);
-        }
-
-        attrs.push(0);
-
-        let pixel_format = NSOpenGLPixelFormat::alloc(nil).initWithAttributes_(&attrs);
-
-        if pixel_format == nil {
-            return Err(GlError::CreationFailed(()));
-        }
+        let pixel_format = create_pixel_format(&config)?;
 
         let view =
             NSOpenGLView::alloc(nil).initWithFrame_pixelFormat_(parent_view.frame(), pixel_format);
 
         if view == nil {
+            let () = msg_send![pixel_format, release];
             return Err(GlError::CreationFailed(()));
         }
 
@@ -94,6 +61,23 @@ impl GlContext {
         NSOpenGLView::display_(view);
         parent_view.addSubview_(view);
 
+        // The view gets a layer once it's added to a layer backed parent. Layers are color matched
+        // to the display, and without a color space the content is assumed to already be in the
+        // display's color space.
+        if config.srgb {
+            let layer: id = msg_send![view, layer];
+            let responds: BOOL = if layer == nil {
+                NO
+            } else {
+                msg_send![layer, respondsToSelector: sel!(setColorspace:)]
+            };
+            if responds == YES {
+                let srgb: id = msg_send![class!(NSColorSpace), sRGBColorSpace];
+                let cg_color_space: *mut c_void = msg_send![srgb, CGColorSpace];
+                let () = msg_send![layer, setColorspace: cg_color_space];
+            }
+        }
+
         let context: id = msg_send![view, openGLContext];
         let () = msg_send![context, retain];
 
@@ -104,7 +88,28 @@ impl GlContext {
 
         let () = msg_send![pixel_format, release];
 
-        Ok(GlContext { view, context })
+        let vsync_mode = if config.vsync { VsyncMode::SwapInterval } else { VsyncMode::Off };
+
+        Ok(GlContext { view: Some(view), context, vsync_mode })
+    }
+
+    /// Create a context that is not attached to any view, so it can be used without a window.
+    pub unsafe fn create_offscreen(config: GlConfig) -> Result<GlContext, GlError> {
+        let pixel_format = create_pixel_format(&config)?;
+
+        let context = NSOpenGLContext::alloc(nil).initWithFormat_shareContext_(pixel_format, nil);
+        let () = msg_send![pixel_format, release];
+
+        if context == nil {
+            return Err(GlError::CreationFailed(()));
+        }
+
+        context.setValues_forParameter_(
+            &(config.vsync as i32),
+            NSOpenGLContextParameter::NSOpenGLCPSwapInterval,
+        );
+
+        Ok(GlContext { view: None, context, vsync_mode: VsyncMode::Off })
     }
 
     pub unsafe fn make_current(&self) {
@@ -127,15 +132,62 @@ impl GlContext {
     pub fn swap_buffers(&self) {
         unsafe {
             self.context.flushBuffer();
-            let () = msg_send![self.view, setNeedsDisplay: YES];
+            if let Some(view) = self.view {
+                let () = msg_send![view, setNeedsDisplay: YES];
+            }
+        }
+    }
+
+    pub fn ns_view(&self) -> Option<id> {
+        self.view
+    }
+
+    pub fn vsync_mode(&self) -> VsyncMode {
+        self.vsync_mode
+    }
+
+    /// NSOpenGL doesn't report when frames are displayed.
+    pub fn next_vblank(&self, _now: Instant) -> Option<Instant> {
+        None
+    }
+
+    /// On macOS the `NSOpenGLView` needs to be resized separtely from our main view, and the
+    /// context needs to be told that its drawable has changed.
+    pub fn resized(&self, size: PhySize) {
+        unsafe {
+            if let Some(view) = self.view {
+                let physical_size = NSSize::new(size.width as f64, size.height as f64);
+                let logical_size: NSSize = msg_send![view, convertSizeFromBacking: physical_size];
+
+                NSView::setFrameSize(view, logical_size);
+                let () = msg_send![view, setNeedsDisplay: YES];
+            }
+
+            let () = msg_send![self.context, update];
         }
     }
 
-    /// On macOS the `NSOpenGLView` needs to be resized separtely from our main view.
-    pub(crate) fn resize(&self, size: NSSize) {
-        unsafe { NSView::setFrameSize(self.view, size) };
+    pub fn set_wants_best_resolution(&self, wants_best_resolution: bool) {
+        if let Some(view) = self.view {
+            unsafe {
+                view.setWantsBestResolutionOpenGLSurface_(if wants_best_resolution {
+                    YES
+                } else {
+                    NO
+                });
+                let () = msg_send![self.context, update];
+            }
+        }
+    }
+
+    /// The context needs to be told when its view moved to another window or screen, since that
+    /// may change the renderer and the drawable's pixel format.
+    pub fn surface_changed(&self) {
         unsafe {
-            let _: () = msg_send![self.view, setNeedsDisplay: YES];
+            let () = msg_send![self.context, update];
+            if let Some(view) = self.view {
+                let () = msg_send![view, setNeedsDisplay: YES];
+            }
         }
     }
 }
@@ -144,7 +196,62 @@ impl Drop for GlContext {
     fn drop(&mut self) {
         unsafe {
             let () = msg_send![self.context, release];
-            let () = msg_send![self.view, release];
+            if let Some(view) = self.view {
+                let () = msg_send![view, release];
+            }
         }
     }
 }
+
+unsafe fn create_pixel_format(config: &GlConfig) -> Result<id, GlError> {
+    let version = if config.version < (3, 2) && config.profile == Profile::Compatibility {
+        NSOpenGLProfileVersionLegacy
+    } else if config.version == (3, 2) && config.profile == Profile::Core {
+        NSOpenGLProfileVersion3_2Core
+    } else if config.version > (3, 2) && config.profile == Profile::Core {
+        NSOpenGLProfileVersion4_1Core
+    } else {
+        return Err(GlError::VersionNotSupported);
+    };
+
+    #[rustfmt::skip]
+    let mut attrs = vec![
+        NSOpenGLPFAOpenGLProfile as u32, version as u32,
+        NSOpenGLPFAColorSize as u32, (config.red_bits + config.blue_bits + config.green_bits) as u32,
+        NSOpenGLPFAAlphaSize as u32, config.alpha_bits as u32,
+        NSOpenGLPFADepthSize as u32, config.depth_bits as u32,
+        NSOpenGLPFAStencilSize as u32, config.stencil_bits as u32,
+        NSOpenGLPFAAccelerated as u32,
+    ];
+
+    if config.samples.is_some() {
+        #[rustfmt::skip]
+        attrs.extend_from_slice(&[
+            NSOpenGLPFAMultisample as u32,
+            NSOpenGLPFASampleBuffers as u32, 1,
+            NSOpenGLPFASamples as u32, config.samples.unwrap() as u32,
+        ]);
+    }
+
+    if config.triple_buffer {
+        attrs.push(NSOpenGLPFATripleBuffer as u32);
+    } else if config.double_buffer {
+        attrs.push(NSOpenGLPFADoubleBuffer as u32);
+    }
+
+    // Offline renderers are the GPUs that aren't currently driving a display, so allowing them
+    // keeps the system from switching to the discrete GPU
+    if config.gpu_preference == GpuPreference::LowPower {
+        attrs.push(NSOpenGLPFAAllowOfflineRenderers as u32);
+    }
+
+    attrs.push(0);
+
+    let pixel_format = NSOpenGLPixelFormat::alloc(nil).initWithAttributes_(&attrs);
+
+    if pixel_format == nil {
+        return Err(GlError::CreationFailed(()));
+    }
+
+    Ok(pixel_format)
+}
@@ -1,10 +1,17 @@
-use std::ffi::c_void;
+use std::cell::{Cell, RefCell, UnsafeCell};
+use std::collections::VecDeque;
+use std::ffi::{c_void, CStr};
 use std::marker::PhantomData;
+use std::os::raw::c_int;
+use std::ptr::null;
+use std::time::Instant;
 
 // On X11 creating the context is a two step process
 #[cfg(not(target_os = "linux"))]
 use raw_window_handle::RawWindowHandle;
 
+use crate::{PhyPoint, PhySize};
+
 #[cfg(target_os = "windows")]
 mod win;
 #[cfg(target_os = "windows")]
@@ -21,6 +28,38 @@ mod macos;
 #[cfg(target_os = "macos")]
 use macos as platform;
 
+// The drawables of `GlArea`s are created by the windowing backends
+#[cfg(target_os = "macos")]
+use crate::macos::GlAreaSurface;
+#[cfg(target_os = "windows")]
+use crate::win::GlAreaSurface;
+#[cfg(target_os = "linux")]
+use crate::x11::GlAreaSurface;
+
+const GL_EXTENSIONS: u32 = 0x1F03;
+const GL_NUM_EXTENSIONS: u32 = 0x821D;
+const GL_MAJOR_VERSION: u32 = 0x821B;
+const GL_MINOR_VERSION: u32 = 0x821C;
+
+type GlGetString = unsafe extern "system" fn(name: u32) -> *const u8;
+type GlGetStringi = unsafe extern "system" fn(name: u32, index: u32) -> *const u8;
+type GlGetIntegerv = unsafe extern "system" fn(name: u32, data: *mut c_int);
+
+// See https://www.khronos.org/registry/OpenGL/extensions/ARB/ARB_sync.txt
+
+const GL_SYNC_GPU_COMMANDS_COMPLETE: u32 = 0x9117;
+const GL_SYNC_FLUSH_COMMANDS_BIT: u32 = 0x00000001;
+
+type GlSync = *const c_void;
+type GlFenceSync = unsafe extern "system" fn(condition: u32, flags: u32) -> GlSync;
+type GlClientWaitSync = unsafe extern "system" fn(sync: GlSync, flags: u32, timeout: u64) -> u32;
+type GlDeleteSync = unsafe extern "system" fn(sync: GlSync);
+type GlFinish = unsafe extern "system" fn();
+
+/// How long presenting a frame waits for an older frame to finish before giving up, in
+/// nanoseconds.
+const FRAME_WAIT_TIMEOUT: u64 = 1_000_000_000;
+
 #[derive(Clone, Debug)]
 pub struct GlConfig {
     pub version: (u8, u8),
@@ -35,6 +74,19 @@ pub struct GlConfig {
     pub srgb: bool,
     pub double_buffer: bool,
     pub vsync: bool,
+    /// Request a third buffer, so rendering the next frame doesn't have to wait for the previous
+    /// one to be displayed. This trades latency for smoother animation. Only supported on macOS,
+    /// other platforms leave the number of buffers to the driver.
+    pub triple_buffer: bool,
+    /// The maximum number of presented frames that may still be rendering on the GPU. Once this
+    /// many frames are in flight, [`GlContext::swap_buffers()`] waits for the oldest one to
+    /// finish, so `Some(1)` gives the lowest latency for things like analyzers. `None` leaves this
+    /// to the driver, which usually queues up to two or three frames. Contexts without sync
+    /// objects, which need OpenGL 3.2 or `ARB_sync`, wait for every frame to finish instead.
+    pub max_frame_latency: Option<u32>,
+    /// Which GPU the context should run on, for laptops that have both an integrated and a
+    /// discrete GPU.
+    pub gpu_preference: GpuPreference,
 }
 
 impl Default for GlConfig {
@@ -52,10 +104,52 @@ impl Default for GlConfig {
             srgb: true,
             double_buffer: true,
             vsync: false,
+            triple_buffer: false,
+            max_frame_latency: None,
+            gpu_preference: GpuPreference::Default,
         }
     }
 }
 
+/// A hint for choosing between an integrated and a discrete GPU, see
+/// [`GlConfig::gpu_preference`]. The platforms only offer limited control over this:
+///
+/// - On X11 [`HighPerformance`][Self::HighPerformance] sets `DRI_PRIME=1` for Mesa drivers unless
+///   the user has already set `DRI_PRIME`. Mesa reads this when OpenGL is first used on a
+///   connection to the X server, so it only takes effect for the first context on a thread.
+/// - On macOS [`LowPower`][Self::LowPower] lets the context run on the integrated GPU. Without
+///   it, creating a context switches the whole system to the discrete GPU unless the application
+///   has set `NSSupportsAutomaticGraphicsSwitching` in its `Info.plist`.
+/// - On Windows the GPU is chosen by the driver based on the host's executable, so this has no
+///   effect.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GpuPreference {
+    /// Leave the choice to the system.
+    Default,
+    /// Prefer the integrated GPU, for lightweight interfaces that shouldn't wake up the discrete
+    /// GPU.
+    LowPower,
+    /// Prefer the discrete GPU.
+    HighPerformance,
+}
+
+/// How a window's buffer swaps are synchronized with the display, see
+/// [`GlContext::vsync_mode()`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VsyncMode {
+    /// Buffer swaps don't wait for the display. This is the case when [`GlConfig::vsync`] is
+    /// disabled, when the driver doesn't support setting a swap interval, and for offscreen
+    /// contexts.
+    Off,
+    /// Buffer swaps wait for the display's next vertical blank.
+    SwapInterval,
+    /// A compositor presents the window's contents in sync with the display, so buffer swaps
+    /// don't wait for the display themselves. Waiting on top of the compositor would only add
+    /// latency, and it would stall the other windows sharing the event loop thread. This is only
+    /// used on X11.
+    Compositor,
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Profile {
     Compatibility,
@@ -66,21 +160,82 @@ pub enum Profile {
 pub enum GlError {
     InvalidWindowHandle,
     VersionNotSupported,
+    /// [`Window::create_gl_context()`][crate::Window::create_gl_context()] was called for a window
+    /// that already has a context.
+    AlreadyCreated,
     CreationFailed(platform::CreationFailedError),
 }
 
 pub struct GlContext {
     context: platform::GlContext,
+    /// Set by [`Window::skip_present()`][crate::Window::skip_present()] for the rest of the
+    /// current callback.
+    swaps_skipped: Cell<bool>,
+    /// Only allocated if [`GlConfig::max_frame_latency`] is set.
+    frame_latency: Option<Box<FrameLatency>>,
+    /// The last frame presented with [`swap_buffers()`][Self::swap_buffers()], until it's been
+    /// reported to [`WindowHandler::on_present()`][crate::WindowHandler::on_present()].
+    last_present: Cell<Option<PresentInfo>>,
     phantom: PhantomData<*mut ()>,
 }
 
+/// Describes a frame that was presented with [`GlContext::swap_buffers()`]. Passed to
+/// [`WindowHandler::on_present()`][crate::WindowHandler::on_present()] after the callback that
+/// presented the frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PresentInfo {
+    /// When the buffer swap returned. With vsync enabled this is usually close to when the
+    /// previous frame appeared on the display.
+    pub swap_time: Instant,
+    /// The time of the next vertical blank after the swap, which is when the frame is expected
+    /// to appear on the display. This is only available on Windows with the desktop compositor
+    /// enabled, and is `None` everywhere else.
+    pub display_time: Option<Instant>,
+}
+
+#[derive(Clone, Copy)]
+struct SyncFunctions {
+    fence_sync: GlFenceSync,
+    client_wait_sync: GlClientWaitSync,
+    delete_sync: GlDeleteSync,
+}
+
+/// Limits the number of frames in flight to [`GlConfig::max_frame_latency`] by inserting a fence
+/// after every presented frame.
+struct FrameLatency {
+    max_frames: usize,
+    /// The fences for the frames in flight, oldest first.
+    fences: RefCell<VecDeque<GlSync>>,
+    /// Loaded on the first frame, since the context needs to be current for that. This is
+    /// `Some(None)` if the context doesn't support sync objects.
+    functions: Cell<Option<Option<SyncFunctions>>>,
+}
+
+impl FrameLatency {
+    fn new(max_frame_latency: Option<u32>) -> Option<Box<Self>> {
+        max_frame_latency.map(|max_frames| {
+            Box::new(Self {
+                max_frames: max_frames as usize,
+                fences: RefCell::new(VecDeque::new()),
+                functions: Cell::new(None),
+            })
+        })
+    }
+}
+
 impl GlContext {
     #[cfg(not(target_os = "linux"))]
     pub(crate) unsafe fn create(
         parent: &RawWindowHandle, config: GlConfig,
     ) -> Result<GlContext, GlError> {
-        platform::GlContext::create(parent, config)
-            .map(|context| GlContext { context, phantom: PhantomData })
+        let frame_latency = FrameLatency::new(config.max_frame_latency);
+        platform::GlContext::create(parent, config).map(|context| GlContext {
+            context,
+            swaps_skipped: Cell::new(false),
+            frame_latency,
+            last_present: Cell::new(None),
+            phantom: PhantomData,
+        })
     }
 
     /// The X11 version needs to be set up in a different way compared to the Windows and macOS
@@ -88,7 +243,36 @@ impl GlContext {
     /// baseview, and then this object can be passed to the user.
     #[cfg(target_os = "linux")]
     pub(crate) fn new(context: platform::GlContext) -> GlContext {
-        GlContext { context, phantom: PhantomData }
+        let frame_latency = FrameLatency::new(context.max_frame_latency());
+        GlContext {
+            context,
+            swaps_skipped: Cell::new(false),
+            frame_latency,
+            last_present: Cell::new(None),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Create an OpenGL context that is not tied to any window. This can be used to compile
+    /// shaders or render thumbnails before a window is ever opened. The context does not have a
+    /// usable default framebuffer, so anything rendered with it should target a framebuffer
+    /// object.
+    ///
+    /// Objects created with this context are not shared with the contexts of windows opened
+    /// later, but compiling shaders ahead of time still warms up the driver's shader cache.
+    ///
+    /// - On X11 this uses a pbuffer on a separate connection to the X server.
+    /// - On Windows this uses a hidden window.
+    /// - On macOS this uses an `NSOpenGLContext` that is not attached to any view.
+    pub fn create_offscreen(config: GlConfig) -> Result<GlContext, GlError> {
+        let frame_latency = FrameLatency::new(config.max_frame_latency);
+        unsafe { platform::GlContext::create_offscreen(config) }.map(|context| GlContext {
+            context,
+            swaps_skipped: Cell::new(false),
+            frame_latency,
+            last_present: Cell::new(None),
+            phantom: PhantomData,
+        })
     }
 
     pub unsafe fn make_current(&self) {
@@ -99,17 +283,268 @@ impl GlContext {
         self.context.make_not_current();
     }
 
+    /// Look up an OpenGL function. Returns a null pointer if the function could not be found,
+    /// although some platforms return a non-null pointer for any function name.
     pub fn get_proc_address(&self, symbol: &str) -> *const c_void {
         self.context.get_proc_address(symbol)
     }
 
+    /// A function loader in the form expected by the `gl` crate's `load_with()` and glow's
+    /// `Context::from_loader_function()`:
+    ///
+    /// ```ignore
+    /// let gl = unsafe { glow::Context::from_loader_function(context.loader()) };
+    /// ```
+    pub fn loader(&self) -> impl Fn(&str) -> *const c_void + '_ {
+        move |symbol| self.get_proc_address(symbol)
+    }
+
+    /// Like [`loader()`][Self::loader()], but for loaders that pass the function names as C
+    /// strings, like glow's `Context::from_loader_function_cstr()`.
+    pub fn loader_cstr(&self) -> impl Fn(&CStr) -> *const c_void + '_ {
+        move |symbol| symbol.to_str().map_or(null(), |symbol| self.get_proc_address(symbol))
+    }
+
+    /// Whether the context supports an OpenGL extension, for instance `"GL_ARB_debug_output"`.
+    /// The context needs to be current.
+    ///
+    /// Core profile contexts list their extensions one at a time, while older contexts list them
+    /// all in a single string. Both are supported.
+    pub fn supports_extension(&self, name: &str) -> bool {
+        let name = name.as_bytes();
+
+        unsafe {
+            let get_integerv = self.get_proc_address("glGetIntegerv");
+            let get_stringi = self.get_proc_address("glGetStringi");
+            if !get_integerv.is_null() && !get_stringi.is_null() {
+                let get_integerv: GlGetIntegerv = std::mem::transmute(get_integerv);
+                let get_stringi: GlGetStringi = std::mem::transmute(get_stringi);
+
+                // This stays at zero on contexts older than OpenGL 3.0
+                let mut num_extensions: c_int = 0;
+                get_integerv(GL_NUM_EXTENSIONS, &mut num_extensions);
+                if num_extensions > 0 {
+                    return (0..num_extensions as u32).any(|index| {
+                        let extension = get_stringi(GL_EXTENSIONS, index);
+                        !extension.is_null()
+                            && CStr::from_ptr(extension as *const _).to_bytes() == name
+                    });
+                }
+            }
+
+            let get_string = self.get_proc_address("glGetString");
+            if get_string.is_null() {
+                return false;
+            }
+            let get_string: GlGetString = std::mem::transmute(get_string);
+
+            let extensions = get_string(GL_EXTENSIONS);
+            !extensions.is_null()
+                && CStr::from_ptr(extensions as *const _)
+                    .to_bytes()
+                    .split(|&c| c == b' ')
+                    .any(|extension| extension == name)
+        }
+    }
+
+    /// How buffer swaps are synchronized with the display. With [`GlConfig::vsync`] enabled, X11
+    /// only uses a swap interval when there's no compositor running, since uncomposited windows
+    /// would tear otherwise. This is checked again whenever a compositor is started or stopped.
+    pub fn vsync_mode(&self) -> VsyncMode {
+        self.context.vsync_mode()
+    }
+
+    /// Present the back buffer. This does nothing if
+    /// [`Window::skip_present()`][crate::Window::skip_present()] was called during the current
+    /// callback.
     pub fn swap_buffers(&self) {
-        self.context.swap_buffers();
+        if !self.swaps_skipped.get() {
+            self.context.swap_buffers();
+            self.limit_frame_latency();
+
+            let swap_time = Instant::now();
+            let display_time = self.context.next_vblank(swap_time);
+            self.last_present.set(Some(PresentInfo { swap_time, display_time }));
+        }
     }
 
-    /// On macOS the `NSOpenGLView` needs to be resized separtely from our main view.
+    /// Wait until at most [`GlConfig::max_frame_latency`] frames are in flight.
+    fn limit_frame_latency(&self) {
+        let frame_latency = match &self.frame_latency {
+            Some(frame_latency) => frame_latency,
+            None => return,
+        };
+
+        let functions = match frame_latency.functions.get() {
+            Some(functions) => functions,
+            None => {
+                let functions = self.load_sync_functions();
+                frame_latency.functions.set(Some(functions));
+                functions
+            }
+        };
+
+        unsafe {
+            let functions = match functions {
+                Some(functions) => functions,
+                None => {
+                    let finish = self.get_proc_address("glFinish");
+                    if !finish.is_null() {
+                        std::mem::transmute::<*const c_void, GlFinish>(finish)();
+                    }
+
+                    return;
+                }
+            };
+
+            let mut fences = frame_latency.fences.borrow_mut();
+            fences.push_back((functions.fence_sync)(GL_SYNC_GPU_COMMANDS_COMPLETE, 0));
+            while fences.len() > frame_latency.max_frames {
+                let fence = fences.pop_front().unwrap();
+                (functions.client_wait_sync)(fence, GL_SYNC_FLUSH_COMMANDS_BIT, FRAME_WAIT_TIMEOUT);
+                (functions.delete_sync)(fence);
+            }
+        }
+    }
+
+    fn load_sync_functions(&self) -> Option<SyncFunctions> {
+        // Some platforms return function pointers for anything, so check for support first. The
+        // version queries need OpenGL 3.0, older contexts leave the version at zero.
+        let mut version: (c_int, c_int) = (0, 0);
+        let get_integerv = self.get_proc_address("glGetIntegerv");
+        if !get_integerv.is_null() {
+            unsafe {
+                let get_integerv: GlGetIntegerv = std::mem::transmute(get_integerv);
+                get_integerv(GL_MAJOR_VERSION, &mut version.0);
+                get_integerv(GL_MINOR_VERSION, &mut version.1);
+            }
+        }
+        if version < (3, 2) && !self.supports_extension("GL_ARB_sync") {
+            return None;
+        }
+
+        let fence_sync = self.get_proc_address("glFenceSync");
+        let client_wait_sync = self.get_proc_address("glClientWaitSync");
+        let delete_sync = self.get_proc_address("glDeleteSync");
+        if fence_sync.is_null() || client_wait_sync.is_null() || delete_sync.is_null() {
+            return None;
+        }
+
+        unsafe {
+            Some(SyncFunctions {
+                fence_sync: std::mem::transmute::<*const c_void, GlFenceSync>(fence_sync),
+                client_wait_sync: std::mem::transmute::<*const c_void, GlClientWaitSync>(
+                    client_wait_sync,
+                ),
+                delete_sync: std::mem::transmute::<*const c_void, GlDeleteSync>(delete_sync),
+            })
+        }
+    }
+
+    pub(crate) fn set_swaps_skipped(&self, skipped: bool) {
+        self.swaps_skipped.set(skipped);
+    }
+
+    /// Take the last frame presented since this was last called, if any.
+    pub(crate) fn take_present_info(&self) -> Option<PresentInfo> {
+        self.last_present.take()
+    }
+
+    /// Called by baseview whenever the window's physical size changes, so the context's drawable
+    /// keeps matching the window's size in physical pixels.
+    pub(crate) fn resized(&self, size: PhySize) {
+        self.context.resized(size);
+    }
+
+    /// Called by baseview when the window's drawable has changed, for instance because the window
+    /// was reparented, so the context keeps rendering to a valid drawable.
+    pub(crate) fn surface_changed(&self) {
+        self.context.surface_changed();
+    }
+
+    /// Called by baseview when a compositor was started or stopped, so the context can switch
+    /// between syncing to the compositor and using a swap interval.
+    #[cfg(target_os = "linux")]
+    pub(crate) fn compositor_changed(&self) {
+        self.context.compositor_changed();
+    }
+
+    /// The `NSOpenGLView` the context renders to, or `None` for offscreen contexts.
+    #[cfg(target_os = "macos")]
+    pub(crate) fn ns_view(&self) -> Option<cocoa::base::id> {
+        self.context.ns_view()
+    }
+
+    /// Whether the context renders at the display's full resolution on Retina displays, which is
+    /// the default. Without this the drawable's size is in points.
     #[cfg(target_os = "macos")]
-    pub(crate) fn resize(&self, size: cocoa::foundation::NSSize) {
-        self.context.resize(size);
+    pub(crate) fn set_wants_best_resolution(&self, wants_best_resolution: bool) {
+        self.context.set_wants_best_resolution(wants_best_resolution);
+    }
+}
+
+/// An additional OpenGL drawable inside of a window, created with
+/// [`Window::create_gl_area()`][crate::Window::create_gl_area()]. Every area has its own context
+/// and buffer swaps, so a window can for instance render an oscilloscope with OpenGL while the
+/// rest of its contents are drawn with a software renderer.
+///
+/// Areas are drawn on top of the window's contents, and they don't receive any input. Mouse events
+/// over an area are sent to the window as usual. An area must be dropped before its window is
+/// closed, for instance while handling [`WindowEvent::WillClose`][crate::WindowEvent::WillClose].
+pub struct GlArea {
+    // Fields are dropped in declaration order, so the context is destroyed before its drawable
+    context: GlContext,
+    surface: GlAreaSurface,
+}
+
+impl GlArea {
+    pub(crate) fn new(context: GlContext, surface: GlAreaSurface) -> GlArea {
+        GlArea { context, surface }
+    }
+
+    /// The area's OpenGL context. Swapping its buffers only presents the area, and it's not
+    /// affected by [`Window::skip_present()`][crate::Window::skip_present()].
+    pub fn context(&self) -> &GlContext {
+        &self.context
+    }
+
+    /// Move and resize the area. The position is relative to the window's top left corner, and
+    /// both the position and the size are in physical pixels.
+    pub fn set_bounds(&self, position: PhyPoint, size: PhySize) {
+        self.surface.set_bounds(position, size);
+        self.context.resized(size);
+    }
+}
+
+/// A window's OpenGL context, which can also be created after the window has been opened. Once
+/// created the context is never replaced, so references to it stay valid for as long as the
+/// window exists.
+#[derive(Default)]
+pub(crate) struct GlContextCell(UnsafeCell<Option<GlContext>>);
+
+impl GlContextCell {
+    pub fn new(context: Option<GlContext>) -> Self {
+        Self(UnsafeCell::new(context))
+    }
+
+    pub fn get(&self) -> Option<&GlContext> {
+        unsafe { (*self.0.get()).as_ref() }
+    }
+
+    /// Store the context, or return it back if the cell already contains one.
+    pub fn set(&self, context: GlContext) -> Result<(), GlContext> {
+        if self.get().is_some() {
+            return Err(context);
+        }
+
+        // SAFETY: There can't be any references to the contents while the cell is empty
+        unsafe { *self.0.get() = Some(context) };
+
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn take(&mut self) -> Option<GlContext> {
+        self.0.get_mut().take()
     }
 }
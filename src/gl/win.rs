@@ -1,17 +1,21 @@
 use std::ffi::{c_void, CString, OsStr};
 use std::os::windows::ffi::OsStrExt;
 use std::ptr::null_mut;
+use std::time::{Duration, Instant};
 
 use raw_window_handle::RawWindowHandle;
 
-use winapi::shared::minwindef::{HINSTANCE, HMODULE};
+use winapi::shared::minwindef::{ATOM, HINSTANCE, HMODULE};
 use winapi::shared::ntdef::WCHAR;
 use winapi::shared::windef::{HDC, HGLRC, HWND};
+use winapi::shared::winerror::S_OK;
+use winapi::um::dwmapi::{DwmGetCompositionTimingInfo, DWM_TIMING_INFO};
 use winapi::um::libloaderapi::{FreeLibrary, GetProcAddress, LoadLibraryA};
+use winapi::um::profileapi::{QueryPerformanceCounter, QueryPerformanceFrequency};
 use winapi::um::wingdi::{
-    wglCreateContext, wglDeleteContext, wglGetProcAddress, wglMakeCurrent, ChoosePixelFormat,
-    DescribePixelFormat, SetPixelFormat, SwapBuffers, PFD_DOUBLEBUFFER, PFD_DRAW_TO_WINDOW,
-    PFD_MAIN_PLANE, PFD_SUPPORT_OPENGL, PFD_TYPE_RGBA, PIXELFORMATDESCRIPTOR,
+    wglCreateContext, wglDeleteContext, wglGetCurrentContext, wglGetProcAddress, wglMakeCurrent,
+    ChoosePixelFormat, DescribePixelFormat, SetPixelFormat, SwapBuffers, PFD_DOUBLEBUFFER,
+    PFD_DRAW_TO_WINDOW, PFD_MAIN_PLANE, PFD_SUPPORT_OPENGL, PFD_TYPE_RGBA, PIXELFORMATDESCRIPTOR,
 };
 use winapi::um::winnt::IMAGE_DOS_HEADER;
 use winapi::um::winuser::{
@@ -19,7 +23,8 @@ use winapi::um::winuser::{
     UnregisterClassW, CS_OWNDC, CW_USEDEFAULT, WNDCLASSW,
 };
 
-use super::{GlConfig, GlError, Profile};
+use super::{GlConfig, GlError, Profile, VsyncMode};
+use crate::PhySize;
 
 // See https://www.khronos.org/registry/OpenGL/extensions/ARB/WGL_ARB_create_context.txt
 
@@ -72,26 +77,22 @@ pub struct GlContext {
     hdc: HDC,
     hglrc: HGLRC,
     gl_library: HMODULE,
+    /// The window offscreen contexts render to. Fields are dropped after `drop()`, so this is only
+    /// destroyed after the device context has been released.
+    hidden_window: Option<HiddenWindow>,
+    vsync_mode: VsyncMode,
 }
 
-extern "C" {
-    static __ImageBase: IMAGE_DOS_HEADER;
+/// An invisible window with its own window class. Used to create the temporary context for
+/// loading the WGL extensions, and as the drawable of offscreen contexts.
+struct HiddenWindow {
+    hwnd: HWND,
+    class: ATOM,
+    hinstance: HINSTANCE,
 }
 
-impl GlContext {
-    pub unsafe fn create(parent: &RawWindowHandle, config: GlConfig) -> Result<GlContext, GlError> {
-        let handle = if let RawWindowHandle::Win32(handle) = parent {
-            handle
-        } else {
-            return Err(GlError::InvalidWindowHandle);
-        };
-
-        if handle.hwnd.is_null() {
-            return Err(GlError::InvalidWindowHandle);
-        }
-
-        // Create temporary window and context to load function pointers
-
+impl HiddenWindow {
+    unsafe fn create() -> Result<HiddenWindow, GlError> {
         let class_name_str = format!("raw-gl-context-window-{}", uuid::Uuid::new_v4().to_simple());
         let mut class_name: Vec<WCHAR> = OsStr::new(&class_name_str).encode_wide().collect();
         class_name.push(0);
@@ -111,7 +112,7 @@ impl GlContext {
             return Err(GlError::CreationFailed(()));
         }
 
-        let hwnd_tmp = CreateWindowExW(
+        let hwnd = CreateWindowExW(
             0,
             class as *const WCHAR,
             [0].as_ptr(),
@@ -126,10 +127,62 @@ impl GlContext {
             std::ptr::null_mut(),
         );
 
-        if hwnd_tmp.is_null() {
+        if hwnd.is_null() {
+            UnregisterClassW(class as *const WCHAR, hinstance);
             return Err(GlError::CreationFailed(()));
         }
 
+        Ok(HiddenWindow { hwnd, class, hinstance })
+    }
+}
+
+impl Drop for HiddenWindow {
+    fn drop(&mut self) {
+        unsafe {
+            DestroyWindow(self.hwnd);
+            UnregisterClassW(self.class as *const WCHAR, self.hinstance);
+        }
+    }
+}
+
+extern "C" {
+    static __ImageBase: IMAGE_DOS_HEADER;
+}
+
+impl GlContext {
+    pub unsafe fn create(parent: &RawWindowHandle, config: GlConfig) -> Result<GlContext, GlError> {
+        let handle = if let RawWindowHandle::Win32(handle) = parent {
+            handle
+        } else {
+            return Err(GlError::InvalidWindowHandle);
+        };
+
+        if handle.hwnd.is_null() {
+            return Err(GlError::InvalidWindowHandle);
+        }
+
+        Self::create_for_window(handle.hwnd as HWND, config)
+    }
+
+    /// Create a context that renders to a hidden window owned by the context, so it can be used
+    /// without any visible window.
+    pub unsafe fn create_offscreen(config: GlConfig) -> Result<GlContext, GlError> {
+        let window = HiddenWindow::create()?;
+
+        let mut context = Self::create_for_window(window.hwnd, config)?;
+        context.hidden_window = Some(window);
+        // Swap intervals only apply to visible windows
+        context.vsync_mode = VsyncMode::Off;
+
+        Ok(context)
+    }
+
+    unsafe fn create_for_window(hwnd: HWND, config: GlConfig) -> Result<GlContext, GlError> {
+        // Create temporary window and context to load function pointers
+
+        let window_tmp = HiddenWindow::create()?;
+        let hwnd_tmp = window_tmp.hwnd;
+
         let hdc_tmp = GetDC(hwnd_tmp);
 
         let pfd_tmp = PIXELFORMATDESCRIPTOR {
@@ -150,8 +203,6 @@ impl GlContext {
         let hglrc_tmp = wglCreateContext(hdc_tmp);
         if hglrc_tmp.is_null() {
             ReleaseDC(hwnd_tmp, hdc_tmp);
-            UnregisterClassW(class as *const WCHAR, hinstance);
-            DestroyWindow(hwnd_tmp);
             return Err(GlError::CreationFailed(()));
         }
 
@@ -193,12 +244,10 @@ impl GlContext {
         wglMakeCurrent(hdc_tmp, std::ptr::null_mut());
         wglDeleteContext(hglrc_tmp);
         ReleaseDC(hwnd_tmp, hdc_tmp);
-        UnregisterClassW(class as *const WCHAR, hinstance);
-        DestroyWindow(hwnd_tmp);
+        drop(window_tmp);
 
         // Create actual context
 
-        let hwnd = handle.hwnd as HWND;
         let hdc = GetDC(hwnd);
 
         #[rustfmt::skip]
@@ -297,14 +346,19 @@ impl GlContext {
         let gl_library_name = CString::new("opengl32.dll").unwrap();
         let gl_library = LoadLibraryA(gl_library_name.as_ptr());
 
+        let mut vsync_mode = VsyncMode::Off;
         #[allow(non_snake_case)]
         if let Some(wglSwapIntervalEXT) = wglSwapIntervalEXT {
             wglMakeCurrent(hdc, hglrc);
             wglSwapIntervalEXT(config.vsync as i32);
             wglMakeCurrent(hdc, std::ptr::null_mut());
+
+            if config.vsync {
+                vsync_mode = VsyncMode::SwapInterval;
+            }
         }
 
-        Ok(GlContext { hwnd, hdc, hglrc, gl_library })
+        Ok(GlContext { hwnd, hdc, hglrc, gl_library, hidden_window: None, vsync_mode })
     }
 
     pub unsafe fn make_current(&self) {
@@ -318,18 +372,74 @@ impl GlContext {
     pub fn get_proc_address(&self, symbol: &str) -> *const c_void {
         let symbol = CString::new(symbol).unwrap();
         let addr = unsafe { wglGetProcAddress(symbol.as_ptr()) as *const c_void };
-        if !addr.is_null() {
+        // Some drivers return small integers instead of null when a function doesn't exist. GL 1.1
+        // functions are only exported by opengl32.dll itself.
+        if !matches!(addr as isize, -1..=3) {
             addr
         } else {
             unsafe { GetProcAddress(self.gl_library, symbol.as_ptr()) as *const c_void }
         }
     }
 
+    pub fn resized(&self, _size: PhySize) {
+        // The context renders directly to the window, so the drawable is resized along with it
+    }
+
+    /// The window's pixel format and device context outlive display mode changes, but the context
+    /// is bound again if it's current so the driver picks up the new mode.
+    pub fn surface_changed(&self) {
+        unsafe {
+            if wglGetCurrentContext() == self.hglrc {
+                self.make_current();
+            }
+        }
+    }
+
     pub fn swap_buffers(&self) {
         unsafe {
             SwapBuffers(self.hdc);
         }
     }
+
+    pub fn vsync_mode(&self) -> VsyncMode {
+        self.vsync_mode
+    }
+
+    /// The time of the desktop compositor's next vertical blank after `now`, or `None` if desktop
+    /// composition is disabled.
+    pub fn next_vblank(&self, now: Instant) -> Option<Instant> {
+        unsafe {
+            let mut timing_info: DWM_TIMING_INFO = std::mem::zeroed();
+            timing_info.cbSize = std::mem::size_of::<DWM_TIMING_INFO>() as u32;
+            // The window handle must be null since Windows 8.1
+            if DwmGetCompositionTimingInfo(null_mut(), &mut timing_info) != S_OK {
+                return None;
+            }
+
+            let mut counter = std::mem::zeroed();
+            let mut frequency = std::mem::zeroed();
+            if QueryPerformanceCounter(&mut counter) == 0
+                || QueryPerformanceFrequency(&mut frequency) == 0
+            {
+                return None;
+            }
+            let counter = *counter.QuadPart() as u64;
+            let frequency = *frequency.QuadPart() as u64;
+
+            // This is usually the last vertical blank, so skip ahead to the next one
+            let mut vblank = timing_info.qpcVBlank;
+            let period = timing_info.qpcRefreshPeriod;
+            if frequency == 0 || period == 0 {
+                return None;
+            }
+            if vblank <= counter {
+                vblank += ((counter - vblank) / period + 1) * period;
+            }
+
+            let nanos = (vblank - counter) as u128 * 1_000_000_000 / frequency as u128;
+            Some(now + Duration::from_nanos(nanos as u64))
+        }
+    }
 }
 
 impl Drop for GlContext {
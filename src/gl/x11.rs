@@ -1,10 +1,13 @@
+use std::cell::Cell;
 use std::ffi::{c_void, CString};
 use std::os::raw::{c_int, c_ulong};
+use std::time::Instant;
 
 use x11::glx;
 use x11::xlib;
 
-use super::{GlConfig, GlError, Profile};
+use super::{GlConfig, GlError, GpuPreference, Profile, VsyncMode};
+use crate::PhySize;
 
 mod errors;
 
@@ -15,6 +18,9 @@ pub enum CreationFailedError {
     GetProcAddressFailed,
     MakeCurrentFailed,
     ContextCreationFailed,
+    DisplayConnectionFailed,
+    PbufferCreationFailed,
+    WindowCreationFailed,
     X11Error(errors::XLibError),
 }
 
@@ -45,13 +51,174 @@ const GLX_FRAMEBUFFER_SRGB_CAPABLE_ARB: i32 = 0x20B2;
 
 fn get_proc_address(symbol: &str) -> *const c_void {
     let symbol = CString::new(symbol).unwrap();
-    unsafe { glx::glXGetProcAddress(symbol.as_ptr() as *const u8).unwrap() as *const c_void }
+    let address = unsafe { glx::glXGetProcAddress(symbol.as_ptr() as *const u8) };
+    address.map_or(std::ptr::null(), |address| address as *const c_void)
+}
+
+/// Decide how a window's buffer swaps should be synchronized. Compositing managers own the
+/// `_NET_WM_CM_S<screen>` selection while they're running, and they already present the window in
+/// sync with the display.
+unsafe fn window_vsync_mode(display: *mut xlib::_XDisplay, vsync: bool) -> VsyncMode {
+    if !vsync {
+        return VsyncMode::Off;
+    }
+
+    let selection = CString::new(format!("_NET_WM_CM_S{}", xlib::XDefaultScreen(display))).unwrap();
+    let atom = xlib::XInternAtom(display, selection.as_ptr(), xlib::False);
+    if xlib::XGetSelectionOwner(display, atom) != 0 {
+        VsyncMode::Compositor
+    } else {
+        VsyncMode::SwapInterval
+    }
+}
+
+/// Find a framebuffer config matching the OpenGL configuration that supports the given drawable
+/// type.
+unsafe fn choose_fb_config(
+    display: *mut xlib::_XDisplay, config: &GlConfig, drawable_type: c_int,
+    error_handler: &mut errors::XErrorHandler,
+) -> Result<glx::GLXFBConfig, GlError> {
+    let fb_configs = choose_fb_configs(display, config, drawable_type, error_handler)?;
+
+    Ok(fb_configs[0])
+}
+
+/// Find all framebuffer configs matching the OpenGL configuration that support the given drawable
+/// type, best match first. The list is never empty.
+unsafe fn choose_fb_configs(
+    display: *mut xlib::_XDisplay, config: &GlConfig, drawable_type: c_int,
+    error_handler: &mut errors::XErrorHandler,
+) -> Result<Vec<glx::GLXFBConfig>, GlError> {
+    // Mesa picks the GPU when GLX is first used on the display connection
+    if config.gpu_preference == GpuPreference::HighPerformance
+        && std::env::var_os("DRI_PRIME").is_none()
+    {
+        std::env::set_var("DRI_PRIME", "1");
+    }
+
+    let screen = xlib::XDefaultScreen(display);
+
+    #[rustfmt::skip]
+    let fb_attribs = [
+        glx::GLX_X_RENDERABLE, 1,
+        glx::GLX_X_VISUAL_TYPE, glx::GLX_TRUE_COLOR,
+        glx::GLX_DRAWABLE_TYPE, drawable_type,
+        glx::GLX_RENDER_TYPE, glx::GLX_RGBA_BIT,
+        glx::GLX_RED_SIZE, config.red_bits as i32,
+        glx::GLX_GREEN_SIZE, config.green_bits as i32,
+        glx::GLX_BLUE_SIZE, config.blue_bits as i32,
+        glx::GLX_ALPHA_SIZE, config.alpha_bits as i32,
+        glx::GLX_DEPTH_SIZE, config.depth_bits as i32,
+        glx::GLX_STENCIL_SIZE, config.stencil_bits as i32,
+        glx::GLX_DOUBLEBUFFER, config.double_buffer as i32,
+        glx::GLX_SAMPLE_BUFFERS, config.samples.is_some() as i32,
+        glx::GLX_SAMPLES, config.samples.unwrap_or(0) as i32,
+        GLX_FRAMEBUFFER_SRGB_CAPABLE_ARB, config.srgb as i32,
+        0,
+    ];
+
+    let mut n_configs = 0;
+    let fb_configs = glx::glXChooseFBConfig(display, screen, fb_attribs.as_ptr(), &mut n_configs);
+
+    error_handler.check()?;
+    if n_configs <= 0 || fb_configs.is_null() {
+        return Err(GlError::CreationFailed(CreationFailedError::InvalidFBConfig));
+    }
+
+    let result = std::slice::from_raw_parts(fb_configs, n_configs as usize).to_vec();
+    xlib::XFree(fb_configs as *mut c_void);
+
+    Ok(result)
+}
+
+/// Create a context for the drawable, which must have been created with a matching framebuffer
+/// config. The swap interval is only set if one is given.
+unsafe fn create_context(
+    display: *mut xlib::_XDisplay, drawable: glx::GLXDrawable, fb_config: glx::GLXFBConfig,
+    config: &GlConfig, swap_interval: Option<c_int>, error_handler: &mut errors::XErrorHandler,
+) -> Result<glx::GLXContext, GlError> {
+    #[allow(non_snake_case)]
+    let glXCreateContextAttribsARB = {
+        let addr = get_proc_address("glXCreateContextAttribsARB");
+        if addr.is_null() {
+            return Err(GlError::CreationFailed(CreationFailedError::GetProcAddressFailed));
+        } else {
+            std::mem::transmute::<*const c_void, GlXCreateContextAttribsARB>(addr)
+        }
+    };
+
+    #[allow(non_snake_case)]
+    let glXSwapIntervalEXT = {
+        let addr = get_proc_address("glXSwapIntervalEXT");
+        if addr.is_null() {
+            return Err(GlError::CreationFailed(CreationFailedError::GetProcAddressFailed));
+        } else {
+            std::mem::transmute::<*const c_void, GlXSwapIntervalEXT>(addr)
+        }
+    };
+
+    error_handler.check()?;
+
+    let profile_mask = match config.profile {
+        Profile::Core => glx::arb::GLX_CONTEXT_CORE_PROFILE_BIT_ARB,
+        Profile::Compatibility => glx::arb::GLX_CONTEXT_COMPATIBILITY_PROFILE_BIT_ARB,
+    };
+
+    #[rustfmt::skip]
+    let ctx_attribs = [
+        glx::arb::GLX_CONTEXT_MAJOR_VERSION_ARB, config.version.0 as i32,
+        glx::arb::GLX_CONTEXT_MINOR_VERSION_ARB, config.version.1 as i32,
+        glx::arb::GLX_CONTEXT_PROFILE_MASK_ARB, profile_mask,
+        0,
+    ];
+
+    let context = glXCreateContextAttribsARB(
+        display,
+        fb_config,
+        std::ptr::null_mut(),
+        1,
+        ctx_attribs.as_ptr(),
+    );
+
+    error_handler.check()?;
+
+    if context.is_null() {
+        return Err(GlError::CreationFailed(CreationFailedError::ContextCreationFailed));
+    }
+
+    let res = glx::glXMakeCurrent(display, drawable, context);
+    error_handler.check()?;
+    if res == 0 {
+        glx::glXDestroyContext(display, context);
+        return Err(GlError::CreationFailed(CreationFailedError::MakeCurrentFailed));
+    }
+
+    if let Some(swap_interval) = swap_interval {
+        glXSwapIntervalEXT(display, drawable, swap_interval);
+        error_handler.check()?;
+    }
+
+    if glx::glXMakeCurrent(display, 0, std::ptr::null_mut()) == 0 {
+        error_handler.check()?;
+        return Err(GlError::CreationFailed(CreationFailedError::MakeCurrentFailed));
+    }
+
+    Ok(context)
 }
 
 pub struct GlContext {
+    /// The window, or the pbuffer for offscreen contexts
     window: c_ulong,
     display: *mut xlib::_XDisplay,
     context: glx::GLXContext,
+    /// Offscreen contexts own both the pbuffer and the display connection
+    offscreen: bool,
+    /// The requested [`GlConfig::vsync`]. When it's enabled, `vsync_mode` switches between the
+    /// compositor and a swap interval as compositors are started and stopped.
+    vsync: bool,
+    vsync_mode: Cell<VsyncMode>,
+    /// [`GlConfig::max_frame_latency`], which is handled by the platform independent context.
+    max_frame_latency: Option<u32>,
 }
 
 /// The frame buffer configuration along with the general OpenGL configuration to somewhat minimize
@@ -85,71 +252,77 @@ impl GlContext {
         }
 
         errors::XErrorHandler::handle(display, |error_handler| {
-            #[allow(non_snake_case)]
-            let glXCreateContextAttribsARB = {
-                let addr = get_proc_address("glXCreateContextAttribsARB");
-                if addr.is_null() {
-                    return Err(GlError::CreationFailed(CreationFailedError::GetProcAddressFailed));
-                } else {
-                    std::mem::transmute::<*const c_void, GlXCreateContextAttribsARB>(addr)
-                }
-            };
+            let vsync = config.gl_config.vsync;
+            let vsync_mode = window_vsync_mode(display, vsync);
+            let context = create_context(
+                display,
+                window,
+                config.fb_config,
+                &config.gl_config,
+                Some((vsync_mode == VsyncMode::SwapInterval) as c_int),
+                error_handler,
+            )?;
 
-            #[allow(non_snake_case)]
-            let glXSwapIntervalEXT = {
-                let addr = get_proc_address("glXSwapIntervalEXT");
-                if addr.is_null() {
-                    return Err(GlError::CreationFailed(CreationFailedError::GetProcAddressFailed));
-                } else {
-                    std::mem::transmute::<*const c_void, GlXSwapIntervalEXT>(addr)
-                }
-            };
+            Ok(GlContext {
+                window,
+                display,
+                context,
+                offscreen: false,
+                vsync,
+                vsync_mode: Cell::new(vsync_mode),
+                max_frame_latency: config.gl_config.max_frame_latency,
+            })
+        })
+    }
 
-            error_handler.check()?;
+    /// Create a context that renders to a 1x1 pbuffer on its own connection to the X server, so it
+    /// can be used without any window.
+    pub unsafe fn create_offscreen(config: GlConfig) -> Result<GlContext, GlError> {
+        let display = xlib::XOpenDisplay(std::ptr::null());
+        if display.is_null() {
+            return Err(GlError::CreationFailed(CreationFailedError::DisplayConnectionFailed));
+        }
 
-            let profile_mask = match config.gl_config.profile {
-                Profile::Core => glx::arb::GLX_CONTEXT_CORE_PROFILE_BIT_ARB,
-                Profile::Compatibility => glx::arb::GLX_CONTEXT_COMPATIBILITY_PROFILE_BIT_ARB,
-            };
+        let result = errors::XErrorHandler::handle(display, |error_handler| {
+            let fb_config =
+                choose_fb_config(display, &config, glx::GLX_PBUFFER_BIT, error_handler)?;
 
             #[rustfmt::skip]
-                let ctx_attribs = [
-                glx::arb::GLX_CONTEXT_MAJOR_VERSION_ARB, config.gl_config.version.0 as i32,
-                glx::arb::GLX_CONTEXT_MINOR_VERSION_ARB, config.gl_config.version.1 as i32,
-                glx::arb::GLX_CONTEXT_PROFILE_MASK_ARB, profile_mask,
+            let pbuffer_attribs = [
+                glx::GLX_PBUFFER_WIDTH, 1,
+                glx::GLX_PBUFFER_HEIGHT, 1,
                 0,
             ];
 
-            let context = glXCreateContextAttribsARB(
-                display,
-                config.fb_config,
-                std::ptr::null_mut(),
-                1,
-                ctx_attribs.as_ptr(),
-            );
-
+            let pbuffer = glx::glXCreatePbuffer(display, fb_config, pbuffer_attribs.as_ptr());
             error_handler.check()?;
-
-            if context.is_null() {
-                return Err(GlError::CreationFailed(CreationFailedError::ContextCreationFailed));
+            if pbuffer == 0 {
+                return Err(GlError::CreationFailed(CreationFailedError::PbufferCreationFailed));
             }
 
-            let res = glx::glXMakeCurrent(display, window, context);
-            error_handler.check()?;
-            if res == 0 {
-                return Err(GlError::CreationFailed(CreationFailedError::MakeCurrentFailed));
+            // Swap intervals only apply to windows
+            match create_context(display, pbuffer, fb_config, &config, None, error_handler) {
+                Ok(context) => Ok(GlContext {
+                    window: pbuffer,
+                    display,
+                    context,
+                    offscreen: true,
+                    vsync: false,
+                    vsync_mode: Cell::new(VsyncMode::Off),
+                    max_frame_latency: config.max_frame_latency,
+                }),
+                Err(err) => {
+                    glx::glXDestroyPbuffer(display, pbuffer);
+                    Err(err)
+                }
             }
+        });
 
-            glXSwapIntervalEXT(display, window, config.gl_config.vsync as i32);
-            error_handler.check()?;
-
-            if glx::glXMakeCurrent(display, 0, std::ptr::null_mut()) == 0 {
-                error_handler.check()?;
-                return Err(GlError::CreationFailed(CreationFailedError::MakeCurrentFailed));
-            }
+        if result.is_err() {
+            xlib::XCloseDisplay(display);
+        }
 
-            Ok(GlContext { window, display, context })
-        })
+        result
     }
 
     /// Find a matching framebuffer config and window visual for the given OpenGL configuration.
@@ -159,39 +332,10 @@ impl GlContext {
         display: *mut xlib::_XDisplay, config: GlConfig,
     ) -> Result<(FbConfig, WindowConfig), GlError> {
         errors::XErrorHandler::handle(display, |error_handler| {
-            let screen = xlib::XDefaultScreen(display);
-
-            #[rustfmt::skip]
-                let fb_attribs = [
-                glx::GLX_X_RENDERABLE, 1,
-                glx::GLX_X_VISUAL_TYPE, glx::GLX_TRUE_COLOR,
-                glx::GLX_DRAWABLE_TYPE, glx::GLX_WINDOW_BIT,
-                glx::GLX_RENDER_TYPE, glx::GLX_RGBA_BIT,
-                glx::GLX_RED_SIZE, config.red_bits as i32,
-                glx::GLX_GREEN_SIZE, config.green_bits as i32,
-                glx::GLX_BLUE_SIZE, config.blue_bits as i32,
-                glx::GLX_ALPHA_SIZE, config.alpha_bits as i32,
-                glx::GLX_DEPTH_SIZE, config.depth_bits as i32,
-                glx::GLX_STENCIL_SIZE, config.stencil_bits as i32,
-                glx::GLX_DOUBLEBUFFER, config.double_buffer as i32,
-                glx::GLX_SAMPLE_BUFFERS, config.samples.is_some() as i32,
-                glx::GLX_SAMPLES, config.samples.unwrap_or(0) as i32,
-                GLX_FRAMEBUFFER_SRGB_CAPABLE_ARB, config.srgb as i32,
-                0,
-            ];
-
-            let mut n_configs = 0;
-            let fb_config =
-                glx::glXChooseFBConfig(display, screen, fb_attribs.as_ptr(), &mut n_configs);
-
-            error_handler.check()?;
-            if n_configs <= 0 || fb_config.is_null() {
-                return Err(GlError::CreationFailed(CreationFailedError::InvalidFBConfig));
-            }
+            let fb_config = choose_fb_config(display, &config, glx::GLX_WINDOW_BIT, error_handler)?;
 
             // Now that we have a matching framebuffer config, we need to know which visual matches
             // thsi config so the window is compatible with the OpenGL context we're about to create
-            let fb_config = *fb_config;
             let visual = glx::glXGetVisualFromFBConfig(display, fb_config);
             if visual.is_null() {
                 return Err(GlError::CreationFailed(CreationFailedError::NoVisual));
@@ -204,6 +348,35 @@ impl GlContext {
         })
     }
 
+    /// Find a framebuffer config for the given OpenGL configuration that can be used with an
+    /// existing window's visual. This is used to create a context for a window that was opened
+    /// without an OpenGL configuration, and it fails if none of the matching framebuffer configs
+    /// use that visual.
+    pub unsafe fn get_fb_config_for_visual(
+        display: *mut xlib::_XDisplay, config: GlConfig, visual: u32,
+    ) -> Result<FbConfig, GlError> {
+        errors::XErrorHandler::handle(display, |error_handler| {
+            let fb_configs =
+                choose_fb_configs(display, &config, glx::GLX_WINDOW_BIT, error_handler)?;
+
+            let fb_config = fb_configs.into_iter().find(|&fb_config| {
+                let visual_info = glx::glXGetVisualFromFBConfig(display, fb_config);
+                if visual_info.is_null() {
+                    return false;
+                }
+
+                let matches = (*visual_info).visualid as u32 == visual;
+                xlib::XFree(visual_info as *mut c_void);
+                matches
+            });
+
+            match fb_config {
+                Some(fb_config) => Ok(FbConfig { fb_config, gl_config: config }),
+                None => Err(GlError::CreationFailed(CreationFailedError::NoVisual)),
+            }
+        })
+    }
+
     pub unsafe fn make_current(&self) {
         errors::XErrorHandler::handle(self.display, |error_handler| {
             let res = glx::glXMakeCurrent(self.display, self.window, self.context);
@@ -228,6 +401,20 @@ impl GlContext {
         get_proc_address(symbol)
     }
 
+    pub fn resized(&self, _size: PhySize) {
+        // The context renders directly to the window, so the drawable is resized along with it
+    }
+
+    /// The window keeps its visual when it's reparented, so the drawable stays valid. The context
+    /// is bound again if it's current so the driver picks up the new window hierarchy.
+    pub fn surface_changed(&self) {
+        unsafe {
+            if glx::glXGetCurrentContext() == self.context {
+                self.make_current();
+            }
+        }
+    }
+
     pub fn swap_buffers(&self) {
         unsafe {
             errors::XErrorHandler::handle(self.display, |error_handler| {
@@ -236,12 +423,60 @@ impl GlContext {
             })
         }
     }
+
+    pub fn vsync_mode(&self) -> VsyncMode {
+        self.vsync_mode.get()
+    }
+
+    /// GLX only reports display times through the `GLX_OML_sync_control` extension, which isn't
+    /// used here.
+    pub fn next_vblank(&self, _now: Instant) -> Option<Instant> {
+        None
+    }
+
+    pub fn max_frame_latency(&self) -> Option<u32> {
+        self.max_frame_latency
+    }
+
+    /// Switch between syncing to the compositor and using a swap interval after a compositor was
+    /// started or stopped.
+    pub fn compositor_changed(&self) {
+        if !self.vsync {
+            return;
+        }
+
+        let vsync_mode = unsafe { window_vsync_mode(self.display, true) };
+        if vsync_mode == self.vsync_mode.replace(vsync_mode) {
+            return;
+        }
+
+        let addr = get_proc_address("glXSwapIntervalEXT");
+        if addr.is_null() {
+            return;
+        }
+
+        unsafe {
+            #[allow(non_snake_case)]
+            let glXSwapIntervalEXT = std::mem::transmute::<*const c_void, GlXSwapIntervalEXT>(addr);
+            let swap_interval = (vsync_mode == VsyncMode::SwapInterval) as c_int;
+
+            errors::XErrorHandler::handle(self.display, |error_handler| {
+                glXSwapIntervalEXT(self.display, self.window, swap_interval);
+                let _ = error_handler.check();
+            })
+        }
+    }
 }
 
 impl Drop for GlContext {
     fn drop(&mut self) {
         unsafe {
             glx::glXDestroyContext(self.display, self.context);
+
+            if self.offscreen {
+                glx::glXDestroyPbuffer(self.display, self.window);
+                xlib::XCloseDisplay(self.display);
+            }
         }
     }
 }
@@ -0,0 +1,13 @@
+/// The kinds of haptic feedback that can be performed with
+/// [`Window::perform_haptic()`][crate::Window::perform_haptic()]. These correspond to the feedback
+/// patterns of Force Touch trackpads on macOS.
+#[derive(Debug, Eq, PartialEq, Clone, Copy, Hash)]
+pub enum HapticPattern {
+    /// Feedback that doesn't have a more specific meaning.
+    Generic,
+    /// Something snapped into alignment, like a dragged object lining up with a guide or a knob
+    /// reaching its center position.
+    Alignment,
+    /// A value moved to a discrete level, like a knob passing a detent.
+    LevelChange,
+}
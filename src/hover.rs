@@ -0,0 +1,139 @@
+use std::cell::Cell;
+
+use crate::MouseEvent;
+
+/// Keeps track of whether the cursor is hovering over the window, so the backends can send
+/// [`MouseEvent::CursorEntered`] and [`MouseEvent::CursorLeft`] in balanced pairs and stop sending
+/// hover events while something is being dragged over the window. See
+/// [`MouseEvent::DragEntered`] for the resulting event order.
+#[derive(Debug, Default)]
+pub(crate) struct HoverState {
+    hovered: Cell<bool>,
+    dragging: Cell<bool>,
+}
+
+impl HoverState {
+    /// Pass a cursor movement, enter, or leave event through the hover state. `send` is called for
+    /// every event that should be sent to the handler in its place. This may be no events at all
+    /// during a drag, or a synthesized `CursorEntered` event before a cursor movement.
+    pub fn filter(&self, event: MouseEvent, mut send: impl FnMut(MouseEvent)) {
+        if self.dragging.get() {
+            return;
+        }
+
+        match event {
+            MouseEvent::CursorEntered => {
+                if !self.hovered.replace(true) {
+                    send(event);
+                }
+            }
+            MouseEvent::CursorLeft => {
+                if self.hovered.replace(false) {
+                    send(event);
+                }
+            }
+            MouseEvent::CursorMoved { .. } => {
+                if !self.hovered.replace(true) {
+                    send(MouseEvent::CursorEntered);
+                }
+                send(event);
+            }
+            _ => send(event),
+        }
+    }
+
+    /// Must be called before sending a [`MouseEvent::DragEntered`] event. If the cursor was
+    /// hovering over the window, `send` is called with a `CursorLeft` event so the handler can
+    /// clear its hover state before the drag starts.
+    pub fn drag_entered(&self, send: impl FnOnce(MouseEvent)) {
+        self.dragging.set(true);
+        if self.hovered.replace(false) {
+            send(MouseEvent::CursorLeft);
+        }
+    }
+
+    /// Must be called after sending a [`MouseEvent::DragLeft`] or [`MouseEvent::DragDropped`]
+    /// event. The next cursor movement is then preceded by a `CursorEntered` event again.
+    pub fn drag_ended(&self) {
+        self.dragging.set(false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_events::moved;
+    use crate::{Event, PointerDevice};
+
+    fn cursor_moved(x: f64, y: f64) -> MouseEvent {
+        match moved(PointerDevice::default(), x, y) {
+            Event::Mouse(event) => event,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Feed `events` through `hover` and return the events the handler would have received.
+    fn filter_all(hover: &HoverState, events: Vec<MouseEvent>) -> Vec<MouseEvent> {
+        let mut sent = Vec::new();
+        for event in events {
+            hover.filter(event, |event| sent.push(event));
+        }
+        sent
+    }
+
+    #[test]
+    fn balances_enter_and_leave() {
+        let hover = HoverState::default();
+
+        // A movement without an enter event gets one, and repeated enters and leaves are dropped
+        let sent = filter_all(
+            &hover,
+            vec![
+                cursor_moved(1.0, 1.0),
+                MouseEvent::CursorEntered,
+                MouseEvent::CursorLeft,
+                MouseEvent::CursorLeft,
+            ],
+        );
+        assert_eq!(
+            sent,
+            [MouseEvent::CursorEntered, cursor_moved(1.0, 1.0), MouseEvent::CursorLeft]
+        );
+    }
+
+    #[test]
+    fn leaves_before_a_drag_and_reenters_after() {
+        let hover = HoverState::default();
+        let mut sent = filter_all(&hover, vec![MouseEvent::CursorEntered, cursor_moved(1.0, 1.0)]);
+
+        hover.drag_entered(|event| sent.push(event));
+        sent.push(MouseEvent::DragLeft);
+        sent.extend(filter_all(&hover, vec![cursor_moved(2.0, 2.0), MouseEvent::CursorLeft]));
+        hover.drag_ended();
+        sent.extend(filter_all(&hover, vec![cursor_moved(3.0, 3.0)]));
+
+        assert_eq!(
+            sent,
+            [
+                MouseEvent::CursorEntered,
+                cursor_moved(1.0, 1.0),
+                MouseEvent::CursorLeft,
+                MouseEvent::DragLeft,
+                MouseEvent::CursorEntered,
+                cursor_moved(3.0, 3.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn drag_without_hovering_sends_no_leave() {
+        let hover = HoverState::default();
+        let mut sent = Vec::new();
+
+        hover.drag_entered(|event| sent.push(event));
+        hover.drag_ended();
+        sent.extend(filter_all(&hover, vec![MouseEvent::CursorLeft, MouseEvent::CursorEntered]));
+
+        assert_eq!(sent, [MouseEvent::CursorEntered]);
+    }
+}
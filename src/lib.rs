@@ -7,20 +7,51 @@ mod win;
 #[cfg(target_os = "linux")]
 mod x11;
 
+mod accelerator;
+mod app;
 mod clipboard;
 mod event;
+mod frame_stats;
+mod haptic;
+mod hover;
 mod keyboard;
 mod mouse_cursor;
+mod platform_error;
+mod scancode;
+#[cfg(test)]
+mod test_events;
+mod text;
 mod window;
 mod window_info;
 mod window_open_options;
 
+pub mod drag;
+#[cfg(feature = "event-log")]
+pub mod event_log;
+#[cfg(feature = "gestures")]
+pub mod gestures;
 #[cfg(feature = "opengl")]
 pub mod gl;
+#[cfg(feature = "recording")]
+pub mod recording;
+#[cfg(feature = "virtual-clock")]
+pub mod virtual_clock;
+#[cfg(feature = "watchdog")]
+pub mod watchdog;
 
+pub use accelerator::{GlobalShortcutError, KeyCombo};
+pub use app::run_app;
 pub use clipboard::*;
 pub use event::*;
+pub use frame_stats::{AnimationFrame, FrameStats};
+pub use haptic::HapticPattern;
 pub use mouse_cursor::MouseCursor;
+pub use platform_error::*;
 pub use window::*;
 pub use window_info::*;
 pub use window_open_options::*;
+
+/// The exact version of `keyboard-types` used in [`Event::Keyboard`]. Use this re-export instead of
+/// depending on `keyboard-types` directly so the types can't end up coming from two different
+/// versions of the crate.
+pub use keyboard_types;
@@ -10,19 +10,21 @@ pub enum Cursor {
 }
 
 impl From<MouseCursor> for Cursor {
+    #[allow(deprecated)]
     fn from(cursor: MouseCursor) -> Self {
         match cursor {
             MouseCursor::Default => Cursor::Native("arrowCursor"),
-            MouseCursor::Hand => Cursor::Native("openHandCursor"),
+            MouseCursor::Hand => Cursor::Native("pointingHandCursor"),
+            MouseCursor::Grab => Cursor::Native("openHandCursor"),
             MouseCursor::HandGrabbing => Cursor::Native("closedHandCursor"),
             MouseCursor::Text => Cursor::Native("IBeamCursor"),
             MouseCursor::VerticalText => Cursor::Native("IBeamCursorForVerticalLayout"),
             MouseCursor::Copy => Cursor::Native("dragCopyCursor"),
             MouseCursor::Alias => Cursor::Native("dragLinkCursor"),
-            MouseCursor::NotAllowed | MouseCursor::PtrNotAllowed => {
+            MouseCursor::NotAllowed | MouseCursor::PtrNotAllowed | MouseCursor::NoDrop => {
                 Cursor::Native("operationNotAllowedCursor")
             }
-            // MouseCursor:: => Cursor::Native("contextualMenuCursor"),
+            MouseCursor::ContextMenu => Cursor::Native("contextualMenuCursor"),
             MouseCursor::Crosshair => Cursor::Native("crosshairCursor"),
             MouseCursor::EResize => Cursor::Native("resizeRightCursor"),
             MouseCursor::NResize => Cursor::Native("resizeUpCursor"),
@@ -36,6 +38,7 @@ impl From<MouseCursor> for Cursor {
             MouseCursor::Help => Cursor::Undocumented("_helpCursor"),
             MouseCursor::ZoomIn => Cursor::Undocumented("_zoomInCursor"),
             MouseCursor::ZoomOut => Cursor::Undocumented("_zoomOutCursor"),
+            MouseCursor::Move | MouseCursor::AllScroll => Cursor::Undocumented("_moveCursor"),
             MouseCursor::NeResize => Cursor::Undocumented("_windowResizeNorthEastCursor"),
             MouseCursor::NwResize => Cursor::Undocumented("_windowResizeNorthWestCursor"),
             MouseCursor::SeResize => Cursor::Undocumented("_windowResizeSouthEastCursor"),
@@ -47,14 +50,15 @@ impl From<MouseCursor> for Cursor {
                 Cursor::Undocumented("_windowResizeNorthWestSouthEastCursor")
             }
 
-            MouseCursor::Working | MouseCursor::PtrWorking => {
-                Cursor::Undocumented("busyButClickableCursor")
-            }
+            // macOS doesn't have a cursor for an unresponsive application, the system shows the
+            // spinning wait cursor by itself when the main thread is blocked
+            MouseCursor::Wait
+            | MouseCursor::Progress
+            | MouseCursor::Working
+            | MouseCursor::PtrWorking => Cursor::Undocumented("busyButClickableCursor"),
 
             _ => Cursor::Native("arrowCursor"),
             // MouseCursor::Hidden => todo!(),
-            // MouseCursor::Move => todo!(),
-            // MouseCursor::AllScroll => todo!(),
             // MouseCursor::Cell => todo!(),
         }
     }
@@ -0,0 +1,51 @@
+use cocoa::base::id;
+use cocoa::foundation::{NSPoint, NSRect, NSSize};
+use objc::{msg_send, sel, sel_impl};
+
+use crate::gl::{GlArea, GlContext, GlError};
+use crate::{PhyPoint, PhySize};
+
+/// The `NSOpenGLView` a [`GlArea`] renders to. The view is owned by the area's context, this only
+/// keeps it positioned within the window's view and removes it again when the area is dropped.
+pub(crate) struct GlAreaSurface {
+    view: id,
+}
+
+impl GlAreaSurface {
+    pub fn set_bounds(&self, position: PhyPoint, size: PhySize) {
+        unsafe {
+            // This converts the same way as the context's `resized()`, which also sets the size.
+            // The window's view is flipped, so the origin is the area's top left corner.
+            let physical_origin = NSSize::new(position.x as f64, position.y as f64);
+            let physical_size = NSSize::new(size.width as f64, size.height as f64);
+            let origin: NSSize = msg_send![self.view, convertSizeFromBacking: physical_origin];
+            let size: NSSize = msg_send![self.view, convertSizeFromBacking: physical_size];
+
+            let frame = NSRect::new(NSPoint::new(origin.width, origin.height), size);
+            let () = msg_send![self.view, setFrame: frame];
+        }
+    }
+}
+
+impl Drop for GlAreaSurface {
+    fn drop(&mut self) {
+        unsafe {
+            let () = msg_send![self.view, removeFromSuperview];
+            let () = msg_send![self.view, release];
+        }
+    }
+}
+
+/// Turn a context created for the window's view into an area. Contexts created for a view add their
+/// own `NSOpenGLView` as a subview of that view.
+pub(super) fn create_gl_area(
+    context: GlContext, position: PhyPoint, size: PhySize,
+) -> Result<GlArea, GlError> {
+    let view = context.ns_view().ok_or(GlError::CreationFailed(()))?;
+    let () = unsafe { msg_send![view, retain] };
+
+    let surface = GlAreaSurface { view };
+    surface.set_bounds(position, size);
+
+    Ok(GlArea::new(context, surface))
+}
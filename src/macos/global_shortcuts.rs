@@ -0,0 +1,242 @@
+use std::cell::{Cell, RefCell};
+use std::ffi::c_void;
+use std::mem::size_of;
+use std::ptr::null_mut;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use cocoa::base::id;
+use keyboard_types::Modifiers;
+use objc::runtime::Object;
+
+use super::keyboard::code_to_key_code;
+use super::window::WindowState;
+use crate::{Event, GlobalShortcutError, KeyCombo};
+
+type OSStatus = i32;
+type EventHandlerCallRef = *mut c_void;
+type EventHandlerRef = *mut c_void;
+type EventHotKeyRef = *mut c_void;
+type EventRef = *mut c_void;
+type EventTargetRef = *mut c_void;
+type EventHandlerUPP = extern "C" fn(EventHandlerCallRef, EventRef, *mut c_void) -> OSStatus;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct EventHotKeyID {
+    signature: u32,
+    id: u32,
+}
+
+#[repr(C)]
+struct EventTypeSpec {
+    event_class: u32,
+    event_kind: u32,
+}
+
+#[allow(non_upper_case_globals)]
+mod consts {
+    pub const noErr: i32 = 0;
+    pub const eventNotHandledErr: i32 = -9874;
+    /// `'keyb'`
+    pub const kEventClassKeyboard: u32 = 0x6b65_7962;
+    pub const kEventHotKeyPressed: u32 = 5;
+    /// `'----'`
+    pub const kEventParamDirectObject: u32 = 0x2d2d_2d2d;
+    /// `'hkid'`
+    pub const typeEventHotKeyID: u32 = 0x686b_6964;
+
+    pub const cmdKey: u32 = 1 << 8;
+    pub const shiftKey: u32 = 1 << 9;
+    pub const optionKey: u32 = 1 << 11;
+    pub const controlKey: u32 = 1 << 12;
+}
+use consts::*;
+
+#[link(name = "Carbon", kind = "framework")]
+unsafe extern "C" {
+    fn GetApplicationEventTarget() -> EventTargetRef;
+    fn InstallEventHandler(
+        target: EventTargetRef, handler: EventHandlerUPP, num_types: usize,
+        list: *const EventTypeSpec, user_data: *mut c_void, out_ref: *mut EventHandlerRef,
+    ) -> OSStatus;
+    fn RemoveEventHandler(handler: EventHandlerRef) -> OSStatus;
+    fn RegisterEventHotKey(
+        key_code: u32, modifiers: u32, hot_key_id: EventHotKeyID, target: EventTargetRef,
+        options: u32, out_ref: *mut EventHotKeyRef,
+    ) -> OSStatus;
+    fn UnregisterEventHotKey(hot_key: EventHotKeyRef) -> OSStatus;
+    fn GetEventParameter(
+        event: EventRef, name: u32, desired_type: u32, actual_type: *mut u32, buffer_size: usize,
+        actual_size: *mut usize, data: *mut c_void,
+    ) -> OSStatus;
+}
+
+/// Identifies the hot keys registered by baseview, `'bsvw'`.
+const HOT_KEY_SIGNATURE: u32 = 0x6273_7677;
+
+/// Hot key ids are shared by all windows, since every window's event handler sees every hot key.
+static NEXT_HOT_KEY_ID: AtomicU32 = AtomicU32::new(0);
+
+struct HotKey {
+    hot_key_ref: EventHotKeyRef,
+    hot_key_id: u32,
+    id: u32,
+    key_code: u32,
+    modifiers: u32,
+}
+
+/// The Carbon hot keys registered for a window's global shortcuts. These are the only way to
+/// receive key presses while another application is active without asking for accessibility
+/// permissions.
+pub(super) struct GlobalShortcuts {
+    /// The application event handler receiving the hot key events, installed along with the first
+    /// hot key.
+    event_handler: Cell<EventHandlerRef>,
+    hot_keys: RefCell<Vec<HotKey>>,
+}
+
+impl GlobalShortcuts {
+    pub fn new() -> Self {
+        Self { event_handler: Cell::new(null_mut()), hot_keys: RefCell::new(Vec::new()) }
+    }
+
+    /// Register a hot key, sending its events to the window state of `ns_view`.
+    pub fn register(
+        &self, ns_view: id, combo: KeyCombo, id: u32,
+    ) -> Result<(), GlobalShortcutError> {
+        let key_code = combo
+            .code()
+            .and_then(code_to_key_code)
+            .map(u32::from)
+            .ok_or(GlobalShortcutError::UnsupportedKey)?;
+        let modifiers = hot_key_modifiers(combo.modifiers);
+
+        let mut hot_keys = self.hot_keys.borrow_mut();
+        if let Some(hot_key) = hot_keys
+            .iter_mut()
+            .find(|hot_key| hot_key.key_code == key_code && hot_key.modifiers == modifiers)
+        {
+            hot_key.id = id;
+            return Ok(());
+        }
+
+        unsafe {
+            if self.event_handler.get().is_null() {
+                let event_type = EventTypeSpec {
+                    event_class: kEventClassKeyboard,
+                    event_kind: kEventHotKeyPressed,
+                };
+                let mut event_handler = null_mut();
+                let status = InstallEventHandler(
+                    GetApplicationEventTarget(),
+                    handle_hot_key,
+                    1,
+                    &event_type,
+                    ns_view as *mut c_void,
+                    &mut event_handler,
+                );
+                if status != noErr {
+                    return Err(GlobalShortcutError::Unavailable);
+                }
+
+                self.event_handler.set(event_handler);
+            }
+
+            let hot_key_id = NEXT_HOT_KEY_ID.fetch_add(1, Ordering::Relaxed);
+            let mut hot_key_ref = null_mut();
+            let status = RegisterEventHotKey(
+                key_code,
+                modifiers,
+                EventHotKeyID { signature: HOT_KEY_SIGNATURE, id: hot_key_id },
+                GetApplicationEventTarget(),
+                0,
+                &mut hot_key_ref,
+            );
+            if status != noErr {
+                return Err(GlobalShortcutError::Unavailable);
+            }
+
+            hot_keys.push(HotKey { hot_key_ref, hot_key_id, id, key_code, modifiers });
+        }
+
+        Ok(())
+    }
+
+    /// Remove the shortcuts registered with `id`.
+    pub fn unregister(&self, id: u32) {
+        self.hot_keys.borrow_mut().retain(|hot_key| {
+            if hot_key.id != id {
+                return true;
+            }
+
+            unsafe { UnregisterEventHotKey(hot_key.hot_key_ref) };
+            false
+        });
+    }
+
+    /// Remove all hot keys and the event handler. This needs to happen before the view is
+    /// released, since the event handler refers to it.
+    pub fn unregister_all(&self) {
+        for hot_key in self.hot_keys.borrow_mut().drain(..) {
+            unsafe { UnregisterEventHotKey(hot_key.hot_key_ref) };
+        }
+
+        let event_handler = self.event_handler.replace(null_mut());
+        if !event_handler.is_null() {
+            unsafe { RemoveEventHandler(event_handler) };
+        }
+    }
+
+    fn find(&self, hot_key_id: u32) -> Option<u32> {
+        self.hot_keys
+            .borrow()
+            .iter()
+            .find(|hot_key| hot_key.hot_key_id == hot_key_id)
+            .map(|hot_key| hot_key.id)
+    }
+}
+
+extern "C" fn handle_hot_key(
+    _call_ref: EventHandlerCallRef, event: EventRef, user_data: *mut c_void,
+) -> OSStatus {
+    let mut hot_key_id = EventHotKeyID { signature: 0, id: 0 };
+    let status = unsafe {
+        GetEventParameter(
+            event,
+            kEventParamDirectObject,
+            typeEventHotKeyID,
+            null_mut(),
+            size_of::<EventHotKeyID>(),
+            null_mut(),
+            &mut hot_key_id as *mut EventHotKeyID as *mut c_void,
+        )
+    };
+    if status != noErr || hot_key_id.signature != HOT_KEY_SIGNATURE {
+        return eventNotHandledErr;
+    }
+
+    // Every window installs its own handler, so hot keys registered by other windows are passed on
+    // to the next handler
+    let state = unsafe { WindowState::from_view(&*(user_data as *const Object)) };
+    match state.window_inner.global_shortcuts.find(hot_key_id.id) {
+        Some(id) => {
+            state.trigger_deferrable_event(Event::GlobalShortcut(id));
+            noErr
+        }
+        None => eventNotHandledErr,
+    }
+}
+
+fn hot_key_modifiers(modifiers: Modifiers) -> u32 {
+    let key_modifiers = [
+        (Modifiers::SHIFT, shiftKey),
+        (Modifiers::CONTROL, controlKey),
+        (Modifiers::ALT, optionKey),
+        (Modifiers::META, cmdKey),
+    ];
+
+    key_modifiers
+        .iter()
+        .filter(|(modifier, _)| modifiers.contains(*modifier))
+        .fold(0, |hot_key_modifiers, (_, flag)| hot_key_modifiers | flag)
+}
@@ -38,10 +38,9 @@ pub(crate) fn from_nsstring(s: id) -> String {
 
 /// State for processing of keyboard events.
 ///
-/// This needs to be stateful for proper processing of dead keys. The current
-/// implementation is somewhat primitive and is not based on IME; in the future
-/// when IME is implemented, it will need to be redone somewhat, letting the IME
-/// be the authoritative source of truth for Unicode string values of keys.
+/// This needs to be stateful to detect modifier key presses and releases. Dead keys
+/// are composed through the input method afterwards, see
+/// [`super::text_input::interpret_key_event()`].
 ///
 /// Most of the logic in this module is adapted from Mozilla, and in particular
 /// TextInputHandler.mm.
@@ -187,6 +186,15 @@ fn key_code_to_code(key_code: u16) -> Code {
     }
 }
 
+/// The virtual key code of the physical key with this code, the inverse of [`key_code_to_code()`].
+pub(super) fn code_to_key_code(code: Code) -> Option<u16> {
+    if code == Code::Unidentified {
+        return None;
+    }
+
+    (0..0x80).find(|&key_code| key_code_to_code(key_code) == code)
+}
+
 /// Convert code to key.
 ///
 /// On macOS, for non-printable keys, the keyCode we get from the event serves is
@@ -338,6 +346,8 @@ impl KeyboardState {
     }
 }
 
+// Mac keyboards don't have Num Lock or Scroll Lock keys, so Caps Lock is the only lock key.
+// `NSNumericPadKeyMask` is not a lock state, it's set for any key on the numeric keypad.
 const MODIFIER_MAP: &[(NSEventModifierFlags, Modifiers)] = &[
     (NSEventModifierFlags::NSShiftKeyMask, Modifiers::SHIFT),
     (NSEventModifierFlags::NSAlternateKeyMask, Modifiers::ALT),
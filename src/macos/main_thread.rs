@@ -0,0 +1,51 @@
+use std::ffi::c_void;
+
+use cocoa::base::{BOOL, NO};
+use objc::{class, msg_send, sel, sel_impl};
+
+#[repr(C)]
+struct DispatchQueue {
+    _private: [u8; 0],
+}
+
+// libdispatch is part of libSystem, so it doesn't need to be linked explicitly. The main queue is
+// exposed as this symbol, `dispatch_get_main_queue()` is a macro.
+unsafe extern "C" {
+    static _dispatch_main_q: DispatchQueue;
+    fn dispatch_sync_f(
+        queue: *const DispatchQueue, context: *mut c_void, work: extern "C" fn(*mut c_void),
+    );
+}
+
+pub(crate) fn is_main_thread() -> bool {
+    let is_main_thread: BOOL = unsafe { msg_send![class!(NSThread), isMainThread] };
+    is_main_thread != NO
+}
+
+/// Run `f` on the main thread and return its result. `f` is called directly when this is already
+/// the main thread. Otherwise the calling thread blocks until the main thread's run loop has run
+/// `f`, so this deadlocks if the main thread is waiting for the calling thread.
+///
+/// `f` doesn't need to be `Send` because the calling thread is blocked while it runs, but anything
+/// it touches that isn't `Send` must only ever be used on the main thread.
+pub(super) fn run_on_main_thread<R, F: FnOnce() -> R>(f: F) -> R {
+    if is_main_thread() {
+        return f();
+    }
+
+    extern "C" fn work<R, F: FnOnce() -> R>(context: *mut c_void) {
+        let (f, result) = unsafe { &mut *(context as *mut (Option<F>, Option<R>)) };
+        *result = Some(f.take().unwrap()());
+    }
+
+    let mut context: (Option<F>, Option<R>) = (Some(f), None);
+    unsafe {
+        dispatch_sync_f(
+            &_dispatch_main_q,
+            &mut context as *mut (Option<F>, Option<R>) as *mut c_void,
+            work::<R, F>,
+        );
+    }
+
+    context.1.unwrap()
+}
@@ -1,10 +1,20 @@
 mod cursor;
+#[cfg(feature = "opengl")]
+mod gl_area;
+mod global_shortcuts;
 mod keyboard;
+mod main_thread;
+mod text_input;
 mod view;
 mod window;
 
 pub use window::*;
 
+pub(crate) use main_thread::is_main_thread;
+
+#[cfg(feature = "opengl")]
+pub(crate) use gl_area::GlAreaSurface;
+
 #[allow(non_upper_case_globals)]
 mod consts {
     use cocoa::foundation::NSUInteger;
@@ -14,6 +24,8 @@ mod consts {
     pub const NSDragOperationLink: NSUInteger = 2;
     pub const NSDragOperationGeneric: NSUInteger = 4;
     pub const NSDragOperationMove: NSUInteger = 16;
+
+    pub const NSWindowStyleMaskNonactivatingPanel: NSUInteger = 1 << 7;
 }
 use consts::*;
 
@@ -22,4 +34,14 @@ use consts::*;
 #[allow(improper_ctypes)]
 unsafe extern "C" {
     fn CGWarpMouseCursorPosition(point: cocoa::foundation::NSPoint) -> i32;
+    fn CGAssociateMouseAndMouseCursorPosition(connected: u32) -> i32;
+}
+
+#[link(name = "Carbon", kind = "framework")]
+unsafe extern "C" {
+    static kTISPropertyInputSourceID: core_foundation::string::CFStringRef;
+    fn TISCopyCurrentKeyboardLayoutInputSource() -> *mut std::ffi::c_void;
+    fn TISGetInputSourceProperty(
+        input_source: *mut std::ffi::c_void, key: core_foundation::string::CFStringRef,
+    ) -> *const std::ffi::c_void;
 }
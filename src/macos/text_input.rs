@@ -0,0 +1,293 @@
+//! The view's `NSTextInputClient` implementation, which lets input methods compose text while
+//! text input is active. See [`crate::ImeEvent`].
+
+use std::ffi::c_void;
+
+use cocoa::appkit::{NSEvent, NSEventType};
+use cocoa::base::{id, nil, BOOL, NO, YES};
+use cocoa::foundation::{NSArray, NSNotFound, NSPoint, NSRect, NSSize, NSUInteger};
+use keyboard_types::{Key, KeyboardEvent, Modifiers, NamedKey};
+use objc::declare::ClassDecl;
+use objc::runtime::{Object, Protocol, Sel};
+use objc::{class, msg_send, sel, sel_impl, Encode, Encoding};
+
+use crate::text::utf16_to_byte_offset;
+use crate::{Event, ImeEvent};
+
+use super::keyboard::from_nsstring;
+use super::window::WindowState;
+
+/// `NSRange`, which doesn't implement `Encode` in the cocoa crate.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct NSRange {
+    location: NSUInteger,
+    length: NSUInteger,
+}
+
+impl NSRange {
+    const NOT_FOUND: NSRange = NSRange { location: NSNotFound as NSUInteger, length: 0 };
+}
+
+unsafe impl Encode for NSRange {
+    fn encode() -> Encoding {
+        let encoding = format!(
+            "{{_NSRange={}{}}}",
+            NSUInteger::encode().as_str(),
+            NSUInteger::encode().as_str()
+        );
+        unsafe { Encoding::from_str(&encoding) }
+    }
+}
+
+pub(super) unsafe fn add_text_input_client_methods(class: &mut ClassDecl) {
+    // Views only get an input context if they conform to the protocol
+    if let Some(protocol) = Protocol::get("NSTextInputClient") {
+        class.add_protocol(protocol);
+    }
+
+    class.add_method(sel!(hasMarkedText), has_marked_text as extern "C" fn(&Object, Sel) -> BOOL);
+    class.add_method(sel!(markedRange), marked_range as extern "C" fn(&Object, Sel) -> NSRange);
+    class.add_method(sel!(selectedRange), selected_range as extern "C" fn(&Object, Sel) -> NSRange);
+    class.add_method(
+        sel!(setMarkedText:selectedRange:replacementRange:),
+        set_marked_text as extern "C" fn(&Object, Sel, id, NSRange, NSRange),
+    );
+    class.add_method(sel!(unmarkText), unmark_text as extern "C" fn(&Object, Sel));
+    class.add_method(
+        sel!(validAttributesForMarkedText),
+        valid_attributes_for_marked_text as extern "C" fn(&Object, Sel) -> id,
+    );
+    class.add_method(
+        sel!(attributedSubstringForProposedRange:actualRange:),
+        attributed_substring_for_proposed_range
+            as extern "C" fn(&Object, Sel, NSRange, *mut c_void) -> id,
+    );
+    class.add_method(
+        sel!(insertText:replacementRange:),
+        insert_text as extern "C" fn(&Object, Sel, id, NSRange),
+    );
+    class.add_method(
+        sel!(characterIndexForPoint:),
+        character_index_for_point as extern "C" fn(&Object, Sel, NSPoint) -> NSUInteger,
+    );
+    class.add_method(
+        sel!(firstRectForCharacterRange:actualRange:),
+        first_rect_for_character_range
+            as extern "C" fn(&Object, Sel, NSRange, *mut c_void) -> NSRect,
+    );
+    class.add_method(
+        sel!(doCommandBySelector:),
+        do_command_by_selector as extern "C" fn(&Object, Sel, Sel),
+    );
+}
+
+/// Let the input method see a key press first while text input is active. If the input method
+/// consumed the key press, then the returned event has `Key::Named(NamedKey::Process)` so it
+/// doesn't type anything.
+///
+/// Without text input the input method is only used to compose dead keys, see
+/// [`compose_dead_key()`].
+pub(super) unsafe fn interpret_key_event(
+    this: &Object, state: &WindowState, event: id, mut key_event: KeyboardEvent,
+) -> KeyboardEvent {
+    if event.eventType() != NSEventType::NSKeyDown {
+        return key_event;
+    }
+    if state.window_inner.text_input.get().is_none() {
+        return compose_dead_key(this, state, event, key_event);
+    }
+
+    state.interpreting_key.set(true);
+    state.ime_consumed_key.set(false);
+    let events: id = msg_send![class!(NSArray), arrayWithObject: event];
+    let () = msg_send![this, interpretKeyEvents: events];
+    state.interpreting_key.set(false);
+
+    if state.ime_consumed_key.get() {
+        key_event.key = Key::Named(NamedKey::Process);
+        key_event.is_composing = true;
+    }
+
+    key_event
+}
+
+/// A dead key press doesn't produce any characters. The input method marks the accent instead, and
+/// inserts the composed character, like `é`, on the next key press. That key press then carries
+/// the composed character, and the dead key press itself is sent as `Key::Named(NamedKey::Dead)`.
+///
+/// Other key presses aren't passed to the input method, so input methods for languages like
+/// Japanese don't swallow key presses while the handler isn't expecting text.
+unsafe fn compose_dead_key(
+    this: &Object, state: &WindowState, event: id, mut key_event: KeyboardEvent,
+) -> KeyboardEvent {
+    let is_composing = !state.window_inner.marked_text.borrow().is_empty();
+    let is_dead_key = from_nsstring(event.characters()).is_empty();
+    if key_event.modifiers.contains(Modifiers::META) || !(is_composing || is_dead_key) {
+        return key_event;
+    }
+
+    state.interpreting_key.set(true);
+    state.ime_consumed_key.set(false);
+    let events: id = msg_send![class!(NSArray), arrayWithObject: event];
+    let () = msg_send![this, interpretKeyEvents: events];
+    state.interpreting_key.set(false);
+
+    let composed_text = state.composed_text.take();
+    if !composed_text.is_empty() {
+        key_event.key = Key::Character(composed_text);
+    } else if state.ime_consumed_key.get() {
+        key_event.key = Key::Named(NamedKey::Dead);
+    }
+
+    key_event
+}
+
+/// Tell the input method that the text field's cursor moved, so it asks for the new location with
+/// `firstRectForCharacterRange:actualRange:`.
+pub(super) unsafe fn invalidate_character_coordinates(ns_view: id) {
+    let input_context: id = msg_send![ns_view, inputContext];
+    if input_context != nil {
+        let () = msg_send![input_context, invalidateCharacterCoordinates];
+    }
+}
+
+/// The text is either an `NSString` or an `NSAttributedString`.
+unsafe fn input_string(string: id) -> String {
+    let is_attributed: BOOL = msg_send![string, isKindOfClass: class!(NSAttributedString)];
+    if is_attributed == YES {
+        from_nsstring(msg_send![string, string])
+    } else {
+        from_nsstring(string)
+    }
+}
+
+extern "C" fn has_marked_text(this: &Object, _: Sel) -> BOOL {
+    let state = unsafe { WindowState::from_view(this) };
+    let marked_text = state.window_inner.marked_text.borrow();
+
+    if marked_text.is_empty() {
+        NO
+    } else {
+        YES
+    }
+}
+
+extern "C" fn marked_range(this: &Object, _: Sel) -> NSRange {
+    let state = unsafe { WindowState::from_view(this) };
+    let marked_text = state.window_inner.marked_text.borrow();
+
+    if marked_text.is_empty() {
+        NSRange::NOT_FOUND
+    } else {
+        NSRange { location: 0, length: marked_text.encode_utf16().count() as NSUInteger }
+    }
+}
+
+extern "C" fn selected_range(_this: &Object, _: Sel) -> NSRange {
+    NSRange::NOT_FOUND
+}
+
+extern "C" fn set_marked_text(
+    this: &Object, _: Sel, string: id, selected_range: NSRange, _replacement_range: NSRange,
+) {
+    let state = unsafe { WindowState::from_view(this) };
+    if state.window_inner.text_input.get().is_none() {
+        // The accent of a dead key, see `compose_dead_key()`
+        if state.interpreting_key.get() {
+            *state.window_inner.marked_text.borrow_mut() = unsafe { input_string(string) };
+            state.ime_consumed_key.set(true);
+        }
+        return;
+    }
+
+    let text = unsafe { input_string(string) };
+    let cursor =
+        (!text.is_empty() && selected_range.location != NSNotFound as NSUInteger).then(|| {
+            let start = selected_range.location as usize;
+            let end = start + selected_range.length as usize;
+            (utf16_to_byte_offset(&text, start), utf16_to_byte_offset(&text, end))
+        });
+
+    *state.window_inner.marked_text.borrow_mut() = text.clone();
+    state.ime_consumed_key.set(true);
+    state.trigger_event(Event::Ime(ImeEvent::Preedit { text, cursor }));
+}
+
+/// The input method accepts the marked text as it is.
+extern "C" fn unmark_text(this: &Object, _: Sel) {
+    let state = unsafe { WindowState::from_view(this) };
+    let text = state.window_inner.marked_text.take();
+    if state.window_inner.text_input.get().is_none() || text.is_empty() {
+        return;
+    }
+
+    state.ime_consumed_key.set(true);
+    state.trigger_event(Event::Ime(ImeEvent::Commit(text)));
+}
+
+extern "C" fn valid_attributes_for_marked_text(_this: &Object, _: Sel) -> id {
+    unsafe { NSArray::array(nil) }
+}
+
+extern "C" fn attributed_substring_for_proposed_range(
+    _this: &Object, _: Sel, _range: NSRange, _actual_range: *mut c_void,
+) -> id {
+    nil
+}
+
+extern "C" fn insert_text(this: &Object, _: Sel, string: id, _replacement_range: NSRange) {
+    let state = unsafe { WindowState::from_view(this) };
+    if state.window_inner.text_input.get().is_none() {
+        // The character composed from a dead key, see `compose_dead_key()`
+        if state.interpreting_key.get() {
+            state.window_inner.marked_text.take();
+            state.composed_text.borrow_mut().push_str(&unsafe { input_string(string) });
+        }
+        return;
+    }
+
+    // Text inserted by a plain key press is sent as `Event::Text` along with the key press instead
+    let was_composing = !state.window_inner.marked_text.take().is_empty();
+    if state.interpreting_key.get() && !was_composing {
+        return;
+    }
+
+    let text = unsafe { input_string(string) };
+    state.ime_consumed_key.set(true);
+    state.trigger_event(Event::Ime(ImeEvent::Commit(text)));
+}
+
+extern "C" fn character_index_for_point(_this: &Object, _: Sel, _point: NSPoint) -> NSUInteger {
+    NSNotFound as NSUInteger
+}
+
+/// The input method places its candidate window next to this rectangle in screen coordinates.
+extern "C" fn first_rect_for_character_range(
+    this: &Object, _: Sel, _range: NSRange, _actual_range: *mut c_void,
+) -> NSRect {
+    let state = unsafe { WindowState::from_view(this) };
+    let inner = &state.window_inner;
+    let area = match inner.ime_cursor_area.get().or_else(|| inner.text_input.get()) {
+        Some(area) => area,
+        None => return NSRect::new(NSPoint::new(0.0, 0.0), NSSize::new(0.0, 0.0)),
+    };
+
+    unsafe {
+        let rect = NSRect::new(
+            NSPoint::new(area.origin.x, area.origin.y),
+            NSSize::new(area.size.width, area.size.height),
+        );
+        let window: id = msg_send![this, window];
+        if window == nil {
+            return rect;
+        }
+
+        let window_rect: NSRect = msg_send![this, convertRect:rect toView:nil];
+        msg_send![window, convertRectToScreen: window_rect]
+    }
+}
+
+/// Commands like `insertNewline:` are sent as regular key presses instead, and the default
+/// implementation would beep.
+extern "C" fn do_command_by_selector(_this: &Object, _: Sel, _command: Sel) {}
@@ -1,25 +1,31 @@
 use std::ffi::c_void;
+use std::sync::Arc;
 
-use cocoa::appkit::{NSEvent, NSFilenamesPboardType, NSView, NSWindow};
+use cocoa::appkit::{NSEvent, NSEventType, NSFilenamesPboardType, NSView, NSWindow};
 use cocoa::base::{id, nil, BOOL, NO, YES};
-use cocoa::foundation::{NSArray, NSPoint, NSRect, NSSize, NSUInteger};
+use cocoa::foundation::{
+    NSArray, NSAutoreleasePool, NSInteger, NSPoint, NSRect, NSSize, NSString, NSUInteger,
+};
 
+use keyboard_types::Modifiers;
 use objc::{
     class,
     declare::ClassDecl,
     msg_send,
-    runtime::{Class, Object, Sel},
+    runtime::{Class, Object, Protocol, Sel},
     sel, sel_impl,
 };
 use uuid::Uuid;
 
 use crate::MouseEvent::{ButtonPressed, ButtonReleased};
 use crate::{
-    DropData, DropEffect, Event, EventStatus, MouseButton, MouseEvent, Point, ScrollDelta, Size,
-    WindowEvent, WindowInfo, WindowOpenOptions,
+    DropData, DropEffect, Event, EventStatus, InputEvents, LayerContentsScale, MacLayerConfig,
+    MonitorInfo, MouseButton, MouseEvent, Point, PointerDevice, PointerType, ResizeSource,
+    ScrollDelta, Size, WindowEvent, WindowInfo, WindowOpenOptions, WindowScalePolicy,
 };
 
 use super::keyboard::{from_nsstring, make_modifiers};
+use super::text_input::add_text_input_client_methods;
 use super::window::WindowState;
 use super::{
     NSDragOperationCopy, NSDragOperationGeneric, NSDragOperationLink, NSDragOperationMove,
@@ -33,6 +39,15 @@ pub(super) const BASEVIEW_STATE_IVAR: &str = "baseview_state";
 extern "C" {
     static NSWindowDidBecomeKeyNotification: id;
     static NSWindowDidResignKeyNotification: id;
+    static NSApplicationDidChangeScreenParametersNotification: id;
+    static NSWindowDidChangeScreenNotification: id;
+    static NSWindowDidMiniaturizeNotification: id;
+    static NSWindowDidDeminiaturizeNotification: id;
+    static NSWorkspaceWillSleepNotification: id;
+    static NSWorkspaceDidWakeNotification: id;
+    static NSWorkspaceAccessibilityDisplayOptionsDidChangeNotification: id;
+    static NSPreferredScrollerStyleDidChangeNotification: id;
+    static NSTextInputContextKeyboardSelectionDidChangeNotification: id;
 }
 
 macro_rules! add_simple_mouse_class_method {
@@ -41,7 +56,7 @@ macro_rules! add_simple_mouse_class_method {
         extern "C" fn $sel(this: &Object, _: Sel, _: id){
             let state = unsafe { WindowState::from_view(this) };
 
-            state.trigger_deferrable_event(Event::Mouse($event));
+            state.hover.filter($event, |event| state.trigger_deferrable_event(Event::Mouse(event)));
         }
 
         $class.add_method(
@@ -61,10 +76,25 @@ macro_rules! add_mouse_button_class_method {
 
             let modifiers = unsafe { NSEvent::modifierFlags(event) };
 
-            state.trigger_deferrable_event(Event::Mouse($event_ty {
+            let mouse_event = $event_ty {
                 button: $button,
                 modifiers: make_modifiers(modifiers),
-            }));
+                device: unsafe { pointer_device(event) },
+            };
+            let is_release = matches!(mouse_event, ButtonReleased { .. });
+            // Hosts don't always make an embedded view the first responder when it's clicked, in
+            // which case key presses would keep going to the host
+            if !is_release {
+                unsafe { make_first_responder(this) };
+            }
+            state.trigger_deferrable_event(Event::Mouse(mouse_event));
+
+            // An infinite drag ends once all buttons have been released
+            let pressed_buttons: NSUInteger =
+                unsafe { msg_send![class!(NSEvent), pressedMouseButtons] };
+            if is_release && pressed_buttons == 0 {
+                state.window_inner.end_infinite_drag();
+            }
         }
 
         $class.add_method(
@@ -81,9 +111,21 @@ macro_rules! add_simple_keyboard_class_method {
             let state = unsafe { WindowState::from_view(this) };
 
             if let Some(key_event) = state.process_native_key_event(event){
-                let status = state.trigger_event(Event::Keyboard(key_event));
-
-                if let EventStatus::Ignored = status {
+                let key_event = unsafe {
+                    super::text_input::interpret_key_event(this, &state, event, key_event)
+                };
+                // Cmd shortcuts are matched in `performKeyEquivalent:` instead
+                let baseview_event = if key_event.modifiers.contains(Modifiers::META) {
+                    Event::Keyboard(key_event)
+                } else {
+                    state.window_inner.accelerators.translate(key_event)
+                };
+                let key_code = unsafe { event.keyCode() };
+                let status = state.trigger_key_event(baseview_event, key_code);
+
+                // Typing in a text field shouldn't trigger the host's shortcuts
+                let is_typing = state.window_inner.text_input.get().is_some();
+                if status == EventStatus::Ignored && !is_typing {
                     unsafe {
                         let superclass = msg_send![this, superclass];
 
@@ -112,27 +154,61 @@ unsafe fn register_notification(observer: id, notification_name: id, object: id)
     ];
 }
 
+/// `NSWorkspace` notifications are only posted to the workspace's own notification center, not to
+/// the default one.
+pub(super) unsafe fn workspace_notification_center() -> id {
+    let workspace: id = msg_send![class!(NSWorkspace), sharedWorkspace];
+    msg_send![workspace, notificationCenter]
+}
+
+unsafe fn register_workspace_notification(observer: id, notification_name: id) {
+    let _: () = msg_send![
+        workspace_notification_center(),
+        addObserver:observer
+        selector:sel!(handleNotification:)
+        name:notification_name
+        object:nil
+    ];
+}
+
 pub(super) unsafe fn create_view(window_options: &WindowOpenOptions) -> id {
-    let class = create_view_class();
+    let class = create_view_class(window_options.input_events);
 
     let view: id = msg_send![class, alloc];
 
     let size = window_options.size;
 
     view.initWithFrame_(NSRect::new(NSPoint::new(0., 0.), NSSize::new(size.width, size.height)));
+    if window_options.mac_layer.wants_layer {
+        let () = msg_send![view, setWantsLayer: YES];
+    }
 
     register_notification(view, NSWindowDidBecomeKeyNotification, nil);
     register_notification(view, NSWindowDidResignKeyNotification, nil);
-
-    let _: id = msg_send![
+    register_notification(view, NSApplicationDidChangeScreenParametersNotification, nil);
+    register_notification(view, NSWindowDidChangeScreenNotification, nil);
+    register_notification(view, NSWindowDidMiniaturizeNotification, nil);
+    register_notification(view, NSWindowDidDeminiaturizeNotification, nil);
+    register_workspace_notification(view, NSWorkspaceWillSleepNotification);
+    register_workspace_notification(view, NSWorkspaceDidWakeNotification);
+    register_workspace_notification(
         view,
-        registerForDraggedTypes: NSArray::arrayWithObjects(nil, &[NSFilenamesPboardType])
-    ];
+        NSWorkspaceAccessibilityDisplayOptionsDidChangeNotification,
+    );
+    register_notification(view, NSPreferredScrollerStyleDidChangeNotification, nil);
+    register_notification(view, NSTextInputContextKeyboardSelectionDidChangeNotification, nil);
+
+    let mut dragged_types = vec![NSFilenamesPboardType];
+    for format in &window_options.drop_formats {
+        dragged_types.push(NSString::alloc(nil).init_str(format).autorelease());
+    }
+    let _: id =
+        msg_send![view, registerForDraggedTypes: NSArray::arrayWithObjects(nil, &dragged_types)];
 
     view
 }
 
-unsafe fn create_view_class() -> &'static Class {
+unsafe fn create_view_class(input_events: InputEvents) -> &'static Class {
     // Use unique class names so that there are no conflicts between different
     // instances. The class is deleted when the view is released. Previously,
     // the class was stored in a OnceCell after creation. This way, we didn't
@@ -141,9 +217,15 @@ unsafe fn create_view_class() -> &'static Class {
     let class_name = format!("BaseviewNSView_{}", Uuid::new_v4().to_simple());
     let mut class = ClassDecl::new(&class_name, class!(NSView)).unwrap();
 
+    // Without keyboard input the view never becomes the first responder, so key events go to the
+    // host's views instead
     class.add_method(
         sel!(acceptsFirstResponder),
-        property_yes as extern "C" fn(&Object, Sel) -> BOOL,
+        if input_events.keyboard {
+            property_yes as extern "C" fn(&Object, Sel) -> BOOL
+        } else {
+            property_no as extern "C" fn(&Object, Sel) -> BOOL
+        },
     );
     class.add_method(
         sel!(becomeFirstResponder),
@@ -172,18 +254,16 @@ unsafe fn create_view_class() -> &'static Class {
         sel!(viewWillMoveToWindow:),
         view_will_move_to_window as extern "C" fn(&Object, Sel, id),
     );
+    class.add_method(
+        sel!(viewDidMoveToWindow),
+        view_did_move_to_window as extern "C" fn(&Object, Sel),
+    );
     class.add_method(
         sel!(updateTrackingAreas:),
         update_tracking_areas as extern "C" fn(&Object, Sel, id),
     );
 
-    class.add_method(sel!(mouseMoved:), mouse_moved as extern "C" fn(&Object, Sel, id));
-    class.add_method(sel!(mouseDragged:), mouse_moved as extern "C" fn(&Object, Sel, id));
-    class.add_method(sel!(rightMouseDragged:), mouse_moved as extern "C" fn(&Object, Sel, id));
-    class.add_method(sel!(otherMouseDragged:), mouse_moved as extern "C" fn(&Object, Sel, id));
-
-    class.add_method(sel!(scrollWheel:), scroll_wheel as extern "C" fn(&Object, Sel, id));
-
+    class.add_method(sel!(setFrameSize:), set_frame_size as extern "C" fn(&Object, Sel, NSSize));
     class.add_method(
         sel!(viewDidChangeBackingProperties:),
         view_did_change_backing_properties as extern "C" fn(&Object, Sel, id),
@@ -206,23 +286,63 @@ unsafe fn create_view_class() -> &'static Class {
         dragging_updated as extern "C" fn(&Object, Sel, id) -> NSUInteger,
     );
     class.add_method(sel!(draggingExited:), dragging_exited as extern "C" fn(&Object, Sel, id));
+
+    // The view is the source of the dragging sessions started by `Window::start_drag()`
+    if let Some(protocol) = Protocol::get("NSDraggingSource") {
+        class.add_protocol(protocol);
+    }
+    class.add_method(
+        sel!(draggingSession:sourceOperationMaskForDraggingContext:),
+        source_operation_mask as extern "C" fn(&Object, Sel, id, NSInteger) -> NSUInteger,
+    );
+    class.add_method(
+        sel!(draggingSession:endedAtPoint:operation:),
+        dragging_session_ended as extern "C" fn(&Object, Sel, id, NSPoint, NSUInteger),
+    );
     class.add_method(
         sel!(handleNotification:),
         handle_notification as extern "C" fn(&Object, Sel, id),
     );
 
-    add_mouse_button_class_method!(class, mouseDown, ButtonPressed, MouseButton::Left);
-    add_mouse_button_class_method!(class, mouseUp, ButtonReleased, MouseButton::Left);
-    add_mouse_button_class_method!(class, rightMouseDown, ButtonPressed, MouseButton::Right);
-    add_mouse_button_class_method!(class, rightMouseUp, ButtonReleased, MouseButton::Right);
-    add_mouse_button_class_method!(class, otherMouseDown, ButtonPressed, MouseButton::Middle);
-    add_mouse_button_class_method!(class, otherMouseUp, ButtonReleased, MouseButton::Middle);
-    add_simple_mouse_class_method!(class, mouseEntered, MouseEvent::CursorEntered);
-    add_simple_mouse_class_method!(class, mouseExited, MouseEvent::CursorLeft);
+    // NSView passes the events for any of these methods we don't override on to the next
+    // responder, which lets the host handle the input the window didn't subscribe to
+    if input_events.mouse_motion {
+        class.add_method(sel!(mouseMoved:), mouse_moved as extern "C" fn(&Object, Sel, id));
+        class.add_method(sel!(mouseDragged:), mouse_moved as extern "C" fn(&Object, Sel, id));
+        class.add_method(sel!(rightMouseDragged:), mouse_moved as extern "C" fn(&Object, Sel, id));
+        class.add_method(sel!(otherMouseDragged:), mouse_moved as extern "C" fn(&Object, Sel, id));
+        add_simple_mouse_class_method!(class, mouseEntered, MouseEvent::CursorEntered);
+        add_simple_mouse_class_method!(class, mouseExited, MouseEvent::CursorLeft);
+    }
+
+    if input_events.mouse_buttons {
+        class.add_method(sel!(scrollWheel:), scroll_wheel as extern "C" fn(&Object, Sel, id));
+        class.add_method(
+            sel!(magnifyWithEvent:),
+            magnify_with_event as extern "C" fn(&Object, Sel, id),
+        );
+        class.add_method(
+            sel!(pressureChangeWithEvent:),
+            pressure_change_with_event as extern "C" fn(&Object, Sel, id),
+        );
+        add_mouse_button_class_method!(class, mouseDown, ButtonPressed, MouseButton::Left);
+        add_mouse_button_class_method!(class, mouseUp, ButtonReleased, MouseButton::Left);
+        add_mouse_button_class_method!(class, rightMouseDown, ButtonPressed, MouseButton::Right);
+        add_mouse_button_class_method!(class, rightMouseUp, ButtonReleased, MouseButton::Right);
+        add_mouse_button_class_method!(class, otherMouseDown, ButtonPressed, MouseButton::Middle);
+        add_mouse_button_class_method!(class, otherMouseUp, ButtonReleased, MouseButton::Middle);
+    }
 
-    add_simple_keyboard_class_method!(class, keyDown);
-    add_simple_keyboard_class_method!(class, keyUp);
-    add_simple_keyboard_class_method!(class, flagsChanged);
+    if input_events.keyboard {
+        add_simple_keyboard_class_method!(class, keyDown);
+        add_simple_keyboard_class_method!(class, keyUp);
+        add_simple_keyboard_class_method!(class, flagsChanged);
+        class.add_method(
+            sel!(performKeyEquivalent:),
+            perform_key_equivalent as extern "C" fn(&Object, Sel, id) -> BOOL,
+        );
+        add_text_input_client_methods(&mut class);
+    }
 
     class.add_ivar::<*mut c_void>(BASEVIEW_STATE_IVAR);
 
@@ -241,6 +361,21 @@ extern "C" fn accepts_first_mouse(_this: &Object, _sel: Sel, _event: id) -> BOOL
     YES
 }
 
+/// Make the view its window's first responder, unless it already is or it doesn't take keyboard
+/// input.
+pub(super) unsafe fn make_first_responder(view: &Object) {
+    let accepts_first_responder: BOOL = msg_send![view, acceptsFirstResponder];
+    let window: id = msg_send![view, window];
+    if accepts_first_responder == NO || window == nil {
+        return;
+    }
+
+    let first_responder: id = msg_send![window, firstResponder];
+    if !std::ptr::eq(first_responder, view) {
+        let _: BOOL = msg_send![window, makeFirstResponder: view];
+    }
+}
+
 extern "C" fn become_first_responder(this: &Object, _sel: Sel) -> BOOL {
     let state = unsafe { WindowState::from_view(this) };
     let is_key_window = unsafe {
@@ -252,24 +387,56 @@ extern "C" fn become_first_responder(this: &Object, _sel: Sel) -> BOOL {
             false
         }
     };
-    if is_key_window {
-        state.trigger_deferrable_event(Event::Window(WindowEvent::Focused));
-    }
+    // The window becoming the key window is reported through `handleNotification:`
+    state.set_focused(is_key_window);
     YES
 }
 
 extern "C" fn resign_first_responder(this: &Object, _sel: Sel) -> BOOL {
     let state = unsafe { WindowState::from_view(this) };
-    state.trigger_deferrable_event(Event::Window(WindowEvent::Unfocused));
+    // Hold on to the keyboard while a modal interaction has grabbed it
+    if state.window_inner.keyboard_grabbed.get() {
+        return NO;
+    }
+
+    state.set_focused(false);
     YES
 }
 
-extern "C" fn window_should_close(this: &Object, _: Sel, _sender: id) -> BOOL {
+/// Key presses with Cmd held are offered to every view in the window as key equivalents before
+/// they're sent to the first responder, and the host's menus would usually claim them first.
+/// Accelerators that use Cmd are matched here, but only while this view is the first responder.
+extern "C" fn perform_key_equivalent(this: &Object, _sel: Sel, event: id) -> BOOL {
     let state = unsafe { WindowState::from_view(this) };
 
-    state.trigger_event(Event::Window(WindowEvent::WillClose));
+    let is_first_responder = unsafe {
+        let window: id = msg_send![this, window];
+        let first_responder: id = msg_send![window, firstResponder];
+        window != nil && std::ptr::eq(first_responder, this)
+    };
+    let is_key_down = unsafe { event.eventType() } == NSEventType::NSKeyDown;
+    if is_first_responder && is_key_down {
+        let accelerator = state
+            .process_native_key_event(event)
+            .and_then(|key_event| state.window_inner.accelerators.find(&key_event));
+        if let Some(id) = accelerator {
+            let key_code = unsafe { event.keyCode() };
+            if state.trigger_key_event(Event::Accelerator(id), key_code) == EventStatus::Captured {
+                return YES;
+            }
+        }
+    }
+
+    unsafe {
+        let superclass = msg_send![this, superclass];
+        msg_send![super(this, superclass), performKeyEquivalent: event]
+    }
+}
+
+extern "C" fn window_should_close(this: &Object, _: Sel, _sender: id) -> BOOL {
+    let state = unsafe { WindowState::from_view(this) };
 
-    state.window_inner.close();
+    state.close();
 
     NO
 }
@@ -290,14 +457,28 @@ extern "C" fn view_did_change_backing_properties(this: &Object, _: Sel, _: id) {
     unsafe {
         let ns_window: *mut Object = msg_send![this, window];
 
-        let scale_factor: f64 =
-            if ns_window.is_null() { 1.0 } else { NSWindow::backingScaleFactor(ns_window) };
-
         let state = WindowState::from_view(this);
 
+        let unscaled = state.window_inner.scale_policy == WindowScalePolicy::ForceUnscaled;
+        let scale_factor: f64 = if ns_window.is_null() || unscaled {
+            1.0
+        } else {
+            NSWindow::backingScaleFactor(ns_window)
+        };
+
+        update_layer(this, &state.window_inner.mac_layer, scale_factor);
+
         let bounds: NSRect = msg_send![this, bounds];
 
-        let new_window_info = WindowInfo::from_logical_size(
+        // Zero sized views are suspended instead, and resized once they're visible again
+        let has_pixels = bounds.size.width > 0.0 && bounds.size.height > 0.0;
+        if !state.set_suspended(this, !has_pixels) {
+            return;
+        }
+
+        // The logical size is derived from the physical size like on the other platforms, so it
+        // round trips at fractional scale factors
+        let new_window_info = WindowInfo::from_logical_size_snapped(
             Size::new(bounds.size.width, bounds.size.height),
             scale_factor,
         );
@@ -308,8 +489,142 @@ extern "C" fn view_did_change_backing_properties(this: &Object, _: Sel, _: id) {
         // other platform implementations
         if new_window_info.physical_size() != window_info.physical_size() {
             state.window_info.set(new_window_info);
-            state.trigger_deferrable_event(Event::Window(WindowEvent::Resized(new_window_info)));
+            let source = if scale_factor != window_info.scale() {
+                ResizeSource::ScaleFactor
+            } else {
+                state.window_inner.resize_source.get()
+            };
+
+            #[cfg(feature = "opengl")]
+            if let Some(gl_context) = state.window_inner.gl_context.get() {
+                // The OpenGL view's size is set in backing pixels, which aren't the window's
+                // physical pixels when using `WindowScalePolicy::ForceUnscaled`
+                let backing_size: NSSize = msg_send![this, convertSizeToBacking: bounds.size];
+                gl_context.resized(crate::PhySize::new(
+                    backing_size.width.round() as u32,
+                    backing_size.height.round() as u32,
+                ));
+            }
+
+            let event = WindowEvent::Resized(new_window_info, source);
+            state.trigger_deferrable_event(Event::Window(event));
+
+            // The contents under the cursor have most likely moved along with the new size
+            state.window_inner.cursor_refresh_requested.set(true);
+        }
+    }
+}
+
+/// Apply the layer's contents scale and autoresizing mask from [`MacLayerConfig`]. The view may
+/// only get its layer after it has been opened, for instance when a renderer attaches one, so this
+/// is done whenever the view's size or backing scale factor changes.
+unsafe fn update_layer(this: &Object, config: &MacLayerConfig, backing_scale_factor: f64) {
+    let layer: id = msg_send![this, layer];
+    if layer == nil {
+        return;
+    }
+
+    let contents_scale = match config.contents_scale {
+        LayerContentsScale::Unchanged => None,
+        LayerContentsScale::BackingScaleFactor => Some(backing_scale_factor),
+        LayerContentsScale::Fixed(scale) => Some(scale),
+    };
+    if let Some(contents_scale) = contents_scale {
+        let () = msg_send![layer, setContentsScale: contents_scale];
+    }
+
+    if let Some(mask) = config.autoresizing_mask {
+        let () = msg_send![layer, setAutoresizingMask: mask];
+    }
+}
+
+/// Sends a resize event whenever the view's size changes, including for every step of a live
+/// resize of a standalone window.
+extern "C" fn set_frame_size(this: &Object, _: Sel, new_size: NSSize) {
+    unsafe {
+        let superclass = msg_send![this, superclass];
+        let () = msg_send![super(this, superclass), setFrameSize: new_size];
+
+        // The view's size may be set before the window state has been attached to it
+        let state_ptr: *const c_void = *this.get_ivar(BASEVIEW_STATE_IVAR);
+        if state_ptr.is_null() {
+            return;
+        }
+
+        view_did_change_backing_properties(this, sel!(viewDidChangeBackingProperties:), nil);
+    }
+}
+
+/// Called when displays are connected or disconnected, or their configuration changes.
+unsafe fn handle_screen_parameters_changed(this: &Object, state: &WindowState) {
+    // AppKit normally tells us about scale factor changes separately, but this makes sure the
+    // handler always receives the new size before the monitor change event
+    view_did_change_backing_properties(this, sel!(viewDidChangeBackingProperties:), nil);
+
+    let ns_window: id = msg_send![this, window];
+    let screen: id = if ns_window.is_null() { nil } else { msg_send![ns_window, screen] };
+    let refresh_rate = screen_refresh_rate(screen);
+
+    check_surface_changed(this, state);
+
+    let window_inner = &state.window_inner;
+    if window_inner.frame_counter.set_refresh_rate(refresh_rate)
+        && !window_inner.external_frame_clock
+    {
+        WindowState::setup_timer(state);
+    }
+    let monitor_info = MonitorInfo { scale: state.window_info.get().scale(), refresh_rate };
+    state.trigger_deferrable_event(Event::Window(WindowEvent::MonitorChanged(monitor_info)));
+}
+
+/// Returns the refresh rate of an `NSScreen`, which may be `nil`.
+pub(super) unsafe fn screen_refresh_rate(screen: id) -> Option<f64> {
+    // `maximumFramesPerSecond` is only available on macOS 10.15 and up
+    let has_frame_rate: BOOL = if screen == nil {
+        NO
+    } else {
+        msg_send![screen, respondsToSelector: sel!(maximumFramesPerSecond)]
+    };
+    if has_frame_rate == YES {
+        let frames_per_second: NSInteger = msg_send![screen, maximumFramesPerSecond];
+        Some(frames_per_second as f64)
+    } else {
+        None
+    }
+}
+
+/// Called when the view may have moved to another window or the window may have moved to another
+/// screen. The handler needs to recreate its surfaces when either the window or the screen's color
+/// depth changed.
+unsafe fn check_surface_changed(this: &Object, state: &WindowState) {
+    let ns_window: id = msg_send![this, window];
+    if ns_window == nil {
+        return;
+    }
+
+    let screen: id = msg_send![ns_window, screen];
+    let depth = if screen == nil {
+        None
+    } else {
+        // This is an `NSWindowDepth`, which is a 32-bit integer
+        let depth: i32 = msg_send![screen, depth];
+        Some(depth)
+    };
+
+    let (previous_window, previous_depth) = state.surface.get();
+    // The depth is unknown while the window is off screen, which doesn't change the surface
+    let depth = depth.or(previous_depth);
+    state.surface.set((ns_window, depth));
+
+    let window_changed = previous_window != nil && previous_window != ns_window;
+    let depth_changed = previous_depth.is_some() && previous_depth != depth;
+    if window_changed || depth_changed {
+        #[cfg(feature = "opengl")]
+        if let Some(gl_context) = state.window_inner.gl_context.get() {
+            gl_context.surface_changed();
         }
+
+        state.trigger_deferrable_event(Event::Window(WindowEvent::SurfaceChanged));
     }
 }
 
@@ -381,6 +696,19 @@ extern "C" fn view_will_move_to_window(this: &Object, _self: Sel, new_window: id
     }
 }
 
+extern "C" fn view_did_move_to_window(this: &Object, _self: Sel) {
+    unsafe {
+        let superclass = msg_send![this, superclass];
+        let () = msg_send![super(this, superclass), viewDidMoveToWindow];
+
+        let ns_window: id = msg_send![this, window];
+        if ns_window != nil {
+            let state = WindowState::from_view(this);
+            check_surface_changed(this, &state);
+        }
+    }
+}
+
 extern "C" fn update_tracking_areas(this: &Object, _self: Sel, _: id) {
     unsafe {
         let tracking_areas: *mut Object = msg_send![this, trackingAreas];
@@ -400,12 +728,26 @@ extern "C" fn mouse_moved(this: &Object, _sel: Sel, event: id) {
     };
     let modifiers = unsafe { NSEvent::modifierFlags(event) };
 
+    let modifiers = make_modifiers(modifiers);
+    let device = unsafe { pointer_device(event) };
+
+    // The actual cursor doesn't move during an infinite drag, so the virtual cursor follows the
+    // mouse deltas instead. This bypasses the hover state since the cursor can't leave the view.
+    if let Some(position) = state.window_inner.infinite_drag.get() {
+        let position = unsafe {
+            Point::new(position.x + NSEvent::deltaX(event), position.y + NSEvent::deltaY(event))
+        };
+        state.window_inner.infinite_drag.set(Some(position));
+
+        let move_event = MouseEvent::CursorMoved { position, modifiers, device };
+        state.trigger_deferrable_event(Event::Mouse(move_event));
+        return;
+    }
+
     let position = Point { x: point.x, y: point.y };
 
-    state.trigger_deferrable_event(Event::Mouse(MouseEvent::CursorMoved {
-        position,
-        modifiers: make_modifiers(modifiers),
-    }));
+    let move_event = MouseEvent::CursorMoved { position, modifiers, device };
+    state.hover.filter(move_event, |event| state.trigger_deferrable_event(Event::Mouse(event)));
 }
 
 extern "C" fn scroll_wheel(this: &Object, _: Sel, event: id) {
@@ -424,24 +766,121 @@ extern "C" fn scroll_wheel(this: &Object, _: Sel, event: id) {
 
     let modifiers = unsafe { NSEvent::modifierFlags(event) };
 
-    state.trigger_deferrable_event(Event::Mouse(MouseEvent::WheelScrolled {
+    // Scroll events don't have a meaningful subtype, but only trackpads (and the Magic Mouse)
+    // produce precise scrolling deltas
+    let pointer_type = if let ScrollDelta::Pixels { .. } = delta {
+        PointerType::Touchpad
+    } else {
+        PointerType::Mouse
+    };
+
+    let event = MouseEvent::WheelScrolled {
         delta,
         modifiers: make_modifiers(modifiers),
+        device: PointerDevice { id: 0, pointer_type },
+    };
+    let event = if state.window_inner.wheel_zoom { event.into_wheel_zoom() } else { event };
+
+    state.trigger_deferrable_event(Event::Mouse(event));
+}
+
+extern "C" fn magnify_with_event(this: &Object, _: Sel, event: id) {
+    let state = unsafe { WindowState::from_view(this) };
+
+    // Without zoom events the pinch gesture is left to the next responder
+    if !state.window_inner.wheel_zoom {
+        unsafe {
+            let superclass = msg_send![this, superclass];
+            let () = msg_send![super(this, superclass), magnifyWithEvent: event];
+        }
+        return;
+    }
+
+    let (magnification, modifiers) = unsafe {
+        let magnification: f64 = msg_send![event, magnification];
+        (magnification, NSEvent::modifierFlags(event))
+    };
+
+    state.trigger_deferrable_event(Event::Mouse(MouseEvent::Zoomed {
+        scale: (1.0 + magnification) as f32,
+        modifiers: make_modifiers(modifiers),
+        device: PointerDevice { id: 0, pointer_type: PointerType::Touchpad },
     }));
 }
 
-fn get_drag_position(sender: id) -> Point {
-    let point: NSPoint = unsafe { msg_send![sender, draggingLocation] };
+extern "C" fn pressure_change_with_event(this: &Object, _: Sel, event: id) {
+    let state = unsafe { WindowState::from_view(this) };
+
+    let (pressure, stage, modifiers) = unsafe {
+        let pressure: f32 = msg_send![event, pressure];
+        let stage: NSInteger = msg_send![event, stage];
+
+        (pressure, stage, NSEvent::modifierFlags(event))
+    };
+
+    // Pressure events are not mouse events, so asking for their subtype would raise an exception.
+    // Only Force Touch trackpads generate them.
+    state.trigger_deferrable_event(Event::Mouse(MouseEvent::PressureChanged {
+        pressure,
+        stage: stage.max(0) as u8,
+        modifiers: make_modifiers(modifiers),
+        device: PointerDevice { id: 0, pointer_type: PointerType::Touchpad },
+    }));
+}
+
+/// Determine which kind of device generated a mouse event from the event's subtype. AppKit only
+/// exposes a device identifier for tablet events.
+unsafe fn pointer_device(event: id) -> PointerDevice {
+    // `NSEventSubtypeTabletPoint`, `NSEventSubtypeTabletProximity` and `NSEventSubtypeTouch`
+    const TABLET_POINT: i16 = 1;
+    const TABLET_PROXIMITY: i16 = 2;
+    const TOUCH: i16 = 3;
+
+    let subtype: i16 = msg_send![event, subtype];
+    match subtype {
+        TABLET_POINT | TABLET_PROXIMITY => {
+            let device_id: NSUInteger = msg_send![event, deviceID];
+            PointerDevice { id: device_id as u64, pointer_type: PointerType::Tablet }
+        }
+        TOUCH => PointerDevice { id: 0, pointer_type: PointerType::Touchpad },
+        _ => PointerDevice { id: 0, pointer_type: PointerType::Mouse },
+    }
+}
+
+/// The drag position in the view's flipped coordinate system, like the positions of the other
+/// mouse events.
+fn get_drag_position(this: &Object, sender: id) -> Point {
+    let point: NSPoint = unsafe {
+        let point: NSPoint = msg_send![sender, draggingLocation];
+        msg_send![this, convertPoint:point fromView:nil]
+    };
     Point::new(point.x, point.y)
 }
 
-fn get_drop_data(sender: id) -> DropData {
+fn get_drop_data(state: &WindowState, sender: id) -> DropData {
     if sender == nil {
         return DropData::None;
     }
 
     unsafe {
         let pasteboard: id = msg_send![sender, draggingPasteboard];
+
+        for format in &state.window_inner.drop_formats {
+            let pasteboard_type = NSString::alloc(nil).init_str(format).autorelease();
+            let data: id = msg_send![pasteboard, dataForType: pasteboard_type];
+            if data != nil {
+                let bytes: *const u8 = msg_send![data, bytes];
+                let length: NSUInteger = msg_send![data, length];
+                let data = if length == 0 {
+                    Vec::new()
+                } else {
+                    std::slice::from_raw_parts(bytes, length as usize).to_vec()
+                };
+
+                return DropData::Custom { format: format.clone(), data };
+            }
+        }
+
         let file_list: id = msg_send![pasteboard, propertyListForType: NSFilenamesPboardType];
 
         if file_list == nil {
@@ -472,10 +911,13 @@ fn on_event(window_state: &WindowState, event: MouseEvent) -> NSUInteger {
 extern "C" fn dragging_entered(this: &Object, _sel: Sel, sender: id) -> NSUInteger {
     let state = unsafe { WindowState::from_view(this) };
     let modifiers = state.keyboard_state().last_mods();
-    let drop_data = get_drop_data(sender);
+    let drop_data = Arc::new(get_drop_data(&state, sender));
+    *state.drop_data.borrow_mut() = Some(Arc::clone(&drop_data));
+
+    state.hover.drag_entered(|event| state.trigger_deferrable_event(Event::Mouse(event)));
 
     let event = MouseEvent::DragEntered {
-        position: get_drag_position(sender),
+        position: get_drag_position(this, sender),
         modifiers: make_modifiers(modifiers),
         data: drop_data,
     };
@@ -486,10 +928,11 @@ extern "C" fn dragging_entered(this: &Object, _sel: Sel, sender: id) -> NSUInteg
 extern "C" fn dragging_updated(this: &Object, _sel: Sel, sender: id) -> NSUInteger {
     let state = unsafe { WindowState::from_view(this) };
     let modifiers = state.keyboard_state().last_mods();
-    let drop_data = get_drop_data(sender);
+    // The pasteboard doesn't change during a drag, so the data from `draggingEntered:` is reused
+    let drop_data = state.drop_data.borrow().clone().unwrap_or_else(DropData::none);
 
     let event = MouseEvent::DragMoved {
-        position: get_drag_position(sender),
+        position: get_drag_position(this, sender),
         modifiers: make_modifiers(modifiers),
         data: drop_data,
     };
@@ -507,15 +950,17 @@ extern "C" fn prepare_for_drag_operation(_this: &Object, _sel: Sel, _sender: id)
 extern "C" fn perform_drag_operation(this: &Object, _sel: Sel, sender: id) -> BOOL {
     let state = unsafe { WindowState::from_view(this) };
     let modifiers = state.keyboard_state().last_mods();
-    let drop_data = get_drop_data(sender);
+    let drop_data = Arc::new(get_drop_data(&state, sender));
+    state.drop_data.take();
 
     let event = MouseEvent::DragDropped {
-        position: get_drag_position(sender),
+        position: get_drag_position(this, sender),
         modifiers: make_modifiers(modifiers),
         data: drop_data,
     };
 
     let event_status = state.trigger_event(Event::Mouse(event));
+    state.hover.drag_ended();
     match event_status {
         EventStatus::AcceptDrop(_) => YES,
         _ => NO,
@@ -525,13 +970,113 @@ extern "C" fn perform_drag_operation(this: &Object, _sel: Sel, sender: id) -> BO
 extern "C" fn dragging_exited(this: &Object, _sel: Sel, _sender: id) {
     let state = unsafe { WindowState::from_view(this) };
 
+    state.drop_data.take();
     on_event(&state, MouseEvent::DragLeft);
+    state.hover.drag_ended();
+}
+
+extern "C" fn source_operation_mask(
+    _this: &Object, _sel: Sel, _session: id, _context: NSInteger,
+) -> NSUInteger {
+    // The data is only ever offered as a copy
+    NSDragOperationCopy
+}
+
+extern "C" fn dragging_session_ended(
+    this: &Object, _sel: Sel, _session: id, _point: NSPoint, operation: NSUInteger,
+) {
+    let state = unsafe { WindowState::from_view(this) };
+
+    let effect = if operation == NSDragOperationNone {
+        None
+    } else if operation == NSDragOperationMove {
+        Some(DropEffect::Move)
+    } else if operation == NSDragOperationLink {
+        Some(DropEffect::Link)
+    } else if operation == NSDragOperationGeneric {
+        Some(DropEffect::Scroll)
+    } else {
+        Some(DropEffect::Copy)
+    };
+    state.trigger_deferrable_event(Event::DragFinished(effect));
 }
 
 extern "C" fn handle_notification(this: &Object, _cmd: Sel, notification: id) {
     unsafe {
         let state = WindowState::from_view(this);
 
+        let name: id = msg_send![notification, name];
+        let is_screen_change: BOOL =
+            msg_send![name, isEqualToString: NSApplicationDidChangeScreenParametersNotification];
+        if is_screen_change == YES {
+            handle_screen_parameters_changed(this, &state);
+            return;
+        }
+
+        // The notification's object is the window that moved to another screen
+        let is_window_screen_change: BOOL =
+            msg_send![name, isEqualToString: NSWindowDidChangeScreenNotification];
+        if is_window_screen_change == YES {
+            let notification_object: id = msg_send![notification, object];
+            let window: id = msg_send![this, window];
+            if notification_object == window {
+                check_surface_changed(this, &state);
+            }
+            return;
+        }
+
+        let is_miniaturize: BOOL =
+            msg_send![name, isEqualToString: NSWindowDidMiniaturizeNotification];
+        let is_deminiaturize: BOOL =
+            msg_send![name, isEqualToString: NSWindowDidDeminiaturizeNotification];
+        if is_miniaturize == YES || is_deminiaturize == YES {
+            let notification_object: id = msg_send![notification, object];
+            let window: id = msg_send![this, window];
+            if notification_object == window {
+                view_did_change_backing_properties(
+                    this,
+                    sel!(viewDidChangeBackingProperties:),
+                    nil,
+                );
+            }
+            return;
+        }
+
+        let is_will_sleep: BOOL =
+            msg_send![name, isEqualToString: NSWorkspaceWillSleepNotification];
+        if is_will_sleep == YES {
+            state.trigger_deferrable_event(Event::PowerSuspend);
+            return;
+        }
+
+        let is_did_wake: BOOL = msg_send![name, isEqualToString: NSWorkspaceDidWakeNotification];
+        if is_did_wake == YES {
+            state.trigger_deferrable_event(Event::PowerResume);
+            return;
+        }
+
+        let is_accessibility_change: BOOL = msg_send![
+            name,
+            isEqualToString: NSWorkspaceAccessibilityDisplayOptionsDidChangeNotification
+        ];
+        let is_scroller_style_change: BOOL =
+            msg_send![name, isEqualToString: NSPreferredScrollerStyleDidChangeNotification];
+        if is_accessibility_change == YES || is_scroller_style_change == YES {
+            state.trigger_deferrable_event(Event::SystemSettingsChanged);
+            return;
+        }
+
+        // Posted by the text input context of whichever view has keyboard focus, which may be
+        // another window's view
+        let is_keyboard_selection_change: BOOL = msg_send![
+            name,
+            isEqualToString: NSTextInputContextKeyboardSelectionDidChangeNotification
+        ];
+        if is_keyboard_selection_change == YES {
+            state.trigger_deferrable_event(Event::Window(WindowEvent::KeyboardLayoutChanged));
+            return;
+        }
+
         // The subject of the notication, in this case an NSWindow object.
         let notification_object: id = msg_send![notification, object];
 
@@ -546,11 +1091,7 @@ extern "C" fn handle_notification(this: &Object, _cmd: Sel, notification: id) {
         // by the becomeFirstResponder and resignFirstResponder methods on the NSView itself.
         if notification_object == window && first_responder == this as *const Object as id {
             let is_key_window: BOOL = msg_send![window, isKeyWindow];
-            state.trigger_deferrable_event(Event::Window(if is_key_window == YES {
-                WindowEvent::Focused
-            } else {
-                WindowEvent::Unfocused
-            }));
+            state.set_focused(is_key_window == YES);
         }
     }
 }
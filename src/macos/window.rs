@@ -1,19 +1,28 @@
 use std::cell::{Cell, RefCell};
 use std::collections::VecDeque;
 use std::ffi::c_void;
+use std::mem::ManuallyDrop;
 use std::ptr;
 use std::rc::Rc;
+use std::sync::Arc;
 
 use cocoa::appkit::{
-    NSApp, NSApplication, NSApplicationActivationPolicyRegular, NSBackingStoreBuffered,
-    NSPasteboard, NSScreen, NSView, NSWindow, NSWindowStyleMask,
+    NSApp, NSApplication, NSApplicationActivationPolicyRegular, NSBackingStoreBuffered, NSEvent,
+    NSEventModifierFlags, NSEventSubtype, NSEventType, NSMenu, NSMenuItem, NSPasteboard, NSScreen,
+    NSView, NSWindow, NSWindowStyleMask,
 };
 use cocoa::base::{id, nil, BOOL, NO, YES};
-use cocoa::foundation::{NSAutoreleasePool, NSPoint, NSRect, NSSize, NSString};
+use cocoa::foundation::{
+    NSArray, NSAutoreleasePool, NSInteger, NSPoint, NSRect, NSSize, NSString, NSUInteger,
+};
+use core_foundation::base::{Boolean, CFRelease, TCFType};
 use core_foundation::runloop::{
-    CFRunLoop, CFRunLoopTimer, CFRunLoopTimerContext, __CFRunLoopTimer, kCFRunLoopDefaultMode,
+    __CFRunLoopTimer, kCFRunLoopBeforeWaiting, kCFRunLoopCommonModes, CFRunLoop, CFRunLoopActivity,
+    CFRunLoopObserver, CFRunLoopObserverContext, CFRunLoopObserverCreate, CFRunLoopObserverRef,
+    CFRunLoopTimer, CFRunLoopTimerContext,
 };
-use keyboard_types::KeyboardEvent;
+use core_foundation::string::{CFString, CFStringRef};
+use keyboard_types::{KeyboardEvent, Modifiers};
 use objc::class;
 use objc::{msg_send, runtime::Object, sel, sel_impl};
 use raw_window_handle::{
@@ -21,30 +30,96 @@ use raw_window_handle::{
     RawDisplayHandle, RawWindowHandle,
 };
 
+use crate::accelerator::Accelerators;
+use crate::frame_stats::FrameCounter;
+use crate::hover::HoverState;
+use crate::scancode::KeyScancode;
+use crate::text::PendingText;
 use crate::{
-    Event, EventStatus, MouseCursor, Point, Size, WindowHandler, WindowInfo, WindowOpenOptions,
-    WindowScalePolicy,
+    ClipboardFormat, DragData, DropData, Event, EventStatus, FrameStats, GlobalShortcutError,
+    HapticPattern, ImeEvent, InputEvents, KeyCombo, MacLayerConfig, MouseCursor, MouseEvent, Point,
+    PointerDevice, Rect, ResizeSource, Size, WindowEvent, WindowHandler, WindowInfo,
+    WindowOpenOptions, WindowScalePolicy,
 };
 
 use super::cursor::Cursor;
-use super::keyboard::KeyboardState;
-use super::view::{create_view, BASEVIEW_STATE_IVAR};
-use super::CGWarpMouseCursorPosition;
+use super::global_shortcuts::GlobalShortcuts;
+use super::keyboard::{make_modifiers, KeyboardState};
+use super::main_thread::run_on_main_thread;
+use super::text_input::invalidate_character_coordinates;
+use super::view::{
+    create_view, make_first_responder, screen_refresh_rate, workspace_notification_center,
+    BASEVIEW_STATE_IVAR,
+};
+use super::{
+    kTISPropertyInputSourceID, CGAssociateMouseAndMouseCursorPosition, CGWarpMouseCursorPosition,
+    NSWindowStyleMaskNonactivatingPanel, TISCopyCurrentKeyboardLayoutInputSource,
+    TISGetInputSourceProperty,
+};
 
 #[cfg(feature = "opengl")]
-use crate::gl::{GlConfig, GlContext};
+use crate::gl::{GlArea, GlConfig, GlContext, GlContextCell, GlError};
+#[cfg(feature = "virtual-clock")]
+use crate::virtual_clock::{VirtualClock, VirtualFrames};
+#[cfg(feature = "opengl")]
+use crate::PhySize;
 
+/// The handle may be used from a host's background thread if the window was opened from one. The
+/// window state is only ever touched on the main thread, including when the handle is dropped.
 pub struct WindowHandle {
-    state: Rc<WindowState>,
+    state: ManuallyDrop<Rc<WindowState>>,
 }
 
 impl WindowHandle {
     pub fn close(&mut self) {
-        self.state.window_inner.close();
+        run_on_main_thread(|| self.state.close());
+    }
+
+    /// Closing the window is already synchronous on macOS. Once the window has been closed this
+    /// handle holds the last reference to the window state, so the handler will be dropped along
+    /// with the handle.
+    pub fn close_now(&mut self) {
+        self.close();
     }
 
     pub fn is_open(&self) -> bool {
-        self.state.window_inner.open.get()
+        run_on_main_thread(|| self.state.window_inner.open.get())
+    }
+
+    pub fn send_key_event(&self, event: KeyboardEvent) -> bool {
+        run_on_main_thread(|| {
+            // The handler can't be called from within one of its own callbacks
+            if !self.state.window_inner.open.get()
+                || self.state.window_handler.try_borrow_mut().is_err()
+            {
+                return false;
+            }
+
+            let event = self.state.window_inner.accelerators.translate(event);
+            self.state.trigger_event(event) == EventStatus::Captured
+        })
+    }
+
+    /// Sizes are always in points on macOS, so hosts don't set scale factors.
+    pub fn set_scale_factor(&self, _scale: f64) {}
+
+    pub fn tick_frame(&self) {
+        run_on_main_thread(|| {
+            // The handler can't be called from within one of its own callbacks
+            if self.state.window_inner.external_frame_clock
+                && self.state.window_inner.open.get()
+                && self.state.window_handler.try_borrow_mut().is_ok()
+            {
+                self.state.trigger_frame();
+            }
+        })
+    }
+}
+
+impl Drop for WindowHandle {
+    fn drop(&mut self) {
+        let state = unsafe { ManuallyDrop::take(&mut self.state) };
+        run_on_main_thread(move || drop(state));
     }
 }
 
@@ -56,6 +131,9 @@ unsafe impl HasRawWindowHandle for WindowHandle {
 
 pub(super) struct WindowInner {
     open: Cell<bool>,
+    /// Set by [`Window::close()`]. The window is closed once the handler's current callback has
+    /// returned, see [`WindowState::close()`].
+    close_requested: Cell<bool>,
 
     /// Only set if we created the parent window, i.e. we are running in
     /// parentless mode
@@ -63,17 +141,72 @@ pub(super) struct WindowInner {
     /// Only set if we created the parent window, i.e. we are running in
     /// parentless mode
     ns_window: Cell<Option<id>>,
+    /// Whether this is the window opened by [`run_app()`][crate::run_app()], which is closed when
+    /// the process is interrupted.
+    quits_with_app: bool,
     /// Our subclassed NSView
     ns_view: id,
+    /// Whether the view should refuse to resign first responder status, see
+    /// [`Window::grab_keyboard()`].
+    pub(super) keyboard_grabbed: Cell<bool>,
+    /// The virtual cursor position during an infinite drag, see
+    /// [`Window::begin_infinite_drag()`].
+    pub(super) infinite_drag: Cell<Option<Point>>,
+    /// Set by [`Window::refresh_cursor_position()`] and after the view is resized, so the cursor's
+    /// current position is sent before the next frame.
+    pub(super) cursor_refresh_requested: Cell<bool>,
+    /// Set to [`ResizeSource::Handler`] while [`Window::resize()`] resizes the view, which reports
+    /// the new size before `setFrameSize:` returns.
+    pub(super) resize_source: Cell<ResizeSource>,
+    /// The modifiers of the last key event, see [`Window::modifiers()`].
+    modifiers: Cell<Modifiers>,
+    /// The formats requested with [`Window::request_clipboard_data()`], which are read and sent
+    /// before the next frame.
+    pub(super) clipboard_requests: RefCell<Vec<ClipboardFormat>>,
+    /// Set when [`Window::start_drag()`] couldn't start a dragging session, so
+    /// [`Event::DragFinished`] is sent before the next frame.
+    drag_failed: Cell<bool>,
+    pub(super) accelerators: Accelerators,
+    pub(super) frame_counter: FrameCounter,
+    pub(super) global_shortcuts: GlobalShortcuts,
+    /// The text field the user is typing in, see [`Window::set_text_input_active()`].
+    pub(super) text_input: Cell<Option<Rect>>,
+    /// See [`Window::set_ime_cursor_area()`]. The input method is placed at the text field if this
+    /// is `None`.
+    pub(super) ime_cursor_area: Cell<Option<Rect>>,
+    /// The text being composed with an input method, see [`ImeEvent::Preedit`].
+    pub(super) marked_text: RefCell<String>,
+    /// Set when [`Window::clear_text_input()`] cancelled a composition, so [`ImeEvent::Disabled`]
+    /// is sent before the next frame.
+    ime_disabled: Cell<bool>,
+    mouse_cursor: Cell<MouseCursor>,
+    pub(super) scale_policy: WindowScalePolicy,
+    /// See [`WindowOpenOptions::drop_formats`].
+    pub(super) drop_formats: Vec<String>,
+    /// See [`WindowOpenOptions::mac_layer`].
+    pub(super) mac_layer: MacLayerConfig,
+    /// See [`WindowOpenOptions::wheel_zoom`].
+    pub(super) wheel_zoom: bool,
+    /// See [`WindowOpenOptions::external_frame_clock`]. The frame timer isn't started, and frames
+    /// are drawn when the host calls [`WindowHandle::tick_frame()`] instead.
+    pub(super) external_frame_clock: bool,
+    input_events: InputEvents,
+
+    #[cfg(feature = "virtual-clock")]
+    virtual_frames: RefCell<Option<VirtualFrames>>,
 
     #[cfg(feature = "opengl")]
-    gl_context: Option<GlContext>,
+    pub(super) gl_context: GlContextCell,
 }
 
 impl WindowInner {
     pub(super) fn close(&self) {
         if self.open.get() {
             self.open.set(false);
+            self.end_infinite_drag();
+
+            // The hot key event handler uses the NSView's Rc<WindowState>
+            self.global_shortcuts.unregister_all();
 
             unsafe {
                 // Take back ownership of the NSView's Rc<WindowState>
@@ -82,13 +215,20 @@ impl WindowInner {
 
                 // Cancel the frame timer
                 if let Some(frame_timer) = window_state.frame_timer.take() {
-                    CFRunLoop::get_current().remove_timer(&frame_timer, kCFRunLoopDefaultMode);
+                    CFRunLoop::get_current().remove_timer(&frame_timer, kCFRunLoopCommonModes);
+                }
+                #[cfg(feature = "virtual-clock")]
+                self.virtual_frames.replace(None);
+
+                if let Some(observer) = window_state.event_batch_observer.take() {
+                    CFRunLoop::get_current().remove_observer(&observer, kCFRunLoopCommonModes);
                 }
 
                 // Deregister NSView from NotificationCenter.
                 let notification_center: id =
                     msg_send![class!(NSNotificationCenter), defaultCenter];
                 let () = msg_send![notification_center, removeObserver:self.ns_view];
+                let () = msg_send![workspace_notification_center(), removeObserver:self.ns_view];
 
                 drop(window_state);
 
@@ -105,11 +245,70 @@ impl WindowInner {
                 let app = self.ns_app.take();
                 if let Some(app) = app {
                     app.stop_(app);
+
+                    // The application only stops after it has handled an event, which may not be
+                    // the case when the window was closed from a timer
+                    let event = NSEvent::otherEventWithType_location_modifierFlags_timestamp_windowNumber_context_subtype_data1_data2_(
+                        nil,
+                        NSEventType::NSApplicationDefined,
+                        NSPoint::new(0.0, 0.0),
+                        NSEventModifierFlags::empty(),
+                        0.0,
+                        0,
+                        nil,
+                        NSEventSubtype::NSWindowExposedEventType,
+                        0,
+                        0,
+                    );
+                    app.postEvent_atStart_(event, NO);
                 }
             }
         }
     }
 
+    fn begin_infinite_drag(&self) {
+        if self.infinite_drag.get().is_some() {
+            return;
+        }
+
+        unsafe {
+            let ns_window: id = msg_send![self.ns_view, window];
+            let point: NSPoint = msg_send![ns_window, mouseLocationOutsideOfEventStream];
+            let point: NSPoint = msg_send![self.ns_view, convertPoint:point fromView:nil];
+            self.infinite_drag.set(Some(Point::new(point.x, point.y)));
+
+            // The cursor stays in place while the mouse is disassociated from it, but mouse events
+            // still report how far the mouse has moved
+            CGAssociateMouseAndMouseCursorPosition(0);
+            let () = msg_send![class!(NSCursor), hide];
+        }
+    }
+
+    pub(super) fn end_infinite_drag(&self) {
+        if self.infinite_drag.take().is_some() {
+            unsafe {
+                CGAssociateMouseAndMouseCursorPosition(1);
+                let () = msg_send![class!(NSCursor), unhide];
+            }
+        }
+    }
+
+    /// Discard the marked text without sending any events. Returns whether there was any.
+    fn discard_marked_text(&self) -> bool {
+        if self.marked_text.take().is_empty() {
+            return false;
+        }
+
+        unsafe {
+            let input_context: id = msg_send![self.ns_view, inputContext];
+            if input_context != nil {
+                let () = msg_send![input_context, discardMarkedText];
+            }
+        }
+
+        true
+    }
+
     fn raw_window_handle(&self) -> RawWindowHandle {
         if self.open.get() {
             let ns_window = self.ns_window.get().unwrap_or(ptr::null_mut()) as *mut c_void;
@@ -136,40 +335,84 @@ impl<'a> Window<'a> {
         H: WindowHandler + 'static,
         B: FnOnce(&mut crate::Window) -> H,
         B: Send + 'static,
+    {
+        let handle = if let RawWindowHandle::AppKit(handle) = parent.raw_window_handle() {
+            handle
+        } else {
+            panic!("Not a macOS window");
+        };
+
+        // AppKit views can only be created and used on the main thread, but some hosts open their
+        // plugins' editors from a background thread. The window is then created on the main
+        // thread while the host's thread waits for it.
+        let parent_view = handle.ns_view as id;
+        run_on_main_thread(move || Self::open_parented_on_main_thread(parent_view, options, build))
+    }
+
+    fn open_parented_on_main_thread<H, B>(
+        parent_view: id, options: WindowOpenOptions, build: B,
+    ) -> WindowHandle
+    where
+        H: WindowHandler + 'static,
+        B: FnOnce(&mut crate::Window) -> H,
+        B: Send + 'static,
     {
         let pool = unsafe { NSAutoreleasePool::new(nil) };
 
         let scaling = match options.scale {
             WindowScalePolicy::ScaleFactor(scale) => scale,
-            WindowScalePolicy::SystemScaleFactor => 1.0,
+            WindowScalePolicy::SystemScaleFactor
+            | WindowScalePolicy::ForceUnscaled
+            | WindowScalePolicy::HostControlled(_) => 1.0,
         };
 
-        let window_info = WindowInfo::from_logical_size(options.size, scaling);
-
-        let handle = if let RawWindowHandle::AppKit(handle) = parent.raw_window_handle() {
-            handle
-        } else {
-            panic!("Not a macOS window");
-        };
+        let window_info = options.initial_window_info(scaling);
 
         let ns_view = unsafe { create_view(&options) };
 
+        let scale_policy = options.scale;
         let window_inner = WindowInner {
             open: Cell::new(true),
+            close_requested: Cell::new(false),
             ns_app: Cell::new(None),
             ns_window: Cell::new(None),
+            quits_with_app: false,
             ns_view,
+            keyboard_grabbed: Cell::new(false),
+            infinite_drag: Cell::new(None),
+            cursor_refresh_requested: Cell::new(false),
+            resize_source: Cell::new(ResizeSource::External),
+            modifiers: Cell::new(Modifiers::empty()),
+            clipboard_requests: RefCell::new(Vec::new()),
+            drag_failed: Cell::new(false),
+            accelerators: Accelerators::default(),
+            frame_counter: FrameCounter::default(),
+            global_shortcuts: GlobalShortcuts::new(),
+            text_input: Cell::new(None),
+            ime_cursor_area: Cell::new(None),
+            marked_text: RefCell::new(String::new()),
+            ime_disabled: Cell::new(false),
+            mouse_cursor: Cell::new(MouseCursor::Default),
+            scale_policy,
+            drop_formats: options.drop_formats.clone(),
+            mac_layer: options.mac_layer,
+            wheel_zoom: options.wheel_zoom,
+            external_frame_clock: options.external_frame_clock,
+            input_events: options.input_events,
+
+            #[cfg(feature = "virtual-clock")]
+            virtual_frames: RefCell::new(None),
 
             #[cfg(feature = "opengl")]
-            gl_context: options
-                .gl_config
-                .and_then(|gl_config| Self::create_gl_context(None, ns_view, gl_config)),
+            gl_context: GlContextCell::new(options.gl_config.and_then(|gl_config| {
+                Self::create_gl_context_for_view(None, ns_view, gl_config, scale_policy).ok()
+            })),
         };
 
         let window_handle = Self::init(window_inner, window_info, build);
 
         unsafe {
-            let _: id = msg_send![handle.ns_view as *mut Object, addSubview: ns_view];
+            let _: id = msg_send![parent_view, addSubview: ns_view];
 
             let () = msg_send![pool, drain];
         }
@@ -177,7 +420,7 @@ impl<'a> Window<'a> {
         window_handle
     }
 
-    pub fn open_blocking<H, B>(options: WindowOpenOptions, build: B)
+    pub fn open_blocking<H, B>(options: WindowOpenOptions, build: B, quits_with_app: bool)
     where
         H: WindowHandler + 'static,
         B: FnOnce(&mut crate::Window) -> H,
@@ -194,14 +437,21 @@ impl<'a> Window<'a> {
 
         unsafe {
             app.setActivationPolicy_(NSApplicationActivationPolicyRegular);
+
+            // Without a main menu the application can't be quit with Cmd+Q
+            if app.mainMenu() == nil {
+                app.setMainMenu_(create_main_menu());
+            }
         }
 
         let scaling = match options.scale {
             WindowScalePolicy::ScaleFactor(scale) => scale,
-            WindowScalePolicy::SystemScaleFactor => 1.0,
+            WindowScalePolicy::SystemScaleFactor
+            | WindowScalePolicy::ForceUnscaled
+            | WindowScalePolicy::HostControlled(_) => 1.0,
         };
 
-        let window_info = WindowInfo::from_logical_size(options.size, scaling);
+        let window_info = options.initial_window_info(scaling);
 
         let rect = NSRect::new(
             NSPoint::new(0.0, 0.0),
@@ -209,14 +459,37 @@ impl<'a> Window<'a> {
         );
 
         let ns_window = unsafe {
-            let ns_window = NSWindow::alloc(nil).initWithContentRect_styleMask_backing_defer_(
-                rect,
-                NSWindowStyleMask::NSTitledWindowMask
-                    | NSWindowStyleMask::NSClosableWindowMask
-                    | NSWindowStyleMask::NSMiniaturizableWindowMask,
-                NSBackingStoreBuffered,
-                NO,
-            );
+            let style_mask = (NSWindowStyleMask::NSTitledWindowMask
+                | NSWindowStyleMask::NSClosableWindowMask
+                | NSWindowStyleMask::NSMiniaturizableWindowMask)
+                .bits();
+
+            // cocoa doesn't define the non-activating panel style, so this can't use the typed
+            // `initWithContentRect_styleMask_backing_defer_()`
+            let ns_window: id = if options.non_activating {
+                let ns_panel: id = msg_send![class!(NSPanel), alloc];
+                let ns_panel: id = msg_send![ns_panel,
+                    initWithContentRect: rect
+                    styleMask: style_mask | NSWindowStyleMaskNonactivatingPanel
+                    backing: NSBackingStoreBuffered
+                    defer: NO
+                ];
+
+                // Panels are hidden when the application is deactivated by default, but with a
+                // non-activating panel the application is usually not the active one
+                let () = msg_send![ns_panel, setFloatingPanel: YES];
+                let () = msg_send![ns_panel, setHidesOnDeactivate: NO];
+
+                ns_panel
+            } else {
+                let ns_window: id = msg_send![class!(NSWindow), alloc];
+                msg_send![ns_window,
+                    initWithContentRect: rect
+                    styleMask: style_mask
+                    backing: NSBackingStoreBuffered
+                    defer: NO
+                ]
+            };
             ns_window.center();
 
             let title = NSString::alloc(nil).init_str(&options.title).autorelease();
@@ -229,16 +502,45 @@ impl<'a> Window<'a> {
 
         let ns_view = unsafe { create_view(&options) };
 
+        let scale_policy = options.scale;
+        let non_activating = options.non_activating;
         let window_inner = WindowInner {
             open: Cell::new(true),
+            close_requested: Cell::new(false),
             ns_app: Cell::new(Some(app)),
             ns_window: Cell::new(Some(ns_window)),
+            quits_with_app,
             ns_view,
+            keyboard_grabbed: Cell::new(false),
+            infinite_drag: Cell::new(None),
+            cursor_refresh_requested: Cell::new(false),
+            resize_source: Cell::new(ResizeSource::External),
+            modifiers: Cell::new(Modifiers::empty()),
+            clipboard_requests: RefCell::new(Vec::new()),
+            drag_failed: Cell::new(false),
+            accelerators: Accelerators::default(),
+            frame_counter: FrameCounter::default(),
+            global_shortcuts: GlobalShortcuts::new(),
+            text_input: Cell::new(None),
+            ime_cursor_area: Cell::new(None),
+            marked_text: RefCell::new(String::new()),
+            ime_disabled: Cell::new(false),
+            mouse_cursor: Cell::new(MouseCursor::Default),
+            scale_policy,
+            drop_formats: options.drop_formats.clone(),
+            mac_layer: options.mac_layer,
+            wheel_zoom: options.wheel_zoom,
+            external_frame_clock: false,
+            input_events: options.input_events,
+
+            #[cfg(feature = "virtual-clock")]
+            virtual_frames: RefCell::new(None),
 
             #[cfg(feature = "opengl")]
-            gl_context: options
-                .gl_config
-                .and_then(|gl_config| Self::create_gl_context(Some(ns_window), ns_view, gl_config)),
+            gl_context: GlContextCell::new(options.gl_config.and_then(|gl_config| {
+                Self::create_gl_context_for_view(Some(ns_window), ns_view, gl_config, scale_policy)
+                    .ok()
+            })),
         };
 
         let _ = Self::init(window_inner, window_info, build);
@@ -249,6 +551,11 @@ impl<'a> Window<'a> {
 
             let () = msg_send![pool, drain];
 
+            // Bring the window to the front, even when the application was started from a terminal.
+            // Non-activating panels should leave the current application active instead.
+            if !non_activating {
+                app.activateIgnoringOtherApps_(YES);
+            }
             app.run();
         }
     }
@@ -269,7 +576,19 @@ impl<'a> Window<'a> {
             window_handler: RefCell::new(window_handler),
             keyboard_state: KeyboardState::new(),
             frame_timer: Cell::new(None),
+            event_batch_observer: Cell::new(None),
+            in_event_batch: Cell::new(false),
+            pending_text: PendingText::default(),
+            interpreting_key: Cell::new(false),
+            ime_consumed_key: Cell::new(false),
+            composed_text: RefCell::new(String::new()),
             window_info: Cell::new(window_info),
+            hover: HoverState::default(),
+            drop_data: RefCell::new(None),
+            focused: Cell::new(false),
+            surface: Cell::new((nil, None)),
+            suspended: Cell::new(false),
+            pasteboard_change_count: Cell::new(unsafe { pasteboard_change_count() }),
             deferred_events: RefCell::default(),
         });
 
@@ -278,17 +597,81 @@ impl<'a> Window<'a> {
         unsafe {
             (*ns_view).set_ivar(BASEVIEW_STATE_IVAR, window_state_ptr as *const c_void);
 
-            WindowState::setup_timer(window_state_ptr);
+            let refresh_rate = Window { inner: &window_state.window_inner }.refresh_rate();
+            window_state.window_inner.frame_counter.set_refresh_rate(refresh_rate);
+            if !window_state.window_inner.external_frame_clock {
+                WindowState::setup_timer(window_state_ptr);
+            }
+            WindowState::setup_event_batch_observer(window_state_ptr);
         }
 
-        WindowHandle { state: window_state }
+        WindowHandle { state: ManuallyDrop::new(window_state) }
     }
 
     pub fn close(&mut self) {
-        self.inner.close();
+        self.inner.close_requested.set(true);
+    }
+
+    pub fn refresh_cursor_position(&mut self) {
+        self.inner.cursor_refresh_requested.set(true);
+    }
+
+    pub fn frame_stats(&self) -> FrameStats {
+        self.inner.frame_counter.stats()
+    }
+
+    pub fn modifiers(&self) -> Modifiers {
+        self.inner.modifiers.get()
+    }
+
+    pub fn refresh_rate(&self) -> Option<f64> {
+        unsafe {
+            let ns_window: id = msg_send![self.inner.ns_view, window];
+            if ns_window == nil {
+                return None;
+            }
+
+            screen_refresh_rate(msg_send![ns_window, screen])
+        }
+    }
+
+    pub fn set_animation_frames(&mut self, enabled: bool) {
+        self.inner.frame_counter.set_animation_frames(enabled);
+    }
+
+    pub fn request_clipboard_data(&mut self, format: ClipboardFormat) {
+        let mut clipboard_requests = self.inner.clipboard_requests.borrow_mut();
+        if !clipboard_requests.contains(&format) {
+            clipboard_requests.push(format);
+        }
+    }
+
+    pub fn start_drag(&mut self, data: DragData) {
+        if !unsafe { begin_dragging_session(self.inner.ns_view, &data) } {
+            self.inner.drag_failed.set(true);
+        }
     }
 
-    pub fn has_focus(&mut self) -> bool {
+    pub fn keyboard_layout(&self) -> Option<String> {
+        unsafe {
+            let input_source = TISCopyCurrentKeyboardLayoutInputSource();
+            if input_source.is_null() {
+                return None;
+            }
+
+            let id = TISGetInputSourceProperty(input_source, kTISPropertyInputSourceID);
+            let layout = if id.is_null() {
+                None
+            } else {
+                Some(CFString::wrap_under_get_rule(id as CFStringRef).to_string())
+            };
+            CFRelease(input_source);
+
+            layout
+        }
+    }
+
+    pub fn has_focus(&self) -> bool {
         unsafe {
             let view = self.inner.ns_view.as_mut().unwrap();
             let window: id = msg_send![view, window];
@@ -303,13 +686,28 @@ impl<'a> Window<'a> {
     }
 
     pub fn focus(&mut self) {
+        unsafe { make_first_responder(&*self.inner.ns_view) };
+    }
+
+    pub fn grab_keyboard(&mut self, grab: bool) -> bool {
+        self.inner.keyboard_grabbed.set(grab);
+        if !grab {
+            return false;
+        }
+
         unsafe {
-            let view = self.inner.ns_view.as_mut().unwrap();
+            let view = self.inner.ns_view;
             let window: id = msg_send![view, window];
-            if window != nil {
-                msg_send![window, makeFirstResponder:view]
+            if window == nil {
+                self.inner.keyboard_grabbed.set(false);
+                return false;
             }
+
+            let () = msg_send![window, makeKeyWindow];
+            let _: BOOL = msg_send![window, makeFirstResponder: view];
         }
+
+        true
     }
 
     pub fn resize(&mut self, size: Size) {
@@ -318,6 +716,7 @@ impl<'a> Window<'a> {
             // though the size is in fractional pixels.
             let size = NSSize::new(size.width.round(), size.height.round());
 
+            let source = self.inner.resize_source.replace(ResizeSource::Handler);
             unsafe { NSView::setFrameSize(self.inner.ns_view, size) };
             unsafe {
                 let _: () = msg_send![self.inner.ns_view, setNeedsDisplay: YES];
@@ -326,18 +725,124 @@ impl<'a> Window<'a> {
             // When using OpenGL the `NSOpenGLView` needs to be resized separately? Why? Because
             // macOS.
             #[cfg(feature = "opengl")]
-            if let Some(gl_context) = &self.inner.gl_context {
-                gl_context.resize(size);
+            if let Some(gl_context) = self.inner.gl_context.get() {
+                let physical_size: NSSize =
+                    unsafe { msg_send![self.inner.ns_view, convertSizeToBacking: size] };
+                gl_context.resized(PhySize::new(
+                    physical_size.width.round() as u32,
+                    physical_size.height.round() as u32,
+                ));
             }
 
             // If this is a standalone window then we'll also need to resize the window itself
             if let Some(ns_window) = self.inner.ns_window.get() {
                 unsafe { NSWindow::setContentSize_(ns_window, size) };
             }
+            self.inner.resize_source.set(source);
+        }
+    }
+
+    pub fn drag_threshold(&self) -> f64 {
+        3.0
+    }
+
+    pub fn display_color_profile(&self) -> Option<Vec<u8>> {
+        unsafe {
+            let ns_window: id = msg_send![self.inner.ns_view, window];
+            if ns_window == nil {
+                return None;
+            }
+
+            let screen: id = msg_send![ns_window, screen];
+            if screen == nil {
+                return None;
+            }
+
+            let color_space: id = msg_send![screen, colorSpace];
+            if color_space == nil {
+                return None;
+            }
+
+            let data: id = msg_send![color_space, ICCProfileData];
+            if data == nil {
+                return None;
+            }
+
+            let length: NSUInteger = msg_send![data, length];
+            let bytes: *const u8 = msg_send![data, bytes];
+            if bytes.is_null() || length == 0 {
+                return None;
+            }
+
+            Some(std::slice::from_raw_parts(bytes, length as usize).to_vec())
+        }
+    }
+
+    pub fn mouse_cursor(&self) -> MouseCursor {
+        self.inner.mouse_cursor.get()
+    }
+
+    pub fn set_shadow(&mut self, shadow: bool) {
+        // Parented views don't own the window they're in
+        if let Some(ns_window) = self.inner.ns_window.get() {
+            let has_shadow = if shadow { YES } else { NO };
+            unsafe {
+                let () = msg_send![ns_window, setHasShadow: has_shadow];
+                // The shadow follows the shape of the window's content when it's transparent
+                let () = msg_send![ns_window, invalidateShadow];
+            }
+        }
+    }
+
+    pub fn set_shape(&mut self, shape: Option<&[Rect]>) {
+        let ns_view = self.inner.ns_view;
+        unsafe {
+            let rects = match shape {
+                Some(rects) => rects,
+                None => {
+                    let layer: id = msg_send![ns_view, layer];
+                    if layer != nil {
+                        let () = msg_send![layer, setMask: nil];
+                    }
+                    return;
+                }
+            };
+
+            let () = msg_send![ns_view, setWantsLayer: YES];
+            let layer: id = msg_send![ns_view, layer];
+            let bounds: NSRect = msg_send![ns_view, bounds];
+
+            // Only the opaque parts of the mask's sublayers are visible
+            let mask: id = msg_send![class!(CALayer), layer];
+            let () = msg_send![mask, setFrame: bounds];
+            let black: id = msg_send![class!(NSColor), blackColor];
+            let black: *const c_void = msg_send![black, CGColor];
+            for rect in rects {
+                // AppKit flips the geometry of a flipped view's layer, and with that its mask, so
+                // these are the same coordinates as the view's
+                let frame = NSRect::new(
+                    NSPoint::new(rect.origin.x, rect.origin.y),
+                    NSSize::new(rect.size.width, rect.size.height),
+                );
+                let sublayer: id = msg_send![class!(CALayer), layer];
+                let () = msg_send![sublayer, setFrame: frame];
+                let () = msg_send![sublayer, setBackgroundColor: black];
+                let () = msg_send![mask, addSublayer: sublayer];
+            }
+            let () = msg_send![layer, setMask: mask];
+
+            // Standalone windows need to be transparent for the masked out parts to disappear
+            if let Some(ns_window) = self.inner.ns_window.get() {
+                let clear: id = msg_send![class!(NSColor), clearColor];
+                let () = msg_send![ns_window, setOpaque: NO];
+                let () = msg_send![ns_window, setBackgroundColor: clear];
+                let () = msg_send![ns_window, invalidateShadow];
+            }
         }
     }
 
     pub fn set_mouse_cursor(&mut self, cursor: MouseCursor) {
+        self.inner.mouse_cursor.set(cursor);
         let native_cursor = Cursor::from(cursor);
         unsafe {
             let bounds: NSRect = msg_send![self.inner.ns_view as id, bounds];
@@ -359,26 +864,158 @@ impl<'a> Window<'a> {
                 let point_x = bounds.origin.x + point.x;
                 let point_y = screen_height - bounds.origin.y - point.y;
 
-                CGWarpMouseCursorPosition(NSPoint::new(point_x, point_y));
+                warp_mouse_cursor(NSPoint::new(point_x, point_y));
             }
         }
     }
 
+    pub fn set_mouse_position_screen(&mut self, point: Point) {
+        // macOS' global display coordinates already use points with a top left origin
+        unsafe { warp_mouse_cursor(NSPoint::new(point.x, point.y)) };
+    }
+
+    pub fn move_mouse(&mut self, dx: f64, dy: f64) {
+        unsafe {
+            // `mouseLocation` uses a bottom left origin on the primary screen, while
+            // `CGWarpMouseCursorPosition()` uses a top left origin
+            let location = NSEvent::mouseLocation(nil);
+            let primary_screen = NSArray::objectAtIndex(NSScreen::screens(nil), 0);
+            let screen_height = NSScreen::frame(primary_screen).size.height;
+
+            warp_mouse_cursor(NSPoint::new(location.x + dx, screen_height - location.y + dy));
+        }
+    }
+
+    pub fn begin_infinite_drag(&mut self) {
+        self.inner.begin_infinite_drag();
+    }
+
+    pub fn end_infinite_drag(&mut self) {
+        self.inner.end_infinite_drag();
+    }
+
+    pub fn register_accelerator(&mut self, combo: KeyCombo, id: u32) {
+        self.inner.accelerators.register(combo, id);
+    }
+
+    pub fn unregister_accelerator(&mut self, id: u32) {
+        self.inner.accelerators.unregister(id);
+    }
+
+    pub fn register_global_shortcut(
+        &mut self, combo: KeyCombo, id: u32,
+    ) -> Result<(), GlobalShortcutError> {
+        self.inner.global_shortcuts.register(self.inner.ns_view, combo, id)
+    }
+
+    pub fn unregister_global_shortcut(&mut self, id: u32) {
+        self.inner.global_shortcuts.unregister(id);
+    }
+
+    pub fn set_text_input_active(&mut self, rect: Rect) {
+        let was_active = self.inner.text_input.replace(Some(rect)).is_some();
+        self.inner.ime_cursor_area.set(None);
+        // A pending dead key would otherwise become the start of a composition
+        if !was_active {
+            self.inner.discard_marked_text();
+        }
+        unsafe { invalidate_character_coordinates(self.inner.ns_view) };
+    }
+
+    pub fn clear_text_input(&mut self) {
+        let was_active = self.inner.text_input.take().is_some();
+        self.inner.ime_cursor_area.set(None);
+
+        // The view ignores the input method's callbacks for the cancelled composition, since text
+        // input is no longer active
+        if was_active && self.inner.discard_marked_text() {
+            self.inner.ime_disabled.set(true);
+        }
+    }
+
+    pub fn is_text_input_active(&self) -> bool {
+        self.inner.text_input.get().is_some()
+    }
+
+    pub fn set_ime_cursor_area(&mut self, rect: Rect) {
+        self.inner.ime_cursor_area.set(Some(rect));
+        unsafe { invalidate_character_coordinates(self.inner.ns_view) };
+    }
+
+    pub fn perform_haptic(&mut self, pattern: HapticPattern) {
+        // `NSHapticFeedbackPattern` values
+        let pattern: NSInteger = match pattern {
+            HapticPattern::Generic => 0,
+            HapticPattern::Alignment => 1,
+            HapticPattern::LevelChange => 2,
+        };
+        // `NSHapticFeedbackPerformanceTimeNow`, since the feedback usually accompanies input that
+        // is being handled right now
+        let performance_time: NSUInteger = 1;
+
+        unsafe {
+            let performer: id = msg_send![class!(NSHapticFeedbackManager), defaultPerformer];
+            let () = msg_send![
+                performer,
+                performFeedbackPattern:pattern
+                performanceTime:performance_time
+            ];
+        }
+    }
+
     #[cfg(feature = "opengl")]
     pub fn gl_context(&self) -> Option<&GlContext> {
-        self.inner.gl_context.as_ref()
+        self.inner.gl_context.get()
+    }
+
+    #[cfg(feature = "virtual-clock")]
+    pub fn set_virtual_clock(&mut self, clock: &VirtualClock) {
+        *self.inner.virtual_frames.borrow_mut() = Some(VirtualFrames::new(clock));
     }
 
     #[cfg(feature = "opengl")]
-    fn create_gl_context(
-        ns_window: Option<id>, ns_view: id, config: GlConfig,
-    ) -> Option<GlContext> {
+    pub fn create_gl_context(&mut self, config: GlConfig) -> Result<(), GlError> {
+        if self.inner.gl_context.get().is_some() {
+            return Err(GlError::AlreadyCreated);
+        }
+
+        let context = Self::create_gl_context_for_view(
+            self.inner.ns_window.get(),
+            self.inner.ns_view,
+            config,
+            self.inner.scale_policy,
+        )?;
+        self.inner.gl_context.set(context).map_err(|_| GlError::AlreadyCreated)
+    }
+
+    #[cfg(feature = "opengl")]
+    pub fn create_gl_area(
+        &mut self, position: crate::PhyPoint, size: crate::PhySize, config: GlConfig,
+    ) -> Result<GlArea, GlError> {
+        let context = Self::create_gl_context_for_view(
+            self.inner.ns_window.get(),
+            self.inner.ns_view,
+            config,
+            self.inner.scale_policy,
+        )?;
+        super::gl_area::create_gl_area(context, position, size)
+    }
+
+    #[cfg(feature = "opengl")]
+    fn create_gl_context_for_view(
+        ns_window: Option<id>, ns_view: id, config: GlConfig, scale_policy: WindowScalePolicy,
+    ) -> Result<GlContext, GlError> {
         let mut handle = AppKitWindowHandle::empty();
         handle.ns_window = ns_window.unwrap_or(ptr::null_mut()) as *mut c_void;
         handle.ns_view = ns_view as *mut c_void;
         let handle = RawWindowHandle::AppKit(handle);
 
-        unsafe { GlContext::create(&handle, config).ok() }
+        let context = unsafe { GlContext::create(&handle, config)? };
+        if scale_policy == WindowScalePolicy::ForceUnscaled {
+            context.set_wants_best_resolution(false);
+        }
+
+        Ok(context)
     }
 }
 
@@ -387,8 +1024,38 @@ pub(super) struct WindowState {
     window_handler: RefCell<Box<dyn WindowHandler>>,
     keyboard_state: KeyboardState,
     frame_timer: Cell<Option<CFRunLoopTimer>>,
+    /// Finishes the current event batch before the run loop goes to sleep.
+    event_batch_observer: Cell<Option<CFRunLoopObserver>>,
+    /// Whether the handler's `before_events()` has been called without a matching
+    /// `after_events()`.
+    in_event_batch: Cell<bool>,
+    /// Text typed during the current event batch that hasn't been sent yet, see [`Event::Text`].
+    pending_text: PendingText,
+    /// Whether a key press is being passed to the input method, see
+    /// [`super::text_input::interpret_key_event()`].
+    pub(super) interpreting_key: Cell<bool>,
+    /// Whether the input method consumed the key press that's being passed to it.
+    pub(super) ime_consumed_key: Cell<bool>,
+    /// The characters the input method composed from a dead key while text input isn't active.
+    pub(super) composed_text: RefCell<String>,
     /// The last known window info for this window.
     pub window_info: Cell<WindowInfo>,
+    pub(super) hover: HoverState,
+    /// The data being dragged over the view, which is shared by the drag events. `None` when
+    /// nothing is being dragged over the view.
+    pub(super) drop_data: RefCell<Option<Arc<DropData>>>,
+    /// Whether the handler was last told that the window has focus. AppKit can report the same
+    /// change through both the first responder methods and the key window notifications.
+    focused: Cell<bool>,
+    /// The window the view was last added to, and the color depth of that window's screen if it's
+    /// known. The handler's surfaces need to be recreated when either of these changes.
+    pub(super) surface: Cell<(id, Option<i32>)>,
+    /// Whether the handler was last told that the window is suspended, see
+    /// [`WindowEvent::Suspended`].
+    suspended: Cell<bool>,
+    /// The general pasteboard's change count when it was last checked. AppKit doesn't notify
+    /// applications about clipboard changes, so this is polled every frame.
+    pasteboard_change_count: Cell<NSInteger>,
 
     /// Events that will be triggered at the end of `window_handler`'s borrow.
     deferred_events: RefCell<VecDeque<Event>>,
@@ -410,13 +1077,57 @@ impl WindowState {
         state
     }
 
+    /// Suspend the window if it's `zero_sized` or its window is minimized, and resume it otherwise.
+    /// Returns whether the window is visible, and can thus be resized.
+    pub(super) unsafe fn set_suspended(&self, view: &Object, zero_sized: bool) -> bool {
+        let ns_window: id = msg_send![view, window];
+        let miniaturized: BOOL =
+            if ns_window.is_null() { NO } else { msg_send![ns_window, isMiniaturized] };
+
+        let suspended = zero_sized || miniaturized == YES;
+        if suspended != self.suspended.replace(suspended) {
+            let event = if suspended { WindowEvent::Suspended } else { WindowEvent::Resumed };
+            self.trigger_deferrable_event(Event::Window(event));
+        }
+
+        !suspended
+    }
+
     /// Trigger the event immediately and return the event status.
     /// Will panic if `window_handler` is already borrowed (see `trigger_deferrable_event`).
     pub(super) fn trigger_event(&self, event: Event) -> EventStatus {
-        let mut window = crate::Window::new(Window { inner: &self.window_inner });
-        let mut window_handler = self.window_handler.borrow_mut();
-        let status = window_handler.on_event(&mut window, event);
-        self.send_deferred_events(window_handler.as_mut());
+        self.trigger_event_with_key_code(event, None)
+    }
+
+    /// Like [`trigger_event()`][Self::trigger_event()], for a keyboard or accelerator event caused
+    /// by the key with the virtual key code `key_code`, see [`crate::Window::key_scancode()`].
+    pub(super) fn trigger_key_event(&self, event: Event, key_code: u16) -> EventStatus {
+        self.trigger_event_with_key_code(event, Some(key_code))
+    }
+
+    fn trigger_event_with_key_code(&self, event: Event, key_code: Option<u16>) -> EventStatus {
+        if self.close_if_requested() {
+            return EventStatus::Ignored;
+        }
+
+        #[cfg(feature = "event-log")]
+        let _raw_event = crate::event_log::RawEvent::set(current_event_description);
+
+        let status = {
+            let mut window = crate::Window::new(Window { inner: &self.window_inner });
+            let mut window_handler = self.window_handler.borrow_mut();
+            self.begin_event_batch(window_handler.as_mut());
+            if let Some(text) = self.pending_text.key_event(&event) {
+                window_handler.on_event(&mut window, text);
+            }
+            let scancode = key_code.map(|key_code| KeyScancode::set(u32::from(key_code)));
+            let status = window_handler.on_event(&mut window, event);
+            drop(scancode);
+            self.send_deferred_events(window_handler.as_mut());
+            status
+        };
+
+        self.close_if_requested();
         status
     }
 
@@ -424,20 +1135,184 @@ impl WindowState {
     /// otherwise add the event to a queue that will be cleared once `window_handler`'s mutable borrow ends.
     /// As this method might result in the event triggering asynchronously, it can't reliably return the event status.
     pub(super) fn trigger_deferrable_event(&self, event: Event) {
+        if self.close_if_requested() {
+            return;
+        }
+
+        #[cfg(feature = "event-log")]
+        let _raw_event = crate::event_log::RawEvent::set(current_event_description);
+
         if let Ok(mut window_handler) = self.window_handler.try_borrow_mut() {
             let mut window = crate::Window::new(Window { inner: &self.window_inner });
+            self.begin_event_batch(window_handler.as_mut());
             window_handler.on_event(&mut window, event);
             self.send_deferred_events(window_handler.as_mut());
         } else {
             self.deferred_events.borrow_mut().push_back(event);
         }
+
+        self.close_if_requested();
+    }
+
+    /// Send [`WindowEvent::Focused`] or [`WindowEvent::Unfocused`] if the focus has changed since
+    /// the handler was last told about it.
+    pub(super) fn set_focused(&self, focused: bool) {
+        if self.focused.replace(focused) != focused {
+            self.trigger_deferrable_event(Event::Window(if focused {
+                WindowEvent::Focused
+            } else {
+                WindowEvent::Unfocused
+            }));
+        }
+    }
+
+    /// Send a cursor movement for the cursor's current position if it's over the view, see
+    /// [`crate::Window::refresh_cursor_position()`].
+    fn refresh_cursor_position(&self) {
+        let inner = &self.window_inner;
+        // The virtual cursor doesn't follow the actual cursor during an infinite drag
+        if !inner.input_events.mouse_motion || inner.infinite_drag.get().is_some() {
+            return;
+        }
+
+        let (position, modifiers) = unsafe {
+            let ns_window: id = msg_send![inner.ns_view, window];
+            if ns_window == nil {
+                return;
+            }
+
+            // The cursor may be over another window that covers this one
+            let location = NSEvent::mouseLocation(nil);
+            let window_number: NSInteger = msg_send![
+                class!(NSWindow),
+                windowNumberAtPoint: location
+                belowWindowWithWindowNumber: 0 as NSInteger
+            ];
+            let own_window_number: NSInteger = msg_send![ns_window, windowNumber];
+            if window_number != own_window_number {
+                return;
+            }
+
+            let point: NSPoint = msg_send![ns_window, mouseLocationOutsideOfEventStream];
+            let point: NSPoint = msg_send![inner.ns_view, convertPoint:point fromView:nil];
+            let bounds = NSView::bounds(inner.ns_view);
+            let inside: BOOL = msg_send![inner.ns_view, mouse:point inRect:bounds];
+            if inside != YES {
+                return;
+            }
+
+            let modifiers: NSEventModifierFlags = msg_send![class!(NSEvent), modifierFlags];
+            (Point::new(point.x, point.y), make_modifiers(modifiers))
+        };
+
+        let move_event =
+            MouseEvent::CursorMoved { position, modifiers, device: PointerDevice::default() };
+        self.hover.filter(move_event, |event| self.trigger_deferrable_event(Event::Mouse(event)));
     }
 
     pub(super) fn trigger_frame(&self) {
+        if self.close_if_requested() {
+            return;
+        }
+
+        let change_count = unsafe { pasteboard_change_count() };
+        if change_count != self.pasteboard_change_count.replace(change_count) {
+            self.trigger_deferrable_event(Event::ClipboardChanged);
+        }
+
+        if self.window_inner.cursor_refresh_requested.take() {
+            self.refresh_cursor_position();
+        }
+
+        let clipboard_requests = self.window_inner.clipboard_requests.take();
+        for format in clipboard_requests {
+            let data = unsafe { pasteboard_data(&format) };
+            self.trigger_deferrable_event(format.into_event(data));
+        }
+
+        if self.window_inner.drag_failed.take() {
+            self.trigger_deferrable_event(Event::DragFinished(None));
+        }
+
+        if self.window_inner.ime_disabled.take() {
+            self.trigger_deferrable_event(Event::Ime(ImeEvent::Disabled));
+        }
+
+        {
+            let mut window = crate::Window::new(Window { inner: &self.window_inner });
+            let mut window_handler = self.window_handler.borrow_mut();
+            self.send_deferred_events(window_handler.as_mut());
+            // The animation frame is an event like any other, so it's sent as part of the batch
+            let external_clock = self.window_inner.external_frame_clock;
+            if let Some(animation_frame) = self.window_inner.frame_counter.frame(external_clock) {
+                self.begin_event_batch(window_handler.as_mut());
+                let event = Event::Window(WindowEvent::AnimationFrame(animation_frame));
+                window_handler.on_event(&mut window, event);
+            }
+            self.end_event_batch(window_handler.as_mut());
+            window_handler.on_frame(&mut window);
+            #[cfg(feature = "opengl")]
+            window.report_present(window_handler.as_mut());
+        }
+
+        self.close_if_requested();
+    }
+
+    /// Finish the current event batch, send `WillClose` to the handler, and close the window. If
+    /// one of the handler's callbacks is currently running, then this is postponed until that
+    /// callback has returned. The handler doesn't receive any more callbacks after `WillClose`.
+    pub(super) fn close(&self) {
+        if !self.window_inner.open.get() {
+            return;
+        }
+
+        let mut window_handler = match self.window_handler.try_borrow_mut() {
+            Ok(window_handler) => window_handler,
+            Err(_) => {
+                self.window_inner.close_requested.set(true);
+                return;
+            }
+        };
+
+        self.deferred_events.borrow_mut().clear();
+        self.end_event_batch(window_handler.as_mut());
+
         let mut window = crate::Window::new(Window { inner: &self.window_inner });
-        let mut window_handler = self.window_handler.borrow_mut();
-        self.send_deferred_events(window_handler.as_mut());
-        window_handler.on_frame(&mut window);
+        window_handler.on_event(&mut window, Event::Window(WindowEvent::WillClose));
+        drop(window_handler);
+
+        self.window_inner.close();
+    }
+
+    /// Finish closing the window if [`Window::close()`] has been called. Returns `true` if the
+    /// window has been closed or is about to be closed, in which case the handler must not receive
+    /// any more callbacks.
+    fn close_if_requested(&self) -> bool {
+        if self.window_inner.close_requested.get() {
+            self.close();
+        }
+
+        self.window_inner.close_requested.get() || !self.window_inner.open.get()
+    }
+
+    /// AppKit doesn't tell us when it's done dispatching events, so an event batch is started
+    /// lazily when the first event is sent to the handler. It's finished right before the run
+    /// loop goes to sleep or before the next frame, whichever comes first.
+    fn begin_event_batch(&self, window_handler: &mut dyn WindowHandler) {
+        if !self.in_event_batch.replace(true) {
+            let mut window = crate::Window::new(Window { inner: &self.window_inner });
+            window_handler.before_events(&mut window);
+        }
+    }
+
+    fn end_event_batch(&self, window_handler: &mut dyn WindowHandler) {
+        if self.in_event_batch.replace(false) {
+            let mut window = crate::Window::new(Window { inner: &self.window_inner });
+            if let Some(text) = self.pending_text.take() {
+                window_handler.on_event(&mut window, text);
+            }
+            window_handler.after_events(&mut window);
+        }
     }
 
     pub(super) fn keyboard_state(&self) -> &KeyboardState {
@@ -445,13 +1320,46 @@ impl WindowState {
     }
 
     pub(super) fn process_native_key_event(&self, event: *mut Object) -> Option<KeyboardEvent> {
-        self.keyboard_state.process_native_event(event)
+        let key_event = self.keyboard_state.process_native_event(event)?;
+
+        let modifiers = key_event.modifiers;
+        if self.window_inner.modifiers.replace(modifiers) != modifiers {
+            self.trigger_event(Event::Window(WindowEvent::ModifiersChanged(modifiers)));
+        }
+
+        Some(key_event)
     }
 
-    unsafe fn setup_timer(window_state_ptr: *const WindowState) {
+    /// Start the frame timer at the interval for the window's display, or restart it after the
+    /// interval changed.
+    pub(super) unsafe fn setup_timer(window_state_ptr: *const WindowState) {
         extern "C" fn timer_callback(_: *mut __CFRunLoopTimer, window_state_ptr: *mut c_void) {
             unsafe {
-                let window_state = &*(window_state_ptr as *const WindowState);
+                // The handler may close the window, which releases the view's reference to the
+                // window state
+                let window_state_ptr = window_state_ptr as *const WindowState;
+                Rc::increment_strong_count(window_state_ptr);
+                let window_state = Rc::from_raw(window_state_ptr);
+
+                // The window opened through `run_app()` is closed when the process is interrupted
+                if window_state.window_inner.quits_with_app && crate::app::quit_requested() {
+                    window_state.close();
+                    return;
+                }
+
+                #[cfg(feature = "virtual-clock")]
+                if window_state.window_inner.virtual_frames.borrow().is_some() {
+                    let window_inner = &window_state.window_inner;
+                    while let Some(frame) = VirtualFrames::due_frame(
+                        &window_inner.virtual_frames,
+                        &window_inner.frame_counter,
+                    ) {
+                        window_state.trigger_frame();
+                        frame.drawn();
+                    }
+
+                    return;
+                }
 
                 window_state.trigger_frame();
             }
@@ -465,16 +1373,78 @@ impl WindowState {
             copyDescription: None,
         };
 
-        let timer = CFRunLoopTimer::new(0.0, 0.015, 0, 0, timer_callback, &mut timer_context);
+        if let Some(frame_timer) = (*window_state_ptr).frame_timer.take() {
+            CFRunLoop::get_current().remove_timer(&frame_timer, kCFRunLoopCommonModes);
+        }
+
+        let timer = CFRunLoopTimer::new(
+            0.0,
+            (*window_state_ptr).window_inner.frame_counter.interval().as_secs_f64(),
+            0,
+            0,
+            timer_callback,
+            &mut timer_context,
+        );
 
-        CFRunLoop::get_current().add_timer(&timer, kCFRunLoopDefaultMode);
+        // AppKit runs the run loop in the event tracking mode during live resizes and while menus
+        // are open, and the common modes include that mode
+        CFRunLoop::get_current().add_timer(&timer, kCFRunLoopCommonModes);
 
         (*window_state_ptr).frame_timer.set(Some(timer));
     }
 
+    unsafe fn setup_event_batch_observer(window_state_ptr: *const WindowState) {
+        extern "C" fn observer_callback(
+            _: CFRunLoopObserverRef, _: CFRunLoopActivity, window_state_ptr: *mut c_void,
+        ) {
+            unsafe {
+                let window_state_ptr = window_state_ptr as *const WindowState;
+                Rc::increment_strong_count(window_state_ptr);
+                let window_state = Rc::from_raw(window_state_ptr);
+
+                if window_state.close_if_requested() {
+                    return;
+                }
+
+                if let Ok(mut window_handler) = window_state.window_handler.try_borrow_mut() {
+                    window_state.send_deferred_events(window_handler.as_mut());
+                    window_state.end_event_batch(window_handler.as_mut());
+                }
+
+                window_state.close_if_requested();
+            }
+        }
+
+        let mut observer_context = CFRunLoopObserverContext {
+            version: 0,
+            info: window_state_ptr as *mut c_void,
+            retain: None,
+            release: None,
+            copyDescription: None,
+        };
+
+        let observer = CFRunLoopObserver::wrap_under_create_rule(CFRunLoopObserverCreate(
+            ptr::null(),
+            kCFRunLoopBeforeWaiting,
+            true as Boolean,
+            0,
+            observer_callback,
+            &mut observer_context,
+        ));
+
+        CFRunLoop::get_current().add_observer(&observer, kCFRunLoopCommonModes);
+
+        (*window_state_ptr).event_batch_observer.set(Some(observer));
+    }
+
     fn send_deferred_events(&self, window_handler: &mut dyn WindowHandler) {
         let mut window = crate::Window::new(Window { inner: &self.window_inner });
         loop {
+            // Events that were queued before the handler closed the window are dropped
+            if self.window_inner.close_requested.get() {
+                break;
+            }
+
             let next_event = self.deferred_events.borrow_mut().pop_front();
             if let Some(event) = next_event {
                 window_handler.on_event(&mut window, event);
@@ -497,13 +1467,176 @@ unsafe impl<'a> HasRawDisplayHandle for Window<'a> {
     }
 }
 
-pub fn copy_to_clipboard(string: &str) {
+/// Create a main menu containing just the application menu with a Quit item. The Quit item closes
+/// the key window instead of terminating the application, so the handler still receives
+/// `WillClose`.
+unsafe fn create_main_menu() -> id {
+    let main_menu = NSMenu::new(nil).autorelease();
+    let app_menu_item = NSMenuItem::new(nil).autorelease();
+    main_menu.addItem_(app_menu_item);
+
+    let process_info: id = msg_send![class!(NSProcessInfo), processInfo];
+    let process_name: id = msg_send![process_info, processName];
+    let quit_prefix = NSString::alloc(nil).init_str("Quit ").autorelease();
+    let quit_title: id = msg_send![quit_prefix, stringByAppendingString: process_name];
+    let quit_key = NSString::alloc(nil).init_str("q").autorelease();
+    let quit_item = NSMenuItem::alloc(nil)
+        .initWithTitle_action_keyEquivalent_(quit_title, sel!(performClose:), quit_key)
+        .autorelease();
+
+    let app_menu = NSMenu::new(nil).autorelease();
+    app_menu.addItem_(quit_item);
+    app_menu_item.setSubmenu_(app_menu);
+
+    main_menu
+}
+
+/// Move the cursor to a point in global display coordinates, which have their origin at the top
+/// left of the primary screen.
+unsafe fn warp_mouse_cursor(point: NSPoint) {
+    CGWarpMouseCursorPosition(point);
+    // Warping the cursor briefly suppresses mouse movement, which makes drag interactions that
+    // keep moving the cursor feel sluggish. Reassociating the mouse with the cursor ends that.
+    CGAssociateMouseAndMouseCursorPosition(1);
+}
+
+unsafe fn pasteboard_change_count() -> NSInteger {
+    let pasteboard: id = msg_send![class!(NSPasteboard), generalPasteboard];
+    msg_send![pasteboard, changeCount]
+}
+
+/// The pasteboard type for a clipboard format. Custom formats are used as pasteboard types as is.
+/// The returned string is autoreleased.
+unsafe fn pasteboard_type(format: &ClipboardFormat) -> id {
+    match format {
+        ClipboardFormat::Text => cocoa::appkit::NSPasteboardTypeString,
+        ClipboardFormat::Custom(name) => NSString::alloc(nil).init_str(name).autorelease(),
+    }
+}
+
+unsafe fn pasteboard_data(format: &ClipboardFormat) -> Option<Vec<u8>> {
+    let pasteboard: id = msg_send![class!(NSPasteboard), generalPasteboard];
+    let pasteboard_type = pasteboard_type(format);
+
+    if let ClipboardFormat::Text = format {
+        let text: id = msg_send![pasteboard, stringForType: pasteboard_type];
+        return if text == nil {
+            None
+        } else {
+            Some(super::keyboard::from_nsstring(text).into_bytes())
+        };
+    }
+
+    let data: id = msg_send![pasteboard, dataForType: pasteboard_type];
+    if data == nil {
+        return None;
+    }
+
+    let length: NSUInteger = msg_send![data, length];
+    let bytes: *const u8 = msg_send![data, bytes];
+    if bytes.is_null() || length == 0 {
+        return Some(Vec::new());
+    }
+
+    Some(std::slice::from_raw_parts(bytes, length as usize).to_vec())
+}
+
+/// Start a dragging session with the view as its source, see [`Window::start_drag()`]. The session
+/// runs from the application's event loop and ends in `draggingSession:endedAtPoint:operation:`.
+unsafe fn begin_dragging_session(ns_view: id, data: &DragData) -> bool {
+    // The session is started from the mouse event that's currently being handled
+    let event: id = msg_send![NSApp(), currentEvent];
+    if event == nil {
+        return false;
+    }
+    match event.eventType() {
+        NSEventType::NSLeftMouseDown
+        | NSEventType::NSLeftMouseDragged
+        | NSEventType::NSRightMouseDown
+        | NSEventType::NSRightMouseDragged
+        | NSEventType::NSOtherMouseDown
+        | NSEventType::NSOtherMouseDragged => {}
+        _ => return false,
+    }
+
+    // Every item needs an image, so files are dragged with their icon and custom data with the
+    // generic document icon
+    let workspace: id = msg_send![class!(NSWorkspace), sharedWorkspace];
+    let writers: Vec<(id, id)> = match data {
+        DragData::Files(paths) => paths
+            .iter()
+            .map(|path| {
+                let path = NSString::alloc(nil).init_str(&path.to_string_lossy()).autorelease();
+                let url: id = msg_send![class!(NSURL), fileURLWithPath: path];
+                let icon: id = msg_send![workspace, iconForFile: path];
+                (url, icon)
+            })
+            .collect(),
+        DragData::Custom { format, data } => {
+            let item: id = msg_send![class!(NSPasteboardItem), new];
+            let data: id =
+                msg_send![class!(NSData), dataWithBytes: data.as_ptr() length: data.len()];
+            let pasteboard_type = pasteboard_type(&ClipboardFormat::Custom(format.clone()));
+            let _: BOOL = msg_send![item, setData: data forType: pasteboard_type];
+
+            let file_type = NSString::alloc(nil).init_str("public.data").autorelease();
+            let icon: id = msg_send![workspace, iconForFileType: file_type];
+            vec![(item.autorelease(), icon)]
+        }
+    };
+    if writers.is_empty() {
+        return false;
+    }
+
+    let location = ns_view.convertPoint_fromView_(event.locationInWindow(), nil);
+    let items: id = msg_send![class!(NSMutableArray), array];
+    for (writer, icon) in writers {
+        let item: id = msg_send![class!(NSDraggingItem), alloc];
+        let item: id = msg_send![item, initWithPasteboardWriter: writer];
+        let frame = NSRect::new(
+            NSPoint::new(location.x - 16.0, location.y - 16.0),
+            NSSize::new(32.0, 32.0),
+        );
+        let () = msg_send![item, setDraggingFrame: frame contents: icon];
+        let () = msg_send![items, addObject: item];
+        let () = msg_send![item, release];
+    }
+
+    let session: id =
+        msg_send![ns_view, beginDraggingSessionWithItems: items event: event source: ns_view];
+    session != nil
+}
+
+pub fn copy_to_clipboard(contents: &[(ClipboardFormat, &[u8])]) {
     unsafe {
-        let pb = NSPasteboard::generalPasteboard(nil);
+        let pasteboard = NSPasteboard::generalPasteboard(nil);
+        pasteboard.clearContents();
+
+        for (format, data) in contents {
+            let pasteboard_type = pasteboard_type(format);
+            if let ClipboardFormat::Text = format {
+                let text = NSString::alloc(nil).init_str(&String::from_utf8_lossy(data));
+                pasteboard.setString_forType(text.autorelease(), pasteboard_type);
+            } else {
+                let data: id =
+                    msg_send![class!(NSData), dataWithBytes: data.as_ptr() length: data.len()];
+                pasteboard.setData_forType(data, pasteboard_type);
+            }
+        }
+    }
+}
 
-        let ns_str = NSString::alloc(nil).init_str(string);
+/// Describes the `NSEvent` the application is currently handling for the event log. Events sent
+/// in response to notifications are logged with whichever event was handled last.
+#[cfg(feature = "event-log")]
+fn current_event_description() -> String {
+    unsafe {
+        let event: id = msg_send![NSApp(), currentEvent];
+        if event == nil {
+            return String::from("no current NSEvent");
+        }
 
-        pb.clearContents();
-        pb.setString_forType(ns_str, cocoa::appkit::NSPasteboardTypeString);
+        let description: id = msg_send![event, description];
+        super::keyboard::from_nsstring(description)
     }
 }
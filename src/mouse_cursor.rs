@@ -1,20 +1,39 @@
+/// The standard mouse cursor shapes, modelled after the CSS `cursor` property.
+///
+/// Not every platform (or on Linux, every cursor theme) has a native cursor for every shape. In
+/// that case the closest available shape is used, and if there is nothing similar the default
+/// arrow cursor is shown instead.
 #[derive(Debug, Eq, PartialEq, Clone, Copy, PartialOrd, Ord, Hash)]
 pub enum MouseCursor {
     Default,
+    /// A pointing hand, used for links.
     Hand,
+    /// An open hand, indicating that something can be grabbed.
+    Grab,
+    /// A closed hand, indicating that something is being dragged.
     HandGrabbing,
     Help,
+    ContextMenu,
 
     Hidden,
 
     Text,
     VerticalText,
 
+    /// The application is busy and can't be interacted with, usually an hourglass or a spinner.
+    Wait,
+    /// The application is busy in the background but can still be interacted with, usually an
+    /// arrow with a small spinner next to it.
+    Progress,
+    #[deprecated(note = "Use `MouseCursor::Wait` instead")]
     Working,
+    #[deprecated(note = "Use `MouseCursor::Progress` instead")]
     PtrWorking,
 
     NotAllowed,
     PtrNotAllowed,
+    /// The dragged item cannot be dropped at the current location.
+    NoDrop,
 
     ZoomIn,
     ZoomOut,
@@ -0,0 +1,55 @@
+use std::error::Error;
+use std::fmt;
+use std::ptr::null_mut;
+use std::sync::atomic::{AtomicPtr, Ordering};
+
+type ErrorCallback = Box<dyn Fn(&PlatformError) + Send + Sync>;
+
+/// Points to a leaked `ErrorCallback`, or is null if no callback has been set.
+static ERROR_CALLBACK: AtomicPtr<ErrorCallback> = AtomicPtr::new(null_mut());
+
+/// An error the windowing system reported for a request baseview made on behalf of a window. These
+/// errors don't necessarily stop the window from working, but they're often the only clue when a
+/// window doesn't show up under a particular window manager.
+///
+/// A [`Watchdog`][crate::watchdog::Watchdog] also reports handler callbacks that took too long
+/// this way, with the callback's name as the request.
+#[derive(Debug, Clone)]
+pub struct PlatformError {
+    /// The name of the request that failed, for instance `CreateWindow`.
+    pub request: &'static str,
+    /// A description of what went wrong.
+    pub message: String,
+}
+
+impl fmt::Display for PlatformError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} request failed: {}", self.request, self.message)
+    }
+}
+
+impl Error for PlatformError {}
+
+/// Set the function that's called whenever the windowing system reports a [`PlatformError`],
+/// replacing the previous one. Errors are printed to STDERR until a callback has been set.
+///
+/// The callback is shared by all windows, and it may be called from any thread that runs a
+/// window's event loop. Currently only the X11 backend reports errors from the windowing system.
+///
+/// This is meant to be called once when the application or plugin is initialized. A callback
+/// that gets replaced is never freed, since another thread may still be calling it.
+pub fn set_platform_error_callback(callback: impl Fn(&PlatformError) + Send + Sync + 'static) {
+    let callback: Box<ErrorCallback> = Box::new(Box::new(callback));
+    ERROR_CALLBACK.store(Box::into_raw(callback), Ordering::Release);
+}
+
+#[cfg_attr(not(any(target_os = "linux", feature = "watchdog")), allow(dead_code))]
+pub(crate) fn report_platform_error(error: PlatformError) {
+    let callback = ERROR_CALLBACK.load(Ordering::Acquire);
+    if callback.is_null() {
+        eprintln!("baseview: {}", error);
+    } else {
+        // SAFETY: Callbacks are never freed once they've been set
+        unsafe { (*callback)(&error) };
+    }
+}
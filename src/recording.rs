@@ -0,0 +1,190 @@
+//! Recording and replaying the events delivered to a [`WindowHandler`].
+//!
+//! This can be used to capture a reproducible trace of an interaction that causes a bug, so the
+//! same sequence of events can later be replayed into the handler. Recordings are stored as
+//! newline-delimited JSON, with one [`RecordedEvent`] per line.
+//!
+//! ```no_run
+//! # use baseview::{Event, EventStatus, Window, WindowHandler, WindowOpenOptions};
+//! # use baseview::recording::EventRecorder;
+//! # struct MyHandler;
+//! # impl WindowHandler for MyHandler {
+//! #     fn on_frame(&mut self, _window: &mut Window) {}
+//! #     fn on_event(&mut self, _window: &mut Window, _event: Event) -> EventStatus {
+//! #         EventStatus::Ignored
+//! #     }
+//! # }
+//! # fn options() -> WindowOpenOptions { unimplemented!() }
+//! Window::open_blocking(options(), |_| {
+//!     EventRecorder::create(MyHandler, "events.jsonl").unwrap()
+//! })
+//! .unwrap();
+//! ```
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::{Event, EventStatus, Window, WindowHandler};
+
+/// A single event in a recording.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct RecordedEvent {
+    /// The time since the recording was started.
+    pub time: Duration,
+    pub event: Event,
+}
+
+/// A [`WindowHandler`] that records every event sent to the wrapped handler before passing it on.
+///
+/// If writing to the output fails, recording stops but events are still passed on to the wrapped
+/// handler.
+pub struct EventRecorder<H: WindowHandler> {
+    handler: H,
+    output: Option<Box<dyn Write>>,
+    start: Instant,
+}
+
+impl<H: WindowHandler> EventRecorder<H> {
+    pub fn new(handler: H, output: impl Write + 'static) -> Self {
+        Self { handler, output: Some(Box::new(output)), start: Instant::now() }
+    }
+
+    /// Record the events to a newly created file, replacing it if it already exists.
+    pub fn create(handler: H, path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::new(handler, BufWriter::new(File::create(path)?)))
+    }
+
+    /// Returns the wrapped handler.
+    pub fn handler(&mut self) -> &mut H {
+        &mut self.handler
+    }
+
+    fn record(&mut self, event: &Event) {
+        if let Some(output) = &mut self.output {
+            let recorded_event = RecordedEvent { time: self.start.elapsed(), event: event.clone() };
+
+            let result = serde_json::to_writer(&mut *output, &recorded_event)
+                .map_err(io::Error::from)
+                .and_then(|()| output.write_all(b"\n"));
+            if result.is_err() {
+                self.output = None;
+            }
+        }
+    }
+}
+
+impl<H: WindowHandler> WindowHandler for EventRecorder<H> {
+    fn on_frame(&mut self, window: &mut Window) {
+        self.handler.on_frame(window);
+    }
+
+    fn on_event(&mut self, window: &mut Window, event: Event) -> EventStatus {
+        self.record(&event);
+
+        self.handler.on_event(window, event)
+    }
+
+    fn before_events(&mut self, window: &mut Window) {
+        self.handler.before_events(window);
+    }
+
+    fn after_events(&mut self, window: &mut Window) {
+        // Make sure the recording is complete even if the application doesn't exit cleanly
+        if let Some(output) = &mut self.output {
+            if output.flush().is_err() {
+                self.output = None;
+            }
+        }
+
+        self.handler.after_events(window);
+    }
+
+    #[cfg(feature = "opengl")]
+    fn on_present(&mut self, window: &mut Window, info: crate::gl::PresentInfo) {
+        self.handler.on_present(window, info);
+    }
+}
+
+/// A [`WindowHandler`] that replays a recording into the wrapped handler.
+///
+/// Playback starts on the first frame, and recorded events are sent to the handler on the first
+/// frame after their original timestamp, so the original timing is kept up to the frame interval.
+/// Events from the actual window are still passed on to the handler, so the window should be left
+/// alone during playback for a faithful reproduction.
+pub struct EventPlayer<H: WindowHandler> {
+    handler: H,
+    events: VecDeque<RecordedEvent>,
+    start: Option<Instant>,
+}
+
+impl<H: WindowHandler> EventPlayer<H> {
+    /// Read a recording created by [`EventRecorder`]. Returns an error if the recording cannot be
+    /// read or parsed.
+    pub fn new(handler: H, input: impl Read) -> io::Result<Self> {
+        let mut events = VecDeque::new();
+        for line in BufReader::new(input).lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                events.push_back(serde_json::from_str(&line)?);
+            }
+        }
+
+        Ok(Self { handler, events, start: None })
+    }
+
+    /// Read a recording from a file created by [`EventRecorder::create()`].
+    pub fn open(handler: H, path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::new(handler, File::open(path)?)
+    }
+
+    /// Returns the wrapped handler.
+    pub fn handler(&mut self) -> &mut H {
+        &mut self.handler
+    }
+
+    /// Whether all recorded events have been replayed.
+    pub fn is_finished(&self) -> bool {
+        self.events.is_empty()
+    }
+}
+
+impl<H: WindowHandler> WindowHandler for EventPlayer<H> {
+    fn on_frame(&mut self, window: &mut Window) {
+        let elapsed = self.start.get_or_insert_with(Instant::now).elapsed();
+
+        if self.events.front().map_or(false, |event| event.time <= elapsed) {
+            self.handler.before_events(window);
+            while let Some(recorded_event) = self.events.front() {
+                if recorded_event.time > elapsed {
+                    break;
+                }
+
+                let recorded_event = self.events.pop_front().unwrap();
+                self.handler.on_event(window, recorded_event.event);
+            }
+            self.handler.after_events(window);
+        }
+
+        self.handler.on_frame(window);
+    }
+
+    fn on_event(&mut self, window: &mut Window, event: Event) -> EventStatus {
+        self.handler.on_event(window, event)
+    }
+
+    fn before_events(&mut self, window: &mut Window) {
+        self.handler.before_events(window);
+    }
+
+    fn after_events(&mut self, window: &mut Window) {
+        self.handler.after_events(window);
+    }
+
+    #[cfg(feature = "opengl")]
+    fn on_present(&mut self, window: &mut Window, info: crate::gl::PresentInfo) {
+        self.handler.on_present(window, info);
+    }
+}
@@ -0,0 +1,52 @@
+use std::cell::Cell;
+
+thread_local! {
+    /// The scancode of the key event the current thread is sending to a handler, see
+    /// [`KeyScancode::set()`].
+    static KEY_SCANCODE: Cell<Option<u32>> = const { Cell::new(None) };
+}
+
+/// Makes the raw scancode of the key event that's being sent to a handler available through
+/// [`Window::key_scancode()`][crate::Window::key_scancode()] until it's dropped. Key events sent
+/// from within another key event's callback restore the outer event's scancode afterwards.
+pub(crate) struct KeyScancode {
+    previous: Option<u32>,
+}
+
+impl KeyScancode {
+    pub fn set(scancode: u32) -> Self {
+        Self { previous: KEY_SCANCODE.with(|current| current.replace(Some(scancode))) }
+    }
+}
+
+impl Drop for KeyScancode {
+    fn drop(&mut self) {
+        KEY_SCANCODE.with(|current| current.set(self.previous));
+    }
+}
+
+/// The scancode set with [`KeyScancode::set()`], if a key event is being handled.
+pub(crate) fn current() -> Option<u32> {
+    KEY_SCANCODE.with(Cell::get)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restores_the_outer_scancode() {
+        assert_eq!(current(), None);
+
+        let outer = KeyScancode::set(30);
+        assert_eq!(current(), Some(30));
+        {
+            let _inner = KeyScancode::set(44);
+            assert_eq!(current(), Some(44));
+        }
+        assert_eq!(current(), Some(30));
+
+        drop(outer);
+        assert_eq!(current(), None);
+    }
+}
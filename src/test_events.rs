@@ -0,0 +1,18 @@
+//! Builds the pointer events fed to the input helpers in their unit tests.
+
+use keyboard_types::Modifiers;
+
+use crate::{Event, MouseButton, MouseEvent, Point, PointerDevice};
+
+pub(crate) fn moved(device: PointerDevice, x: f64, y: f64) -> Event {
+    let position = Point::new(x, y);
+    Event::Mouse(MouseEvent::CursorMoved { position, modifiers: Modifiers::empty(), device })
+}
+
+pub(crate) fn pressed(device: PointerDevice, button: MouseButton) -> Event {
+    Event::Mouse(MouseEvent::ButtonPressed { button, modifiers: Modifiers::empty(), device })
+}
+
+pub(crate) fn released(device: PointerDevice, button: MouseButton) -> Event {
+    Event::Mouse(MouseEvent::ButtonReleased { button, modifiers: Modifiers::empty(), device })
+}
@@ -0,0 +1,62 @@
+use std::cell::RefCell;
+
+use keyboard_types::{Key, KeyState, Modifiers};
+
+use crate::Event;
+
+/// Collects the characters typed by consecutive key presses so the backends can send them as a
+/// single [`Event::Text`]. The pending text is sent before the next key press that doesn't type
+/// anything, and at the end of the event batch.
+#[derive(Debug, Default)]
+pub(crate) struct PendingText {
+    text: RefCell<String>,
+}
+
+impl PendingText {
+    /// Must be called before a keyboard, accelerator, or input method event is sent to the handler.
+    /// If the event doesn't type any text, then this returns the pending text, which needs to be
+    /// sent first.
+    pub fn key_event(&self, event: &Event) -> Option<Event> {
+        let key_event = match event {
+            Event::Keyboard(key_event) if key_event.state == KeyState::Down => key_event,
+            Event::Keyboard(_) => return None,
+            Event::Accelerator(_) | Event::Ime(_) => return self.take(),
+            _ => return None,
+        };
+
+        match &key_event.key {
+            Key::Character(text)
+                if !key_event.modifiers.intersects(Modifiers::CONTROL | Modifiers::META) =>
+            {
+                self.text.borrow_mut().push_str(text);
+                None
+            }
+            _ => self.take(),
+        }
+    }
+
+    /// Take the pending text at the end of an event batch.
+    pub fn take(&self) -> Option<Event> {
+        let text = std::mem::take(&mut *self.text.borrow_mut());
+        if text.is_empty() {
+            None
+        } else {
+            Some(Event::Text(text))
+        }
+    }
+}
+
+/// Convert an offset in UTF-16 code units, as used by the platforms' input method APIs, to a byte
+/// offset into `text`.
+#[cfg(any(target_os = "windows", target_os = "macos"))]
+pub(crate) fn utf16_to_byte_offset(text: &str, utf16_offset: usize) -> usize {
+    let mut units = 0;
+    for (offset, c) in text.char_indices() {
+        if units >= utf16_offset {
+            return offset;
+        }
+        units += c.len_utf16();
+    }
+
+    text.len()
+}
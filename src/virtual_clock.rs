@@ -0,0 +1,435 @@
+//! Testing animations and other timing logic without real time passing.
+//!
+//! A window normally draws its frames from a timer that runs in real time, so a test that checks
+//! what a handler does over time depends on how fast the machine running it is. With the
+//! `virtual-clock` feature enabled, a window can be driven by a [`VirtualClock`] instead, see
+//! [`Window::set_virtual_clock()`]. The window then only draws frames when the clock is moved
+//! forward with [`VirtualClock::advance()`], one frame for every [`VirtualClock::FRAME_INTERVAL`]
+//! that passes. The clock also has one-shot timers for testing debounced actions.
+//!
+//! ```no_run
+//! # use baseview::{Event, EventStatus, Window, WindowHandler, WindowOpenOptions};
+//! # fn options() -> WindowOpenOptions { unimplemented!() }
+//! use std::time::Duration;
+//! use baseview::virtual_clock::VirtualClock;
+//!
+//! struct FadeIn {
+//!     clock: VirtualClock,
+//!     opacity: f64,
+//! }
+//!
+//! impl WindowHandler for FadeIn {
+//!     fn on_frame(&mut self, _window: &mut Window) {
+//!         // Handlers read the time from the clock so they see the time of the current frame
+//!         self.opacity = (self.clock.now().as_secs_f64() * 2.0).min(1.0);
+//!     }
+//!
+//!     fn on_event(&mut self, _window: &mut Window, _event: Event) -> EventStatus {
+//!         EventStatus::Ignored
+//!     }
+//! }
+//!
+//! let clock = VirtualClock::new();
+//! let window_clock = clock.clone();
+//! std::thread::spawn(move || {
+//!     Window::open_blocking(options(), move |window| {
+//!         window.set_virtual_clock(&window_clock);
+//!         FadeIn { clock: window_clock, opacity: 0.0 }
+//!     })
+//!     .unwrap();
+//! });
+//!
+//! // Once the window has been opened, this draws all of its frames in the first half second
+//! clock.advance(Duration::from_millis(500));
+//! ```
+//!
+//! [`Window::set_virtual_clock()`]: crate::Window::set_virtual_clock()
+
+use std::cell::RefCell;
+use std::fmt;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::frame_stats::FrameCounter;
+
+type TimerCallback = Box<dyn FnOnce() + Send>;
+
+/// Identifies a timer started with [`VirtualClock::set_timer()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TimerId(u64);
+
+struct Timer {
+    id: TimerId,
+    deadline: Duration,
+    callback: TimerCallback,
+}
+
+/// A window whose frames are drawn by the clock.
+struct ClockedWindow {
+    id: u64,
+    next_frame: Duration,
+}
+
+#[derive(Default)]
+struct State {
+    now: Duration,
+    timers: Vec<Timer>,
+    windows: Vec<ClockedWindow>,
+    next_timer_id: u64,
+    next_window_id: u64,
+}
+
+impl State {
+    fn frame_due(&self) -> bool {
+        self.windows.iter().any(|window| window.next_frame <= self.now)
+    }
+
+    /// The index of the timer that should run next, if any are due. Timers are started in order,
+    /// so the first of the timers with the earliest deadline was also started first.
+    fn due_timer(&self) -> Option<usize> {
+        self.timers
+            .iter()
+            .enumerate()
+            .filter(|(_, timer)| timer.deadline <= self.now)
+            .min_by_key(|(_, timer)| timer.deadline)
+            .map(|(index, _)| index)
+    }
+}
+
+struct Shared {
+    state: Mutex<State>,
+    /// Signalled when a window has drawn a frame or stopped using the clock.
+    frame_drawn: Condvar,
+    /// The real time the clock started at, so frames on the clock can be timed with an `Instant`.
+    epoch: Instant,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+}
+
+/// A clock that only moves when it's told to. See the [module documentation][self] for an
+/// example.
+///
+/// This is a handle to the clock, so clones of it can be shared between the test and the
+/// handlers of the windows that use it.
+#[derive(Clone)]
+pub struct VirtualClock {
+    shared: Arc<Shared>,
+}
+
+impl VirtualClock {
+    /// The time between two frames of a window driven by a virtual clock. This doesn't depend on
+    /// the display, so tests behave the same on every machine.
+    pub const FRAME_INTERVAL: Duration = Duration::from_millis(15);
+
+    /// A clock at time zero.
+    pub fn new() -> Self {
+        let shared =
+            Shared { state: Mutex::default(), frame_drawn: Condvar::new(), epoch: Instant::now() };
+        Self { shared: Arc::new(shared) }
+    }
+
+    /// The time since the clock was created. While a frame is drawn or a timer runs, this is the
+    /// time that frame or timer was due at.
+    pub fn now(&self) -> Duration {
+        self.shared.lock().now
+    }
+
+    /// Move the clock forward by `duration`. This stops at every frame and timer that comes due
+    /// along the way, and it waits for the windows using this clock to draw their frames before
+    /// moving on. Timers run on the calling thread, after the frames that are due at the same time.
+    ///
+    /// This must not be called from the handler of a window that uses this clock, since that
+    /// window can't draw its frames until the handler returns.
+    pub fn advance(&self, duration: Duration) {
+        let mut state = self.shared.lock();
+        let end = state.now + duration;
+        loop {
+            state = self.shared.frame_drawn.wait_while(state, |state| state.frame_due()).unwrap();
+
+            if let Some(index) = state.due_timer() {
+                let timer = state.timers.remove(index);
+                drop(state);
+                (timer.callback)();
+                state = self.shared.lock();
+                continue;
+            }
+
+            let next_frame = state.windows.iter().map(|window| window.next_frame);
+            let next_timer = state.timers.iter().map(|timer| timer.deadline);
+            let next = next_frame.chain(next_timer).fold(end, Duration::min);
+            if next <= state.now {
+                break;
+            }
+            state.now = next;
+        }
+    }
+
+    /// Call `callback` once `delay` has passed. Timers that are due at the same time run in the
+    /// order they were started.
+    pub fn set_timer(&self, delay: Duration, callback: impl FnOnce() + Send + 'static) -> TimerId {
+        let mut state = self.shared.lock();
+        let id = TimerId(state.next_timer_id);
+        state.next_timer_id += 1;
+        let deadline = state.now + delay;
+        state.timers.push(Timer { id, deadline, callback: Box::new(callback) });
+
+        id
+    }
+
+    /// Stop a timer before it runs. Returns `false` if the timer has already run or was cancelled.
+    pub fn cancel_timer(&self, id: TimerId) -> bool {
+        let mut state = self.shared.lock();
+        let len = state.timers.len();
+        state.timers.retain(|timer| timer.id != id);
+        state.timers.len() != len
+    }
+}
+
+impl Default for VirtualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for VirtualClock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.shared.lock();
+        f.debug_struct("VirtualClock")
+            .field("now", &state.now)
+            .field("timers", &state.timers.len())
+            .field("windows", &state.windows.len())
+            .finish()
+    }
+}
+
+/// Draws a window's frames on a [`VirtualClock`] instead of on its frame timer. The window keeps
+/// checking for due frames on its frame timer, and it stops using the clock when this is dropped.
+pub(crate) struct VirtualFrames {
+    shared: Arc<Shared>,
+    id: u64,
+}
+
+impl VirtualFrames {
+    /// The window's first frame is due one frame interval from now.
+    pub(crate) fn new(clock: &VirtualClock) -> Self {
+        let mut state = clock.shared.lock();
+        let id = state.next_window_id;
+        state.next_window_id += 1;
+        let next_frame = state.now + VirtualClock::FRAME_INTERVAL;
+        state.windows.push(ClockedWindow { id, next_frame });
+
+        Self { shared: Arc::clone(&clock.shared), id }
+    }
+
+    /// The window's next frame if it's due on its virtual clock. Windows that use a virtual clock
+    /// draw all of their due frames this way instead of drawing frames on their frame timer, and
+    /// the frame counter times these frames on the clock instead of in real time.
+    ///
+    /// `frames` isn't borrowed while the frame is drawn, so the handler may replace the clock.
+    pub(crate) fn due_frame(
+        frames: &RefCell<Option<VirtualFrames>>, frame_counter: &FrameCounter,
+    ) -> Option<DueFrame> {
+        let frames = frames.borrow();
+        let frames = frames.as_ref()?;
+        let state = frames.shared.lock();
+        let window = state.windows.iter().find(|window| window.id == frames.id)?;
+        if window.next_frame > state.now {
+            return None;
+        }
+
+        frame_counter.set_virtual_time(frames.shared.epoch + window.next_frame);
+        Some(DueFrame { shared: Arc::clone(&frames.shared), id: frames.id })
+    }
+}
+
+/// A frame that's due on a virtual clock, see [`VirtualFrames::due_frame()`].
+pub(crate) struct DueFrame {
+    shared: Arc<Shared>,
+    id: u64,
+}
+
+impl DueFrame {
+    /// Lets the clock move on. Must be called once the frame has been drawn.
+    pub(crate) fn drawn(self) {
+        let mut state = self.shared.lock();
+        if let Some(window) = state.windows.iter_mut().find(|window| window.id == self.id) {
+            window.next_frame += VirtualClock::FRAME_INTERVAL;
+        }
+        drop(state);
+        self.shared.frame_drawn.notify_all();
+    }
+}
+
+impl Drop for VirtualFrames {
+    fn drop(&mut self) {
+        self.shared.lock().windows.retain(|window| window.id != self.id);
+        self.shared.frame_drawn.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::mpsc;
+    use std::thread;
+
+    use super::*;
+    use crate::{Event, EventStatus, Size, Window, WindowEvent, WindowHandler, WindowOpenOptions};
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn timers_run_in_order() {
+        let clock = VirtualClock::new();
+        let (tx, rx) = mpsc::channel();
+
+        for (name, delay) in [("b", ms(20)), ("a", ms(10)), ("c", ms(20))] {
+            let tx = tx.clone();
+            let timer_clock = clock.clone();
+            clock.set_timer(delay, move || tx.send((name, timer_clock.now())).unwrap());
+        }
+        clock.advance(ms(15));
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), [("a", ms(10))]);
+        clock.advance(ms(5));
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), [("b", ms(20)), ("c", ms(20))]);
+        assert_eq!(clock.now(), ms(20));
+    }
+
+    #[test]
+    fn debounced_timer_is_restarted() {
+        let clock = VirtualClock::new();
+        let fired = Arc::new(AtomicBool::new(false));
+        let mut timer = None;
+
+        for _ in 0..3 {
+            if let Some(timer) = timer.take() {
+                assert!(clock.cancel_timer(timer));
+            }
+            let fired = Arc::clone(&fired);
+            timer = Some(clock.set_timer(ms(50), move || fired.store(true, Ordering::SeqCst)));
+            clock.advance(ms(30));
+        }
+        assert!(!fired.load(Ordering::SeqCst));
+
+        clock.advance(ms(20));
+        assert!(fired.load(Ordering::SeqCst));
+        assert!(!clock.cancel_timer(timer.unwrap()));
+    }
+
+    #[test]
+    fn advance_waits_for_frames() {
+        let clock = VirtualClock::new();
+        let (tx, rx) = mpsc::channel();
+
+        // This thread stands in for a window's frame timer, which checks for due frames at its
+        // own pace
+        let frames = RefCell::new(Some(VirtualFrames::new(&clock)));
+        let stop = Arc::new(AtomicBool::new(false));
+        let frame_timer = {
+            let (clock, tx, stop) = (clock.clone(), tx.clone(), Arc::clone(&stop));
+            thread::spawn(move || {
+                let frame_counter = FrameCounter::default();
+                while !stop.load(Ordering::SeqCst) {
+                    while let Some(frame) = VirtualFrames::due_frame(&frames, &frame_counter) {
+                        tx.send(("frame", clock.now())).unwrap();
+                        frame.drawn();
+                    }
+                    thread::sleep(ms(1));
+                }
+            })
+        };
+
+        let timer_clock = clock.clone();
+        clock.set_timer(ms(30), move || tx.send(("timer", timer_clock.now())).unwrap());
+        clock.advance(ms(50));
+        stop.store(true, Ordering::SeqCst);
+        frame_timer.join().unwrap();
+
+        assert_eq!(
+            rx.try_iter().collect::<Vec<_>>(),
+            [("frame", ms(15)), ("frame", ms(30)), ("timer", ms(30)), ("frame", ms(45))]
+        );
+        // Windows that are gone aren't waited for
+        clock.advance(ms(50));
+    }
+
+    struct FrameRecorder {
+        clock: VirtualClock,
+        frames: mpsc::Sender<(&'static str, Duration)>,
+        close: Arc<AtomicBool>,
+    }
+
+    impl WindowHandler for FrameRecorder {
+        fn on_frame(&mut self, window: &mut Window) {
+            if self.close.load(Ordering::SeqCst) {
+                window.close();
+            }
+            let _ = self.frames.send(("frame", self.clock.now()));
+        }
+
+        fn on_event(&mut self, _window: &mut Window, event: Event) -> EventStatus {
+            if let Event::Window(WindowEvent::AnimationFrame(frame)) = event {
+                let _ = self.frames.send(("animation", frame.elapsed));
+            }
+            EventStatus::Ignored
+        }
+    }
+
+    /// This opens a real window, so it's skipped on Linux without an X server. AppKit windows can
+    /// only be opened on the main thread, which tests don't run on.
+    #[cfg(not(target_os = "macos"))]
+    #[test]
+    fn drives_window_frames() {
+        if cfg!(target_os = "linux") && std::env::var_os("DISPLAY").is_none() {
+            return;
+        }
+
+        let clock = VirtualClock::new();
+        let close = Arc::new(AtomicBool::new(false));
+        let (frames_tx, frames_rx) = mpsc::channel();
+        let (opened_tx, opened_rx) = mpsc::channel();
+
+        let options = WindowOpenOptions::builder()
+            .title("virtual_clock")
+            .size(Size::new(100.0, 100.0))
+            .build()
+            .unwrap();
+        let window_thread = {
+            let (clock, close) = (clock.clone(), Arc::clone(&close));
+            thread::spawn(move || {
+                Window::open_blocking(options, move |window| {
+                    window.set_virtual_clock(&clock);
+                    window.set_animation_frames(true);
+                    opened_tx.send(()).unwrap();
+                    FrameRecorder { clock, frames: frames_tx, close }
+                })
+                .unwrap();
+            })
+        };
+        opened_rx.recv().unwrap();
+
+        clock.advance(VirtualClock::FRAME_INTERVAL * 3);
+        // Animation frames are timed on the clock as well
+        assert_eq!(
+            frames_rx.try_iter().collect::<Vec<_>>(),
+            [
+                ("animation", ms(0)),
+                ("frame", ms(15)),
+                ("animation", ms(15)),
+                ("frame", ms(30)),
+                ("animation", ms(15)),
+                ("frame", ms(45)),
+            ]
+        );
+
+        close.store(true, Ordering::SeqCst);
+        clock.advance(VirtualClock::FRAME_INTERVAL);
+        window_thread.join().unwrap();
+    }
+}
@@ -0,0 +1,139 @@
+//! Finding callbacks that stall the GUI thread.
+//!
+//! Hosts may flag a plugin as hung when its editor doesn't return control to the event loop for a
+//! while. With the `watchdog` feature enabled, [`Watchdog`] wraps a [`WindowHandler`], times every
+//! call to the wrapped handler, and reports the ones that take longer than a budget through the
+//! [platform error callback][crate::set_platform_error_callback()]:
+//!
+//! ```no_run
+//! # use std::time::Duration;
+//! # use baseview::{Event, EventStatus, Window, WindowHandler, WindowOpenOptions};
+//! # use baseview::watchdog::Watchdog;
+//! # struct MyHandler;
+//! # impl WindowHandler for MyHandler {
+//! #     fn on_frame(&mut self, _window: &mut Window) {}
+//! #     fn on_event(&mut self, _window: &mut Window, _event: Event) -> EventStatus {
+//! #         EventStatus::Ignored
+//! #     }
+//! # }
+//! # fn options() -> WindowOpenOptions { unimplemented!() }
+//! baseview::set_platform_error_callback(|error| eprintln!("{}", error));
+//!
+//! Window::open_blocking(options(), |_| {
+//!     Watchdog::new(MyHandler, Duration::from_millis(100))
+//! })
+//! .unwrap();
+//! ```
+//!
+//! The report includes a backtrace captured when the slow callback returns. That shows where the
+//! handler was called from, for instance whether a frame was drawn from within a modal resize
+//! loop, but not where the time was spent within the handler itself.
+
+use std::time::{Duration, Instant};
+
+use backtrace::Backtrace;
+
+use crate::platform_error::{report_platform_error, PlatformError};
+use crate::{Event, EventStatus, Window, WindowHandler};
+
+/// A [`WindowHandler`] that reports calls to the wrapped handler that take longer than a budget.
+/// See the [module documentation][self] for an example.
+pub struct Watchdog<H: WindowHandler> {
+    handler: H,
+    budget: Duration,
+}
+
+impl<H: WindowHandler> Watchdog<H> {
+    pub fn new(handler: H, budget: Duration) -> Self {
+        Self { handler, budget }
+    }
+
+    /// Returns the wrapped handler.
+    pub fn handler(&mut self) -> &mut H {
+        &mut self.handler
+    }
+
+    /// Change the budget for the wrapped handler's callbacks.
+    pub fn set_budget(&mut self, budget: Duration) {
+        self.budget = budget;
+    }
+
+    fn check(&self, callback: &'static str, start: Instant, event: Option<&'static str>) {
+        let elapsed = start.elapsed();
+        if elapsed <= self.budget {
+            return;
+        }
+
+        let event = match event {
+            Some(event) => format!(" handling a {} event", event),
+            None => String::new(),
+        };
+        report_platform_error(PlatformError {
+            request: callback,
+            message: format!(
+                "took {} ms{}, exceeding the watchdog's budget of {} ms\n{:?}",
+                elapsed.as_millis(),
+                event,
+                self.budget.as_millis(),
+                Backtrace::new()
+            ),
+        });
+    }
+}
+
+impl<H: WindowHandler> WindowHandler for Watchdog<H> {
+    fn on_frame(&mut self, window: &mut Window) {
+        let start = Instant::now();
+        self.handler.on_frame(window);
+        self.check("on_frame", start, None);
+    }
+
+    fn on_event(&mut self, window: &mut Window, event: Event) -> EventStatus {
+        // Named up front, since the handler takes ownership of the event
+        let name = event_name(&event);
+
+        let start = Instant::now();
+        let status = self.handler.on_event(window, event);
+        self.check("on_event", start, Some(name));
+
+        status
+    }
+
+    fn before_events(&mut self, window: &mut Window) {
+        let start = Instant::now();
+        self.handler.before_events(window);
+        self.check("before_events", start, None);
+    }
+
+    fn after_events(&mut self, window: &mut Window) {
+        let start = Instant::now();
+        self.handler.after_events(window);
+        self.check("after_events", start, None);
+    }
+
+    #[cfg(feature = "opengl")]
+    fn on_present(&mut self, window: &mut Window, info: crate::gl::PresentInfo) {
+        let start = Instant::now();
+        self.handler.on_present(window, info);
+        self.check("on_present", start, None);
+    }
+}
+
+fn event_name(event: &Event) -> &'static str {
+    match event {
+        Event::Mouse(_) => "mouse",
+        Event::Keyboard(_) => "keyboard",
+        Event::Text(_) => "text",
+        Event::Ime(_) => "ime",
+        Event::Accelerator(_) => "accelerator",
+        Event::GlobalShortcut(_) => "global shortcut",
+        Event::Window(_) => "window",
+        Event::Device(_) => "device",
+        Event::PowerSuspend => "power suspend",
+        Event::PowerResume => "power resume",
+        Event::SystemSettingsChanged => "system settings changed",
+        Event::ClipboardChanged => "clipboard changed",
+        Event::ClipboardData(_) | Event::ClipboardFormatData { .. } => "clipboard data",
+        Event::DragFinished(_) => "drag finished",
+    }
+}
@@ -0,0 +1,147 @@
+use std::ffi::OsStr;
+use std::os::windows::ffi::OsStrExt;
+use std::ptr::{copy_nonoverlapping, null_mut};
+use std::thread;
+use std::time::Duration;
+
+use winapi::shared::minwindef::UINT;
+use winapi::shared::windef::HWND;
+use winapi::um::winbase::{
+    GlobalAlloc, GlobalFree, GlobalLock, GlobalSize, GlobalUnlock, GMEM_MOVEABLE,
+};
+use winapi::um::winuser::{
+    CloseClipboard, EmptyClipboard, GetClipboardData, IsClipboardFormatAvailable, OpenClipboard,
+    RegisterClipboardFormatW, SetClipboardData, CF_UNICODETEXT,
+};
+
+use crate::ClipboardFormat;
+
+/// The number of times to try opening the clipboard, since another application may briefly have
+/// it open while it's copying something.
+const OPEN_ATTEMPTS: usize = 5;
+
+/// Read the clipboard's data in `format`, with text converted to UTF-8. Returns `None` if the
+/// clipboard doesn't contain the format or couldn't be opened.
+pub(super) fn read(hwnd: HWND, format: &ClipboardFormat) -> Option<Vec<u8>> {
+    let id = format_id(format)?;
+
+    unsafe {
+        if IsClipboardFormatAvailable(id) == 0 || !open(hwnd) {
+            return None;
+        }
+
+        let data = read_global(id, format);
+        CloseClipboard();
+
+        data
+    }
+}
+
+/// Replace the clipboard's contents. Text is converted to the UTF-16 `CF_UNICODETEXT` format.
+pub(super) fn write(contents: &[(ClipboardFormat, &[u8])]) {
+    unsafe {
+        // The clipboard doesn't need to be owned by a window when its data isn't rendered on
+        // demand
+        if !open(null_mut()) {
+            return;
+        }
+
+        EmptyClipboard();
+        for (format, data) in contents {
+            if let Some(id) = format_id(format) {
+                write_global(id, format, data);
+            }
+        }
+
+        CloseClipboard();
+    }
+}
+
+/// `CF_UNICODETEXT` for text, or the id of a registered clipboard format.
+pub(super) fn format_id(format: &ClipboardFormat) -> Option<UINT> {
+    match format {
+        ClipboardFormat::Text => Some(CF_UNICODETEXT),
+        ClipboardFormat::Custom(name) => {
+            let name: Vec<u16> = OsStr::new(name).encode_wide().chain(Some(0)).collect();
+            match unsafe { RegisterClipboardFormatW(name.as_ptr()) } {
+                0 => None,
+                id => Some(id),
+            }
+        }
+    }
+}
+
+unsafe fn open(hwnd: HWND) -> bool {
+    for attempt in 0..OPEN_ATTEMPTS {
+        if attempt > 0 {
+            thread::sleep(Duration::from_millis(2));
+        }
+
+        if OpenClipboard(hwnd) != 0 {
+            return true;
+        }
+    }
+
+    false
+}
+
+/// Must be called while the clipboard is open.
+unsafe fn read_global(id: UINT, format: &ClipboardFormat) -> Option<Vec<u8>> {
+    let handle = GetClipboardData(id);
+    if handle.is_null() {
+        return None;
+    }
+
+    let data = GlobalLock(handle) as *const u8;
+    if data.is_null() {
+        return None;
+    }
+
+    // The size may be rounded up, which custom formats have to account for themselves
+    let size = GlobalSize(handle);
+    let data = match format {
+        ClipboardFormat::Text => {
+            // The text is null terminated, but the terminator may be missing if the data was put
+            // on the clipboard by a misbehaving application
+            let text = std::slice::from_raw_parts(data as *const u16, size / 2);
+            let len = text.iter().position(|&c| c == 0).unwrap_or(text.len());
+            String::from_utf16_lossy(&text[..len]).into_bytes()
+        }
+        ClipboardFormat::Custom(_) => std::slice::from_raw_parts(data, size).to_vec(),
+    };
+
+    GlobalUnlock(handle);
+
+    Some(data)
+}
+
+/// Must be called while the clipboard is open.
+unsafe fn write_global(id: UINT, format: &ClipboardFormat, data: &[u8]) {
+    let text: Vec<u16>;
+    let bytes = match format {
+        ClipboardFormat::Text => {
+            let data = String::from_utf8_lossy(data);
+            text = OsStr::new(data.as_ref()).encode_wide().chain(Some(0)).collect();
+            std::slice::from_raw_parts(text.as_ptr() as *const u8, text.len() * 2)
+        }
+        ClipboardFormat::Custom(_) => data,
+    };
+
+    let handle = GlobalAlloc(GMEM_MOVEABLE, bytes.len());
+    if handle.is_null() {
+        return;
+    }
+
+    let buffer = GlobalLock(handle) as *mut u8;
+    if buffer.is_null() {
+        GlobalFree(handle);
+        return;
+    }
+    copy_nonoverlapping(bytes.as_ptr(), buffer, bytes.len());
+    GlobalUnlock(handle);
+
+    // The clipboard owns the memory once it has been set
+    if SetClipboardData(id, handle).is_null() {
+        GlobalFree(handle);
+    }
+}
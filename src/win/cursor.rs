@@ -7,23 +7,28 @@ use winapi::{
     },
 };
 
+#[allow(deprecated)]
 pub fn cursor_to_lpcwstr(cursor: MouseCursor) -> LPCWSTR {
     match cursor {
         MouseCursor::Default => IDC_ARROW,
         MouseCursor::Hand => IDC_HAND,
+        // Windows doesn't have open and closed hand cursors
+        MouseCursor::Grab => IDC_HAND,
         MouseCursor::HandGrabbing => IDC_SIZEALL,
         MouseCursor::Help => IDC_HELP,
+        MouseCursor::ContextMenu => IDC_ARROW,
         // an empty LPCWSTR results in the cursor being hidden
         MouseCursor::Hidden => std::ptr::null(),
 
         MouseCursor::Text => IDC_IBEAM,
         MouseCursor::VerticalText => IDC_IBEAM,
 
-        MouseCursor::Working => IDC_WAIT,
-        MouseCursor::PtrWorking => IDC_APPSTARTING,
+        MouseCursor::Wait | MouseCursor::Working => IDC_WAIT,
+        MouseCursor::Progress | MouseCursor::PtrWorking => IDC_APPSTARTING,
 
         MouseCursor::NotAllowed => IDC_NO,
         MouseCursor::PtrNotAllowed => IDC_NO,
+        MouseCursor::NoDrop => IDC_NO,
 
         MouseCursor::ZoomIn => IDC_ARROW,
         MouseCursor::ZoomOut => IDC_ARROW,
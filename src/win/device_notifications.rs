@@ -0,0 +1,114 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::mem::size_of;
+use std::ptr::null_mut;
+
+use winapi::shared::guiddef::{IsEqualGUID, GUID};
+use winapi::shared::minwindef::{DWORD, LPARAM, WPARAM};
+use winapi::shared::windef::HWND;
+use winapi::um::dbt::{
+    DBT_DEVICEARRIVAL, DBT_DEVICEREMOVECOMPLETE, DBT_DEVTYP_DEVICEINTERFACE,
+    DEV_BROADCAST_DEVICEINTERFACE_W, DEV_BROADCAST_HDR,
+};
+use winapi::um::winuser::{
+    RegisterDeviceNotificationW, UnregisterDeviceNotification, DEVICE_NOTIFY_WINDOW_HANDLE,
+    HDEVNOTIFY,
+};
+
+use crate::{DeviceEvent, DeviceKind, PointerType};
+
+/// `GUID_DEVINTERFACE_MOUSE`, used for mice, touchpads and other HID pointing devices.
+const GUID_DEVINTERFACE_MOUSE: GUID = GUID {
+    Data1: 0x378de44c,
+    Data2: 0x56ef,
+    Data3: 0x11d1,
+    Data4: [0xbc, 0x8c, 0x00, 0xa0, 0xc9, 0x14, 0x05, 0xdd],
+};
+
+/// `GUID_DEVINTERFACE_KEYBOARD`
+const GUID_DEVINTERFACE_KEYBOARD: GUID = GUID {
+    Data1: 0x884b96c3,
+    Data2: 0x56ef,
+    Data3: 0x11d1,
+    Data4: [0xbc, 0x8c, 0x00, 0xa0, 0xc9, 0x14, 0x05, 0xdd],
+};
+
+/// Registers a window for `WM_DEVICECHANGE` notifications about keyboards and pointing devices
+/// being plugged in or unplugged. Without this, windows only receive the generic
+/// `DBT_DEVNODES_CHANGED` notification, and only if they're top level windows.
+pub(super) struct DeviceNotifications {
+    handles: [HDEVNOTIFY; 2],
+}
+
+impl DeviceNotifications {
+    pub unsafe fn register(hwnd: HWND) -> Self {
+        let register = |class_guid: GUID| {
+            let mut filter: DEV_BROADCAST_DEVICEINTERFACE_W = std::mem::zeroed();
+            filter.dbcc_size = size_of::<DEV_BROADCAST_DEVICEINTERFACE_W>() as DWORD;
+            filter.dbcc_devicetype = DBT_DEVTYP_DEVICEINTERFACE;
+            filter.dbcc_classguid = class_guid;
+
+            RegisterDeviceNotificationW(
+                hwnd as *mut _,
+                &mut filter as *mut DEV_BROADCAST_DEVICEINTERFACE_W as *mut _,
+                DEVICE_NOTIFY_WINDOW_HANDLE,
+            )
+        };
+
+        Self { handles: [register(GUID_DEVINTERFACE_MOUSE), register(GUID_DEVINTERFACE_KEYBOARD)] }
+    }
+}
+
+impl Drop for DeviceNotifications {
+    fn drop(&mut self) {
+        for handle in self.handles.iter_mut() {
+            if !handle.is_null() {
+                unsafe { UnregisterDeviceNotification(*handle) };
+                *handle = null_mut();
+            }
+        }
+    }
+}
+
+/// Translate a `WM_DEVICECHANGE` message into a [`DeviceEvent`]. Returns `None` for devices we
+/// didn't register for and for all other kinds of device changes.
+pub(super) unsafe fn translate_device_change(
+    wparam: WPARAM, lparam: LPARAM,
+) -> Option<DeviceEvent> {
+    let added = match wparam {
+        DBT_DEVICEARRIVAL => true,
+        DBT_DEVICEREMOVECOMPLETE => false,
+        _ => return None,
+    };
+
+    let header = lparam as *const DEV_BROADCAST_HDR;
+    if header.is_null() || (*header).dbch_devicetype != DBT_DEVTYP_DEVICEINTERFACE {
+        return None;
+    }
+
+    let interface = &*(lparam as *const DEV_BROADCAST_DEVICEINTERFACE_W);
+    let kind = if IsEqualGUID(&interface.dbcc_classguid, &GUID_DEVINTERFACE_MOUSE) {
+        // The interface class doesn't tell touchpads and tablets apart from mice
+        DeviceKind::Pointer(PointerType::Unknown)
+    } else if IsEqualGUID(&interface.dbcc_classguid, &GUID_DEVINTERFACE_KEYBOARD) {
+        DeviceKind::Keyboard
+    } else {
+        return None;
+    };
+
+    // The notification only contains the device's interface path, which uniquely identifies the
+    // device for as long as it's connected. The path's case is not guaranteed to be consistent
+    // between notifications.
+    let name_ptr = interface.dbcc_name.as_ptr();
+    let mut name_len = 0;
+    while *name_ptr.add(name_len) != 0 {
+        name_len += 1;
+    }
+    let name = String::from_utf16_lossy(std::slice::from_raw_parts(name_ptr, name_len));
+
+    let mut hasher = DefaultHasher::new();
+    name.to_lowercase().hash(&mut hasher);
+    let id = hasher.finish();
+
+    Some(if added { DeviceEvent::Added { id, kind } } else { DeviceEvent::Removed { id, kind } })
+}
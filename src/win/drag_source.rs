@@ -0,0 +1,263 @@
+use std::ffi::c_void;
+use std::mem::size_of;
+use std::os::windows::ffi::OsStrExt;
+use std::ptr::{copy_nonoverlapping, null, null_mut};
+
+use winapi::shared::guiddef::{IsEqualIID, GUID, REFIID};
+use winapi::shared::minwindef::{BOOL, DWORD, TRUE, UINT};
+use winapi::shared::ntdef::{HRESULT, ULONG};
+use winapi::shared::windef::POINT;
+use winapi::shared::winerror::{
+    DRAGDROP_S_CANCEL, DRAGDROP_S_DROP, DRAGDROP_S_USEDEFAULTCURSORS, E_NOINTERFACE, S_OK,
+};
+use winapi::shared::wtypes::DVASPECT_CONTENT;
+use winapi::um::objidl::{IDataObject, STGMEDIUM_u, FORMATETC, STGMEDIUM, TYMED_HGLOBAL};
+use winapi::um::oleidl::{
+    DROPEFFECT_COPY, DROPEFFECT_LINK, DROPEFFECT_MOVE, DROPEFFECT_NONE, DROPEFFECT_SCROLL,
+};
+use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+use winapi::um::winbase::{GlobalAlloc, GlobalFree, GlobalLock, GlobalUnlock, GMEM_MOVEABLE};
+use winapi::um::winuser::{CF_HDROP, MK_LBUTTON, MK_MBUTTON, MK_RBUTTON, MK_XBUTTON1, MK_XBUTTON2};
+use winapi::Interface;
+
+use crate::{ClipboardFormat, DragData, DropEffect};
+
+use super::clipboard;
+
+// Not defined by winapi
+extern "system" {
+    fn DoDragDrop(
+        pDataObj: *mut IDataObject, pDropSource: *mut IDropSource, dwOKEffects: DWORD,
+        pdwEffect: *mut DWORD,
+    ) -> HRESULT;
+    fn SHCreateDataObject(
+        pidlFolder: *const c_void, cidl: UINT, apidl: *const c_void, pdtInner: *mut IDataObject,
+        riid: REFIID, ppv: *mut *mut c_void,
+    ) -> HRESULT;
+}
+
+/// `IDropSource` isn't defined by winapi either. Its IID is `00000121-0000-0000-C000-000000000046`.
+const IID_IDROP_SOURCE: GUID = GUID {
+    Data1: 0x00000121,
+    Data2: 0x0000,
+    Data3: 0x0000,
+    Data4: [0xc0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x46],
+};
+
+#[allow(non_snake_case)] // stick to the original names
+#[repr(C)]
+struct IDropSourceVtbl {
+    parent: IUnknownVtbl,
+    QueryContinueDrag: unsafe extern "system" fn(
+        this: *mut IDropSource,
+        fEscapePressed: BOOL,
+        grfKeyState: DWORD,
+    ) -> HRESULT,
+    GiveFeedback: unsafe extern "system" fn(this: *mut IDropSource, dwEffect: DWORD) -> HRESULT,
+}
+
+#[allow(non_snake_case)]
+#[repr(C)]
+struct IDropSource {
+    lpVtbl: *const IDropSourceVtbl,
+}
+
+const DROP_SOURCE_VTBL: IDropSourceVtbl = IDropSourceVtbl {
+    parent: IUnknownVtbl {
+        QueryInterface: DropSource::query_interface,
+        AddRef: DropSource::add_ref,
+        Release: DropSource::release,
+    },
+    QueryContinueDrag: DropSource::query_continue_drag,
+    GiveFeedback: DropSource::give_feedback,
+};
+
+/// The `DROPFILES` header of `CF_HDROP` data, which is followed by the null terminated paths.
+#[allow(non_camel_case_types, non_snake_case, clippy::upper_case_acronyms)]
+#[repr(C)]
+struct DROPFILES {
+    pFiles: DWORD,
+    pt: POINT,
+    fNC: BOOL,
+    fWide: BOOL,
+}
+
+const MOUSE_BUTTONS: DWORD =
+    (MK_LBUTTON | MK_RBUTTON | MK_MBUTTON | MK_XBUTTON1 | MK_XBUTTON2) as DWORD;
+
+/// Only lives on the stack for the duration of [`drag()`], since `DoDragDrop()` doesn't hold on
+/// to the drop source after it returns. That's why it isn't reference counted.
+#[repr(C)]
+struct DropSource {
+    base: IDropSource,
+}
+
+impl DropSource {
+    #[allow(non_snake_case)]
+    unsafe extern "system" fn query_interface(
+        this: *mut IUnknown, riid: REFIID, ppvObject: *mut *mut winapi::ctypes::c_void,
+    ) -> HRESULT {
+        if IsEqualIID(&*riid, &IUnknown::uuidof()) || IsEqualIID(&*riid, &IID_IDROP_SOURCE) {
+            *ppvObject = this as *mut winapi::ctypes::c_void;
+            return S_OK;
+        }
+
+        *ppvObject = null_mut();
+        E_NOINTERFACE
+    }
+
+    unsafe extern "system" fn add_ref(_this: *mut IUnknown) -> ULONG {
+        1
+    }
+
+    unsafe extern "system" fn release(_this: *mut IUnknown) -> ULONG {
+        1
+    }
+
+    #[allow(non_snake_case)]
+    unsafe extern "system" fn query_continue_drag(
+        _this: *mut IDropSource, fEscapePressed: BOOL, grfKeyState: DWORD,
+    ) -> HRESULT {
+        if fEscapePressed != 0 {
+            DRAGDROP_S_CANCEL
+        } else if grfKeyState & MOUSE_BUTTONS == 0 {
+            DRAGDROP_S_DROP
+        } else {
+            S_OK
+        }
+    }
+
+    #[allow(non_snake_case)]
+    unsafe extern "system" fn give_feedback(_this: *mut IDropSource, _dwEffect: DWORD) -> HRESULT {
+        DRAGDROP_S_USEDEFAULTCURSORS
+    }
+}
+
+/// Drag the data out of the window, see [`crate::Window::start_drag()`]. This runs a modal loop
+/// until the data has been dropped or the drag has been cancelled, and returns the effect the drop
+/// target chose.
+pub(super) fn drag(data: &DragData) -> Option<DropEffect> {
+    unsafe {
+        let mut data_object: *mut IDataObject = null_mut();
+        let hresult = SHCreateDataObject(
+            null(),
+            0,
+            null(),
+            null_mut(),
+            &IDataObject::uuidof(),
+            &mut data_object as *mut *mut IDataObject as *mut *mut c_void,
+        );
+        if hresult != S_OK || data_object.is_null() {
+            return None;
+        }
+
+        let effect = if set_data(&*data_object, data) {
+            let mut drop_source = DropSource { base: IDropSource { lpVtbl: &DROP_SOURCE_VTBL } };
+            let mut effect = DROPEFFECT_NONE;
+            let hresult =
+                DoDragDrop(data_object, &mut drop_source.base, DROPEFFECT_COPY, &mut effect);
+
+            if hresult == DRAGDROP_S_DROP {
+                drop_effect(effect)
+            } else {
+                None
+            }
+        } else {
+            None
+        };
+
+        (*data_object).Release();
+
+        effect
+    }
+}
+
+unsafe fn set_data(data_object: &IDataObject, data: &DragData) -> bool {
+    let (id, bytes) = match data {
+        DragData::Files(paths) => (CF_HDROP, hdrop(paths)),
+        DragData::Custom { format, data } => {
+            match clipboard::format_id(&ClipboardFormat::Custom(format.clone())) {
+                Some(id) => (id, data.clone()),
+                None => return false,
+            }
+        }
+    };
+
+    let handle = GlobalAlloc(GMEM_MOVEABLE, bytes.len());
+    if handle.is_null() {
+        return false;
+    }
+
+    let buffer = GlobalLock(handle) as *mut u8;
+    if buffer.is_null() {
+        GlobalFree(handle);
+        return false;
+    }
+    copy_nonoverlapping(bytes.as_ptr(), buffer, bytes.len());
+    GlobalUnlock(handle);
+
+    let format = FORMATETC {
+        cfFormat: id as u16,
+        ptd: null_mut(),
+        dwAspect: DVASPECT_CONTENT,
+        lindex: -1,
+        tymed: TYMED_HGLOBAL,
+    };
+    // winapi declares the medium's union as a pointer, which has the same layout as the handle
+    let medium = STGMEDIUM {
+        tymed: TYMED_HGLOBAL,
+        u: handle as *mut STGMEDIUM_u,
+        pUnkForRelease: null_mut(),
+    };
+
+    // winapi has a bug where the medium parameter has the type `*const FORMATETC`. The data object
+    // owns the memory once it has been set.
+    let medium = &medium as *const STGMEDIUM as *const FORMATETC;
+    if data_object.SetData(&format, medium, TRUE) != S_OK {
+        GlobalFree(handle);
+        return false;
+    }
+
+    true
+}
+
+/// `CF_HDROP` data with wide paths.
+fn hdrop(paths: &[std::path::PathBuf]) -> Vec<u8> {
+    let mut wide: Vec<u16> = Vec::new();
+    for path in paths {
+        wide.extend(path.as_os_str().encode_wide());
+        wide.push(0);
+    }
+    wide.push(0);
+
+    let header = DROPFILES {
+        pFiles: size_of::<DROPFILES>() as DWORD,
+        pt: POINT { x: 0, y: 0 },
+        fNC: 0,
+        fWide: TRUE,
+    };
+
+    let mut bytes = Vec::with_capacity(size_of::<DROPFILES>() + wide.len() * 2);
+    bytes.extend_from_slice(unsafe {
+        std::slice::from_raw_parts(&header as *const DROPFILES as *const u8, size_of::<DROPFILES>())
+    });
+    for unit in wide {
+        bytes.extend_from_slice(&unit.to_ne_bytes());
+    }
+
+    bytes
+}
+
+fn drop_effect(effect: DWORD) -> Option<DropEffect> {
+    if effect & DROPEFFECT_MOVE != 0 {
+        Some(DropEffect::Move)
+    } else if effect & DROPEFFECT_COPY != 0 {
+        Some(DropEffect::Copy)
+    } else if effect & DROPEFFECT_LINK != 0 {
+        Some(DropEffect::Link)
+    } else if effect & DROPEFFECT_SCROLL != 0 {
+        Some(DropEffect::Scroll)
+    } else {
+        None
+    }
+}
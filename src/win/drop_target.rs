@@ -1,8 +1,9 @@
-use std::ffi::OsString;
+use std::ffi::{OsStr, OsString};
 use std::mem::transmute;
-use std::os::windows::prelude::OsStringExt;
+use std::os::windows::prelude::{OsStrExt, OsStringExt};
 use std::ptr::null_mut;
 use std::rc::{Rc, Weak};
+use std::sync::Arc;
 
 use winapi::shared::guiddef::{IsEqualIID, REFIID};
 use winapi::shared::minwindef::{DWORD, WPARAM};
@@ -17,13 +18,19 @@ use winapi::um::oleidl::{
 };
 use winapi::um::shellapi::{DragQueryFileW, HDROP};
 use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
-use winapi::um::winuser::{ScreenToClient, CF_HDROP};
+use winapi::um::winbase::{GlobalLock, GlobalSize, GlobalUnlock};
+use winapi::um::winuser::{RegisterClipboardFormatW, ScreenToClient, CF_HDROP};
 use winapi::Interface;
 
 use crate::{DropData, DropEffect, Event, EventStatus, MouseEvent, PhyPoint, Point};
 
 use super::WindowState;
 
+// Not defined by winapi
+extern "system" {
+    fn ReleaseStgMedium(medium: *mut STGMEDIUM);
+}
+
 // These function pointers have to be stored in a (const) variable before they can be transmuted
 // Transmuting is needed because winapi has a bug where the pt parameter has an incorrect
 // type `*const POINTL`
@@ -74,18 +81,35 @@ pub(super) struct DropTarget {
     // These are cached since DragOver and DragLeave callbacks don't provide them,
     // and handling drag move events gets awkward on the client end otherwise
     drag_position: Point,
-    drop_data: DropData,
+    drop_data: Arc<DropData>,
+
+    /// The formats from [`WindowOpenOptions::drop_formats`][crate::WindowOpenOptions::drop_formats]
+    /// along with their registered clipboard format ids.
+    formats: Vec<(String, u16)>,
 }
 
 impl DropTarget {
-    pub(super) fn new(window_state: Weak<WindowState>) -> Self {
+    pub(super) fn new(window_state: Weak<WindowState>, drop_formats: &[String]) -> Self {
+        let formats = drop_formats
+            .iter()
+            .filter_map(|format| {
+                let name: Vec<u16> = OsStr::new(format).encode_wide().chain(Some(0)).collect();
+                match unsafe { RegisterClipboardFormatW(name.as_ptr()) } {
+                    0 => None,
+                    id => Some((format.clone(), id as u16)),
+                }
+            })
+            .collect();
+
         Self {
             base: IDropTarget { lpVtbl: &DROP_TARGET_VTBL },
 
             window_state,
 
             drag_position: Point::new(0.0, 0.0),
-            drop_data: DropData::None,
+            drop_data: DropData::none(),
+
+            formats,
         }
     }
 
@@ -94,6 +118,13 @@ impl DropTarget {
         let Some(window_state) = self.window_state.upgrade() else {
             return;
         };
+        // The window is closed when the next message is handled, see `Window::close()`
+        if window_state.is_closing() {
+            if let Some(pdwEffect) = pdwEffect {
+                unsafe { *pdwEffect = DROPEFFECT_NONE };
+            }
+            return;
+        }
 
         unsafe {
             let mut window = crate::Window::new(window_state.create_window());
@@ -114,6 +145,13 @@ impl DropTarget {
         }
     }
 
+    /// Resume sending hover events once the drag has left the window or has been dropped.
+    fn drag_ended(&self) {
+        if let Some(window_state) = self.window_state.upgrade() {
+            window_state.hover.drag_ended();
+        }
+    }
+
     fn parse_coordinates(&mut self, pt: POINTL) {
         let Some(window_state) = self.window_state.upgrade() else {
             return;
@@ -127,6 +165,13 @@ impl DropTarget {
     }
 
     fn parse_drop_data(&mut self, data_object: &IDataObject) {
+        for (name, id) in &self.formats {
+            if let Some(data) = unsafe { get_global_data(data_object, *id) } {
+                self.drop_data = Arc::new(DropData::Custom { format: name.clone(), data });
+                return;
+            }
+        }
+
         let format = FORMATETC {
             cfFormat: CF_HDROP as u16,
             ptd: null_mut(),
@@ -140,7 +185,7 @@ impl DropTarget {
         unsafe {
             let hresult = data_object.GetData(&format, &mut medium);
             if hresult != S_OK {
-                self.drop_data = DropData::None;
+                self.drop_data = DropData::none();
                 return;
             }
 
@@ -148,7 +193,7 @@ impl DropTarget {
 
             let item_count = DragQueryFileW(hdrop, 0xFFFFFFFF, null_mut(), 0);
             if item_count == 0 {
-                self.drop_data = DropData::None;
+                self.drop_data = DropData::none();
                 return;
             }
 
@@ -164,7 +209,7 @@ impl DropTarget {
                 paths.push(OsString::from_wide(&buffer[..characters as usize]).into())
             }
 
-            self.drop_data = DropData::Files(paths);
+            self.drop_data = Arc::new(DropData::Files(paths));
         }
     }
 
@@ -217,6 +262,8 @@ impl DropTarget {
         drop_target.parse_coordinates(pt);
         drop_target.parse_drop_data(&*pDataObj);
 
+        window_state.hover.drag_entered(|event| drop_target.on_event(None, event));
+
         let event = MouseEvent::DragEntered {
             position: drop_target.drag_position,
             modifiers,
@@ -254,6 +301,7 @@ impl DropTarget {
     unsafe extern "system" fn drag_leave(this: *mut IDropTarget) -> HRESULT {
         let drop_target = &mut *(this as *mut DropTarget);
         drop_target.on_event(None, MouseEvent::DragLeft);
+        drop_target.drag_ended();
         S_OK
     }
 
@@ -280,6 +328,37 @@ impl DropTarget {
         };
 
         drop_target.on_event(Some(pdwEffect), event);
+        drop_target.drag_ended();
         S_OK
     }
 }
+
+/// Copy the contents of a format that the data object provides through global memory.
+unsafe fn get_global_data(data_object: &IDataObject, format: u16) -> Option<Vec<u8>> {
+    let format = FORMATETC {
+        cfFormat: format,
+        ptd: null_mut(),
+        dwAspect: DVASPECT_CONTENT,
+        lindex: -1,
+        tymed: TYMED_HGLOBAL,
+    };
+
+    let mut medium = STGMEDIUM { tymed: 0, u: null_mut(), pUnkForRelease: null_mut() };
+    if data_object.GetData(&format, &mut medium) != S_OK {
+        return None;
+    }
+
+    let hglobal = *(*medium.u).hGlobal();
+    let ptr = GlobalLock(hglobal) as *const u8;
+    let data = if ptr.is_null() {
+        None
+    } else {
+        let data = std::slice::from_raw_parts(ptr, GlobalSize(hglobal)).to_vec();
+        GlobalUnlock(hglobal);
+        Some(data)
+    };
+
+    ReleaseStgMedium(&mut medium);
+
+    data
+}
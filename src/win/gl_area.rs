@@ -0,0 +1,150 @@
+use std::cell::Cell;
+use std::ffi::{c_void, OsStr};
+use std::os::windows::ffi::OsStrExt;
+use std::ptr::null_mut;
+
+use raw_window_handle::{RawWindowHandle, Win32WindowHandle};
+use winapi::shared::minwindef::{ATOM, LPARAM, LRESULT, UINT, WPARAM};
+use winapi::shared::windef::HWND;
+use winapi::um::winuser::{
+    CreateWindowExW, DefWindowProcW, DestroyWindow, GetWindowLongPtrW, IsWindow, RegisterClassW,
+    SetWindowLongPtrW, SetWindowPos, UnregisterClassW, CS_OWNDC, GWL_STYLE, HTTRANSPARENT,
+    SWP_NOACTIVATE, SWP_NOZORDER, WM_ERASEBKGND, WM_NCHITTEST, WNDCLASSW, WS_CHILD,
+    WS_CLIPCHILDREN, WS_CLIPSIBLINGS, WS_VISIBLE,
+};
+
+use super::window_class::module_instance;
+use crate::gl::{GlArea, GlConfig, GlContext, GlError};
+use crate::{PhyPoint, PhySize};
+
+thread_local! {
+    /// The window class used by the GL areas on this thread, and the number of areas using it. The
+    /// class is unregistered along with the last area, so it never outlives the module.
+    static AREA_CLASS: Cell<(ATOM, usize)> = const { Cell::new((0, 0)) };
+}
+
+/// The child window a [`GlArea`] renders to.
+pub(crate) struct GlAreaSurface {
+    hwnd: HWND,
+}
+
+impl GlAreaSurface {
+    pub fn set_bounds(&self, position: PhyPoint, size: PhySize) {
+        unsafe {
+            SetWindowPos(
+                self.hwnd,
+                null_mut(),
+                position.x,
+                position.y,
+                size.width as i32,
+                size.height as i32,
+                SWP_NOZORDER | SWP_NOACTIVATE,
+            );
+        }
+    }
+}
+
+impl Drop for GlAreaSurface {
+    fn drop(&mut self) {
+        unsafe {
+            // The child window is already gone if the area outlived its parent
+            if IsWindow(self.hwnd) != 0 {
+                DestroyWindow(self.hwnd);
+            }
+
+            release_class();
+        }
+    }
+}
+
+/// Create a child window of `parent` and a context rendering to that window.
+pub(super) unsafe fn create_gl_area(
+    parent: HWND, position: PhyPoint, size: PhySize, config: GlConfig,
+) -> Result<GlArea, GlError> {
+    let class = acquire_class();
+    if class == 0 {
+        return Err(GlError::CreationFailed(()));
+    }
+
+    let hwnd = CreateWindowExW(
+        0,
+        class as _,
+        [0].as_ptr(),
+        WS_CHILD | WS_VISIBLE | WS_CLIPSIBLINGS,
+        position.x,
+        position.y,
+        size.width as i32,
+        size.height as i32,
+        parent,
+        null_mut(),
+        module_instance(),
+        null_mut(),
+    );
+    if hwnd.is_null() {
+        release_class();
+        return Err(GlError::CreationFailed(()));
+    }
+
+    let surface = GlAreaSurface { hwnd };
+
+    // Otherwise the parent window's own rendering would draw over the area
+    let style = GetWindowLongPtrW(parent, GWL_STYLE);
+    SetWindowLongPtrW(parent, GWL_STYLE, style | WS_CLIPCHILDREN as isize);
+
+    let mut handle = Win32WindowHandle::empty();
+    handle.hwnd = hwnd as *mut c_void;
+    let context = GlContext::create(&RawWindowHandle::Win32(handle), config)?;
+
+    Ok(GlArea::new(context, surface))
+}
+
+unsafe extern "system" fn wnd_proc(
+    hwnd: HWND, msg: UINT, wparam: WPARAM, lparam: LPARAM,
+) -> LRESULT {
+    match msg {
+        // Let the parent window handle all mouse input over the area
+        WM_NCHITTEST => HTTRANSPARENT as LRESULT,
+        // The area is only ever drawn to with OpenGL
+        WM_ERASEBKGND => 1,
+        _ => DefWindowProcW(hwnd, msg, wparam, lparam),
+    }
+}
+
+unsafe fn acquire_class() -> ATOM {
+    AREA_CLASS.with(|class| {
+        let (mut atom, count) = class.get();
+        if count == 0 {
+            let class_name_str = format!("Baseview-GlArea-{}", uuid::Uuid::new_v4().to_simple());
+            let mut class_name: Vec<u16> = OsStr::new(&class_name_str).encode_wide().collect();
+            class_name.push(0);
+
+            let wnd_class = WNDCLASSW {
+                style: CS_OWNDC,
+                lpfnWndProc: Some(wnd_proc),
+                hInstance: module_instance(),
+                lpszClassName: class_name.as_ptr(),
+                ..std::mem::zeroed()
+            };
+
+            atom = RegisterClassW(&wnd_class);
+            if atom == 0 {
+                return 0;
+            }
+        }
+
+        class.set((atom, count + 1));
+        atom
+    })
+}
+
+unsafe fn release_class() {
+    AREA_CLASS.with(|class| {
+        let (atom, count) = class.get();
+        if count == 1 {
+            UnregisterClassW(atom as _, module_instance());
+            class.set((0, 0));
+        } else {
+            class.set((atom, count.saturating_sub(1)));
+        }
+    })
+}
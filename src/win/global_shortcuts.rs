@@ -0,0 +1,105 @@
+use std::cell::{Cell, RefCell};
+
+use keyboard_types::Modifiers;
+use winapi::shared::minwindef::UINT;
+use winapi::shared::windef::HWND;
+use winapi::um::winuser::{
+    RegisterHotKey, UnregisterHotKey, MOD_ALT, MOD_CONTROL, MOD_NOREPEAT, MOD_SHIFT, MOD_WIN,
+};
+
+use super::keyboard::code_to_vk;
+use crate::{GlobalShortcutError, KeyCombo};
+
+/// Applications may use hot key ids up to this value, the rest is reserved for shared DLLs.
+const MAX_HOT_KEY_ID: i32 = 0xBFFF;
+
+struct HotKey {
+    hot_key_id: i32,
+    id: u32,
+    vk: UINT,
+    modifiers: UINT,
+}
+
+/// The hot keys registered for a window's global shortcuts. Windows sends `WM_HOTKEY` with the hot
+/// key's id to the window when one of them is pressed.
+pub(super) struct GlobalShortcuts {
+    hwnd: HWND,
+    hot_keys: RefCell<Vec<HotKey>>,
+    next_hot_key_id: Cell<i32>,
+}
+
+impl GlobalShortcuts {
+    pub fn new(hwnd: HWND) -> Self {
+        Self { hwnd, hot_keys: RefCell::new(Vec::new()), next_hot_key_id: Cell::new(0) }
+    }
+
+    pub fn register(&self, combo: KeyCombo, id: u32) -> Result<(), GlobalShortcutError> {
+        let vk = combo.code().and_then(code_to_vk).ok_or(GlobalShortcutError::UnsupportedKey)?;
+        let modifiers = hot_key_modifiers(combo.modifiers);
+
+        let mut hot_keys = self.hot_keys.borrow_mut();
+        if let Some(hot_key) =
+            hot_keys.iter_mut().find(|hot_key| hot_key.vk == vk && hot_key.modifiers == modifiers)
+        {
+            hot_key.id = id;
+            return Ok(());
+        }
+
+        let hot_key_id = self.next_hot_key_id.get();
+        self.next_hot_key_id.set(if hot_key_id == MAX_HOT_KEY_ID { 0 } else { hot_key_id + 1 });
+
+        // Holding the keys down would otherwise send `WM_HOTKEY` for every key repeat
+        let registered =
+            unsafe { RegisterHotKey(self.hwnd, hot_key_id, modifiers | MOD_NOREPEAT as UINT, vk) };
+        if registered == 0 {
+            return Err(GlobalShortcutError::Unavailable);
+        }
+
+        hot_keys.push(HotKey { hot_key_id, id, vk, modifiers });
+
+        Ok(())
+    }
+
+    /// Remove the shortcuts registered with `id`.
+    pub fn unregister(&self, id: u32) {
+        self.hot_keys.borrow_mut().retain(|hot_key| {
+            if hot_key.id != id {
+                return true;
+            }
+
+            unsafe { UnregisterHotKey(self.hwnd, hot_key.hot_key_id) };
+            false
+        });
+    }
+
+    /// The id of the shortcut for the hot key id from a `WM_HOTKEY` message.
+    pub fn find(&self, hot_key_id: i32) -> Option<u32> {
+        self.hot_keys
+            .borrow()
+            .iter()
+            .find(|hot_key| hot_key.hot_key_id == hot_key_id)
+            .map(|hot_key| hot_key.id)
+    }
+}
+
+impl Drop for GlobalShortcuts {
+    fn drop(&mut self) {
+        for hot_key in self.hot_keys.get_mut().drain(..) {
+            unsafe { UnregisterHotKey(self.hwnd, hot_key.hot_key_id) };
+        }
+    }
+}
+
+fn hot_key_modifiers(modifiers: Modifiers) -> UINT {
+    let key_modifiers = [
+        (Modifiers::SHIFT, MOD_SHIFT),
+        (Modifiers::CONTROL, MOD_CONTROL),
+        (Modifiers::ALT, MOD_ALT),
+        (Modifiers::META, MOD_WIN),
+    ];
+
+    key_modifiers
+        .iter()
+        .filter(|(modifier, _)| modifiers.contains(*modifier))
+        .fold(0, |hot_key_modifiers, (_, flag)| hot_key_modifiers | *flag as UINT)
+}
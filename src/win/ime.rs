@@ -0,0 +1,140 @@
+use std::ptr::null_mut;
+
+use winapi::shared::minwindef::{BOOL, DWORD, LPVOID};
+use winapi::shared::ntdef::LONG;
+use winapi::shared::windef::{HWND, POINT, RECT};
+use winapi::um::imm::{
+    ImmGetContext, ImmReleaseContext, ImmSetCompositionWindow, CFS_EXCLUDE, CFS_RECT,
+    COMPOSITIONFORM, HIMC,
+};
+
+use crate::text::utf16_to_byte_offset;
+use crate::ImeEvent;
+
+// Not defined by winapi
+extern "system" {
+    fn ImmGetCompositionStringW(himc: HIMC, index: DWORD, buf: LPVOID, len: DWORD) -> LONG;
+    fn ImmSetCandidateWindow(himc: HIMC, candidate: *mut CANDIDATEFORM) -> BOOL;
+    fn ImmNotifyIME(himc: HIMC, action: DWORD, index: DWORD, value: DWORD) -> BOOL;
+}
+
+// Stick to the original names
+#[allow(non_camel_case_types, non_snake_case, clippy::upper_case_acronyms)]
+#[repr(C)]
+struct CANDIDATEFORM {
+    dwIndex: DWORD,
+    dwStyle: DWORD,
+    ptCurrentPos: POINT,
+    rcArea: RECT,
+}
+
+const GCS_COMPSTR: DWORD = 0x0008;
+const GCS_CURSORPOS: DWORD = 0x0080;
+const GCS_RESULTSTR: DWORD = 0x0800;
+const NI_COMPOSITIONSTR: DWORD = 0x0015;
+const CPS_CANCEL: DWORD = 0x0004;
+
+/// A window's input method context, which is released again when this is dropped.
+pub(super) struct ImeContext {
+    hwnd: HWND,
+    himc: HIMC,
+}
+
+impl ImeContext {
+    /// Returns `None` if the window doesn't have an input method context, for instance because the
+    /// current keyboard layout doesn't use an input method.
+    pub unsafe fn get(hwnd: HWND) -> Option<Self> {
+        let himc = ImmGetContext(hwnd);
+        if himc.is_null() {
+            None
+        } else {
+            Some(Self { hwnd, himc })
+        }
+    }
+
+    /// Place the composition and candidate windows next to `area`, which is in physical
+    /// coordinates relative to the window's client area.
+    pub fn set_cursor_area(&self, area: RECT) {
+        let mut composition_form = COMPOSITIONFORM {
+            dwStyle: CFS_RECT,
+            ptCurrentPos: POINT { x: area.left, y: area.top },
+            rcArea: area,
+        };
+        // The candidate window is kept from covering the area
+        let mut candidate_form = CANDIDATEFORM {
+            dwIndex: 0,
+            dwStyle: CFS_EXCLUDE,
+            ptCurrentPos: POINT { x: area.left, y: area.bottom },
+            rcArea: area,
+        };
+
+        unsafe {
+            ImmSetCompositionWindow(self.himc, &mut composition_form);
+            ImmSetCandidateWindow(self.himc, &mut candidate_form);
+        }
+    }
+
+    /// The events for a `WM_IME_COMPOSITION` message. The committed text is sent before the new
+    /// composition, since both can change in the same message.
+    pub fn composition_events(&self, flags: DWORD) -> Vec<ImeEvent> {
+        let mut events = Vec::new();
+        if flags & GCS_RESULTSTR != 0 {
+            if let Some(text) = self.string(GCS_RESULTSTR) {
+                events.push(ImeEvent::Commit(text));
+            }
+        }
+        if flags & GCS_COMPSTR != 0 {
+            if let Some(text) = self.string(GCS_COMPSTR) {
+                let cursor = if text.is_empty() {
+                    None
+                } else {
+                    let position = unsafe {
+                        ImmGetCompositionStringW(self.himc, GCS_CURSORPOS, null_mut(), 0)
+                    };
+                    (position >= 0).then(|| {
+                        let offset = utf16_to_byte_offset(&text, position as usize);
+                        (offset, offset)
+                    })
+                };
+                events.push(ImeEvent::Preedit { text, cursor });
+            }
+        }
+
+        events
+    }
+
+    /// Cancel the current composition without committing it.
+    pub fn cancel_composition(&self) {
+        unsafe { ImmNotifyIME(self.himc, NI_COMPOSITIONSTR, CPS_CANCEL, 0) };
+    }
+
+    fn string(&self, index: DWORD) -> Option<String> {
+        unsafe {
+            // The length is in bytes
+            let len = ImmGetCompositionStringW(self.himc, index, null_mut(), 0);
+            if len < 0 {
+                return None;
+            }
+
+            let mut buffer = vec![0u16; len as usize / 2];
+            let len = ImmGetCompositionStringW(
+                self.himc,
+                index,
+                buffer.as_mut_ptr() as LPVOID,
+                len as DWORD,
+            );
+            if len < 0 {
+                return None;
+            }
+            buffer.truncate(len as usize / 2);
+
+            Some(String::from_utf16_lossy(&buffer))
+        }
+    }
+}
+
+impl Drop for ImeContext {
+    fn drop(&mut self) {
+        unsafe { ImmReleaseContext(self.hwnd, self.himc) };
+    }
+}
@@ -0,0 +1,160 @@
+use std::cell::Cell;
+use std::mem::{size_of, zeroed};
+use std::ptr::{null, null_mut};
+
+use winapi::ctypes::c_void;
+use winapi::shared::minwindef::{LPARAM, UINT};
+use winapi::shared::windef::{HWND, POINT, RECT};
+use winapi::um::winuser::{
+    ClipCursor, GetClipCursor, GetCursorPos, GetRawInputData, GetRegisteredRawInputDevices,
+    GetSystemMetrics, RegisterRawInputDevices, ScreenToClient, HRAWINPUT, MOUSE_MOVE_ABSOLUTE,
+    RAWINPUT, RAWINPUTDEVICE, RAWINPUTHEADER, RIDEV_INPUTSINK, RIDEV_REMOVE, RID_INPUT,
+    RIM_TYPEMOUSE, SM_CXVIRTUALSCREEN, SM_CYVIRTUALSCREEN, SM_XVIRTUALSCREEN, SM_YVIRTUALSCREEN,
+};
+
+use crate::{PhyPoint, Point, WindowInfo};
+
+/// `HID_USAGE_PAGE_GENERIC` and `HID_USAGE_GENERIC_MOUSE`
+const MOUSE_USAGE_PAGE: u16 = 0x01;
+const MOUSE_USAGE: u16 = 0x02;
+
+/// An infinite drag started with [`crate::Window::begin_infinite_drag()`]. The cursor is frozen
+/// in place by clipping it to a single pixel, and the mouse's movement is read from raw input
+/// instead. Dropping this ends the drag.
+pub(super) struct InfiniteDrag {
+    /// The virtual cursor position in logical coordinates.
+    position: Cell<Point>,
+    /// The cursor clip rectangle before the drag, if the cursor was clipped.
+    previous_clip: Option<RECT>,
+    /// Only one window per process can receive raw mouse input. If the host registered for it,
+    /// then its registration is restored after the drag.
+    previous_registration: Option<RAWINPUTDEVICE>,
+}
+
+impl InfiniteDrag {
+    pub unsafe fn begin(hwnd: HWND, window_info: &WindowInfo) -> Self {
+        let mut cursor_position = POINT { x: 0, y: 0 };
+        GetCursorPos(&mut cursor_position);
+
+        let mut client_position = cursor_position;
+        ScreenToClient(hwnd, &mut client_position);
+        let position = PhyPoint::new(client_position.x, client_position.y).to_logical(window_info);
+
+        let previous_clip = cursor_clip();
+        let clip = RECT {
+            left: cursor_position.x,
+            top: cursor_position.y,
+            right: cursor_position.x + 1,
+            bottom: cursor_position.y + 1,
+        };
+        ClipCursor(&clip);
+
+        let previous_registration = registered_mouse_input();
+        // `RIDEV_INPUTSINK` is needed because plugin windows are often not in the foreground
+        let device = RAWINPUTDEVICE {
+            usUsagePage: MOUSE_USAGE_PAGE,
+            usUsage: MOUSE_USAGE,
+            dwFlags: RIDEV_INPUTSINK,
+            hwndTarget: hwnd,
+        };
+        RegisterRawInputDevices(&device, 1, size_of::<RAWINPUTDEVICE>() as UINT);
+
+        Self { position: Cell::new(position), previous_clip, previous_registration }
+    }
+
+    /// Handle a `WM_INPUT` message. Returns the new virtual cursor position if the mouse moved.
+    /// Absolute pointing devices like pens don't report relative movement, so they don't move the
+    /// virtual cursor.
+    pub unsafe fn handle_raw_input(
+        &self, lparam: LPARAM, window_info: &WindowInfo,
+    ) -> Option<Point> {
+        let mut raw_input: RAWINPUT = zeroed();
+        let mut size = size_of::<RAWINPUT>() as UINT;
+        let result = GetRawInputData(
+            lparam as HRAWINPUT,
+            RID_INPUT,
+            &mut raw_input as *mut RAWINPUT as *mut c_void,
+            &mut size,
+            size_of::<RAWINPUTHEADER>() as UINT,
+        );
+        if result == UINT::MAX || raw_input.header.dwType != RIM_TYPEMOUSE {
+            return None;
+        }
+
+        let mouse = raw_input.data.mouse();
+        if mouse.usFlags & MOUSE_MOVE_ABSOLUTE != 0 || (mouse.lLastX == 0 && mouse.lLastY == 0) {
+            return None;
+        }
+
+        // The relative movement is roughly in physical pixels
+        let position = self.position.get();
+        let position = Point::new(
+            position.x + f64::from(mouse.lLastX) * window_info.scale_recip(),
+            position.y + f64::from(mouse.lLastY) * window_info.scale_recip(),
+        );
+        self.position.set(position);
+
+        Some(position)
+    }
+}
+
+impl Drop for InfiniteDrag {
+    fn drop(&mut self) {
+        unsafe {
+            match &self.previous_clip {
+                Some(previous_clip) => ClipCursor(previous_clip),
+                None => ClipCursor(null()),
+            };
+
+            let device = self.previous_registration.unwrap_or(RAWINPUTDEVICE {
+                usUsagePage: MOUSE_USAGE_PAGE,
+                usUsage: MOUSE_USAGE,
+                dwFlags: RIDEV_REMOVE,
+                hwndTarget: null_mut(),
+            });
+            RegisterRawInputDevices(&device, 1, size_of::<RAWINPUTDEVICE>() as UINT);
+        }
+    }
+}
+
+/// The rectangle the cursor is currently confined to, or `None` if it can move across the entire
+/// virtual screen.
+unsafe fn cursor_clip() -> Option<RECT> {
+    let mut clip: RECT = zeroed();
+    if GetClipCursor(&mut clip) == 0 {
+        return None;
+    }
+
+    let left = GetSystemMetrics(SM_XVIRTUALSCREEN);
+    let top = GetSystemMetrics(SM_YVIRTUALSCREEN);
+    let is_virtual_screen = clip.left == left
+        && clip.top == top
+        && clip.right == left + GetSystemMetrics(SM_CXVIRTUALSCREEN)
+        && clip.bottom == top + GetSystemMetrics(SM_CYVIRTUALSCREEN);
+    if is_virtual_screen {
+        None
+    } else {
+        Some(clip)
+    }
+}
+
+/// The process' current raw mouse input registration, if there is one.
+unsafe fn registered_mouse_input() -> Option<RAWINPUTDEVICE> {
+    let device_size = size_of::<RAWINPUTDEVICE>() as UINT;
+
+    let mut count: UINT = 0;
+    GetRegisteredRawInputDevices(null_mut(), &mut count, device_size);
+    if count == 0 {
+        return None;
+    }
+
+    let mut devices: Vec<RAWINPUTDEVICE> = vec![zeroed(); count as usize];
+    if GetRegisteredRawInputDevices(devices.as_mut_ptr(), &mut count, device_size) == UINT::MAX {
+        return None;
+    }
+    devices.truncate(count as usize);
+
+    devices
+        .into_iter()
+        .find(|device| device.usUsagePage == MOUSE_USAGE_PAGE && device.usUsage == MOUSE_USAGE)
+}
@@ -24,30 +24,36 @@ use std::ops::RangeInclusive;
 
 use keyboard_types::{Code, Key, KeyState, KeyboardEvent, Location, Modifiers, NamedKey};
 
-use winapi::shared::minwindef::{HKL, INT, LPARAM, UINT, WPARAM};
+use winapi::shared::minwindef::{DWORD, HKL, INT, LPARAM, UINT, WPARAM};
 use winapi::shared::ntdef::SHORT;
 use winapi::shared::windef::HWND;
 use winapi::um::winuser::{
-    GetKeyState, GetKeyboardLayout, MapVirtualKeyExW, PeekMessageW, ToUnicodeEx, MAPVK_VK_TO_CHAR,
-    MAPVK_VSC_TO_VK_EX, MK_CONTROL, MK_SHIFT, PM_NOREMOVE, VK_ACCEPT, VK_ADD, VK_APPS, VK_ATTN,
-    VK_BACK, VK_BROWSER_BACK, VK_BROWSER_FAVORITES, VK_BROWSER_FORWARD, VK_BROWSER_HOME,
-    VK_BROWSER_REFRESH, VK_BROWSER_SEARCH, VK_BROWSER_STOP, VK_CANCEL, VK_CAPITAL, VK_CLEAR,
-    VK_CONTROL, VK_CONVERT, VK_CRSEL, VK_DECIMAL, VK_DELETE, VK_DIVIDE, VK_DOWN, VK_END, VK_EREOF,
-    VK_ESCAPE, VK_EXECUTE, VK_EXSEL, VK_F1, VK_F10, VK_F11, VK_F12, VK_F2, VK_F3, VK_F4, VK_F5,
-    VK_F6, VK_F7, VK_F8, VK_F9, VK_FINAL, VK_HELP, VK_HOME, VK_INSERT, VK_JUNJA, VK_KANA, VK_KANJI,
-    VK_LAUNCH_APP1, VK_LAUNCH_APP2, VK_LAUNCH_MAIL, VK_LAUNCH_MEDIA_SELECT, VK_LCONTROL, VK_LEFT,
-    VK_LMENU, VK_LSHIFT, VK_LWIN, VK_MEDIA_NEXT_TRACK, VK_MEDIA_PLAY_PAUSE, VK_MEDIA_PREV_TRACK,
+    GetKeyState, GetKeyboardLayout, GetKeyboardState, GetMessageTime, MapVirtualKeyExW,
+    PeekMessageW, ToUnicodeEx, MAPVK_VK_TO_CHAR, MAPVK_VSC_TO_VK_EX, MK_CONTROL, MK_SHIFT,
+    PM_NOREMOVE, VK_ACCEPT, VK_ADD, VK_APPS, VK_ATTN, VK_BACK, VK_BROWSER_BACK,
+    VK_BROWSER_FAVORITES, VK_BROWSER_FORWARD, VK_BROWSER_HOME, VK_BROWSER_REFRESH,
+    VK_BROWSER_SEARCH, VK_BROWSER_STOP, VK_CANCEL, VK_CAPITAL, VK_CLEAR, VK_CONTROL, VK_CONVERT,
+    VK_CRSEL, VK_DECIMAL, VK_DELETE, VK_DIVIDE, VK_DOWN, VK_END, VK_EREOF, VK_ESCAPE, VK_EXECUTE,
+    VK_EXSEL, VK_F1, VK_F10, VK_F11, VK_F12, VK_F2, VK_F3, VK_F4, VK_F5, VK_F6, VK_F7, VK_F8,
+    VK_F9, VK_FINAL, VK_HELP, VK_HOME, VK_INSERT, VK_JUNJA, VK_KANA, VK_KANJI, VK_LAUNCH_APP1,
+    VK_LAUNCH_APP2, VK_LAUNCH_MAIL, VK_LAUNCH_MEDIA_SELECT, VK_LCONTROL, VK_LEFT, VK_LMENU,
+    VK_LSHIFT, VK_LWIN, VK_MEDIA_NEXT_TRACK, VK_MEDIA_PLAY_PAUSE, VK_MEDIA_PREV_TRACK,
     VK_MEDIA_STOP, VK_MENU, VK_MODECHANGE, VK_MULTIPLY, VK_NEXT, VK_NONCONVERT, VK_NUMLOCK,
     VK_NUMPAD0, VK_NUMPAD1, VK_NUMPAD2, VK_NUMPAD3, VK_NUMPAD4, VK_NUMPAD5, VK_NUMPAD6, VK_NUMPAD7,
     VK_NUMPAD8, VK_NUMPAD9, VK_OEM_ATTN, VK_OEM_CLEAR, VK_PAUSE, VK_PLAY, VK_PRINT, VK_PRIOR,
     VK_PROCESSKEY, VK_RCONTROL, VK_RETURN, VK_RIGHT, VK_RMENU, VK_RSHIFT, VK_RWIN, VK_SCROLL,
     VK_SELECT, VK_SHIFT, VK_SLEEP, VK_SNAPSHOT, VK_SUBTRACT, VK_TAB, VK_UP, VK_VOLUME_DOWN,
-    VK_VOLUME_MUTE, VK_VOLUME_UP, VK_ZOOM, WM_CHAR, WM_INPUTLANGCHANGE, WM_KEYDOWN, WM_KEYUP,
-    WM_SYSCHAR, WM_SYSKEYDOWN, WM_SYSKEYUP,
+    VK_VOLUME_MUTE, VK_VOLUME_UP, VK_ZOOM, WM_CHAR, WM_DEADCHAR, WM_INPUTLANGCHANGE, WM_KEYDOWN,
+    WM_KEYUP, WM_SYSCHAR, WM_SYSDEADCHAR, WM_SYSKEYDOWN, WM_SYSKEYUP,
 };
 
 const VK_ABNT_C2: INT = 0xc2;
 
+/// `wFlags` bit for `ToUnicodeEx` that leaves the kernel-mode keyboard state untouched, so
+/// querying a key does not consume (or start) a pending dead key. Only honored on Windows 10
+/// 1607 and later; older versions ignore it.
+const TOUNICODE_NO_STATE_CHANGE: UINT = 1 << 2;
+
 /// A (non-extended) virtual key code.
 type VkCode = u8;
 
@@ -66,6 +72,9 @@ pub(super) struct KeyboardState {
     has_altgr: bool,
     stash_vk: Option<VkCode>,
     stash_utf16: Vec<u16>,
+    /// Set while the synthetic left Control press that Windows sends along with AltGr is held,
+    /// so the matching release can be swallowed as well.
+    fake_control_down: bool,
 }
 
 /// Virtual key codes that are considered printable.
@@ -86,6 +95,11 @@ const PRINTABLE_VKS: &[RangeInclusive<VkCode>] = &[
 /// Bits of lparam indicating scan code, including extended bit.
 const SCAN_MASK: LPARAM = 0x1ff_0000;
 
+/// The scan code of a key message, with `0x100` set for extended keys.
+pub(super) fn scan_code(lparam: LPARAM) -> u32 {
+    ((lparam & SCAN_MASK) >> 16) as u32
+}
+
 /// Determine whether there are more messages in the queue for this key event.
 ///
 /// When this function returns `false`, there is another message in the queue
@@ -102,6 +116,41 @@ unsafe fn is_last_message(hwnd: HWND, msg: UINT, lparam: LPARAM) -> bool {
     avail == 0 || msg.lParam & SCAN_MASK != lparam & SCAN_MASK
 }
 
+/// Determine whether `TranslateMessage` queued a dead character for this key event.
+///
+/// When this returns `true` the key press started a dead key sequence, and the composed
+/// character will arrive with the `WM_CHAR` of the next key press.
+unsafe fn has_pending_dead_char(hwnd: HWND, msg: UINT, lparam: LPARAM) -> bool {
+    let expected_msg = match msg {
+        WM_KEYDOWN => WM_DEADCHAR,
+        WM_SYSKEYDOWN => WM_SYSDEADCHAR,
+        _ => unreachable!(),
+    };
+    let mut msg = mem::zeroed();
+    let avail = PeekMessageW(&mut msg, hwnd, expected_msg, expected_msg, PM_NOREMOVE);
+    avail != 0 && msg.lParam & SCAN_MASK == lparam & SCAN_MASK
+}
+
+/// Determine whether this is the synthetic left Control press Windows sends right before the
+/// right Alt press when AltGr is pressed on a layout that has it.
+///
+/// Both messages carry the same timestamp, which is how browsers tell them apart from the user
+/// actually pressing Control.
+unsafe fn is_fake_altgr_control(hwnd: HWND, wparam: WPARAM, lparam: LPARAM) -> bool {
+    let is_extended = (lparam & 0x100_0000) != 0;
+    if wparam as INT != VK_CONTROL || is_extended {
+        return false;
+    }
+
+    let mut next = mem::zeroed::<winapi::um::winuser::MSG>();
+    let avail = PeekMessageW(&mut next, hwnd, WM_KEYDOWN, WM_SYSKEYDOWN, PM_NOREMOVE);
+    avail != 0
+        && (next.message == WM_KEYDOWN || next.message == WM_SYSKEYDOWN)
+        && next.wParam as INT == VK_MENU
+        && (next.lParam & 0x100_0000) != 0
+        && next.time == GetMessageTime() as DWORD
+}
+
 const MODIFIER_MAP: &[(INT, Modifiers, SHORT)] = &[
     (VK_MENU, Modifiers::ALT, 0x80),
     (VK_CAPITAL, Modifiers::CAPS_LOCK, 0x1),
@@ -258,6 +307,22 @@ fn scan_to_code(scan_code: u32) -> Code {
     }
 }
 
+/// The virtual key of the physical key with this code in the current keyboard layout, the inverse
+/// of [`scan_to_code()`].
+pub(super) fn code_to_vk(code: Code) -> Option<UINT> {
+    if code == Code::Unidentified {
+        return None;
+    }
+
+    let scan_code = (0..0x200).find(|&scan_code| scan_to_code(scan_code) == code)?;
+    // `MapVirtualKeyExW()` expects extended scan codes to be prefixed with 0xE0
+    let scan_code = if scan_code & 0x100 != 0 { 0xE000 | (scan_code & 0xFF) } else { scan_code };
+    match unsafe { MapVirtualKeyExW(scan_code, MAPVK_VSC_TO_VK_EX, GetKeyboardLayout(0)) } {
+        0 => None,
+        vk => Some(vk),
+    }
+}
+
 fn vk_to_key(vk: VkCode) -> Option<Key> {
     Some(match vk as INT {
         VK_CANCEL => Key::Named(NamedKey::Cancel),
@@ -397,8 +462,16 @@ impl KeyboardState {
             let stash_vk = None;
             let stash_utf16 = Vec::new();
             let has_altgr = false;
-            let mut result =
-                KeyboardState { hkl, key_vals, dead_keys, has_altgr, stash_vk, stash_utf16 };
+            let fake_control_down = false;
+            let mut result = KeyboardState {
+                hkl,
+                key_vals,
+                dead_keys,
+                has_altgr,
+                stash_vk,
+                stash_utf16,
+                fake_control_down,
+            };
             result.load_keyboard_layout();
             result
         }
@@ -408,8 +481,9 @@ impl KeyboardState {
     ///
     /// This is the main interface point for generating cooked keyboard events
     /// from raw platform messages. It should be called for each relevant message,
-    /// which comprises: `WM_KEYDOWN`, `WM_KEYUP`, `WM_CHAR`, `WM_SYSKEYDOWN`,
-    /// `WM_SYSKEYUP`, `WM_SYSCHAR`, and `WM_INPUTLANGCHANGE`.
+    /// which comprises: `WM_KEYDOWN`, `WM_KEYUP`, `WM_CHAR`, `WM_DEADCHAR`,
+    /// `WM_SYSKEYDOWN`, `WM_SYSKEYUP`, `WM_SYSCHAR`, `WM_SYSDEADCHAR`, and
+    /// `WM_INPUTLANGCHANGE`.
     ///
     /// As a general theory, many keyboard events generate a sequence of platform
     /// messages. In these cases, we stash information from all messages but the
@@ -431,6 +505,18 @@ impl KeyboardState {
     /// a key event with `key = Key::Character("´´")`, which also matches browser
     /// behavior.
     ///
+    /// The first press of a dead key is followed by a `WM_DEADCHAR` instead of a
+    /// `WM_CHAR`. That press is reported as `Key::Named(NamedKey::Dead)` and the
+    /// `WM_DEADCHAR` itself is swallowed; the composed character is delivered with
+    /// the next key press.
+    ///
+    /// Some hosts never call `TranslateMessage` for the plugin window, in which case
+    /// no character messages arrive at all. A key press without any pending
+    /// character message is then translated here with `ToUnicodeEx`, which performs
+    /// the same stateful dead key composition `TranslateMessage` would have done. We
+    /// never call it when character messages are pending, since translating the same
+    /// key twice would either duplicate or swallow the dead key.
+    ///
     /// # Safety
     ///
     /// The `hwnd` argument must be a valid `HWND`. Similarly, the `lparam` must be
@@ -442,12 +528,24 @@ impl KeyboardState {
         match msg {
             WM_KEYDOWN | WM_SYSKEYDOWN => {
                 //println!("keydown wparam {:x} lparam {:x}", wparam, lparam);
-                let scan_code = ((lparam & SCAN_MASK) >> 16) as u32;
+                if self.has_altgr && is_fake_altgr_control(hwnd, wparam, lparam) {
+                    self.fake_control_down = true;
+                    return None;
+                }
+
+                let scan_code = scan_code(lparam);
                 let vk = self.refine_vk(wparam as u8, scan_code);
-                if is_last_message(hwnd, msg, lparam) {
+                let is_dead_key = has_pending_dead_char(hwnd, msg, lparam);
+                if is_dead_key || is_last_message(hwnd, msg, lparam) {
                     let modifiers = self.get_modifiers();
                     let code = scan_to_code(scan_code);
-                    let key = vk_to_key(vk).unwrap_or_else(|| self.get_base_key(vk, modifiers));
+                    let key = match vk_to_key(vk) {
+                        Some(key) => key,
+                        None if is_dead_key => Key::Named(NamedKey::Dead),
+                        None => self
+                            .translate_untranslated_key(vk, scan_code)
+                            .unwrap_or_else(|| self.get_base_key(vk, modifiers)),
+                    };
                     let repeat = (lparam & 0x4000_0000) != 0;
                     let is_extended = (lparam & 0x100_0000) != 0;
                     let location = vk_to_location(vk, is_extended);
@@ -468,13 +566,18 @@ impl KeyboardState {
                 }
             }
             WM_KEYUP | WM_SYSKEYUP => {
-                let scan_code = ((lparam & SCAN_MASK) >> 16) as u32;
+                let is_extended = (lparam & 0x100_0000) != 0;
+                if self.fake_control_down && wparam as INT == VK_CONTROL && !is_extended {
+                    self.fake_control_down = false;
+                    return None;
+                }
+
+                let scan_code = scan_code(lparam);
                 let vk = self.refine_vk(wparam as u8, scan_code);
                 let modifiers = self.get_modifiers();
                 let code = scan_to_code(scan_code);
                 let key = vk_to_key(vk).unwrap_or_else(|| self.get_base_key(vk, modifiers));
                 let repeat = false;
-                let is_extended = (lparam & 0x100_0000) != 0;
                 let location = vk_to_location(vk, is_extended);
                 let state = KeyState::Up;
                 let event = KeyboardEvent {
@@ -493,7 +596,7 @@ impl KeyboardState {
                 if is_last_message(hwnd, msg, lparam) {
                     let stash_vk = self.stash_vk.take();
                     let modifiers = self.get_modifiers();
-                    let scan_code = ((lparam & SCAN_MASK) >> 16) as u32;
+                    let scan_code = scan_code(lparam);
                     let vk = self.refine_vk(stash_vk.unwrap_or(0), scan_code);
                     let code = scan_to_code(scan_code);
                     let key = if self.stash_utf16.is_empty() && wparam < 0x20 {
@@ -526,6 +629,8 @@ impl KeyboardState {
                     None
                 }
             }
+            // The dead key press itself has already been reported from the key down message
+            WM_DEADCHAR | WM_SYSDEADCHAR => None,
             WM_INPUTLANGCHANGE => {
                 self.hkl = lparam as HKL;
                 self.load_keyboard_layout();
@@ -592,6 +697,10 @@ impl KeyboardState {
     /// states, because it's not practical to query that at keyboard event
     /// time (the main culprit is that `ToUnicodeEx` is stateful).
     ///
+    /// Since the layout is also reloaded in response to `WM_INPUTLANGCHANGE`,
+    /// the queries are made with [`TOUNICODE_NO_STATE_CHANGE`] so that a dead
+    /// key the user has already typed survives the reload.
+    ///
     /// The logic is based on Mozilla KeyboardLayout::LoadLayout but is
     /// considerably simplified.
     fn load_keyboard_layout(&mut self) {
@@ -619,7 +728,7 @@ impl KeyboardState {
                         key_state.as_ptr(),
                         uni_chars.as_mut_ptr(),
                         uni_chars.len() as _,
-                        0,
+                        TOUNICODE_NO_STATE_CHANGE,
                         self.hkl,
                     );
                     match ret.cmp(&0) {
@@ -640,7 +749,8 @@ impl KeyboardState {
                             }
                         }
                         Ordering::Less => {
-                            // It's a dead key, press it again to reset the state.
+                            // It's a dead key. On systems that ignore
+                            // `TOUNICODE_NO_STATE_CHANGE`, press it again to reset the state.
                             self.dead_keys.insert((vk, shift_state));
                             let _ = ToUnicodeEx(
                                 vk as UINT,
@@ -648,7 +758,7 @@ impl KeyboardState {
                                 key_state.as_ptr(),
                                 uni_chars.as_mut_ptr(),
                                 uni_chars.len() as _,
-                                0,
+                                TOUNICODE_NO_STATE_CHANGE,
                                 self.hkl,
                             );
                         }
@@ -679,6 +789,48 @@ impl KeyboardState {
         }
     }
 
+    /// Translate a printable key press for which `TranslateMessage` did not queue any
+    /// character messages.
+    ///
+    /// This uses the live keyboard state (including AltGr and any pending dead key) and
+    /// updates it the same way `TranslateMessage` would, so dead key composition keeps
+    /// working for hosts that don't translate messages for our window.
+    fn translate_untranslated_key(&self, vk: VkCode, scan_code: u32) -> Option<Key> {
+        if !PRINTABLE_VKS.iter().any(|range| range.contains(&vk)) {
+            return None;
+        }
+
+        unsafe {
+            let mut key_state = [0u8; 256];
+            if GetKeyboardState(key_state.as_mut_ptr()) == 0 {
+                return None;
+            }
+
+            let mut uni_chars = [0u16; 5];
+            let ret = ToUnicodeEx(
+                vk as UINT,
+                scan_code,
+                key_state.as_ptr(),
+                uni_chars.as_mut_ptr(),
+                uni_chars.len() as _,
+                0,
+                self.hkl,
+            );
+            match ret.cmp(&0) {
+                Ordering::Greater => {
+                    let utf16_slice = &uni_chars[..ret as usize];
+                    // Control characters are handled through `get_base_key()` instead
+                    if utf16_slice.iter().all(|&c| c < 0x20) {
+                        return None;
+                    }
+                    String::from_utf16(utf16_slice).ok().map(Key::Character)
+                }
+                Ordering::Less => Some(Key::Named(NamedKey::Dead)),
+                Ordering::Equal => None,
+            }
+        }
+    }
+
     /// Map a virtual key code to a code unit, also indicate if dead key.
     ///
     /// Bit 31 is set if the mapping is to a dead key. The bottom bits contain the code unit.
@@ -1,7 +1,21 @@
+mod clipboard;
 mod cursor;
+mod device_notifications;
+mod drag_source;
 mod drop_target;
+#[cfg(feature = "opengl")]
+mod gl_area;
+mod global_shortcuts;
+mod ime;
+mod infinite_drag;
 mod keyboard;
+mod power_notifications;
+mod settings_notifications;
 mod win32_window;
 mod window;
+mod window_class;
 
 pub use window::*;
+
+#[cfg(feature = "opengl")]
+pub(crate) use gl_area::GlAreaSurface;
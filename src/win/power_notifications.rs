@@ -0,0 +1,35 @@
+use std::ptr::null_mut;
+
+use winapi::shared::minwindef::{BOOL, DWORD};
+use winapi::shared::windef::HWND;
+use winapi::um::winnt::HANDLE;
+use winapi::um::winuser::{DEVICE_NOTIFY_WINDOW_HANDLE, HPOWERNOTIFY};
+
+// Not defined by winapi
+extern "system" {
+    fn RegisterSuspendResumeNotification(hRecipient: HANDLE, Flags: DWORD) -> HPOWERNOTIFY;
+    fn UnregisterSuspendResumeNotification(Handle: HPOWERNOTIFY) -> BOOL;
+}
+
+/// Registers a window for `WM_POWERBROADCAST` suspend and resume notifications. Windows only
+/// broadcasts those to top level windows, so child windows need to explicitly ask for them.
+pub(super) struct PowerNotifications {
+    handle: HPOWERNOTIFY,
+}
+
+impl PowerNotifications {
+    pub unsafe fn register(hwnd: HWND) -> Self {
+        Self {
+            handle: RegisterSuspendResumeNotification(hwnd as HANDLE, DEVICE_NOTIFY_WINDOW_HANDLE),
+        }
+    }
+}
+
+impl Drop for PowerNotifications {
+    fn drop(&mut self) {
+        if !self.handle.is_null() {
+            unsafe { UnregisterSuspendResumeNotification(self.handle) };
+            self.handle = null_mut();
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use std::cell::RefCell;
+use std::ptr::null_mut;
+
+use winapi::shared::minwindef::{LPARAM, LRESULT, WPARAM};
+use winapi::shared::windef::{HHOOK, HWND};
+use winapi::um::processthreadsapi::GetCurrentThreadId;
+use winapi::um::winuser::{
+    CallNextHookEx, GetAncestor, PostMessageW, SetWindowsHookExW, UnhookWindowsHookEx, CWPSTRUCT,
+    GA_ROOT, HC_ACTION, WH_CALLWNDPROC, WM_SETTINGCHANGE,
+};
+
+use super::window::BV_SETTINGS_CHANGED;
+
+thread_local! {
+    /// The hook and the windows registered on this thread, see [`SettingsNotifications`].
+    static SETTINGS_HOOK: RefCell<(HHOOK, Vec<HWND>)> = const { RefCell::new((null_mut(), Vec::new())) };
+}
+
+/// Forwards `WM_SETTINGCHANGE` to a child window. Windows only broadcasts the message to top level
+/// windows, so a thread-local `WH_CALLWNDPROC` hook watches for the message being sent to the
+/// child window's top level window, and posts [`BV_SETTINGS_CHANGED`] to the child window when it
+/// is. The hook is shared by all child windows on the thread.
+pub(super) struct SettingsNotifications {
+    hwnd: HWND,
+}
+
+impl SettingsNotifications {
+    pub unsafe fn register(hwnd: HWND) -> Self {
+        SETTINGS_HOOK.with(|hook| {
+            let (handle, windows) = &mut *hook.borrow_mut();
+            if handle.is_null() {
+                *handle = SetWindowsHookExW(
+                    WH_CALLWNDPROC,
+                    Some(hook_proc),
+                    null_mut(),
+                    GetCurrentThreadId(),
+                );
+            }
+
+            windows.push(hwnd);
+        });
+
+        Self { hwnd }
+    }
+}
+
+impl Drop for SettingsNotifications {
+    fn drop(&mut self) {
+        SETTINGS_HOOK.with(|hook| {
+            let (handle, windows) = &mut *hook.borrow_mut();
+            windows.retain(|&hwnd| hwnd != self.hwnd);
+            if windows.is_empty() && !handle.is_null() {
+                unsafe { UnhookWindowsHookEx(*handle) };
+                *handle = null_mut();
+            }
+        });
+    }
+}
+
+unsafe extern "system" fn hook_proc(code: i32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    if code == HC_ACTION {
+        let message = &*(lparam as *const CWPSTRUCT);
+        if message.message == WM_SETTINGCHANGE {
+            SETTINGS_HOOK.with(|hook| {
+                // The message is posted, so the window's handler can't run while this is borrowed
+                let (_, windows) = &*hook.borrow();
+                for &hwnd in windows {
+                    if GetAncestor(hwnd, GA_ROOT) == message.hwnd {
+                        PostMessageW(hwnd, BV_SETTINGS_CHANGED, 0, 0);
+                    }
+                }
+            });
+        }
+    }
+
+    CallNextHookEx(null_mut(), code, wparam, lparam)
+}
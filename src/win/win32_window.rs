@@ -1,24 +1,40 @@
+use super::window_class::module_instance;
 use crate::PhySize;
 use std::convert::TryFrom;
-use std::ffi::OsStr;
-use std::os::windows::ffi::OsStrExt;
+use std::ffi::{OsStr, OsString};
+use std::os::windows::ffi::{OsStrExt, OsStringExt};
 use std::ptr::null_mut;
-use winapi::shared::minwindef::{ATOM, DWORD};
+use winapi::shared::minwindef::{ATOM, DWORD, LRESULT, MAX_PATH};
 use winapi::shared::windef::{HWND, RECT};
+use winapi::um::dwmapi::DwmExtendFrameIntoClientArea;
+use winapi::um::uxtheme::MARGINS;
+use winapi::um::wingdi::{GetICMProfileW, DEVMODEW};
 use winapi::um::winuser::{
-    AdjustWindowRectEx, CreateWindowExW, GetDpiForWindow, SetWindowPos, SWP_NOACTIVATE, SWP_NOMOVE,
-    SWP_NOZORDER, USER_DEFAULT_SCREEN_DPI, WS_CAPTION, WS_CHILD, WS_CLIPSIBLINGS, WS_MAXIMIZEBOX,
-    WS_MINIMIZEBOX, WS_POPUPWINDOW, WS_SIZEBOX, WS_VISIBLE,
+    AdjustWindowRectEx, CreateWindowExW, EnumDisplaySettingsW, GetDC, GetDpiForWindow,
+    GetMonitorInfoW, GetSystemMetricsForDpi, GetWindowRect, IsZoomed, MonitorFromWindow, ReleaseDC,
+    SetWindowPos, ENUM_CURRENT_SETTINGS, HTBOTTOM, HTBOTTOMLEFT, HTBOTTOMRIGHT, HTCLIENT, HTLEFT,
+    HTRIGHT, HTTOP, HTTOPLEFT, HTTOPRIGHT, MONITORINFO, MONITORINFOEXW, MONITOR_DEFAULTTONEAREST,
+    SM_CXPADDEDBORDER, SM_CXSIZEFRAME, SM_CYSIZEFRAME, SWP_FRAMECHANGED, SWP_NOACTIVATE,
+    SWP_NOMOVE, SWP_NOSIZE, SWP_NOZORDER, USER_DEFAULT_SCREEN_DPI, WS_CAPTION, WS_CHILD,
+    WS_CLIPSIBLINGS, WS_MAXIMIZEBOX, WS_MINIMIZEBOX, WS_POPUPWINDOW, WS_SIZEBOX, WS_VISIBLE,
 };
 
 // TODO: handle proper destruction of this window during errors/panics/etc.
 pub(crate) struct Win32Window {
     pub handle: HWND,
     style_flags: DWORD,
+    /// Whether the window's client area covers the entire window, see
+    /// [`WindowOpenOptions::borderless`][crate::WindowOpenOptions::borderless].
+    borderless: bool,
 }
 
 impl Win32Window {
-    pub fn create(window_class: ATOM, title: &str, size: PhySize, parent: Option<HWND>) -> Self {
+    /// `borderless` only has an effect for top level windows. The frame needs to be recomputed with
+    /// [`enable_borderless_frame()`][Self::enable_borderless_frame()] once the window procedure
+    /// handles `WM_NCCALCSIZE`.
+    pub fn create(
+        window_class: ATOM, title: &str, size: PhySize, parent: Option<HWND>, borderless: bool,
+    ) -> Self {
         let mut title: Vec<u16> = OsStr::new(title).encode_wide().collect();
         title.push(0);
 
@@ -34,7 +50,10 @@ impl Win32Window {
                 | WS_CLIPSIBLINGS
         };
 
-        let size = client_size_to_window_size(size, style_flags);
+        // The window keeps its normal styles so it can still be resized, snapped, and minimized
+        // and maximized with animations
+        let borderless = borderless && parent.is_none();
+        let size = if borderless { size } else { client_size_to_window_size(size, style_flags) };
 
         // TODO: handle errors
         let hwnd = unsafe {
@@ -49,19 +68,101 @@ impl Win32Window {
                 size.height as i32,
                 parent.unwrap_or(null_mut()),
                 null_mut(),
-                null_mut(),
+                // This must match the instance the window class was registered with
+                module_instance(),
                 null_mut(),
             )
         };
 
-        Win32Window { style_flags, handle: hwnd }
+        Win32Window { style_flags, handle: hwnd, borderless }
+    }
+
+    pub fn is_borderless(&self) -> bool {
+        self.borderless
+    }
+
+    /// Make the window procedure's `WM_NCCALCSIZE` handling take effect, and keep the drop shadow
+    /// the system normally draws around the window frame.
+    pub fn enable_borderless_frame(&self) {
+        let margins =
+            MARGINS { cxLeftWidth: 0, cxRightWidth: 0, cyTopHeight: 1, cyBottomHeight: 0 };
+
+        unsafe {
+            // This fails when desktop composition is disabled, and then there's no shadow to keep
+            DwmExtendFrameIntoClientArea(self.handle, &margins);
+            SetWindowPos(
+                self.handle,
+                null_mut(),
+                0,
+                0,
+                0,
+                0,
+                SWP_FRAMECHANGED | SWP_NOMOVE | SWP_NOSIZE | SWP_NOZORDER | SWP_NOACTIVATE,
+            );
+        }
+    }
+
+    /// Compute the client area for `WM_NCCALCSIZE` from the proposed window rectangle, for
+    /// borderless windows. Maximized windows extend past the edges of the monitor by the width of
+    /// their resize borders, so that part is cut off.
+    pub fn borderless_client_rect(&self, rect: &mut RECT) {
+        if unsafe { IsZoomed(self.handle) } != 0 {
+            let (border_x, border_y) = self.resize_border_size();
+            rect.left += border_x;
+            rect.top += border_y;
+            rect.right -= border_x;
+            rect.bottom -= border_y;
+        }
+    }
+
+    /// Handle `WM_NCHITTEST` for borderless windows. The edges of the window act as resize
+    /// borders, and everything else belongs to the client area. The position is in screen
+    /// coordinates.
+    pub fn borderless_hit_test(&self, x: i32, y: i32) -> LRESULT {
+        let mut rect = RECT { left: 0, top: 0, right: 0, bottom: 0 };
+        if unsafe { IsZoomed(self.handle) != 0 || GetWindowRect(self.handle, &mut rect) == 0 } {
+            return HTCLIENT;
+        }
+
+        let (border_x, border_y) = self.resize_border_size();
+        let left = x < rect.left + border_x;
+        let right = x >= rect.right - border_x;
+        let top = y < rect.top + border_y;
+        let bottom = y >= rect.bottom - border_y;
+
+        match (left, right, top, bottom) {
+            (true, _, true, _) => HTTOPLEFT,
+            (_, true, true, _) => HTTOPRIGHT,
+            (true, _, _, true) => HTBOTTOMLEFT,
+            (_, true, _, true) => HTBOTTOMRIGHT,
+            (true, _, _, _) => HTLEFT,
+            (_, true, _, _) => HTRIGHT,
+            (_, _, true, _) => HTTOP,
+            (_, _, _, true) => HTBOTTOM,
+            _ => HTCLIENT,
+        }
+    }
+
+    /// The width and height of the resize borders the window would normally have, at the DPI of
+    /// the monitor it's on.
+    fn resize_border_size(&self) -> (i32, i32) {
+        unsafe {
+            let dpi = GetDpiForWindow(self.handle);
+            let padding = GetSystemMetricsForDpi(SM_CXPADDEDBORDER, dpi);
+
+            (
+                GetSystemMetricsForDpi(SM_CXSIZEFRAME, dpi) + padding,
+                GetSystemMetricsForDpi(SM_CYSIZEFRAME, dpi) + padding,
+            )
+        }
     }
 
     /// Resizes the window.
     ///
     /// This *will* immediately trigger a WM_SIZE event.
     pub fn resize(&self, size: PhySize) {
-        let window_size = client_size_to_window_size(size, self.style_flags);
+        let window_size =
+            if self.borderless { size } else { client_size_to_window_size(size, self.style_flags) };
 
         unsafe {
             SetWindowPos(
@@ -99,6 +200,71 @@ impl Win32Window {
         let dpi = unsafe { GetDpiForWindow(self.handle) };
         dpi as f64 / USER_DEFAULT_SCREEN_DPI as f64
     }
+
+    /// Returns the refresh rate of the monitor the window is currently on, if it's known.
+    pub fn current_refresh_rate(&self) -> Option<f64> {
+        let mode = self.current_display_mode()?;
+
+        // Zero and one both stand for the display hardware's default refresh rate
+        match mode.dmDisplayFrequency {
+            0 | 1 => None,
+            frequency => Some(frequency as f64),
+        }
+    }
+
+    /// Returns the color depth in bits per pixel of the monitor the window is currently on, if
+    /// it's known.
+    pub fn current_color_depth(&self) -> Option<u32> {
+        self.current_display_mode().map(|mode| mode.dmBitsPerPel)
+    }
+
+    /// Returns the contents of the ICC profile assigned to the monitor the window is currently on.
+    pub fn current_color_profile(&self) -> Option<Vec<u8>> {
+        let mut path = [0u16; MAX_PATH];
+        let mut path_size = path.len() as DWORD;
+        unsafe {
+            // The window class uses `CS_OWNDC`, so this is the window's own device context
+            let hdc = GetDC(self.handle);
+            if hdc.is_null() {
+                return None;
+            }
+
+            let result = GetICMProfileW(hdc, &mut path_size, path.as_mut_ptr());
+            ReleaseDC(self.handle, hdc);
+            if result == 0 {
+                return None;
+            }
+        }
+
+        let path_len = path.iter().position(|&c| c == 0).unwrap_or(path.len());
+        std::fs::read(OsString::from_wide(&path[..path_len])).ok()
+    }
+
+    fn current_display_mode(&self) -> Option<DEVMODEW> {
+        unsafe {
+            let monitor = MonitorFromWindow(self.handle, MONITOR_DEFAULTTONEAREST);
+
+            let mut monitor_info: MONITORINFOEXW = std::mem::zeroed();
+            monitor_info.cbSize = std::mem::size_of::<MONITORINFOEXW>() as DWORD;
+            let monitor_info_ptr = &mut monitor_info as *mut MONITORINFOEXW as *mut MONITORINFO;
+            if GetMonitorInfoW(monitor, monitor_info_ptr) == 0 {
+                return None;
+            }
+
+            let mut mode: DEVMODEW = std::mem::zeroed();
+            mode.dmSize = std::mem::size_of::<DEVMODEW>() as u16;
+            if EnumDisplaySettingsW(
+                monitor_info.szDevice.as_ptr(),
+                ENUM_CURRENT_SETTINGS,
+                &mut mode,
+            ) == 0
+            {
+                return None;
+            }
+
+            Some(mode)
+        }
+    }
 }
 
 pub fn client_size_to_window_size(size: PhySize, window_flags: DWORD) -> PhySize {
@@ -1,69 +1,132 @@
-use winapi::shared::guiddef::GUID;
-use winapi::shared::minwindef::{ATOM, LOWORD, LPARAM, LRESULT, UINT, WPARAM};
-use winapi::shared::windef::{HWND, POINT, RECT};
-use winapi::um::combaseapi::CoCreateGuid;
+use winapi::shared::minwindef::{DWORD, HIWORD, INT, LOWORD, LPARAM, LRESULT, TRUE, UINT, WPARAM};
+use winapi::shared::windef::{HHOOK, HWND, POINT, RECT};
+use winapi::shared::winerror::SUCCEEDED;
 use winapi::um::ole2::{OleInitialize, RegisterDragDrop, RevokeDragDrop};
 use winapi::um::oleidl::LPDROPTARGET;
+use winapi::um::processthreadsapi::GetCurrentThreadId;
+use winapi::um::wingdi::{CombineRgn, CreateRectRgn, DeleteObject, RGN_OR};
+use winapi::um::winnls::LCIDToLocaleName;
+use winapi::um::winnt::{LCID, LOCALE_NAME_MAX_LENGTH};
 use winapi::um::winuser::{
-    ClientToScreen, DefWindowProcW, DestroyWindow, DispatchMessageW, GetFocus, GetMessageW,
-    GetWindowLongPtrW, LoadCursorW, PostMessageW, RegisterClassW, ReleaseCapture, SetCapture,
-    SetCursor, SetCursorPos, SetFocus, SetProcessDpiAwarenessContext, SetTimer, SetWindowLongPtrW,
-    TrackMouseEvent, TranslateMessage, UnregisterClassW, CS_OWNDC, GET_XBUTTON_WPARAM,
-    GWLP_USERDATA, HTCLIENT, IDC_ARROW, MSG, TRACKMOUSEEVENT, USER_DEFAULT_SCREEN_DPI, WHEEL_DELTA,
-    WM_CHAR, WM_CLOSE, WM_CREATE, WM_DPICHANGED, WM_INPUTLANGCHANGE, WM_KEYDOWN, WM_KEYUP,
-    WM_LBUTTONDOWN, WM_LBUTTONUP, WM_MBUTTONDOWN, WM_MBUTTONUP, WM_MOUSEHWHEEL, WM_MOUSELEAVE,
-    WM_MOUSEMOVE, WM_MOUSEWHEEL, WM_NCDESTROY, WM_RBUTTONDOWN, WM_RBUTTONUP, WM_SETCURSOR,
-    WM_SHOWWINDOW, WM_SIZE, WM_SYSCHAR, WM_SYSKEYDOWN, WM_SYSKEYUP, WM_TIMER, WM_USER,
-    WM_XBUTTONDOWN, WM_XBUTTONUP, WNDCLASSW, XBUTTON1, XBUTTON2,
+    AddClipboardFormatListener, CallNextHookEx, ClientToScreen, DefWindowProcW, DestroyWindow,
+    DispatchMessageW, GetCursorPos, GetFocus, GetKeyState, GetKeyboardLayout, GetMessageExtraInfo,
+    GetMessageW, GetPointerInfo, GetQueueStatus, GetSystemMetrics, GetWindowLongPtrW,
+    GetWindowRect, IsChild, IsWindow, LoadCursorW, PostMessageW, ReleaseCapture,
+    RemoveClipboardFormatListener, ScreenToClient, SendMessageW, SetCapture, SetCursor,
+    SetCursorPos, SetFocus, SetProcessDpiAwarenessContext, SetTimer, SetWindowLongPtrW,
+    SetWindowRgn, SetWindowsHookExW, SystemParametersInfoW, TrackMouseEvent, TranslateMessage,
+    UnhookWindowsHookEx, WindowFromPoint, DLGC_WANTALLKEYS, DLGC_WANTARROWS, DLGC_WANTCHARS,
+    DLGC_WANTTAB, GET_XBUTTON_WPARAM, GWLP_USERDATA, HC_ACTION, HTCLIENT, MK_CONTROL, MK_LBUTTON,
+    MK_MBUTTON, MK_RBUTTON, MK_SHIFT, MSG, NCCALCSIZE_PARAMS, PBT_APMRESUMEAUTOMATIC,
+    PBT_APMSUSPEND, POINTER_INFO, PT_MOUSE, PT_PEN, PT_TOUCH, PT_TOUCHPAD, QS_ALLINPUT,
+    SIZE_MINIMIZED, SM_CXDRAG, SM_CYDRAG, SPI_GETWHEELSCROLLLINES, TRACKMOUSEEVENT,
+    USER_DEFAULT_SCREEN_DPI, VK_CONTROL, VK_F10, VK_F4, VK_LBUTTON, VK_MBUTTON, VK_MENU,
+    VK_RBUTTON, VK_SHIFT, WHEEL_DELTA, WHEEL_PAGESCROLL, WH_KEYBOARD, WM_CAPTURECHANGED, WM_CHAR,
+    WM_CLIPBOARDUPDATE, WM_CLOSE, WM_CREATE, WM_DEADCHAR, WM_DESTROY, WM_DEVICECHANGE,
+    WM_DISPLAYCHANGE, WM_DPICHANGED, WM_GETDLGCODE, WM_HOTKEY, WM_IME_COMPOSITION,
+    WM_IME_ENDCOMPOSITION, WM_IME_STARTCOMPOSITION, WM_INPUT, WM_INPUTLANGCHANGE, WM_KEYDOWN,
+    WM_KEYUP, WM_LBUTTONDOWN, WM_LBUTTONUP, WM_MBUTTONDOWN, WM_MBUTTONUP, WM_MOUSEHWHEEL,
+    WM_MOUSELEAVE, WM_MOUSEMOVE, WM_MOUSEWHEEL, WM_NCCALCSIZE, WM_NCDESTROY, WM_NCHITTEST,
+    WM_POINTERDOWN, WM_POINTERUP, WM_POINTERUPDATE, WM_POWERBROADCAST, WM_RBUTTONDOWN,
+    WM_RBUTTONUP, WM_SETCURSOR, WM_SETTINGCHANGE, WM_SHOWWINDOW, WM_SIZE, WM_SYSCHAR,
+    WM_SYSDEADCHAR, WM_SYSKEYDOWN, WM_SYSKEYUP, WM_TIMER, WM_USER, WM_XBUTTONDOWN, WM_XBUTTONUP,
+    XBUTTON1, XBUTTON2,
 };
 
 use std::cell::{Cell, Ref, RefCell, RefMut};
 use std::collections::VecDeque;
-use std::ffi::{c_void, OsStr};
-use std::os::windows::ffi::OsStrExt;
+use std::ffi::c_void;
 use std::ptr::null_mut;
 use std::rc::Rc;
 
+use keyboard_types::{KeyboardEvent, Modifiers};
 use raw_window_handle::{
     HasRawDisplayHandle, HasRawWindowHandle, RawDisplayHandle, RawWindowHandle, Win32WindowHandle,
     WindowsDisplayHandle,
 };
 
 const BV_WINDOW_MUST_CLOSE: UINT = WM_USER + 1;
+/// Sent by [`WindowHandle::send_key_event()`]. The `lparam` points to the `KeyboardEvent`, and the
+/// result is 1 if the handler captured the event.
+const BV_HOST_KEY_EVENT: UINT = WM_USER + 2;
+/// Sent by [`WindowHandle::set_scale_factor()`]. The `lparam` points to the new `f64` scale factor.
+const BV_SET_SCALE_FACTOR: UINT = WM_USER + 3;
+/// Sent by [`WindowHandle::tick_frame()`]. This draws a frame like the frame timer does.
+const BV_TICK_FRAME: UINT = WM_USER + 4;
+/// Posted to parented windows when their top level window receives `WM_SETTINGCHANGE`, see
+/// [`SettingsNotifications`].
+pub(super) const BV_SETTINGS_CHANGED: UINT = WM_USER + 5;
+
+// Not defined by winapi
+const SPI_GETWHEELSCROLLCHARS: UINT = 0x006C;
+
+// Not defined by winapi
+extern "system" {
+    fn OleUninitialize();
+}
 
+use crate::accelerator::Accelerators;
+use crate::frame_stats::FrameCounter;
+use crate::hover::HoverState;
+use crate::scancode::KeyScancode;
+use crate::text::PendingText;
 use crate::{
-    Event, MouseButton, MouseCursor, MouseEvent, PhyPoint, PhySize, Point, ScrollDelta, Size,
-    WindowEvent, WindowHandler, WindowInfo, WindowOpenOptions, WindowScalePolicy,
+    ClipboardFormat, DragData, Event, EventStatus, FrameStats, GlobalShortcutError, HapticPattern,
+    ImeEvent, InputEvents, KeyCombo, MonitorInfo, MouseButton, MouseCursor, MouseEvent, PhyPoint,
+    PhySize, Point, PointerDevice, PointerType, Rect, ResizeSource, ScrollDelta, Size, WindowEvent,
+    WindowHandler, WindowInfo, WindowOpenOptions, WindowScalePolicy,
 };
 
+use super::clipboard;
 use super::cursor::cursor_to_lpcwstr;
+use super::device_notifications::{translate_device_change, DeviceNotifications};
+use super::drag_source;
 use super::drop_target::DropTarget;
-use super::keyboard::KeyboardState;
+use super::global_shortcuts::GlobalShortcuts;
+use super::ime::ImeContext;
+use super::infinite_drag::InfiniteDrag;
+use super::keyboard::{self, KeyboardState};
+use super::power_notifications::PowerNotifications;
+use super::settings_notifications::SettingsNotifications;
+use super::window_class;
 
 #[cfg(feature = "opengl")]
-use crate::gl::GlContext;
+use crate::gl::{GlArea, GlConfig, GlContext, GlContextCell, GlError};
+#[cfg(feature = "virtual-clock")]
+use crate::virtual_clock::{VirtualClock, VirtualFrames};
 use crate::win::win32_window::Win32Window;
 
-unsafe fn generate_guid() -> String {
-    let mut guid: GUID = std::mem::zeroed();
-    CoCreateGuid(&mut guid);
-    format!(
-        "{:0X}-{:0X}-{:0X}-{:0X}{:0X}-{:0X}{:0X}{:0X}{:0X}{:0X}{:0X}\0",
-        guid.Data1,
-        guid.Data2,
-        guid.Data3,
-        guid.Data4[0],
-        guid.Data4[1],
-        guid.Data4[2],
-        guid.Data4[3],
-        guid.Data4[4],
-        guid.Data4[5],
-        guid.Data4[6],
-        guid.Data4[7]
-    )
+const WIN_FRAME_TIMER: usize = 4242;
+
+thread_local! {
+    /// The window that currently holds the keyboard grab on this thread, if any. See
+    /// [`Window::grab_keyboard()`].
+    static KEYBOARD_GRAB_HWND: Cell<HWND> = const { Cell::new(null_mut()) };
 }
 
-const WIN_FRAME_TIMER: usize = 4242;
+/// A thread-local `WH_KEYBOARD` hook that redirects key presses meant for other windows on this
+/// thread to the window holding the keyboard grab. The messages are posted rather than sent so
+/// they still pass through the host's `TranslateMessage()` call.
+unsafe extern "system" fn keyboard_grab_hook(code: i32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    if code == HC_ACTION {
+        let grab_hwnd = KEYBOARD_GRAB_HWND.with(|hwnd| hwnd.get());
+        if !grab_hwnd.is_null() && GetFocus() != grab_hwnd {
+            let is_release = lparam & (1 << 31) != 0;
+            let alt_down = lparam & (1 << 29) != 0;
+            let msg = match (is_release, alt_down) {
+                (false, false) => WM_KEYDOWN,
+                (true, false) => WM_KEYUP,
+                (false, true) => WM_SYSKEYDOWN,
+                (true, true) => WM_SYSKEYUP,
+            };
+
+            PostMessageW(grab_hwnd, msg, wparam, lparam);
+            return 1;
+        }
+    }
+
+    CallNextHookEx(null_mut(), code, wparam, lparam)
+}
 
 pub struct WindowHandle {
     hwnd: Option<HWND>,
@@ -79,9 +142,49 @@ impl WindowHandle {
         }
     }
 
+    /// Destroy the window right away instead of posting a message to it, so the window's handler
+    /// has been dropped by the time this function returns. This must be called from the thread
+    /// the window was created on.
+    pub fn close_now(&mut self) {
+        if let Some(hwnd) = self.hwnd.take() {
+            if self.is_open.get() {
+                unsafe { DestroyWindow(hwnd) };
+                window_class::unregister_if_unused();
+            }
+        }
+    }
+
     pub fn is_open(&self) -> bool {
         self.is_open.get()
     }
+
+    pub fn send_key_event(&self, event: KeyboardEvent) -> bool {
+        match self.hwnd {
+            Some(hwnd) if self.is_open.get() => unsafe {
+                SendMessageW(hwnd, BV_HOST_KEY_EVENT, 0, &event as *const KeyboardEvent as LPARAM)
+                    == 1
+            },
+            _ => false,
+        }
+    }
+
+    pub fn set_scale_factor(&self, scale: f64) {
+        if let Some(hwnd) = self.hwnd {
+            if self.is_open.get() {
+                unsafe {
+                    SendMessageW(hwnd, BV_SET_SCALE_FACTOR, 0, &scale as *const f64 as LPARAM)
+                };
+            }
+        }
+    }
+
+    pub fn tick_frame(&self) {
+        if let Some(hwnd) = self.hwnd {
+            if self.is_open.get() {
+                unsafe { SendMessageW(hwnd, BV_TICK_FRAME, 0, 0) };
+            }
+        }
+    }
 }
 
 unsafe impl HasRawWindowHandle for WindowHandle {
@@ -127,7 +230,38 @@ unsafe extern "system" fn wnd_proc(
 
     let window_state_ptr = GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *mut WindowState;
     if !window_state_ptr.is_null() {
-        let result = wnd_proc_inner(hwnd, msg, wparam, lparam, &*window_state_ptr);
+        // The window may be destroyed from within this call, for instance when handling
+        // `BV_WINDOW_MUST_CLOSE`. The window state must stay alive until this call has returned.
+        Rc::increment_strong_count(window_state_ptr);
+        let _window_state = Rc::from_raw(window_state_ptr);
+
+        #[cfg(feature = "event-log")]
+        let _raw_event = crate::event_log::RawEvent::set(|| {
+            format!("message {:#06x}, wparam {:#x}, lparam {:#x}", msg, wparam, lparam)
+        });
+
+        // Messages are dispatched by the host's message loop, so we can't tell where one iteration
+        // ends. Instead, an event batch is started when the first message comes in and it's
+        // finished once there are no more messages waiting in the thread's queue, or when the
+        // frame timer fires. Nested calls to the window procedure are part of the outer call's
+        // batch.
+        let is_outermost_call = (*window_state_ptr).wnd_proc_depth.get() == 0;
+        (*window_state_ptr).wnd_proc_depth.set((*window_state_ptr).wnd_proc_depth.get() + 1);
+        if is_outermost_call {
+            if msg == WM_TIMER || msg == BV_TICK_FRAME || msg == WM_NCDESTROY {
+                (*window_state_ptr).end_event_batch();
+            } else {
+                (*window_state_ptr).begin_event_batch();
+            }
+        }
+
+        // The handler doesn't receive any callbacks once the window is closing, see
+        // `WindowState::close()`
+        let result = if (*window_state_ptr).is_closing() {
+            None
+        } else {
+            wnd_proc_inner(hwnd, msg, wparam, lparam, &*window_state_ptr)
+        };
 
         // If any of the above event handlers caused tasks to be pushed to the deferred tasks list,
         // then we'll try to handle them now
@@ -144,10 +278,26 @@ unsafe extern "system" fn wnd_proc(
             (*window_state_ptr).handle_deferred_task(task);
         }
 
+        // Closing the window from within a callback takes effect as soon as the outermost call
+        // has finished dispatching the current message
+        if is_outermost_call && msg != WM_NCDESTROY && (*window_state_ptr).close_requested.get() {
+            (*window_state_ptr).close();
+        }
+
+        if is_outermost_call && msg != WM_NCDESTROY && HIWORD(GetQueueStatus(QS_ALLINPUT)) == 0 {
+            (*window_state_ptr).end_event_batch();
+        }
+        (*window_state_ptr).wnd_proc_depth.set((*window_state_ptr).wnd_proc_depth.get() - 1);
+
         // NOTE: This is not handled in `wnd_proc_inner` because of the deferred task loop above
         if msg == WM_NCDESTROY {
+            (*window_state_ptr).release_keyboard_grab();
             RevokeDragDrop(hwnd);
-            unregister_wnd_class((*window_state_ptr).window_class);
+            RemoveClipboardFormatListener(hwnd);
+            if (*window_state_ptr).ole_initialized {
+                OleUninitialize();
+            }
+            window_class::release();
             SetWindowLongPtrW(hwnd, GWLP_USERDATA, 0);
             drop(Rc::from_raw(window_state_ptr));
         }
@@ -162,61 +312,129 @@ unsafe extern "system" fn wnd_proc(
     DefWindowProcW(hwnd, msg, wparam, lparam)
 }
 
+fn is_unsubscribed_input(msg: UINT, input_events: InputEvents) -> bool {
+    match msg {
+        WM_CHAR | WM_SYSCHAR | WM_DEADCHAR | WM_SYSDEADCHAR | WM_KEYDOWN | WM_SYSKEYDOWN
+        | WM_KEYUP | WM_SYSKEYUP | WM_GETDLGCODE => !input_events.keyboard,
+        WM_MOUSEMOVE | WM_MOUSELEAVE | WM_INPUT => !input_events.mouse_motion,
+        WM_LBUTTONDOWN | WM_LBUTTONUP | WM_MBUTTONDOWN | WM_MBUTTONUP | WM_RBUTTONDOWN
+        | WM_RBUTTONUP | WM_XBUTTONDOWN | WM_XBUTTONUP | WM_MOUSEWHEEL | WM_MOUSEHWHEEL => {
+            !input_events.mouse_buttons
+        }
+        _ => false,
+    }
+}
+
 /// Our custom `wnd_proc` handler. If the result contains a value, then this is returned after
 /// handling any deferred tasks. otherwise the default window procedure is invoked.
 unsafe fn wnd_proc_inner(
     hwnd: HWND, msg: UINT, wparam: WPARAM, lparam: LPARAM, window_state: &WindowState,
 ) -> Option<LRESULT> {
+    // Input the window didn't subscribe to is left to the default window procedure, which passes
+    // scroll wheel messages on to the parent window
+    if is_unsubscribed_input(msg, window_state.input_events) {
+        return None;
+    }
+
     match msg {
         WM_MOUSEMOVE => {
-            let mut window = crate::Window::new(window_state.create_window());
-
-            let mut mouse_was_outside_window = window_state.mouse_was_outside_window.borrow_mut();
-            if *mouse_was_outside_window {
-                // this makes Windows track whether the mouse leaves the window.
-                // When the mouse leaves it results in a `WM_MOUSELEAVE` event.
-                let mut track_mouse = TRACKMOUSEEVENT {
-                    cbSize: std::mem::size_of::<TRACKMOUSEEVENT>() as u32,
-                    dwFlags: winapi::um::winuser::TME_LEAVE,
-                    hwndTrack: hwnd,
-                    dwHoverTime: winapi::um::winuser::HOVER_DEFAULT,
-                };
-                // Couldn't find a good way to track whether the mouse enters,
-                // but if `WM_MOUSEMOVE` happens, the mouse must have entered.
-                TrackMouseEvent(&mut track_mouse);
-                *mouse_was_outside_window = false;
-
-                let enter_event = Event::Mouse(MouseEvent::CursorEntered);
-                window_state
-                    .handler
-                    .borrow_mut()
-                    .as_mut()
-                    .unwrap()
-                    .on_event(&mut window, enter_event);
+            // The cursor is frozen during an infinite drag, the movement comes from `WM_INPUT`
+            if window_state.infinite_drag.borrow().is_some() {
+                return Some(0);
             }
 
+            let mut window = crate::Window::new(window_state.create_window());
+
             let x = (lparam & 0xFFFF) as i16 as i32;
             let y = ((lparam >> 16) & 0xFFFF) as i16 as i32;
 
             let physical_pos = PhyPoint { x, y };
             let logical_pos = physical_pos.to_logical(&window_state.window_info());
-            let move_event = Event::Mouse(MouseEvent::CursorMoved {
+            let move_event = MouseEvent::CursorMoved {
                 position: logical_pos,
                 modifiers: window_state
                     .keyboard_state
                     .borrow()
                     .get_modifiers_from_mouse_wparam(wparam),
+                device: window_state.mouse_message_device(),
+            };
+            window_state.hover.filter(move_event, |event| {
+                if let MouseEvent::CursorEntered = event {
+                    // this makes Windows track whether the mouse leaves the window.
+                    // When the mouse leaves it results in a `WM_MOUSELEAVE` event.
+                    let mut track_mouse = TRACKMOUSEEVENT {
+                        cbSize: std::mem::size_of::<TRACKMOUSEEVENT>() as u32,
+                        dwFlags: winapi::um::winuser::TME_LEAVE,
+                        hwndTrack: hwnd,
+                        dwHoverTime: winapi::um::winuser::HOVER_DEFAULT,
+                    };
+                    // Couldn't find a good way to track whether the mouse enters,
+                    // but if `WM_MOUSEMOVE` happens, the mouse must have entered.
+                    // `HoverState` sends the `CursorEntered` event for us in that case.
+                    TrackMouseEvent(&mut track_mouse);
+                }
+
+                let event = Event::Mouse(event);
+                window_state.handler.borrow_mut().as_mut().unwrap().on_event(&mut window, event);
             });
-            window_state.handler.borrow_mut().as_mut().unwrap().on_event(&mut window, move_event);
             Some(0)
         }
 
+        WM_POINTERDOWN | WM_POINTERUP | WM_POINTERUPDATE => {
+            let mut pointer_info: POINTER_INFO = std::mem::zeroed();
+            // This is `GET_POINTERID_WPARAM()`, which winapi doesn't define
+            let pointer_id = LOWORD(wparam as u32) as u32;
+            if GetPointerInfo(pointer_id, &mut pointer_info) != 0 {
+                let pointer_type = match pointer_info.pointerType {
+                    PT_MOUSE => PointerType::Mouse,
+                    PT_TOUCHPAD => PointerType::Touchpad,
+                    PT_PEN => PointerType::Tablet,
+                    PT_TOUCH => PointerType::Touchscreen,
+                    _ => PointerType::Unknown,
+                };
+
+                window_state.pointer_device.set(Some(PointerDevice {
+                    id: pointer_info.sourceDevice as usize as u64,
+                    pointer_type,
+                }));
+            }
+
+            // The default window procedure turns these into the regular mouse messages, which is
+            // where the actual events are sent from. Only the primary contact is promoted, so
+            // additional touch points are dropped, see the `gestures` module.
+            None
+        }
+        WM_INPUT => {
+            let position = window_state
+                .infinite_drag
+                .borrow()
+                .as_ref()
+                .and_then(|drag| drag.handle_raw_input(lparam, &window_state.window_info()));
+            if let Some(position) = position {
+                let mut window = crate::Window::new(window_state.create_window());
+                let event = Event::Mouse(MouseEvent::CursorMoved {
+                    position,
+                    modifiers: window_state.keyboard_state.borrow().get_modifiers(),
+                    device: window_state.mouse_message_device(),
+                });
+                window_state.handler.borrow_mut().as_mut().unwrap().on_event(&mut window, event);
+            }
+
+            // The default window procedure cleans up after the raw input
+            None
+        }
+        // The mouse capture is released once all buttons are released, or when another window
+        // takes it away
+        WM_CAPTURECHANGED => {
+            window_state.end_infinite_drag();
+            None
+        }
         WM_MOUSELEAVE => {
             let mut window = crate::Window::new(window_state.create_window());
-            let event = Event::Mouse(MouseEvent::CursorLeft);
-            window_state.handler.borrow_mut().as_mut().unwrap().on_event(&mut window, event);
-
-            *window_state.mouse_was_outside_window.borrow_mut() = true;
+            window_state.hover.filter(MouseEvent::CursorLeft, |event| {
+                let event = Event::Mouse(event);
+                window_state.handler.borrow_mut().as_mut().unwrap().on_event(&mut window, event);
+            });
             Some(0)
         }
         WM_MOUSEWHEEL | WM_MOUSEHWHEEL => {
@@ -224,19 +442,31 @@ unsafe fn wnd_proc_inner(
 
             let value = (wparam >> 16) as i16;
             let value = value as i32;
-            let value = value as f32 / WHEEL_DELTA as f32;
-
-            let event = Event::Mouse(MouseEvent::WheelScrolled {
-                delta: if msg == WM_MOUSEWHEEL {
-                    ScrollDelta::Lines { x: 0.0, y: value }
+            let notches = value as f32 / WHEEL_DELTA as f32;
+
+            // Apply the user's lines or characters per notch setting
+            let settings = window_state.wheel_settings.get();
+            let delta = if msg == WM_MOUSEWHEEL {
+                // `WHEEL_PAGESCROLL` means that the wheel should scroll one page at a time
+                if settings.lines_per_notch == WHEEL_PAGESCROLL {
+                    ScrollDelta::Pages { x: 0.0, y: notches }
                 } else {
-                    ScrollDelta::Lines { x: value, y: 0.0 }
-                },
+                    ScrollDelta::Lines { x: 0.0, y: notches * settings.lines_per_notch as f32 }
+                }
+            } else {
+                ScrollDelta::Lines { x: notches * settings.chars_per_notch as f32, y: 0.0 }
+            };
+
+            let event = MouseEvent::WheelScrolled {
+                delta,
                 modifiers: window_state
                     .keyboard_state
                     .borrow()
                     .get_modifiers_from_mouse_wparam(wparam),
-            });
+                device: window_state.mouse_message_device(),
+            };
+            let event =
+                Event::Mouse(if window_state.wheel_zoom { event.into_wheel_zoom() } else { event });
 
             window_state.handler.borrow_mut().as_mut().unwrap().on_event(&mut window, event);
 
@@ -272,6 +502,7 @@ unsafe fn wnd_proc_inner(
                                 .keyboard_state
                                 .borrow()
                                 .get_modifiers_from_mouse_wparam(wparam),
+                            device: window_state.mouse_message_device(),
                         }
                     }
                     WM_LBUTTONUP | WM_MBUTTONUP | WM_RBUTTONUP | WM_XBUTTONUP => {
@@ -287,6 +518,7 @@ unsafe fn wnd_proc_inner(
                                 .keyboard_state
                                 .borrow()
                                 .get_modifiers_from_mouse_wparam(wparam),
+                            device: window_state.mouse_message_device(),
                         }
                     }
                     _ => {
@@ -306,52 +538,165 @@ unsafe fn wnd_proc_inner(
 
             None
         }
-        WM_TIMER => {
+        WM_DEVICECHANGE => {
+            if let Some(event) = translate_device_change(wparam, lparam) {
+                let mut window = crate::Window::new(window_state.create_window());
+                window_state
+                    .handler
+                    .borrow_mut()
+                    .as_mut()
+                    .unwrap()
+                    .on_event(&mut window, Event::Device(event));
+            }
+
+            None
+        }
+        WM_CLIPBOARDUPDATE => {
             let mut window = crate::Window::new(window_state.create_window());
+            window_state
+                .handler
+                .borrow_mut()
+                .as_mut()
+                .unwrap()
+                .on_event(&mut window, Event::ClipboardChanged);
+
+            Some(0)
+        }
+        WM_POWERBROADCAST => {
+            let event = match wparam {
+                PBT_APMSUSPEND => Event::PowerSuspend,
+                // This is sent for every resume, while `PBT_APMRESUMESUSPEND` is only sent when
+                // the resume was triggered by the user
+                PBT_APMRESUMEAUTOMATIC => Event::PowerResume,
+                _ => return None,
+            };
+
+            let mut window = crate::Window::new(window_state.create_window());
+            window_state.handler.borrow_mut().as_mut().unwrap().on_event(&mut window, event);
+
+            Some(1)
+        }
+        WM_SETTINGCHANGE | BV_SETTINGS_CHANGED => {
+            window_state.wheel_settings.set(WheelSettings::query());
 
+            let mut window = crate::Window::new(window_state.create_window());
+            window_state
+                .handler
+                .borrow_mut()
+                .as_mut()
+                .unwrap()
+                .on_event(&mut window, Event::SystemSettingsChanged);
+
+            Some(0)
+        }
+        WM_HOTKEY => {
+            let id = window_state.global_shortcuts.find(wparam as i32)?;
+
+            let mut window = crate::Window::new(window_state.create_window());
+            window_state
+                .handler
+                .borrow_mut()
+                .as_mut()
+                .unwrap()
+                .on_event(&mut window, Event::GlobalShortcut(id));
+
+            Some(0)
+        }
+        WM_TIMER => {
             if wparam == WIN_FRAME_TIMER {
-                window_state.handler.borrow_mut().as_mut().unwrap().on_frame(&mut window);
+                #[cfg(feature = "virtual-clock")]
+                if window_state.virtual_frames.borrow().is_some() {
+                    while let Some(frame) = VirtualFrames::due_frame(
+                        &window_state.virtual_frames,
+                        &window_state.frame_counter,
+                    ) {
+                        window_state.draw_frame();
+                        frame.drawn();
+                    }
+
+                    return Some(0);
+                }
+
+                window_state.draw_frame();
+            }
+
+            Some(0)
+        }
+        BV_TICK_FRAME => {
+            if window_state.external_frame_clock {
+                window_state.draw_frame();
             }
 
             Some(0)
         }
         WM_CLOSE => {
-            // Make sure to release the borrow before the DefWindowProc call
-            {
-                let mut window = crate::Window::new(window_state.create_window());
+            // The window is destroyed once the current callback has returned, just like when the
+            // handler calls `Window::close()`
+            window_state.close_requested.set(true);
+            Some(0)
+        }
+        // This is only reached when the window is destroyed without going through
+        // `WindowState::close()`, for instance when the host destroys the parent window
+        WM_DESTROY => {
+            window_state.end_infinite_drag();
+            window_state.send_will_close();
+            None
+        }
+        // The layout change is also sent without keyboard input, for windows that only display the
+        // layout
+        WM_INPUTLANGCHANGE => {
+            window_state.keyboard_state.borrow_mut().process_message(hwnd, msg, wparam, lparam);
 
-                window_state
-                    .handler
-                    .borrow_mut()
-                    .as_mut()
-                    .unwrap()
-                    .on_event(&mut window, Event::Window(WindowEvent::WillClose));
-            }
+            let mut window = crate::Window::new(window_state.create_window());
+            window_state
+                .handler
+                .borrow_mut()
+                .as_mut()
+                .unwrap()
+                .on_event(&mut window, Event::Window(WindowEvent::KeyboardLayoutChanged));
 
-            // DestroyWindow(hwnd);
-            // Some(0)
-            Some(DefWindowProcW(hwnd, msg, wparam, lparam))
+            Some(0)
         }
-        WM_CHAR | WM_SYSCHAR | WM_KEYDOWN | WM_SYSKEYDOWN | WM_KEYUP | WM_SYSKEYUP
-        | WM_INPUTLANGCHANGE => {
+        WM_CHAR | WM_SYSCHAR | WM_DEADCHAR | WM_SYSDEADCHAR | WM_KEYDOWN | WM_SYSKEYDOWN
+        | WM_KEYUP | WM_SYSKEYUP => {
             let mut window = crate::Window::new(window_state.create_window());
 
             let opt_event =
                 window_state.keyboard_state.borrow_mut().process_message(hwnd, msg, wparam, lparam);
 
             if let Some(event) = opt_event {
-                window_state
-                    .handler
-                    .borrow_mut()
-                    .as_mut()
-                    .unwrap()
-                    .on_event(&mut window, Event::Keyboard(event));
+                let modifiers = event.modifiers;
+                let event = window_state.accelerators.translate(event);
+                let mut handler = window_state.handler.borrow_mut();
+                if window_state.modifiers.replace(modifiers) != modifiers {
+                    let modifiers_changed = WindowEvent::ModifiersChanged(modifiers);
+                    handler
+                        .as_mut()
+                        .unwrap()
+                        .on_event(&mut window, Event::Window(modifiers_changed));
+                }
+                if let Some(text) = window_state.pending_text.key_event(&event) {
+                    handler.as_mut().unwrap().on_event(&mut window, text);
+                }
+                let _scancode = KeyScancode::set(keyboard::scan_code(lparam));
+                handler.as_mut().unwrap().on_event(&mut window, event);
             }
 
-            if msg != WM_SYSKEYDOWN {
-                Some(0)
-            } else {
+            // Handling the system key messages keeps `DefWindowProc()` from beeping at Alt+key
+            // combinations, and from activating the host's menu bar when Alt or F10 is released
+            let vk = wparam as INT;
+            let is_menu_key = vk == VK_MENU || vk == VK_F10;
+            let pass_on = match msg {
+                WM_SYSKEYDOWN => {
+                    vk == VK_F4 || (is_menu_key && !window_state.input_events.menu_keys)
+                }
+                WM_SYSKEYUP => is_menu_key && !window_state.input_events.menu_keys,
+                _ => false,
+            };
+            if pass_on {
                 None
+            } else {
+                Some(0)
             }
         }
         WM_SIZE => {
@@ -360,25 +705,50 @@ unsafe fn wnd_proc_inner(
                 height: ((lparam >> 16) & 0xFFFF) as u16 as u32,
             };
 
+            // Minimized and zero sized windows are suspended instead, and resized once they're
+            // visible again
+            let suspended = wparam == SIZE_MINIMIZED
+                || new_physical_size.width == 0
+                || new_physical_size.height == 0;
+            if suspended != window_state.suspended.replace(suspended) {
+                let mut window = crate::Window::new(window_state.create_window());
+                let event = if suspended { WindowEvent::Suspended } else { WindowEvent::Resumed };
+                window_state
+                    .handler
+                    .borrow_mut()
+                    .as_mut()
+                    .unwrap()
+                    .on_event(&mut window, Event::Window(event));
+            }
+
             // Only send the event if anything changed
-            if new_physical_size == window_state.current_size.get() {
+            if suspended || new_physical_size == window_state.current_size.get() {
                 return None;
             }
 
             window_state.current_size.set(new_physical_size);
 
+            #[cfg(feature = "opengl")]
+            if let Some(gl_context) = window_state.gl_context.get() {
+                gl_context.resized(new_physical_size);
+            }
+
             let mut window = crate::Window::new(window_state.create_window());
             let new_size = WindowInfo::from_physical_size(
                 new_physical_size,
                 window_state.current_scale_factor.get(),
             );
+            let source = window_state.resize_source.get();
 
             window_state
                 .handler
                 .borrow_mut()
                 .as_mut()
                 .unwrap()
-                .on_event(&mut window, Event::Window(WindowEvent::Resized(new_size)));
+                .on_event(&mut window, Event::Window(WindowEvent::Resized(new_size, source)));
+
+            // The contents under the cursor have most likely moved along with the new size
+            window_state.refresh_cursor_position();
 
             None
         }
@@ -391,6 +761,87 @@ unsafe fn wnd_proc_inner(
 
             None
         }
+        WM_DISPLAYCHANGE => {
+            // A display was added or removed, or its resolution changed. The monitor's scale factor
+            // may have changed with it, which will resize the window and send a resize event.
+            window_state.set_new_scale_factor(window_state.window.current_scale_factor(), None);
+
+            let color_depth = window_state.window.current_color_depth();
+            if window_state.color_depth.replace(color_depth) != color_depth {
+                #[cfg(feature = "opengl")]
+                if let Some(gl_context) = window_state.gl_context.get() {
+                    gl_context.surface_changed();
+                }
+
+                let mut window = crate::Window::new(window_state.create_window());
+                window_state
+                    .handler
+                    .borrow_mut()
+                    .as_mut()
+                    .unwrap()
+                    .on_event(&mut window, Event::Window(WindowEvent::SurfaceChanged));
+            }
+
+            let monitor_info = MonitorInfo {
+                scale: window_state.current_scale_factor.get(),
+                refresh_rate: window_state.window.current_refresh_rate(),
+            };
+            if window_state.frame_counter.set_refresh_rate(monitor_info.refresh_rate) {
+                window_state.start_frame_timer();
+            }
+
+            let mut window = crate::Window::new(window_state.create_window());
+            window_state
+                .handler
+                .borrow_mut()
+                .as_mut()
+                .unwrap()
+                .on_event(&mut window, Event::Window(WindowEvent::MonitorChanged(monitor_info)));
+
+            None
+        }
+        // Hosts that use `IsDialogMessage()` in their message loop would otherwise use Tab, Enter,
+        // and the arrow keys for dialog navigation. Claiming the characters also stops the dialog
+        // from treating letter keys as mnemonics for its other controls.
+        WM_GETDLGCODE => {
+            if window_state.text_input.get().is_some() {
+                Some(DLGC_WANTALLKEYS)
+            } else if window_state.input_events.navigation_keys {
+                Some(DLGC_WANTARROWS | DLGC_WANTTAB | DLGC_WANTCHARS)
+            } else {
+                None
+            }
+        }
+        WM_IME_STARTCOMPOSITION => {
+            window_state.position_ime();
+            // The handler draws the preedit text instead of the IME's composition window
+            if window_state.text_input.get().is_some() {
+                Some(0)
+            } else {
+                None
+            }
+        }
+        // Without text input, `DefWindowProc()` sends the composed text as `WM_CHAR` messages
+        WM_IME_COMPOSITION if window_state.text_input.get().is_some() => {
+            let events = match ImeContext::get(hwnd) {
+                Some(ime) => ime.composition_events(lparam as DWORD),
+                None => Vec::new(),
+            };
+            for event in events {
+                window_state.send_ime_event(event);
+            }
+
+            Some(0)
+        }
+        WM_IME_ENDCOMPOSITION if window_state.text_input.get().is_some() => {
+            // The composition may have been cancelled without committing anything
+            if window_state.ime_composing.get() {
+                window_state
+                    .send_ime_event(ImeEvent::Preedit { text: String::new(), cursor: None });
+            }
+
+            Some(0)
+        }
         // If WM_SETCURSOR returns `None`, WM_SETCURSOR continues to get handled by the outer window(s),
         // If it returns `Some(1)`, the current window decides what the cursor is
         WM_SETCURSOR => {
@@ -398,8 +849,11 @@ unsafe fn wnd_proc_inner(
             let mouse_in_window = low_word == HTCLIENT;
             if mouse_in_window {
                 // Here we need to set the cursor back to what the state says, since it can have changed when outside the window
-                let cursor =
-                    LoadCursorW(null_mut(), cursor_to_lpcwstr(window_state.cursor_icon.get()));
+                let cursor = if window_state.infinite_drag.borrow().is_some() {
+                    null_mut()
+                } else {
+                    LoadCursorW(null_mut(), cursor_to_lpcwstr(window_state.cursor_icon.get()))
+                };
                 unsafe {
                     SetCursor(cursor);
                 }
@@ -412,37 +866,70 @@ unsafe fn wnd_proc_inner(
         // NOTE: `WM_NCDESTROY` is handled in the outer function because this deallocates the window
         //        state
         BV_WINDOW_MUST_CLOSE => {
-            DestroyWindow(hwnd);
+            window_state.close_requested.set(true);
+            Some(0)
+        }
+        WM_NCCALCSIZE if wparam != 0 && window_state.window.is_borderless() => {
+            let params = &mut *(lparam as *mut NCCALCSIZE_PARAMS);
+            window_state.window.borderless_client_rect(&mut params.rgrc[0]);
+
             Some(0)
         }
+        WM_NCHITTEST if window_state.window.is_borderless() => {
+            let x = (lparam & 0xFFFF) as i16 as i32;
+            let y = ((lparam >> 16) & 0xFFFF) as i16 as i32;
+
+            Some(window_state.window.borderless_hit_test(x, y))
+        }
+        BV_SET_SCALE_FACTOR => {
+            let task = WindowTask::SetScaleFactor(*(lparam as *const f64));
+            window_state.deferred_tasks.borrow_mut().push_back(task);
+
+            Some(0)
+        }
+        BV_HOST_KEY_EVENT => {
+            // The handler can't be called from within one of its own callbacks
+            let mut handler = match window_state.handler.try_borrow_mut() {
+                Ok(handler) => handler,
+                Err(_) => return Some(0),
+            };
+
+            let event = (*(lparam as *const KeyboardEvent)).clone();
+            let event = window_state.accelerators.translate(event);
+            let mut window = crate::Window::new(window_state.create_window());
+            if let Some(text) = window_state.pending_text.key_event(&event) {
+                handler.as_mut().unwrap().on_event(&mut window, text);
+            }
+            let status = handler.as_mut().unwrap().on_event(&mut window, event);
+
+            Some((status == EventStatus::Captured) as LRESULT)
+        }
         _ => None,
     }
 }
 
-unsafe fn register_wnd_class() -> ATOM {
-    // We generate a unique name for the new window class to prevent name collisions
-    let class_name_str = format!("Baseview-{}", generate_guid());
-    let mut class_name: Vec<u16> = OsStr::new(&class_name_str).encode_wide().collect();
-    class_name.push(0);
-
-    let wnd_class = WNDCLASSW {
-        style: CS_OWNDC,
-        lpfnWndProc: Some(wnd_proc),
-        hInstance: null_mut(),
-        lpszClassName: class_name.as_ptr(),
-        cbClsExtra: 0,
-        cbWndExtra: 0,
-        hIcon: null_mut(),
-        hCursor: LoadCursorW(null_mut(), IDC_ARROW),
-        hbrBackground: null_mut(),
-        lpszMenuName: null_mut(),
-    };
-
-    RegisterClassW(&wnd_class)
+/// The user's scroll wheel settings.
+#[derive(Debug, Clone, Copy)]
+struct WheelSettings {
+    /// The number of lines to scroll per vertical notch, or `WHEEL_PAGESCROLL` to scroll a page
+    /// at a time.
+    lines_per_notch: UINT,
+    /// The number of characters to scroll per horizontal notch.
+    chars_per_notch: UINT,
 }
 
-unsafe fn unregister_wnd_class(wnd_class: ATOM) {
-    UnregisterClassW(wnd_class as _, null_mut());
+impl WheelSettings {
+    fn query() -> Self {
+        let mut settings = Self { lines_per_notch: 3, chars_per_notch: 3 };
+        unsafe {
+            let lines_per_notch = &mut settings.lines_per_notch as *mut UINT as *mut _;
+            SystemParametersInfoW(SPI_GETWHEELSCROLLLINES, 0, lines_per_notch, 0);
+            let chars_per_notch = &mut settings.chars_per_notch as *mut UINT as *mut _;
+            SystemParametersInfoW(SPI_GETWHEELSCROLLCHARS, 0, chars_per_notch, 0);
+        }
+
+        settings
+    }
 }
 
 /// All data associated with the window. This uses internal mutability so the outer struct doesn't
@@ -455,18 +942,69 @@ pub(super) struct WindowState {
     /// struct associated with this HWND through `unsafe { GetWindowLongPtrW(self.hwnd,
     /// GWLP_USERDATA) } as *const WindowState`.
     pub window: Win32Window,
-    window_class: ATOM,
     current_size: Cell<PhySize>,
+    /// Why the window is being resized while baseview is resizing it, which is reported along with
+    /// the `WM_SIZE` message that `SetWindowPos()` sends before it returns.
+    resize_source: Cell<ResizeSource>,
     current_scale_factor: Cell<f64>,
+    /// The color depth of the window's display. The handler's surfaces need to be recreated when
+    /// this changes.
+    color_depth: Cell<Option<u32>>,
     _parent_handle: Option<ParentHandle>,
     keyboard_state: RefCell<KeyboardState>,
+    /// The modifiers of the last key event, see [`crate::Window::modifiers()`].
+    modifiers: Cell<Modifiers>,
     mouse_button_counter: Cell<usize>,
-    mouse_was_outside_window: RefCell<bool>,
+    pub(super) hover: HoverState,
     cursor_icon: Cell<MouseCursor>,
+    /// The keyboard hook installed while this window holds the keyboard grab.
+    keyboard_hook: Cell<HHOOK>,
+    /// The touch or pen device from the last `WM_POINTER*` message. Windows promotes those to
+    /// regular mouse messages, which don't say which device they came from.
+    pointer_device: Cell<Option<PointerDevice>>,
+    /// The nesting depth of `wnd_proc()` calls for this window, used to determine event batches.
+    wnd_proc_depth: Cell<usize>,
+    /// Whether the handler's `before_events()` has been called without a matching
+    /// `after_events()`.
+    in_event_batch: Cell<bool>,
+    /// Set when the window should be closed once the current message has been handled.
+    close_requested: Cell<bool>,
+    /// Set once the handler has received `WillClose`. It doesn't receive any callbacks after that.
+    will_close_sent: Cell<bool>,
+    _device_notifications: DeviceNotifications,
+    /// Whether `OleInitialize()` succeeded, in which case it needs to be balanced by a call to
+    /// `OleUninitialize()` when the window is destroyed.
+    ole_initialized: bool,
+    /// Only needed for child windows, top level windows always receive power notifications.
+    _power_notifications: Option<PowerNotifications>,
+    /// Only needed for child windows, `WM_SETTINGCHANGE` is only broadcast to top level windows.
+    _settings_notifications: Option<SettingsNotifications>,
     // Initialized late so the `Window` can hold a reference to this `WindowState`
     handler: RefCell<Option<Box<dyn WindowHandler>>>,
     _drop_target: RefCell<Option<Rc<DropTarget>>>,
     scale_policy: WindowScalePolicy,
+    input_events: InputEvents,
+    /// The infinite drag in progress, see [`crate::Window::begin_infinite_drag()`].
+    infinite_drag: RefCell<Option<InfiniteDrag>>,
+    accelerators: Accelerators,
+    global_shortcuts: GlobalShortcuts,
+    frame_counter: FrameCounter,
+    /// The text field the user is typing in, see [`crate::Window::set_text_input_active()`].
+    text_input: Cell<Option<Rect>>,
+    /// See [`crate::Window::set_ime_cursor_area()`]. The IME is placed at the text field if this is
+    /// `None`.
+    ime_cursor_area: Cell<Option<Rect>>,
+    /// Whether the handler was last sent non-empty preedit text, see [`ImeEvent::Preedit`].
+    ime_composing: Cell<bool>,
+    /// Text typed during the current event batch that hasn't been sent yet, see [`Event::Text`].
+    pending_text: PendingText,
+    /// See [`WindowOpenOptions::wheel_zoom`].
+    wheel_zoom: bool,
+    /// Cached since they're needed for every scroll wheel notch.
+    wheel_settings: Cell<WheelSettings>,
+    /// See [`WindowOpenOptions::external_frame_clock`]. The frame timer isn't started, and frames
+    /// are drawn when the host calls [`WindowHandle::tick_frame()`] instead.
+    external_frame_clock: bool,
 
     /// Tasks that should be executed at the end of `wnd_proc`. This is needed to avoid mutably
     /// borrowing the fields from `WindowState` more than once. For instance, when the window
@@ -474,9 +1012,15 @@ pub(super) struct WindowState {
     /// borrowed in `wnd_proc`. So the `resize()` function below cannot also mutably borrow that
     /// window state at the same time.
     pub deferred_tasks: RefCell<VecDeque<WindowTask>>,
+    /// Whether the handler was last told that the window is suspended, see
+    /// [`WindowEvent::Suspended`].
+    suspended: Cell<bool>,
 
     #[cfg(feature = "opengl")]
-    pub gl_context: Option<GlContext>,
+    pub gl_context: GlContextCell,
+
+    #[cfg(feature = "virtual-clock")]
+    virtual_frames: RefCell<Option<VirtualFrames>>,
 }
 
 impl WindowState {
@@ -503,19 +1047,300 @@ impl WindowState {
                 // `self.window_info` will be modified in response to the `WM_SIZE` event that
                 // follows the `SetWindowPos()` call
                 let scaling = self.current_scale_factor.get();
-                let new_size = WindowInfo::from_logical_size(size, scaling);
+                let new_size = WindowInfo::from_logical_size_snapped(size, scaling);
 
+                let source = self.resize_source.replace(ResizeSource::Handler);
                 self.window.resize(new_size.physical_size());
+                self.resize_source.set(source);
+            }
+            WindowTask::SetScaleFactor(scale) => {
+                if let WindowScalePolicy::HostControlled(_) = self.scale_policy {
+                    self.set_host_scale_factor(scale);
+                }
+            }
+            WindowTask::SendClipboardData(format) => {
+                // The callback that requested the data may have closed the window
+                if !self.is_closing() {
+                    let data = clipboard::read(self.window.handle, &format);
+                    let mut window = crate::Window::new(self.create_window());
+                    self.handler
+                        .borrow_mut()
+                        .as_mut()
+                        .unwrap()
+                        .on_event(&mut window, format.into_event(data));
+                }
+            }
+            WindowTask::StartDrag(data) => {
+                if self.is_closing() {
+                    return;
+                }
+
+                let effect = drag_source::drag(&data);
+                if !self.is_closing() {
+                    let mut window = crate::Window::new(self.create_window());
+                    self.handler
+                        .borrow_mut()
+                        .as_mut()
+                        .unwrap()
+                        .on_event(&mut window, Event::DragFinished(effect));
+                }
+            }
+            WindowTask::SendImeDisabled => {
+                if !self.is_closing() {
+                    self.send_ime_event(ImeEvent::Disabled);
+                }
             }
         }
     }
 
+    /// Apply a scale factor set through [`WindowHandle::set_scale_factor()`].
+    fn set_host_scale_factor(&self, new_scale_factor: f64) {
+        let current_size = self.window_info();
+        let new_size =
+            WindowInfo::from_logical_size_snapped(current_size.logical_size(), new_scale_factor);
+        if new_size.physical_size() != current_size.physical_size() {
+            self.apply_scale_factor(new_scale_factor, None);
+            return;
+        }
+
+        // The window won't receive a `WM_SIZE` event if its physical size stays the same, so the
+        // handler needs to be told about the new scale factor here
+        self.current_scale_factor.set(new_scale_factor);
+
+        let mut window = crate::Window::new(self.create_window());
+        let new_size =
+            WindowInfo::from_physical_size(current_size.physical_size(), new_scale_factor);
+        let source = ResizeSource::ScaleFactor;
+        self.handler
+            .borrow_mut()
+            .as_mut()
+            .unwrap()
+            .on_event(&mut window, Event::Window(WindowEvent::Resized(new_size, source)));
+        self.refresh_cursor_position();
+    }
+
+    /// Post a `WM_MOUSEMOVE` message for the cursor's current position if it's over the window,
+    /// see [`crate::Window::refresh_cursor_position()`]. The message is handled after the current
+    /// callback has returned, like any other cursor movement.
+    pub(super) fn refresh_cursor_position(&self) {
+        let hwnd = self.window.handle;
+
+        unsafe {
+            let mut point = POINT { x: 0, y: 0 };
+            if GetCursorPos(&mut point) == 0 {
+                return;
+            }
+
+            // The cursor may be over a window covering this one, or over a child window like a GL
+            // area
+            let window_under_cursor = WindowFromPoint(point);
+            if window_under_cursor != hwnd && IsChild(hwnd, window_under_cursor) == 0 {
+                return;
+            }
+            ScreenToClient(hwnd, &mut point);
+
+            let mut key_state = 0;
+            for &(vk, mk) in &[
+                (VK_CONTROL, MK_CONTROL),
+                (VK_SHIFT, MK_SHIFT),
+                (VK_LBUTTON, MK_LBUTTON),
+                (VK_MBUTTON, MK_MBUTTON),
+                (VK_RBUTTON, MK_RBUTTON),
+            ] {
+                if GetKeyState(vk) < 0 {
+                    key_state |= mk;
+                }
+            }
+
+            let lparam = (point.x as u16 as u32 | (point.y as u16 as u32) << 16) as LPARAM;
+            PostMessageW(hwnd, WM_MOUSEMOVE, key_state as WPARAM, lparam);
+        }
+    }
+
+    /// End the current infinite drag, if any, and show the cursor again.
+    fn end_infinite_drag(&self) {
+        if self.infinite_drag.borrow_mut().take().is_some() {
+            unsafe {
+                SetCursor(LoadCursorW(null_mut(), cursor_to_lpcwstr(self.cursor_icon.get())))
+            };
+        }
+    }
+
+    pub(super) fn is_closing(&self) -> bool {
+        self.close_requested.get() || self.will_close_sent.get()
+    }
+
+    /// Finish the current event batch and send `WillClose` to the handler, then destroy the
+    /// window. The handler doesn't receive any more callbacks after this.
+    unsafe fn close(&self) {
+        self.send_will_close();
+        DestroyWindow(self.window.handle);
+        window_class::unregister_if_unused();
+    }
+
+    fn send_will_close(&self) {
+        if self.will_close_sent.get() {
+            return;
+        }
+
+        self.end_event_batch();
+        self.will_close_sent.set(true);
+
+        if let Some(handler) = self.handler.borrow_mut().as_mut() {
+            let mut window = crate::Window::new(self.create_window());
+            handler.on_event(&mut window, Event::Window(WindowEvent::WillClose));
+        }
+    }
+
+    fn draw_frame(&self) {
+        // The handler can't be called from within one of its own callbacks
+        if self.handler.try_borrow_mut().is_err() {
+            return;
+        }
+
+        // The animation frame is an event like any other, so it's sent as part of a batch
+        if let Some(animation_frame) = self.frame_counter.frame(self.external_frame_clock) {
+            self.begin_event_batch();
+            let mut window = crate::Window::new(self.create_window());
+            self.handler
+                .borrow_mut()
+                .as_mut()
+                .unwrap()
+                .on_event(&mut window, Event::Window(WindowEvent::AnimationFrame(animation_frame)));
+            self.end_event_batch();
+        }
+
+        let mut handler = self.handler.borrow_mut();
+        let mut window = crate::Window::new(self.create_window());
+        let handler = handler.as_mut().unwrap();
+        handler.on_frame(&mut window);
+        #[cfg(feature = "opengl")]
+        window.report_present(handler.as_mut());
+    }
+
+    /// Start the frame timer at the interval for the window's display, or restart it after the
+    /// interval changed.
+    fn start_frame_timer(&self) {
+        if !self.external_frame_clock {
+            let interval = self.frame_counter.interval().as_millis() as u32;
+            unsafe { SetTimer(self.window.handle, WIN_FRAME_TIMER, interval, None) };
+        }
+    }
+
+    fn begin_event_batch(&self) {
+        if self.in_event_batch.get() || self.is_closing() {
+            return;
+        }
+
+        // The handler is initialized after the window has been created
+        if let Some(handler) = self.handler.borrow_mut().as_mut() {
+            self.in_event_batch.set(true);
+
+            let mut window = crate::Window::new(self.create_window());
+            handler.before_events(&mut window);
+        }
+    }
+
+    fn end_event_batch(&self) {
+        if !self.in_event_batch.replace(false) {
+            return;
+        }
+
+        if let Some(handler) = self.handler.borrow_mut().as_mut() {
+            let mut window = crate::Window::new(self.create_window());
+            if let Some(text) = self.pending_text.take() {
+                handler.on_event(&mut window, text);
+            }
+            handler.after_events(&mut window);
+        }
+    }
+
+    /// The device that generated the mouse message currently being handled. Mouse messages that
+    /// were synthesized from touch or pen input are marked with a signature in their extra info.
+    unsafe fn mouse_message_device(&self) -> PointerDevice {
+        const MI_WP_SIGNATURE: usize = 0xFF515700;
+        const SIGNATURE_MASK: usize = 0xFFFFFF00;
+        const TOUCH_BIT: usize = 0x80;
+
+        let extra_info = GetMessageExtraInfo() as usize;
+        if extra_info & SIGNATURE_MASK != MI_WP_SIGNATURE {
+            return PointerDevice { id: 0, pointer_type: PointerType::Mouse };
+        }
+
+        self.pointer_device.get().unwrap_or(PointerDevice {
+            id: 0,
+            pointer_type: if extra_info & TOUCH_BIT != 0 {
+                PointerType::Touchscreen
+            } else {
+                PointerType::Tablet
+            },
+        })
+    }
+
+    /// Send an input method event, after any pending text that needs to go first.
+    fn send_ime_event(&self, event: ImeEvent) {
+        if let ImeEvent::Preedit { text, .. } = &event {
+            self.ime_composing.set(!text.is_empty());
+        } else {
+            self.ime_composing.set(false);
+        }
+
+        let event = Event::Ime(event);
+        let mut window = crate::Window::new(self.create_window());
+        let mut handler = self.handler.borrow_mut();
+        if let Some(text) = self.pending_text.key_event(&event) {
+            handler.as_mut().unwrap().on_event(&mut window, text);
+        }
+        handler.as_mut().unwrap().on_event(&mut window, event);
+    }
+
+    /// Place the IME's windows at the text field's cursor, if text input is active.
+    fn position_ime(&self) {
+        let rect = match self.text_input.get() {
+            Some(rect) => self.ime_cursor_area.get().unwrap_or(rect),
+            None => return,
+        };
+
+        let window_info = self.window_info();
+        let origin = rect.origin.to_physical(&window_info);
+        let size = rect.size.to_physical(&window_info);
+        let area = RECT {
+            left: origin.x,
+            top: origin.y,
+            right: origin.x + size.width as i32,
+            bottom: origin.y + size.height as i32,
+        };
+
+        if let Some(ime) = unsafe { ImeContext::get(self.window.handle) } {
+            ime.set_cursor_area(area);
+        }
+    }
+
+    fn release_keyboard_grab(&self) {
+        let hook = self.keyboard_hook.replace(null_mut());
+        if !hook.is_null() {
+            unsafe { UnhookWindowsHookEx(hook) };
+        }
+
+        KEYBOARD_GRAB_HWND.with(|hwnd| {
+            if hwnd.get() == self.window.handle {
+                hwnd.set(null_mut());
+            }
+        });
+    }
+
     fn set_new_scale_factor(&self, new_scale_factor: f64, suggested_dimensions: Option<&RECT>) {
         // We don't care about window DPI changes when using a forced scale factor
         if self.scale_policy != WindowScalePolicy::SystemScaleFactor {
             return;
         }
 
+        self.apply_scale_factor(new_scale_factor, suggested_dimensions);
+    }
+
+    /// Resize the window to keep its logical size at the new scale factor. The handler is sent a
+    /// resize event with the new scale factor once the window has been resized.
+    fn apply_scale_factor(&self, new_scale_factor: f64, suggested_dimensions: Option<&RECT>) {
         let current_scale_factor = self.current_scale_factor.get();
 
         if new_scale_factor == current_scale_factor {
@@ -526,6 +1351,7 @@ impl WindowState {
 
         // Windows makes us resize the window manually. This will trigger another `WM_SIZE` event,
         // which will then set the actual size and send the new scale factor to the handler.
+        let source = self.resize_source.replace(ResizeSource::ScaleFactor);
         if let Some(suggested_dimensions) = suggested_dimensions {
             // If we have suggested dimensions (from the DPI changed event), then we use them directly
             self.window.set_raw_pos(suggested_dimensions);
@@ -534,11 +1360,14 @@ impl WindowState {
             let current_size =
                 WindowInfo::from_physical_size(self.current_size.get(), current_scale_factor);
 
-            let new_size =
-                WindowInfo::from_logical_size(current_size.logical_size(), new_scale_factor);
+            let new_size = WindowInfo::from_logical_size_snapped(
+                current_size.logical_size(),
+                new_scale_factor,
+            );
 
             self.window.resize(new_size.physical_size());
         }
+        self.resize_source.set(source);
     }
 }
 
@@ -549,6 +1378,18 @@ pub(super) enum WindowTask {
     /// Resize the window to the given size. The size is in logical pixels. DPI scaling is applied
     /// automatically.
     Resize(Size),
+    /// Change the window's scale factor while keeping its logical size. Only used with
+    /// [`WindowScalePolicy::HostControlled`].
+    SetScaleFactor(f64),
+    /// Send the clipboard's data in a format to the handler, see
+    /// [`crate::Window::request_clipboard_data()`].
+    SendClipboardData(ClipboardFormat),
+    /// Drag data out of the window, see [`crate::Window::start_drag()`]. This runs a modal loop,
+    /// so it can't happen from within the handler's callback.
+    StartDrag(DragData),
+    /// Send [`ImeEvent::Disabled`] after [`crate::Window::clear_text_input()`] cancelled a
+    /// composition.
+    SendImeDisabled,
 }
 
 pub struct Window<'a> {
@@ -573,7 +1414,7 @@ impl Window<'_> {
         window_handle
     }
 
-    pub fn open_blocking<H, B>(options: WindowOpenOptions, build: B)
+    pub fn open_blocking<H, B>(options: WindowOpenOptions, build: B, quits_with_app: bool)
     where
         H: WindowHandler + 'static,
         B: FnOnce(&mut crate::Window) -> H,
@@ -584,13 +1425,23 @@ impl Window<'_> {
         unsafe {
             let mut msg: MSG = std::mem::zeroed();
 
-            loop {
-                let status = GetMessageW(&mut msg, hwnd, 0, 0);
+            // Messages for all of the thread's windows need to be dispatched, including the hidden
+            // windows COM uses for drag and drop
+            while IsWindow(hwnd) != 0 {
+                let status = GetMessageW(&mut msg, null_mut(), 0, 0);
 
                 if status == -1 {
                     break;
                 }
 
+                // A `WM_QUIT` message means the application should quit, and `run_app()` asks its
+                // window to close when the process is interrupted. The window is still closed
+                // normally in both cases so the handler receives `WillClose`.
+                if status == 0 || (quits_with_app && crate::app::quit_requested()) {
+                    SendMessageW(hwnd, WM_CLOSE, 0, 0);
+                    break;
+                }
+
                 TranslateMessage(&msg);
                 DispatchMessageW(&msg);
             }
@@ -606,19 +1457,30 @@ impl Window<'_> {
         B: Send + 'static,
     {
         unsafe {
-            let window_class = register_wnd_class();
+            // COM needs to be initialized before registering the drop target below. Every successful
+            // call must be balanced by a call to `OleUninitialize()`, which happens when the window
+            // is destroyed.
+            let ole_initialized = SUCCEEDED(OleInitialize(null_mut()));
+
+            let window_class = window_class::acquire(wnd_proc);
             // todo: manage error ^
 
             let initial_scale_factor = match options.scale {
                 WindowScalePolicy::SystemScaleFactor => 1.0,
-                WindowScalePolicy::ScaleFactor(scale) => scale,
+                WindowScalePolicy::ScaleFactor(scale)
+                | WindowScalePolicy::HostControlled(scale) => scale,
+                WindowScalePolicy::ForceUnscaled => 1.0,
             };
 
-            let initial_size =
-                WindowInfo::from_logical_size(options.size, initial_scale_factor).physical_size();
+            let initial_size = options.initial_window_info(initial_scale_factor).physical_size();
 
-            let raw_window =
-                Win32Window::create(window_class, &options.title, initial_size, parent);
+            let raw_window = Win32Window::create(
+                window_class,
+                &options.title,
+                initial_size,
+                parent,
+                options.borderless,
+            );
 
             #[cfg(feature = "opengl")]
             let gl_context: Option<GlContext> = options.gl_config.and_then(|gl_config| {
@@ -632,26 +1494,69 @@ impl Window<'_> {
             let (parent_handle, window_handle) = ParentHandle::new(raw_window.handle);
             let parent_handle = if parent.is_some() { Some(parent_handle) } else { None };
 
+            let device_notifications = DeviceNotifications::register(raw_window.handle);
+            let power_notifications = if parent.is_some() {
+                Some(PowerNotifications::register(raw_window.handle))
+            } else {
+                None
+            };
+            let settings_notifications = if parent.is_some() {
+                Some(SettingsNotifications::register(raw_window.handle))
+            } else {
+                None
+            };
+
+            let color_depth = raw_window.current_color_depth();
+            let global_shortcuts = GlobalShortcuts::new(raw_window.handle);
+
             let window_state = Rc::new(WindowState {
                 window: raw_window,
-                window_class,
                 current_size: Cell::new(initial_size),
+                resize_source: Cell::new(ResizeSource::External),
                 current_scale_factor: Cell::new(initial_scale_factor),
+                color_depth: Cell::new(color_depth),
                 _parent_handle: parent_handle,
                 keyboard_state: RefCell::new(KeyboardState::new()),
+                modifiers: Cell::new(Modifiers::empty()),
                 mouse_button_counter: Cell::new(0),
-                mouse_was_outside_window: RefCell::new(true),
+                hover: HoverState::default(),
                 cursor_icon: Cell::new(MouseCursor::Default),
+                keyboard_hook: Cell::new(null_mut()),
+                pointer_device: Cell::new(None),
+                wnd_proc_depth: Cell::new(0),
+                in_event_batch: Cell::new(false),
+                close_requested: Cell::new(false),
+                will_close_sent: Cell::new(false),
+                _device_notifications: device_notifications,
+                ole_initialized,
+                _power_notifications: power_notifications,
+                _settings_notifications: settings_notifications,
                 // The Window refers to this `WindowState`, so this `handler` needs to be
                 // initialized later
                 handler: RefCell::new(None),
                 _drop_target: RefCell::new(None),
                 scale_policy: options.scale,
+                input_events: options.input_events,
+                infinite_drag: RefCell::new(None),
+                accelerators: Accelerators::default(),
+                global_shortcuts,
+                frame_counter: FrameCounter::default(),
+                text_input: Cell::new(None),
+                ime_cursor_area: Cell::new(None),
+                ime_composing: Cell::new(false),
+                pending_text: PendingText::default(),
+                wheel_zoom: options.wheel_zoom,
+                wheel_settings: Cell::new(WheelSettings::query()),
+                external_frame_clock: options.external_frame_clock && parent.is_some(),
 
                 deferred_tasks: RefCell::new(VecDeque::with_capacity(4)),
+                suspended: Cell::new(false),
 
                 #[cfg(feature = "opengl")]
-                gl_context,
+                gl_context: GlContextCell::new(gl_context),
+
+                #[cfg(feature = "virtual-clock")]
+                virtual_frames: RefCell::new(None),
             });
 
             let handler = {
@@ -662,14 +1567,16 @@ impl Window<'_> {
             *window_state.handler.borrow_mut() = Some(Box::new(handler));
 
             // Only works on Windows 10 unfortunately.
-            SetProcessDpiAwarenessContext(
-                winapi::shared::windef::DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE,
-            );
+            if options.scale != WindowScalePolicy::ForceUnscaled {
+                SetProcessDpiAwarenessContext(
+                    winapi::shared::windef::DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE,
+                );
+            }
 
-            let drop_target = Rc::new(DropTarget::new(Rc::downgrade(&window_state)));
+            let drop_target =
+                Rc::new(DropTarget::new(Rc::downgrade(&window_state), &options.drop_formats));
             *window_state._drop_target.borrow_mut() = Some(drop_target.clone());
 
-            OleInitialize(null_mut());
             RegisterDragDrop(window_state.window.handle, Rc::as_ptr(&drop_target) as LPDROPTARGET);
 
             SetWindowLongPtrW(
@@ -677,7 +1584,13 @@ impl Window<'_> {
                 GWLP_USERDATA,
                 Rc::into_raw(window_state.clone()) as *const _ as _,
             );
-            SetTimer(window_state.window.handle, WIN_FRAME_TIMER, 15, None);
+            window_state.frame_counter.set_refresh_rate(window_state.window.current_refresh_rate());
+            window_state.start_frame_timer();
+            AddClipboardFormatListener(window_state.window.handle);
+
+            if window_state.window.is_borderless() {
+                window_state.window.enable_borderless_frame();
+            }
 
             // Now that the window exists, we can get the actual DPI of the screen it's on.
             window_state.set_new_scale_factor(window_state.window.current_scale_factor(), None);
@@ -686,13 +1599,122 @@ impl Window<'_> {
         }
     }
 
+    pub fn refresh_cursor_position(&mut self) {
+        self.state.refresh_cursor_position();
+    }
+
+    pub fn frame_stats(&self) -> FrameStats {
+        self.state.frame_counter.stats()
+    }
+
+    pub fn refresh_rate(&self) -> Option<f64> {
+        self.state.window.current_refresh_rate()
+    }
+
+    pub fn modifiers(&self) -> Modifiers {
+        self.state.modifiers.get()
+    }
+
+    pub fn set_animation_frames(&mut self, enabled: bool) {
+        self.state.frame_counter.set_animation_frames(enabled);
+    }
+
+    pub fn request_clipboard_data(&mut self, format: ClipboardFormat) {
+        self.state.deferred_tasks.borrow_mut().push_back(WindowTask::SendClipboardData(format));
+    }
+
+    pub fn start_drag(&mut self, data: DragData) {
+        self.state.deferred_tasks.borrow_mut().push_back(WindowTask::StartDrag(data));
+    }
+
     pub fn close(&mut self) {
+        self.state.close_requested.set(true);
+
+        // Callbacks from the drop target don't happen inside of the window procedure, so the
+        // window still needs to be woken up to actually close it
         unsafe {
             PostMessageW(self.state.window.handle, BV_WINDOW_MUST_CLOSE, 0, 0);
         }
     }
 
-    pub fn has_focus(&mut self) -> bool {
+    pub fn begin_infinite_drag(&mut self) {
+        let mut infinite_drag = self.state.infinite_drag.borrow_mut();
+        if infinite_drag.is_none() {
+            let window_info = self.state.window_info();
+            *infinite_drag =
+                Some(unsafe { InfiniteDrag::begin(self.state.window.handle, &window_info) });
+            unsafe { SetCursor(null_mut()) };
+        }
+    }
+
+    pub fn end_infinite_drag(&mut self) {
+        self.state.end_infinite_drag();
+    }
+
+    pub fn register_accelerator(&mut self, combo: KeyCombo, id: u32) {
+        self.state.accelerators.register(combo, id);
+    }
+
+    pub fn unregister_accelerator(&mut self, id: u32) {
+        self.state.accelerators.unregister(id);
+    }
+
+    pub fn register_global_shortcut(
+        &mut self, combo: KeyCombo, id: u32,
+    ) -> Result<(), GlobalShortcutError> {
+        self.state.global_shortcuts.register(combo, id)
+    }
+
+    pub fn unregister_global_shortcut(&mut self, id: u32) {
+        self.state.global_shortcuts.unregister(id);
+    }
+
+    pub fn set_text_input_active(&mut self, rect: Rect) {
+        self.state.text_input.set(Some(rect));
+        self.state.ime_cursor_area.set(None);
+        self.state.position_ime();
+    }
+
+    pub fn clear_text_input(&mut self) {
+        self.state.text_input.set(None);
+        self.state.ime_cursor_area.set(None);
+
+        // The messages for the cancelled composition are no longer sent to the handler, since
+        // text input is no longer active
+        if self.state.ime_composing.replace(false) {
+            if let Some(ime) = unsafe { ImeContext::get(self.state.window.handle) } {
+                ime.cancel_composition();
+            }
+            self.state.deferred_tasks.borrow_mut().push_back(WindowTask::SendImeDisabled);
+        }
+    }
+
+    pub fn is_text_input_active(&self) -> bool {
+        self.state.text_input.get().is_some()
+    }
+
+    pub fn set_ime_cursor_area(&mut self, rect: Rect) {
+        self.state.ime_cursor_area.set(Some(rect));
+        self.state.position_ime();
+    }
+
+    pub fn perform_haptic(&mut self, _pattern: HapticPattern) {}
+
+    pub fn keyboard_layout(&self) -> Option<String> {
+        // The low word of the layout handle is the layout's language
+        let language = unsafe { GetKeyboardLayout(0) } as usize & 0xFFFF;
+        let mut name = [0u16; LOCALE_NAME_MAX_LENGTH];
+        let len =
+            unsafe { LCIDToLocaleName(language as LCID, name.as_mut_ptr(), name.len() as i32, 0) };
+
+        // The length includes the null terminator
+        if len <= 1 {
+            return None;
+        }
+        String::from_utf16(&name[..len as usize - 1]).ok()
+    }
+
+    pub fn has_focus(&self) -> bool {
         let focused_window = unsafe { GetFocus() };
         focused_window == self.state.window.handle
     }
@@ -703,6 +1725,34 @@ impl Window<'_> {
         }
     }
 
+    pub fn grab_keyboard(&mut self, grab: bool) -> bool {
+        if !grab {
+            self.state.release_keyboard_grab();
+            return false;
+        }
+
+        unsafe {
+            if self.state.keyboard_hook.get().is_null() {
+                let hook = SetWindowsHookExW(
+                    WH_KEYBOARD,
+                    Some(keyboard_grab_hook),
+                    null_mut(),
+                    GetCurrentThreadId(),
+                );
+                if hook.is_null() {
+                    return false;
+                }
+
+                self.state.keyboard_hook.set(hook);
+            }
+
+            KEYBOARD_GRAB_HWND.with(|hwnd| hwnd.set(self.state.window.handle));
+            SetFocus(self.state.window.handle);
+        }
+
+        true
+    }
+
     pub fn resize(&mut self, size: Size) {
         // To avoid reentrant event handler calls we'll defer the actual resizing until after the
         // event has been handled
@@ -710,6 +1760,66 @@ impl Window<'_> {
         self.state.deferred_tasks.borrow_mut().push_back(task);
     }
 
+    pub fn drag_threshold(&self) -> f64 {
+        let width = unsafe { GetSystemMetrics(SM_CXDRAG) };
+        let height = unsafe { GetSystemMetrics(SM_CYDRAG) };
+        f64::from(width.max(height)) * self.state.window_info().scale_recip()
+    }
+
+    pub fn display_color_profile(&self) -> Option<Vec<u8>> {
+        self.state.window.current_color_profile()
+    }
+
+    pub fn mouse_cursor(&self) -> MouseCursor {
+        self.state.cursor_icon.get()
+    }
+
+    pub fn set_shadow(&mut self, _shadow: bool) {}
+
+    pub fn set_shape(&mut self, shape: Option<&[Rect]>) {
+        let hwnd = self.state.window.handle;
+        let rects = match shape {
+            Some(rects) => rects,
+            None => {
+                unsafe { SetWindowRgn(hwnd, null_mut(), TRUE) };
+                return;
+            }
+        };
+
+        unsafe {
+            // Window regions are relative to the window's top left corner, including the frame
+            let mut window_rect = RECT { left: 0, top: 0, right: 0, bottom: 0 };
+            let mut client_origin = POINT { x: 0, y: 0 };
+            GetWindowRect(hwnd, &mut window_rect);
+            ClientToScreen(hwnd, &mut client_origin);
+            let offset_x = client_origin.x - window_rect.left;
+            let offset_y = client_origin.y - window_rect.top;
+
+            let window_info = self.state.window_info();
+            let region = CreateRectRgn(0, 0, 0, 0);
+            for rect in rects {
+                let top_left = rect.origin.to_physical(&window_info);
+                let bottom_right =
+                    Point::new(rect.origin.x + rect.size.width, rect.origin.y + rect.size.height)
+                        .to_physical(&window_info);
+
+                let rect_region = CreateRectRgn(
+                    top_left.x + offset_x,
+                    top_left.y + offset_y,
+                    bottom_right.x + offset_x,
+                    bottom_right.y + offset_y,
+                );
+                CombineRgn(region, region, rect_region, RGN_OR);
+                DeleteObject(rect_region as _);
+            }
+
+            // The system owns the region after this call
+            if SetWindowRgn(hwnd, region, TRUE) == 0 {
+                DeleteObject(region as _);
+            }
+        }
+    }
+
     pub fn set_mouse_cursor(&mut self, mouse_cursor: MouseCursor) {
         self.state.cursor_icon.set(mouse_cursor);
         unsafe {
@@ -729,9 +1839,54 @@ impl Window<'_> {
         }
     }
 
+    pub fn set_mouse_position_screen(&mut self, point: Point) {
+        let point = point.to_physical(&self.state.window_info());
+
+        unsafe {
+            SetCursorPos(point.x, point.y);
+        }
+    }
+
+    pub fn move_mouse(&mut self, dx: f64, dy: f64) {
+        let offset = Point::new(dx, dy).to_physical(&self.state.window_info());
+
+        unsafe {
+            let mut point = POINT { x: 0, y: 0 };
+            if GetCursorPos(&mut point) != 0 {
+                SetCursorPos(point.x + offset.x, point.y + offset.y);
+            }
+        }
+    }
+
     #[cfg(feature = "opengl")]
     pub fn gl_context(&self) -> Option<&GlContext> {
-        self.state.gl_context.as_ref()
+        self.state.gl_context.get()
+    }
+
+    #[cfg(feature = "opengl")]
+    pub fn create_gl_context(&mut self, config: GlConfig) -> Result<(), GlError> {
+        if self.state.gl_context.get().is_some() {
+            return Err(GlError::AlreadyCreated);
+        }
+
+        let mut handle = Win32WindowHandle::empty();
+        handle.hwnd = self.state.window.handle as *mut c_void;
+        let handle = RawWindowHandle::Win32(handle);
+
+        let context = unsafe { GlContext::create(&handle, config)? };
+        self.state.gl_context.set(context).map_err(|_| GlError::AlreadyCreated)
+    }
+
+    #[cfg(feature = "opengl")]
+    pub fn create_gl_area(
+        &mut self, position: crate::PhyPoint, size: crate::PhySize, config: GlConfig,
+    ) -> Result<GlArea, GlError> {
+        unsafe { super::gl_area::create_gl_area(self.state.window.handle, position, size, config) }
+    }
+
+    #[cfg(feature = "virtual-clock")]
+    pub fn set_virtual_clock(&mut self, clock: &VirtualClock) {
+        *self.state.virtual_frames.borrow_mut() = Some(VirtualFrames::new(clock));
     }
 }
 
@@ -750,6 +1905,6 @@ unsafe impl HasRawDisplayHandle for Window<'_> {
     }
 }
 
-pub fn copy_to_clipboard(_data: &str) {
-    todo!()
+pub fn copy_to_clipboard(contents: &[(ClipboardFormat, &[u8])]) {
+    clipboard::write(contents);
 }
@@ -0,0 +1,136 @@
+use std::cell::RefCell;
+use std::ffi::OsStr;
+use std::os::windows::ffi::OsStrExt;
+use std::ptr::null_mut;
+
+use winapi::shared::guiddef::GUID;
+use winapi::shared::minwindef::{ATOM, HINSTANCE, LPARAM, LRESULT, UINT, WPARAM};
+use winapi::shared::windef::HWND;
+use winapi::um::combaseapi::CoCreateGuid;
+use winapi::um::winnt::IMAGE_DOS_HEADER;
+use winapi::um::winuser::{
+    LoadCursorW, RegisterClassW, UnregisterClassW, CS_OWNDC, IDC_ARROW, WNDCLASSW,
+};
+
+type WindowProc = unsafe extern "system" fn(HWND, UINT, WPARAM, LPARAM) -> LRESULT;
+
+extern "C" {
+    static __ImageBase: IMAGE_DOS_HEADER;
+}
+
+thread_local! {
+    /// The window class shared by all of baseview's windows on this thread.
+    static WINDOW_CLASS: RefCell<Option<WindowClass>> = const { RefCell::new(None) };
+}
+
+/// Returns the module that contains baseview's code. When baseview is used in a plugin, this is
+/// the plugin's DLL and not the host's executable.
+pub(super) fn module_instance() -> HINSTANCE {
+    unsafe { &__ImageBase as *const IMAGE_DOS_HEADER as HINSTANCE }
+}
+
+/// A window class that's shared by all of baseview's windows on a single thread. Registering a
+/// class per window would exhaust the process' atom table in hosts that open and close hundreds
+/// of plugin editors, since a class can't be unregistered until its window has been destroyed.
+///
+/// The class is registered for this module with a unique name, so multiple plugins using baseview,
+/// or multiple copies of the same plugin, can't interfere with each other's classes. It's
+/// unregistered once the last window using it has been destroyed.
+struct WindowClass {
+    atom: ATOM,
+    /// The number of windows on this thread that have been created with this class and that have
+    /// not yet been destroyed.
+    window_count: usize,
+}
+
+impl Drop for WindowClass {
+    fn drop(&mut self) {
+        unsafe { UnregisterClassW(self.atom as _, module_instance()) };
+    }
+}
+
+/// Get the window class for a new window, registering it first if needed. Every call must be
+/// balanced by a call to [`release()`] when the window receives `WM_NCDESTROY`. Returns 0 if the
+/// class could not be registered.
+pub(super) unsafe fn acquire(wnd_proc: WindowProc) -> ATOM {
+    WINDOW_CLASS.with(|class| {
+        let mut class = class.borrow_mut();
+        let class = match &mut *class {
+            Some(class) => class,
+            None => {
+                let atom = register(wnd_proc);
+                if atom == 0 {
+                    return 0;
+                }
+
+                class.insert(WindowClass { atom, window_count: 0 })
+            }
+        };
+
+        class.window_count += 1;
+        class.atom
+    })
+}
+
+/// Called when one of the class' windows receives `WM_NCDESTROY`. The window still exists at that
+/// point, so the class can only be unregistered afterwards with [`unregister_if_unused()`].
+pub(super) fn release() {
+    WINDOW_CLASS.with(|class| {
+        if let Some(class) = &mut *class.borrow_mut() {
+            class.window_count = class.window_count.saturating_sub(1);
+        }
+    });
+}
+
+/// Unregister this thread's window class if it's no longer used by any windows. This should be
+/// called after `DestroyWindow()` returns. Windows that are destroyed along with their parent
+/// window leave the class registered until the next window is closed, or until the thread exits.
+pub(super) fn unregister_if_unused() {
+    WINDOW_CLASS.with(|class| {
+        let mut class = class.borrow_mut();
+        if matches!(&*class, Some(class) if class.window_count == 0) {
+            *class = None;
+        }
+    });
+}
+
+unsafe fn register(wnd_proc: WindowProc) -> ATOM {
+    // We generate a unique name for the new window class to prevent name collisions
+    let class_name_str = format!("Baseview-{}", generate_guid());
+    let mut class_name: Vec<u16> = OsStr::new(&class_name_str).encode_wide().collect();
+    class_name.push(0);
+
+    let wnd_class = WNDCLASSW {
+        style: CS_OWNDC,
+        lpfnWndProc: Some(wnd_proc),
+        hInstance: module_instance(),
+        lpszClassName: class_name.as_ptr(),
+        cbClsExtra: 0,
+        cbWndExtra: 0,
+        hIcon: null_mut(),
+        hCursor: LoadCursorW(null_mut(), IDC_ARROW),
+        hbrBackground: null_mut(),
+        lpszMenuName: null_mut(),
+    };
+
+    RegisterClassW(&wnd_class)
+}
+
+unsafe fn generate_guid() -> String {
+    let mut guid: GUID = std::mem::zeroed();
+    CoCreateGuid(&mut guid);
+    format!(
+        "{:0X}-{:0X}-{:0X}-{:0X}{:0X}-{:0X}{:0X}{:0X}{:0X}{:0X}{:0X}\0",
+        guid.Data1,
+        guid.Data2,
+        guid.Data3,
+        guid.Data4[0],
+        guid.Data4[1],
+        guid.Data4[2],
+        guid.Data4[3],
+        guid.Data4[4],
+        guid.Data4[5],
+        guid.Data4[6],
+        guid.Data4[7]
+    )
+}
@@ -1,12 +1,17 @@
 use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
 
+use keyboard_types::{KeyboardEvent, Modifiers};
 use raw_window_handle::{
     HasRawDisplayHandle, HasRawWindowHandle, RawDisplayHandle, RawWindowHandle,
 };
 
 use crate::event::{Event, EventStatus};
-use crate::window_open_options::WindowOpenOptions;
-use crate::{MouseCursor, Point, Size};
+use crate::window_open_options::{WindowOpenOptions, WindowOpenOptionsError};
+use crate::{
+    ClipboardFormat, DragData, FrameStats, GlobalShortcutError, HapticPattern, KeyCombo,
+    MouseCursor, Point, Rect, Size,
+};
 
 #[cfg(target_os = "macos")]
 use crate::macos as platform;
@@ -26,7 +31,14 @@ impl WindowHandle {
         Self { window_handle, phantom: PhantomData }
     }
 
-    /// Close the window
+    /// Close the window. Dropping the handle does not close the window, so this should be called
+    /// before the parent window is destroyed.
+    ///
+    /// On Linux this waits for the window thread to shut down, which means the handler has
+    /// received [`WindowEvent::WillClose`][crate::WindowEvent::WillClose] and its OpenGL context
+    /// has been destroyed by the time this returns. The exception is closing a child of another
+    /// baseview window from within the parent's callbacks. The child shares the parent's thread,
+    /// so it's closed right after the current callback instead.
     pub fn close(&mut self) {
         self.window_handle.close();
     }
@@ -36,6 +48,46 @@ impl WindowHandle {
     pub fn is_open(&self) -> bool {
         self.window_handle.is_open()
     }
+
+    /// Send a key event that the host received on the window's behalf to the window's handler,
+    /// and return whether the handler captured it. Plugin APIs pass key events to the plugin this
+    /// way while the host's own window has keyboard focus, like VST3's `IPlugView::onKeyDown()`.
+    /// The result should be reported back to the host, so it can process the keys the handler
+    /// returned [`EventStatus::Ignored`] for itself. Registered accelerators are matched like they
+    /// are for regular key events.
+    ///
+    /// This returns `false` if the window has been closed, or if it's called from within one of
+    /// the handler's callbacks. On Linux the handler runs on the window's event loop thread, so
+    /// this waits for that thread to process the event. Events that the handler doesn't get to
+    /// within a quarter of a second are reported as not captured.
+    pub fn send_key_event(&self, event: KeyboardEvent) -> bool {
+        self.window_handle.send_key_event(event)
+    }
+
+    /// Change the scale factor of a window opened with
+    /// [`WindowScalePolicy::HostControlled`][crate::WindowScalePolicy::HostControlled], for
+    /// instance when the host calls CLAP's `set_scale()`. The window keeps its logical size, so its
+    /// physical size changes with the scale factor, and the handler receives a
+    /// [`WindowEvent::Resized`][crate::WindowEvent::Resized] event with the new scale factor.
+    ///
+    /// This has no effect for windows using other scale policies, for scale factors that are not
+    /// positive, and on macOS.
+    pub fn set_scale_factor(&self, scale: f64) {
+        if scale.is_finite() && scale > 0.0 {
+            self.window_handle.set_scale_factor(scale);
+        }
+    }
+
+    /// Call the handler's [`on_frame()`][WindowHandler::on_frame()] for a window opened with
+    /// [`WindowOpenOptions::external_frame_clock`][crate::WindowOpenOptions::external_frame_clock].
+    /// This does nothing for other windows, for windows that have been closed, and when it's
+    /// called from within one of the handler's callbacks.
+    ///
+    /// On Linux the handler runs on the window's event loop thread, so the frame is drawn
+    /// asynchronously. On macOS it's drawn on the main thread.
+    pub fn tick_frame(&self) {
+        self.window_handle.tick_frame();
+    }
 }
 
 unsafe impl HasRawWindowHandle for WindowHandle {
@@ -44,13 +96,123 @@ unsafe impl HasRawWindowHandle for WindowHandle {
     }
 }
 
+/// A handle to a window opened with [`Window::open_parented_scoped()`]. Unlike a regular
+/// [`WindowHandle`], the window's handler may borrow data that lives for `'env`. The window is
+/// closed and its handler is dropped when this handle is dropped.
+#[cfg(any(target_os = "windows", target_os = "macos"))]
+pub struct ScopedWindowHandle<'env> {
+    window_handle: WindowHandle,
+    phantom: PhantomData<&'env mut ()>,
+}
+
+#[cfg(any(target_os = "windows", target_os = "macos"))]
+impl ScopedWindowHandle<'_> {
+    /// Returns `true` if the window is still open, and returns `false`
+    /// if the window was closed/dropped.
+    pub fn is_open(&self) -> bool {
+        self.window_handle.is_open()
+    }
+
+    /// See [`WindowHandle::send_key_event()`].
+    pub fn send_key_event(&self, event: KeyboardEvent) -> bool {
+        self.window_handle.send_key_event(event)
+    }
+
+    /// See [`WindowHandle::set_scale_factor()`].
+    pub fn set_scale_factor(&self, scale: f64) {
+        self.window_handle.set_scale_factor(scale);
+    }
+
+    /// See [`WindowHandle::tick_frame()`].
+    pub fn tick_frame(&self) {
+        self.window_handle.tick_frame();
+    }
+}
+
+#[cfg(any(target_os = "windows", target_os = "macos"))]
+impl Drop for ScopedWindowHandle<'_> {
+    fn drop(&mut self) {
+        // The handler may not outlive `'env`, so the window can't be closed asynchronously here
+        self.window_handle.window_handle.close_now();
+    }
+}
+
+#[cfg(any(target_os = "windows", target_os = "macos"))]
+unsafe impl HasRawWindowHandle for ScopedWindowHandle<'_> {
+    fn raw_window_handle(&self) -> RawWindowHandle {
+        self.window_handle.raw_window_handle()
+    }
+}
+
+/// Receives events and frame callbacks for a window. Every callback is passed a `&mut Window` that
+/// can be used to interact with the window from within that callback.
+///
+/// None of these callbacks are ever called reentrantly. Events caused by calling a method on the
+/// [`Window`] from within a callback, like the
+/// [`WindowEvent::Resized`][crate::WindowEvent::Resized] event that may follow a call to
+/// [`Window::resize()`], are only delivered after the current callback has returned. This makes
+/// it safe to, for instance, resize the window again while handling a resize event.
 pub trait WindowHandler {
     fn on_frame(&mut self, window: &mut Window);
     fn on_event(&mut self, window: &mut Window, event: Event) -> EventStatus;
+
+    /// Called once before the window starts dispatching a batch of pending events to
+    /// [`on_event()`][Self::on_event()]. Together with [`after_events()`][Self::after_events()]
+    /// this can be used to update layout or schedule repaints once after all pending events have
+    /// been handled, instead of after every single event.
+    ///
+    /// Depending on the platform, this may also be called for batches that end up not containing
+    /// any events.
+    fn before_events(&mut self, _window: &mut Window) {}
+
+    /// Called once after the window has dispatched a batch of pending events. A batch that was
+    /// started with [`before_events()`][Self::before_events()] is always finished before the next
+    /// call to [`on_frame()`][Self::on_frame()].
+    fn after_events(&mut self, _window: &mut Window) {}
+
+    /// Called after a callback, usually [`on_frame()`][Self::on_frame()], presented a frame with
+    /// [`GlContext::swap_buffers()`][crate::gl::GlContext::swap_buffers()]. The timestamps in
+    /// `info` can be used to align rendering, for instance of audio meters, with the time the
+    /// frame is actually displayed. Frames skipped with
+    /// [`Window::skip_present()`][Window::skip_present()] are not reported.
+    #[cfg(feature = "opengl")]
+    fn on_present(&mut self, _window: &mut Window, _info: crate::gl::PresentInfo) {}
+}
+
+/// Shows a busy cursor until it's dropped, see [`Window::busy_cursor()`]. Any cursor set through
+/// this guard is replaced by the original cursor when the guard is dropped.
+pub struct BusyCursor<'w, 'a> {
+    window: &'w mut Window<'a>,
+    previous_cursor: MouseCursor,
+}
+
+impl<'a> Deref for BusyCursor<'_, 'a> {
+    type Target = Window<'a>;
+
+    fn deref(&self) -> &Self::Target {
+        self.window
+    }
+}
+
+impl<'a> DerefMut for BusyCursor<'_, 'a> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.window
+    }
+}
+
+impl Drop for BusyCursor<'_, '_> {
+    fn drop(&mut self) {
+        self.window.set_mouse_cursor(self.previous_cursor);
+    }
 }
 
+/// The window as seen from within the [`WindowHandler`]'s callbacks. Methods that change the
+/// window's state take `&mut self`, while methods that only query it take `&self`.
 pub struct Window<'a> {
     window: platform::Window<'a>,
+    /// See [`skip_present()`][Self::skip_present()]. A new `Window` is created for every callback,
+    /// so this only lasts for the current callback.
+    present_skipped: bool,
 
     // so that Window is !Send on all platforms
     phantom: PhantomData<*mut ()>,
@@ -59,67 +221,655 @@ pub struct Window<'a> {
 impl<'a> Window<'a> {
     #[cfg(target_os = "windows")]
     pub(crate) fn new(window: platform::Window<'a>) -> Window<'a> {
-        Window { window, phantom: PhantomData }
+        Window::from_platform(window)
     }
 
     #[cfg(not(target_os = "windows"))]
     pub(crate) fn new(window: platform::Window) -> Window {
-        Window { window, phantom: PhantomData }
+        Window::from_platform(window)
     }
 
-    pub fn open_parented<P, H, B>(parent: &P, options: WindowOpenOptions, build: B) -> WindowHandle
+    fn from_platform<'w>(window: platform::Window<'w>) -> Window<'w> {
+        let window = Window { window, present_skipped: false, phantom: PhantomData };
+
+        // A previous callback may have skipped presenting its frame
+        #[cfg(feature = "opengl")]
+        if let Some(gl_context) = window.gl_context() {
+            gl_context.set_swaps_skipped(false);
+        }
+
+        window
+    }
+
+    /// Open a window as a child of `parent`, which is usually a window owned by the host.
+    ///
+    /// On Linux every window normally gets its own thread and X11 connection. When `parent` is
+    /// another baseview window, the new window instead joins the parent's thread and connection,
+    /// and its handler is built and run on that thread. The thread keeps running until all of its
+    /// windows have been closed.
+    ///
+    /// On macOS this may be called from a thread other than the main thread, as some hosts open
+    /// their plugins' editors from a background thread. The window is then created on the main
+    /// thread and this blocks until it has been opened, so the main thread must not be waiting for
+    /// the calling thread. The handler is built and runs on the main thread, and the returned
+    /// handle may be used and dropped on the calling thread.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error without opening a window if `options` don't pass
+    /// [`WindowOpenOptions::validate()`].
+    pub fn open_parented<P, H, B>(
+        parent: &P, options: WindowOpenOptions, build: B,
+    ) -> Result<WindowHandle, WindowOpenOptionsError>
     where
         P: HasRawWindowHandle,
         H: WindowHandler + 'static,
         B: FnOnce(&mut Window) -> H,
         B: Send + 'static,
     {
+        options.validate()?;
+
+        #[cfg(feature = "event-log")]
+        let window_handle = platform::Window::open_parented(parent, options, log_events(build));
+        #[cfg(not(feature = "event-log"))]
         let window_handle = platform::Window::open_parented::<P, H, B>(parent, options, build);
-        WindowHandle::new(window_handle)
+        Ok(WindowHandle::new(window_handle))
     }
 
-    pub fn open_blocking<H, B>(options: WindowOpenOptions, build: B)
+    /// Like [`open_parented()`][Self::open_parented()], but the handler and the closure that
+    /// builds it only need to live for `'env` instead of being `'static`, and the closure doesn't
+    /// need to be `Send`. This lets the handler borrow the host's state instead of sharing it
+    /// through an `Arc<Mutex<...>>`.
+    ///
+    /// This is only available on platforms where parented windows run on the host's thread.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error without opening a window if `options` don't pass
+    /// [`WindowOpenOptions::validate()`].
+    ///
+    /// # Panics
+    ///
+    /// On macOS this panics when called from a thread other than the main thread. Unlike
+    /// [`open_parented()`][Self::open_parented()] it can't move the window's creation to the main
+    /// thread, because the closure and the handler may not be sent to another thread.
+    ///
+    /// # Safety
+    ///
+    /// The returned handle must be dropped before `'env` ends. The borrow checker enforces this
+    /// unless the handle is leaked, for instance with [`std::mem::forget()`] or through a reference
+    /// cycle. Leaking the handle would keep the window open with a handler that refers to data
+    /// that no longer exists.
+    #[cfg(any(target_os = "windows", target_os = "macos"))]
+    pub unsafe fn open_parented_scoped<'env, P, H, B>(
+        parent: &P, options: WindowOpenOptions, build: B,
+    ) -> Result<ScopedWindowHandle<'env>, WindowOpenOptionsError>
+    where
+        P: HasRawWindowHandle,
+        H: WindowHandler + 'env,
+        B: FnOnce(&mut Window) -> H + 'env,
+    {
+        #[cfg(target_os = "macos")]
+        assert!(
+            platform::is_main_thread(),
+            "Window::open_parented_scoped() must be called from the main thread on macOS"
+        );
+
+        let build: ScopedBuild<'env> = Box::new(move |window| Box::new(build(window)));
+        // SAFETY: The handler is dropped when the `ScopedWindowHandle` is dropped, which the caller
+        //         guarantees happens before `'env` ends
+        let build: ScopedBuild<'static> = std::mem::transmute(build);
+        // SAFETY: On these platforms `build` is called on this thread before `open_parented()`
+        //         returns. On macOS that only holds on the main thread, which is asserted above.
+        let build = AssertSend(build);
+
+        let window_handle = Self::open_parented(parent, options, move |window| {
+            ScopedHandler(build.into_inner()(window))
+        })?;
+
+        Ok(ScopedWindowHandle { window_handle, phantom: PhantomData })
+    }
+
+    /// Open a window with its own event loop and block until it has been closed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error without opening a window if `options` don't pass
+    /// [`WindowOpenOptions::validate()`].
+    pub fn open_blocking<H, B>(
+        options: WindowOpenOptions, build: B,
+    ) -> Result<(), WindowOpenOptionsError>
     where
         H: WindowHandler + 'static,
         B: FnOnce(&mut Window) -> H,
         B: Send + 'static,
     {
-        platform::Window::open_blocking::<H, B>(options, build)
+        Self::open_blocking_inner(options, build, false)
     }
 
-    /// Close the window
+    /// [`Window::open_blocking()`], used by [`run_app()`][crate::run_app()] to mark the window as
+    /// the application's window. Only that window is closed when the process is interrupted.
+    pub(crate) fn open_blocking_inner<H, B>(
+        options: WindowOpenOptions, build: B, quits_with_app: bool,
+    ) -> Result<(), WindowOpenOptionsError>
+    where
+        H: WindowHandler + 'static,
+        B: FnOnce(&mut Window) -> H,
+        B: Send + 'static,
+    {
+        options.validate()?;
+
+        #[cfg(feature = "event-log")]
+        platform::Window::open_blocking(options, log_events(build), quits_with_app);
+        #[cfg(not(feature = "event-log"))]
+        platform::Window::open_blocking::<H, B>(options, build, quits_with_app);
+        Ok(())
+    }
+
+    /// Close the window. This is safe to call from any of the handler's callbacks.
+    ///
+    /// The window is closed as soon as the current callback returns, before control is handed back
+    /// to the platform's event loop. The handler doesn't receive any further events after the
+    /// current callback, including events that are already pending. Instead, any event batch that
+    /// is in progress is finished with [`WindowHandler::after_events()`], after which the handler
+    /// receives [`WindowEvent::WillClose`][crate::WindowEvent::WillClose] as its final callback.
+    /// Calling this more than once has no further effect.
     pub fn close(&mut self) {
         self.window.close();
     }
 
     /// Resize the window to the given size. The size is always in logical pixels. DPI scaling will
-    /// automatically be accounted for.
+    /// automatically be accounted for. The [`WindowEvent::Resized`][crate::WindowEvent::Resized]
+    /// event for the new size is sent with [`ResizeSource::Handler`][crate::ResizeSource::Handler].
+    ///
+    /// On X11 the resize is only finished once the X server confirms it. Calling this again with
+    /// the size the window is already being resized to has no effect, and if the window is resized
+    /// again before the X server has confirmed an earlier resize, then the earlier size is never
+    /// reported to the handler.
     pub fn resize(&mut self, size: Size) {
         self.window.resize(size);
     }
 
+    /// Show or hide the drop shadow the system draws around a standalone window. Windows with
+    /// transparent or custom shaped content can turn it off so they don't get a rectangular
+    /// shadow. The shadow is shown by default.
+    ///
+    /// This currently only has an effect on macOS, where it's `NSWindow`'s `hasShadow` property.
+    pub fn set_shadow(&mut self, shadow: bool) {
+        self.window.set_shadow(shadow);
+    }
+
+    /// Restrict the window's visible and clickable area to the union of these rectangles in
+    /// logical coordinates, so borderless windows can have rounded corners or other non-rectangular
+    /// silhouettes. Passing `None` makes the whole window visible again. The shape is not updated
+    /// when the window is resized or its scale factor changes, so it should be set again after a
+    /// [`WindowEvent::Resized`][crate::WindowEvent::Resized] event.
+    ///
+    /// - On X11 this uses the Shape extension.
+    /// - On Windows this uses a window region.
+    /// - On macOS this masks the view's layer. Standalone windows are made transparent so the
+    ///   masked out areas show the desktop behind them.
+    pub fn set_shape(&mut self, shape: Option<&[Rect]>) {
+        self.window.set_shape(shape);
+    }
+
     pub fn set_mouse_cursor(&mut self, cursor: MouseCursor) {
         self.window.set_mouse_cursor(cursor);
     }
 
+    /// Show [`MouseCursor::Wait`] until the returned guard is dropped, after which the previous
+    /// cursor is restored. This is meant for long blocking operations like loading a preset. The
+    /// guard dereferences to the window, so the window can still be used in the meantime.
+    ///
+    /// On macOS the cursor only changes once the event loop runs again, and the system shows its
+    /// own wait cursor when the application stops responding.
+    pub fn busy_cursor(&mut self) -> BusyCursor<'_, 'a> {
+        let previous_cursor = self.window.mouse_cursor();
+        self.window.set_mouse_cursor(MouseCursor::Wait);
+
+        BusyCursor { window: self, previous_cursor }
+    }
+
+    /// How far in logical pixels the cursor has to move while a mouse button is held before the
+    /// press counts as a drag instead of a click, as configured in the system settings. This is
+    /// used by [`DragDetector`][crate::drag::DragDetector].
+    ///
+    /// - On Windows this is the `SM_CXDRAG` system metric.
+    /// - On X11 this is the `Net/DndDragThreshold` XSETTINGS value, or 8 physical pixels if the
+    ///   desktop environment doesn't provide one.
+    /// - macOS doesn't have a setting for this, so this is always 3 points.
+    pub fn drag_threshold(&self) -> f64 {
+        self.window.drag_threshold()
+    }
+
+    /// The ICC profile of the display the window is currently on, so color critical drawing code
+    /// can convert its colors to the display's color space instead of assuming sRGB. Returns
+    /// `None` if the display doesn't have a profile, or if it couldn't be read. The profile may
+    /// have changed after a [`WindowEvent::MonitorChanged`][crate::WindowEvent::MonitorChanged]
+    /// event.
+    ///
+    /// - On Windows this is the profile assigned to the display in the color management settings.
+    /// - On X11 this is the `_ICC_PROFILE` property that color management daemons like colord set
+    ///   on the display's RandR output, or on the root window for the primary display.
+    /// - On macOS this is the profile of the window's screen. macOS color matches OpenGL content
+    ///   itself when the view is layer backed, and baseview marks the OpenGL surface as sRGB when
+    ///   `GlConfig::srgb` is set.
+    pub fn display_color_profile(&self) -> Option<Vec<u8>> {
+        self.window.display_color_profile()
+    }
+
+    /// Move the mouse cursor to a position relative to the window's top left corner.
     pub fn set_mouse_position(&mut self, point: Point) {
         self.window.set_mouse_position(point);
     }
 
-    pub fn has_focus(&mut self) -> bool {
+    /// Move the mouse cursor to a position in screen coordinates, relative to the top left corner
+    /// of the primary screen. The position is in logical coordinates, and it is converted to
+    /// physical coordinates using this window's scale factor.
+    pub fn set_mouse_position_screen(&mut self, point: Point) {
+        self.window.set_mouse_position_screen(point);
+    }
+
+    /// Move the mouse cursor by a logical offset from its current position. Together with
+    /// [`set_mouse_position()`][Self::set_mouse_position()] this can be used to let the cursor
+    /// wrap around during drag interactions.
+    pub fn move_mouse(&mut self, dx: f64, dy: f64) {
+        self.window.move_mouse(dx, dy);
+    }
+
+    /// Query the cursor's current position and send it as a
+    /// [`MouseEvent::CursorMoved`][crate::MouseEvent::CursorMoved] event if the cursor is over the
+    /// window. Call this after scrolling or otherwise moving the window's contents, so hover
+    /// states don't refer to whatever used to be under the cursor until the mouse is moved again.
+    /// This already happens automatically after the window has been resized.
+    ///
+    /// The event is sent after the current callback has returned. On macOS it's sent before the
+    /// next frame. Nothing is sent during an infinite drag, or if the window doesn't receive
+    /// [`InputEvents::mouse_motion`][crate::InputEvents::mouse_motion] events.
+    pub fn refresh_cursor_position(&mut self) {
+        self.window.refresh_cursor_position();
+    }
+
+    /// Read the text on the system clipboard, for instance to paste it into a text field. The text
+    /// is sent as [`Event::ClipboardData`] after the current callback has returned. On macOS it's
+    /// sent before the next frame.
+    ///
+    /// On X11 the clipboard's contents belong to the application they were copied from, so they
+    /// arrive once that application has sent them. Calling this again before then doesn't send
+    /// another event.
+    pub fn request_clipboard_contents(&mut self) {
+        self.window.request_clipboard_data(ClipboardFormat::Text);
+    }
+
+    /// Read the clipboard's data in a specific format, for instance data copied with
+    /// [`copy_formats_to_clipboard()`][crate::copy_formats_to_clipboard()]. This works like
+    /// [`request_clipboard_contents()`][Self::request_clipboard_contents()], except that formats
+    /// other than text are sent as [`Event::ClipboardFormatData`]. Requests for several formats
+    /// are answered in the order they were made.
+    pub fn request_clipboard_data(&mut self, format: ClipboardFormat) {
+        self.window.request_clipboard_data(format);
+    }
+
+    /// Start dragging `data` out of the window, for instance to drag a rendered audio clip into a
+    /// DAW's arrangement. This should be called while a mouse button is held, usually in response
+    /// to a [`MouseEvent::DragStarted`][crate::MouseEvent::DragStarted] event. The data is offered
+    /// to be copied, and the handler receives [`Event::DragFinished`] once the drag has ended.
+    ///
+    /// On Windows the drag starts once the current callback has returned, and the window's
+    /// handler keeps running while the operating system tracks the drag. On Linux the drag is
+    /// tracked through XDND, and only drop targets that support XDND accept the data.
+    pub fn start_drag(&mut self, data: DragData) {
+        self.window.start_drag(data);
+    }
+
+    /// Register a keyboard shortcut for this window. Key presses matching `combo` are sent as
+    /// [`Event::Accelerator`] with `id` instead of as keyboard events. Registering the same
+    /// combination again replaces its id, and several combinations can share the same id.
+    ///
+    /// Accelerators are only matched while the window receives keyboard input, so they don't
+    /// interfere with the host's shortcuts when the window doesn't have focus. Use
+    /// [`KeyCombo::primary()`] for shortcuts that should use Cmd on macOS and Ctrl elsewhere.
+    pub fn register_accelerator(&mut self, combo: KeyCombo, id: u32) {
+        self.window.register_accelerator(combo, id);
+    }
+
+    /// Remove all accelerators registered with `id`.
+    pub fn unregister_accelerator(&mut self, id: u32) {
+        self.window.unregister_accelerator(id);
+    }
+
+    /// Register a keyboard shortcut that works system wide. Key presses matching `combo` are sent
+    /// to this window as [`Event::GlobalShortcut`] with `id`, even while another application has
+    /// keyboard focus. Registering the same combination again replaces its id. The shortcuts are
+    /// removed when the window is closed.
+    ///
+    /// This is meant for standalone applications, like a tuner that can be toggled from anywhere.
+    /// Plugin windows should use [`register_accelerator()`][Self::register_accelerator()]
+    /// instead, since a global shortcut takes the keys away from the host and every other
+    /// application. Character keys are matched by their position on a US keyboard rather than by
+    /// the character they produce with the current layout.
+    ///
+    /// This uses `XGrabKey()` on the root window on Linux, `RegisterHotKey()` on Windows, and
+    /// Carbon hot keys on macOS. Fails if another application already uses the combination.
+    pub fn register_global_shortcut(
+        &mut self, combo: KeyCombo, id: u32,
+    ) -> Result<(), GlobalShortcutError> {
+        self.window.register_global_shortcut(combo, id)
+    }
+
+    /// Remove all global shortcuts registered with `id`.
+    pub fn unregister_global_shortcut(&mut self, id: u32) {
+        self.window.unregister_global_shortcut(id);
+    }
+
+    /// Perform haptic feedback, for instance to let a knob click when it passes a detent. This
+    /// only has an effect on macOS with a Force Touch trackpad, and only while the user is
+    /// touching the trackpad. It does nothing on other platforms.
+    pub fn perform_haptic(&mut self, pattern: HapticPattern) {
+        self.window.perform_haptic(pattern);
+    }
+
+    /// Start an infinite drag, like the drags used to turn knobs. The cursor is hidden and stays
+    /// in place, while [`MouseEvent::CursorMoved`][crate::MouseEvent::CursorMoved] events report a
+    /// virtual cursor position that keeps following the mouse past the window and screen edges.
+    /// Cursor enter and leave events are not sent during the drag. The drag ends once all mouse
+    /// buttons have been released, after which the cursor reappears where the drag started.
+    ///
+    /// This should be called in response to a
+    /// [`MouseEvent::ButtonPressed`][crate::MouseEvent::ButtonPressed] event. Only relative
+    /// pointing devices like mice and touchpads can move the virtual cursor past the screen
+    /// edges. Calling this during a drag has no effect.
+    pub fn begin_infinite_drag(&mut self) {
+        self.window.begin_infinite_drag();
+    }
+
+    /// End an infinite drag before the mouse buttons have been released. This has no effect if
+    /// there is no drag in progress.
+    pub fn end_infinite_drag(&mut self) {
+        self.window.end_infinite_drag();
+    }
+
+    /// The identifier of the active keyboard layout, for labeling the keys of a virtual keyboard.
+    /// The format depends on the platform:
+    ///
+    /// - On X11 this is the XKB layout name, like `us` or `de`. Switching between multiple
+    ///   configured layouts is picked up from the window's key events.
+    /// - On Windows this is the locale name of the layout's language, like `en-US` or `de-DE`.
+    /// - On macOS this is the input source ID, like `com.apple.keylayout.US` or
+    ///   `com.apple.keylayout.German`.
+    ///
+    /// Returns `None` if the layout can't be determined.
+    pub fn keyboard_layout(&self) -> Option<String> {
+        self.window.keyboard_layout()
+    }
+
+    pub fn has_focus(&self) -> bool {
         self.window.has_focus()
     }
 
+    /// Give the window keyboard focus. On X11 standalone windows ask the window manager to
+    /// activate them with the time of the user's last key or button press in the window, so window
+    /// managers that prevent focus stealing may still refuse if there hasn't been any.
     pub fn focus(&mut self) {
         self.window.focus()
     }
 
+    /// Grab the keyboard so that key events keep being delivered to this window, even when the
+    /// host moves keyboard focus elsewhere. This is useful for modal interactions such as popups
+    /// that need to be sure to receive Escape or Enter. Pass `false` to release the grab again.
+    ///
+    /// Returns whether the window holds the keyboard grab after this call, which may not be the
+    /// case if another application already grabbed the keyboard.
+    ///
+    /// - On X11 this uses `XGrabKeyboard`.
+    /// - On Windows the window is focused, and key presses sent to other windows on the same
+    ///   thread are redirected to this window.
+    /// - On macOS the window is made the key window, and the view refuses to resign first
+    ///   responder status while the grab is active.
+    pub fn grab_keyboard(&mut self, grab: bool) -> bool {
+        self.window.grab_keyboard(grab)
+    }
+
+    /// Tell baseview that the user is typing in a text field, located at `rect` in logical
+    /// coordinates, until [`clear_text_input()`][Self::clear_text_input()] is called. While text
+    /// input is active, key presses are consumed by this window even when the handler returns
+    /// [`EventStatus::Ignored`] for them, so the host doesn't interpret the typing as its own
+    /// shortcuts. Call this again whenever the text field moves.
+    ///
+    /// Text composed with an input method is sent as [`Event::Ime`] events while text input is
+    /// active. The input method's candidate window is placed next to `rect` until
+    /// [`set_ime_cursor_area()`][Self::set_ime_cursor_area()] is called.
+    ///
+    /// - On Windows hosts that use dialog navigation no longer take Tab, Enter, and the arrow keys
+    ///   away from the window.
+    /// - On macOS key presses are no longer passed up the responder chain. Shortcuts using Cmd are
+    ///   still offered to the host's menus first, unless they're registered as accelerators.
+    /// - On X11 key presses are never passed to the host. Only compose sequences, like dead keys,
+    ///   are sent as [`Event::Ime`] events, and they only compose while text input is active.
+    pub fn set_text_input_active(&mut self, rect: Rect) {
+        self.window.set_text_input_active(rect);
+    }
+
+    /// End text input started with [`set_text_input_active()`][Self::set_text_input_active()], for
+    /// instance when the text field loses focus. If text was being composed with an input method,
+    /// then the composition is cancelled and
+    /// [`ImeEvent::Disabled`][crate::ImeEvent::Disabled] is sent.
+    pub fn clear_text_input(&mut self) {
+        self.window.clear_text_input();
+    }
+
+    /// Tell baseview whether one of the handler's text fields has keyboard focus, for handlers that
+    /// only track focus and not where the text field is. Enabling text input is the same as
+    /// [`set_text_input_active()`][Self::set_text_input_active()] with an empty area at the
+    /// window's top left corner, and the area of text input that's already active is kept.
+    /// Disabling it is the same as [`clear_text_input()`][Self::clear_text_input()].
+    ///
+    /// While text input is disabled, key presses are plain shortcuts, and the ones the handler
+    /// returns [`EventStatus::Ignored`] for are passed on to the host. While it's enabled, input
+    /// methods and dead keys compose text, and key presses aren't passed to the host. Call
+    /// [`set_ime_cursor_area()`][Self::set_ime_cursor_area()] to move the input method's candidate
+    /// window next to the text field.
+    pub fn set_text_input_enabled(&mut self, enabled: bool) {
+        if !enabled {
+            self.clear_text_input();
+        } else if !self.window.is_text_input_active() {
+            let area = Rect::new(Point::new(0.0, 0.0), Size::new(0.0, 0.0));
+            self.set_text_input_active(area);
+        }
+    }
+
+    /// Tell the input method where the text field's cursor is, in logical coordinates, so its
+    /// candidate window appears next to the text being composed instead of covering it. This should
+    /// be called whenever the cursor moves during text input, including after each
+    /// [`ImeEvent::Preedit`][crate::ImeEvent::Preedit]. The area is reset by
+    /// [`set_text_input_active()`][Self::set_text_input_active()].
+    ///
+    /// On X11 this has no effect, since compose sequences don't have a candidate window.
+    pub fn set_ime_cursor_area(&mut self, rect: Rect) {
+        self.window.set_ime_cursor_area(rect);
+    }
+
+    /// Tell baseview that nothing was drawn during the current callback, usually
+    /// [`WindowHandler::on_frame()`], because the window's contents haven't changed. Calls to
+    /// `GlContext::swap_buffers()` do nothing for the rest of the callback, so the system's
+    /// compositor doesn't need to compose the unchanged window again. Renderers that present their
+    /// frames some other way, like `softbuffer`, can check
+    /// [`present_skipped()`][Self::present_skipped()] instead.
+    pub fn skip_present(&mut self) {
+        self.present_skipped = true;
+
+        #[cfg(feature = "opengl")]
+        if let Some(gl_context) = self.gl_context() {
+            gl_context.set_swaps_skipped(true);
+        }
+    }
+
+    /// Whether [`skip_present()`][Self::skip_present()] was called during the current callback.
+    pub fn present_skipped(&self) -> bool {
+        self.present_skipped
+    }
+
+    /// Counters for the window's frame timer since the window was opened, which show whether
+    /// [`on_frame()`][WindowHandler::on_frame()] is being called less often than intended because
+    /// the handler can't keep up.
+    ///
+    /// Baseview doesn't coalesce or drop input events itself: every cursor movement the platform
+    /// reports is sent to the handler. Windows and macOS may merge cursor movements before they
+    /// reach the window, which isn't visible to applications.
+    pub fn frame_stats(&self) -> FrameStats {
+        self.window.frame_stats()
+    }
+
+    /// Returns the modifiers that are currently active, as of the last
+    /// [`WindowEvent::ModifiersChanged`][crate::WindowEvent::ModifiersChanged] event.
+    pub fn modifiers(&self) -> Modifiers {
+        self.window.modifiers()
+    }
+
+    /// Returns the platform's raw scancode for the physical key behind the
+    /// [`Event::Keyboard`][crate::Event::Keyboard] event that's currently being handled, or the
+    /// [`Event::Accelerator`][crate::Event::Accelerator] event it was translated to. Returns `None`
+    /// while handling any other event, and for key events forwarded with
+    /// [`WindowHandle::send_key_event()`].
+    ///
+    /// This is the evdev keycode on Linux, which is the X11 keycode minus 8. On Windows it's the
+    /// scan code with `0x100` set for extended keys, like `0x11D` for the right Control key. On
+    /// macOS it's the event's virtual key code. Most handlers should use the event's `code`
+    /// instead, which is derived from this but doesn't depend on the platform. The scancode is
+    /// still there for keys that don't have a `code`.
+    pub fn key_scancode(&self) -> Option<u32> {
+        crate::scancode::current()
+    }
+
+    /// Returns the refresh rate in Hz of the display the window is on, if it's known. The frame
+    /// timer calls [`WindowHandler::on_frame()`] at this rate, or every 15 ms if it isn't known.
+    ///
+    /// On X11 this is the refresh rate of the monitor showing the center of the window, and it's
+    /// only known when the X server supports RandR.
+    pub fn refresh_rate(&self) -> Option<f64> {
+        self.window.refresh_rate()
+    }
+
+    /// Send a [`WindowEvent::AnimationFrame`][crate::WindowEvent::AnimationFrame] event with the
+    /// frame's timing and the display's refresh period right before every call to
+    /// [`WindowHandler::on_frame()`]. This is disabled by default, and should only be enabled while
+    /// something is animating.
+    pub fn set_animation_frames(&mut self, enabled: bool) {
+        self.window.set_animation_frames(enabled);
+    }
+
+    /// Called by the backends after [`WindowHandler::on_frame()`] to pass the frame it presented,
+    /// if any, to [`WindowHandler::on_present()`].
+    #[cfg(feature = "opengl")]
+    pub(crate) fn report_present<H: WindowHandler + ?Sized>(&mut self, handler: &mut H) {
+        if let Some(info) = self.gl_context().and_then(|context| context.take_present_info()) {
+            handler.on_present(self, info);
+        }
+    }
+
     /// If provided, then an OpenGL context will be created for this window. You'll be able to
     /// access this context through [crate::Window::gl_context].
     #[cfg(feature = "opengl")]
     pub fn gl_context(&self) -> Option<&crate::gl::GlContext> {
         self.window.gl_context()
     }
+
+    /// Create an OpenGL context for a window that was opened without a
+    /// [`gl_config`][crate::WindowOpenOptions::gl_config], for instance once the handler has
+    /// decided which renderer to use. The context is then available through
+    /// [`gl_context()`][Self::gl_context()].
+    ///
+    /// On X11 the window's visual was chosen without an OpenGL configuration in mind, so this
+    /// fails if none of the framebuffer configs matching `config` can use that visual. Setting
+    /// `gl_config` when opening the window avoids this.
+    #[cfg(feature = "opengl")]
+    pub fn create_gl_context(
+        &mut self, config: crate::gl::GlConfig,
+    ) -> Result<(), crate::gl::GlError> {
+        self.window.create_gl_context(config)
+    }
+
+    /// Create an additional OpenGL drawable with its own context inside of this window, see
+    /// [`GlArea`][crate::gl::GlArea]. The position is relative to the window's top left corner,
+    /// and both the position and the size are in physical pixels.
+    ///
+    /// - On X11 this is a child window with a visual matching `config`. The Shape extension is
+    ///   used to let input pass through to the window.
+    /// - On Windows this is a child window that is transparent to hit testing.
+    /// - On macOS this is an `NSOpenGLView` that is added as a subview of the window's view.
+    #[cfg(feature = "opengl")]
+    pub fn create_gl_area(
+        &mut self, position: crate::PhyPoint, size: crate::PhySize, config: crate::gl::GlConfig,
+    ) -> Result<crate::gl::GlArea, crate::gl::GlError> {
+        self.window.create_gl_area(position, size, config)
+    }
+
+    /// Draw this window's frames as `clock` is advanced instead of on the window's frame timer,
+    /// see the [`virtual_clock`][crate::virtual_clock] module. Call this from the closure that
+    /// builds the handler so the window doesn't draw any frames in real time.
+    #[cfg(feature = "virtual-clock")]
+    pub fn set_virtual_clock(&mut self, clock: &crate::virtual_clock::VirtualClock) {
+        self.window.set_virtual_clock(clock);
+    }
+}
+
+/// Wrap the handler built by `build` so its events end up in the [event log][crate::event_log].
+#[cfg(feature = "event-log")]
+fn log_events<H, B>(build: B) -> impl FnOnce(&mut Window) -> crate::event_log::EventLogger<H>
+where
+    H: WindowHandler + 'static,
+    B: FnOnce(&mut Window) -> H,
+    B: Send + 'static,
+{
+    move |window: &mut Window| crate::event_log::EventLogger::new(build(window))
+}
+
+/// The type-erased `build` closure passed to [`Window::open_parented_scoped()`].
+#[cfg(any(target_os = "windows", target_os = "macos"))]
+type ScopedBuild<'env> = Box<dyn FnOnce(&mut Window) -> Box<dyn WindowHandler + 'env> + 'env>;
+
+/// Forwards to a type-erased handler whose lifetime has been extended by
+/// [`Window::open_parented_scoped()`].
+#[cfg(any(target_os = "windows", target_os = "macos"))]
+struct ScopedHandler(Box<dyn WindowHandler>);
+
+#[cfg(any(target_os = "windows", target_os = "macos"))]
+impl WindowHandler for ScopedHandler {
+    fn on_frame(&mut self, window: &mut Window) {
+        self.0.on_frame(window)
+    }
+
+    fn on_event(&mut self, window: &mut Window, event: Event) -> EventStatus {
+        self.0.on_event(window, event)
+    }
+
+    fn before_events(&mut self, window: &mut Window) {
+        self.0.before_events(window)
+    }
+
+    fn after_events(&mut self, window: &mut Window) {
+        self.0.after_events(window)
+    }
+
+    #[cfg(feature = "opengl")]
+    fn on_present(&mut self, window: &mut Window, info: crate::gl::PresentInfo) {
+        self.0.on_present(window, info)
+    }
+}
+
+/// Allows moving a value into a closure that requires `Send` when that closure is known to be
+/// called on the current thread.
+#[cfg(any(target_os = "windows", target_os = "macos"))]
+struct AssertSend<T>(T);
+
+#[cfg(any(target_os = "windows", target_os = "macos"))]
+unsafe impl<T> Send for AssertSend<T> {}
+
+#[cfg(any(target_os = "windows", target_os = "macos"))]
+impl<T> AssertSend<T> {
+    fn into_inner(self) -> T {
+        self.0
+    }
 }
 
 unsafe impl<'a> HasRawWindowHandle for Window<'a> {
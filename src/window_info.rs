@@ -1,5 +1,40 @@
+/// How fractional physical coordinates are turned into whole pixels when converting from logical
+/// to physical coordinates. At fractional scale factors a logical size usually doesn't map to a
+/// whole number of physical pixels, and a toolkit that rounds differently from baseview ends up one
+/// pixel off, for instance between its OpenGL viewport and the window's actual size.
+///
+/// Baseview itself always uses [`Rounding::Round`], which is the default.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "recording", derive(serde::Serialize, serde::Deserialize))]
+pub enum Rounding {
+    /// Round down
+    Floor,
+    /// Round to the nearest pixel, with halfway cases rounded away from zero
+    Round,
+    /// Round up
+    Ceil,
+}
+
+impl Default for Rounding {
+    fn default() -> Self {
+        Rounding::Round
+    }
+}
+
+impl Rounding {
+    /// Round a physical coordinate to a whole number of pixels.
+    pub fn apply(self, value: f64) -> f64 {
+        match self {
+            Rounding::Floor => value.floor(),
+            Rounding::Round => value.round(),
+            Rounding::Ceil => value.ceil(),
+        }
+    }
+}
+
 /// The info about the window
 #[derive(Debug, Copy, Clone)]
+#[cfg_attr(feature = "recording", derive(serde::Serialize, serde::Deserialize))]
 pub struct WindowInfo {
     logical_size: Size,
     physical_size: PhySize,
@@ -9,16 +44,46 @@ pub struct WindowInfo {
 
 impl WindowInfo {
     pub fn from_logical_size(logical_size: Size, scale: f64) -> Self {
+        Self::from_logical_size_rounded(logical_size, scale, Rounding::Round)
+    }
+
+    /// Like [`from_logical_size()`][Self::from_logical_size()], but with a custom rounding policy
+    /// for the physical size.
+    pub fn from_logical_size_rounded(logical_size: Size, scale: f64, rounding: Rounding) -> Self {
         let scale_recip = if scale == 1.0 { 1.0 } else { 1.0 / scale };
 
         let physical_size = PhySize {
-            width: (logical_size.width * scale).round() as u32,
-            height: (logical_size.height * scale).round() as u32,
+            width: rounding.apply(logical_size.width * scale) as u32,
+            height: rounding.apply(logical_size.height * scale) as u32,
         };
 
         Self { logical_size, physical_size, scale, scale_recip }
     }
 
+    /// Like [`from_logical_size()`][Self::from_logical_size()], but the logical size is adjusted
+    /// to match the rounded physical size. Converting the resulting logical size to physical
+    /// pixels and back again always gives the same sizes, which isn't the case at fractional
+    /// scale factors when the logical size doesn't map to a whole number of pixels.
+    ///
+    /// ```
+    /// # use baseview::{Size, WindowInfo};
+    /// for scale in [1.0, 1.25, 1.5, 1.75, 2.0, 2.25] {
+    ///     for width in [1.0, 333.0, 333.3, 500.0, 1001.7] {
+    ///         let snapped = WindowInfo::from_logical_size_snapped(Size::new(width, 250.0), scale);
+    ///         let from_logical = WindowInfo::from_logical_size(snapped.logical_size(), scale);
+    ///         let from_physical = WindowInfo::from_physical_size(snapped.physical_size(), scale);
+    ///
+    ///         assert_eq!(from_logical.physical_size(), snapped.physical_size());
+    ///         assert_eq!(from_physical.logical_size(), snapped.logical_size());
+    ///     }
+    /// }
+    /// ```
+    pub fn from_logical_size_snapped(logical_size: Size, scale: f64) -> Self {
+        let physical_size = Self::from_logical_size(logical_size, scale).physical_size;
+
+        Self::from_physical_size(physical_size, scale)
+    }
+
     pub fn from_physical_size(physical_size: PhySize, scale: f64) -> Self {
         let scale_recip = if scale == 1.0 { 1.0 } else { 1.0 / scale };
 
@@ -53,6 +118,7 @@ impl WindowInfo {
 
 /// A point in logical coordinates
 #[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "recording", derive(serde::Serialize, serde::Deserialize))]
 pub struct Point {
     pub x: f64,
     pub y: f64,
@@ -67,15 +133,22 @@ impl Point {
     /// Convert to actual physical coordinates
     #[inline]
     pub fn to_physical(&self, window_info: &WindowInfo) -> PhyPoint {
+        self.to_physical_rounded(window_info, Rounding::Round)
+    }
+
+    /// Convert to actual physical coordinates using a custom rounding policy
+    #[inline]
+    pub fn to_physical_rounded(&self, window_info: &WindowInfo, rounding: Rounding) -> PhyPoint {
         PhyPoint {
-            x: (self.x * window_info.scale()).round() as i32,
-            y: (self.y * window_info.scale()).round() as i32,
+            x: rounding.apply(self.x * window_info.scale()) as i32,
+            y: rounding.apply(self.y * window_info.scale()) as i32,
         }
     }
 }
 
 /// A point in actual physical coordinates
 #[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "recording", derive(serde::Serialize, serde::Deserialize))]
 pub struct PhyPoint {
     pub x: i32,
     pub y: i32,
@@ -99,6 +172,7 @@ impl PhyPoint {
 
 /// A size in logical coordinates
 #[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "recording", derive(serde::Serialize, serde::Deserialize))]
 pub struct Size {
     pub width: f64,
     pub height: f64,
@@ -113,15 +187,22 @@ impl Size {
     /// Convert to actual physical size
     #[inline]
     pub fn to_physical(&self, window_info: &WindowInfo) -> PhySize {
+        self.to_physical_rounded(window_info, Rounding::Round)
+    }
+
+    /// Convert to actual physical size using a custom rounding policy
+    #[inline]
+    pub fn to_physical_rounded(&self, window_info: &WindowInfo, rounding: Rounding) -> PhySize {
         PhySize {
-            width: (self.width * window_info.scale()).round() as u32,
-            height: (self.height * window_info.scale()).round() as u32,
+            width: rounding.apply(self.width * window_info.scale()) as u32,
+            height: rounding.apply(self.height * window_info.scale()) as u32,
         }
     }
 }
 
 /// An actual size in physical coordinates
 #[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "recording", derive(serde::Serialize, serde::Deserialize))]
 pub struct PhySize {
     pub width: u32,
     pub height: u32,
@@ -142,3 +223,97 @@ impl PhySize {
         }
     }
 }
+
+/// A rectangle in logical coordinates
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "recording", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rect {
+    /// The top left corner
+    pub origin: Point,
+    pub size: Size,
+}
+
+impl Rect {
+    /// Create a new rectangle in logical coordinates
+    pub fn new(origin: Point, size: Size) -> Self {
+        Self { origin, size }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROUNDINGS: [Rounding; 3] = [Rounding::Floor, Rounding::Round, Rounding::Ceil];
+
+    #[test]
+    fn rounds_halfway_cases_away_from_zero() {
+        assert_eq!(Rounding::default(), Rounding::Round);
+        assert_eq!(Rounding::Round.apply(2.5), 3.0);
+        assert_eq!(Rounding::Round.apply(-2.5), -3.0);
+        assert_eq!(Rounding::Floor.apply(-2.5), -3.0);
+        assert_eq!(Rounding::Ceil.apply(-2.5), -2.0);
+
+        // Whole pixels are left alone
+        for rounding in ROUNDINGS {
+            assert_eq!(rounding.apply(4.0), 4.0);
+        }
+    }
+
+    #[test]
+    fn rounds_sizes_at_fractional_scales() {
+        // 333 logical pixels are 416.25, 499.5, and 582.75 physical pixels
+        let cases = [(1.25, [416, 416, 417]), (1.5, [499, 500, 500]), (1.75, [582, 583, 583])];
+        for (scale, expected) in cases {
+            let window_info = WindowInfo::from_logical_size(Size::new(100.0, 100.0), scale);
+            for (rounding, width) in ROUNDINGS.iter().copied().zip(expected) {
+                let size = Size::new(333.0, 0.0).to_physical_rounded(&window_info, rounding);
+                assert_eq!(size, PhySize::new(width, 0));
+
+                let window_info =
+                    WindowInfo::from_logical_size_rounded(Size::new(333.0, 0.0), scale, rounding);
+                assert_eq!(window_info.physical_size(), PhySize::new(width, 0));
+                assert_eq!(window_info.logical_size(), Size::new(333.0, 0.0));
+            }
+        }
+    }
+
+    #[test]
+    fn snapped_sizes_round_trip() {
+        for scale in [1.25, 1.5, 1.75] {
+            for width in [1.0, 333.0, 333.3, 500.0, 1001.7] {
+                let snapped = WindowInfo::from_logical_size_snapped(Size::new(width, 250.0), scale);
+                let physical_size = snapped.logical_size().to_physical(&snapped);
+                let logical_size = physical_size.to_logical(&snapped);
+
+                assert_eq!(physical_size, snapped.physical_size());
+                assert_eq!(logical_size, snapped.logical_size());
+            }
+        }
+    }
+
+    #[test]
+    fn snapped_sizes_survive_scale_changes() {
+        // This is what the backends do when the scale factor changes: the logical size is kept,
+        // and the window is resized to the matching physical size
+        let mut window_info = WindowInfo::from_logical_size_snapped(Size::new(333.3, 250.0), 1.0);
+        for scale in [1.25, 1.5, 1.75, 1.25] {
+            let resized = WindowInfo::from_logical_size_snapped(window_info.logical_size(), scale);
+            let reported = WindowInfo::from_physical_size(resized.physical_size(), scale);
+
+            assert_eq!(reported.logical_size(), resized.logical_size());
+            window_info = resized;
+        }
+    }
+
+    #[test]
+    fn rounds_points_at_fractional_scales() {
+        let window_info = WindowInfo::from_logical_size(Size::new(100.0, 100.0), 1.5);
+        let point = Point::new(3.0, -3.0);
+
+        assert_eq!(point.to_physical_rounded(&window_info, Rounding::Floor), PhyPoint::new(4, -5));
+        assert_eq!(point.to_physical_rounded(&window_info, Rounding::Round), PhyPoint::new(5, -5));
+        assert_eq!(point.to_physical_rounded(&window_info, Rounding::Ceil), PhyPoint::new(5, -4));
+        assert_eq!(point.to_physical(&window_info), PhyPoint::new(5, -5));
+    }
+}
@@ -1,4 +1,7 @@
-use crate::Size;
+use std::error::Error;
+use std::fmt;
+
+use crate::{Size, WindowInfo};
 
 /// The dpi scaling policy of the window
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -7,6 +10,161 @@ pub enum WindowScalePolicy {
     SystemScaleFactor,
     /// Use the given dpi scale factor (e.g. `1.0` = 96 dpi)
     ScaleFactor(f64),
+    /// Always use a scale factor of `1.0` and opt out of the system's DPI handling altogether, for
+    /// hosts that already scale plugin windows themselves. Unlike `ScaleFactor(1.0)`:
+    ///
+    /// - On Windows the process is not marked as DPI aware. If the host isn't DPI aware either,
+    ///   then Windows scales the window up itself on high DPI displays.
+    /// - On macOS the scale factor stays at `1.0` on Retina displays, so physical pixels are the
+    ///   same as points. OpenGL contexts render at that resolution and macOS scales them up.
+    /// - On X11 there is no system scaling to opt out of, so this is the same as
+    ///   `ScaleFactor(1.0)`.
+    ForceUnscaled,
+    /// Use the scale factor passed by the host, like CLAP's `set_scale()` and VST3's
+    /// `IPlugViewContentScaleSupport`. This is the initial scale factor, and the host can change
+    /// it later through [`WindowHandle::set_scale_factor()`]. The system's scale factor is ignored.
+    ///
+    /// On macOS hosts don't pass scale factors since sizes are always in points, so there this
+    /// behaves like `SystemScaleFactor`.
+    ///
+    /// [`WindowHandle::set_scale_factor()`]: crate::WindowHandle::set_scale_factor()
+    HostControlled(f64),
+}
+
+/// The categories of input events a window subscribes to. Input that the window doesn't subscribe
+/// to is not delivered to its [`WindowHandler`][crate::WindowHandler], and where the platform
+/// allows it that input goes to the parent window instead. This lets a plugin window leave, for
+/// instance, keyboard handling to the host.
+///
+/// On Linux unselected events propagate to the parent window. On Windows unhandled messages are
+/// passed to `DefWindowProc()`, which forwards scroll wheel messages to the parent window. On macOS
+/// unhandled events are passed on to the next responder, which is usually the parent view.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputEvents {
+    /// Key presses and releases. Without these the window will never take keyboard focus on
+    /// macOS.
+    pub keyboard: bool,
+    /// Cursor movement, including the cursor entering and leaving the window.
+    pub mouse_motion: bool,
+    /// Mouse button presses and releases, click pressure changes, and scroll wheel events. These
+    /// are one category because X11 reports scrolling as button presses.
+    pub mouse_buttons: bool,
+    /// Tab and the arrow keys, which hosts that run their windows as dialogs on Windows would
+    /// otherwise use to move the focus between controls. Without these the keys never reach the
+    /// window in those hosts. This only has an effect on Windows, and only together with
+    /// `keyboard`. All keys are claimed while text input is active, see
+    /// [`Window::set_text_input_active()`][crate::Window::set_text_input_active()].
+    pub navigation_keys: bool,
+    /// Alt and F10 pressed on their own, which Windows would otherwise pass on to the host so it
+    /// can activate its menu bar. Without these the keys are still sent to the window, but the
+    /// host's menu bar is activated as usual, taking the keyboard focus with it. Claiming them
+    /// keeps Alt-modified drags from activating the menu bar when Alt is released. This only has
+    /// an effect on Windows, and only together with `keyboard`. Alt+F4 is always passed on so it
+    /// can close the host's window.
+    pub menu_keys: bool,
+}
+
+impl InputEvents {
+    /// Subscribe to all input events. This is the default.
+    pub const ALL: Self = Self {
+        keyboard: true,
+        mouse_motion: true,
+        mouse_buttons: true,
+        navigation_keys: true,
+        menu_keys: true,
+    };
+    /// Don't subscribe to any input events, leaving all input to the parent window.
+    pub const NONE: Self = Self {
+        keyboard: false,
+        mouse_motion: false,
+        mouse_buttons: false,
+        navigation_keys: false,
+        menu_keys: false,
+    };
+}
+
+impl Default for InputEvents {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+/// How the window's view is backed by a Core Animation layer on macOS. Renderers differ in what
+/// they expect here: Metal and wgpu based renderers attach a layer of their own, while renderers
+/// that draw into the view through AppKit can flicker while resizing when the view is layer-backed.
+/// These options have no effect on other platforms.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MacLayerConfig {
+    /// Make the view layer-backed by setting its `wantsLayer` property. Without this the view draws
+    /// into the window's backing store like a classic NSView, unless a renderer attaches a layer
+    /// to it.
+    pub wants_layer: bool,
+    /// The `contentsScale` of the view's layer, which is applied whenever the view's size or
+    /// backing scale factor changes.
+    pub contents_scale: LayerContentsScale,
+    /// The `autoresizingMask` of the view's layer as a raw `CAAutoresizingMask`, for instance
+    /// `0x12` for `kCALayerWidthSizable | kCALayerHeightSizable`. The layer's mask is left alone
+    /// when this is `None`.
+    pub autoresizing_mask: Option<u32>,
+}
+
+impl MacLayerConfig {
+    /// Leave the view's layer to AppKit and the renderer. This is the default.
+    pub const DEFAULT: Self = Self {
+        wants_layer: false,
+        contents_scale: LayerContentsScale::Unchanged,
+        autoresizing_mask: None,
+    };
+}
+
+impl Default for MacLayerConfig {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// See [`MacLayerConfig::contents_scale`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LayerContentsScale {
+    /// Leave the layer's contents scale alone. Layers created by a renderer default to a contents
+    /// scale of `1.0`, which looks blurry on Retina displays.
+    Unchanged,
+    /// Keep the contents scale at the window's backing scale factor. This is `1.0` with
+    /// [`WindowScalePolicy::ForceUnscaled`].
+    BackingScaleFactor,
+    /// Use a fixed contents scale.
+    Fixed(f64),
+}
+
+/// How the window's visual is chosen on X11. The visual determines the window's color depth, and
+/// whether it has an alpha channel. Some hosts misrender child windows whose depth differs from
+/// their own, in which case the parent's visual or a 24-bit visual works better. This has no effect
+/// on other platforms.
+///
+/// Some X servers and hosts refuse to create a child window whose visual differs from its parent's.
+/// The window then falls back to the parent's visual, which is reported through the
+/// [platform error callback][crate::set_platform_error_callback()], except with
+/// [`X11Visual::RequireArgb`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum X11Visual {
+    /// Use a 32-bit ARGB visual if the server has one so the window can be transparent, and the
+    /// parent window's visual otherwise. With an OpenGL context the visual is chosen to match the
+    /// context's configuration instead. This is the default.
+    PreferArgb,
+    /// Use a 32-bit ARGB visual. Opening the window fails if the server doesn't have one, or if the
+    /// window can't be created with it.
+    RequireArgb,
+    /// Use the parent window's visual, which for parented windows is the host's visual.
+    Parent,
+    /// Use a 24-bit TrueColor visual without an alpha channel, or the parent window's visual if the
+    /// server doesn't have one.
+    TrueColor24,
+}
+
+impl Default for X11Visual {
+    fn default() -> Self {
+        Self::PreferArgb
+    }
 }
 
 /// The options for opening a new window
@@ -22,8 +180,371 @@ pub struct WindowOpenOptions {
     /// The dpi scaling policy
     pub scale: WindowScalePolicy,
 
+    /// The categories of input events the window subscribes to
+    pub input_events: InputEvents,
+
+    /// Adjust the initial logical size so it maps to a whole number of physical pixels, see
+    /// [`WindowInfo::from_logical_size_snapped()`]. At fractional scale factors the logical size
+    /// the handler is first told about otherwise differs slightly from the one it's told about
+    /// after the window has been resized, since that one is computed from the physical size.
+    pub snap_logical_size: bool,
+
+    /// Open a standalone window without the system's title bar and borders, for applications that
+    /// draw their own. The window can still be resized from its edges, and it keeps the system's
+    /// drop shadow. Moving the window is up to the application. This only has an effect on
+    /// Windows, and not for parented windows.
+    pub borderless: bool,
+
+    /// Open a standalone window as a floating panel that doesn't activate the application when it's
+    /// clicked, so a tool window can be used without taking activation away from a DAW that's
+    /// controlled with the keyboard. The panel can still receive keyboard input. This only has an
+    /// effect on macOS, and not for parented windows.
+    pub non_activating: bool,
+
+    /// Data formats besides files that the window accepts in drag and drop, like the clip formats
+    /// of a DAW. When the dragged data offers one of these formats, the handler receives its raw
+    /// contents as [`DropData::Custom`][crate::DropData::Custom]. Formats earlier in the list
+    /// take precedence. On Linux these are XDND targets, which are usually MIME types. On Windows
+    /// they are the names of registered clipboard formats, and on macOS they are pasteboard types.
+    pub drop_formats: Vec<String>,
+
+    /// Send [`MouseEvent::Zoomed`][crate::MouseEvent::Zoomed] instead of
+    /// [`MouseEvent::WheelScrolled`][crate::MouseEvent::WheelScrolled] for vertical scrolling
+    /// while Ctrl is held, like browsers do. Windows reports pinch gestures on touchpads the same
+    /// way. On macOS pinch gestures are sent as zoom events as well.
+    pub wheel_zoom: bool,
+
+    /// Don't run the window's frame timer. Instead, [`WindowHandler::on_frame()`] is called each
+    /// time the host calls [`WindowHandle::tick_frame()`], for instance from the idle callbacks of
+    /// plugin APIs whose hosts expect the editor to be drawn from there. This only has an effect
+    /// for parented windows.
+    ///
+    /// [`WindowHandler::on_frame()`]: crate::WindowHandler::on_frame()
+    /// [`WindowHandle::tick_frame()`]: crate::WindowHandle::tick_frame()
+    pub external_frame_clock: bool,
+
+    /// How the view is backed by a Core Animation layer on macOS, see [`MacLayerConfig`].
+    pub mac_layer: MacLayerConfig,
+
+    /// How the window's visual is chosen on X11, see [`X11Visual`]. With an OpenGL context and a
+    /// visual other than [`X11Visual::PreferArgb`], the context's configuration is matched against
+    /// the chosen visual.
+    pub x11_visual: X11Visual,
+
     /// If provided, then an OpenGL context will be created for this window. You'll be able to
     /// access this context through [crate::Window::gl_context].
     #[cfg(feature = "opengl")]
     pub gl_config: Option<crate::gl::GlConfig>,
 }
+
+impl WindowOpenOptions {
+    /// Create a [`WindowOpenOptionsBuilder`], which starts out with sensible defaults and checks
+    /// the options with [`validate()`][Self::validate()] when they are built.
+    pub fn builder() -> WindowOpenOptionsBuilder {
+        WindowOpenOptionsBuilder::default()
+    }
+
+    /// Check whether these options make sense before they are passed on to the platform code.
+    pub fn validate(&self) -> Result<(), WindowOpenOptionsError> {
+        let Size { width, height } = self.size;
+        if !(width.is_finite() && height.is_finite() && width >= 1.0 && height >= 1.0) {
+            return Err(WindowOpenOptionsError::InvalidSize(self.size));
+        }
+
+        if let WindowScalePolicy::ScaleFactor(scale) | WindowScalePolicy::HostControlled(scale) =
+            self.scale
+        {
+            if !(scale.is_finite() && scale > 0.0) {
+                return Err(WindowOpenOptionsError::InvalidScaleFactor(scale));
+            }
+        }
+
+        if let LayerContentsScale::Fixed(scale) = self.mac_layer.contents_scale {
+            if !(scale.is_finite() && scale > 0.0) {
+                return Err(WindowOpenOptionsError::InvalidScaleFactor(scale));
+            }
+        }
+
+        #[cfg(feature = "opengl")]
+        if let Some(gl_config) = &self.gl_config {
+            if gl_config.version.0 == 0 {
+                return Err(WindowOpenOptionsError::InvalidGlConfig(
+                    "the OpenGL major version must be at least 1",
+                ));
+            }
+            if gl_config.samples == Some(0) {
+                return Err(WindowOpenOptionsError::InvalidGlConfig(
+                    "the number of samples must be at least 1, use `None` to disable multisampling",
+                ));
+            }
+            if gl_config.max_frame_latency == Some(0) {
+                return Err(WindowOpenOptionsError::InvalidGlConfig(
+                    "the maximum frame latency must be at least 1, use `None` for no limit",
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The window's initial size at the given scale factor, taking
+    /// [`snap_logical_size`][Self::snap_logical_size] into account.
+    pub(crate) fn initial_window_info(&self, scale: f64) -> WindowInfo {
+        if self.snap_logical_size {
+            WindowInfo::from_logical_size_snapped(self.size, scale)
+        } else {
+            WindowInfo::from_logical_size(self.size, scale)
+        }
+    }
+}
+
+/// The reason why [`WindowOpenOptions::validate()`] rejected a set of options
+#[derive(Debug, Clone, PartialEq)]
+pub enum WindowOpenOptionsError {
+    /// The window's size was not at least one logical pixel in both dimensions
+    InvalidSize(Size),
+    /// A fixed scale factor or layer contents scale was not a positive number
+    InvalidScaleFactor(f64),
+    /// The OpenGL configuration cannot be satisfied
+    #[cfg(feature = "opengl")]
+    InvalidGlConfig(&'static str),
+}
+
+impl fmt::Display for WindowOpenOptionsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WindowOpenOptionsError::InvalidSize(size) => write!(
+                f,
+                "invalid window size {}x{}, the size must be at least 1x1",
+                size.width, size.height
+            ),
+            WindowOpenOptionsError::InvalidScaleFactor(scale) => {
+                write!(f, "invalid scale factor {}, the scale factor must be positive", scale)
+            }
+            #[cfg(feature = "opengl")]
+            WindowOpenOptionsError::InvalidGlConfig(reason) => {
+                write!(f, "invalid OpenGL configuration: {}", reason)
+            }
+        }
+    }
+}
+
+impl Error for WindowOpenOptionsError {}
+
+/// A builder for [`WindowOpenOptions`], created with [`WindowOpenOptions::builder()`].
+///
+/// OpenGL contexts can only be requested with [`gl_config()`][Self::gl_config()] when the
+/// `opengl` feature is enabled, so that mistake is caught at compile time.
+pub struct WindowOpenOptionsBuilder {
+    options: WindowOpenOptions,
+}
+
+impl Default for WindowOpenOptionsBuilder {
+    fn default() -> Self {
+        Self {
+            options: WindowOpenOptions {
+                title: String::from("baseview"),
+                size: Size::new(500.0, 400.0),
+                scale: WindowScalePolicy::SystemScaleFactor,
+                input_events: InputEvents::ALL,
+                snap_logical_size: false,
+                borderless: false,
+                non_activating: false,
+                drop_formats: Vec::new(),
+                wheel_zoom: false,
+                external_frame_clock: false,
+                mac_layer: MacLayerConfig::DEFAULT,
+                x11_visual: X11Visual::PreferArgb,
+                #[cfg(feature = "opengl")]
+                gl_config: None,
+            },
+        }
+    }
+}
+
+impl WindowOpenOptionsBuilder {
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.options.title = title.into();
+        self
+    }
+
+    /// The logical size of the window. Defaults to 500x400.
+    pub fn size(mut self, size: Size) -> Self {
+        self.options.size = size;
+        self
+    }
+
+    /// The dpi scaling policy. Defaults to [`WindowScalePolicy::SystemScaleFactor`].
+    pub fn scale(mut self, scale: WindowScalePolicy) -> Self {
+        self.options.scale = scale;
+        self
+    }
+
+    /// The categories of input events the window subscribes to. Defaults to [`InputEvents::ALL`].
+    pub fn input_events(mut self, input_events: InputEvents) -> Self {
+        self.options.input_events = input_events;
+        self
+    }
+
+    /// Snap the initial logical size to whole physical pixels. Defaults to `false`.
+    pub fn snap_logical_size(mut self, snap_logical_size: bool) -> Self {
+        self.options.snap_logical_size = snap_logical_size;
+        self
+    }
+
+    /// Open a standalone window without the system's title bar and borders, see
+    /// [`WindowOpenOptions::borderless`]. Defaults to `false`.
+    pub fn borderless(mut self, borderless: bool) -> Self {
+        self.options.borderless = borderless;
+        self
+    }
+
+    /// Open a standalone window as a non-activating floating panel on macOS, see
+    /// [`WindowOpenOptions::non_activating`]. Defaults to `false`.
+    pub fn non_activating(mut self, non_activating: bool) -> Self {
+        self.options.non_activating = non_activating;
+        self
+    }
+
+    /// Accept a data format in drag and drop, see [`WindowOpenOptions::drop_formats`]. This can be
+    /// called multiple times to accept multiple formats.
+    pub fn drop_format(mut self, format: impl Into<String>) -> Self {
+        self.options.drop_formats.push(format.into());
+        self
+    }
+
+    /// Turn Ctrl+scroll and pinch gestures into zoom events, see [`WindowOpenOptions::wheel_zoom`].
+    /// Defaults to `false`.
+    pub fn wheel_zoom(mut self, wheel_zoom: bool) -> Self {
+        self.options.wheel_zoom = wheel_zoom;
+        self
+    }
+
+    /// Drive the window's frames from the host, see [`WindowOpenOptions::external_frame_clock`].
+    /// Defaults to `false`.
+    pub fn external_frame_clock(mut self, external_frame_clock: bool) -> Self {
+        self.options.external_frame_clock = external_frame_clock;
+        self
+    }
+
+    /// How the view is backed by a Core Animation layer on macOS. Defaults to
+    /// [`MacLayerConfig::DEFAULT`].
+    pub fn mac_layer(mut self, mac_layer: MacLayerConfig) -> Self {
+        self.options.mac_layer = mac_layer;
+        self
+    }
+
+    /// How the window's visual is chosen on X11. Defaults to [`X11Visual::PreferArgb`].
+    pub fn x11_visual(mut self, x11_visual: X11Visual) -> Self {
+        self.options.x11_visual = x11_visual;
+        self
+    }
+
+    /// Create an OpenGL context for the window using this configuration.
+    #[cfg(feature = "opengl")]
+    pub fn gl_config(mut self, gl_config: crate::gl::GlConfig) -> Self {
+        self.options.gl_config = Some(gl_config);
+        self
+    }
+
+    /// Validate and return the options.
+    pub fn build(self) -> Result<WindowOpenOptions, WindowOpenOptionsError> {
+        self.options.validate()?;
+
+        Ok(self.options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn defaults() -> WindowOpenOptions {
+        WindowOpenOptions::builder().build().unwrap()
+    }
+
+    #[test]
+    fn builder_defaults_are_valid() {
+        let options = defaults();
+
+        assert_eq!(options.size, Size::new(500.0, 400.0));
+        assert_eq!(options.scale, WindowScalePolicy::SystemScaleFactor);
+        assert_eq!(options.validate(), Ok(()));
+    }
+
+    #[test]
+    fn rejects_sizes_below_one_pixel() {
+        for size in [
+            Size::new(0.0, 400.0),
+            Size::new(500.0, 0.5),
+            Size::new(-1.0, 400.0),
+            Size::new(f64::NAN, 400.0),
+            Size::new(500.0, f64::INFINITY),
+        ] {
+            let result = WindowOpenOptions::builder().size(size).build();
+            assert!(matches!(result, Err(WindowOpenOptionsError::InvalidSize(_))));
+        }
+
+        let options = WindowOpenOptions { size: Size::new(1.0, 1.0), ..defaults() };
+        assert_eq!(options.validate(), Ok(()));
+    }
+
+    #[test]
+    fn rejects_non_positive_scale_factors() {
+        for scale in [0.0, -2.0, f64::NAN] {
+            let options =
+                WindowOpenOptions { scale: WindowScalePolicy::ScaleFactor(scale), ..defaults() };
+            assert!(matches!(
+                options.validate(),
+                Err(WindowOpenOptionsError::InvalidScaleFactor(_))
+            ));
+
+            let options =
+                WindowOpenOptions { scale: WindowScalePolicy::HostControlled(scale), ..defaults() };
+            assert!(matches!(
+                options.validate(),
+                Err(WindowOpenOptionsError::InvalidScaleFactor(_))
+            ));
+        }
+
+        let options =
+            WindowOpenOptions { scale: WindowScalePolicy::ScaleFactor(1.5), ..defaults() };
+        assert_eq!(options.validate(), Ok(()));
+    }
+
+    #[test]
+    fn rejects_non_positive_layer_contents_scales() {
+        let mac_layer = MacLayerConfig {
+            contents_scale: LayerContentsScale::Fixed(0.0),
+            ..MacLayerConfig::DEFAULT
+        };
+        let options = WindowOpenOptions { mac_layer, ..defaults() };
+
+        assert_eq!(options.validate(), Err(WindowOpenOptionsError::InvalidScaleFactor(0.0)));
+    }
+
+    #[cfg(feature = "opengl")]
+    #[test]
+    fn rejects_impossible_gl_configs() {
+        use crate::gl::{GlConfig, Profile};
+
+        let validate = |gl_config: GlConfig| {
+            WindowOpenOptions { gl_config: Some(gl_config), ..defaults() }.validate()
+        };
+
+        assert_eq!(validate(GlConfig::default()), Ok(()));
+        assert!(validate(GlConfig { version: (0, 0), ..GlConfig::default() }).is_err());
+        // Context profiles only exist since OpenGL 3.2, the profile is ignored for older versions
+        assert_eq!(validate(GlConfig { version: (3, 1), ..GlConfig::default() }), Ok(()));
+        assert_eq!(
+            validate(GlConfig {
+                version: (2, 1),
+                profile: Profile::Compatibility,
+                ..GlConfig::default()
+            }),
+            Ok(())
+        );
+        assert!(validate(GlConfig { samples: Some(0), ..GlConfig::default() }).is_err());
+        assert!(validate(GlConfig { max_frame_latency: Some(0), ..GlConfig::default() }).is_err());
+    }
+}
@@ -0,0 +1,515 @@
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use x11rb::connection::{Connection, RequestConnection};
+use x11rb::protocol::xproto::{
+    Atom, AtomEnum, ChangeWindowAttributesAux, ConnectionExt as _, CreateWindowAux, EventMask,
+    GetPropertyReply, PropMode, Property, PropertyNotifyEvent, SelectionClearEvent,
+    SelectionNotifyEvent, SelectionRequestEvent, Timestamp, Window as XWindow, WindowClass,
+    SELECTION_NOTIFY_EVENT,
+};
+use x11rb::wrapper::ConnectionExt as _;
+use x11rb::{COPY_DEPTH_FROM_PARENT, COPY_FROM_PARENT};
+
+use super::errors::CheckRequest;
+use super::XcbConnection;
+use crate::{ClipboardFormat, Event};
+
+/// Data larger than this is sent in chunks of this size using the `INCR` protocol, as long as the
+/// server's maximum request size doesn't call for smaller chunks.
+const INCR_CHUNK_SIZE: usize = 64 * 1024;
+
+/// How long to wait for the selection's owner to answer a request, or to send the next chunk of
+/// an `INCR` transfer, before giving up on reading that format.
+const READ_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Owns the `CLIPBOARD` selection for [`crate::copy_formats_to_clipboard()`] and answers other
+/// clients' requests for its contents. The selection belongs to the connection, so the copied data
+/// stays available until another client takes over the selection or the connection is closed.
+#[derive(Default)]
+pub(super) struct Clipboard {
+    /// An unmapped window that owns the selection, created the first time something is copied.
+    owner: Cell<Option<XWindow>>,
+    /// The copied data and the target for each of its formats, with text stored as
+    /// `UTF8_STRING`. This is emptied once another client has taken over the selection.
+    contents: RefCell<Vec<(Atom, Rc<[u8]>)>>,
+    /// `INCR` transfers in progress. These keep the data they're sending, so copying something
+    /// else in the meantime doesn't corrupt them.
+    transfers: RefCell<Vec<Transfer>>,
+}
+
+/// The remaining data for an `INCR` transfer. The requestor deletes the property to ask for the
+/// next chunk, and a zero length chunk ends the transfer.
+struct Transfer {
+    requestor: XWindow,
+    property: Atom,
+    target: Atom,
+    data: Rc<[u8]>,
+    offset: usize,
+}
+
+impl Clipboard {
+    /// Replace the clipboard's contents by taking ownership of the `CLIPBOARD` selection.
+    pub fn set(&self, xcb_connection: &XcbConnection, contents: &[(ClipboardFormat, Vec<u8>)]) {
+        let owner = match self.owner(xcb_connection) {
+            Some(owner) => owner,
+            None => return,
+        };
+
+        *self.contents.borrow_mut() = contents
+            .iter()
+            .filter_map(|(format, data)| {
+                Some((format_target(xcb_connection, format)?, Rc::from(data.as_slice())))
+            })
+            .collect();
+
+        // Other clients use the timestamp to tell which owner is the most recent one, so this uses
+        // the time of the input that most likely caused the copy
+        let conn = &xcb_connection.conn;
+        let time = xcb_connection.last_user_time.get();
+        let _ =
+            conn.set_selection_owner(owner, xcb_connection.atoms.CLIPBOARD, time).check_reported();
+        let _ = conn.flush();
+    }
+
+    /// The copied data for `target` and the type it's stored as, if this connection still owns the
+    /// selection and has data for that target. `TEXT` and `STRING` are served from the text.
+    fn data(&self, xcb_connection: &XcbConnection, target: Atom) -> Option<(Atom, Rc<[u8]>)> {
+        let atoms = &xcb_connection.atoms;
+
+        // `STRING` is supposed to be Latin-1, but in practice clients accept UTF-8 there as well.
+        // `TEXT` lets the owner pick the encoding.
+        let (format, data_type) = if target == atoms.UTF8_STRING || target == atoms.TEXT {
+            (atoms.UTF8_STRING, atoms.UTF8_STRING)
+        } else if target == u32::from(AtomEnum::STRING) {
+            (atoms.UTF8_STRING, AtomEnum::STRING.into())
+        } else {
+            (target, target)
+        };
+
+        let contents = self.contents.borrow();
+        let (_, data) = contents.iter().find(|(atom, _)| *atom == format)?;
+
+        Some((data_type, Rc::clone(data)))
+    }
+
+    /// Whether a selection event is meant for the clipboard rather than one of the windows.
+    pub fn owns(&self, window: XWindow) -> bool {
+        self.owner.get() == Some(window)
+    }
+
+    /// Whether a property change on `window` may be part of an `INCR` transfer.
+    pub fn is_transferring_to(&self, window: XWindow) -> bool {
+        self.transfers.borrow().iter().any(|transfer| transfer.requestor == window)
+    }
+
+    pub fn handle_selection_request(
+        &self, xcb_connection: &XcbConnection, request: &SelectionRequestEvent,
+    ) {
+        // Obsolete clients don't specify a property, in which case the target is used instead
+        let property =
+            if request.property == x11rb::NONE { request.target } else { request.property };
+
+        let converted = request.selection == xcb_connection.atoms.CLIPBOARD
+            && self.convert(xcb_connection, request.requestor, property, request.target);
+
+        let notify = SelectionNotifyEvent {
+            response_type: SELECTION_NOTIFY_EVENT,
+            sequence: 0,
+            time: request.time,
+            requestor: request.requestor,
+            selection: request.selection,
+            target: request.target,
+            property: if converted { property } else { x11rb::NONE },
+        };
+
+        let conn = &xcb_connection.conn;
+        let _ = conn.send_event(false, request.requestor, EventMask::NO_EVENT, notify);
+        let _ = conn.flush();
+    }
+
+    pub fn handle_selection_clear(
+        &self, xcb_connection: &XcbConnection, event: &SelectionClearEvent,
+    ) {
+        if event.selection == xcb_connection.atoms.CLIPBOARD {
+            self.contents.borrow_mut().clear();
+        }
+    }
+
+    /// Send the next chunk of an `INCR` transfer once the requestor has deleted the previous one.
+    pub fn handle_property_notify(
+        &self, xcb_connection: &XcbConnection, event: &PropertyNotifyEvent,
+    ) {
+        if event.state != Property::DELETE {
+            return;
+        }
+
+        let conn = &xcb_connection.conn;
+        let mut transfers = self.transfers.borrow_mut();
+        let index = match transfers.iter().position(|transfer| {
+            transfer.requestor == event.window && transfer.property == event.atom
+        }) {
+            Some(index) => index,
+            None => return,
+        };
+
+        let transfer = &mut transfers[index];
+        let chunk_size = chunk_size(conn);
+        let end = (transfer.offset + chunk_size).min(transfer.data.len());
+        let chunk = &transfer.data[transfer.offset..end];
+        let _ = conn.change_property8(
+            PropMode::REPLACE,
+            transfer.requestor,
+            transfer.property,
+            transfer.target,
+            chunk,
+        );
+
+        // The zero length chunk has now been sent as well
+        if chunk.is_empty() {
+            let transfer = transfers.remove(index);
+            if !transfers.iter().any(|other| other.requestor == transfer.requestor) {
+                let _ = conn.change_window_attributes(
+                    transfer.requestor,
+                    &ChangeWindowAttributesAux::new().event_mask(EventMask::NO_EVENT),
+                );
+            }
+        } else {
+            transfer.offset = end;
+        }
+
+        let _ = conn.flush();
+    }
+
+    /// Store the data on the requestor's window in the format it asked for. Returns `false` if the
+    /// format isn't available.
+    fn convert(
+        &self, xcb_connection: &XcbConnection, requestor: XWindow, property: Atom, target: Atom,
+    ) -> bool {
+        let conn = &xcb_connection.conn;
+        let atoms = &xcb_connection.atoms;
+
+        if target == atoms.TARGETS {
+            let mut targets = vec![atoms.TARGETS];
+            for &(format, _) in self.contents.borrow().iter() {
+                if format == atoms.UTF8_STRING {
+                    targets.extend([atoms.UTF8_STRING, atoms.TEXT, AtomEnum::STRING.into()]);
+                } else {
+                    targets.push(format);
+                }
+            }
+
+            let _ = conn.change_property32(
+                PropMode::REPLACE,
+                requestor,
+                property,
+                AtomEnum::ATOM,
+                &targets,
+            );
+
+            return true;
+        }
+
+        let (data_type, data) = match self.data(xcb_connection, target) {
+            Some(data) => data,
+            None => return false,
+        };
+
+        // Our client's event mask on one of our own windows is that window's event mask, so `INCR`
+        // can only be used for other clients' windows
+        let setup = conn.setup();
+        let is_own_window = requestor & !setup.resource_id_mask == setup.resource_id_base;
+        if data.len() <= chunk_size(conn) || is_own_window {
+            let _ = conn.change_property8(PropMode::REPLACE, requestor, property, data_type, &data);
+            return true;
+        }
+
+        // The requestor deletes the property once it has seen the `INCR` type, which starts the
+        // transfer in `handle_property_notify()`
+        let _ = conn.change_window_attributes(
+            requestor,
+            &ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE),
+        );
+        let _ = conn.change_property32(
+            PropMode::REPLACE,
+            requestor,
+            property,
+            atoms.INCR,
+            &[data.len() as u32],
+        );
+
+        let mut transfers = self.transfers.borrow_mut();
+        transfers
+            .retain(|transfer| transfer.requestor != requestor || transfer.property != property);
+        transfers.push(Transfer { requestor, property, target: data_type, data, offset: 0 });
+
+        true
+    }
+
+    fn owner(&self, xcb_connection: &XcbConnection) -> Option<XWindow> {
+        if let Some(owner) = self.owner.get() {
+            return Some(owner);
+        }
+
+        let conn = &xcb_connection.conn;
+        let owner = conn.generate_id().ok()?;
+        conn.create_window(
+            COPY_DEPTH_FROM_PARENT,
+            owner,
+            xcb_connection.screen().root,
+            0,
+            0,
+            1,
+            1,
+            0,
+            WindowClass::INPUT_ONLY,
+            COPY_FROM_PARENT,
+            &CreateWindowAux::new(),
+        )
+        .check_reported()
+        .ok()?;
+
+        self.owner.set(Some(owner));
+        Some(owner)
+    }
+}
+
+/// Reads the clipboard's contents for [`crate::Window::request_clipboard_data()`]. The owner
+/// converts the selection to a property on the window, possibly in chunks through `INCR`, after
+/// which the data is sent to the handler at the end of the event batch.
+#[derive(Default)]
+pub(super) struct ClipboardReader {
+    /// The formats that have been requested but haven't been read yet. The first one is being
+    /// read.
+    requests: RefCell<VecDeque<ClipboardFormat>>,
+    state: Cell<ReadState>,
+    /// When to give up on the format being read if the owner doesn't answer.
+    deadline: Cell<Option<Instant>>,
+    /// The chunks of an `INCR` transfer received so far.
+    incremental_data: RefCell<Option<Vec<u8>>>,
+    /// The events for the formats that have been read.
+    events: RefCell<Vec<Event>>,
+}
+
+#[derive(Clone, Copy, Default)]
+enum ReadState {
+    #[default]
+    Idle,
+    /// Waiting for the owner to convert the selection to `target`.
+    Converting { target: Atom },
+    /// Receiving the data in chunks.
+    Incremental,
+}
+
+impl ClipboardReader {
+    /// Ask the selection's owner for its data in `format`. Formats that are already being read
+    /// are only sent once.
+    pub fn request(
+        &self, xcb_connection: &XcbConnection, window: XWindow, time: Timestamp,
+        format: ClipboardFormat,
+    ) {
+        let mut requests = self.requests.borrow_mut();
+        if requests.contains(&format) {
+            return;
+        }
+
+        requests.push_back(format);
+        if requests.len() == 1 {
+            drop(requests);
+            self.read_next(xcb_connection, window, time);
+        }
+    }
+
+    pub fn handle_selection_notify(
+        &self, xcb_connection: &XcbConnection, event: &SelectionNotifyEvent,
+    ) {
+        let target = match self.state.get() {
+            // A late reply to a request that timed out is ignored
+            ReadState::Converting { target }
+                if event.selection == xcb_connection.atoms.CLIPBOARD && event.target == target =>
+            {
+                target
+            }
+            _ => return,
+        };
+
+        if event.property == x11rb::NONE {
+            // Older clients may only support `STRING`
+            let string = AtomEnum::STRING.into();
+            if target == xcb_connection.atoms.UTF8_STRING
+                && convert(xcb_connection, event.requestor, string, event.time)
+            {
+                self.set_state(ReadState::Converting { target: string });
+            } else {
+                self.finish(xcb_connection, event.requestor, event.time, None);
+            }
+
+            return;
+        }
+
+        // Deleting the property tells the owner to start sending chunks if it uses `INCR`
+        match read_property(xcb_connection, event.requestor, event.property) {
+            Some(reply) if reply.type_ == xcb_connection.atoms.INCR => {
+                self.set_state(ReadState::Incremental);
+                *self.incremental_data.borrow_mut() = Some(Vec::new());
+            }
+            Some(reply) => {
+                let data = decode(reply.type_, reply.value);
+                self.finish(xcb_connection, event.requestor, event.time, Some(data));
+            }
+            None => self.finish(xcb_connection, event.requestor, event.time, None),
+        }
+    }
+
+    pub fn handle_property_notify(
+        &self, xcb_connection: &XcbConnection, event: &PropertyNotifyEvent,
+    ) {
+        if !matches!(self.state.get(), ReadState::Incremental)
+            || event.state != Property::NEW_VALUE
+            || event.atom != xcb_connection.atoms.CLIPBOARD
+        {
+            return;
+        }
+
+        let reply = match read_property(xcb_connection, event.window, event.atom) {
+            Some(reply) => reply,
+            None => {
+                self.finish(xcb_connection, event.window, event.time, None);
+                return;
+            }
+        };
+
+        // A zero length chunk ends the transfer
+        if reply.value.is_empty() {
+            let data = self.incremental_data.borrow_mut().take().unwrap_or_default();
+            let data = decode(reply.type_, data);
+            self.finish(xcb_connection, event.window, event.time, Some(data));
+        } else if let Some(data) = self.incremental_data.borrow_mut().as_mut() {
+            data.extend_from_slice(&reply.value);
+            self.set_state(ReadState::Incremental);
+        }
+    }
+
+    /// Give up on the format being read if the selection's owner hasn't answered in time. Its
+    /// event is then sent without any data.
+    pub fn check_timeout(&self, xcb_connection: &XcbConnection, window: XWindow) {
+        if matches!(self.deadline.get(), Some(deadline) if Instant::now() >= deadline) {
+            let time = xcb_connection.last_user_time.get();
+            self.finish(xcb_connection, window, time, None);
+        }
+    }
+
+    /// Whether there are events to send to the handler.
+    pub fn has_events(&self) -> bool {
+        !self.events.borrow().is_empty()
+    }
+
+    /// Take the events for the formats that have been read since the last call.
+    pub fn take_events(&self) -> Vec<Event> {
+        std::mem::take(&mut *self.events.borrow_mut())
+    }
+
+    /// Start reading the first requested format.
+    fn read_next(&self, xcb_connection: &XcbConnection, window: XWindow, time: Timestamp) {
+        let format = match self.requests.borrow().front() {
+            Some(format) => format.clone(),
+            None => return,
+        };
+
+        let target = match format_target(xcb_connection, &format) {
+            Some(target) => target,
+            None => return self.finish(xcb_connection, window, time, None),
+        };
+
+        // The selection can't be converted through the server when we own it ourselves, since the
+        // data may be too large for a single request
+        if let Some((data_type, data)) = xcb_connection.clipboard.data(xcb_connection, target) {
+            let data = decode(data_type, data.to_vec());
+            return self.finish(xcb_connection, window, time, Some(data));
+        }
+
+        if convert(xcb_connection, window, target, time) {
+            self.set_state(ReadState::Converting { target });
+        } else {
+            self.finish(xcb_connection, window, time, None);
+        }
+    }
+
+    /// Update the read state, and restart the timeout while waiting for the owner.
+    fn set_state(&self, state: ReadState) {
+        let deadline = match state {
+            ReadState::Idle => None,
+            ReadState::Converting { .. } | ReadState::Incremental => {
+                Some(Instant::now() + READ_TIMEOUT)
+            }
+        };
+
+        self.state.set(state);
+        self.deadline.set(deadline);
+    }
+
+    /// Queue the event for the format being read, and move on to the next one.
+    fn finish(
+        &self, xcb_connection: &XcbConnection, window: XWindow, time: Timestamp,
+        data: Option<Vec<u8>>,
+    ) {
+        self.set_state(ReadState::Idle);
+        self.incremental_data.borrow_mut().take();
+
+        let format = self.requests.borrow_mut().pop_front();
+        if let Some(format) = format {
+            self.events.borrow_mut().push(format.into_event(data));
+        }
+
+        self.read_next(xcb_connection, window, time);
+    }
+}
+
+/// The target for a clipboard format. Text is requested as `UTF8_STRING`, and custom formats use
+/// the atom with the format's name.
+fn format_target(xcb_connection: &XcbConnection, format: &ClipboardFormat) -> Option<Atom> {
+    match format {
+        ClipboardFormat::Text => Some(xcb_connection.atoms.UTF8_STRING),
+        ClipboardFormat::Custom(name) => xcb_connection.atom(name).ok(),
+    }
+}
+
+/// Ask the clipboard's owner to store its contents in the `CLIPBOARD` property on `window`.
+fn convert(xcb_connection: &XcbConnection, window: XWindow, target: Atom, time: Timestamp) -> bool {
+    let atoms = &xcb_connection.atoms;
+    let conn = &xcb_connection.conn;
+    let converted =
+        conn.convert_selection(window, atoms.CLIPBOARD, target, atoms.CLIPBOARD, time).is_ok();
+    let _ = conn.flush();
+
+    converted
+}
+
+/// Read and delete a property containing (part of) the clipboard's contents.
+fn read_property(
+    xcb_connection: &XcbConnection, window: XWindow, property: Atom,
+) -> Option<GetPropertyReply> {
+    xcb_connection
+        .conn
+        .get_property(true, window, property, AtomEnum::ANY, 0, u32::MAX / 4)
+        .ok()?
+        .reply()
+        .ok()
+}
+
+/// Convert `STRING` data to UTF-8. `STRING` is Latin-1, which maps directly onto the first 256
+/// code points. Other types are left as they are.
+fn decode(data_type: Atom, data: Vec<u8>) -> Vec<u8> {
+    if data_type == u32::from(AtomEnum::STRING) {
+        data.iter().map(|&byte| char::from(byte)).collect::<String>().into_bytes()
+    } else {
+        data
+    }
+}
+
+/// The size of the chunks for `INCR` transfers, leaving room for the request's header.
+fn chunk_size(conn: &impl RequestConnection) -> usize {
+    INCR_CHUNK_SIZE.min(conn.maximum_request_bytes().saturating_sub(100))
+}
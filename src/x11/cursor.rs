@@ -3,10 +3,52 @@ use std::error::Error;
 use x11rb::connection::Connection;
 use x11rb::cursor::Handle as CursorHandle;
 use x11rb::protocol::xproto::{ConnectionExt as _, Cursor};
+use x11rb::resource_manager::Database;
 use x11rb::xcb_ffi::XCBConnection;
 
 use crate::MouseCursor;
 
+use super::xsettings::XSettings;
+
+/// x11rb picks the cursor theme and size from the `XCURSOR_THEME` and `XCURSOR_SIZE` environment
+/// variables, and otherwise from the X resources. Desktop environments like GNOME don't set
+/// either, and instead publish these settings through XSETTINGS, already scaled for HiDPI
+/// displays. This creates a resource database containing only the cursor related settings, with
+/// the XSETTINGS values filling in for anything that isn't set as an X resource.
+pub(super) fn cursor_resources(resources: &Database, xsettings: &XSettings) -> Database {
+    let mut data = String::new();
+
+    let theme = resources
+        .get_string("Xcursor.theme", "")
+        .or_else(|| xsettings.get_string("Gtk/CursorThemeName"));
+    if let Some(theme) = theme {
+        data.push_str(&format!("Xcursor.theme: {}\n", theme));
+    }
+
+    let size = match resources.get_value::<u32>("Xcursor.size", "") {
+        Ok(Some(size)) if size > 0 => Some(size),
+        _ => xsettings
+            .get_int("Gtk/CursorThemeSize")
+            .filter(|&size| size > 0)
+            .map(|size| size as u32),
+    };
+    if let Some(size) = size {
+        data.push_str(&format!("Xcursor.size: {}\n", size));
+    }
+
+    // This is used to derive the cursor size when neither of the above is set. The XSETTINGS
+    // value is stored as 1024 * DPI.
+    let dpi = match resources.get_value::<u32>("Xft.dpi", "") {
+        Ok(Some(dpi)) if dpi > 0 => Some(dpi),
+        _ => xsettings.get_int("Xft/DPI").filter(|&dpi| dpi > 0).map(|dpi| dpi as u32 / 1024),
+    };
+    if let Some(dpi) = dpi {
+        data.push_str(&format!("Xft.dpi: {}\n", dpi));
+    }
+
+    Database::new_from_data(data.as_bytes())
+}
+
 fn create_empty_cursor(conn: &XCBConnection, screen: usize) -> Result<Cursor, Box<dyn Error>> {
     let cursor_id = conn.generate_id()?;
     let pixmap_id = conn.generate_id()?;
@@ -42,6 +84,7 @@ fn load_first_existing_cursor(
     Ok(None)
 }
 
+#[allow(deprecated)]
 pub(super) fn get_xcursor(
     conn: &XCBConnection, screen: usize, cursor_handle: &CursorHandle, cursor: MouseCursor,
 ) -> Result<Cursor, Box<dyn Error>> {
@@ -51,28 +94,33 @@ pub(super) fn get_xcursor(
     let cursor = match cursor {
         MouseCursor::Default => None, // catch this in the fallback case below
 
-        MouseCursor::Hand => loadn(&["hand2", "hand1"])?,
-        MouseCursor::HandGrabbing => loadn(&["closedhand", "grabbing"])?,
-        MouseCursor::Help => load("question_arrow")?,
+        MouseCursor::Hand => loadn(&["pointer", "hand2", "hand1"])?,
+        MouseCursor::Grab => loadn(&["grab", "openhand", "hand1"])?,
+        MouseCursor::HandGrabbing => loadn(&["grabbing", "closedhand"])?,
+        MouseCursor::Help => loadn(&["help", "question_arrow"])?,
+        MouseCursor::ContextMenu => load("context-menu")?,
 
         MouseCursor::Hidden => Some(create_empty_cursor(conn, screen)?),
 
         MouseCursor::Text => loadn(&["text", "xterm"])?,
         MouseCursor::VerticalText => load("vertical-text")?,
 
-        MouseCursor::Working => load("watch")?,
-        MouseCursor::PtrWorking => load("left_ptr_watch")?,
+        MouseCursor::Wait | MouseCursor::Working => loadn(&["wait", "watch"])?,
+        MouseCursor::Progress | MouseCursor::PtrWorking => {
+            loadn(&["progress", "left_ptr_watch", "half-busy"])?
+        }
 
         MouseCursor::NotAllowed => load("crossed_circle")?,
         MouseCursor::PtrNotAllowed => loadn(&["no-drop", "crossed_circle"])?,
+        MouseCursor::NoDrop => loadn(&["no-drop", "dnd-no-drop", "crossed_circle"])?,
 
-        MouseCursor::ZoomIn => load("zoom-in")?,
-        MouseCursor::ZoomOut => load("zoom-out")?,
+        MouseCursor::ZoomIn => loadn(&["zoom-in", "zoom_in"])?,
+        MouseCursor::ZoomOut => loadn(&["zoom-out", "zoom_out"])?,
 
         MouseCursor::Alias => load("link")?,
         MouseCursor::Copy => load("copy")?,
         MouseCursor::Move => load("move")?,
-        MouseCursor::AllScroll => load("all-scroll")?,
+        MouseCursor::AllScroll => loadn(&["all-scroll", "fleur"])?,
         MouseCursor::Cell => load("plus")?,
         MouseCursor::Crosshair => load("crosshair")?,
 
@@ -84,12 +132,14 @@ pub(super) fn get_xcursor(
         MouseCursor::SeResize => load("bottom_right_corner")?,
         MouseCursor::SwResize => load("bottom_left_corner")?,
         MouseCursor::WResize => load("left_side")?,
-        MouseCursor::EwResize => load("h_double_arrow")?,
-        MouseCursor::NsResize => load("v_double_arrow")?,
-        MouseCursor::NwseResize => loadn(&["bd_double_arrow", "size_bdiag"])?,
-        MouseCursor::NeswResize => loadn(&["fd_double_arrow", "size_fdiag"])?,
-        MouseCursor::ColResize => loadn(&["split_h", "h_double_arrow"])?,
-        MouseCursor::RowResize => loadn(&["split_v", "v_double_arrow"])?,
+        MouseCursor::EwResize => loadn(&["ew-resize", "h_double_arrow"])?,
+        MouseCursor::NsResize => loadn(&["ns-resize", "v_double_arrow"])?,
+        // Note that `size_fdiag` and `size_bdiag` are named after the direction of the diagonal
+        // line (`\` and `/`) rather than the direction of the arrow
+        MouseCursor::NwseResize => loadn(&["nwse-resize", "bd_double_arrow", "size_fdiag"])?,
+        MouseCursor::NeswResize => loadn(&["nesw-resize", "fd_double_arrow", "size_bdiag"])?,
+        MouseCursor::ColResize => loadn(&["col-resize", "split_h", "h_double_arrow"])?,
+        MouseCursor::RowResize => loadn(&["row-resize", "split_v", "v_double_arrow"])?,
     };
 
     if let Some(cursor) = cursor {
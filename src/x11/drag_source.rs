@@ -0,0 +1,400 @@
+use std::cell::{Cell, RefCell};
+use std::os::unix::ffi::OsStrExt;
+use std::time::{Duration, Instant};
+
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{
+    Atom, AtomEnum, ClientMessageEvent, ConnectionExt as _, EventMask, PropMode,
+    SelectionNotifyEvent, SelectionRequestEvent, Timestamp, Window as XWindow,
+    SELECTION_NOTIFY_EVENT,
+};
+use x11rb::wrapper::ConnectionExt as _;
+
+use crate::{DragData, DropEffect};
+
+use super::drop_target::XDND_VERSION;
+use super::event_loop::BUTTON_MASK;
+use super::XcbConnection;
+
+/// How long to wait for the target's `XdndFinished` after the drop before giving up on it.
+const FINISH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Drags data out of a window through XDND, see [`crate::Window::start_drag()`]. The drag uses the
+/// implicit pointer grab of the button press it was started from, so the window keeps receiving
+/// the pointer's motion while it's outside of the window. We look for an XDND aware window under
+/// the pointer, send it the drag's position, and serve the `XdndSelection` once it's dropped.
+#[derive(Default)]
+pub(super) struct DragSource {
+    drag: RefCell<Option<Drag>>,
+    /// The result of the last drag, which is sent to the handler at the end of the event batch.
+    finished: Cell<Option<Option<DropEffect>>>,
+}
+
+struct Drag {
+    data: DragData,
+    /// The target for the data, `text/uri-list` for files.
+    format: Atom,
+    target: Option<Target>,
+    /// Set once the button has been released over a target that accepted the drag, after which
+    /// we wait for the target to finish.
+    dropped: bool,
+    /// When to stop waiting for the target to finish after the drop.
+    deadline: Option<Instant>,
+}
+
+struct Target {
+    window: XWindow,
+    /// The window the target's messages are sent to, which is the target itself unless it set
+    /// `XdndProxy`.
+    proxy: XWindow,
+    version: u32,
+    /// The action from the target's last `XdndStatus`, or `None` if it didn't accept the drag.
+    action: Option<Atom>,
+    /// Whether we're waiting for an `XdndStatus` before the next position can be sent.
+    awaiting_status: bool,
+    /// The last position that couldn't be sent yet, along with its time.
+    pending_position: Option<(u32, Timestamp)>,
+}
+
+impl DragSource {
+    pub fn is_active(&self) -> bool {
+        self.drag.borrow().is_some()
+    }
+
+    /// Start a drag by taking ownership of the `XdndSelection`. The drag finishes right away if
+    /// no button is held, since the pointer isn't grabbed then.
+    pub fn start(
+        &self, xcb_connection: &XcbConnection, window: XWindow, time: Timestamp, data: DragData,
+    ) {
+        let buttons_held = xcb_connection
+            .conn
+            .query_pointer(window)
+            .ok()
+            .and_then(|cookie| cookie.reply().ok())
+            .map_or(false, |pointer| u16::from(pointer.mask) & BUTTON_MASK != 0);
+        if self.is_active() || !buttons_held {
+            self.finished.set(Some(None));
+            return;
+        }
+
+        let atoms = &xcb_connection.atoms;
+        let format = match &data {
+            DragData::Files(_) => Some(atoms.TEXT_URI_LIST),
+            DragData::Custom { format, .. } => xcb_connection.atom(format).ok(),
+        };
+        let format = match format {
+            Some(format) => format,
+            None => {
+                self.finished.set(Some(None));
+                return;
+            }
+        };
+
+        let conn = &xcb_connection.conn;
+        let _ = conn.set_selection_owner(window, atoms.XdndSelection, time);
+        let _ = conn.flush();
+
+        *self.drag.borrow_mut() =
+            Some(Drag { data, format, target: None, dropped: false, deadline: None });
+    }
+
+    /// Handle a pointer movement during the drag, in root window coordinates.
+    pub fn motion(
+        &self, xcb_connection: &XcbConnection, window: XWindow, root_x: i16, root_y: i16,
+        time: Timestamp,
+    ) {
+        let mut drag = self.drag.borrow_mut();
+        let drag = match drag.as_mut() {
+            Some(drag) if !drag.dropped => drag,
+            _ => return,
+        };
+
+        let atoms = &xcb_connection.atoms;
+        let found = find_target(xcb_connection, root_x, root_y);
+        if drag.target.as_ref().map(|target| target.window) != found.map(|(window, _)| window) {
+            if let Some(target) = drag.target.take() {
+                send_message(xcb_connection, &target, atoms.XdndLeave, [window, 0, 0, 0, 0]);
+            }
+
+            if let Some((target_window, version)) = found {
+                let target = Target {
+                    window: target_window,
+                    proxy: find_proxy(xcb_connection, target_window),
+                    version: version.min(XDND_VERSION),
+                    action: None,
+                    awaiting_status: false,
+                    pending_position: None,
+                };
+                send_message(
+                    xcb_connection,
+                    &target,
+                    atoms.XdndEnter,
+                    [window, target.version << 24, drag.format, 0, 0],
+                );
+                drag.target = Some(target);
+            }
+        }
+
+        if let Some(target) = &mut drag.target {
+            let position = ((root_x as u16 as u32) << 16) | root_y as u16 as u32;
+            if target.awaiting_status {
+                target.pending_position = Some((position, time));
+            } else {
+                send_position(xcb_connection, window, target, position, time);
+            }
+        }
+    }
+
+    /// Handle `XdndStatus` and `XdndFinished`.
+    pub fn handle_client_message(
+        &self, xcb_connection: &XcbConnection, window: XWindow, event: &ClientMessageEvent,
+    ) {
+        let atoms = &xcb_connection.atoms;
+        let data = event.data.as_data32();
+
+        let mut drag = self.drag.borrow_mut();
+        let current = match drag.as_mut() {
+            Some(drag) => drag,
+            None => return,
+        };
+        let target = match &mut current.target {
+            Some(target) if target.window == data[0] => target,
+            _ => return,
+        };
+
+        if event.type_ == atoms.XdndStatus {
+            target.awaiting_status = false;
+            target.action = if data[1] & 1 != 0 {
+                Some(if data[4] == x11rb::NONE { atoms.XdndActionCopy } else { data[4] })
+            } else {
+                None
+            };
+
+            if let Some((position, time)) = target.pending_position.take() {
+                if !current.dropped {
+                    send_position(xcb_connection, window, target, position, time);
+                }
+            }
+        } else if event.type_ == atoms.XdndFinished && current.dropped {
+            // Older targets don't report whether the drop succeeded
+            let action = if target.version >= 5 {
+                (data[1] & 1 != 0).then(|| data[2])
+            } else {
+                target.action
+            };
+
+            let effect = action.map(|action| drop_effect(xcb_connection, action));
+            *drag = None;
+            self.finished.set(Some(effect));
+        }
+    }
+
+    /// Handle the release of the button that started the drag. The data is dropped onto the
+    /// current target if it accepted the drag, otherwise the drag is cancelled.
+    pub fn release(&self, xcb_connection: &XcbConnection, window: XWindow, time: Timestamp) {
+        let mut drag = self.drag.borrow_mut();
+        let current = match drag.as_mut() {
+            Some(drag) if !drag.dropped => drag,
+            _ => return,
+        };
+
+        let atoms = &xcb_connection.atoms;
+        match &current.target {
+            Some(target) if target.action.is_some() => {
+                send_message(xcb_connection, target, atoms.XdndDrop, [window, 0, time, 0, 0]);
+                current.dropped = true;
+                current.deadline = Some(Instant::now() + FINISH_TIMEOUT);
+            }
+            _ => {
+                drop(drag);
+                self.cancel(xcb_connection, window);
+            }
+        }
+    }
+
+    /// Cancel the drag, for instance when Escape is pressed.
+    pub fn cancel(&self, xcb_connection: &XcbConnection, window: XWindow) {
+        let drag = match self.drag.borrow_mut().take() {
+            Some(drag) => drag,
+            None => return,
+        };
+
+        if let Some(target) = drag.target {
+            let leave = xcb_connection.atoms.XdndLeave;
+            send_message(xcb_connection, &target, leave, [window, 0, 0, 0, 0]);
+        }
+        self.finished.set(Some(None));
+    }
+
+    /// End the drag without an action if the target hasn't finished in time after the drop.
+    pub fn check_timeout(&self) {
+        let timed_out = matches!(
+            self.drag.borrow().as_ref(),
+            Some(Drag { deadline: Some(deadline), .. }) if Instant::now() >= *deadline
+        );
+        if timed_out {
+            *self.drag.borrow_mut() = None;
+            self.finished.set(Some(None));
+        }
+    }
+
+    /// Send the dragged data to the drop target.
+    pub fn handle_selection_request(
+        &self, xcb_connection: &XcbConnection, request: &SelectionRequestEvent,
+    ) {
+        let atoms = &xcb_connection.atoms;
+        let conn = &xcb_connection.conn;
+        let property =
+            if request.property == x11rb::NONE { request.target } else { request.property };
+
+        let drag = self.drag.borrow();
+        let converted = match drag.as_ref() {
+            Some(drag) if request.selection == atoms.XdndSelection => {
+                if request.target == atoms.TARGETS {
+                    let targets = [atoms.TARGETS, drag.format];
+                    conn.change_property32(
+                        PropMode::REPLACE,
+                        request.requestor,
+                        property,
+                        AtomEnum::ATOM,
+                        &targets,
+                    )
+                    .is_ok()
+                } else if request.target == drag.format {
+                    let data = match &drag.data {
+                        DragData::Files(paths) => uri_list(paths),
+                        DragData::Custom { data, .. } => data.clone(),
+                    };
+                    conn.change_property8(
+                        PropMode::REPLACE,
+                        request.requestor,
+                        property,
+                        drag.format,
+                        &data,
+                    )
+                    .is_ok()
+                } else {
+                    false
+                }
+            }
+            _ => false,
+        };
+
+        let notify = SelectionNotifyEvent {
+            response_type: SELECTION_NOTIFY_EVENT,
+            sequence: 0,
+            time: request.time,
+            requestor: request.requestor,
+            selection: request.selection,
+            target: request.target,
+            property: if converted { property } else { x11rb::NONE },
+        };
+        let _ = conn.send_event(false, request.requestor, EventMask::NO_EVENT, notify);
+        let _ = conn.flush();
+    }
+
+    /// Whether a drag has finished since the last call to
+    /// [`take_finished()`][Self::take_finished()].
+    pub fn has_finished(&self) -> bool {
+        self.finished.get().is_some()
+    }
+
+    /// The result of the drag that has finished, if any.
+    pub fn take_finished(&self) -> Option<Option<DropEffect>> {
+        self.finished.take()
+    }
+}
+
+/// Find the XDND aware window under the pointer, along with its protocol version. This is usually
+/// the top level window, but an embedded window may be a drop target of its own.
+fn find_target(xcb_connection: &XcbConnection, root_x: i16, root_y: i16) -> Option<(XWindow, u32)> {
+    let conn = &xcb_connection.conn;
+    let root = xcb_connection.screen().root;
+
+    let mut window = root;
+    loop {
+        let child =
+            conn.translate_coordinates(root, window, root_x, root_y).ok()?.reply().ok()?.child;
+        if child == x11rb::NONE {
+            return None;
+        }
+
+        let version = xcb_connection
+            .get_property32(child, xcb_connection.atoms.XdndAware, AtomEnum::ATOM)
+            .and_then(|value| value.first().copied());
+        if let Some(version) = version {
+            return Some((child, version));
+        }
+
+        window = child;
+    }
+}
+
+/// The window that receives the messages for a drop target. A target may set `XdndProxy` to have
+/// them sent elsewhere, which only counts if the proxy window points to itself as well, since the
+/// property may otherwise be left over from a proxy that no longer exists.
+fn find_proxy(xcb_connection: &XcbConnection, target: XWindow) -> XWindow {
+    let proxy_of = |window| {
+        xcb_connection
+            .get_property32(window, xcb_connection.atoms.XdndProxy, AtomEnum::WINDOW)
+            .and_then(|value| value.first().copied())
+    };
+
+    match proxy_of(target) {
+        Some(proxy) if proxy_of(proxy) == Some(proxy) => proxy,
+        _ => target,
+    }
+}
+
+fn send_position(
+    xcb_connection: &XcbConnection, window: XWindow, target: &mut Target, position: u32,
+    time: Timestamp,
+) {
+    let atoms = &xcb_connection.atoms;
+    send_message(
+        xcb_connection,
+        target,
+        atoms.XdndPosition,
+        [window, 0, position, time, atoms.XdndActionCopy],
+    );
+    target.awaiting_status = true;
+}
+
+/// Send a message to the target, or to its proxy. The message's window is the target either way.
+fn send_message(
+    xcb_connection: &XcbConnection, target: &Target, message_type: Atom, data: [u32; 5],
+) {
+    let conn = &xcb_connection.conn;
+    let event = ClientMessageEvent::new(32, target.window, message_type, data);
+    let _ = conn.send_event(false, target.proxy, EventMask::NO_EVENT, event);
+    let _ = conn.flush();
+}
+
+fn drop_effect(xcb_connection: &XcbConnection, action: Atom) -> DropEffect {
+    let atoms = &xcb_connection.atoms;
+    if action == atoms.XdndActionMove {
+        DropEffect::Move
+    } else if action == atoms.XdndActionLink {
+        DropEffect::Link
+    } else {
+        DropEffect::Copy
+    }
+}
+
+/// Format paths as a `text/uri-list` with `file://` URIs.
+fn uri_list(paths: &[std::path::PathBuf]) -> Vec<u8> {
+    let mut list = Vec::new();
+    for path in paths {
+        list.extend_from_slice(b"file://");
+        for &byte in path.as_os_str().as_bytes() {
+            if byte.is_ascii_alphanumeric() || b"/-_.~".contains(&byte) {
+                list.push(byte);
+            } else {
+                list.extend_from_slice(format!("%{:02X}", byte).as_bytes());
+            }
+        }
+        list.extend_from_slice(b"\r\n");
+    }
+
+    list
+}
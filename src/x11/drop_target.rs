@@ -0,0 +1,364 @@
+use std::cell::RefCell;
+use std::ffi::OsString;
+use std::os::unix::ffi::OsStringExt;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use x11rb::connection::Connection;
+use x11rb::errors::ReplyError;
+use x11rb::protocol::xproto::{
+    Atom, AtomEnum, ClientMessageEvent, ConnectionExt as _, EventMask, PropMode,
+    SelectionNotifyEvent, Timestamp, Window as XWindow,
+};
+use x11rb::wrapper::ConnectionExt as _;
+
+use keyboard_types::Modifiers;
+
+use crate::{DropData, DropEffect, EventStatus, MouseEvent, Point};
+
+use super::errors::CheckRequest;
+use super::XcbConnection;
+
+/// The version of the XDND protocol we implement, see
+/// <https://www.freedesktop.org/wiki/Specifications/XDND/>.
+pub(super) const XDND_VERSION: u32 = 5;
+
+/// Receives drags from other applications through the XDND protocol. The source sends us client
+/// messages while something is dragged over the window. The data is requested once, when the
+/// first position arrives, and `XdndStatus` isn't sent until it has been converted, so the handler
+/// receives [`MouseEvent::DragEntered`] with the data just like on the other platforms. The source
+/// waits for a status before sending the next position.
+pub(super) struct DropTarget {
+    /// The formats from [`WindowOpenOptions::drop_formats`][crate::WindowOpenOptions::drop_formats]
+    /// along with their atoms.
+    formats: Vec<(String, Atom)>,
+    drag: RefCell<Option<Drag>>,
+}
+
+struct Drag {
+    source: XWindow,
+    /// The protocol version used by the source, which may be older than ours.
+    version: u32,
+    /// The format the data is requested in, or `None` if the source doesn't offer any of the
+    /// formats we accept.
+    target: Option<(Atom, Option<String>)>,
+    state: DragState,
+    position: Point,
+    modifiers: Modifiers,
+    /// Shared with the events sent to the handler, so they don't copy the data.
+    data: Arc<DropData>,
+}
+
+#[derive(Clone, Copy)]
+enum DragState {
+    /// Waiting for the first position.
+    Entered,
+    /// Waiting for the data to be converted. The source may give up on waiting for our status and
+    /// drop anyway, in which case the drop is handled once the data has arrived.
+    Converting { dropped: bool },
+    /// The handler has received [`MouseEvent::DragEntered`].
+    Active,
+}
+
+impl DropTarget {
+    /// Announce that `window` accepts drops, and intern the custom formats' atoms.
+    pub fn new(
+        xcb_connection: &XcbConnection, window: XWindow, drop_formats: &[String],
+    ) -> Result<Self, ReplyError> {
+        let conn = &xcb_connection.conn;
+        conn.change_property32(
+            PropMode::REPLACE,
+            window,
+            xcb_connection.atoms.XdndAware,
+            AtomEnum::ATOM,
+            &[XDND_VERSION],
+        )
+        .check_reported()?;
+
+        let mut formats = Vec::with_capacity(drop_formats.len());
+        for format in drop_formats {
+            let atom = xcb_connection.atom(format)?;
+            formats.push((format.clone(), atom));
+        }
+
+        Ok(Self { formats, drag: RefCell::new(None) })
+    }
+
+    /// Handle `XdndEnter`. This replaces any drag the source didn't end properly.
+    pub fn enter(&self, xcb_connection: &XcbConnection, event: &ClientMessageEvent) {
+        let data = event.data.as_data32();
+        let source = data[0];
+        let version = (data[1] >> 24).min(XDND_VERSION);
+
+        // Sources list up to three types in the message itself, and the rest in a property
+        let types = if data[1] & 1 != 0 {
+            xcb_connection
+                .get_property32(source, xcb_connection.atoms.XdndTypeList, AtomEnum::ATOM)
+                .unwrap_or_default()
+        } else {
+            data[2..5].iter().copied().filter(|&atom| atom != x11rb::NONE).collect::<Vec<_>>()
+        };
+
+        let target = self
+            .formats
+            .iter()
+            .find(|(_, atom)| types.contains(atom))
+            .map(|(format, atom)| (*atom, Some(format.clone())))
+            .or_else(|| {
+                let uri_list = xcb_connection.atoms.TEXT_URI_LIST;
+                types.contains(&uri_list).then(|| (uri_list, None))
+            });
+
+        *self.drag.borrow_mut() = Some(Drag {
+            source,
+            version,
+            target,
+            state: DragState::Entered,
+            position: Point::new(0.0, 0.0),
+            modifiers: Modifiers::empty(),
+            data: DropData::none(),
+        });
+    }
+
+    /// Handle `XdndPosition`, with the position already translated to the window's logical
+    /// coordinates. Returns the event to send to the handler, after which
+    /// [`respond()`][Self::respond()] needs to be called with the handler's status. Returns
+    /// `None` while the data is still being converted.
+    pub fn position(
+        &self, xcb_connection: &XcbConnection, window: XWindow, event: &ClientMessageEvent,
+        position: Point, modifiers: Modifiers,
+    ) -> Option<MouseEvent> {
+        let data = event.data.as_data32();
+        let mut drag = self.drag.borrow_mut();
+        let drag = drag.as_mut().filter(|drag| drag.source == data[0])?;
+        drag.position = position;
+        drag.modifiers = modifiers;
+
+        match drag.state {
+            DragState::Entered => {
+                let time = data[3];
+                if let Some((target, _)) = drag.target {
+                    if convert(xcb_connection, window, target, time) {
+                        drag.state = DragState::Converting { dropped: false };
+                        return None;
+                    }
+                }
+
+                drag.state = DragState::Active;
+                Some(MouseEvent::DragEntered { position, modifiers, data: drag.data.clone() })
+            }
+            DragState::Converting { .. } => None,
+            DragState::Active => {
+                Some(MouseEvent::DragMoved { position, modifiers, data: drag.data.clone() })
+            }
+        }
+    }
+
+    /// Handle `XdndLeave`. Returns whether the handler needs to be sent
+    /// [`MouseEvent::DragLeft`].
+    pub fn leave(&self, event: &ClientMessageEvent) -> bool {
+        let mut drag = self.drag.borrow_mut();
+        match drag.as_ref() {
+            Some(current) if current.source == event.data.as_data32()[0] => {
+                let entered = matches!(current.state, DragState::Active);
+                *drag = None;
+                entered
+            }
+            _ => false,
+        }
+    }
+
+    /// Handle `XdndDrop`. Returns the event to send to the handler, after which
+    /// [`respond()`][Self::respond()] needs to be called with the handler's status.
+    pub fn handle_drop(
+        &self, xcb_connection: &XcbConnection, window: XWindow, event: &ClientMessageEvent,
+    ) -> Option<MouseEvent> {
+        let mut drag = self.drag.borrow_mut();
+        let current = drag.as_mut().filter(|drag| drag.source == event.data.as_data32()[0])?;
+
+        match current.state {
+            DragState::Active => Some(MouseEvent::DragDropped {
+                position: current.position,
+                modifiers: current.modifiers,
+                data: current.data.clone(),
+            }),
+            DragState::Converting { .. } => {
+                current.state = DragState::Converting { dropped: true };
+                None
+            }
+            // The source never sent a position, so the handler hasn't seen this drag
+            DragState::Entered => {
+                let drag = drag.take().unwrap();
+                finish(xcb_connection, window, &drag, None);
+                None
+            }
+        }
+    }
+
+    /// Handle the converted data. Returns the events to send to the handler, each of which needs
+    /// to be followed by a call to [`respond()`][Self::respond()].
+    pub fn handle_selection_notify(
+        &self, xcb_connection: &XcbConnection, event: &SelectionNotifyEvent,
+    ) -> Vec<MouseEvent> {
+        let mut drag = self.drag.borrow_mut();
+        let drag = match drag.as_mut() {
+            Some(drag) if event.selection == xcb_connection.atoms.XdndSelection => drag,
+            _ => return Vec::new(),
+        };
+        let dropped = match drag.state {
+            DragState::Converting { dropped } => dropped,
+            _ => return Vec::new(),
+        };
+
+        if event.property != x11rb::NONE {
+            let reply = xcb_connection
+                .conn
+                .get_property(true, event.requestor, event.property, AtomEnum::ANY, 0, u32::MAX / 4)
+                .ok()
+                .and_then(|cookie| cookie.reply().ok());
+
+            // Large transfers through `INCR` aren't supported, since they would hold up the drag
+            if let Some(reply) = reply.filter(|reply| reply.type_ != xcb_connection.atoms.INCR) {
+                drag.data = Arc::new(match &drag.target {
+                    Some((_, Some(format))) => {
+                        DropData::Custom { format: format.clone(), data: reply.value }
+                    }
+                    _ => parse_uri_list(&reply.value),
+                });
+            }
+        }
+
+        drag.state = DragState::Active;
+
+        let (position, modifiers) = (drag.position, drag.modifiers);
+        let mut events =
+            vec![MouseEvent::DragEntered { position, modifiers, data: drag.data.clone() }];
+        if dropped {
+            events.push(MouseEvent::DragDropped { position, modifiers, data: drag.data.clone() });
+        }
+
+        events
+    }
+
+    /// Tell the source whether the handler accepted the drag after it has been sent a
+    /// [`MouseEvent::DragEntered`], [`MouseEvent::DragMoved`], or [`MouseEvent::DragDropped`]
+    /// event. The drag is over after a drop.
+    pub fn respond(
+        &self, xcb_connection: &XcbConnection, window: XWindow, dropped: bool, status: EventStatus,
+    ) {
+        let effect = match status {
+            EventStatus::AcceptDrop(effect) => Some(effect),
+            _ => None,
+        };
+
+        if dropped {
+            if let Some(drag) = self.drag.borrow_mut().take() {
+                finish(xcb_connection, window, &drag, effect);
+            }
+            return;
+        }
+
+        let drag = self.drag.borrow();
+        let drag = match drag.as_ref() {
+            Some(drag) => drag,
+            None => return,
+        };
+
+        // The empty rectangle and the second flag ask the source to keep sending positions
+        let flags = if effect.is_some() { 0b11 } else { 0b10 };
+        let action = action_atom(xcb_connection, effect);
+        send_message(
+            xcb_connection,
+            drag.source,
+            xcb_connection.atoms.XdndStatus,
+            [window, flags, 0, 0, action],
+        );
+    }
+}
+
+/// Ask the source to store the dragged data in the `XdndSelection` property on `window`.
+fn convert(xcb_connection: &XcbConnection, window: XWindow, target: Atom, time: Timestamp) -> bool {
+    let atoms = &xcb_connection.atoms;
+    let conn = &xcb_connection.conn;
+    let converted = conn
+        .convert_selection(window, atoms.XdndSelection, target, atoms.XdndSelection, time)
+        .is_ok();
+    let _ = conn.flush();
+
+    converted
+}
+
+/// Send `XdndFinished` to end the drag. Older sources don't expect the result.
+fn finish(
+    xcb_connection: &XcbConnection, window: XWindow, drag: &Drag, effect: Option<DropEffect>,
+) {
+    let data = if drag.version >= 5 {
+        [window, effect.is_some() as u32, action_atom(xcb_connection, effect), 0, 0]
+    } else {
+        [window, 0, 0, 0, 0]
+    };
+
+    send_message(xcb_connection, drag.source, xcb_connection.atoms.XdndFinished, data);
+}
+
+/// The XDND action for an accepted drop. XDND doesn't have an equivalent of
+/// [`DropEffect::Scroll`], so that uses the private action.
+fn action_atom(xcb_connection: &XcbConnection, effect: Option<DropEffect>) -> Atom {
+    let atoms = &xcb_connection.atoms;
+    match effect {
+        Some(DropEffect::Copy) => atoms.XdndActionCopy,
+        Some(DropEffect::Move) => atoms.XdndActionMove,
+        Some(DropEffect::Link) => atoms.XdndActionLink,
+        Some(DropEffect::Scroll) => atoms.XdndActionPrivate,
+        None => x11rb::NONE,
+    }
+}
+
+fn send_message(
+    xcb_connection: &XcbConnection, destination: XWindow, message_type: Atom, data: [u32; 5],
+) {
+    let conn = &xcb_connection.conn;
+    let event = ClientMessageEvent::new(32, destination, message_type, data);
+    let _ = conn.send_event(false, destination, EventMask::NO_EVENT, event);
+    let _ = conn.flush();
+}
+
+/// Parse a `text/uri-list`, keeping only the local files.
+fn parse_uri_list(data: &[u8]) -> DropData {
+    let paths: Vec<PathBuf> = data
+        .split(|&byte| byte == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+        .filter(|line| !line.is_empty() && !line.starts_with(b"#"))
+        .filter_map(|uri| uri.strip_prefix(b"file://"))
+        // The URI may contain a host name before the path
+        .filter_map(|uri| uri.iter().position(|&byte| byte == b'/').map(|start| &uri[start..]))
+        .map(|path| PathBuf::from(OsString::from_vec(percent_decode(path))))
+        .collect();
+
+    if paths.is_empty() {
+        DropData::None
+    } else {
+        DropData::Files(paths)
+    }
+}
+
+fn percent_decode(data: &[u8]) -> Vec<u8> {
+    let hex = |byte: u8| (byte as char).to_digit(16).map(|digit| digit as u8);
+
+    let mut decoded = Vec::with_capacity(data.len());
+    let mut i = 0;
+    while i < data.len() {
+        if data[i] == b'%' && i + 2 < data.len() {
+            if let (Some(high), Some(low)) = (hex(data[i + 1]), hex(data[i + 2])) {
+                decoded.push(high << 4 | low);
+                i += 3;
+                continue;
+            }
+        }
+
+        decoded.push(data[i]);
+        i += 1;
+    }
+
+    decoded
+}
@@ -0,0 +1,31 @@
+use x11rb::connection::RequestConnection;
+use x11rb::cookie::VoidCookie;
+use x11rb::errors::{ConnectionError, ReplyError};
+use x11rb::x11_utils::X11Error;
+
+use crate::platform_error::{report_platform_error, PlatformError};
+
+/// Pass an error the X server sent us on to the platform error callback.
+pub(super) fn report_x11_error(error: &X11Error) {
+    report_platform_error(PlatformError {
+        request: error.request_name.unwrap_or("unknown"),
+        message: format!("{:?} (bad value {:#x})", error.error_kind, error.bad_value),
+    });
+}
+
+/// Wait for the X server to process a request so errors are reported right away, along with the
+/// request that caused them. Errors for unchecked requests only show up later in the event queue.
+pub(super) trait CheckRequest {
+    fn check_reported(self) -> Result<(), ReplyError>;
+}
+
+impl<C: RequestConnection> CheckRequest for Result<VoidCookie<'_, C>, ConnectionError> {
+    fn check_reported(self) -> Result<(), ReplyError> {
+        let result = self.map_err(ReplyError::from).and_then(|cookie| cookie.check());
+        if let Err(ReplyError::X11Error(error)) = &result {
+            report_x11_error(error);
+        }
+
+        result
+    }
+}
@@ -1,126 +1,450 @@
-use crate::x11::keyboard::{convert_key_press_event, convert_key_release_event, key_mods};
-use crate::x11::{ParentHandle, Window, WindowInner};
+use crate::hover::HoverState;
+use crate::scancode::KeyScancode;
+use crate::text::PendingText;
+#[cfg(feature = "virtual-clock")]
+use crate::virtual_clock::VirtualFrames;
+use crate::x11::keyboard::{
+    convert_key_press_event, convert_key_release_event, key_mods, LockMasks,
+};
+use crate::x11::xkb::{ComposeChange, Xkb};
+use crate::x11::{InfiniteDrag, ParentHandle, Window, WindowInner};
 use crate::{
-    Event, MouseButton, MouseEvent, PhyPoint, PhySize, ScrollDelta, WindowEvent, WindowHandler,
-    WindowInfo,
+    Event, EventStatus, ImeEvent, MonitorInfo, MouseButton, MouseEvent, PhyPoint, PhySize, Point,
+    PointerDevice, PointerType, ResizeSource, ScrollDelta, WindowEvent, WindowHandler, WindowInfo,
+    WindowScalePolicy,
+};
+use keyboard_types::{Key, KeyboardEvent, Modifiers, NamedKey};
+use std::collections::HashSet;
+use std::time::Instant;
+use x11rb::protocol::xinput::{DeviceId, Fp1616, ModifierInfo, RawMotionEvent};
+use x11rb::protocol::xproto::{
+    ClientMessageEvent, ConnectionExt as _, KeyButMask, Window as XWindow,
 };
-use std::error::Error;
-use std::os::fd::AsRawFd;
-use std::time::{Duration, Instant};
-use x11rb::connection::Connection;
 use x11rb::protocol::Event as XEvent;
 
+/// X11 doesn't have a system-wide scroll speed setting, so we use the same number of lines per
+/// scroll wheel notch as the Windows default and most toolkits.
+const LINES_PER_NOTCH: f32 = 3.0;
+
+/// `Button1Mask` through `Button5Mask`, the mouse buttons in the core protocol's key and button
+/// state.
+pub(super) const BUTTON_MASK: u16 = 0x1f00;
+
+/// A single window's part of the event loop. The [`EventLoopThread`][super::event_loop_thread]
+/// drives the event loops of all windows on a thread, and passes the window's events on to this.
 pub(super) struct EventLoop {
     handler: Box<dyn WindowHandler>,
     window: WindowInner,
     parent_handle: Option<ParentHandle>,
+    /// Whether this is the window opened by [`run_app()`][crate::run_app()], which is closed when
+    /// the process is interrupted.
+    quits_with_app: bool,
 
+    /// Whether [`WindowHandler::before_events()`] has been called for the current batch of events.
+    in_batch: bool,
     new_physical_size: Option<PhySize>,
-    frame_interval: Duration,
-    event_loop_running: bool,
+    /// What caused `new_physical_size`, see [`WindowInner::take_requested_size()`].
+    resize_source: ResizeSource,
+    /// Set when RandR reports that the monitor configuration has changed, so the handler is
+    /// notified once after all pending events have been processed.
+    monitor_changed: bool,
+    /// Set when the host reparents the window, so the handler is told to recreate its surfaces
+    /// once after all pending events have been processed.
+    surface_changed: bool,
+    /// Whether the window is unmapped, see [`WindowEvent::Suspended`]. Size changes are held back
+    /// in `new_physical_size` until the window is mapped again.
+    suspended: bool,
+    /// Text typed during the current batch that hasn't been sent yet, see [`Event::Text`].
+    pending_text: PendingText,
+    /// The keycodes of the keys that were pressed while the window had the keyboard focus and
+    /// haven't been released yet. With detectable auto-repeat, another press of one of these keys
+    /// is a repeat.
+    pressed_keys: HashSet<u8>,
+    /// Keeps the hover events balanced and suspends them during drag and drop.
+    hover: HoverState,
+    /// When the frame timer last called [`on_frame()`][Self::on_frame()], see
+    /// [`on_frame_timer()`][Self::on_frame_timer()].
+    last_frame: Instant,
 }
 
 impl EventLoop {
     pub fn new(
         window: WindowInner, handler: impl WindowHandler + 'static,
-        parent_handle: Option<ParentHandle>,
+        parent_handle: Option<ParentHandle>, quits_with_app: bool,
     ) -> Self {
         Self {
             window,
             handler: Box::new(handler),
             parent_handle,
-            frame_interval: Duration::from_millis(15),
-            event_loop_running: false,
+            quits_with_app,
+            in_batch: false,
             new_physical_size: None,
+            resize_source: ResizeSource::External,
+            monitor_changed: false,
+            surface_changed: false,
+            suspended: false,
+            pending_text: PendingText::default(),
+            pressed_keys: HashSet::new(),
+            hover: HoverState::default(),
+            last_frame: Instant::now(),
         }
     }
 
-    #[inline]
-    fn drain_xcb_events(&mut self) -> Result<(), Box<dyn Error>> {
+    pub fn window_id(&self) -> XWindow {
+        self.window.window_id
+    }
+
+    /// Whether the window has been asked to close. The handler doesn't receive any more events
+    /// after that, except for the final `WillClose` sent by [`close()`][Self::close()].
+    pub fn is_closing(&self) -> bool {
+        self.window.close_requested.get()
+    }
+
+    pub fn has_external_frame_clock(&self) -> bool {
+        self.window.external_frame_clock
+    }
+
+    /// The time the frame timer should call [`on_frame()`][Self::on_frame()] next.
+    pub fn next_frame(&self) -> Instant {
+        self.last_frame + self.window.frame_counter.interval()
+    }
+
+    /// Draw a frame if it's time for the next one. We'll try to keep a consistent frame pace. If
+    /// the last frame couldn't be processed in the expected frame time, this will throttle down
+    /// to prevent multiple frames from being queued up.
+    pub fn on_frame_timer(&mut self) {
+        let interval = self.window.frame_counter.interval();
+        let next_frame = self.last_frame + interval;
+        if Instant::now() >= next_frame {
+            self.on_frame();
+            self.last_frame = Instant::max(next_frame, Instant::now() - interval);
+        }
+    }
+
+    pub fn has_infinite_drag(&self) -> bool {
+        self.window.infinite_drag.get().is_some()
+    }
+
+    /// The handler's batch hooks are only called when there's actually something to process, so
+    /// the batch starts with the first event for this window.
+    fn begin_batch(&mut self) {
+        if self.in_batch {
+            return;
+        }
+
         // the X server has a tendency to send spurious/extraneous configure notify events when a
         // window is resized, and we need to batch those together and just send one resize event
         // when they've all been coalesced.
-        self.new_physical_size = None;
+        self.in_batch = true;
+        if !self.suspended {
+            self.new_physical_size = None;
+        }
+        self.monitor_changed = false;
+        self.surface_changed = false;
+
+        self.handler.before_events(&mut crate::Window::new(Window { inner: &self.window }));
+    }
+
+    /// Handle an event from the X server. Once all pending events have been handled,
+    /// [`finish_batch()`][Self::finish_batch()] needs to be called.
+    pub fn handle_event(&mut self, event: XEvent) {
+        if self.is_closing() {
+            return;
+        }
+
+        self.begin_batch();
+        self.handle_xcb_event(event);
+    }
+
+    /// Send an event to the handler as part of the current batch.
+    pub fn send_event(&mut self, event: Event) {
+        if self.is_closing() {
+            return;
+        }
+
+        self.begin_batch();
+        self.handler.on_event(&mut crate::Window::new(Window { inner: &self.window }), event);
+    }
 
-        while let Some(event) = self.window.xcb_connection.conn.poll_for_event()? {
-            self.handle_xcb_event(event);
+    /// Send a key event that the host forwarded to the window. Returns whether the handler captured
+    /// it. This is part of the current batch.
+    pub fn handle_host_key_event(&mut self, event: KeyboardEvent) -> bool {
+        if self.is_closing() {
+            return false;
         }
 
-        if let Some(size) = self.new_physical_size.take() {
+        self.begin_batch();
+        let event = self.window.accelerators.translate(event);
+        self.send_key_event(event, None) == EventStatus::Captured
+    }
+
+    /// Send a keyboard or accelerator event, after any pending text that needs to go first.
+    /// `keycode` is the X11 keycode of the key behind the event, see
+    /// [`crate::Window::key_scancode()`].
+    fn send_key_event(&mut self, event: Event, keycode: Option<u8>) -> EventStatus {
+        let mut window = crate::Window::new(Window { inner: &self.window });
+        if let Some(text) = self.pending_text.key_event(&event) {
+            self.handler.on_event(&mut window, text);
+        }
+
+        // X11 keycodes are evdev keycodes offset by 8
+        let _scancode =
+            keycode.map(|keycode| KeyScancode::set(u32::from(keycode).saturating_sub(8)));
+        self.handler.on_event(&mut window, event)
+    }
+
+    /// Change the window's scale factor while keeping its logical size, if the window uses
+    /// [`WindowScalePolicy::HostControlled`]. The handler is sent a resize event once the window
+    /// has been resized.
+    pub fn set_scale_factor(&mut self, scale: f64) {
+        if self.is_closing()
+            || !matches!(self.window.scale_policy, WindowScalePolicy::HostControlled(_))
+        {
+            return;
+        }
+
+        let window_info = self.window.window_info;
+        if scale == window_info.scale() {
+            return;
+        }
+
+        self.begin_batch();
+        let logical_size = window_info.logical_size();
+        self.window.window_info =
+            WindowInfo::from_physical_size(window_info.physical_size(), scale);
+
+        let new_window_info = WindowInfo::from_logical_size_snapped(logical_size, scale);
+        if new_window_info.physical_size() == window_info.physical_size() {
+            // There won't be a `ConfigureNotify` event if the physical size stays the same
+            self.new_physical_size = Some(window_info.physical_size());
+            self.resize_source = ResizeSource::ScaleFactor;
+        } else {
+            self.window.request_size(new_window_info.physical_size(), ResizeSource::ScaleFactor);
+        }
+    }
+
+    pub fn finish_batch(&mut self) {
+        if !self.in_batch {
+            return;
+        }
+        self.in_batch = false;
+
+        // The handler doesn't receive any more events once the window is closing, but the current
+        // batch is still finished
+        if self.is_closing() {
+            self.handler.after_events(&mut crate::Window::new(Window { inner: &self.window }));
+            return;
+        }
+
+        if let Some(text) = self.pending_text.take() {
+            self.handler.on_event(&mut crate::Window::new(Window { inner: &self.window }), text);
+        }
+
+        for event in self.window.clipboard_reader.take_events() {
+            self.handler.on_event(&mut crate::Window::new(Window { inner: &self.window }), event);
+        }
+
+        if self.window.ime_disabled.take() {
+            self.handler.on_event(
+                &mut crate::Window::new(Window { inner: &self.window }),
+                Event::Ime(ImeEvent::Disabled),
+            );
+        }
+
+        if let Some(effect) = self.window.drag_source.take_finished() {
+            self.handler.on_event(
+                &mut crate::Window::new(Window { inner: &self.window }),
+                Event::DragFinished(effect),
+            );
+        }
+
+        if let Some(size) = self.new_physical_size.filter(|_| !self.suspended) {
+            self.new_physical_size = None;
             self.window.window_info =
                 WindowInfo::from_physical_size(size, self.window.window_info.scale());
 
             let window_info = self.window.window_info;
+            let source = self.resize_source;
+
+            #[cfg(feature = "opengl")]
+            if let Some(gl_context) = self.window.gl_context.get() {
+                gl_context.resized(size);
+            }
+
+            self.handler.on_event(
+                &mut crate::Window::new(Window { inner: &self.window }),
+                Event::Window(WindowEvent::Resized(window_info, source)),
+            );
+
+            // The contents under the pointer have most likely moved along with the new size
+            self.window.cursor_refresh_requested.set(true);
+        }
+
+        if self.surface_changed {
+            #[cfg(feature = "opengl")]
+            if let Some(gl_context) = self.window.gl_context.get() {
+                gl_context.surface_changed();
+            }
 
             self.handler.on_event(
                 &mut crate::Window::new(Window { inner: &self.window }),
-                Event::Window(WindowEvent::Resized(window_info)),
+                Event::Window(WindowEvent::SurfaceChanged),
             );
         }
 
-        Ok(())
+        if self.monitor_changed {
+            self.handle_monitor_changed();
+        }
+
+        if self.window.cursor_refresh_requested.take() {
+            self.refresh_cursor_position();
+        }
+
+        self.handler.after_events(&mut crate::Window::new(Window { inner: &self.window }));
     }
 
-    // Event loop
-    // FIXME: poll() acts fine on linux, sometimes funky on *BSD. XCB upstream uses a define to
-    // switch between poll() and select() (the latter of which is fine on *BSD), and we should do
-    // the same.
-    pub fn run(&mut self) -> Result<(), Box<dyn Error>> {
-        let xcb_fd = self.window.xcb_connection.conn.as_raw_fd();
+    fn handle_monitor_changed(&mut self) {
+        let xcb_connection = &self.window.xcb_connection;
+
+        // The desktop environment may have updated the DPI along with the new configuration. The
+        // window keeps its physical size, so only the logical size changes with the scale factor.
+        if self.window.scale_policy == WindowScalePolicy::SystemScaleFactor {
+            xcb_connection.refresh_resources();
 
-        let mut last_frame = Instant::now();
-        self.event_loop_running = true;
+            let scale = xcb_connection.get_scaling().unwrap_or(1.0);
+            if scale != self.window.window_info.scale() {
+                self.window.window_info =
+                    WindowInfo::from_physical_size(self.window.window_info.physical_size(), scale);
 
-        while self.event_loop_running {
-            // We'll try to keep a consistent frame pace. If the last frame couldn't be processed in
-            // the expected frame time, this will throttle down to prevent multiple frames from
-            // being queued up. The conditional here is needed because event handling and frame
-            // drawing is interleaved. The `poll()` function below will wait until the next frame
-            // can be drawn, or until the window receives an event. We thus need to manually check
-            // if it's already time to draw a new frame.
-            let next_frame = last_frame + self.frame_interval;
-            if Instant::now() >= next_frame {
-                self.handler.on_frame(&mut crate::Window::new(Window { inner: &self.window }));
-                last_frame = Instant::max(next_frame, Instant::now() - self.frame_interval);
+                let window_info = self.window.window_info;
+                self.handler.on_event(
+                    &mut crate::Window::new(Window { inner: &self.window }),
+                    Event::Window(WindowEvent::Resized(window_info, ResizeSource::ScaleFactor)),
+                );
             }
+        }
 
-            self.drain_xcb_events()?;
+        let monitor_info = MonitorInfo {
+            scale: self.window.window_info.scale(),
+            refresh_rate: self.window.xcb_connection.get_refresh_rate(self.window.window_id),
+        };
+        self.window.frame_counter.set_refresh_rate(monitor_info.refresh_rate);
 
-            let mut fd = libc::pollfd { fd: xcb_fd, events: libc::POLLIN, revents: 0 };
-            let result = unsafe {
-                libc::poll(
-                    &mut fd,
-                    1 as _,
-                    next_frame.duration_since(Instant::now()).subsec_millis() as i32,
-                )
-            };
+        self.handler.on_event(
+            &mut crate::Window::new(Window { inner: &self.window }),
+            Event::Window(WindowEvent::MonitorChanged(monitor_info)),
+        );
+    }
+
+    pub fn handle_power_events(&mut self, events: &[Event]) {
+        if self.is_closing() {
+            return;
+        }
 
-            // FIXME: handle errors
-            if result == -1 || (fd.revents & libc::POLLERR) != 0 {
-                panic!("xcb connection poll error");
+        self.handler.before_events(&mut crate::Window::new(Window { inner: &self.window }));
+
+        for event in events {
+            self.handler
+                .on_event(&mut crate::Window::new(Window { inner: &self.window }), event.clone());
+            if self.is_closing() {
+                break;
             }
+        }
+
+        self.handler.after_events(&mut crate::Window::new(Window { inner: &self.window }));
+    }
 
-            if (fd.revents & libc::POLLIN) != 0 {
-                self.drain_xcb_events()?;
+    /// Give up on clipboard reads that the other client stopped answering. This runs on the frame
+    /// timer, also for windows that are driven by an external frame clock.
+    pub fn check_timeouts(&mut self) {
+        if self.is_closing() {
+            return;
+        }
+
+        let window = &self.window;
+        window.clipboard_reader.check_timeout(&window.xcb_connection, window.window_id);
+        window.drag_source.check_timeout();
+
+        // The event loop finishes this batch after handling the next events
+        if window.clipboard_reader.has_events() || window.drag_source.has_finished() {
+            self.begin_batch();
+        }
+    }
+
+    pub fn on_frame(&mut self) {
+        if !self.is_closing() {
+            // The animation frame is an event like any other, so it's sent as part of a batch
+            if let Some(animation_frame) =
+                self.window.frame_counter.frame(self.window.external_frame_clock)
+            {
+                self.send_event(Event::Window(WindowEvent::AnimationFrame(animation_frame)));
+                self.finish_batch();
+                if self.is_closing() {
+                    return;
+                }
+            }
+
+            let mut window = crate::Window::new(Window { inner: &self.window });
+            self.handler.on_frame(&mut window);
+            #[cfg(feature = "opengl")]
+            window.report_present(self.handler.as_mut());
+
+            // The event loop finishes this batch after handling the next events
+            let window = &self.window;
+            if window.cursor_refresh_requested.get()
+                || window.clipboard_reader.has_events()
+                || window.drag_source.has_finished()
+                || window.ime_disabled.get()
+            {
+                self.begin_batch();
             }
+        }
+    }
 
-            // Check if the parents's handle was dropped (such as when the host
-            // requested the window to close)
-            if let Some(parent_handle) = &self.parent_handle {
+    /// Check whether the host has requested the window to close.
+    pub fn check_parent(&mut self) {
+        match &self.parent_handle {
+            Some(parent_handle) => {
                 if parent_handle.parent_did_drop() {
-                    self.handle_must_close();
-                    self.window.close_requested.set(false);
+                    self.window.close_requested.set(true);
                 }
             }
-
-            // Check if the user has requested the window to close
-            if self.window.close_requested.get() {
-                self.handle_must_close();
-                self.window.close_requested.set(false);
+            // The window opened through `run_app()` is closed when the process is interrupted
+            None => {
+                if self.quits_with_app && crate::app::quit_requested() {
+                    self.window.close_requested.set(true);
+                }
             }
         }
+    }
 
-        Ok(())
+    /// Send the final `WillClose` event and destroy the window. This is always the last callback
+    /// the handler receives.
+    pub fn close(mut self) {
+        self.handle_must_close();
+    }
+
+    /// Draw the frames that are due on the window's virtual clock. Returns `false` if the window's
+    /// frames aren't driven by a virtual clock.
+    #[cfg(feature = "virtual-clock")]
+    pub fn draw_virtual_frames(&mut self) -> bool {
+        if self.window.virtual_frames.borrow().is_none() {
+            return false;
+        }
+
+        while let Some(frame) =
+            VirtualFrames::due_frame(&self.window.virtual_frames, &self.window.frame_counter)
+        {
+            self.on_frame();
+            frame.drawn();
+        }
+
+        true
+    }
+
+    #[cfg(not(feature = "virtual-clock"))]
+    pub fn draw_virtual_frames(&mut self) -> bool {
+        false
     }
 
     fn handle_xcb_event(&mut self, event: XEvent) {
@@ -148,104 +472,248 @@ impl EventLoop {
             ////
             // window
             ////
-            XEvent::ClientMessage(event) => {
+            XEvent::ClientMessage(event)
                 if event.format == 32
                     && event.data.as_data32()[0]
-                        == self.window.xcb_connection.atoms.WM_DELETE_WINDOW
-                {
-                    self.handle_close_requested();
-                }
+                        == self.window.xcb_connection.atoms.WM_DELETE_WINDOW =>
+            {
+                self.handle_close_requested();
+            }
+
+            XEvent::ClientMessage(event) if event.format == 32 => {
+                self.handle_xdnd_message(&event);
             }
 
             XEvent::ConfigureNotify(event) => {
                 let new_physical_size = PhySize::new(event.width as u32, event.height as u32);
+                let source = match self.window.take_requested_size(new_physical_size) {
+                    Some(source) => source,
+                    None => return,
+                };
 
                 if self.new_physical_size.is_some()
                     || new_physical_size != self.window.window_info.physical_size()
                 {
                     self.new_physical_size = Some(new_physical_size);
+                    self.resize_source = source;
                 }
             }
 
-            ////
-            // mouse
-            ////
-            XEvent::MotionNotify(event) => {
-                let physical_pos = PhyPoint::new(event.event_x as i32, event.event_y as i32);
-                let logical_pos = physical_pos.to_logical(&self.window.window_info);
+            // The server destroys our window when the host destroys the parent window without
+            // closing our window first. Nothing can be done with the window after that.
+            XEvent::DestroyNotify(event) if event.window == self.window.window_id => {
+                self.window.close_requested.set(true);
+            }
+
+            // Only the host moving an embedded window is reported. Window managers also reparent
+            // top level windows into their frames when they're mapped.
+            XEvent::ReparentNotify(event)
+                if event.window == self.window.window_id && self.parent_handle.is_some() =>
+            {
+                self.surface_changed = true;
+            }
+
+            // Window managers unmap top level windows when they're minimized, and hosts unmap
+            // embedded windows when they hide them
+            // Window managers unmap top level windows when they're minimized, and hosts unmap
+            // embedded windows when they hide them
+            XEvent::UnmapNotify(event) if event.window == self.window.window_id => {
+                self.set_suspended(true);
+            }
+
+            XEvent::MapNotify(event) if event.window == self.window.window_id => {
+                self.set_suspended(false);
+            }
 
+            XEvent::XfixesSelectionNotify(event)
+                if event.selection == self.window.xcb_connection.compositor_selection =>
+            {
+                #[cfg(feature = "opengl")]
+                if let Some(gl_context) = self.window.gl_context.get() {
+                    gl_context.compositor_changed();
+                }
+            }
+
+            XEvent::XfixesSelectionNotify(_) => {
                 self.handler.on_event(
                     &mut crate::Window::new(Window { inner: &self.window }),
-                    Event::Mouse(MouseEvent::CursorMoved {
-                        position: logical_pos,
-                        modifiers: key_mods(event.state),
-                    }),
+                    Event::ClipboardChanged,
                 );
             }
 
-            XEvent::EnterNotify(event) => {
-                self.handler.on_event(
-                    &mut crate::Window::new(Window { inner: &self.window }),
-                    Event::Mouse(MouseEvent::CursorEntered),
+            XEvent::SelectionNotify(event)
+                if event.selection == self.window.xcb_connection.atoms.XdndSelection =>
+            {
+                let events = self
+                    .window
+                    .drop_target
+                    .handle_selection_notify(&self.window.xcb_connection, &event);
+                for event in events {
+                    self.send_drag_event(event);
+                }
+            }
+
+            XEvent::SelectionNotify(event) => {
+                self.window
+                    .clipboard_reader
+                    .handle_selection_notify(&self.window.xcb_connection, &event);
+            }
+
+            XEvent::SelectionRequest(event) => {
+                self.window
+                    .drag_source
+                    .handle_selection_request(&self.window.xcb_connection, &event);
+            }
+
+            XEvent::PropertyNotify(event) => {
+                self.window
+                    .clipboard_reader
+                    .handle_property_notify(&self.window.xcb_connection, &event);
+            }
+
+            XEvent::RandrScreenChangeNotify(_) | XEvent::RandrNotify(_) => {
+                self.monitor_changed = true;
+            }
+
+            ////
+            // mouse
+            ////
+            // The pointer's motion and the button release during a drag belong to the drag source
+            XEvent::MotionNotify(event) if self.window.drag_source.is_active() => {
+                self.window.drag_source.motion(
+                    &self.window.xcb_connection,
+                    self.window.window_id,
+                    event.root_x,
+                    event.root_y,
+                    event.time,
+                );
+            }
+
+            XEvent::XinputMotion(event) if self.window.drag_source.is_active() => {
+                self.window.drag_source.motion(
+                    &self.window.xcb_connection,
+                    self.window.window_id,
+                    (event.root_x >> 16) as i16,
+                    (event.root_y >> 16) as i16,
+                    event.time,
                 );
-                // since no `MOTION_NOTIFY` event is generated when `ENTER_NOTIFY` is generated,
-                // we generate a CursorMoved as well, so the mouse position from here isn't lost
+            }
+
+            XEvent::ButtonRelease(event) if self.window.drag_source.is_active() => {
+                let xcb_connection = &self.window.xcb_connection;
+                self.window.drag_source.release(xcb_connection, self.window.window_id, event.time);
+            }
+
+            XEvent::XinputButtonRelease(event) if self.window.drag_source.is_active() => {
+                let xcb_connection = &self.window.xcb_connection;
+                self.window.drag_source.release(xcb_connection, self.window.window_id, event.time);
+            }
+
+            XEvent::MotionNotify(event) if self.window.infinite_drag.get().is_some() => {
+                let pointer_position = (f64::from(event.event_x), f64::from(event.event_y));
+                self.handle_infinite_drag_pointer(pointer_position, event.state, None);
+            }
+
+            XEvent::MotionNotify(event) => {
                 let physical_pos = PhyPoint::new(event.event_x as i32, event.event_y as i32);
                 let logical_pos = physical_pos.to_logical(&self.window.window_info);
-                self.handler.on_event(
-                    &mut crate::Window::new(Window { inner: &self.window }),
-                    Event::Mouse(MouseEvent::CursorMoved {
-                        position: logical_pos,
-                        modifiers: key_mods(event.state),
-                    }),
+                self.handle_cursor_moved(logical_pos, event.state, PointerDevice::default());
+            }
+
+            XEvent::XinputMotion(event) if self.window.infinite_drag.get().is_some() => {
+                let pointer_position = (fp1616_to_f64(event.event_x), fp1616_to_f64(event.event_y));
+                let device = self.pointer_device(event.sourceid);
+                self.handle_infinite_drag_pointer(
+                    pointer_position,
+                    xinput_mods(&event.mods),
+                    Some(device),
                 );
             }
 
-            XEvent::LeaveNotify(_) => {
-                self.handler.on_event(
-                    &mut crate::Window::new(Window { inner: &self.window }),
-                    Event::Mouse(MouseEvent::CursorLeft),
+            XEvent::XinputRawMotion(event) => {
+                let device = self.pointer_device(event.sourceid);
+                if let Some(drag) = self.window.infinite_drag.get() {
+                    if !is_absolute_device(device) {
+                        let (dx, dy) = raw_motion_delta(&event);
+                        self.move_infinite_drag(drag, dx, dy, device);
+                    }
+                }
+            }
+
+            XEvent::XinputMotion(event) => {
+                // XInput reports sub-pixel positions as 16.16 fixed point numbers
+                let scale_recip = self.window.window_info.scale_recip();
+                let logical_pos = Point::new(
+                    fp1616_to_f64(event.event_x) * scale_recip,
+                    fp1616_to_f64(event.event_y) * scale_recip,
                 );
+                let device = self.pointer_device(event.sourceid);
+                self.handle_cursor_moved(logical_pos, xinput_mods(&event.mods), device);
             }
 
-            XEvent::ButtonPress(event) => match event.detail {
-                4..=7 => {
-                    self.handler.on_event(
-                        &mut crate::Window::new(Window { inner: &self.window }),
-                        Event::Mouse(MouseEvent::WheelScrolled {
-                            delta: match event.detail {
-                                4 => ScrollDelta::Lines { x: 0.0, y: 1.0 },
-                                5 => ScrollDelta::Lines { x: 0.0, y: -1.0 },
-                                6 => ScrollDelta::Lines { x: -1.0, y: 0.0 },
-                                7 => ScrollDelta::Lines { x: 1.0, y: 0.0 },
-                                _ => unreachable!(),
-                            },
-                            modifiers: key_mods(event.state),
-                        }),
-                    );
+            // The pointer is hidden during an infinite drag, and the virtual cursor never leaves
+            XEvent::EnterNotify(_) | XEvent::LeaveNotify(_)
+                if self.window.infinite_drag.get().is_some() => {}
+
+            XEvent::EnterNotify(event) => {
+                self.window.pointer_inside.set(true);
+                Window { inner: &self.window }.restore_mouse_cursor();
+
+                if self.window.input_events.mouse_motion {
+                    self.send_hover_event(MouseEvent::CursorEntered);
+                    // since no `MOTION_NOTIFY` event is generated when `ENTER_NOTIFY` is generated,
+                    // we generate a CursorMoved as well, so the mouse position from here isn't lost
+                    let physical_pos = PhyPoint::new(event.event_x as i32, event.event_y as i32);
+                    let logical_pos = physical_pos.to_logical(&self.window.window_info);
+                    self.handle_cursor_moved(logical_pos, event.state, PointerDevice::default());
                 }
-                detail => {
-                    let button_id = mouse_id(detail);
-                    self.handler.on_event(
-                        &mut crate::Window::new(Window { inner: &self.window }),
-                        Event::Mouse(MouseEvent::ButtonPressed {
-                            button: button_id,
-                            modifiers: key_mods(event.state),
-                        }),
-                    );
+            }
+
+            XEvent::LeaveNotify(event) => {
+                self.window.pointer_inside.set(false);
+                // While a button is held the pointer is grabbed by this window, and the cursor
+                // should stay the same until the drag ends
+                if u16::from(event.state) & BUTTON_MASK == 0 {
+                    Window { inner: &self.window }.reset_mouse_cursor();
                 }
-            },
+
+                if self.window.input_events.mouse_motion {
+                    self.send_hover_event(MouseEvent::CursorLeft);
+                }
+            }
+
+            XEvent::ButtonPress(event) => {
+                self.window.update_user_time(event.time);
+                self.handle_button_press(event.detail, event.state, PointerDevice::default());
+            }
+
+            XEvent::XinputButtonPress(event) => {
+                self.window.update_user_time(event.time);
+                let device = self.pointer_device(event.sourceid);
+                self.handle_button_press(event.detail as u8, xinput_mods(&event.mods), device);
+            }
 
             XEvent::ButtonRelease(event) => {
-                if !(4..=7).contains(&event.detail) {
-                    let button_id = mouse_id(event.detail);
-                    self.handler.on_event(
-                        &mut crate::Window::new(Window { inner: &self.window }),
-                        Event::Mouse(MouseEvent::ButtonReleased {
-                            button: button_id,
-                            modifiers: key_mods(event.state),
-                        }),
-                    );
+                self.handle_button_release(event.detail, event.state, PointerDevice::default());
+
+                // The core protocol only reports the state of the first five buttons
+                let state = u16::from(event.state);
+                let pressed = |button: u8| button <= 5 && state & (1 << (7 + button)) != 0;
+                if !other_buttons_held(event.detail, pressed) {
+                    self.end_drag();
+                }
+            }
+
+            XEvent::XinputButtonRelease(event) => {
+                let device = self.pointer_device(event.sourceid);
+                self.handle_button_release(event.detail as u8, xinput_mods(&event.mods), device);
+
+                let pressed = |button: u8| {
+                    let word = event.button_mask.get(usize::from(button) / 32).copied();
+                    word.map_or(false, |word| word & (1 << (button % 32)) != 0)
+                };
+                if !other_buttons_held(event.detail as u8, pressed) {
+                    self.end_drag();
                 }
             }
 
@@ -253,36 +721,380 @@ impl EventLoop {
             // keys
             ////
             XEvent::KeyPress(event) => {
-                self.handler.on_event(
-                    &mut crate::Window::new(Window { inner: &self.window }),
-                    Event::Keyboard(convert_key_press_event(&event)),
-                );
+                self.window.update_user_time(event.time);
+                self.update_keyboard_group(event.state);
+                let keycode = Some(event.detail);
+                let repeat = !self.pressed_keys.insert(event.detail);
+                let compose = self.window.text_input.get().is_some();
+                let mut event =
+                    convert_key_press_event(&event, self.lock_masks(), self.xkb(), compose);
+                event.repeat = repeat;
+                let compose_change = self.xkb().and_then(Xkb::take_compose_change);
+                // Escape cancels a drag, like it does for other toolkits' drag sources
+                let drag_source = &self.window.drag_source;
+                if event.key == Key::Named(NamedKey::Escape) && drag_source.is_active() {
+                    drag_source.cancel(&self.window.xcb_connection, self.window.window_id);
+                    return;
+                }
+
+                // Compose sequences are sent as input method events while text input is active
+                match compose_change {
+                    Some(ComposeChange::Preedit(text)) => {
+                        event.key = Key::Named(NamedKey::Process);
+                        event.is_composing = true;
+                        self.send_key_event(Event::Keyboard(event), keycode);
+                        let cursor = Some((text.len(), text.len()));
+                        self.send_key_event(Event::Ime(ImeEvent::Preedit { text, cursor }), None);
+                    }
+                    Some(ComposeChange::Commit(text)) => {
+                        event.key = Key::Named(NamedKey::Process);
+                        event.is_composing = true;
+                        self.send_key_event(Event::Keyboard(event), keycode);
+                        self.send_key_event(Event::Ime(ImeEvent::Commit(text)), None);
+                    }
+                    Some(ComposeChange::Cancelled) => {
+                        let text = String::new();
+                        let cancelled = ImeEvent::Preedit { text, cursor: None };
+                        self.send_key_event(Event::Ime(cancelled), None);
+                        self.send_key_event(self.window.accelerators.translate(event), keycode);
+                    }
+                    None => {
+                        self.send_key_event(self.window.accelerators.translate(event), keycode);
+                    }
+                }
             }
 
             XEvent::KeyRelease(event) => {
-                self.handler.on_event(
-                    &mut crate::Window::new(Window { inner: &self.window }),
-                    Event::Keyboard(convert_key_release_event(&event)),
-                );
+                self.pressed_keys.remove(&event.detail);
+                self.update_keyboard_group(event.state);
+                let keycode = Some(event.detail);
+                let event = convert_key_release_event(&event, self.lock_masks(), self.xkb());
+                self.send_key_event(Event::Keyboard(event), keycode);
+            }
+
+            // Keys released while another window has the focus never send a release to this one
+            XEvent::FocusOut(event) if event.event == self.window.window_id => {
+                self.pressed_keys.clear();
             }
 
             _ => {}
         }
     }
 
+    fn set_suspended(&mut self, suspended: bool) {
+        if suspended == self.suspended {
+            return;
+        }
+        self.suspended = suspended;
+
+        let event = if suspended { WindowEvent::Suspended } else { WindowEvent::Resumed };
+        self.handler.on_event(
+            &mut crate::Window::new(Window { inner: &self.window }),
+            Event::Window(event),
+        );
+    }
+
+    fn handle_cursor_moved(
+        &mut self, logical_pos: Point, state: KeyButMask, device: PointerDevice,
+    ) {
+        self.send_hover_event(MouseEvent::CursorMoved {
+            position: logical_pos,
+            modifiers: key_mods(state, self.lock_masks()),
+            device,
+        });
+    }
+
+    /// Send a cursor movement, enter, or leave event to the handler through the [`HoverState`].
+    fn send_hover_event(&mut self, event: MouseEvent) {
+        let Self { handler, window, hover, .. } = self;
+        hover.filter(event, |event| {
+            handler
+                .on_event(&mut crate::Window::new(Window { inner: window }), Event::Mouse(event));
+        });
+    }
+
+    /// Query the pointer's position and send it to the handler as a cursor movement, see
+    /// [`Window::refresh_cursor_position()`][crate::Window::refresh_cursor_position()].
+    fn refresh_cursor_position(&mut self) {
+        // The cursor is frozen during an infinite drag
+        if !self.window.input_events.mouse_motion
+            || !self.window.pointer_inside.get()
+            || self.window.infinite_drag.get().is_some()
+        {
+            return;
+        }
+
+        let conn = &self.window.xcb_connection.conn;
+        let pointer = match conn.query_pointer(self.window.window_id) {
+            Ok(cookie) => match cookie.reply() {
+                Ok(pointer) if pointer.same_screen => pointer,
+                _ => return,
+            },
+            Err(_) => return,
+        };
+
+        let physical_pos = PhyPoint::new(pointer.win_x as i32, pointer.win_y as i32);
+        let logical_pos = physical_pos.to_logical(&self.window.window_info);
+        self.handle_cursor_moved(logical_pos, pointer.mask, PointerDevice::default());
+    }
+
+    /// Handle the client messages a drag source sends us through XDND, see [`DropTarget`], and
+    /// the replies from a drop target while dragging data out of the window, see [`DragSource`].
+    ///
+    /// [`DropTarget`]: super::drop_target::DropTarget
+    /// [`DragSource`]: super::drag_source::DragSource
+    fn handle_xdnd_message(&mut self, event: &ClientMessageEvent) {
+        let xcb_connection = &self.window.xcb_connection;
+        let atoms = &xcb_connection.atoms;
+        let drop_target = &self.window.drop_target;
+        let window_id = self.window.window_id;
+
+        let drag_event = if event.type_ == atoms.XdndEnter {
+            drop_target.enter(xcb_connection, event);
+            None
+        } else if event.type_ == atoms.XdndPosition {
+            let (position, modifiers) = self.drag_position(event.data.as_data32()[2]);
+            drop_target.position(xcb_connection, window_id, event, position, modifiers)
+        } else if event.type_ == atoms.XdndLeave {
+            drop_target.leave(event).then(|| MouseEvent::DragLeft)
+        } else if event.type_ == atoms.XdndDrop {
+            drop_target.handle_drop(xcb_connection, window_id, event)
+        } else if event.type_ == atoms.XdndStatus || event.type_ == atoms.XdndFinished {
+            self.window.drag_source.handle_client_message(xcb_connection, window_id, event);
+            None
+        } else {
+            None
+        };
+
+        if let Some(drag_event) = drag_event {
+            self.send_drag_event(drag_event);
+        }
+    }
+
+    /// Translate the root window coordinates from an `XdndPosition` message to the window's
+    /// logical coordinates, and query the modifiers since the message doesn't include them.
+    fn drag_position(&self, root_position: u32) -> (Point, Modifiers) {
+        let root_x = (root_position >> 16) as i16 as i32;
+        let root_y = root_position as i16 as i32;
+
+        let conn = &self.window.xcb_connection.conn;
+        let pointer = conn.query_pointer(self.window.window_id).ok().and_then(|c| c.reply().ok());
+        let (physical_pos, mask) = match pointer {
+            Some(pointer) => {
+                let window_x = i32::from(pointer.root_x) - i32::from(pointer.win_x);
+                let window_y = i32::from(pointer.root_y) - i32::from(pointer.win_y);
+                (PhyPoint::new(root_x - window_x, root_y - window_y), pointer.mask)
+            }
+            None => (PhyPoint::new(root_x, root_y), KeyButMask::default()),
+        };
+
+        let logical_pos = physical_pos.to_logical(&self.window.window_info);
+        (logical_pos, key_mods(mask, self.lock_masks()))
+    }
+
+    /// Send a drag and drop event to the handler, and tell the drag source whether it was
+    /// accepted.
+    fn send_drag_event(&mut self, event: MouseEvent) {
+        let respond = !matches!(event, MouseEvent::DragLeft);
+        let dropped = matches!(event, MouseEvent::DragDropped { .. });
+        let ended = !respond || dropped;
+
+        if matches!(event, MouseEvent::DragEntered { .. }) {
+            let Self { handler, window, hover, .. } = self;
+            hover.drag_entered(|event| {
+                handler.on_event(
+                    &mut crate::Window::new(Window { inner: window }),
+                    Event::Mouse(event),
+                );
+            });
+        }
+
+        let status = self
+            .handler
+            .on_event(&mut crate::Window::new(Window { inner: &self.window }), Event::Mouse(event));
+        if ended {
+            self.hover.drag_ended();
+        }
+        if respond {
+            let xcb_connection = &self.window.xcb_connection;
+            self.window.drop_target.respond(xcb_connection, self.window.window_id, dropped, status);
+        }
+    }
+
+    /// Handle a pointer movement during an infinite drag. `pointer_position` is the pointer's
+    /// actual position in physical pixels. This only moves the virtual cursor for absolute
+    /// pointing devices, and for any device on servers without XInput2. Other devices move it
+    /// through raw motion events, which aren't stopped by the screen edges.
+    fn handle_infinite_drag_pointer(
+        &mut self, pointer_position: (f64, f64), state: KeyButMask, device: Option<PointerDevice>,
+    ) {
+        let mut drag = match self.window.infinite_drag.get() {
+            Some(drag) => drag,
+            None => return,
+        };
+
+        let (dx, dy) = (
+            pointer_position.0 - drag.pointer_position.0,
+            pointer_position.1 - drag.pointer_position.1,
+        );
+        drag.pointer_position = pointer_position;
+        drag.modifiers = state;
+        self.window.infinite_drag.set(Some(drag));
+
+        match device {
+            Some(device) if !is_absolute_device(device) => {}
+            _ => self.move_infinite_drag(drag, dx, dy, device.unwrap_or_default()),
+        }
+    }
+
+    /// Move the virtual cursor of an infinite drag by a physical offset.
+    fn move_infinite_drag(
+        &mut self, mut drag: InfiniteDrag, dx: f64, dy: f64, device: PointerDevice,
+    ) {
+        if dx == 0.0 && dy == 0.0 {
+            return;
+        }
+
+        let scale_recip = self.window.window_info.scale_recip();
+        drag.position.x += dx * scale_recip;
+        drag.position.y += dy * scale_recip;
+        self.window.infinite_drag.set(Some(drag));
+
+        self.handle_cursor_moved(drag.position, drag.modifiers, device);
+    }
+
+    fn handle_button_press(&mut self, detail: u8, state: KeyButMask, device: PointerDevice) {
+        let modifiers = key_mods(state, self.lock_masks());
+
+        let event = match detail {
+            4..=7 => MouseEvent::WheelScrolled {
+                delta: match detail {
+                    4 => ScrollDelta::Lines { x: 0.0, y: LINES_PER_NOTCH },
+                    5 => ScrollDelta::Lines { x: 0.0, y: -LINES_PER_NOTCH },
+                    6 => ScrollDelta::Lines { x: -LINES_PER_NOTCH, y: 0.0 },
+                    7 => ScrollDelta::Lines { x: LINES_PER_NOTCH, y: 0.0 },
+                    _ => unreachable!(),
+                },
+                modifiers,
+                device,
+            },
+            detail => MouseEvent::ButtonPressed { button: mouse_id(detail), modifiers, device },
+        };
+        let event = if self.window.wheel_zoom { event.into_wheel_zoom() } else { event };
+
+        self.handler
+            .on_event(&mut crate::Window::new(Window { inner: &self.window }), Event::Mouse(event));
+    }
+
+    /// Called once all mouse buttons have been released.
+    fn end_drag(&self) {
+        let window = Window { inner: &self.window };
+        window.end_infinite_drag();
+
+        // The cursor was kept when the pointer left the window during the drag
+        if !self.window.pointer_inside.get() {
+            window.reset_mouse_cursor();
+        }
+    }
+
+    fn handle_button_release(&mut self, detail: u8, state: KeyButMask, device: PointerDevice) {
+        // The scroll wheel also generates release events, which we ignore
+        if (4..=7).contains(&detail) {
+            return;
+        }
+
+        self.handler.on_event(
+            &mut crate::Window::new(Window { inner: &self.window }),
+            Event::Mouse(MouseEvent::ButtonReleased {
+                button: mouse_id(detail),
+                modifiers: key_mods(state, self.lock_masks()),
+                device,
+            }),
+        );
+    }
+
+    #[inline]
+    fn lock_masks(&self) -> LockMasks {
+        self.window.xcb_connection.lock_masks.get()
+    }
+
+    #[inline]
+    fn xkb(&self) -> Option<&Xkb> {
+        self.window.xcb_connection.xkb.as_ref()
+    }
+
+    /// With XKB, bits 13 and 14 of a key event's state contain the active keyboard group, which
+    /// is used to look up the layout in `XcbConnection::get_keyboard_layout()`. The group change
+    /// is usually reported by an XKB state event first.
+    fn update_keyboard_group(&mut self, state: KeyButMask) {
+        let group = (u16::from(state) >> 13) & 0b11;
+        if self.window.xcb_connection.set_keyboard_group(group as usize) {
+            self.send_event(Event::Window(WindowEvent::KeyboardLayoutChanged));
+        }
+    }
+
+    fn pointer_device(&self, id: DeviceId) -> PointerDevice {
+        let xcb_connection = &self.window.xcb_connection;
+        match &xcb_connection.pointer_devices {
+            Some(pointer_devices) => pointer_devices.device(&xcb_connection.conn, id),
+            None => PointerDevice::default(),
+        }
+    }
+
     fn handle_close_requested(&mut self) {
         // FIXME: handler should decide whether window stays open or not
-        self.handle_must_close();
+        self.window.close_requested.set(true);
     }
 
+    /// Send the final `WillClose` event. This is always the last callback the handler receives.
     fn handle_must_close(&mut self) {
         self.handler.on_event(
             &mut crate::Window::new(Window { inner: &self.window }),
             Event::Window(WindowEvent::WillClose),
         );
+    }
+}
+
+/// XInput2 events report the modifier state separately from the button state, but the modifier
+/// bits are the same as in the core protocol.
+fn xinput_mods(mods: &ModifierInfo) -> KeyButMask {
+    KeyButMask::from(mods.effective as u16)
+}
+
+/// XInput reports sub-pixel positions as 16.16 fixed point numbers.
+fn fp1616_to_f64(value: Fp1616) -> f64 {
+    f64::from(value) / 65536.0
+}
+
+/// Absolute pointing devices report their position instead of relative movement in raw events.
+fn is_absolute_device(device: PointerDevice) -> bool {
+    matches!(device.pointer_type, PointerType::Tablet | PointerType::Touchscreen)
+}
+
+/// The relative movement of a raw motion event along the X and Y axes, in physical pixels.
+fn raw_motion_delta(event: &RawMotionEvent) -> (f64, f64) {
+    // The values are only included for the axes in the valuator mask, in order of their index
+    let mut delta = [0.0; 2];
+    let mut values = event.axisvalues.iter();
+    for (axis, delta) in delta.iter_mut().enumerate() {
+        let is_set = event.valuator_mask.first().map_or(false, |mask| mask & (1 << axis) != 0);
+        if !is_set {
+            continue;
+        }
 
-        self.event_loop_running = false;
+        if let Some(value) = values.next() {
+            // A 32.32 fixed point number
+            *delta = f64::from(value.integral) + f64::from(value.frac) / 4294967296.0;
+        }
     }
+
+    (delta[0], delta[1])
+}
+
+/// Whether any mouse button other than `released` is still held down, according to `pressed`.
+/// The scroll wheel buttons are never held down.
+fn other_buttons_held(released: u8, pressed: impl Fn(u8) -> bool) -> bool {
+    [1, 2, 3, 8, 9].iter().any(|&button| button != released && pressed(button))
 }
 
 fn mouse_id(id: u8) -> MouseButton {
@@ -0,0 +1,742 @@
+use std::cell::RefCell;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::os::fd::AsRawFd;
+use std::ptr::null_mut;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle, ThreadId};
+use std::time::{Duration, Instant};
+
+use keyboard_types::{KeyboardEvent, Modifiers};
+use x11rb::connection::Connection;
+use x11rb::protocol::xinput::{
+    self, ConnectionExt as _, DeviceType, HierarchyEvent, HierarchyMask,
+};
+use x11rb::protocol::xproto::{Mapping, Window as XWindow};
+use x11rb::protocol::Event as XEvent;
+
+use super::errors::report_x11_error;
+use super::event_loop::EventLoop;
+use super::event_loop_waker::EventLoopWaker;
+use super::sleep_monitor::SleepMonitor;
+use super::XcbConnection;
+use crate::frame_stats::FRAME_INTERVAL;
+use crate::platform_error::{report_platform_error, PlatformError};
+use crate::{ClipboardFormat, DeviceEvent, DeviceKind, Event, WindowEvent};
+
+/// How often the event loop checks whether a window's virtual clock has been advanced.
+const VIRTUAL_CLOCK_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Opens a window on an event loop thread. See [`LoopHandle::open()`].
+pub(super) type OpenRequest = Box<dyn FnOnce() + Send>;
+
+/// Requests that other threads can send to an event loop thread through a [`LoopHandle`].
+enum Request {
+    Open(OpenRequest),
+    /// See [`LoopHandle::send_key_event()`].
+    KeyEvent {
+        window: XWindow,
+        event: KeyboardEvent,
+        captured: Sender<bool>,
+    },
+    /// See [`LoopHandle::set_scale_factor()`].
+    SetScaleFactor {
+        window: XWindow,
+        scale: f64,
+    },
+    /// See [`LoopHandle::tick_frame()`].
+    TickFrame {
+        window: XWindow,
+    },
+    /// See [`copy_to_clipboard()`].
+    CopyToClipboard(Vec<(ClipboardFormat, Vec<u8>)>),
+}
+
+/// The windows running on an event loop thread, indexed by their X11 window. Windows that are
+/// opened as a child of one of these windows join the same thread instead of starting a new one.
+///
+/// Points to a leaked mutex, or is null if no window has been opened yet.
+static LOOP_THREADS: AtomicPtr<Mutex<Vec<(XWindow, LoopHandle)>>> = AtomicPtr::new(null_mut());
+
+thread_local! {
+    /// The event loop thread's state, if this is an event loop thread.
+    static CURRENT_THREAD: RefCell<Option<ThreadState>> = const { RefCell::new(None) };
+}
+
+struct ThreadState {
+    xcb_connection: Rc<XcbConnection>,
+    handle: LoopHandle,
+    /// Windows that have been opened on this thread but that haven't been added to the event loop
+    /// yet. Windows can be opened from within a handler's callbacks, while the event loop is busy.
+    opened_windows: Vec<EventLoop>,
+}
+
+/// Used to open windows on another event loop thread.
+#[derive(Clone)]
+pub(super) struct LoopHandle {
+    thread: ThreadId,
+    requests: Sender<Request>,
+    /// Without a waker, requests are only handled once the event loop wakes up for the next frame.
+    pub waker: Option<Arc<EventLoopWaker>>,
+}
+
+impl LoopHandle {
+    /// The event loop thread running the baseview window `window`, if it's one of ours.
+    pub fn find(window: XWindow) -> Option<Self> {
+        let loop_threads = loop_threads().lock().unwrap();
+        loop_threads.iter().find(|(id, _)| *id == window).map(|(_, handle)| handle.clone())
+    }
+
+    /// Whether this is the event loop thread the caller is running on.
+    pub fn is_current(&self) -> bool {
+        self.thread == thread::current().id()
+    }
+
+    /// Run `request` on the event loop thread. The request is dropped without being run if the
+    /// thread shuts down first, which only happens once all of its windows have been closed.
+    pub fn open(&self, request: OpenRequest) {
+        self.send(Request::Open(request));
+    }
+
+    /// Send a key event that the host received on behalf of `window` to the window's handler, and
+    /// wait up to `timeout` for the handler to process it. Returns whether the handler captured
+    /// the event. Events sent from the event loop thread itself can't be processed while one of
+    /// the thread's handlers is busy, so those are never captured.
+    pub fn send_key_event(&self, window: XWindow, event: KeyboardEvent, timeout: Duration) -> bool {
+        if self.is_current() {
+            return false;
+        }
+
+        let (captured_send, captured_recv) = channel();
+        self.send(Request::KeyEvent { window, event, captured: captured_send });
+
+        // The sender is dropped without a reply if the window has been closed in the meantime
+        captured_recv.recv_timeout(timeout).unwrap_or(false)
+    }
+
+    /// Change the scale factor of a window on this thread. The window is resized asynchronously.
+    pub fn set_scale_factor(&self, window: XWindow, scale: f64) {
+        self.send(Request::SetScaleFactor { window, scale });
+    }
+
+    /// Draw a frame for a window on this thread that uses an external frame clock. The frame is
+    /// drawn asynchronously.
+    pub fn tick_frame(&self, window: XWindow) {
+        self.send(Request::TickFrame { window });
+    }
+
+    fn send(&self, request: Request) {
+        let _ = self.requests.send(request);
+        if let Some(waker) = &self.waker {
+            waker.wake();
+        }
+    }
+}
+
+fn loop_threads() -> &'static Mutex<Vec<(XWindow, LoopHandle)>> {
+    let mut loop_threads = LOOP_THREADS.load(Ordering::Acquire);
+    if loop_threads.is_null() {
+        let new = Box::into_raw(Box::new(Mutex::new(Vec::new())));
+        loop_threads = match LOOP_THREADS.compare_exchange(
+            null_mut(),
+            new,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        ) {
+            Ok(_) => new,
+            Err(existing) => {
+                // Another thread got there first
+                drop(unsafe { Box::from_raw(new) });
+                existing
+            }
+        };
+    }
+
+    unsafe { &*loop_threads }
+}
+
+/// Returned when a window is opened on a thread that isn't an event loop thread.
+#[derive(Debug)]
+pub(super) struct NotOnEventLoopThread;
+
+impl fmt::Display for NotOnEventLoopThread {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "windows can only be opened on an event loop thread")
+    }
+}
+
+impl Error for NotOnEventLoopThread {}
+
+/// The X11 connection of the event loop thread the caller is running on. Windows can only be
+/// opened on event loop threads.
+pub(super) fn current_connection() -> Result<Rc<XcbConnection>, NotOnEventLoopThread> {
+    CURRENT_THREAD.with(|state| {
+        let state = state.borrow();
+        let state = state.as_ref().ok_or(NotOnEventLoopThread)?;
+        Ok(Rc::clone(&state.xcb_connection))
+    })
+}
+
+/// Add a window that has just been opened to the current thread's event loop.
+pub(super) fn add_window(window: EventLoop) -> Result<(), NotOnEventLoopThread> {
+    CURRENT_THREAD.with(|state| {
+        let mut state = state.borrow_mut();
+        let state = state.as_mut().ok_or(NotOnEventLoopThread)?;
+
+        let mut loop_threads = loop_threads().lock().unwrap();
+        loop_threads.push((window.window_id(), state.handle.clone()));
+        state.opened_windows.push(window);
+        Ok(())
+    })
+}
+
+/// Replace the clipboard's contents. The `CLIPBOARD` selection has to be owned by a connection
+/// that keeps answering requests for it, so this uses the connection of the calling event loop
+/// thread, or else that of any other event loop thread. Nothing happens if no windows are open.
+pub(super) fn copy_to_clipboard(contents: &[(ClipboardFormat, &[u8])]) {
+    let contents: Vec<_> =
+        contents.iter().map(|(format, data)| (format.clone(), data.to_vec())).collect();
+
+    let copied = CURRENT_THREAD.with(|state| match state.borrow().as_ref() {
+        Some(state) => {
+            state.xcb_connection.clipboard.set(&state.xcb_connection, &contents);
+            true
+        }
+        None => false,
+    });
+    if copied {
+        return;
+    }
+
+    let handle = loop_threads().lock().unwrap().first().map(|(_, handle)| handle.clone());
+    if let Some(handle) = handle {
+        handle.send(Request::CopyToClipboard(contents));
+    }
+}
+
+fn take_opened_windows() -> Vec<EventLoop> {
+    CURRENT_THREAD.with(|state| match state.borrow_mut().as_mut() {
+        Some(state) => std::mem::take(&mut state.opened_windows),
+        None => Vec::new(),
+    })
+}
+
+fn has_opened_windows() -> bool {
+    CURRENT_THREAD
+        .with(|state| state.borrow().as_ref().map_or(false, |s| !s.opened_windows.is_empty()))
+}
+
+fn unregister_window(window: XWindow) {
+    loop_threads().lock().unwrap().retain(|(id, _)| *id != window);
+}
+
+/// Runs the event loops of all windows sharing an X11 connection. The thread keeps running until
+/// the last of its windows has been closed.
+pub(super) struct EventLoopThread {
+    xcb_connection: Rc<XcbConnection>,
+    windows: Vec<EventLoop>,
+    requests: Receiver<Request>,
+    waker: Option<Arc<EventLoopWaker>>,
+    /// Our connection to the DBus system bus, used to receive power events. This is `None` if the
+    /// system bus could not be reached or if the connection has been lost.
+    sleep_monitor: Option<SleepMonitor>,
+}
+
+impl EventLoopThread {
+    /// Start a new event loop thread with its own X11 connection, and open its first window by
+    /// running `open` on it. `waker` should be the waker that was given to the window's
+    /// [`ParentHandle`][super::ParentHandle], if it has one.
+    pub fn spawn(waker: Option<Arc<EventLoopWaker>>, open: OpenRequest) -> JoinHandle<()> {
+        thread::spawn(move || {
+            let mut event_loop = match Self::new(waker) {
+                Ok(event_loop) => event_loop,
+                Err(err) => {
+                    report_platform_error(PlatformError {
+                        request: "OpenDisplay",
+                        message: format!("could not connect to the X server: {err}"),
+                    });
+                    return;
+                }
+            };
+
+            open();
+            if let Err(err) = event_loop.run() {
+                report_platform_error(PlatformError {
+                    request: "EventLoop",
+                    message: format!("the window thread stopped: {err}"),
+                });
+            }
+
+            // The windows are still registered if the event loop stopped because of an error
+            let thread = thread::current().id();
+            loop_threads().lock().unwrap().retain(|(_, handle)| handle.thread != thread);
+            CURRENT_THREAD.with(|state| state.borrow_mut().take());
+        })
+    }
+
+    fn new(waker: Option<Arc<EventLoopWaker>>) -> Result<Self, Box<dyn Error>> {
+        let xcb_connection = Rc::new(XcbConnection::new()?);
+        let root = xcb_connection.screen().root;
+
+        // Used to notify the handlers about devices being plugged in. Hierarchy events can only be
+        // selected for all devices at once.
+        if xcb_connection.pointer_devices.is_some() {
+            xcb_connection.conn.xinput_xi_select_events(
+                root,
+                &[xinput::EventMask {
+                    deviceid: xinput::Device::ALL.into(),
+                    mask: vec![xinput::XIEventMask::HIERARCHY],
+                }],
+            )?;
+        }
+
+        // Used to notify the handlers about displays being connected, disconnected, or
+        // reconfigured
+        if let Some(monitors) = &xcb_connection.monitors {
+            monitors.select_events(&xcb_connection.conn, root)?;
+        }
+
+        // Used to notify the handlers about changes to the desktop's settings
+        xcb_connection.select_settings_events();
+
+        let (requests_send, requests_recv) = channel();
+        let handle = LoopHandle {
+            thread: thread::current().id(),
+            requests: requests_send,
+            waker: waker.clone(),
+        };
+
+        CURRENT_THREAD.with(|state| {
+            *state.borrow_mut() = Some(ThreadState {
+                xcb_connection: Rc::clone(&xcb_connection),
+                handle,
+                opened_windows: Vec::new(),
+            })
+        });
+
+        Ok(Self {
+            xcb_connection,
+            windows: Vec::new(),
+            requests: requests_recv,
+            waker,
+            sleep_monitor: SleepMonitor::connect(),
+        })
+    }
+
+    /// The time the next of the windows' frames is due, see [`EventLoop::next_frame()`].
+    fn next_frame(&self) -> Instant {
+        self.windows
+            .iter()
+            .filter(|window| !window.has_external_frame_clock())
+            .map(EventLoop::next_frame)
+            .min()
+            .unwrap_or_else(|| Instant::now() + FRAME_INTERVAL)
+    }
+
+    /// Add the windows that have been opened since the last call, and close the windows that have
+    /// requested to be closed. Returns `false` once there are no windows left.
+    fn update_windows(&mut self) -> bool {
+        while let Ok(request) = self.requests.try_recv() {
+            self.handle_request(request);
+        }
+
+        loop {
+            self.windows.extend(take_opened_windows());
+            self.close_windows();
+            if !self.windows.is_empty() {
+                return true;
+            }
+
+            // Requests are only sent while one of our windows is registered, but one may have been
+            // sent just before the last window was closed. A handler may also have opened a window
+            // while it was being closed.
+            match self.requests.try_recv() {
+                Ok(request) => self.handle_request(request),
+                Err(_) if has_opened_windows() => {}
+                Err(_) => return false,
+            }
+        }
+    }
+
+    fn handle_request(&mut self, request: Request) {
+        match request {
+            Request::Open(open) => open(),
+            Request::KeyEvent { window, event, captured } => {
+                self.windows.extend(take_opened_windows());
+                if let Some(window) = self.windows.iter_mut().find(|w| w.window_id() == window) {
+                    let _ = captured.send(window.handle_host_key_event(event));
+                    window.finish_batch();
+                }
+            }
+            Request::SetScaleFactor { window, scale } => {
+                self.windows.extend(take_opened_windows());
+                if let Some(window) = self.windows.iter_mut().find(|w| w.window_id() == window) {
+                    window.set_scale_factor(scale);
+                    window.finish_batch();
+                }
+            }
+            Request::TickFrame { window } => {
+                self.windows.extend(take_opened_windows());
+                if let Some(window) = self.windows.iter_mut().find(|w| w.window_id() == window) {
+                    if window.has_external_frame_clock() {
+                        window.on_frame();
+                        window.finish_batch();
+                    }
+                }
+            }
+            Request::CopyToClipboard(contents) => {
+                self.xcb_connection.clipboard.set(&self.xcb_connection, &contents);
+            }
+        }
+    }
+
+    fn close_windows(&mut self) {
+        let mut i = 0;
+        while i < self.windows.len() {
+            if self.windows[i].is_closing() {
+                let window = self.windows.remove(i);
+                unregister_window(window.window_id());
+                window.close();
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    fn settings_changed(&mut self) {
+        self.xcb_connection.refresh_settings();
+        for window in &mut self.windows {
+            window.send_event(Event::SystemSettingsChanged);
+        }
+    }
+
+    fn keyboard_layout_changed(&mut self) {
+        for window in &mut self.windows {
+            window.send_event(Event::Window(WindowEvent::KeyboardLayoutChanged));
+        }
+    }
+
+    fn modifiers_changed(&mut self, modifiers: Modifiers) {
+        for window in &mut self.windows {
+            window.send_event(Event::Window(WindowEvent::ModifiersChanged(modifiers)));
+        }
+    }
+
+    fn drain_xcb_events(&mut self) -> Result<(), Box<dyn Error>> {
+        while let Some(event) = self.xcb_connection.conn.poll_for_event()? {
+            #[cfg(feature = "event-log")]
+            let _raw_event = crate::event_log::RawEvent::set(|| format!("{:?}", event));
+
+            // Windows opened by a handler may already receive events in this batch
+            self.windows.extend(take_opened_windows());
+
+            match event {
+                XEvent::MappingNotify(event) if event.request != Mapping::POINTER => {
+                    self.xcb_connection.refresh_lock_masks();
+                    self.xcb_connection.refresh_keymap();
+                    if event.request == Mapping::KEYBOARD {
+                        self.keyboard_layout_changed();
+                    }
+                }
+
+                // XKB state events aren't tied to a window
+                XEvent::XkbStateNotify(event) => {
+                    let group = u8::from(event.group) as usize;
+                    if self.xcb_connection.set_keyboard_group(group) {
+                        self.keyboard_layout_changed();
+                    }
+                    if let Some(modifiers) = self.xcb_connection.set_modifiers(event.mods) {
+                        self.modifiers_changed(modifiers);
+                    }
+                }
+
+                XEvent::XinputHierarchy(event) => {
+                    for device_event in self.device_events(event) {
+                        for window in &mut self.windows {
+                            window.send_event(Event::Device(device_event));
+                        }
+                    }
+                }
+
+                XEvent::RandrScreenChangeNotify(_) | XEvent::RandrNotify(_) => {
+                    for window in &mut self.windows {
+                        window.handle_event(event.clone());
+                    }
+                }
+
+                // Raw events are reported on the root window, and they're only selected during an
+                // infinite drag
+                XEvent::XinputRawMotion(_) => {
+                    if let Some(window) =
+                        self.windows.iter_mut().find(|window| window.has_infinite_drag())
+                    {
+                        window.handle_event(event);
+                    }
+                }
+
+                // Grabbed keys for global shortcuts are reported on the root window
+                XEvent::KeyPress(event) if event.event == self.xcb_connection.screen().root => {
+                    let shortcut = self.xcb_connection.global_shortcuts.key_press(&event);
+                    if let Some((window_id, id)) = shortcut {
+                        if let Some(window) =
+                            self.windows.iter_mut().find(|window| window.window_id() == window_id)
+                        {
+                            window.send_event(Event::GlobalShortcut(id));
+                        }
+                    }
+                }
+                XEvent::KeyRelease(event) if event.event == self.xcb_connection.screen().root => {
+                    self.xcb_connection.global_shortcuts.key_release(&event);
+                }
+
+                // The clipboard's selection window isn't one of the windows
+                XEvent::SelectionRequest(event)
+                    if self.xcb_connection.clipboard.owns(event.owner) =>
+                {
+                    self.xcb_connection
+                        .clipboard
+                        .handle_selection_request(&self.xcb_connection, &event);
+                }
+                XEvent::SelectionClear(event)
+                    if self.xcb_connection.clipboard.owns(event.owner) =>
+                {
+                    self.xcb_connection
+                        .clipboard
+                        .handle_selection_clear(&self.xcb_connection, &event);
+                }
+                // Large clipboard contents are sent in chunks as the requestor deletes the property
+                XEvent::PropertyNotify(event)
+                    if self.xcb_connection.clipboard.is_transferring_to(event.window) =>
+                {
+                    self.xcb_connection
+                        .clipboard
+                        .handle_property_notify(&self.xcb_connection, &event);
+                }
+
+                // The XSETTINGS manager changed its settings, or another one took over
+                XEvent::PropertyNotify(event)
+                    if self.xcb_connection.is_settings_change(event.window, event.atom) =>
+                {
+                    self.settings_changed();
+                }
+                XEvent::XfixesSelectionNotify(event)
+                    if event.selection == self.xcb_connection.xsettings_selection =>
+                {
+                    self.settings_changed();
+                }
+
+                // Errors caused by requests that weren't checked when they were sent
+                XEvent::Error(error) => report_x11_error(&error),
+
+                event => {
+                    let window_id = match event_window(&event) {
+                        Some(window_id) => window_id,
+                        None => continue,
+                    };
+
+                    if let Some(window) =
+                        self.windows.iter_mut().find(|window| window.window_id() == window_id)
+                    {
+                        window.handle_event(event);
+                    }
+                }
+            }
+        }
+
+        for window in &mut self.windows {
+            window.finish_batch();
+        }
+
+        Ok(())
+    }
+
+    fn device_events(&self, event: HierarchyEvent) -> Vec<DeviceEvent> {
+        let pointer_devices = match &self.xcb_connection.pointer_devices {
+            Some(pointer_devices) => pointer_devices,
+            None => return Vec::new(),
+        };
+
+        // Hot-plugged devices are first added and then enabled in a separate event, and they're
+        // disabled before they are removed. Only enabled devices can be queried, so these are the
+        // flags we care about. Master devices are virtual and are thus ignored.
+        let mut events = Vec::new();
+        for info in event.infos {
+            let added = info.flags.contains(HierarchyMask::DEVICE_ENABLED);
+            let removed = info.flags.contains(HierarchyMask::DEVICE_DISABLED);
+            if !added && !removed {
+                continue;
+            }
+
+            let event = match (info.type_, added) {
+                (DeviceType::SLAVE_POINTER, true) => {
+                    let device = pointer_devices.added(&self.xcb_connection.conn, info.deviceid);
+                    DeviceEvent::Added {
+                        id: device.id,
+                        kind: DeviceKind::Pointer(device.pointer_type),
+                    }
+                }
+                (DeviceType::SLAVE_POINTER, false) => {
+                    let device = pointer_devices.removed(info.deviceid);
+                    DeviceEvent::Removed {
+                        id: device.id,
+                        kind: DeviceKind::Pointer(device.pointer_type),
+                    }
+                }
+                (DeviceType::SLAVE_KEYBOARD, true) => {
+                    DeviceEvent::Added { id: info.deviceid as u64, kind: DeviceKind::Keyboard }
+                }
+                (DeviceType::SLAVE_KEYBOARD, false) => {
+                    DeviceEvent::Removed { id: info.deviceid as u64, kind: DeviceKind::Keyboard }
+                }
+                _ => continue,
+            };
+
+            events.push(event);
+        }
+
+        events
+    }
+
+    fn drain_power_events(&mut self) {
+        let events = match self.sleep_monitor.as_mut().map(|monitor| monitor.read_events()) {
+            Some(Ok(events)) if !events.is_empty() => events,
+            Some(Ok(_)) | None => return,
+            Some(Err(_)) => {
+                self.sleep_monitor = None;
+                return;
+            }
+        };
+
+        for window in &mut self.windows {
+            window.handle_power_events(&events);
+        }
+    }
+
+    // Event loop
+    // FIXME: poll() acts fine on linux, sometimes funky on *BSD. XCB upstream uses a define to
+    // switch between poll() and select() (the latter of which is fine on *BSD), and we should do
+    // the same.
+    fn run(&mut self) -> Result<(), Box<dyn Error>> {
+        let xcb_fd = self.xcb_connection.conn.as_raw_fd();
+
+        // Closing a window, either from within one of the handler's callbacks or by any of the
+        // other means below, takes effect as soon as the current callback returns. The window
+        // doesn't receive any other callbacks after that, and `WillClose` is sent once the current
+        // step of the loop is done.
+        while self.update_windows() {
+            // Each window draws its frames at the refresh rate of its own display. Event handling
+            // and frame drawing is interleaved, and the `poll()` function below will wait until
+            // the next frame can be drawn or until a window receives an event. We thus need to
+            // manually check if it's already time to draw a new frame.
+            //
+            // Windows driven by a virtual clock only draw the frames that are due on their clock.
+            let mut uses_virtual_clock = false;
+            for window in &mut self.windows {
+                window.check_timeouts();
+                if window.draw_virtual_frames() {
+                    uses_virtual_clock = true;
+                } else if !window.has_external_frame_clock() {
+                    window.on_frame_timer();
+                }
+            }
+            let next_frame = self.next_frame();
+
+            self.drain_xcb_events()?;
+            if !self.update_windows() {
+                break;
+            }
+
+            // `poll()` ignores negative file descriptors, so this works without a sleep monitor or
+            // a waker
+            let sleep_monitor_fd = self.sleep_monitor.as_ref().map_or(-1, |m| m.as_raw_fd());
+            let waker_fd = self.waker.as_ref().map_or(-1, |waker| waker.as_raw_fd());
+            let mut fds = [
+                libc::pollfd { fd: xcb_fd, events: libc::POLLIN, revents: 0 },
+                libc::pollfd { fd: sleep_monitor_fd, events: libc::POLLIN, revents: 0 },
+                libc::pollfd { fd: waker_fd, events: libc::POLLIN, revents: 0 },
+            ];
+            let timeout = if uses_virtual_clock {
+                VIRTUAL_CLOCK_POLL_INTERVAL
+            } else {
+                next_frame.duration_since(Instant::now())
+            };
+            let result = unsafe {
+                libc::poll(fds.as_mut_ptr(), fds.len() as _, timeout.subsec_millis() as i32)
+            };
+
+            // A signal arriving while we're waiting interrupts the call, which is not an error
+            let interrupted =
+                result == -1 && io::Error::last_os_error().kind() == io::ErrorKind::Interrupted;
+
+            // FIXME: handle errors
+            if (result == -1 && !interrupted) || (fds[0].revents & libc::POLLERR) != 0 {
+                panic!("xcb connection poll error");
+            }
+
+            // The waker is used both for close requests from the host and for windows opened from
+            // other threads, which are checked for every time around the loop
+            if (fds[2].revents & libc::POLLIN) != 0 {
+                if let Some(waker) = &self.waker {
+                    waker.reset();
+                }
+            }
+
+            // The host wakes up the event loop when it closes a window, so the handler gets to
+            // release its resources before `close()` returns and the host destroys the parent
+            // window
+            for window in &mut self.windows {
+                window.check_parent();
+            }
+            if !self.update_windows() {
+                break;
+            }
+
+            if (fds[0].revents & libc::POLLIN) != 0 {
+                self.drain_xcb_events()?;
+                if !self.update_windows() {
+                    break;
+                }
+            }
+
+            // Hangups and errors are detected when reading from the socket
+            if fds[1].revents != 0 {
+                self.drain_power_events();
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The window an event is meant for, for events that are sent to a single window.
+fn event_window(event: &XEvent) -> Option<XWindow> {
+    let window = match event {
+        XEvent::ClientMessage(event) => event.window,
+        XEvent::ConfigureNotify(event) => event.event,
+        XEvent::DestroyNotify(event) => event.event,
+        XEvent::ReparentNotify(event) => event.event,
+        XEvent::MapNotify(event) => event.event,
+        XEvent::UnmapNotify(event) => event.event,
+        XEvent::XfixesSelectionNotify(event) => event.window,
+        XEvent::SelectionNotify(event) => event.requestor,
+        XEvent::SelectionRequest(event) => event.owner,
+        XEvent::PropertyNotify(event) => event.window,
+        XEvent::MotionNotify(event) => event.event,
+        XEvent::EnterNotify(event) => event.event,
+        XEvent::LeaveNotify(event) => event.event,
+        XEvent::ButtonPress(event) => event.event,
+        XEvent::ButtonRelease(event) => event.event,
+        XEvent::KeyPress(event) => event.event,
+        XEvent::KeyRelease(event) => event.event,
+        XEvent::XinputMotion(event) => event.event,
+        XEvent::XinputButtonPress(event) => event.event,
+        XEvent::XinputButtonRelease(event) => event.event,
+        _ => return None,
+    };
+
+    Some(window)
+}
@@ -0,0 +1,71 @@
+use std::io;
+use std::os::fd::{AsRawFd, RawFd};
+
+/// An eventfd that other threads can use to wake up a window's event loop while it's waiting for
+/// events, so it can respond to requests from the host immediately instead of after the next
+/// frame.
+///
+/// Sandboxes sometimes don't allow eventfds, so this falls back to a self-pipe if one can't be
+/// created.
+pub(crate) struct EventLoopWaker {
+    /// The fd the event loop polls. This is the same as `write_fd` for an eventfd.
+    read_fd: RawFd,
+    write_fd: RawFd,
+}
+
+impl EventLoopWaker {
+    pub fn new() -> io::Result<Self> {
+        let fd = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC | libc::EFD_NONBLOCK) };
+        if fd != -1 {
+            return Ok(Self { read_fd: fd, write_fd: fd });
+        }
+
+        let mut fds = [-1; 2];
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC | libc::O_NONBLOCK) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self { read_fd: fds[0], write_fd: fds[1] })
+    }
+
+    /// Wake up the event loop. Multiple wakeups before the event loop gets to run are coalesced.
+    pub fn wake(&self) {
+        // An eventfd needs exactly 8 bytes. With a pipe, a full buffer means the event loop hasn't
+        // been reset yet, so it's fine if the write fails.
+        let value: u64 = 1;
+        unsafe { libc::write(self.write_fd, &value as *const u64 as *const libc::c_void, 8) };
+    }
+
+    /// Called by the event loop after it's been woken up, so the next `poll()` blocks again.
+    pub fn reset(&self) {
+        // An eventfd is reset by a single read, while a pipe needs to be drained
+        let mut buffer = [0u64; 16];
+        loop {
+            let read = unsafe {
+                libc::read(
+                    self.read_fd,
+                    buffer.as_mut_ptr() as *mut libc::c_void,
+                    std::mem::size_of_val(&buffer),
+                )
+            };
+            if read <= 0 || self.read_fd == self.write_fd {
+                break;
+            }
+        }
+    }
+}
+
+impl AsRawFd for EventLoopWaker {
+    fn as_raw_fd(&self) -> RawFd {
+        self.read_fd
+    }
+}
+
+impl Drop for EventLoopWaker {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.read_fd) };
+        if self.write_fd != self.read_fd {
+            unsafe { libc::close(self.write_fd) };
+        }
+    }
+}
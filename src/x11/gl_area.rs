@@ -0,0 +1,109 @@
+use std::os::raw::c_ulong;
+use std::rc::Rc;
+
+use x11rb::connection::Connection;
+use x11rb::protocol::shape::{ConnectionExt as _, SK, SO};
+use x11rb::protocol::xproto::{
+    ClipOrdering, ColormapAlloc, ConfigureWindowAux, ConnectionExt as _, CreateWindowAux,
+    Window as XWindow, WindowClass,
+};
+
+use super::errors::CheckRequest;
+use super::XcbConnection;
+use crate::gl::x11::CreationFailedError;
+use crate::gl::{platform, GlArea, GlConfig, GlContext, GlError};
+use crate::{PhyPoint, PhySize};
+
+/// The child window a [`GlArea`] renders to. It has the visual of the area's framebuffer config,
+/// which doesn't need to match the parent window's visual.
+pub(crate) struct GlAreaSurface {
+    xcb_connection: Rc<XcbConnection>,
+    window_id: XWindow,
+    color_map: u32,
+}
+
+impl GlAreaSurface {
+    pub fn set_bounds(&self, position: PhyPoint, size: PhySize) {
+        let conn = &self.xcb_connection.conn;
+        let configure_window = conn.configure_window(
+            self.window_id,
+            &ConfigureWindowAux::new()
+                .x(position.x)
+                .y(position.y)
+                // Zero sized windows are not allowed
+                .width(size.width.max(1))
+                .height(size.height.max(1)),
+        );
+        // Errors are passed on to the error callback, there's nothing else we can do about them
+        let _ = configure_window.check_reported();
+    }
+}
+
+impl Drop for GlAreaSurface {
+    fn drop(&mut self) {
+        let conn = &self.xcb_connection.conn;
+        let _ = conn.destroy_window(self.window_id);
+        let _ = conn.free_colormap(self.color_map);
+        let _ = conn.flush();
+    }
+}
+
+/// Create a child window of `parent` with a visual matching `config`, and a context rendering to
+/// that window.
+pub(super) fn create_gl_area(
+    xcb_connection: &Rc<XcbConnection>, parent: XWindow, position: PhyPoint, size: PhySize,
+    config: GlConfig,
+) -> Result<GlArea, GlError> {
+    let display = xcb_connection.dpy;
+    let (fb_config, window_config) =
+        unsafe { platform::GlContext::get_fb_config_and_visual(display, config)? };
+
+    let conn = &xcb_connection.conn;
+
+    // A colormap and border pixel are needed when the depth differs from the parent's
+    let color_map = conn.generate_id().map_err(creation_failed)?;
+    conn.create_colormap(
+        ColormapAlloc::NONE,
+        color_map,
+        xcb_connection.screen().root,
+        window_config.visual,
+    )
+    .check_reported()
+    .map_err(creation_failed)?;
+
+    let window_id = conn.generate_id().map_err(creation_failed)?;
+    let surface = GlAreaSurface { xcb_connection: xcb_connection.clone(), window_id, color_map };
+    conn.create_window(
+        window_config.depth,
+        window_id,
+        parent,
+        position.x as i16,
+        position.y as i16,
+        size.width.max(1) as u16,
+        size.height.max(1) as u16,
+        0,
+        WindowClass::INPUT_OUTPUT,
+        window_config.visual,
+        &CreateWindowAux::new().colormap(color_map).border_pixel(0).background_pixmap(x11rb::NONE),
+    )
+    .check_reported()
+    .map_err(creation_failed)?;
+
+    // An empty input shape makes the pointer pass through to the parent window, so the area
+    // doesn't cause crossing events. Errors, including the server not supporting the extension,
+    // are passed on to the error callback. Input events would still propagate to the parent.
+    let _ = conn
+        .shape_rectangles(SO::SET, SK::INPUT, ClipOrdering::UNSORTED, window_id, 0, 0, &[])
+        .check_reported();
+
+    conn.map_window(window_id).check_reported().map_err(creation_failed)?;
+    conn.flush().map_err(creation_failed)?;
+
+    let context = unsafe { platform::GlContext::create(window_id as c_ulong, display, fb_config)? };
+
+    Ok(GlArea::new(GlContext::new(context), surface))
+}
+
+fn creation_failed<E>(_error: E) -> GlError {
+    GlError::CreationFailed(CreationFailedError::WindowCreationFailed)
+}
@@ -0,0 +1,136 @@
+use std::cell::{Cell, RefCell};
+
+use x11rb::connection::Connection;
+use x11rb::errors::ReplyError;
+use x11rb::protocol::xproto::{
+    ConnectionExt as _, GrabMode, KeyPressEvent, KeyReleaseEvent, Keycode, ModMask, Timestamp,
+    Window as XWindow,
+};
+
+use super::keyboard::{code_to_hardware_keycode, shortcut_mods, LockMasks};
+use crate::{GlobalShortcutError, KeyCombo};
+
+/// A key grabbed on the root window for one of a window's global shortcuts.
+struct Grab {
+    window: XWindow,
+    id: u32,
+    keycode: Keycode,
+    modifiers: u16,
+    /// The lock modifier combinations the key was grabbed with, so they can be released again
+    /// after the lock keys have been remapped.
+    lock_combinations: Vec<u16>,
+}
+
+/// The global shortcuts registered by the windows on a connection. Key grabs belong to the
+/// connection rather than to a window, so the windows sharing a connection also share these.
+#[derive(Default)]
+pub(super) struct GlobalShortcuts {
+    grabs: RefCell<Vec<Grab>>,
+    /// The keycode and time of the last grabbed key release. Auto repeat sends a release and a
+    /// press with the same time for every repeat, which is how those presses are told apart.
+    last_release: Cell<Option<(Keycode, Timestamp)>>,
+}
+
+impl GlobalShortcuts {
+    pub fn register(
+        &self, conn: &impl Connection, root: XWindow, lock_masks: LockMasks, window: XWindow,
+        combo: KeyCombo, id: u32,
+    ) -> Result<(), GlobalShortcutError> {
+        let keycode = combo
+            .code()
+            .and_then(code_to_hardware_keycode)
+            .ok_or(GlobalShortcutError::UnsupportedKey)?;
+        let modifiers = shortcut_mods(combo.modifiers);
+
+        let mut grabs = self.grabs.borrow_mut();
+        if let Some(grab) =
+            grabs.iter_mut().find(|grab| grab.keycode == keycode && grab.modifiers == modifiers)
+        {
+            if grab.window != window {
+                return Err(GlobalShortcutError::Unavailable);
+            }
+
+            grab.id = id;
+            return Ok(());
+        }
+
+        let lock_combinations = lock_masks.combinations();
+        for (i, &locks) in lock_combinations.iter().enumerate() {
+            // Grabbing a key another client has already grabbed fails with `BadAccess`. That's
+            // expected, so it's not passed on to the error callback.
+            let grabbed = conn
+                .grab_key(
+                    false,
+                    root,
+                    ModMask::from(modifiers | locks),
+                    keycode,
+                    GrabMode::ASYNC,
+                    GrabMode::ASYNC,
+                )
+                .map_err(ReplyError::from)
+                .and_then(|cookie| cookie.check());
+            if grabbed.is_err() {
+                for &locks in &lock_combinations[..i] {
+                    let _ = conn.ungrab_key(keycode, root, ModMask::from(modifiers | locks));
+                }
+                let _ = conn.flush();
+
+                return Err(GlobalShortcutError::Unavailable);
+            }
+        }
+
+        grabs.push(Grab { window, id, keycode, modifiers, lock_combinations });
+
+        Ok(())
+    }
+
+    /// Remove a window's shortcuts with this id.
+    pub fn unregister(&self, conn: &impl Connection, root: XWindow, window: XWindow, id: u32) {
+        self.release(conn, root, |grab| grab.window == window && grab.id == id);
+    }
+
+    /// Remove all of a window's shortcuts when it's closed.
+    pub fn unregister_window(&self, conn: &impl Connection, root: XWindow, window: XWindow) {
+        self.release(conn, root, |grab| grab.window == window);
+    }
+
+    /// The window and id of the shortcut triggered by a key press on the root window, if any.
+    pub fn key_press(&self, event: &KeyPressEvent) -> Option<(XWindow, u32)> {
+        if self.last_release.take() == Some((event.detail, event.time)) {
+            return None;
+        }
+
+        let modifiers = u16::from(event.state) & shortcut_mods(keyboard_types::Modifiers::all());
+        self.grabs
+            .borrow()
+            .iter()
+            .find(|grab| grab.keycode == event.detail && grab.modifiers == modifiers)
+            .map(|grab| (grab.window, grab.id))
+    }
+
+    pub fn key_release(&self, event: &KeyReleaseEvent) {
+        self.last_release.set(Some((event.detail, event.time)));
+    }
+
+    fn release(
+        &self, conn: &impl Connection, root: XWindow, mut predicate: impl FnMut(&Grab) -> bool,
+    ) {
+        let mut released = false;
+        self.grabs.borrow_mut().retain(|grab| {
+            if !predicate(grab) {
+                return true;
+            }
+
+            for &locks in &grab.lock_combinations {
+                let _ = conn.ungrab_key(grab.keycode, root, ModMask::from(grab.modifiers | locks));
+            }
+            released = true;
+
+            false
+        });
+
+        if released {
+            let _ = conn.flush();
+        }
+    }
+}
@@ -18,16 +18,21 @@
 
 //! X11 keyboard handling
 
-use x11rb::protocol::xproto::{KeyButMask, KeyPressEvent, KeyReleaseEvent};
+use std::error::Error;
+
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{ConnectionExt as _, KeyButMask, KeyPressEvent, KeyReleaseEvent};
 
 use keyboard_types::*;
 
 use crate::keyboard::code_to_location;
 
+use super::xkb::Xkb;
+
 /// Convert a hardware scan code to a key.
 ///
-/// Note: this is a hardcoded layout. We need to detect the user's
-/// layout from the system and apply it.
+/// This is the US layout, which is used for the keys that don't produce characters, and for all
+/// keys if xkbcommon isn't available. See [`layout_key()`].
 fn code_to_key(code: Code, m: Modifiers) -> Key {
     fn a(s: &str) -> Key {
         Key::Character(s.into())
@@ -360,9 +365,119 @@ fn hardware_keycode_to_code(hw_keycode: u16) -> Code {
     }
 }
 
+/// The `Num_Lock` keysym.
+const XK_NUM_LOCK: u32 = 0xff7f;
+/// The `Scroll_Lock` keysym.
+const XK_SCROLL_LOCK: u32 = 0xff14;
+
+/// The modifier bits the Num Lock and Scroll Lock keys are bound to.
+///
+/// Unlike Caps Lock, which always uses the `Lock` modifier, X11 doesn't have dedicated modifier
+/// bits for these keys. They're assigned to one of `Mod1` through `Mod5` by the server's modifier
+/// mapping instead, so we need to look them up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct LockMasks {
+    num_lock: KeyButMask,
+    scroll_lock: KeyButMask,
+}
+
+impl Default for LockMasks {
+    fn default() -> Self {
+        // This is what virtually every X server uses, but Scroll Lock is usually not bound at all
+        Self { num_lock: KeyButMask::MOD2, scroll_lock: KeyButMask::from(0u16) }
+    }
+}
+
+impl LockMasks {
+    /// Find the modifier bits the lock keys are bound to. This needs to be queried again when
+    /// receiving a `MappingNotify` event.
+    pub fn query(conn: &impl Connection) -> Result<Self, Box<dyn Error>> {
+        let setup = conn.setup();
+        let min_keycode = setup.min_keycode;
+        let keycode_count = setup.max_keycode - min_keycode + 1;
+
+        let modifier_mapping = conn.get_modifier_mapping()?.reply()?;
+        let keyboard_mapping = conn.get_keyboard_mapping(min_keycode, keycode_count)?.reply()?;
+        let keysyms_per_keycode = keyboard_mapping.keysyms_per_keycode as usize;
+        let keycodes_per_modifier = modifier_mapping.keycodes.len() / 8;
+        if keysyms_per_keycode == 0 || keycodes_per_modifier == 0 {
+            return Ok(Self::default());
+        }
+
+        let mut masks =
+            Self { num_lock: KeyButMask::from(0u16), scroll_lock: KeyButMask::from(0u16) };
+        for (modifier_idx, keycodes) in
+            modifier_mapping.keycodes.chunks(keycodes_per_modifier).enumerate()
+        {
+            let mask = KeyButMask::from(1u16 << modifier_idx);
+            for &keycode in keycodes.iter().filter(|&&keycode| keycode >= min_keycode) {
+                let start = (keycode - min_keycode) as usize * keysyms_per_keycode;
+                let keysyms = match keyboard_mapping.keysyms.get(start..start + keysyms_per_keycode)
+                {
+                    Some(keysyms) => keysyms,
+                    None => continue,
+                };
+
+                if keysyms.contains(&XK_NUM_LOCK) {
+                    masks.num_lock = mask;
+                }
+                if keysyms.contains(&XK_SCROLL_LOCK) {
+                    masks.scroll_lock = mask;
+                }
+            }
+        }
+
+        Ok(masks)
+    }
+
+    /// Every combination of the lock modifiers, including none of them. Key grabs only match the
+    /// exact modifier state, so a grab needs to be made for each of these to work regardless of
+    /// which lock keys are active.
+    pub fn combinations(&self) -> Vec<u16> {
+        let masks = [KeyButMask::LOCK, self.num_lock, self.scroll_lock];
+
+        let mut combinations = vec![0];
+        for mask in masks.iter().map(|&mask| u16::from(mask)).filter(|&mask| mask != 0) {
+            for i in 0..combinations.len() {
+                let combination = combinations[i] | mask;
+                if !combinations.contains(&combination) {
+                    combinations.push(combination);
+                }
+            }
+        }
+
+        combinations
+    }
+}
+
+/// The modifier bits for a shortcut's modifiers, matching the mapping used by [`key_mods()`].
+pub(super) fn shortcut_mods(modifiers: Modifiers) -> u16 {
+    let key_masks = [
+        (Modifiers::SHIFT, KeyButMask::SHIFT),
+        (Modifiers::CONTROL, KeyButMask::CONTROL),
+        (Modifiers::ALT, KeyButMask::MOD1),
+        (Modifiers::META, KeyButMask::MOD4),
+    ];
+
+    key_masks
+        .iter()
+        .filter(|(modifier, _)| modifiers.contains(*modifier))
+        .fold(0, |mods, (_, mask)| mods | u16::from(*mask))
+}
+
+/// The hardware keycode of the physical key with this code, the inverse of
+/// [`hardware_keycode_to_code()`].
+pub(super) fn code_to_hardware_keycode(code: Code) -> Option<u8> {
+    if code == Code::Unidentified {
+        return None;
+    }
+
+    (8..=255).find(|&keycode| hardware_keycode_to_code(keycode.into()) == code)
+}
+
 // Extracts the keyboard modifiers from, e.g., the `state` field of
 // `x11rb::protocol::xproto::ButtonPressEvent`
-pub(super) fn key_mods(mods: KeyButMask) -> Modifiers {
+pub(super) fn key_mods(mods: KeyButMask, lock_masks: LockMasks) -> Modifiers {
     let mut ret = Modifiers::default();
     let key_masks = [
         (KeyButMask::SHIFT, Modifiers::SHIFT),
@@ -371,36 +486,57 @@ pub(super) fn key_mods(mods: KeyButMask) -> Modifiers {
         // like a reasonable default for US keyboards, at least,
         // where the "windows" key seems to be MOD_MASK_4.
         (KeyButMask::MOD1, Modifiers::ALT),
-        (KeyButMask::MOD2, Modifiers::NUM_LOCK),
         (KeyButMask::MOD4, Modifiers::META),
         (KeyButMask::LOCK, Modifiers::CAPS_LOCK),
+        (lock_masks.num_lock, Modifiers::NUM_LOCK),
+        (lock_masks.scroll_lock, Modifiers::SCROLL_LOCK),
     ];
     for (mask, modifiers) in &key_masks {
-        if mods.contains(*mask) {
+        if u16::from(*mask) != 0 && mods.contains(*mask) {
             ret |= *modifiers;
         }
     }
     ret
 }
 
-pub(super) fn convert_key_press_event(key_press: &KeyPressEvent) -> KeyboardEvent {
+/// The key for a key event. Keys that produce characters on the US layout are translated with the
+/// user's layout instead, if xkbcommon is available. The others keep their US meaning, so Enter or
+/// the arrow keys are recognized regardless of the layout. Dead keys are composed on key presses
+/// if `compose` is set, see [`Xkb::key()`].
+fn layout_key(
+    code: Code, modifiers: Modifiers, keycode: u8, mask: KeyButMask, state: KeyState,
+    xkb: Option<&Xkb>, compose: bool,
+) -> Key {
+    let key = code_to_key(code, modifiers);
+    match (&key, xkb) {
+        (Key::Character(_), Some(xkb)) => xkb.key(keycode, mask, state, compose).unwrap_or(key),
+        _ => key,
+    }
+}
+
+/// Dead keys only compose if `compose` is set, which is while text input is active.
+pub(super) fn convert_key_press_event(
+    key_press: &KeyPressEvent, lock_masks: LockMasks, xkb: Option<&Xkb>, compose: bool,
+) -> KeyboardEvent {
     let hw_keycode = key_press.detail;
     let code = hardware_keycode_to_code(hw_keycode.into());
-    let modifiers = key_mods(key_press.state);
-    let key = code_to_key(code, modifiers);
-    let location = code_to_location(code);
+    let modifiers = key_mods(key_press.state, lock_masks);
     let state = KeyState::Down;
+    let key = layout_key(code, modifiers, hw_keycode, key_press.state, state, xkb, compose);
+    let location = code_to_location(code);
 
     KeyboardEvent { code, key, modifiers, location, state, repeat: false, is_composing: false }
 }
 
-pub(super) fn convert_key_release_event(key_release: &KeyReleaseEvent) -> KeyboardEvent {
+pub(super) fn convert_key_release_event(
+    key_release: &KeyReleaseEvent, lock_masks: LockMasks, xkb: Option<&Xkb>,
+) -> KeyboardEvent {
     let hw_keycode = key_release.detail;
     let code = hardware_keycode_to_code(hw_keycode.into());
-    let modifiers = key_mods(key_release.state);
-    let key = code_to_key(code, modifiers);
-    let location = code_to_location(code);
+    let modifiers = key_mods(key_release.state, lock_masks);
     let state = KeyState::Up;
+    let key = layout_key(code, modifiers, hw_keycode, key_release.state, state, xkb, false);
+    let location = code_to_location(code);
 
     KeyboardEvent { code, key, modifiers, location, state, repeat: false, is_composing: false }
 }
@@ -4,7 +4,24 @@ use xcb_connection::XcbConnection;
 mod window;
 pub use window::*;
 
+#[cfg(feature = "opengl")]
+mod gl_area;
+#[cfg(feature = "opengl")]
+pub(crate) use gl_area::GlAreaSurface;
+
+mod clipboard;
 mod cursor;
+mod drag_source;
+mod drop_target;
+mod errors;
 mod event_loop;
+mod event_loop_thread;
+mod event_loop_waker;
+mod global_shortcuts;
 mod keyboard;
+mod monitors;
+mod pointer_devices;
+mod sleep_monitor;
 mod visual_info;
+mod xkb;
+mod xsettings;
@@ -0,0 +1,127 @@
+use std::error::Error;
+
+use x11rb::connection::Connection;
+use x11rb::protocol::randr::{self, ConnectionExt as _, ModeFlag, NotifyMask};
+use x11rb::protocol::xproto::{Atom, AtomEnum, ConnectionExt as _, Window as XWindow};
+
+/// Queries monitor information through RandR, and lets us know when the monitor configuration
+/// changes.
+pub(super) struct Monitors;
+
+impl Monitors {
+    /// Returns `None` if the server doesn't support RandR 1.2, which is the first version that
+    /// exposes individual monitors (CRTCs).
+    pub fn new(conn: &impl Connection) -> Option<Self> {
+        conn.extension_information(randr::X11_EXTENSION_NAME).ok()??;
+
+        let version = conn.randr_query_version(1, 2).ok()?.reply().ok()?;
+        if (version.major_version, version.minor_version) < (1, 2) {
+            return None;
+        }
+
+        Some(Self)
+    }
+
+    /// Ask the server to send screen and CRTC change events whenever displays are connected,
+    /// disconnected, or reconfigured.
+    pub fn select_events(
+        &self, conn: &impl Connection, root: XWindow,
+    ) -> Result<(), Box<dyn Error>> {
+        conn.randr_select_input(root, NotifyMask::SCREEN_CHANGE | NotifyMask::CRTC_CHANGE)?;
+
+        Ok(())
+    }
+
+    /// Returns the refresh rate of the monitor showing the center of the window, if the window is
+    /// on any monitor.
+    pub fn refresh_rate(
+        &self, conn: &impl Connection, root: XWindow, window: XWindow,
+    ) -> Option<f64> {
+        let (resources, info) = self.window_crtc(conn, root, window)?;
+        let mode = resources.modes.iter().find(|mode| mode.id == info.mode)?;
+        mode_refresh_rate(mode)
+    }
+
+    /// Returns the contents of the `_ICC_PROFILE` property of the output showing the center of the
+    /// window, if the color management daemon has set one.
+    pub fn icc_profile(
+        &self, conn: &impl Connection, root: XWindow, window: XWindow, icc_profile_atom: Atom,
+    ) -> Option<Vec<u8>> {
+        let (_, info) = self.window_crtc(conn, root, window)?;
+        let output = *info.outputs.first()?;
+        let property = conn
+            .randr_get_output_property(
+                output,
+                icc_profile_atom,
+                AtomEnum::ANY,
+                0,
+                u32::MAX / 4,
+                false,
+                false,
+            )
+            .ok()?
+            .reply()
+            .ok()?;
+
+        if property.data.is_empty() {
+            None
+        } else {
+            Some(property.data)
+        }
+    }
+
+    /// Finds the enabled CRTC showing the center of the window.
+    fn window_crtc(
+        &self, conn: &impl Connection, root: XWindow, window: XWindow,
+    ) -> Option<(randr::GetScreenResourcesCurrentReply, randr::GetCrtcInfoReply)> {
+        let geometry = conn.get_geometry(window).ok()?.reply().ok()?;
+        let center = conn
+            .translate_coordinates(
+                window,
+                root,
+                (geometry.width / 2) as i16,
+                (geometry.height / 2) as i16,
+            )
+            .ok()?
+            .reply()
+            .ok()?;
+        let (x, y) = (i32::from(center.dst_x), i32::from(center.dst_y));
+
+        let resources = conn.randr_get_screen_resources_current(root).ok()?.reply().ok()?;
+        for &crtc in &resources.crtcs {
+            let info =
+                conn.randr_get_crtc_info(crtc, resources.config_timestamp).ok()?.reply().ok()?;
+            // Disabled CRTCs don't have a mode
+            if info.mode == x11rb::NONE {
+                continue;
+            }
+
+            let (crtc_x, crtc_y) = (i32::from(info.x), i32::from(info.y));
+            let contains_window = x >= crtc_x
+                && x < crtc_x + i32::from(info.width)
+                && y >= crtc_y
+                && y < crtc_y + i32::from(info.height);
+            if contains_window {
+                return Some((resources, info));
+            }
+        }
+
+        None
+    }
+}
+
+fn mode_refresh_rate(mode: &randr::ModeInfo) -> Option<f64> {
+    let mut vtotal = f64::from(mode.vtotal);
+    if mode.mode_flags.contains(ModeFlag::DOUBLE_SCAN) {
+        vtotal *= 2.0;
+    }
+    if mode.mode_flags.contains(ModeFlag::INTERLACE) {
+        vtotal /= 2.0;
+    }
+
+    if mode.htotal == 0 || vtotal == 0.0 {
+        return None;
+    }
+
+    Some(f64::from(mode.dot_clock) / (f64::from(mode.htotal) * vtotal))
+}
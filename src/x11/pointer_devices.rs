@@ -0,0 +1,95 @@
+use std::cell::RefCell;
+use std::collections::hash_map::{Entry, HashMap};
+use std::error::Error;
+
+use x11rb::connection::Connection;
+use x11rb::protocol::xinput::{
+    self, ConnectionExt as _, DeviceClassData, DeviceId, TouchMode, XIDeviceInfo,
+};
+
+use crate::{PointerDevice, PointerType};
+
+/// Keeps track of the XInput2 pointer devices we've seen events from.
+///
+/// XInput2 events only carry the id of the physical (slave) device that generated them, so the
+/// device type is looked up lazily the first time a device is seen and then cached.
+pub(super) struct PointerDevices {
+    types: RefCell<HashMap<DeviceId, PointerType>>,
+}
+
+impl PointerDevices {
+    /// Returns `None` if the server doesn't support XInput 2.0. We ask for version 2.2 because the
+    /// server only reports touch classes to clients that support them.
+    pub fn new(conn: &impl Connection) -> Option<Self> {
+        let version = conn.xinput_xi_query_version(2, 2).ok()?.reply().ok()?;
+        if version.major_version < 2 {
+            return None;
+        }
+
+        Some(Self { types: RefCell::new(HashMap::new()) })
+    }
+
+    pub fn device(&self, conn: &impl Connection, id: DeviceId) -> PointerDevice {
+        // PANIC: we never call out to user code while the cache is borrowed
+        let mut types = self.types.borrow_mut();
+
+        let pointer_type = match types.entry(id) {
+            Entry::Occupied(entry) => *entry.get(),
+            Entry::Vacant(entry) => {
+                *entry.insert(query_pointer_type(conn, id).unwrap_or(PointerType::Unknown))
+            }
+        };
+
+        PointerDevice { id: id as u64, pointer_type }
+    }
+
+    /// Called when a device has been plugged in. Device ids are reused, so anything we knew about
+    /// a previous device with the same id is discarded.
+    pub fn added(&self, conn: &impl Connection, id: DeviceId) -> PointerDevice {
+        self.types.borrow_mut().remove(&id);
+
+        self.device(conn, id)
+    }
+
+    /// Called when a device has been unplugged. The device can no longer be queried at this
+    /// point, so this returns the type we last knew about.
+    pub fn removed(&self, id: DeviceId) -> PointerDevice {
+        let pointer_type = self.types.borrow_mut().remove(&id).unwrap_or(PointerType::Unknown);
+
+        PointerDevice { id: id as u64, pointer_type }
+    }
+}
+
+fn query_pointer_type(conn: &impl Connection, id: DeviceId) -> Result<PointerType, Box<dyn Error>> {
+    let reply = conn.xinput_xi_query_device(id)?.reply()?;
+
+    Ok(reply.infos.iter().find(|info| info.deviceid == id).map_or(PointerType::Unknown, classify))
+}
+
+/// XInput2 doesn't have a notion of device types, so this is based on the touch class of the
+/// device and otherwise on the name the driver gave it.
+fn classify(info: &XIDeviceInfo) -> PointerType {
+    for class in &info.classes {
+        if let DeviceClassData::Touch(touch) = &class.data {
+            return if touch.mode == TouchMode::DIRECT {
+                PointerType::Touchscreen
+            } else {
+                PointerType::Touchpad
+            };
+        }
+    }
+
+    let name = String::from_utf8_lossy(&info.name).to_lowercase();
+    if ["touchpad", "trackpad"].iter().any(|pattern| name.contains(pattern)) {
+        PointerType::Touchpad
+    } else if ["stylus", "pen", "eraser", "tablet", "wacom"]
+        .iter()
+        .any(|pattern| name.contains(pattern))
+    {
+        PointerType::Tablet
+    } else if info.type_ == xinput::DeviceType::SLAVE_POINTER {
+        PointerType::Mouse
+    } else {
+        PointerType::Unknown
+    }
+}
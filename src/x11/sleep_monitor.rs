@@ -0,0 +1,550 @@
+use std::convert::TryInto;
+use std::io::{self, Read, Write};
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+
+use crate::Event;
+
+const DEFAULT_SYSTEM_BUS_PATH: &str = "/var/run/dbus/system_bus_socket";
+
+const MATCH_RULE: &str = "type='signal',sender='org.freedesktop.login1',\
+                          interface='org.freedesktop.login1.Manager',member='PrepareForSleep'";
+
+/// How long a write to the bus may wait for the socket's send buffer to drain.
+const WRITE_TIMEOUT_MS: i32 = 1000;
+
+const MESSAGE_TYPE_METHOD_CALL: u8 = 1;
+const MESSAGE_TYPE_SIGNAL: u8 = 4;
+
+const FIELD_PATH: u8 = 1;
+const FIELD_INTERFACE: u8 = 2;
+const FIELD_MEMBER: u8 = 3;
+const FIELD_DESTINATION: u8 = 6;
+const FIELD_SIGNATURE: u8 = 8;
+
+/// Listens for systemd-logind's `PrepareForSleep` signal on the DBus system bus to find out when
+/// the system is about to be suspended or has just resumed. This speaks just enough of the DBus
+/// wire protocol to subscribe to that one signal, so we don't need to link against libdbus.
+pub(super) struct SleepMonitor {
+    stream: UnixStream,
+    /// Bytes received from the bus that don't form a complete message yet.
+    buffer: Vec<u8>,
+    /// Whether the bus has accepted our credentials. Until then the bus sends line based
+    /// authentication replies instead of messages.
+    authenticated: bool,
+}
+
+impl SleepMonitor {
+    /// Returns `None` if the system bus is not reachable. This is expected on systems that don't
+    /// use DBus, in which case we simply won't emit any power events.
+    ///
+    /// This doesn't wait for the bus to reply, so a slow bus can't hold up opening a window. The
+    /// authentication is finished in [`read_events()`][Self::read_events()] once the bus replies.
+    pub fn connect() -> Option<Self> {
+        let path = system_bus_path()?;
+        let stream = UnixStream::connect(path).ok()?;
+
+        Self::new(stream).ok()
+    }
+
+    fn new(stream: UnixStream) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        let mut monitor = Self { stream, buffer: Vec::new(), authenticated: false };
+
+        // The external mechanism authenticates us using the socket's peer credentials, and it
+        // wants our user ID as a hex encoded decimal string
+        let uid = unsafe { libc::getuid() };
+        monitor.write_all(&auth_request(uid))?;
+
+        Ok(monitor)
+    }
+
+    /// Handle the bus's reply to our authentication request if it has been received, and
+    /// subscribe to the signal once we've been accepted.
+    fn authenticate(&mut self) -> io::Result<()> {
+        let line_end = match self.buffer.windows(2).position(|window| window == b"\r\n") {
+            Some(line_end) => line_end,
+            None => return Ok(()),
+        };
+        if !self.buffer.starts_with(b"OK ") {
+            let response = String::from_utf8_lossy(&self.buffer[..line_end]).into_owned();
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, response));
+        }
+        self.buffer.drain(..line_end + 2);
+        self.authenticated = true;
+
+        // The replies to these calls are simply skipped over when reading signals
+        let mut messages = b"BEGIN\r\n".to_vec();
+        messages.extend(bus_method_call(1, "Hello", None));
+        messages.extend(bus_method_call(2, "AddMatch", Some(MATCH_RULE)));
+        self.write_all(&messages)
+    }
+
+    /// Like [`Write::write_all()`], but waits for the nonblocking socket to become writable
+    /// instead of failing when the send buffer is full. The bus only gets a bounded amount of
+    /// time to make room so a stalled bus can't hang the event loop.
+    fn write_all(&mut self, mut data: &[u8]) -> io::Result<()> {
+        while !data.is_empty() {
+            match self.stream.write(data) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(len) => data = &data[len..],
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    wait_until_writable(self.stream.as_raw_fd())?
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Read all pending messages from the bus and translate any `PrepareForSleep` signals into
+    /// power events. An error means the connection to the bus has been lost.
+    pub fn read_events(&mut self) -> io::Result<Vec<Event>> {
+        let mut chunk = [0u8; 4096];
+        loop {
+            match self.stream.read(&mut chunk) {
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(len) => self.buffer.extend_from_slice(&chunk[..len]),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
+        }
+
+        if !self.authenticated {
+            self.authenticate()?;
+            if !self.authenticated {
+                return Ok(Vec::new());
+            }
+        }
+
+        let mut events = Vec::new();
+        let mut consumed = 0;
+        while let Some(length) = message_length(&self.buffer[consumed..]) {
+            if self.buffer.len() - consumed < length {
+                break;
+            }
+
+            if let Some(going_to_sleep) =
+                parse_prepare_for_sleep(&self.buffer[consumed..][..length])
+            {
+                events.push(if going_to_sleep { Event::PowerSuspend } else { Event::PowerResume });
+            }
+
+            consumed += length;
+        }
+        self.buffer.drain(..consumed);
+
+        Ok(events)
+    }
+}
+
+impl AsRawFd for SleepMonitor {
+    fn as_raw_fd(&self) -> RawFd {
+        self.stream.as_raw_fd()
+    }
+}
+
+/// Wait for up to [`WRITE_TIMEOUT_MS`] for `fd` to accept more data.
+fn wait_until_writable(fd: RawFd) -> io::Result<()> {
+    let mut pollfd = libc::pollfd { fd, events: libc::POLLOUT, revents: 0 };
+    match unsafe { libc::poll(&mut pollfd, 1, WRITE_TIMEOUT_MS) } {
+        0 => Err(io::ErrorKind::TimedOut.into()),
+        -1 => {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                Ok(())
+            } else {
+                Err(err)
+            }
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Find the path of the system bus socket. Only the `unix:path=` transport is supported.
+fn system_bus_path() -> Option<String> {
+    let address = match std::env::var("DBUS_SYSTEM_BUS_ADDRESS") {
+        Ok(address) => address,
+        Err(_) => return Some(String::from(DEFAULT_SYSTEM_BUS_PATH)),
+    };
+
+    address.split(';').filter_map(|address| address.strip_prefix("unix:")).find_map(|params| {
+        params.split(',').find_map(|param| param.strip_prefix("path=")).map(unescape_address)
+    })
+}
+
+/// Undo the `%xx` escaping used in DBus addresses.
+fn unescape_address(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = if bytes[i] == b'%' {
+            value.get(i + 1..i + 3).and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        } else {
+            None
+        };
+
+        match escaped {
+            Some(byte) => {
+                result.push(byte);
+                i += 3;
+            }
+            None => {
+                result.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&result).into_owned()
+}
+
+/// The `AUTH EXTERNAL` command for the given user ID, which is sent as the hex encoded decimal
+/// representation of the ID. This starts with the nul byte every connection has to start with.
+fn auth_request(uid: u32) -> Vec<u8> {
+    let uid_hex: String = uid.to_string().bytes().map(|byte| format!("{:02x}", byte)).collect();
+    format!("\0AUTH EXTERNAL {}\r\n", uid_hex).into_bytes()
+}
+
+/// Serialize a method call to the bus daemon itself, optionally with a single string argument.
+fn bus_method_call(serial: u32, member: &str, argument: Option<&str>) -> Vec<u8> {
+    let mut body = Vec::new();
+    if let Some(argument) = argument {
+        write_string(&mut body, argument);
+    }
+
+    let mut fields = Vec::new();
+    // The fields array starts at offset 16 within the message, which is already 8-byte aligned
+    write_field(&mut fields, FIELD_PATH, b'o', |buf| write_string(buf, "/org/freedesktop/DBus"));
+    write_field(&mut fields, FIELD_INTERFACE, b's', |buf| {
+        write_string(buf, "org.freedesktop.DBus")
+    });
+    write_field(&mut fields, FIELD_MEMBER, b's', |buf| write_string(buf, member));
+    write_field(&mut fields, FIELD_DESTINATION, b's', |buf| {
+        write_string(buf, "org.freedesktop.DBus")
+    });
+    if argument.is_some() {
+        write_field(&mut fields, FIELD_SIGNATURE, b'g', |buf| {
+            buf.extend_from_slice(&[1, b's', 0]);
+        });
+    }
+
+    let mut message = vec![b'l', MESSAGE_TYPE_METHOD_CALL, 0, 1];
+    message.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    message.extend_from_slice(&serial.to_le_bytes());
+    message.extend_from_slice(&(fields.len() as u32).to_le_bytes());
+    message.extend_from_slice(&fields);
+    pad_to(&mut message, 8);
+    message.extend_from_slice(&body);
+
+    message
+}
+
+/// Write a header field, which is a `(byte, variant)` struct. `buf` must start at an 8-byte
+/// aligned offset within the message.
+fn write_field(buf: &mut Vec<u8>, code: u8, signature: u8, write_value: impl FnOnce(&mut Vec<u8>)) {
+    pad_to(buf, 8);
+    buf.extend_from_slice(&[code, 1, signature, 0]);
+    write_value(buf);
+}
+
+fn write_string(buf: &mut Vec<u8>, value: &str) {
+    pad_to(buf, 4);
+    buf.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    buf.extend_from_slice(value.as_bytes());
+    buf.push(0);
+}
+
+fn pad_to(buf: &mut Vec<u8>, alignment: usize) {
+    while buf.len() % alignment != 0 {
+        buf.push(0);
+    }
+}
+
+fn align(offset: usize, alignment: usize) -> usize {
+    (offset + alignment - 1) / alignment * alignment
+}
+
+/// A minimal reader for the bits of the wire format we need to parse, in either byte order.
+struct MessageReader<'a> {
+    data: &'a [u8],
+    little_endian: bool,
+}
+
+impl MessageReader<'_> {
+    fn u32(&self, offset: usize) -> Option<u32> {
+        let bytes = self.data.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if self.little_endian { u32::from_le_bytes(bytes) } else { u32::from_be_bytes(bytes) })
+    }
+
+    /// Read a string or object path. Returns the string and the offset past its nul terminator.
+    fn string(&self, offset: usize) -> Option<(&[u8], usize)> {
+        let offset = align(offset, 4);
+        let len = self.u32(offset)? as usize;
+        let value = self.data.get(offset + 4..offset + 4 + len)?;
+        Some((value, offset + 4 + len + 1))
+    }
+
+    /// Read a signature. Returns the signature and the offset past its nul terminator.
+    fn signature(&self, offset: usize) -> Option<(&[u8], usize)> {
+        let len = *self.data.get(offset)? as usize;
+        let value = self.data.get(offset + 1..offset + 1 + len)?;
+        Some((value, offset + 1 + len + 1))
+    }
+}
+
+/// Returns the total length of the message at the start of `data`, or `None` if not enough of
+/// the message has been received yet to tell.
+fn message_length(data: &[u8]) -> Option<usize> {
+    let reader = MessageReader { data, little_endian: *data.first()? == b'l' };
+    let body_len = reader.u32(4)? as usize;
+    let fields_len = reader.u32(12)? as usize;
+
+    Some(align(16 + fields_len, 8) + body_len)
+}
+
+/// Returns the signal's argument if `message` is logind's `PrepareForSleep` signal. The argument
+/// is `true` when the system is about to go to sleep, and `false` when it has woken up again.
+fn parse_prepare_for_sleep(message: &[u8]) -> Option<bool> {
+    let reader = MessageReader { data: message, little_endian: message[0] == b'l' };
+    if message[1] != MESSAGE_TYPE_SIGNAL {
+        return None;
+    }
+
+    let fields_end = 16 + reader.u32(12)? as usize;
+    let (mut interface, mut member, mut signature) = (None, None, None);
+    let mut offset = 16;
+    while offset < fields_end {
+        offset = align(offset, 8);
+        let code = *message.get(offset)?;
+        let (field_signature, value_offset) = reader.signature(offset + 1)?;
+        offset = match field_signature {
+            b"s" | b"o" => {
+                let (value, end) = reader.string(value_offset)?;
+                match code {
+                    FIELD_INTERFACE => interface = Some(value),
+                    FIELD_MEMBER => member = Some(value),
+                    _ => (),
+                }
+                end
+            }
+            b"g" => {
+                let (value, end) = reader.signature(value_offset)?;
+                if code == FIELD_SIGNATURE {
+                    signature = Some(value);
+                }
+                end
+            }
+            b"u" => align(value_offset, 4) + 4,
+            // The specification doesn't define any other field types
+            _ => return None,
+        };
+    }
+
+    let is_prepare_for_sleep = interface == Some(&b"org.freedesktop.login1.Manager"[..])
+        && member == Some(&b"PrepareForSleep"[..])
+        && signature == Some(&b"b"[..]);
+    if !is_prepare_for_sleep {
+        return None;
+    }
+
+    // Booleans are marshalled as 32-bit integers
+    reader.u32(align(fields_end, 8)).map(|value| value != 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Serialize a signal the way the bus would send it.
+    fn signal(serial: u32, interface: &str, member: &str, argument: Option<bool>) -> Vec<u8> {
+        let mut body = Vec::new();
+        if let Some(argument) = argument {
+            body.extend_from_slice(&(argument as u32).to_le_bytes());
+        }
+
+        let mut fields = Vec::new();
+        write_field(&mut fields, FIELD_PATH, b'o', |buf| {
+            write_string(buf, "/org/freedesktop/login1")
+        });
+        write_field(&mut fields, FIELD_INTERFACE, b's', |buf| write_string(buf, interface));
+        write_field(&mut fields, FIELD_MEMBER, b's', |buf| write_string(buf, member));
+        if argument.is_some() {
+            write_field(&mut fields, FIELD_SIGNATURE, b'g', |buf| {
+                buf.extend_from_slice(&[1, b'b', 0]);
+            });
+        }
+
+        let mut message = vec![b'l', MESSAGE_TYPE_SIGNAL, 0, 1];
+        message.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        message.extend_from_slice(&serial.to_le_bytes());
+        message.extend_from_slice(&(fields.len() as u32).to_le_bytes());
+        message.extend_from_slice(&fields);
+        pad_to(&mut message, 8);
+        message.extend_from_slice(&body);
+
+        message
+    }
+
+    fn prepare_for_sleep(going_to_sleep: bool) -> Vec<u8> {
+        let interface = "org.freedesktop.login1.Manager";
+        signal(7, interface, "PrepareForSleep", Some(going_to_sleep))
+    }
+
+    /// Read every string header field of a message, in order.
+    fn string_fields(message: &[u8]) -> Vec<(u8, String)> {
+        let reader = MessageReader { data: message, little_endian: true };
+        let fields_end = 16 + reader.u32(12).unwrap() as usize;
+        let mut fields = Vec::new();
+        let mut offset = 16;
+        while offset < fields_end {
+            offset = align(offset, 8);
+            let code = message[offset];
+            let (signature, value_offset) = reader.signature(offset + 1).unwrap();
+            offset = if signature == b"g" {
+                reader.signature(value_offset).unwrap().1
+            } else {
+                let (value, end) = reader.string(value_offset).unwrap();
+                fields.push((code, String::from_utf8(value.to_vec()).unwrap()));
+                end
+            };
+        }
+
+        fields
+    }
+
+    #[test]
+    fn unescapes_addresses() {
+        assert_eq!(unescape_address("/run/dbus/system_bus_socket"), "/run/dbus/system_bus_socket");
+        assert_eq!(unescape_address("/tmp/bus%20socket%2c1"), "/tmp/bus socket,1");
+        // Invalid and truncated escapes are kept as they are
+        assert_eq!(unescape_address("/tmp/%zz"), "/tmp/%zz");
+        assert_eq!(unescape_address("/tmp/%2"), "/tmp/%2");
+    }
+
+    #[test]
+    fn encodes_the_uid_for_external_auth() {
+        assert_eq!(auth_request(1000), b"\0AUTH EXTERNAL 31303030\r\n");
+        assert_eq!(auth_request(0), b"\0AUTH EXTERNAL 30\r\n");
+    }
+
+    #[test]
+    fn marshals_bus_method_calls() {
+        let hello = bus_method_call(1, "Hello", None);
+        assert_eq!(&hello[..4], &[b'l', MESSAGE_TYPE_METHOD_CALL, 0, 1]);
+        assert_eq!(message_length(&hello), Some(hello.len()));
+        assert_eq!(
+            string_fields(&hello),
+            [
+                (FIELD_PATH, String::from("/org/freedesktop/DBus")),
+                (FIELD_INTERFACE, String::from("org.freedesktop.DBus")),
+                (FIELD_MEMBER, String::from("Hello")),
+                (FIELD_DESTINATION, String::from("org.freedesktop.DBus")),
+            ]
+        );
+
+        let add_match = bus_method_call(2, "AddMatch", Some(MATCH_RULE));
+        assert_eq!(message_length(&add_match), Some(add_match.len()));
+        let reader = MessageReader { data: &add_match, little_endian: true };
+        assert_eq!(reader.u32(8), Some(2));
+        let body_start = add_match.len() - reader.u32(4).unwrap() as usize;
+        assert_eq!(body_start % 8, 0);
+        assert_eq!(reader.string(body_start), Some((MATCH_RULE.as_bytes(), add_match.len())));
+    }
+
+    #[test]
+    fn measures_partial_messages() {
+        let message = prepare_for_sleep(true);
+
+        assert_eq!(message_length(&message), Some(message.len()));
+        assert_eq!(message_length(&message[..16]), Some(message.len()));
+        assert_eq!(message_length(&message[..15]), None);
+        assert_eq!(message_length(&[]), None);
+    }
+
+    #[test]
+    fn parses_prepare_for_sleep() {
+        assert_eq!(parse_prepare_for_sleep(&prepare_for_sleep(true)), Some(true));
+        assert_eq!(parse_prepare_for_sleep(&prepare_for_sleep(false)), Some(false));
+
+        let other_member = signal(3, "org.freedesktop.login1.Manager", "SessionNew", None);
+        assert_eq!(parse_prepare_for_sleep(&other_member), None);
+        let other_interface = signal(3, "org.example.Manager", "PrepareForSleep", Some(true));
+        assert_eq!(parse_prepare_for_sleep(&other_interface), None);
+        let method_call = bus_method_call(3, "PrepareForSleep", None);
+        assert_eq!(parse_prepare_for_sleep(&method_call), None);
+
+        // A message cut off in the middle of its header fields
+        let message = prepare_for_sleep(true);
+        assert_eq!(parse_prepare_for_sleep(&message[..40]), None);
+    }
+
+    #[test]
+    fn authenticates_and_reads_signals() {
+        let (client, mut bus) = UnixStream::pair().unwrap();
+        let mut monitor = SleepMonitor::new(client).unwrap();
+
+        let uid = unsafe { libc::getuid() };
+        let mut request = vec![0; auth_request(uid).len()];
+        bus.read_exact(&mut request).unwrap();
+        assert_eq!(request, auth_request(uid));
+
+        // Nothing happens until the bus replies
+        assert!(monitor.read_events().unwrap().is_empty());
+        assert!(!monitor.authenticated);
+
+        bus.write_all(b"OK 0123456789abcdef\r\n").unwrap();
+        assert!(monitor.read_events().unwrap().is_empty());
+        assert!(monitor.authenticated);
+
+        let mut begin = [0; 7];
+        bus.read_exact(&mut begin).unwrap();
+        assert_eq!(&begin, b"BEGIN\r\n");
+
+        // A signal split over two reads, followed by an unrelated one
+        let mut messages = prepare_for_sleep(true);
+        messages.extend(signal(8, "org.freedesktop.login1.Manager", "SessionNew", None));
+        messages.extend(prepare_for_sleep(false));
+        bus.write_all(&messages[..20]).unwrap();
+        assert!(monitor.read_events().unwrap().is_empty());
+        bus.write_all(&messages[20..]).unwrap();
+        let events = monitor.read_events().unwrap();
+        assert!(matches!(events[..], [Event::PowerSuspend, Event::PowerResume]));
+
+        drop(bus);
+        assert!(monitor.read_events().is_err());
+    }
+
+    #[test]
+    fn waits_for_a_full_send_buffer() {
+        let (client, mut bus) = UnixStream::pair().unwrap();
+        let mut monitor = SleepMonitor::new(client).unwrap();
+
+        // Far more than fits in the socket's send buffer, so the write has to wait for the bus
+        let data = vec![0xab; 4 * 1024 * 1024];
+        let expected_len = auth_request(unsafe { libc::getuid() }).len() + data.len();
+        let reader = std::thread::spawn(move || {
+            let mut received = vec![0; expected_len];
+            bus.read_exact(&mut received).unwrap();
+            received
+        });
+
+        monitor.write_all(&data).unwrap();
+        let received = reader.join().unwrap();
+        assert!(received.ends_with(&data));
+    }
+
+    #[test]
+    fn fails_when_the_bus_rejects_us() {
+        let (client, mut bus) = UnixStream::pair().unwrap();
+        let mut monitor = SleepMonitor::new(client).unwrap();
+
+        bus.write_all(b"REJECTED EXTERNAL\r\n").unwrap();
+        let err = monitor.read_events().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+    }
+}
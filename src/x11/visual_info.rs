@@ -1,4 +1,5 @@
 use crate::x11::xcb_connection::XcbConnection;
+use crate::X11Visual;
 use std::error::Error;
 use x11rb::connection::Connection;
 use x11rb::protocol::xproto::{
@@ -18,10 +19,23 @@ pub(super) struct WindowVisualConfig {
 // TODO: make visual negotiation actually check all of a visual's parameters
 impl WindowVisualConfig {
     #[cfg(feature = "opengl")]
-    pub fn find_best_visual_config_for_gl(
-        connection: &XcbConnection, gl_config: Option<crate::gl::GlConfig>,
+    pub fn find_visual_config_for_gl(
+        connection: &XcbConnection, parent: x11rb::protocol::xproto::Window, visual: X11Visual,
+        gl_config: Option<crate::gl::GlConfig>,
     ) -> Result<Self, Box<dyn Error>> {
-        let Some(gl_config) = gl_config else { return Self::find_best_visual_config(connection) };
+        let Some(gl_config) = gl_config else {
+            return Self::find_visual_config(connection, visual);
+        };
+
+        // With an explicit visual the visual is picked first, and the context then uses an
+        // fbconfig that matches it
+        if visual != X11Visual::PreferArgb {
+            let mut config = Self::find_visual_config(connection, visual)?;
+            config.fb_config =
+                fb_config_for_visual(connection, parent, config.visual_id, gl_config);
+
+            return Ok(config);
+        }
 
         let result = unsafe {
             crate::gl::platform::GlContext::get_fb_config_and_visual(connection.dpy, gl_config)
@@ -41,20 +55,60 @@ impl WindowVisualConfig {
         if let Some(result) = result {
             Ok(result)
         } else {
-            Self::find_best_visual_config(connection)
+            Self::find_visual_config(connection, visual)
         }
     }
 
-    pub fn find_best_visual_config(connection: &XcbConnection) -> Result<Self, Box<dyn Error>> {
-        match find_visual_for_depth(connection.screen(), 32) {
-            None => Ok(Self::copy_from_parent()),
-            Some(visual_id) => Ok(Self {
-                #[cfg(feature = "opengl")]
-                fb_config: None,
-                visual_id,
-                visual_depth: 32,
-                color_map: Some(create_color_map(connection, visual_id)?),
+    pub fn find_visual_config(
+        connection: &XcbConnection, visual: X11Visual,
+    ) -> Result<Self, Box<dyn Error>> {
+        let screen = connection.screen();
+        match visual {
+            X11Visual::PreferArgb => match find_visual_for_depth(screen, 32) {
+                Some(visual_id) => Self::with_visual(connection, visual_id, 32),
+                None => Ok(Self::copy_from_parent()),
+            },
+            X11Visual::RequireArgb => match find_visual_for_depth(screen, 32) {
+                Some(visual_id) => Self::with_visual(connection, visual_id, 32),
+                None => Err("the X server doesn't have a 32-bit ARGB visual".into()),
+            },
+            X11Visual::Parent => Ok(Self::copy_from_parent()),
+            X11Visual::TrueColor24 => match find_visual_for_depth(screen, 24) {
+                Some(visual_id) => Self::with_visual(connection, visual_id, 24),
+                None => Ok(Self::copy_from_parent()),
+            },
+        }
+    }
+
+    fn with_visual(
+        connection: &XcbConnection, visual_id: Visualid, visual_depth: u8,
+    ) -> Result<Self, Box<dyn Error>> {
+        Ok(Self {
+            #[cfg(feature = "opengl")]
+            fb_config: None,
+            visual_id,
+            visual_depth,
+            color_map: Some(create_color_map(connection, visual_id)?),
+        })
+    }
+
+    /// Use the parent window's visual instead, after the window couldn't be created with this one.
+    /// The color map created for this visual is freed.
+    pub fn parent_fallback(
+        self, connection: &XcbConnection,
+        #[cfg(feature = "opengl")] parent: x11rb::protocol::xproto::Window,
+        #[cfg(feature = "opengl")] gl_config: Option<crate::gl::GlConfig>,
+    ) -> Self {
+        if let Some(color_map) = self.color_map {
+            let _ = connection.conn.free_colormap(color_map);
+        }
+
+        Self {
+            #[cfg(feature = "opengl")]
+            fb_config: gl_config.and_then(|gl_config| {
+                fb_config_for_visual(connection, parent, COPY_FROM_PARENT, gl_config)
             }),
+            ..Self::copy_from_parent()
         }
     }
 
@@ -69,6 +123,29 @@ impl WindowVisualConfig {
     }
 }
 
+/// An fbconfig for an OpenGL context on a window with this visual. For windows that copy their
+/// parent's visual, this looks up the parent window's visual.
+#[cfg(feature = "opengl")]
+fn fb_config_for_visual(
+    connection: &XcbConnection, parent: x11rb::protocol::xproto::Window, visual_id: Visualid,
+    gl_config: crate::gl::GlConfig,
+) -> Option<crate::gl::x11::FbConfig> {
+    let visual_id = if visual_id == COPY_FROM_PARENT {
+        connection.conn.get_window_attributes(parent).ok()?.reply().ok()?.visual
+    } else {
+        visual_id
+    };
+
+    unsafe {
+        crate::gl::platform::GlContext::get_fb_config_for_visual(
+            connection.dpy,
+            gl_config,
+            visual_id,
+        )
+        .ok()
+    }
+}
+
 // For this 32-bit depth to work, you also need to define a color map and set a border
 // pixel: https://cgit.freedesktop.org/xorg/xserver/tree/dix/window.c#n818
 fn create_color_map(
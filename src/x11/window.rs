@@ -1,48 +1,134 @@
-use std::cell::Cell;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
 use std::error::Error;
 use std::ffi::c_void;
+use std::rc::Rc;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::{self, sync_channel, Receiver, SyncSender};
 use std::sync::Arc;
-use std::thread;
+use std::time::Duration;
 
+use keyboard_types::{KeyboardEvent, Modifiers};
 use raw_window_handle::{
     HasRawDisplayHandle, HasRawWindowHandle, RawDisplayHandle, RawWindowHandle, XlibDisplayHandle,
     XlibWindowHandle,
 };
 
 use x11rb::connection::Connection;
+use x11rb::errors::ReplyError;
+use x11rb::protocol::shape::{ConnectionExt as _, SK, SO};
+use x11rb::protocol::xfixes::{ConnectionExt as _, SelectionEventMask};
+use x11rb::protocol::xinput::{self, ConnectionExt as _};
 use x11rb::protocol::xproto::{
-    AtomEnum, ChangeWindowAttributesAux, ConfigureWindowAux, ConnectionExt as _, CreateGCAux,
-    CreateWindowAux, EventMask, PropMode, Visualid, Window as XWindow, WindowClass,
+    AtomEnum, ChangeWindowAttributesAux, ClientMessageEvent, ClipOrdering, ConfigureWindowAux,
+    ConnectionExt as _, CreateWindowAux, EventMask, GrabMode, GrabStatus, Gravity, InputFocus,
+    KeyButMask, Rectangle, Timestamp, Visualid, Window as XWindow, WindowClass,
 };
-use x11rb::wrapper::ConnectionExt as _;
+use x11rb::protocol::ErrorKind;
+use x11rb::{COPY_FROM_PARENT, CURRENT_TIME};
 
+use super::clipboard::ClipboardReader;
+use super::drag_source::DragSource;
+use super::drop_target::DropTarget;
+use super::errors::{report_x11_error, CheckRequest};
 use super::XcbConnection;
+use crate::platform_error::{report_platform_error, PlatformError};
 use crate::{
-    Event, MouseCursor, Point, Size, WindowEvent, WindowHandler, WindowInfo, WindowOpenOptions,
-    WindowScalePolicy,
+    ClipboardFormat, DragData, Event, FrameStats, GlobalShortcutError, HapticPattern, InputEvents,
+    KeyCombo, MouseCursor, PhySize, Point, Rect, ResizeSource, Size, WindowEvent, WindowHandler,
+    WindowInfo, WindowOpenOptions, WindowScalePolicy, X11Visual,
 };
 
+use crate::accelerator::Accelerators;
+use crate::frame_stats::FrameCounter;
 #[cfg(feature = "opengl")]
-use crate::gl::{platform, GlContext};
+use crate::gl::{platform, GlArea, GlConfig, GlContext, GlContextCell, GlError};
+#[cfg(feature = "virtual-clock")]
+use crate::virtual_clock::{VirtualClock, VirtualFrames};
 use crate::x11::event_loop::EventLoop;
+use crate::x11::event_loop_thread::{self, EventLoopThread, LoopHandle};
+use crate::x11::event_loop_waker::EventLoopWaker;
 use crate::x11::visual_info::WindowVisualConfig;
 
+/// How long [`WindowHandle::close()`] waits for the window thread to shut down. This only guards
+/// against the window thread being blocked, normally shutting down takes at most a frame.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How long [`WindowHandle::send_key_event()`] waits for the handler. Key events the handler
+/// doesn't get to in time are left to the host.
+const KEY_EVENT_TIMEOUT: Duration = Duration::from_millis(250);
+
 pub struct WindowHandle {
     raw_window_handle: Option<RawWindowHandle>,
     close_requested: SyncSender<()>,
+    /// Disconnects once the window has been destroyed.
+    window_closed: Receiver<()>,
+    /// Wakes up the window's event loop so it handles close requests right away.
+    waker: Option<Arc<EventLoopWaker>>,
     is_open: Arc<AtomicBool>,
 }
 
 impl WindowHandle {
+    /// Ask the window thread to close the window, and wait for the window to be destroyed. This
+    /// never blocks for longer than [`CLOSE_TIMEOUT`], so a window thread that's stuck can't take
+    /// the host down with it. Closing a window that has already been closed does nothing.
+    ///
+    /// A child of another baseview window runs on its parent's thread. When that thread closes the
+    /// window, for instance from the parent's handler, the window is closed once the current
+    /// callback has returned, so this doesn't wait for it.
     pub fn close(&mut self) {
-        self.close_requested.send(()).ok();
+        // This fails if a close has already been requested, or if the window thread has exited
+        let _ = self.close_requested.try_send(());
+        if let Some(waker) = &self.waker {
+            waker.wake();
+        }
+
+        if !self.is_on_window_thread() {
+            let _ = self.window_closed.recv_timeout(CLOSE_TIMEOUT);
+        }
+    }
+
+    fn is_on_window_thread(&self) -> bool {
+        match self.raw_window_handle {
+            Some(RawWindowHandle::Xlib(handle)) => LoopHandle::find(handle.window as XWindow)
+                .map_or(false, |loop_handle| loop_handle.is_current()),
+            _ => false,
+        }
     }
 
     pub fn is_open(&self) -> bool {
         self.is_open.load(Ordering::Relaxed)
     }
+
+    pub fn send_key_event(&self, event: KeyboardEvent) -> bool {
+        let window_id = match self.raw_window_handle {
+            Some(RawWindowHandle::Xlib(handle)) if self.is_open() => handle.window as XWindow,
+            _ => return false,
+        };
+
+        match LoopHandle::find(window_id) {
+            Some(loop_handle) => loop_handle.send_key_event(window_id, event, KEY_EVENT_TIMEOUT),
+            None => false,
+        }
+    }
+
+    pub fn set_scale_factor(&self, scale: f64) {
+        if let Some(RawWindowHandle::Xlib(handle)) = self.raw_window_handle {
+            let window_id = handle.window as XWindow;
+            if let Some(loop_handle) = LoopHandle::find(window_id) {
+                loop_handle.set_scale_factor(window_id, scale);
+            }
+        }
+    }
+
+    pub fn tick_frame(&self) {
+        if let Some(RawWindowHandle::Xlib(handle)) = self.raw_window_handle {
+            let window_id = handle.window as XWindow;
+            if let Some(loop_handle) = LoopHandle::find(window_id) {
+                loop_handle.tick_frame(window_id);
+            }
+        }
+    }
 }
 
 unsafe impl HasRawWindowHandle for WindowHandle {
@@ -59,23 +145,37 @@ unsafe impl HasRawWindowHandle for WindowHandle {
 
 pub(crate) struct ParentHandle {
     close_requested: Receiver<()>,
+    /// Dropped together with this handle. The event loop drops this handle after the window has
+    /// been destroyed.
+    _window_closed: SyncSender<()>,
     is_open: Arc<AtomicBool>,
 }
 
 impl ParentHandle {
-    pub fn new() -> (Self, WindowHandle) {
-        let (close_send, close_recv) = sync_channel(0);
+    /// `waker` should wake up the event loop thread the window runs on. Without a waker, close
+    /// requests are only noticed once the event loop wakes up for the next frame.
+    pub fn new(waker: Option<Arc<EventLoopWaker>>) -> (Self, WindowHandle) {
+        // These channels have room for a message so sending never blocks the host
+        let (close_send, close_recv) = sync_channel(1);
+        let (closed_send, closed_recv) = sync_channel(1);
         let is_open = Arc::new(AtomicBool::new(true));
 
         let handle = WindowHandle {
             raw_window_handle: None,
             close_requested: close_send,
+            window_closed: closed_recv,
+            waker,
             is_open: Arc::clone(&is_open),
         };
 
-        (Self { close_requested: close_recv, is_open }, handle)
+        let parent_handle =
+            Self { close_requested: close_recv, _window_closed: closed_send, is_open };
+
+        (parent_handle, handle)
     }
 
+    /// Returns `true` once the host has called [`WindowHandle::close()`]. Dropping the
+    /// `WindowHandle` does not close the window, just like on the other platforms.
     pub fn parent_did_drop(&self) -> bool {
         self.close_requested.try_recv().is_ok()
     }
@@ -90,15 +190,146 @@ impl Drop for ParentHandle {
 pub(crate) struct WindowInner {
     // GlContext should be dropped **before** XcbConnection is dropped
     #[cfg(feature = "opengl")]
-    gl_context: Option<GlContext>,
+    pub(crate) gl_context: GlContextCell,
 
-    pub(crate) xcb_connection: XcbConnection,
-    window_id: XWindow,
+    /// Shared with the other windows on the same event loop thread.
+    pub(crate) xcb_connection: Rc<XcbConnection>,
+    pub(crate) window_id: XWindow,
     pub(crate) window_info: WindowInfo,
+    pub(crate) scale_policy: WindowScalePolicy,
+    /// Parented windows aren't managed by the window manager, so they take focus directly.
+    is_parented: bool,
+    /// The time of the last key or button press in the window. Window managers with focus
+    /// stealing prevention ignore focus requests that don't come with a recent user interaction.
+    last_user_time: Cell<Timestamp>,
     visual_id: Visualid,
     mouse_cursor: Cell<MouseCursor>,
+    /// Whether the pointer is inside the window, tracked through enter and leave events.
+    pub(crate) pointer_inside: Cell<bool>,
+    /// Whether the window's cursor was removed with [`Window::reset_mouse_cursor()`].
+    cursor_reset: Cell<bool>,
+    pub(crate) input_events: InputEvents,
+    /// See [`WindowOpenOptions::wheel_zoom`].
+    pub(crate) wheel_zoom: bool,
+    /// See [`WindowOpenOptions::external_frame_clock`]. Frames are then only drawn when the host
+    /// calls [`WindowHandle::tick_frame()`].
+    pub(crate) external_frame_clock: bool,
+    /// The infinite drag in progress, see [`crate::Window::begin_infinite_drag()`].
+    pub(crate) infinite_drag: Cell<Option<InfiniteDrag>>,
+    /// Set by [`Window::refresh_cursor_position()`] and after the window is resized, so the event
+    /// loop sends the pointer's current position at the end of the current batch.
+    pub(crate) cursor_refresh_requested: Cell<bool>,
+    /// The sizes baseview resized the window to whose `ConfigureNotify` events haven't arrived yet,
+    /// and why, oldest first. See [`WindowInner::request_size()`].
+    pub(crate) requested_sizes: RefCell<VecDeque<(PhySize, ResizeSource)>>,
+    pub(crate) accelerators: Accelerators,
+    /// See [`Window::request_clipboard_data()`].
+    pub(super) clipboard_reader: ClipboardReader,
+    /// Receives drag and drop through XDND.
+    pub(super) drop_target: DropTarget,
+    /// See [`Window::start_drag()`].
+    pub(super) drag_source: DragSource,
+    /// The text field the user is typing in, see [`crate::Window::set_text_input_active()`]. Dead
+    /// keys only compose while this is set.
+    pub(super) text_input: Cell<Option<Rect>>,
+    /// Set when [`Window::clear_text_input()`] cancelled a compose sequence, so the event loop
+    /// sends [`ImeEvent::Disabled`][crate::ImeEvent::Disabled] at the end of the current batch.
+    pub(super) ime_disabled: Cell<bool>,
+    pub(crate) frame_counter: FrameCounter,
 
     pub(crate) close_requested: Cell<bool>,
+
+    #[cfg(feature = "virtual-clock")]
+    pub(crate) virtual_frames: RefCell<Option<VirtualFrames>>,
+}
+
+impl WindowInner {
+    /// Remember the time of a key or button press for [`Window::focus()`]. Top level windows also
+    /// tell the window manager about it through `_NET_WM_USER_TIME`.
+    pub(crate) fn update_user_time(&self, time: Timestamp) {
+        self.last_user_time.set(time);
+        self.xcb_connection.last_user_time.set(time);
+
+        if !self.is_parented {
+            let _ = self.xcb_connection.set_property32(
+                self.window_id,
+                self.xcb_connection.atoms._NET_WM_USER_TIME,
+                AtomEnum::CARDINAL,
+                &[time],
+            );
+            let _ = self.xcb_connection.conn.flush();
+        }
+    }
+
+    /// Resize the window to a physical size. The `ConfigureNotify` event that follows is reported
+    /// to the handler with `source`. Asking for the size the window is already being resized to
+    /// does nothing, so handlers that call [`Window::resize()`] in response to their own resize
+    /// events don't keep resizing the window.
+    pub(crate) fn request_size(&self, size: PhySize, source: ResizeSource) {
+        let mut requested_sizes = self.requested_sizes.borrow_mut();
+        if requested_sizes.back().map_or(false, |&(requested_size, _)| requested_size == size) {
+            return;
+        }
+
+        let configure_window = self.xcb_connection.conn.configure_window(
+            self.window_id,
+            &ConfigureWindowAux::new().width(size.width).height(size.height),
+        );
+        // Errors are passed on to the error callback, there's nothing else we can do about them
+        let _ = configure_window.check_reported();
+        requested_sizes.push_back((size, source));
+    }
+
+    /// Match a `ConfigureNotify` event's size with the sizes requested by baseview. Returns what
+    /// caused the new size, or `None` if the event is the echo of an older request that another
+    /// one has replaced since. Reporting that size would make the handler see the window shrink
+    /// or grow back for a moment, and handlers that negotiate their size could answer it with
+    /// another resize, which would never settle.
+    pub(crate) fn take_requested_size(&self, size: PhySize) -> Option<ResizeSource> {
+        let mut requested_sizes = self.requested_sizes.borrow_mut();
+        let index =
+            match requested_sizes.iter().position(|&(requested_size, _)| requested_size == size) {
+                Some(index) => index,
+                None => return Some(ResizeSource::External),
+            };
+
+        let (_, source) = requested_sizes[index];
+        requested_sizes.drain(..=index);
+        requested_sizes.is_empty().then(|| source)
+    }
+}
+
+impl Drop for WindowInner {
+    fn drop(&mut self) {
+        // The OpenGL context needs to be destroyed before the window it renders to
+        #[cfg(feature = "opengl")]
+        drop(self.gl_context.take());
+
+        // The connection outlives the window when it's shared with other windows, so the window
+        // isn't destroyed when the connection is closed, and neither are its key grabs
+        let root = self.xcb_connection.screen().root;
+        self.xcb_connection.global_shortcuts.unregister_window(
+            &self.xcb_connection.conn,
+            root,
+            self.window_id,
+        );
+        let _ = self.xcb_connection.conn.destroy_window(self.window_id);
+        let _ = self.xcb_connection.conn.flush();
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct InfiniteDrag {
+    /// Where the pointer was on the root window when the drag started. It's moved back there once
+    /// the drag ends.
+    root_position: (i16, i16),
+    /// The pointer's actual position relative to the window in physical pixels. This is used to
+    /// compute the movement of absolute pointing devices, which don't report relative motion.
+    pub(crate) pointer_position: (f64, f64),
+    /// The virtual cursor position in logical coordinates.
+    pub(crate) position: Point,
+    /// The modifier state from the last pointer event, since raw motion events don't include it.
+    pub(crate) modifiers: KeyButMask,
 }
 
 pub struct Window<'a> {
@@ -129,20 +360,47 @@ impl<'a> Window<'a> {
 
         let (tx, rx) = mpsc::sync_channel::<WindowOpenResult>(1);
 
-        let (parent_handle, mut window_handle) = ParentHandle::new();
+        // Child windows of our own windows join their parent's X11 connection and event loop
+        // thread instead of starting a new one
+        let parent_loop = LoopHandle::find(parent_id);
+        let waker = match &parent_loop {
+            Some(parent_loop) => parent_loop.waker.clone(),
+            None => EventLoopWaker::new().ok().map(Arc::new),
+        };
+
+        let (parent_handle, mut window_handle) = ParentHandle::new(waker.clone());
 
-        thread::spawn(move || {
-            Self::window_thread(Some(parent_id), options, build, tx.clone(), Some(parent_handle))
-                .unwrap();
+        let open: Box<dyn FnOnce() + Send> = Box::new(move || {
+            let result =
+                Self::open_window(Some(parent_id), options, build, tx, Some(parent_handle), false);
+            if let Err(err) = result {
+                report_platform_error(PlatformError {
+                    request: "CreateWindow",
+                    message: format!("could not open the window: {err}"),
+                });
+            }
         });
 
-        let raw_window_handle = rx.recv().unwrap().unwrap();
-        window_handle.raw_window_handle = Some(raw_window_handle.0);
+        match parent_loop {
+            // The window is opened from within one of the parent's handler's callbacks, so the
+            // event loop can't open it for us
+            Some(parent_loop) if parent_loop.is_current() => open(),
+            Some(parent_loop) => parent_loop.open(open),
+            None => {
+                EventLoopThread::spawn(waker, open);
+            }
+        }
+
+        // If the window could not be opened then the window thread has already shut down, and the
+        // handle simply reports the window as closed
+        if let Ok(Ok(raw_window_handle)) = rx.recv() {
+            window_handle.raw_window_handle = Some(raw_window_handle.0);
+        }
 
         window_handle
     }
 
-    pub fn open_blocking<H, B>(options: WindowOpenOptions, build: B)
+    pub fn open_blocking<H, B>(options: WindowOpenOptions, build: B, quits_with_app: bool)
     where
         H: WindowHandler + 'static,
         B: FnOnce(&mut crate::Window) -> H,
@@ -150,9 +408,14 @@ impl<'a> Window<'a> {
     {
         let (tx, rx) = mpsc::sync_channel::<WindowOpenResult>(1);
 
-        let thread = thread::spawn(move || {
-            Self::window_thread(None, options, build, tx, None).unwrap();
-        });
+        // Child windows opened from other threads still need to be able to wake up the event loop
+        let waker = EventLoopWaker::new().ok().map(Arc::new);
+        let thread = EventLoopThread::spawn(
+            waker,
+            Box::new(move || {
+                Self::open_window(None, options, build, tx, None, quits_with_app).unwrap();
+            }),
+        );
 
         let _ = rx.recv().unwrap().unwrap();
 
@@ -161,92 +424,192 @@ impl<'a> Window<'a> {
         });
     }
 
-    fn window_thread<H, B>(
+    /// Create a window and add it to the event loop of the current thread, which must be an event
+    /// loop thread. See [`EventLoop::new()`] for `quits_with_app`.
+    fn open_window<H, B>(
         parent: Option<u32>, options: WindowOpenOptions, build: B,
         tx: mpsc::SyncSender<WindowOpenResult>, parent_handle: Option<ParentHandle>,
+        quits_with_app: bool,
     ) -> Result<(), Box<dyn Error>>
     where
         H: WindowHandler + 'static,
         B: FnOnce(&mut crate::Window) -> H,
         B: Send + 'static,
     {
-        // Connect to the X server
-        // FIXME: baseview error type instead of unwrap()
-        let xcb_connection = XcbConnection::new()?;
+        let xcb_connection = event_loop_thread::current_connection()?;
 
         // Get screen information
         let screen = xcb_connection.screen();
         let parent_id = parent.unwrap_or(screen.root);
 
-        let gc_id = xcb_connection.conn.generate_id()?;
-        xcb_connection.conn.create_gc(
-            gc_id,
-            parent_id,
-            &CreateGCAux::new().foreground(screen.black_pixel).graphics_exposures(0),
-        )?;
-
         let scaling = match options.scale {
             WindowScalePolicy::SystemScaleFactor => xcb_connection.get_scaling().unwrap_or(1.0),
-            WindowScalePolicy::ScaleFactor(scale) => scale,
+            WindowScalePolicy::ScaleFactor(scale) | WindowScalePolicy::HostControlled(scale) => {
+                scale
+            }
+            WindowScalePolicy::ForceUnscaled => 1.0,
         };
 
-        let window_info = WindowInfo::from_logical_size(options.size, scaling);
+        let window_info = options.initial_window_info(scaling);
 
         #[cfg(feature = "opengl")]
-        let visual_info =
-            WindowVisualConfig::find_best_visual_config_for_gl(&xcb_connection, options.gl_config)?;
+        let mut visual_info = WindowVisualConfig::find_visual_config_for_gl(
+            &xcb_connection,
+            parent_id,
+            options.x11_visual,
+            options.gl_config.clone(),
+        )?;
 
         #[cfg(not(feature = "opengl"))]
-        let visual_info = WindowVisualConfig::find_best_visual_config(&xcb_connection)?;
+        let mut visual_info =
+            WindowVisualConfig::find_visual_config(&xcb_connection, options.x11_visual)?;
+
+        // Input events the window doesn't select propagate to the parent window, so the host
+        // gets to handle those itself
+        let input_events = options.input_events;
+        // Property changes are needed to read large clipboard contents
+        let mut event_mask =
+            EventMask::EXPOSURE | EventMask::STRUCTURE_NOTIFY | EventMask::PROPERTY_CHANGE;
+        let mut pointer_event_mask = xinput::XIEventMask::from(0u32);
+        if input_events.keyboard {
+            // Losing the focus resets which keys are held down, see `EventLoop::pressed_keys`
+            event_mask |= EventMask::KEY_PRESS | EventMask::KEY_RELEASE | EventMask::FOCUS_CHANGE;
+        }
+        // Crossing events don't propagate, and they're needed to reset the cursor when the pointer
+        // leaves the window
+        event_mask |= EventMask::ENTER_WINDOW | EventMask::LEAVE_WINDOW;
+        if input_events.mouse_motion {
+            event_mask |= EventMask::POINTER_MOTION;
+            pointer_event_mask |= xinput::XIEventMask::MOTION;
+        }
+        if input_events.mouse_buttons {
+            event_mask |= EventMask::BUTTON_PRESS | EventMask::BUTTON_RELEASE;
+            pointer_event_mask |=
+                xinput::XIEventMask::BUTTON_PRESS | xinput::XIEventMask::BUTTON_RELEASE;
+        }
 
         let window_id = xcb_connection.conn.generate_id()?;
-        xcb_connection.conn.create_window(
-            visual_info.visual_depth,
-            window_id,
-            parent_id,
-            0,                                         // x coordinate of the new window
-            0,                                         // y coordinate of the new window
-            window_info.physical_size().width as u16,  // window width
-            window_info.physical_size().height as u16, // window height
-            0,                                         // window border
-            WindowClass::INPUT_OUTPUT,
-            visual_info.visual_id,
-            &CreateWindowAux::new()
-                .event_mask(
-                    EventMask::EXPOSURE
-                        | EventMask::POINTER_MOTION
-                        | EventMask::BUTTON_PRESS
-                        | EventMask::BUTTON_RELEASE
-                        | EventMask::KEY_PRESS
-                        | EventMask::KEY_RELEASE
-                        | EventMask::STRUCTURE_NOTIFY
-                        | EventMask::ENTER_WINDOW
-                        | EventMask::LEAVE_WINDOW,
+        let create_window = |visual_info: &WindowVisualConfig| {
+            xcb_connection
+                .conn
+                .create_window(
+                    visual_info.visual_depth,
+                    window_id,
+                    parent_id,
+                    0,                                         // x coordinate of the new window
+                    0,                                         // y coordinate of the new window
+                    window_info.physical_size().width as u16,  // window width
+                    window_info.physical_size().height as u16, // window height
+                    0,                                         // window border
+                    WindowClass::INPUT_OUTPUT,
+                    visual_info.visual_id,
+                    &CreateWindowAux::new()
+                        .event_mask(event_mask)
+                        // As mentioned above, these two values are needed to be able to create a
+                        // window with a depth of 32-bits when the parent window has a different
+                        // depth
+                        .colormap(visual_info.color_map)
+                        .border_pixel(0)
+                        // Software renderers present finished frames straight to the window, so
+                        // the server should never clear it. Without a background the server
+                        // leaves exposed areas alone until the next frame is drawn, and the north
+                        // west bit gravity keeps the existing contents in place while the window
+                        // is being resized instead of discarding them. Both of these would
+                        // otherwise cause visible flicker.
+                        .background_pixmap(x11rb::NONE)
+                        .bit_gravity(Gravity::NORTH_WEST),
                 )
-                // As mentioned above, these two values are needed to be able to create a window
-                // with a depth of 32-bits when the parent window has a different depth
-                .colormap(visual_info.color_map)
-                .border_pixel(0),
-        )?;
-        xcb_connection.conn.map_window(window_id)?;
+                .map_err(ReplyError::from)
+                .and_then(|cookie| cookie.check())
+        };
+
+        // Some servers and hosts refuse child windows whose visual differs from their parent's.
+        // Rather than failing to open the editor, the window then uses the parent's visual.
+        let mut created = create_window(&visual_info);
+        if let Err(ReplyError::X11Error(error)) = &created {
+            let can_fall_back = visual_info.visual_id != COPY_FROM_PARENT
+                && options.x11_visual != X11Visual::RequireArgb;
+            if error.error_kind == ErrorKind::Match && can_fall_back {
+                report_platform_error(PlatformError {
+                    request: "CreateWindow",
+                    message: format!(
+                        "a {}-bit visual isn't supported here, using the parent window's visual",
+                        visual_info.visual_depth
+                    ),
+                });
+
+                #[cfg(feature = "opengl")]
+                let fallback = visual_info.parent_fallback(
+                    &xcb_connection,
+                    parent_id,
+                    options.gl_config.clone(),
+                );
+                #[cfg(not(feature = "opengl"))]
+                let fallback = visual_info.parent_fallback(&xcb_connection);
+                visual_info = fallback;
+                created = create_window(&visual_info);
+            }
+        }
+        // This is the request that fails when the parent window is invalid, so it should be
+        // reported before anything else
+        if let Err(ReplyError::X11Error(error)) = &created {
+            report_x11_error(error);
+        }
+        created?;
+
+        // When XInput2 is available we use its pointer events instead of the core ones, since
+        // those tell us which physical device generated them. Selecting these replaces the
+        // corresponding core events, which are still used on servers without XInput2.
+        if xcb_connection.pointer_devices.is_some() {
+            xcb_connection.conn.xinput_xi_select_events(
+                window_id,
+                &[xinput::EventMask {
+                    deviceid: xinput::Device::ALL_MASTER.into(),
+                    mask: vec![pointer_event_mask],
+                }],
+            )?;
+        }
+
+        if xcb_connection.has_xfixes {
+            let selection_events = SelectionEventMask::SET_SELECTION_OWNER
+                | SelectionEventMask::SELECTION_WINDOW_DESTROY
+                | SelectionEventMask::SELECTION_CLIENT_CLOSE;
+            xcb_connection.conn.xfixes_select_selection_input(
+                window_id,
+                xcb_connection.atoms.CLIPBOARD,
+                selection_events,
+            )?;
+
+            // The OpenGL context's vsync mode depends on whether a compositor is running
+            #[cfg(feature = "opengl")]
+            xcb_connection.conn.xfixes_select_selection_input(
+                window_id,
+                xcb_connection.compositor_selection,
+                selection_events,
+            )?;
+        }
+
+        xcb_connection.conn.map_window(window_id).check_reported()?;
 
         // Change window title
         let title = options.title;
-        xcb_connection.conn.change_property8(
-            PropMode::REPLACE,
+        let set_title = xcb_connection.set_property8(
             window_id,
             AtomEnum::WM_NAME,
             AtomEnum::STRING,
             title.as_bytes(),
-        )?;
+        );
+        set_title.check_reported()?;
 
-        xcb_connection.conn.change_property32(
-            PropMode::REPLACE,
+        let set_protocols = xcb_connection.set_property32(
             window_id,
             xcb_connection.atoms.WM_PROTOCOLS,
             AtomEnum::ATOM,
             &[xcb_connection.atoms.WM_DELETE_WINDOW],
-        )?;
+        );
+        set_protocols.check_reported()?;
+
+        let drop_target = DropTarget::new(&xcb_connection, window_id, &options.drop_formats)?;
 
         xcb_connection.conn.flush()?;
 
@@ -255,7 +618,7 @@ impl<'a> Window<'a> {
         //       compared to when raw-gl-context was a separate crate.
         #[cfg(feature = "opengl")]
         let gl_context = visual_info.fb_config.and_then(|fb_config| {
-            use std::ffi::c_ulong;
+            use std::os::raw::c_ulong;
 
             let window = window_id as c_ulong;
             let display = xcb_connection.dpy;
@@ -269,14 +632,36 @@ impl<'a> Window<'a> {
             xcb_connection,
             window_id,
             window_info,
+            scale_policy: options.scale,
+            is_parented: parent.is_some(),
+            last_user_time: Cell::new(CURRENT_TIME),
             visual_id: visual_info.visual_id,
             mouse_cursor: Cell::new(MouseCursor::default()),
+            pointer_inside: Cell::new(false),
+            cursor_reset: Cell::new(false),
+            input_events,
+            wheel_zoom: options.wheel_zoom,
+            external_frame_clock: options.external_frame_clock && parent.is_some(),
+            infinite_drag: Cell::new(None),
+            cursor_refresh_requested: Cell::new(false),
+            requested_sizes: RefCell::new(VecDeque::new()),
+            accelerators: Accelerators::default(),
+            clipboard_reader: ClipboardReader::default(),
+            drop_target,
+            drag_source: DragSource::default(),
+            text_input: Cell::new(None),
+            ime_disabled: Cell::new(false),
+            frame_counter: FrameCounter::default(),
 
             close_requested: Cell::new(false),
 
+            #[cfg(feature = "virtual-clock")]
+            virtual_frames: RefCell::new(None),
+
             #[cfg(feature = "opengl")]
-            gl_context,
+            gl_context: GlContextCell::new(gl_context),
         };
+        inner.frame_counter.set_refresh_rate(inner.xcb_connection.get_refresh_rate(window_id));
 
         let mut window = crate::Window::new(Window { inner: &mut inner });
 
@@ -284,20 +669,91 @@ impl<'a> Window<'a> {
 
         // Send an initial window resized event so the user is alerted of
         // the correct dpi scaling.
-        handler.on_event(&mut window, Event::Window(WindowEvent::Resized(window_info)));
+        let event = WindowEvent::Resized(window_info, ResizeSource::External);
+        handler.on_event(&mut window, Event::Window(event));
 
         let _ = tx.send(Ok(SendableRwh(window.raw_window_handle())));
 
-        EventLoop::new(inner, handler, parent_handle).run()?;
+        event_loop_thread::add_window(EventLoop::new(
+            inner,
+            handler,
+            parent_handle,
+            quits_with_app,
+        ))?;
 
         Ok(())
     }
 
+    pub fn drag_threshold(&self) -> f64 {
+        f64::from(self.inner.xcb_connection.drag_threshold.get())
+            * self.inner.window_info.scale_recip()
+    }
+
+    pub fn display_color_profile(&self) -> Option<Vec<u8>> {
+        self.inner.xcb_connection.get_icc_profile(self.inner.window_id)
+    }
+
+    pub fn mouse_cursor(&self) -> MouseCursor {
+        self.inner.mouse_cursor.get()
+    }
+
+    pub fn set_shadow(&mut self, _shadow: bool) {}
+
+    pub fn set_shape(&mut self, shape: Option<&[Rect]>) {
+        let conn = &self.inner.xcb_connection.conn;
+        let window_info = &self.inner.window_info;
+
+        // Errors, including the server not supporting the extension, are passed on to the error
+        // callback
+        let _ = match shape {
+            Some(rects) => {
+                let rectangles: Vec<Rectangle> = rects
+                    .iter()
+                    .map(|rect| {
+                        let top_left = rect.origin.to_physical(window_info);
+                        let bottom_right = Point::new(
+                            rect.origin.x + rect.size.width,
+                            rect.origin.y + rect.size.height,
+                        )
+                        .to_physical(window_info);
+
+                        Rectangle {
+                            x: top_left.x as i16,
+                            y: top_left.y as i16,
+                            width: (bottom_right.x - top_left.x).max(0) as u16,
+                            height: (bottom_right.y - top_left.y).max(0) as u16,
+                        }
+                    })
+                    .collect();
+
+                conn.shape_rectangles(
+                    SO::SET,
+                    SK::BOUNDING,
+                    ClipOrdering::UNSORTED,
+                    self.inner.window_id,
+                    0,
+                    0,
+                    &rectangles,
+                )
+                .check_reported()
+            }
+            None => conn
+                .shape_mask(SO::SET, SK::BOUNDING, self.inner.window_id, 0, 0, x11rb::NONE)
+                .check_reported(),
+        };
+    }
+
     pub fn set_mouse_cursor(&self, mouse_cursor: MouseCursor) {
         if self.inner.mouse_cursor.get() == mouse_cursor {
             return;
         }
 
+        // The cursor is applied once the pointer enters the window again
+        if self.inner.cursor_reset.get() {
+            self.inner.mouse_cursor.set(mouse_cursor);
+            return;
+        }
+
         let xid = self.inner.xcb_connection.get_cursor(mouse_cursor).unwrap();
 
         if xid != 0 {
@@ -311,6 +767,39 @@ impl<'a> Window<'a> {
         self.inner.mouse_cursor.set(mouse_cursor);
     }
 
+    /// Remove the window's cursor after the pointer has left the window, so the parent window's
+    /// cursor is shown instead. Otherwise the cursor can stick around over the host's UI, for
+    /// instance when a drag ends outside of the window.
+    pub(crate) fn reset_mouse_cursor(&self) {
+        if self.inner.cursor_reset.replace(true) {
+            return;
+        }
+
+        let conn = &self.inner.xcb_connection.conn;
+        let _ = conn.change_window_attributes(
+            self.inner.window_id,
+            &ChangeWindowAttributesAux::new().cursor(x11rb::NONE),
+        );
+        let _ = conn.flush();
+    }
+
+    /// Apply the window's cursor again after [`reset_mouse_cursor()`][Self::reset_mouse_cursor()]
+    /// when the pointer enters the window.
+    pub(crate) fn restore_mouse_cursor(&self) {
+        if !self.inner.cursor_reset.replace(false) {
+            return;
+        }
+
+        let xcb_connection = &self.inner.xcb_connection;
+        let cursor =
+            xcb_connection.get_cursor(self.inner.mouse_cursor.get()).unwrap_or(x11rb::NONE);
+        let _ = xcb_connection.conn.change_window_attributes(
+            self.inner.window_id,
+            &ChangeWindowAttributesAux::new().cursor(cursor),
+        );
+        let _ = xcb_connection.conn.flush();
+    }
+
     pub fn set_mouse_position(&self, point: Point) {
         let point = point.to_physical(&self.inner.window_info);
 
@@ -327,35 +816,334 @@ impl<'a> Window<'a> {
         let _ = self.inner.xcb_connection.conn.flush();
     }
 
+    pub fn set_mouse_position_screen(&self, point: Point) {
+        let point = point.to_physical(&self.inner.window_info);
+
+        let _ = self.inner.xcb_connection.conn.warp_pointer(
+            x11rb::NONE,
+            self.inner.xcb_connection.screen().root,
+            0,
+            0,
+            0,
+            0,
+            point.x as i16,
+            point.y as i16,
+        );
+        let _ = self.inner.xcb_connection.conn.flush();
+    }
+
+    pub fn move_mouse(&self, dx: f64, dy: f64) {
+        let offset = Point::new(dx, dy).to_physical(&self.inner.window_info);
+
+        // Without a destination window the pointer is moved relative to its current position
+        let _ = self.inner.xcb_connection.conn.warp_pointer(
+            x11rb::NONE,
+            x11rb::NONE,
+            0,
+            0,
+            0,
+            0,
+            offset.x as i16,
+            offset.y as i16,
+        );
+        let _ = self.inner.xcb_connection.conn.flush();
+    }
+
     pub fn close(&mut self) {
         self.inner.close_requested.set(true);
     }
 
-    pub fn has_focus(&mut self) -> bool {
-        false
+    pub fn refresh_cursor_position(&mut self) {
+        self.inner.cursor_refresh_requested.set(true);
     }
 
-    pub fn focus(&mut self) {}
+    pub fn frame_stats(&self) -> FrameStats {
+        self.inner.frame_counter.stats()
+    }
 
-    pub fn resize(&mut self, size: Size) {
-        let scaling = self.inner.window_info.scale();
-        let new_window_info = WindowInfo::from_logical_size(size, scaling);
+    pub fn refresh_rate(&self) -> Option<f64> {
+        self.inner.xcb_connection.get_refresh_rate(self.inner.window_id)
+    }
 
-        let _ = self.inner.xcb_connection.conn.configure_window(
+    pub fn modifiers(&self) -> Modifiers {
+        self.inner.xcb_connection.modifiers()
+    }
+
+    pub fn set_animation_frames(&mut self, enabled: bool) {
+        self.inner.frame_counter.set_animation_frames(enabled);
+    }
+
+    pub fn request_clipboard_data(&mut self, format: ClipboardFormat) {
+        self.inner.clipboard_reader.request(
+            &self.inner.xcb_connection,
             self.inner.window_id,
-            &ConfigureWindowAux::new()
-                .width(new_window_info.physical_size().width)
-                .height(new_window_info.physical_size().height),
+            self.inner.last_user_time.get(),
+            format,
         );
-        let _ = self.inner.xcb_connection.conn.flush();
+    }
+
+    pub fn begin_infinite_drag(&self) {
+        if self.inner.infinite_drag.get().is_some() {
+            return;
+        }
+
+        let xcb_connection = &self.inner.xcb_connection;
+        let conn = &xcb_connection.conn;
+        let pointer =
+            match conn.query_pointer(self.inner.window_id).ok().and_then(|c| c.reply().ok()) {
+                Some(pointer) => pointer,
+                None => return,
+            };
+
+        // The pointer is grabbed by the window while a button is held, and the grab window's
+        // cursor is shown regardless of where the pointer is
+        if let Ok(cursor) = xcb_connection.get_cursor(MouseCursor::Hidden) {
+            let _ = conn.change_window_attributes(
+                self.inner.window_id,
+                &ChangeWindowAttributesAux::new().cursor(cursor),
+            );
+        }
+
+        // The pointer stops at the screen edges, but raw motion events keep reporting how far the
+        // mouse was moved. These can only be selected on the root window.
+        if xcb_connection.pointer_devices.is_some() {
+            let _ = conn.xinput_xi_select_events(
+                xcb_connection.screen().root,
+                &[xinput::EventMask {
+                    deviceid: xinput::Device::ALL_MASTER.into(),
+                    mask: vec![xinput::XIEventMask::RAW_MOTION],
+                }],
+            );
+        }
+        let _ = conn.flush();
+
+        let pointer_position = (f64::from(pointer.win_x), f64::from(pointer.win_y));
+        self.inner.infinite_drag.set(Some(InfiniteDrag {
+            root_position: (pointer.root_x, pointer.root_y),
+            pointer_position,
+            position: Point::new(
+                pointer_position.0 * self.inner.window_info.scale_recip(),
+                pointer_position.1 * self.inner.window_info.scale_recip(),
+            ),
+            modifiers: pointer.mask,
+        }));
+    }
 
-        // This will trigger a `ConfigureNotify` event which will in turn change `self.window_info`
-        // and notify the window handler about it
+    pub fn end_infinite_drag(&self) {
+        let drag = match self.inner.infinite_drag.take() {
+            Some(drag) => drag,
+            None => return,
+        };
+
+        let xcb_connection = &self.inner.xcb_connection;
+        let conn = &xcb_connection.conn;
+        let root = xcb_connection.screen().root;
+        if xcb_connection.pointer_devices.is_some() {
+            let _ = conn.xinput_xi_select_events(
+                root,
+                &[xinput::EventMask {
+                    deviceid: xinput::Device::ALL_MASTER.into(),
+                    mask: vec![xinput::XIEventMask::from(0u32)],
+                }],
+            );
+        }
+
+        let cursor =
+            xcb_connection.get_cursor(self.inner.mouse_cursor.get()).unwrap_or(x11rb::NONE);
+        let _ = conn.change_window_attributes(
+            self.inner.window_id,
+            &ChangeWindowAttributesAux::new().cursor(cursor),
+        );
+
+        let (root_x, root_y) = drag.root_position;
+        let _ = conn.warp_pointer(x11rb::NONE, root, 0, 0, 0, 0, root_x, root_y);
+        let _ = conn.flush();
+    }
+
+    pub fn register_accelerator(&mut self, combo: KeyCombo, id: u32) {
+        self.inner.accelerators.register(combo, id);
+    }
+
+    pub fn unregister_accelerator(&mut self, id: u32) {
+        self.inner.accelerators.unregister(id);
+    }
+
+    pub fn register_global_shortcut(
+        &mut self, combo: KeyCombo, id: u32,
+    ) -> Result<(), GlobalShortcutError> {
+        let xcb_connection = &self.inner.xcb_connection;
+        xcb_connection.global_shortcuts.register(
+            &xcb_connection.conn,
+            xcb_connection.screen().root,
+            xcb_connection.lock_masks.get(),
+            self.inner.window_id,
+            combo,
+            id,
+        )
+    }
+
+    pub fn unregister_global_shortcut(&mut self, id: u32) {
+        let xcb_connection = &self.inner.xcb_connection;
+        xcb_connection.global_shortcuts.unregister(
+            &xcb_connection.conn,
+            xcb_connection.screen().root,
+            self.inner.window_id,
+            id,
+        );
+    }
+
+    pub fn set_text_input_active(&mut self, rect: Rect) {
+        self.inner.text_input.set(Some(rect));
+    }
+
+    pub fn clear_text_input(&mut self) {
+        let was_active = self.inner.text_input.take().is_some();
+        if let Some(xkb) = &self.inner.xcb_connection.xkb {
+            if xkb.reset_compose() && was_active {
+                self.inner.ime_disabled.set(true);
+            }
+        }
+    }
+
+    pub fn is_text_input_active(&self) -> bool {
+        self.inner.text_input.get().is_some()
+    }
+
+    pub fn set_ime_cursor_area(&mut self, _rect: Rect) {}
+
+    pub fn perform_haptic(&mut self, _pattern: HapticPattern) {}
+
+    pub fn start_drag(&mut self, data: DragData) {
+        let inner = self.inner;
+        let time = inner.last_user_time.get();
+        inner.drag_source.start(&inner.xcb_connection, inner.window_id, time, data);
+    }
+
+    pub fn keyboard_layout(&self) -> Option<String> {
+        self.inner.xcb_connection.get_keyboard_layout()
+    }
+
+    pub fn has_focus(&self) -> bool {
+        let conn = &self.inner.xcb_connection.conn;
+        let reply = conn.get_input_focus().ok().and_then(|cookie| cookie.reply().ok());
+
+        matches!(reply, Some(reply) if reply.focus == self.inner.window_id)
+    }
+
+    pub fn focus(&mut self) {
+        let xcb_connection = &self.inner.xcb_connection;
+        let time = self.inner.last_user_time.get();
+
+        if self.inner.is_parented {
+            let _ = xcb_connection
+                .conn
+                .set_input_focus(InputFocus::PARENT, self.inner.window_id, time)
+                .check_reported();
+            return;
+        }
+
+        // Top level windows ask the window manager to activate them, as a normal application
+        // would after the user interacted with it
+        let event = ClientMessageEvent::new(
+            32,
+            self.inner.window_id,
+            xcb_connection.atoms._NET_ACTIVE_WINDOW,
+            [1, time, 0, 0, 0],
+        );
+        let _ = xcb_connection
+            .conn
+            .send_event(
+                false,
+                xcb_connection.screen().root,
+                EventMask::SUBSTRUCTURE_REDIRECT | EventMask::SUBSTRUCTURE_NOTIFY,
+                event,
+            )
+            .check_reported();
+    }
+
+    pub fn grab_keyboard(&mut self, grab: bool) -> bool {
+        let conn = &self.inner.xcb_connection.conn;
+
+        if !grab {
+            let _ = conn.ungrab_keyboard(x11rb::CURRENT_TIME);
+            let _ = conn.flush();
+
+            return false;
+        }
+
+        // With `owner_events` set, key events are still reported normally to our own window while
+        // everything else is redirected to it
+        let reply = conn
+            .grab_keyboard(
+                true,
+                self.inner.window_id,
+                x11rb::CURRENT_TIME,
+                GrabMode::ASYNC,
+                GrabMode::ASYNC,
+            )
+            .ok()
+            .and_then(|cookie| cookie.reply().ok());
+
+        matches!(reply, Some(reply) if reply.status == GrabStatus::SUCCESS)
+    }
+
+    pub fn resize(&mut self, size: Size) {
+        let scaling = self.inner.window_info.scale();
+        let new_window_info = WindowInfo::from_logical_size_snapped(size, scaling);
+
+        self.inner.request_size(new_window_info.physical_size(), ResizeSource::Handler);
     }
 
     #[cfg(feature = "opengl")]
     pub fn gl_context(&self) -> Option<&crate::gl::GlContext> {
-        self.inner.gl_context.as_ref()
+        self.inner.gl_context.get()
+    }
+
+    #[cfg(feature = "opengl")]
+    pub fn create_gl_context(&mut self, config: GlConfig) -> Result<(), GlError> {
+        use std::os::raw::c_ulong;
+
+        if self.inner.gl_context.get().is_some() {
+            return Err(GlError::AlreadyCreated);
+        }
+
+        // The window's visual can no longer be changed at this point, so the context needs to use
+        // an fbconfig matching whatever visual the window ended up with
+        let xcb_connection = &self.inner.xcb_connection;
+        let visual = xcb_connection
+            .conn
+            .get_window_attributes(self.inner.window_id)
+            .ok()
+            .and_then(|cookie| cookie.reply().ok())
+            .ok_or(GlError::InvalidWindowHandle)?
+            .visual;
+
+        let window = self.inner.window_id as c_ulong;
+        let display = xcb_connection.dpy;
+        let context = unsafe {
+            let fb_config = platform::GlContext::get_fb_config_for_visual(display, config, visual)?;
+            platform::GlContext::create(window, display, fb_config)?
+        };
+
+        self.inner.gl_context.set(GlContext::new(context)).map_err(|_| GlError::AlreadyCreated)
+    }
+
+    #[cfg(feature = "opengl")]
+    pub fn create_gl_area(
+        &mut self, position: crate::PhyPoint, size: crate::PhySize, config: GlConfig,
+    ) -> Result<GlArea, GlError> {
+        super::gl_area::create_gl_area(
+            &self.inner.xcb_connection,
+            self.inner.window_id,
+            position,
+            size,
+            config,
+        )
+    }
+
+    #[cfg(feature = "virtual-clock")]
+    pub fn set_virtual_clock(&mut self, clock: &VirtualClock) {
+        *self.inner.virtual_frames.borrow_mut() = Some(VirtualFrames::new(clock));
     }
 }
 
@@ -382,6 +1170,6 @@ unsafe impl<'a> HasRawDisplayHandle for Window<'a> {
     }
 }
 
-pub fn copy_to_clipboard(_data: &str) {
-    todo!()
+pub fn copy_to_clipboard(contents: &[(ClipboardFormat, &[u8])]) {
+    super::event_loop_thread::copy_to_clipboard(contents);
 }
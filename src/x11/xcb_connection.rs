@@ -1,23 +1,64 @@
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::hash_map::{Entry, HashMap};
 use std::error::Error;
 
 use x11::{xlib, xlib::Display, xlib_xcb};
 
 use x11rb::connection::Connection;
+use x11rb::cookie::VoidCookie;
 use x11rb::cursor::Handle as CursorHandle;
-use x11rb::protocol::xproto::{Cursor, Screen};
+use x11rb::errors::{ConnectionError, ReplyError};
+use x11rb::protocol::xfixes::{ConnectionExt as _, SelectionEventMask};
+use x11rb::protocol::xkb::{self, ConnectionExt as _};
+use x11rb::protocol::xproto::{
+    Atom, AtomEnum, ChangeWindowAttributesAux, ConnectionExt as _, Cursor, EventMask,
+    GetPropertyReply, ModMask, PropMode, Screen, Timestamp, Window as XWindow,
+};
 use x11rb::resource_manager;
+use x11rb::wrapper::ConnectionExt as _;
 use x11rb::xcb_ffi::XCBConnection;
 
 use crate::MouseCursor;
+use keyboard_types::Modifiers;
 
+use super::clipboard::Clipboard;
 use super::cursor;
+use super::global_shortcuts::GlobalShortcuts;
+use super::keyboard::{key_mods, LockMasks};
+use super::monitors::Monitors;
+use super::pointer_devices::PointerDevices;
+use super::xkb::Xkb;
+use super::xsettings::XSettings;
 
 x11rb::atom_manager! {
     pub Atoms: AtomsCookie {
         WM_PROTOCOLS,
         WM_DELETE_WINDOW,
+        _ICC_PROFILE,
+        _NET_ACTIVE_WINDOW,
+        _NET_WM_USER_TIME,
+        CLIPBOARD,
+        TARGETS,
+        UTF8_STRING,
+        TEXT,
+        INCR,
+        _XKB_RULES_NAMES,
+        _XSETTINGS_SETTINGS,
+        XdndAware,
+        XdndProxy,
+        XdndEnter,
+        XdndPosition,
+        XdndStatus,
+        XdndLeave,
+        XdndDrop,
+        XdndFinished,
+        XdndSelection,
+        XdndTypeList,
+        XdndActionCopy,
+        XdndActionMove,
+        XdndActionLink,
+        XdndActionPrivate,
+        TEXT_URI_LIST: b"text/uri-list",
     }
 }
 
@@ -29,9 +70,52 @@ pub struct XcbConnection {
     pub(crate) conn: XCBConnection,
     pub(crate) screen: usize,
     pub(crate) atoms: Atoms,
-    pub(crate) resources: resource_manager::Database,
+    /// Atoms interned with [`XcbConnection::atom()`], like the atoms for custom clipboard and drag
+    /// formats.
+    atom_cache: RefCell<HashMap<String, Atom>>,
+    /// The X resources, which are read again when the monitor configuration changes since the
+    /// desktop environment may update `Xft.dpi` along with it.
+    pub(crate) resources: RefCell<resource_manager::Database>,
     pub(crate) cursor_handle: CursorHandle,
     pub(super) cursor_cache: RefCell<HashMap<MouseCursor, u32>>,
+    /// The modifier bits for Num Lock and Scroll Lock, see [`LockMasks`].
+    pub(super) lock_masks: Cell<LockMasks>,
+    /// The active XKB group, which selects the active layout from the ones configured in
+    /// `_XKB_RULES_NAMES`. This is updated from XKB state events, and from the state of key
+    /// events in case the server doesn't send those.
+    keyboard_group: Cell<usize>,
+    /// The keyboard's modifier state, which is updated from XKB state events. Unlike the state of
+    /// key events, this already includes the change made by the modifier key that was pressed.
+    modifiers: Cell<Modifiers>,
+    /// Translates keys with the user's keyboard layout. This is `None` if xkbcommon isn't
+    /// installed, in which case keys are translated with the US layout.
+    pub(super) xkb: Option<Xkb>,
+    /// Used to identify the device behind XInput2 pointer events. This is `None` if the server
+    /// doesn't support XInput2, in which case we fall back to core pointer events.
+    pub(super) pointer_devices: Option<PointerDevices>,
+    /// Used to look up the refresh rate of the window's monitor. This is `None` if the server
+    /// doesn't support RandR 1.2.
+    pub(super) monitors: Option<Monitors>,
+    /// Whether the server supports XFixes, which reports changes to the clipboard's owner.
+    pub(super) has_xfixes: bool,
+    /// The `_NET_WM_CM_S<screen>` selection, which is owned by the compositing manager while one
+    /// is running.
+    pub(super) compositor_selection: Atom,
+    /// The `_XSETTINGS_S<screen>` selection, which is owned by the XSETTINGS manager while one is
+    /// running.
+    pub(super) xsettings_selection: Atom,
+    /// The XSETTINGS manager's window, on which changes to the settings are selected.
+    pub(super) xsettings_owner: Cell<XWindow>,
+    /// The drag threshold in physical pixels, see [`crate::Window::drag_threshold()`]. This is
+    /// updated when the XSETTINGS change.
+    pub(super) drag_threshold: Cell<u32>,
+    /// The keys grabbed for [`crate::Window::register_global_shortcut()`].
+    pub(super) global_shortcuts: GlobalShortcuts,
+    /// The `CLIPBOARD` selection owned for [`crate::copy_to_clipboard()`].
+    pub(super) clipboard: Clipboard,
+    /// The time of the last key or button press in any of the connection's windows, used when
+    /// taking ownership of the clipboard.
+    pub(super) last_user_time: Cell<Timestamp>,
 }
 
 impl XcbConnection {
@@ -48,16 +132,53 @@ impl XcbConnection {
 
         let atoms = Atoms::new(&conn)?.reply()?;
         let resources = resource_manager::new_from_default(&conn)?;
-        let cursor_handle = CursorHandle::new(&conn, screen, &resources)?.reply()?;
+        let xsettings = XSettings::query(&conn, screen).unwrap_or_default();
+        let cursor_resources = cursor::cursor_resources(&resources, &xsettings);
+        let drag_threshold = drag_threshold(&xsettings);
+        let cursor_handle = CursorHandle::new(&conn, screen, &cursor_resources)?.reply()?;
+        let lock_masks = LockMasks::query(&conn).unwrap_or_default();
+        let xkb = Xkb::new(conn.get_raw_xcb_connection());
+        select_keyboard_state_events(&conn);
+        let modifiers = query_modifiers(&conn, lock_masks);
+        enable_detectable_auto_repeat(&conn);
+        let pointer_devices = PointerDevices::new(&conn);
+        let monitors = Monitors::new(&conn);
+        // The extension has to be told which version we want before it can be used
+        let has_xfixes = conn
+            .xfixes_query_version(1, 0)
+            .ok()
+            .and_then(|cookie| cookie.reply().ok())
+            .map_or(false, |version| version.major_version >= 1);
+        let compositor_selection = format!("_NET_WM_CM_S{}", screen);
+        let compositor_selection =
+            conn.intern_atom(false, compositor_selection.as_bytes())?.reply()?.atom;
+        let xsettings_selection = format!("_XSETTINGS_S{}", screen);
+        let xsettings_selection =
+            conn.intern_atom(false, xsettings_selection.as_bytes())?.reply()?.atom;
 
         Ok(Self {
             dpy,
             conn,
             screen,
             atoms,
-            resources,
+            atom_cache: RefCell::new(HashMap::new()),
+            resources: RefCell::new(resources),
             cursor_handle,
             cursor_cache: RefCell::new(HashMap::new()),
+            lock_masks: Cell::new(lock_masks),
+            keyboard_group: Cell::new(0),
+            modifiers: Cell::new(modifiers),
+            xkb,
+            pointer_devices,
+            monitors,
+            has_xfixes,
+            compositor_selection,
+            xsettings_selection,
+            xsettings_owner: Cell::new(x11rb::NONE),
+            drag_threshold: Cell::new(drag_threshold),
+            global_shortcuts: GlobalShortcuts::default(),
+            clipboard: Clipboard::default(),
+            last_user_time: Cell::new(x11rb::CURRENT_TIME),
         })
     }
 
@@ -65,7 +186,7 @@ impl XcbConnection {
     // If this gives you `None`, fall back to `get_scaling_screen_dimensions`.
     // If neither work, I guess just assume 96.0 and don't do any scaling.
     fn get_scaling_xft(&self) -> Result<Option<f64>, Box<dyn Error>> {
-        if let Some(dpi) = self.resources.get_value::<u32>("Xft.dpi", "")? {
+        if let Some(dpi) = self.resources.borrow().get_value::<u32>("Xft.dpi", "")? {
             Ok(Some(dpi as f64 / 96.0))
         } else {
             Ok(None)
@@ -118,9 +239,196 @@ impl XcbConnection {
         }
     }
 
+    /// Query the lock key modifier bits again after the server's keyboard or modifier mapping
+    /// has changed.
+    pub fn refresh_lock_masks(&self) {
+        if let Ok(lock_masks) = LockMasks::query(&self.conn) {
+            self.lock_masks.set(lock_masks);
+        }
+    }
+
+    /// Update the active XKB group. Returns whether it changed, in which case the windows should be
+    /// sent [`crate::WindowEvent::KeyboardLayoutChanged`].
+    pub fn set_keyboard_group(&self, group: usize) -> bool {
+        self.keyboard_group.replace(group) != group
+    }
+
+    pub fn modifiers(&self) -> Modifiers {
+        self.modifiers.get()
+    }
+
+    /// Update the modifier state from the modifier mask of an XKB state event. Returns the new
+    /// modifiers if they changed, in which case the windows should be sent
+    /// [`crate::WindowEvent::ModifiersChanged`].
+    pub fn set_modifiers(&self, mods: ModMask) -> Option<Modifiers> {
+        let modifiers = key_mods(u16::from(mods).into(), self.lock_masks.get());
+        (self.modifiers.replace(modifiers) != modifiers).then(|| modifiers)
+    }
+
+    /// Fetch the keymap again after the keyboard mapping changed.
+    pub fn refresh_keymap(&self) {
+        if let Some(xkb) = &self.xkb {
+            xkb.refresh();
+        }
+    }
+
+    /// Select the events that tell us about changed XSETTINGS, see [`Self::refresh_settings()`].
+    pub fn select_settings_events(&self) {
+        // A new settings manager taking over the selection is reported on the root window
+        if self.has_xfixes {
+            let _ = self.conn.xfixes_select_selection_input(
+                self.screen().root,
+                self.xsettings_selection,
+                SelectionEventMask::SET_SELECTION_OWNER
+                    | SelectionEventMask::SELECTION_WINDOW_DESTROY
+                    | SelectionEventMask::SELECTION_CLIENT_CLOSE,
+            );
+        }
+
+        self.select_settings_owner();
+    }
+
+    /// Whether a `PropertyNotify` event is for the XSETTINGS manager's settings.
+    pub fn is_settings_change(&self, window: XWindow, atom: Atom) -> bool {
+        window != x11rb::NONE
+            && window == self.xsettings_owner.get()
+            && atom == self.atoms._XSETTINGS_SETTINGS
+    }
+
+    /// Read the XSETTINGS again after the settings manager changed them or was replaced.
+    pub fn refresh_settings(&self) {
+        let xsettings = XSettings::query(&self.conn, self.screen).unwrap_or_default();
+        self.drag_threshold.set(drag_threshold(&xsettings));
+
+        self.select_settings_owner();
+    }
+
+    /// Select property changes on the settings manager's window, which may have changed since the
+    /// last time.
+    fn select_settings_owner(&self) {
+        let owner = self
+            .conn
+            .get_selection_owner(self.xsettings_selection)
+            .ok()
+            .and_then(|cookie| cookie.reply().ok())
+            .map_or(x11rb::NONE, |reply| reply.owner);
+        if owner == self.xsettings_owner.replace(owner) || owner == x11rb::NONE {
+            return;
+        }
+
+        let _ = self.conn.change_window_attributes(
+            owner,
+            &ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE),
+        );
+        let _ = self.conn.flush();
+    }
+
+    /// Read the X resources again, for instance after the monitor configuration has changed.
+    pub fn refresh_resources(&self) {
+        if let Ok(resources) = resource_manager::new_from_default(&self.conn) {
+            *self.resources.borrow_mut() = resources;
+        }
+    }
+
+    /// Returns the refresh rate of the monitor the window is on, if it's known.
+    pub fn get_refresh_rate(&self, window: XWindow) -> Option<f64> {
+        self.monitors.as_ref()?.refresh_rate(&self.conn, self.screen().root, window)
+    }
+
+    /// Returns the ICC profile of the monitor the window is on. Color management daemons like
+    /// colord set this on the RandR outputs, and on the root window for the primary monitor.
+    pub fn get_icc_profile(&self, window: XWindow) -> Option<Vec<u8>> {
+        let root = self.screen().root;
+        let atom = self.atoms._ICC_PROFILE;
+        let output_profile = self
+            .monitors
+            .as_ref()
+            .and_then(|monitors| monitors.icc_profile(&self.conn, root, window, atom));
+        if output_profile.is_some() {
+            return output_profile;
+        }
+
+        self.get_property8(root, atom, AtomEnum::ANY).filter(|profile| !profile.is_empty())
+    }
+
+    /// Returns the name of the active XKB layout, like `us` or `de`. The configured layouts are
+    /// read from the `_XKB_RULES_NAMES` property that `setxkbmap` and the desktop environments set
+    /// on the root window.
+    pub fn get_keyboard_layout(&self) -> Option<String> {
+        let root = self.screen().root;
+        let atom = self.atoms._XKB_RULES_NAMES;
+        let property = self.get_property8(root, atom, AtomEnum::STRING)?;
+
+        // The property contains the rules, model, layouts, variants, and options, each terminated
+        // by a null byte. Multiple layouts are separated by commas.
+        let layouts = property.split(|&byte| byte == 0).nth(2)?;
+        let layouts = String::from_utf8_lossy(layouts);
+        let mut layouts = layouts.split(',').map(str::trim);
+        let layout = layouts.clone().nth(self.keyboard_group.get()).or_else(|| layouts.next())?;
+        if layout.is_empty() {
+            None
+        } else {
+            Some(layout.to_string())
+        }
+    }
+
     pub fn screen(&self) -> &Screen {
         &self.conn.setup().roots[self.screen]
     }
+
+    /// Returns the atom with this name, creating it if it doesn't exist yet. The well-known atoms
+    /// are interned up front in [`Atoms`], and other atoms are cached after the first call so
+    /// they only cost a round trip once.
+    pub fn atom(&self, name: &str) -> Result<Atom, ReplyError> {
+        if let Some(&atom) = self.atom_cache.borrow().get(name) {
+            return Ok(atom);
+        }
+
+        let atom = self.conn.intern_atom(false, name.as_bytes())?.reply()?.atom;
+        self.atom_cache.borrow_mut().insert(name.to_string(), atom);
+
+        Ok(atom)
+    }
+
+    /// Reads a property with 8-bit values, like a string. Returns `None` if the property doesn't
+    /// exist, or if it has a different type or format.
+    pub fn get_property8(
+        &self, window: XWindow, property: Atom, type_: impl Into<Atom>,
+    ) -> Option<Vec<u8>> {
+        let reply = self.get_property(window, property, type_.into())?;
+        reply.value8().map(Iterator::collect)
+    }
+
+    /// Reads a property with 32-bit values, like a list of atoms or windows. Returns `None` if the
+    /// property doesn't exist, or if it has a different type or format.
+    pub fn get_property32(
+        &self, window: XWindow, property: Atom, type_: impl Into<Atom>,
+    ) -> Option<Vec<u32>> {
+        let reply = self.get_property(window, property, type_.into())?;
+        reply.value32().map(Iterator::collect)
+    }
+
+    /// Replaces a property with 8-bit values.
+    pub fn set_property8(
+        &self, window: XWindow, property: impl Into<Atom>, type_: impl Into<Atom>, data: &[u8],
+    ) -> Result<VoidCookie<'_, XCBConnection>, ConnectionError> {
+        self.conn.change_property8(PropMode::REPLACE, window, property, type_, data)
+    }
+
+    /// Replaces a property with 32-bit values.
+    pub fn set_property32(
+        &self, window: XWindow, property: impl Into<Atom>, type_: impl Into<Atom>, data: &[u32],
+    ) -> Result<VoidCookie<'_, XCBConnection>, ConnectionError> {
+        self.conn.change_property32(PropMode::REPLACE, window, property, type_, data)
+    }
+
+    fn get_property(
+        &self, window: XWindow, property: Atom, type_: Atom,
+    ) -> Option<GetPropertyReply> {
+        let cookie =
+            self.conn.get_property(false, window, property, type_, 0, u32::MAX / 4).ok()?;
+        cookie.reply().ok()
+    }
 }
 
 impl Drop for XcbConnection {
@@ -130,3 +438,60 @@ impl Drop for XcbConnection {
         }
     }
 }
+
+/// Ask for XKB state events when the active group or the modifiers change. Unlike key events, these
+/// are sent regardless of which window has the keyboard focus.
+fn select_keyboard_state_events(conn: &XCBConnection) {
+    let supported = conn
+        .xkb_use_extension(1, 0)
+        .ok()
+        .and_then(|cookie| cookie.reply().ok())
+        .map_or(false, |reply| reply.supported);
+    if !supported {
+        return;
+    }
+
+    let details = xkb::SelectEventsAux::new().state_notify(xkb::SelectEventsAuxStateNotify {
+        affect_state: xkb::StatePart::GROUP_STATE | xkb::StatePart::MODIFIER_STATE,
+        state_details: xkb::StatePart::GROUP_STATE | xkb::StatePart::MODIFIER_STATE,
+    });
+    let _ = conn.xkb_select_events(
+        xkb::ID::USE_CORE_KBD.into(),
+        xkb::EventType::from(0u16),
+        xkb::EventType::from(0u16),
+        xkb::MapPart::from(0u16),
+        xkb::MapPart::from(0u16),
+        &details,
+    );
+}
+
+/// The modifiers that are active when the connection is opened, like a Caps Lock that's already on.
+fn query_modifiers(conn: &XCBConnection, lock_masks: LockMasks) -> Modifiers {
+    conn.xkb_get_state(xkb::ID::USE_CORE_KBD.into())
+        .ok()
+        .and_then(|cookie| cookie.reply().ok())
+        .map_or_else(Modifiers::empty, |state| key_mods(u16::from(state.mods).into(), lock_masks))
+}
+
+/// Without this the server sends a release before every repeated key press, so repeats can't be
+/// told apart from the user pressing the key again. With it, a held key only sends presses until
+/// it's released.
+fn enable_detectable_auto_repeat(conn: &XCBConnection) {
+    let _ = conn.xkb_per_client_flags(
+        xkb::ID::USE_CORE_KBD.into(),
+        xkb::PerClientFlag::DETECTABLE_AUTO_REPEAT,
+        xkb::PerClientFlag::DETECTABLE_AUTO_REPEAT,
+        xkb::BoolCtrl::from(0u32),
+        xkb::BoolCtrl::from(0u32),
+        xkb::BoolCtrl::from(0u32),
+    );
+}
+
+/// The drag threshold from the XSETTINGS in physical pixels.
+fn drag_threshold(xsettings: &XSettings) -> u32 {
+    // GTK uses the same default when the setting is missing
+    xsettings
+        .get_int("Net/DndDragThreshold")
+        .filter(|&threshold| threshold > 0)
+        .map_or(8, |threshold| threshold as u32)
+}
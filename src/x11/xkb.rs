@@ -0,0 +1,436 @@
+use std::cell::{Cell, RefCell};
+use std::ffi::{c_void, CString};
+use std::os::raw::{c_char, c_int};
+use std::os::unix::ffi::OsStringExt;
+
+use keyboard_types::{Key, KeyState, NamedKey};
+use x11rb::protocol::xproto::KeyButMask;
+
+type XkbContext = c_void;
+type XkbKeymap = c_void;
+type XkbState = c_void;
+type XkbComposeTable = c_void;
+type XkbComposeState = c_void;
+
+/// The range of the `XKB_KEY_dead_*` keysyms.
+const DEAD_KEYSYMS: std::ops::RangeInclusive<u32> = 0xfe50..=0xfe93;
+
+const XKB_COMPOSE_FEED_ACCEPTED: c_int = 1;
+const XKB_COMPOSE_COMPOSING: c_int = 1;
+const XKB_COMPOSE_COMPOSED: c_int = 2;
+const XKB_COMPOSE_CANCELLED: c_int = 3;
+
+/// How a key press changed the compose sequence, see [`Xkb::take_compose_change()`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) enum ComposeChange {
+    /// The sequence continued, and this is the text composed so far.
+    Preedit(String),
+    /// The sequence finished with this text.
+    Commit(String),
+    /// The key press cancelled the sequence and types its own character instead.
+    Cancelled,
+}
+
+/// The functions we use from `libxkbcommon` and `libxkbcommon-x11`. These are loaded at runtime,
+/// so the libraries don't need to be installed to build or run baseview.
+struct XkbFunctions {
+    context_new: unsafe extern "C" fn(flags: c_int) -> *mut XkbContext,
+    context_unref: unsafe extern "C" fn(context: *mut XkbContext),
+    keymap_unref: unsafe extern "C" fn(keymap: *mut XkbKeymap),
+    state_new: unsafe extern "C" fn(keymap: *mut XkbKeymap) -> *mut XkbState,
+    state_unref: unsafe extern "C" fn(state: *mut XkbState),
+    state_update_mask: unsafe extern "C" fn(
+        state: *mut XkbState,
+        depressed_mods: u32,
+        latched_mods: u32,
+        locked_mods: u32,
+        depressed_layout: u32,
+        latched_layout: u32,
+        locked_layout: u32,
+    ) -> c_int,
+    state_key_get_one_sym: unsafe extern "C" fn(state: *mut XkbState, key: u32) -> u32,
+    keysym_to_utf8: unsafe extern "C" fn(keysym: u32, buffer: *mut c_char, size: usize) -> c_int,
+    compose_table_new_from_locale: unsafe extern "C" fn(
+        context: *mut XkbContext,
+        locale: *const c_char,
+        flags: c_int,
+    ) -> *mut XkbComposeTable,
+    compose_table_unref: unsafe extern "C" fn(table: *mut XkbComposeTable),
+    compose_state_new:
+        unsafe extern "C" fn(table: *mut XkbComposeTable, flags: c_int) -> *mut XkbComposeState,
+    compose_state_unref: unsafe extern "C" fn(state: *mut XkbComposeState),
+    compose_state_feed: unsafe extern "C" fn(state: *mut XkbComposeState, keysym: u32) -> c_int,
+    compose_state_reset: unsafe extern "C" fn(state: *mut XkbComposeState),
+    compose_state_get_status: unsafe extern "C" fn(state: *mut XkbComposeState) -> c_int,
+    compose_state_get_utf8: unsafe extern "C" fn(
+        state: *mut XkbComposeState,
+        buffer: *mut c_char,
+        size: usize,
+    ) -> c_int,
+    x11_setup_xkb_extension: unsafe extern "C" fn(
+        connection: *mut c_void,
+        major_version: u16,
+        minor_version: u16,
+        flags: c_int,
+        major_version_out: *mut u16,
+        minor_version_out: *mut u16,
+        base_event_out: *mut u8,
+        base_error_out: *mut u8,
+    ) -> c_int,
+    x11_get_core_keyboard_device_id: unsafe extern "C" fn(connection: *mut c_void) -> i32,
+    x11_keymap_new_from_device: unsafe extern "C" fn(
+        context: *mut XkbContext,
+        connection: *mut c_void,
+        device_id: i32,
+        flags: c_int,
+    ) -> *mut XkbKeymap,
+}
+
+impl XkbFunctions {
+    /// The libraries are never unloaded, since they may be shared with other windows' threads.
+    unsafe fn load() -> Option<Self> {
+        let xkbcommon = open(b"libxkbcommon.so.0\0")?;
+        let xkbcommon_x11 = open(b"libxkbcommon-x11.so.0\0")?;
+
+        Some(Self {
+            context_new: symbol(xkbcommon, b"xkb_context_new\0")?,
+            context_unref: symbol(xkbcommon, b"xkb_context_unref\0")?,
+            keymap_unref: symbol(xkbcommon, b"xkb_keymap_unref\0")?,
+            state_new: symbol(xkbcommon, b"xkb_state_new\0")?,
+            state_unref: symbol(xkbcommon, b"xkb_state_unref\0")?,
+            state_update_mask: symbol(xkbcommon, b"xkb_state_update_mask\0")?,
+            state_key_get_one_sym: symbol(xkbcommon, b"xkb_state_key_get_one_sym\0")?,
+            keysym_to_utf8: symbol(xkbcommon, b"xkb_keysym_to_utf8\0")?,
+            compose_table_new_from_locale: symbol(
+                xkbcommon,
+                b"xkb_compose_table_new_from_locale\0",
+            )?,
+            compose_table_unref: symbol(xkbcommon, b"xkb_compose_table_unref\0")?,
+            compose_state_new: symbol(xkbcommon, b"xkb_compose_state_new\0")?,
+            compose_state_unref: symbol(xkbcommon, b"xkb_compose_state_unref\0")?,
+            compose_state_feed: symbol(xkbcommon, b"xkb_compose_state_feed\0")?,
+            compose_state_reset: symbol(xkbcommon, b"xkb_compose_state_reset\0")?,
+            compose_state_get_status: symbol(xkbcommon, b"xkb_compose_state_get_status\0")?,
+            compose_state_get_utf8: symbol(xkbcommon, b"xkb_compose_state_get_utf8\0")?,
+            x11_setup_xkb_extension: symbol(xkbcommon_x11, b"xkb_x11_setup_xkb_extension\0")?,
+            x11_get_core_keyboard_device_id: symbol(
+                xkbcommon_x11,
+                b"xkb_x11_get_core_keyboard_device_id\0",
+            )?,
+            x11_keymap_new_from_device: symbol(xkbcommon_x11, b"xkb_x11_keymap_new_from_device\0")?,
+        })
+    }
+}
+
+unsafe fn open(name: &[u8]) -> Option<*mut c_void> {
+    let library = libc::dlopen(name.as_ptr() as *const c_char, libc::RTLD_LAZY | libc::RTLD_LOCAL);
+    (!library.is_null()).then(|| library)
+}
+
+/// The locale that determines the compose sequences, following the same precedence as
+/// `setlocale()`.
+fn compose_locale() -> CString {
+    ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .filter_map(std::env::var_os)
+        .find(|locale| !locale.is_empty())
+        .and_then(|locale| CString::new(locale.into_vec()).ok())
+        .unwrap_or_else(|| CString::new("C").unwrap())
+}
+
+/// The accent shown as preedit text for a dead key, which xkbcommon doesn't translate to text.
+fn dead_key_accent(keysym: u32) -> Option<char> {
+    Some(match keysym {
+        0xfe50 => '`',
+        0xfe51 => '\u{b4}',
+        0xfe52 => '^',
+        0xfe53 => '~',
+        0xfe54 => '\u{af}',
+        0xfe55 => '\u{2d8}',
+        0xfe56 => '\u{2d9}',
+        0xfe57 => '\u{a8}',
+        0xfe58 => '\u{2da}',
+        0xfe59 => '\u{2dd}',
+        0xfe5a => '\u{2c7}',
+        0xfe5b => '\u{b8}',
+        0xfe5c => '\u{2db}',
+        _ => return None,
+    })
+}
+
+/// `T` must be the function pointer type matching the symbol's signature.
+unsafe fn symbol<T: Copy>(library: *mut c_void, name: &[u8]) -> Option<T> {
+    let symbol = libc::dlsym(library, name.as_ptr() as *const c_char);
+    (!symbol.is_null()).then(|| std::mem::transmute_copy(&symbol))
+}
+
+/// Translates key presses to characters with the user's keyboard layout through xkbcommon. The
+/// modifier and layout state is taken from each key event, so only the keymap needs to be kept up
+/// to date, see [`Xkb::refresh()`]. Dead keys are composed with the compose table for the user's
+/// locale, and the backend sends the compose sequences as input method events.
+pub(super) struct Xkb {
+    functions: XkbFunctions,
+    /// The raw `xcb_connection_t` of the window thread's connection.
+    connection: *mut c_void,
+    device_id: i32,
+    context: *mut XkbContext,
+    keymap: Cell<*mut XkbKeymap>,
+    state: Cell<*mut XkbState>,
+    /// Null if there's no compose table for the locale, in which case dead keys don't compose.
+    compose_table: *mut XkbComposeTable,
+    compose_state: *mut XkbComposeState,
+    /// The accents and characters of the compose sequence in progress.
+    preedit: RefCell<String>,
+    compose_change: RefCell<Option<ComposeChange>>,
+}
+
+impl Xkb {
+    /// Returns `None` if xkbcommon isn't installed, or if the server doesn't support XKB.
+    pub fn new(connection: *mut c_void) -> Option<Self> {
+        unsafe {
+            let functions = XkbFunctions::load()?;
+
+            let (mut major, mut minor, mut base_event, mut base_error) = (0, 0, 0, 0);
+            let supported = (functions.x11_setup_xkb_extension)(
+                connection,
+                1,
+                0,
+                0,
+                &mut major,
+                &mut minor,
+                &mut base_event,
+                &mut base_error,
+            );
+            let device_id = (functions.x11_get_core_keyboard_device_id)(connection);
+            if supported != 1 || device_id == -1 {
+                return None;
+            }
+
+            let context = (functions.context_new)(0);
+            if context.is_null() {
+                return None;
+            }
+
+            let locale = compose_locale();
+            let compose_table =
+                (functions.compose_table_new_from_locale)(context, locale.as_ptr(), 0);
+            let compose_state = if compose_table.is_null() {
+                std::ptr::null_mut()
+            } else {
+                (functions.compose_state_new)(compose_table, 0)
+            };
+
+            let xkb = Self {
+                functions,
+                connection,
+                device_id,
+                context,
+                keymap: Cell::new(std::ptr::null_mut()),
+                state: Cell::new(std::ptr::null_mut()),
+                compose_table,
+                compose_state,
+                preedit: RefCell::new(String::new()),
+                compose_change: RefCell::new(None),
+            };
+            xkb.refresh();
+            if xkb.state.get().is_null() {
+                return None;
+            }
+
+            Some(xkb)
+        }
+    }
+
+    /// Fetch the keymap from the server again after it has been changed, which is reported as a
+    /// `MappingNotify` event.
+    pub fn refresh(&self) {
+        unsafe {
+            let keymap = (self.functions.x11_keymap_new_from_device)(
+                self.context,
+                self.connection,
+                self.device_id,
+                0,
+            );
+            if keymap.is_null() {
+                return;
+            }
+
+            let state = (self.functions.state_new)(keymap);
+            if state.is_null() {
+                (self.functions.keymap_unref)(keymap);
+                return;
+            }
+
+            self.release_keymap();
+            self.keymap.set(keymap);
+            self.state.set(state);
+        }
+    }
+
+    /// The key the user's layout produces for a key event with this keycode and state. Returns
+    /// `None` for keys that don't produce a printable character, like Enter or the function keys,
+    /// which are better identified by their physical location.
+    ///
+    /// If `compose` is set, key presses advance the compose state. A key press that continues a
+    /// compose sequence is `Key::Named(NamedKey::Dead)`, and the key press that completes it has
+    /// the composed character. How the key press changed the sequence can then be taken with
+    /// [`take_compose_change()`][Self::take_compose_change()].
+    pub fn key(
+        &self, keycode: u8, state: KeyButMask, key_state: KeyState, compose: bool,
+    ) -> Option<Key> {
+        let state_bits = u32::from(u16::from(state));
+        // The core modifier bits map directly to xkbcommon's real modifiers, and bits 13 and 14
+        // contain the active layout
+        let mods = state_bits & 0xff;
+        let layout = (state_bits >> 13) & 0b11;
+
+        let keysym = unsafe {
+            let xkb_state = self.state.get();
+            (self.functions.state_update_mask)(xkb_state, mods, 0, 0, 0, 0, layout);
+            (self.functions.state_key_get_one_sym)(xkb_state, u32::from(keycode))
+        };
+        if key_state == KeyState::Down && compose {
+            if let Some(key) = self.compose(keysym) {
+                return Some(key);
+            }
+        }
+        if DEAD_KEYSYMS.contains(&keysym) {
+            return Some(Key::Named(NamedKey::Dead));
+        }
+
+        // The keysym isn't affected by Ctrl, so Ctrl+A still produces `a` rather than a control
+        // character
+        self.keysym_text(keysym).map(Key::Character)
+    }
+
+    /// The printable text for a keysym, if any.
+    fn keysym_text(&self, keysym: u32) -> Option<String> {
+        let mut buffer = [0u8; 8];
+        let len = unsafe {
+            (self.functions.keysym_to_utf8)(
+                keysym,
+                buffer.as_mut_ptr() as *mut c_char,
+                buffer.len(),
+            )
+        };
+        // The length includes the null terminator
+        if len <= 1 {
+            return None;
+        }
+
+        let text = std::str::from_utf8(&buffer[..len as usize - 1]).ok()?;
+        if text.chars().any(char::is_control) {
+            None
+        } else {
+            Some(text.to_owned())
+        }
+    }
+
+    /// Feed a key press's keysym to the compose state. Returns `None` if the key press isn't part
+    /// of a compose sequence, or if it cancelled one, in which case it types its own character.
+    fn compose(&self, keysym: u32) -> Option<Key> {
+        if self.compose_state.is_null() {
+            return None;
+        }
+
+        unsafe {
+            let accepted = (self.functions.compose_state_feed)(self.compose_state, keysym);
+            if accepted != XKB_COMPOSE_FEED_ACCEPTED {
+                return None;
+            }
+
+            match (self.functions.compose_state_get_status)(self.compose_state) {
+                XKB_COMPOSE_COMPOSING => {
+                    let mut preedit = self.preedit.borrow_mut();
+                    match dead_key_accent(keysym) {
+                        Some(accent) => preedit.push(accent),
+                        None => preedit.extend(self.keysym_text(keysym)),
+                    }
+                    // The compose key itself doesn't show anything
+                    if !preedit.is_empty() {
+                        self.set_compose_change(ComposeChange::Preedit(preedit.clone()));
+                    }
+
+                    Some(Key::Named(NamedKey::Dead))
+                }
+                XKB_COMPOSE_COMPOSED => {
+                    let mut buffer = [0u8; 64];
+                    let len = (self.functions.compose_state_get_utf8)(
+                        self.compose_state,
+                        buffer.as_mut_ptr() as *mut c_char,
+                        buffer.len(),
+                    );
+                    (self.functions.compose_state_reset)(self.compose_state);
+                    self.preedit.borrow_mut().clear();
+
+                    // Unlike `xkb_keysym_to_utf8()`, the length excludes the null terminator
+                    let text = buffer.get(..len.max(0) as usize).unwrap_or_default();
+                    let text = std::str::from_utf8(text).unwrap_or_default();
+                    if text.is_empty() {
+                        self.set_compose_change(ComposeChange::Cancelled);
+                        return None;
+                    }
+
+                    self.set_compose_change(ComposeChange::Commit(text.to_owned()));
+                    Some(Key::Character(text.to_owned()))
+                }
+                XKB_COMPOSE_CANCELLED => {
+                    if self.reset_compose() {
+                        self.set_compose_change(ComposeChange::Cancelled);
+                    }
+                    None
+                }
+                _ => None,
+            }
+        }
+    }
+
+    /// Cancel the compose sequence in progress, if any. Returns whether any preedit text was
+    /// shown for it.
+    pub fn reset_compose(&self) -> bool {
+        if !self.compose_state.is_null() {
+            unsafe { (self.functions.compose_state_reset)(self.compose_state) };
+        }
+
+        let mut preedit = self.preedit.borrow_mut();
+        let had_preedit = !preedit.is_empty();
+        preedit.clear();
+        had_preedit
+    }
+
+    /// How the last key press passed to [`key()`][Self::key()] changed the compose sequence, if
+    /// it was part of one.
+    pub fn take_compose_change(&self) -> Option<ComposeChange> {
+        self.compose_change.take()
+    }
+
+    fn set_compose_change(&self, change: ComposeChange) {
+        *self.compose_change.borrow_mut() = Some(change);
+    }
+
+    unsafe fn release_keymap(&self) {
+        let state = self.state.replace(std::ptr::null_mut());
+        if !state.is_null() {
+            (self.functions.state_unref)(state);
+        }
+        let keymap = self.keymap.replace(std::ptr::null_mut());
+        if !keymap.is_null() {
+            (self.functions.keymap_unref)(keymap);
+        }
+    }
+}
+
+impl Drop for Xkb {
+    fn drop(&mut self) {
+        unsafe {
+            self.release_keymap();
+            if !self.compose_state.is_null() {
+                (self.functions.compose_state_unref)(self.compose_state);
+            }
+            if !self.compose_table.is_null() {
+                (self.functions.compose_table_unref)(self.compose_table);
+            }
+            (self.functions.context_unref)(self.context);
+        }
+    }
+}
@@ -0,0 +1,281 @@
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::error::Error;
+
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{AtomEnum, ConnectionExt as _};
+
+/// A single value from the XSETTINGS manager.
+#[derive(Debug)]
+pub(super) enum XSetting {
+    Integer(i32),
+    String(String),
+}
+
+/// The settings published by the XSETTINGS manager, which is how desktop environments like GNOME
+/// share settings such as the cursor theme with X11 clients. See
+/// <https://specifications.freedesktop.org/xsettings-spec/0.5/>.
+#[derive(Debug, Default)]
+pub(super) struct XSettings {
+    settings: HashMap<String, XSetting>,
+}
+
+impl XSettings {
+    /// Read the current settings for the given screen. Returns an empty set of settings if there
+    /// is no settings manager running.
+    pub fn query(conn: &impl Connection, screen: usize) -> Result<Self, Box<dyn Error>> {
+        let selection_name = format!("_XSETTINGS_S{}", screen);
+        let selection = conn.intern_atom(false, selection_name.as_bytes())?.reply()?.atom;
+        let settings_atom = conn.intern_atom(false, b"_XSETTINGS_SETTINGS")?.reply()?.atom;
+
+        let owner = conn.get_selection_owner(selection)?.reply()?.owner;
+        if owner == x11rb::NONE {
+            return Ok(Self::default());
+        }
+
+        let reply = conn
+            .get_property(false, owner, settings_atom, AtomEnum::ANY, 0, u32::MAX / 4)?
+            .reply()?;
+
+        Ok(Self { settings: parse(&reply.value).unwrap_or_default() })
+    }
+
+    pub fn get_int(&self, name: &str) -> Option<i32> {
+        match self.settings.get(name) {
+            Some(XSetting::Integer(value)) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn get_string(&self, name: &str) -> Option<&str> {
+        match self.settings.get(name) {
+            Some(XSetting::String(value)) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+/// Parse the contents of the `_XSETTINGS_SETTINGS` property. Returns `None` if the data is
+/// malformed.
+fn parse(data: &[u8]) -> Option<HashMap<String, XSetting>> {
+    let mut reader = Reader { data, pos: 0, big_endian: *data.first()? == 1 };
+
+    // Byte order, three bytes of padding, and the serial
+    reader.skip(8)?;
+    let num_settings = reader.u32()?;
+
+    let mut settings = HashMap::new();
+    for _ in 0..num_settings {
+        let setting_type = reader.u8()?;
+        reader.skip(1)?;
+        let name_len = reader.u16()? as usize;
+        let name = String::from_utf8_lossy(reader.padded_bytes(name_len)?).into_owned();
+        // The serial of the last change to this setting
+        reader.skip(4)?;
+
+        let value = match setting_type {
+            0 => XSetting::Integer(reader.u32()? as i32),
+            1 => {
+                let len = reader.u32()? as usize;
+                XSetting::String(String::from_utf8_lossy(reader.padded_bytes(len)?).into_owned())
+            }
+            // We don't use any color settings, so these are skipped
+            2 => {
+                reader.skip(8)?;
+                continue;
+            }
+            _ => return None,
+        };
+
+        settings.insert(name, value);
+    }
+
+    Some(settings)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    big_endian: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+
+        Some(bytes)
+    }
+
+    /// Read `len` bytes, followed by padding up to the next multiple of four bytes.
+    fn padded_bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.bytes(len)?;
+        self.skip((4 - len % 4) % 4)?;
+
+        Some(bytes)
+    }
+
+    fn skip(&mut self, len: usize) -> Option<()> {
+        self.bytes(len).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        let bytes = self.bytes(2)?.try_into().ok()?;
+        Some(if self.big_endian { u16::from_be_bytes(bytes) } else { u16::from_le_bytes(bytes) })
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        let bytes = self.bytes(4)?.try_into().ok()?;
+        Some(if self.big_endian { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Serializes settings the way a settings manager does.
+    struct Writer {
+        data: Vec<u8>,
+        big_endian: bool,
+        num_settings: u32,
+    }
+
+    impl Writer {
+        fn new(big_endian: bool) -> Self {
+            let mut writer =
+                Self { data: vec![big_endian as u8, 0, 0, 0], big_endian, num_settings: 0 };
+            // The serial, and the number of settings which is filled in by `finish()`
+            writer.u32(7);
+            writer.u32(0);
+            writer
+        }
+
+        fn int(mut self, name: &str, value: i32) -> Self {
+            self.header(0, name);
+            self.u32(value as u32);
+            self
+        }
+
+        fn string(mut self, name: &str, value: &str) -> Self {
+            self.header(1, name);
+            self.u32(value.len() as u32);
+            self.padded(value.as_bytes());
+            self
+        }
+
+        fn color(mut self, name: &str, rgba: [u16; 4]) -> Self {
+            self.header(2, name);
+            for channel in rgba {
+                self.u16(channel);
+            }
+            self
+        }
+
+        fn finish(mut self) -> Vec<u8> {
+            let num_settings = self.num_settings;
+            let bytes = if self.big_endian {
+                num_settings.to_be_bytes()
+            } else {
+                num_settings.to_le_bytes()
+            };
+            self.data[8..12].copy_from_slice(&bytes);
+            self.data
+        }
+
+        fn header(&mut self, setting_type: u8, name: &str) {
+            self.num_settings += 1;
+            self.data.extend([setting_type, 0]);
+            self.u16(name.len() as u16);
+            self.padded(name.as_bytes());
+            // The setting's serial
+            self.u32(3);
+        }
+
+        fn padded(&mut self, bytes: &[u8]) {
+            self.data.extend(bytes);
+            self.data.resize(self.data.len() + (4 - bytes.len() % 4) % 4, 0);
+        }
+
+        fn u16(&mut self, value: u16) {
+            let bytes = if self.big_endian { value.to_be_bytes() } else { value.to_le_bytes() };
+            self.data.extend(bytes);
+        }
+
+        fn u32(&mut self, value: u32) {
+            let bytes = if self.big_endian { value.to_be_bytes() } else { value.to_le_bytes() };
+            self.data.extend(bytes);
+        }
+    }
+
+    fn settings(data: &[u8]) -> XSettings {
+        XSettings { settings: parse(data).expect("the settings should parse") }
+    }
+
+    #[test]
+    fn parses_both_byte_orders() {
+        for big_endian in [false, true] {
+            let data = Writer::new(big_endian)
+                .int("Xft/DPI", 98304)
+                .string("Gtk/CursorThemeName", "Adwaita")
+                .int("Net/DoubleClickTime", -1)
+                .finish();
+
+            let settings = settings(&data);
+            assert_eq!(settings.get_int("Xft/DPI"), Some(98304));
+            assert_eq!(settings.get_string("Gtk/CursorThemeName"), Some("Adwaita"));
+            assert_eq!(settings.get_int("Net/DoubleClickTime"), Some(-1));
+        }
+    }
+
+    #[test]
+    fn values_have_types() {
+        let data =
+            Writer::new(false).int("Gtk/CursorThemeSize", 24).string("Net/ThemeName", "").finish();
+
+        let settings = settings(&data);
+        assert_eq!(settings.get_string("Gtk/CursorThemeSize"), None);
+        assert_eq!(settings.get_int("Net/ThemeName"), None);
+        assert_eq!(settings.get_string("Net/ThemeName"), Some(""));
+        assert_eq!(settings.get_int("Net/CursorBlinkTime"), None);
+    }
+
+    #[test]
+    fn colors_are_skipped() {
+        // Names and strings with lengths that aren't multiples of four are padded
+        let data = Writer::new(true)
+            .color("Gtk/ColorScheme", [0xffff, 0x8000, 0, 0xffff])
+            .string("Gtk/FontName", "Cantarell 11")
+            .int("Xft/Hinting", 1)
+            .finish();
+
+        let settings = settings(&data);
+        assert!(!settings.settings.contains_key("Gtk/ColorScheme"));
+        assert_eq!(settings.get_string("Gtk/FontName"), Some("Cantarell 11"));
+        assert_eq!(settings.get_int("Xft/Hinting"), Some(1));
+    }
+
+    #[test]
+    fn rejects_truncated_input() {
+        let data =
+            Writer::new(false).int("Xft/DPI", 98304).string("Net/ThemeName", "Adwaita").finish();
+
+        assert!(parse(&data).is_some());
+        for len in 0..data.len() {
+            assert!(parse(&data[..len]).is_none(), "{} of {} bytes", len, data.len());
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_types() {
+        let mut data = Writer::new(false).int("Xft/DPI", 98304).finish();
+        // The setting's type directly follows the header
+        data[12] = 3;
+
+        assert!(parse(&data).is_none());
+    }
+}